@@ -0,0 +1,78 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use generic_serial_protocol::{
+    encode_frame, message_types, Decoder, Message, SerialManager, MAX_PAYLOAD_LENGTH,
+};
+use std::hint::black_box;
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+/// A payload of every byte value, so that 2 in every 256 bytes need escaping
+fn payload(length: usize) -> Vec<u8> {
+    (0..=u8::MAX).cycle().take(length).collect()
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for length in [16, 1024, MAX_PAYLOAD_LENGTH] {
+        let payload = payload(length);
+        group.throughput(Throughput::Bytes(length as u64));
+        group.bench_function(format!("frame/{length}"), |b| {
+            b.iter(|| encode_frame(black_box(0), black_box(&payload)));
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for length in [16, 1024, MAX_PAYLOAD_LENGTH] {
+        let frame = encode_frame(0, &payload(length));
+        group.throughput(Throughput::Bytes(length as u64));
+        group.bench_function(format!("frame/{length}"), |b| {
+            b.iter_batched_ref(
+                Decoder::new,
+                |decoder| {
+                    frame
+                        .iter()
+                        .filter_map(|&byte| decoder.push(byte))
+                        .for_each(|event| {
+                            black_box(event);
+                        });
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// Sends messages through a Unix domain socket pair and receives them on another thread
+fn end_to_end(c: &mut Criterion) {
+    const COUNT: usize = 100;
+
+    let mut group = c.benchmark_group("end_to_end");
+    for length in [16, 1024, 60 * 1024] {
+        let message = Message::Bytes(message_types::Bytes {
+            data: payload(length),
+        });
+        group.throughput(Throughput::Bytes((COUNT * length) as u64));
+        group.bench_function(format!("bytes/{length}"), |b| {
+            b.iter(|| {
+                let (stream1, stream2) = UnixStream::pair().unwrap();
+                let receiver = thread::spawn(move || {
+                    let mut receiver = SerialManager::new(stream2);
+                    for _ in 0..COUNT {
+                        black_box(receiver.receive().unwrap());
+                    }
+                });
+                let mut sender = SerialManager::new(stream1);
+                sender.send_all(vec![message.clone(); COUNT]).unwrap();
+                receiver.join().unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode, end_to_end);
+criterion_main!(benches);
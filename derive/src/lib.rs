@@ -0,0 +1,85 @@
+#![warn(clippy::pedantic)]
+
+//! Derive macros for `generic-serial-protocol`, re-exported by it with the `derive` feature
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Implements `EnumField` for a fieldless `#[repr(u8)]` enum, encoding each variant as its
+/// discriminant
+#[proc_macro_derive(EnumField)]
+pub fn derive_enum_field(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    enum_field(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn enum_field(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            "EnumField can only be derived for enums",
+        ));
+    };
+    if !is_repr_u8(input)? {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "EnumField requires #[repr(u8)], so that every discriminant fits in a byte",
+        ));
+    }
+    let mut variants = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new_spanned(
+                variant,
+                "EnumField variants cannot have fields",
+            ));
+        }
+        variants.push(&variant.ident);
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::generic_serial_protocol::EnumField for #name #type_generics
+        #where_clause
+        {
+            const DESCRIPTOR: ::generic_serial_protocol::schema::EnumDescriptor =
+                ::generic_serial_protocol::schema::EnumDescriptor {
+                    name: stringify!(#name),
+                    variants: &[#((stringify!(#variants), Self::#variants as u8),)*],
+                };
+
+            fn to_u8(&self) -> u8 {
+                match self {
+                    #(Self::#variants => Self::#variants as u8,)*
+                }
+            }
+
+            fn from_u8(value: u8) -> ::core::option::Option<Self> {
+                #(
+                    if value == Self::#variants as u8 {
+                        return ::core::option::Option::Some(Self::#variants);
+                    }
+                )*
+                ::core::option::Option::None
+            }
+        }
+    })
+}
+
+fn is_repr_u8(input: &DeriveInput) -> Result<bool, Error> {
+    let mut repr_u8 = false;
+    for attr in &input.attrs {
+        if attr.path().is_ident("repr") {
+            attr.parse_nested_meta(|meta| {
+                repr_u8 |= meta.path.is_ident("u8");
+                Ok(())
+            })?;
+        }
+    }
+    Ok(repr_u8)
+}
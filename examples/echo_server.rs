@@ -0,0 +1,49 @@
+//! Sends every message received over a serial device straight back
+//!
+//! ```sh
+//! cargo run --example echo_server -- /dev/ttyUSB0
+//! ```
+//!
+//! Configure the device's baud rate beforehand, e.g. with `stty -F /dev/ttyUSB0 115200 raw`.
+
+use generic_serial_protocol::{ReceiveError, SerialManager};
+use std::env;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: echo_server <device>");
+        return ExitCode::FAILURE;
+    };
+    let mut manager = match SerialManager::open_com(&path) {
+        Ok(manager) => manager.with_unknown_messages(true),
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    loop {
+        match manager.receive() {
+            Ok(message) => {
+                println!("echoing {message}");
+                if let Err(e) = manager.send(message) {
+                    eprintln!("send failed: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+            // A corrupted frame is skipped, and the next one is decoded as usual
+            Err(e @ (ReceiveError::Decode { .. } | ReceiveError::UnexpectedBytes(_))) => {
+                eprintln!("{e}");
+            }
+            Err(ReceiveError::PeerClosed) => {
+                println!("peer closed the connection");
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Two peers chatting over a pseudo-terminal pair, as they would over a serial cable
+//!
+//! ```sh
+//! cargo run --example pty_chat
+//! ```
+//!
+//! Each line typed is sent by alice as a `MyString` and answered by bob, running in a thread on
+//! the other end of the pair. Both ends are put into raw mode, so that the terminal line
+//! discipline passes every byte through unchanged, as a serial port configured with
+//! `stty raw` would.
+
+#[cfg(unix)]
+fn main() -> std::process::ExitCode {
+    unix::main()
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("pseudo-terminals are only available on Unix");
+}
+
+#[cfg(unix)]
+mod unix {
+    use generic_serial_protocol::{message_types, Message, ReceiveError, SerialManager};
+    use std::fs::File;
+    use std::io::{self, BufRead};
+    use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+    use std::process::ExitCode;
+    use std::ptr;
+    use std::thread;
+
+    pub fn main() -> ExitCode {
+        let (master, slave) = match open_pty() {
+            Ok(pair) => pair,
+            Err(e) => {
+                eprintln!("openpty: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let mut alice = SerialManager::new(master);
+        let mut bob = SerialManager::new(slave);
+
+        let bob = thread::spawn(move || loop {
+            match bob.receive() {
+                Ok(Message::MyString(message_types::MyString { string })) => {
+                    let reply = format!("bob read {} characters", string.chars().count());
+                    bob.send(Message::MyString(message_types::MyString { string: reply }))?;
+                }
+                Ok(message) => eprintln!("bob ignored {message}"),
+                Err(ReceiveError::PeerClosed) => return Ok::<_, io::Error>(()),
+                Err(e) => eprintln!("bob: {e}"),
+            }
+        });
+
+        println!("Type a line to send it from alice to bob, or end input to hang up");
+        for line in io::stdin().lock().lines() {
+            let result = line.and_then(|string| {
+                alice.send(Message::MyString(message_types::MyString { string }))
+            });
+            if let Err(e) = result {
+                eprintln!("alice: {e}");
+                return ExitCode::FAILURE;
+            }
+            match alice.receive() {
+                Ok(reply) => println!("{reply}"),
+                Err(e) => {
+                    eprintln!("alice: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        // Sends a Goodbye, so that bob stops receiving
+        if let Err(e) = alice.close() {
+            eprintln!("alice: {e}");
+            return ExitCode::FAILURE;
+        }
+        match bob.join() {
+            Ok(Ok(())) => ExitCode::SUCCESS,
+            Ok(Err(e)) => {
+                eprintln!("bob: {e}");
+                ExitCode::FAILURE
+            }
+            Err(_) => ExitCode::FAILURE,
+        }
+    }
+
+    /// Opens a pseudo-terminal pair in raw mode, returning its master and slave ends
+    fn open_pty() -> io::Result<(File, File)> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        // SAFETY: the name, termios and window size arguments may be null
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty succeeded, so both are open file descriptors owned by nothing else
+        let (master, slave) = unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr initialises the termios on success, which is checked before it is
+        // read
+        unsafe {
+            if libc::tcgetattr(slave.as_raw_fd(), termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut termios = termios.assume_init();
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok((master, slave))
+    }
+}
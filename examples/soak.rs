@@ -0,0 +1,142 @@
+//! Soak tests a link for hours, exchanging randomized messages and checking every one comes back
+//! unchanged and in order, and prints a report
+//!
+//! ```sh
+//! # Over a simulated 115200 baud link with occasional bit errors, for 10 minutes
+//! cargo run --release --example soak -- --duration 600
+//! # Against a device running the echo_server example, for 24 hours
+//! cargo run --release --example soak -- --duration 86400 /dev/ttyUSB0
+//! ```
+//!
+//! Configure a device's baud rate and a short read timeout beforehand, e.g. with
+//! `stty -F /dev/ttyUSB0 115200 raw min 0 time 1`. Both ends of the simulated link stack a
+//! `Crc32` layer, so bit errors show up as decode errors and lost messages rather than
+//! corrupted ones. Exits with failure if any message came back changed or out of order, or
+//! memory grew more than allowed.
+
+use generic_serial_protocol::layer::Crc32;
+use generic_serial_protocol::testing::NoisyChannel;
+use generic_serial_protocol::{SerialManager, Soak, SoakReport, SoakSample};
+use std::env;
+use std::io::{Read, Write};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+fn main() -> ExitCode {
+    let mut soak = Soak::new();
+    let mut device = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let seconds = |value: Option<String>| {
+            value
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs)
+        };
+        match arg.as_str() {
+            "--duration" => match seconds(args.next()) {
+                Some(duration) => soak = soak.with_duration(duration),
+                None => return usage(),
+            },
+            "--interval" => match seconds(args.next()) {
+                Some(interval) => soak = soak.with_interval(interval),
+                None => return usage(),
+            },
+            _ if device.is_none() && !arg.starts_with('-') => device = Some(arg),
+            _ => return usage(),
+        }
+    }
+
+    let result = match device {
+        Some(path) => match SerialManager::open_com(&path) {
+            Ok(mut manager) => run(&mut manager, &soak),
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => simulated(&soak),
+    };
+    match result {
+        Ok(report) if report.passed() => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: soak [--duration <seconds>] [--interval <seconds>] [<device>]");
+    ExitCode::FAILURE
+}
+
+/// Soak tests a simulated link, with a thread echoing messages back on its far end
+fn simulated(soak: &Soak) -> std::io::Result<SoakReport> {
+    let (mut host, device) = NoisyChannel::new()
+        .with_baud_rate(115_200)
+        .with_latency(Duration::from_millis(1))
+        .with_bit_error_probability(1e-6)
+        .pair();
+    host.set_read_timeout(Some(Duration::from_millis(1)));
+    let mut echo = SerialManager::new(device).with_layer(Crc32);
+    thread::spawn(move || loop {
+        match echo.receive() {
+            Ok(message) => {
+                if echo.send(message).is_err() {
+                    return;
+                }
+            }
+            Err(e) if e.decode_error().is_some() => {}
+            Err(_) => return,
+        }
+    });
+    run(&mut SerialManager::new(host).with_layer(Crc32), soak)
+}
+
+fn run<T: Read + Write>(
+    manager: &mut SerialManager<T>,
+    soak: &Soak,
+) -> std::io::Result<SoakReport> {
+    println!(
+        "{:>10} {:>10} {:>10} {:>6} {:>9} {:>12} {:>7} {:>13} {:>10}",
+        "elapsed",
+        "sent",
+        "received",
+        "lost",
+        "corrupted",
+        "out of order",
+        "resyncs",
+        "decode errors",
+        "memory"
+    );
+    let report = manager.run_soak_with(soak, print_sample)?;
+    println!("{}", "-".repeat(97));
+    print_sample(&report.total);
+
+    match report.memory_growth() {
+        Some(growth) => println!("memory growth: {} KiB", growth / 1024),
+        None => println!("memory growth: not measured"),
+    }
+    println!("{}", if report.passed() { "PASSED" } else { "FAILED" });
+    Ok(report)
+}
+
+fn print_sample(sample: &SoakSample) {
+    let memory = sample
+        .resident_memory
+        .map_or_else(|| "-".to_string(), |bytes| format!("{} KiB", bytes / 1024));
+    println!(
+        "{:>9.0}s {:>10} {:>10} {:>6} {:>9} {:>12} {:>7} {:>13} {:>10}",
+        sample.elapsed.as_secs_f64(),
+        sample.messages_sent,
+        sample.messages_received,
+        sample.messages_lost,
+        sample.corrupted,
+        sample.out_of_order,
+        sample.resyncs,
+        sample.decode_errors,
+        memory
+    );
+}
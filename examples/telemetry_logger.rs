@@ -0,0 +1,92 @@
+//! Logs every message received over a serial device as a line of CSV, with the time it arrived
+//!
+//! ```sh
+//! cargo run --example telemetry_logger -- /dev/ttyUSB0 > telemetry.csv
+//! ```
+//!
+//! Numeric readings are written to their own column, so that the log can be plotted directly.
+//! `Log` messages from the device are written to stderr as well.
+
+use generic_serial_protocol::{message_types, Message, ReceiveError, SerialManager};
+use std::env;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::time::UNIX_EPOCH;
+
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: telemetry_logger <device>");
+        return ExitCode::FAILURE;
+    };
+    let mut manager = match SerialManager::open_com(&path) {
+        Ok(manager) => manager.with_unknown_messages(true),
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match log(&mut manager, &mut io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn log<T: io::Read + io::Write>(
+    manager: &mut SerialManager<T>,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    writeln!(out, "time,message_type,value,message")?;
+    loop {
+        let (message, received_at) = match manager.receive_timestamped() {
+            Ok(received) => received,
+            Err(e @ (ReceiveError::Decode { .. } | ReceiveError::UnexpectedBytes(_))) => {
+                eprintln!("{e}");
+                continue;
+            }
+            Err(ReceiveError::PeerClosed) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Message::Log(message_types::Log {
+            level,
+            module,
+            text,
+        }) = &message
+        {
+            eprintln!("[{level:?} {module}] {text}");
+        }
+
+        let time = received_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let value = reading(&message).map(|value| value.to_string());
+        let description = message.to_string().replace('"', "\"\"");
+        writeln!(
+            out,
+            "{time:.6},{},{},\"{description}\"",
+            message.message_type(),
+            value.unwrap_or_default()
+        )?;
+        out.flush()?;
+    }
+}
+
+/// The reading carried by a message holding a single number
+fn reading(message: &Message) -> Option<f64> {
+    Some(match message {
+        Message::U8(message_types::U8 { num }) => f64::from(*num),
+        Message::U16(message_types::U16 { num }) => f64::from(*num),
+        Message::U32(message_types::U32 { num }) => f64::from(*num),
+        Message::I8(message_types::I8 { num }) => f64::from(*num),
+        Message::I16(message_types::I16 { num }) => f64::from(*num),
+        Message::I32(message_types::I32 { num }) => f64::from(*num),
+        Message::F32(message_types::F32 { num }) => f64::from(*num),
+        Message::F64(message_types::F64 { num }) => *num,
+        _ => return None,
+    })
+}
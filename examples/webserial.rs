@@ -0,0 +1,42 @@
+//! A browser-based monitor, passing every message received from a WebSerial port to a JavaScript
+//! callback
+//!
+//! ```sh
+//! cargo build --release --example webserial --target wasm32-unknown-unknown --features webserial
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/examples/webserial.wasm --out-dir pkg
+//! ```
+//!
+//! and from a page, after a click so that the browser lets it ask for a port:
+//!
+//! ```js
+//! import init, { monitor } from "./pkg/webserial.js";
+//!
+//! await init();
+//! const port = await navigator.serial.requestPort();
+//! await monitor(port, 115200, (message) => console.log(message));
+//! ```
+//!
+//! The device is asked to identify itself first, so its `DeviceInfo` is among the first messages.
+
+use generic_serial_protocol::{message_types, Message, SerialManager, WebSerialPort};
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+/// Opens `port` and calls `on_message` with each message received, as text, until the port is
+/// closed or fails
+#[wasm_bindgen]
+pub async fn monitor(port: JsValue, baud_rate: u32, on_message: Function) -> Result<(), JsValue> {
+    let port = WebSerialPort::open(port, baud_rate)
+        .await
+        .map_err(JsError::from)?;
+    let mut manager = SerialManager::new(port.clone()).with_unknown_messages(true);
+    manager
+        .send(Message::Identify(message_types::Identify {}))
+        .map_err(JsError::from)?;
+    loop {
+        port.readable().await;
+        for message in manager.drain().map_err(JsError::from)? {
+            on_message.call1(&JsValue::NULL, &message.to_string().into())?;
+        }
+    }
+}
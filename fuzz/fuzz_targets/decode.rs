@@ -0,0 +1,14 @@
+#![no_main]
+
+use generic_serial_protocol::{Decoder, DecoderEvent, Message};
+use libfuzzer_sys::fuzz_target;
+
+// Random byte streams must never panic the decoder or the payload decoding
+fuzz_target!(|bytes: &[u8]| {
+    let mut decoder = Decoder::new();
+    for &byte in bytes {
+        if let Some(DecoderEvent::Frame(frame)) = decoder.push(byte) {
+            let _ = Message::from_bytes(frame.message_type, frame.payload);
+        }
+    }
+});
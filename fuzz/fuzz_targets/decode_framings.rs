@@ -0,0 +1,56 @@
+#![no_main]
+
+use generic_serial_protocol::{Decoder, DecoderEvent, Endianness, Framing, Message, Resync};
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+
+const FRAMINGS: [Framing; 7] = [
+    Framing::Native,
+    Framing::Slip,
+    Framing::Cobs,
+    Framing::Compact,
+    Framing::Extended,
+    Framing::Binary,
+    Framing::CheckedHeader,
+];
+
+// Random byte streams must never panic the decoder in any configuration, and it must never hold
+// more than a few frames' worth of bytes
+fuzz_target!(|input: &[u8]| {
+    let [framing, options, bytes @ ..] = input else {
+        return;
+    };
+    let framing = FRAMINGS[usize::from(*framing) % FRAMINGS.len()];
+    let endianness = if options & 0x01 == 0 {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    };
+    let resync = match (options >> 1) & 0x03 {
+        0 => Resync::StartByte,
+        1 => Resync::Checksum,
+        _ => Resync::IdleGap(Duration::from_millis(1)),
+    };
+    let mut decoder = Decoder::new()
+        .with_framing(framing)
+        .with_endianness(endianness)
+        .with_resync(resync);
+    if options & 0x08 != 0 {
+        decoder = decoder.with_trailer(0x0A);
+    }
+    if options & 0x10 != 0 {
+        decoder = decoder.with_padding();
+    }
+
+    let limit = 3 * (framing.max_payload_length() + 16);
+    for &byte in bytes {
+        let mut event = decoder.push(byte);
+        while let Some(next) = event {
+            if let DecoderEvent::Frame(frame) = next {
+                let _ = Message::from_bytes(frame.message_type, frame.payload);
+            }
+            event = decoder.poll();
+        }
+        assert!(decoder.buffered() <= limit);
+    }
+});
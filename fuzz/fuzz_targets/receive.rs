@@ -0,0 +1,31 @@
+#![no_main]
+
+use generic_serial_protocol::{Framing, ReceiveError, ReplayConnection, SerialManager};
+use libfuzzer_sys::fuzz_target;
+
+// Random byte streams must never panic the receive path, from reading through decoding frames
+// and payloads to handling errors, and every byte must eventually be consumed
+fuzz_target!(|input: &[u8]| {
+    let [framing, bytes @ ..] = input else {
+        return;
+    };
+    let framing = match framing % 5 {
+        0 => Framing::Native,
+        1 => Framing::Slip,
+        2 => Framing::Cobs,
+        3 => Framing::Binary,
+        _ => Framing::CheckedHeader,
+    };
+    let mut manager = SerialManager::new(ReplayConnection::new(bytes.to_vec()))
+        .with_framing(framing)
+        .with_unknown_messages(true);
+
+    // Every message or error received comes from at least one byte of input, so this ends once
+    // the replay runs out
+    for _ in 0..=2 * bytes.len() {
+        if let Err(ReceiveError::Io(_)) = manager.receive() {
+            return;
+        }
+    }
+    panic!("receive did not reach the end of the input");
+});
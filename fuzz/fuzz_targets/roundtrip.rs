@@ -0,0 +1,15 @@
+#![no_main]
+
+use generic_serial_protocol::{roundtrip, Message};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|message: Message| {
+    let result = roundtrip(message.clone());
+    if let Message::Unknown { .. } = message {
+        // Arbitrary bytes need not be a valid payload for the type they claim
+        return;
+    }
+    let decoded = result.expect("encoded message failed to decode");
+    assert_eq!(decoded.message_type(), message.message_type());
+    assert_eq!(decoded.to_bytes(), message.to_bytes());
+});
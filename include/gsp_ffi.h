@@ -0,0 +1,84 @@
+#ifndef GSP_FFI_H
+#define GSP_FFI_H
+
+/* Generated by cbindgen from src/ffi. Do not edit, regenerate instead. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * An opaque frame decoder handle
+ */
+typedef struct GspDecoder GspDecoder;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Encodes a frame with an already encoded payload into `out`.
+ *
+ * Returns the length of the frame, or 0 if `out_capacity` is too small or the payload is longer
+ * than the maximum payload length.
+ *
+ * # Safety
+ *
+ * `payload` must point to `payload_length` readable bytes, and `out` must point to
+ * `out_capacity` writable bytes. Either may be null if its length is 0.
+ */
+size_t gsp_encode_frame(uint16_t message_type,
+                        const uint8_t *payload,
+                        size_t payload_length,
+                        uint8_t *out,
+                        size_t out_capacity);
+
+/**
+ * Creates a new decoder, which must be freed with `gsp_decoder_free`
+ */
+GspDecoder *gsp_decoder_new(void);
+
+/**
+ * Frees a decoder created with `gsp_decoder_new`
+ *
+ * # Safety
+ *
+ * `decoder` must have been returned by `gsp_decoder_new` and not yet freed, or be null.
+ */
+void gsp_decoder_free(GspDecoder *decoder);
+
+/**
+ * Pushes received bytes into the decoder, stopping early if a frame is completed.
+ *
+ * Returns the number of bytes consumed. If it is less than `length`, a frame is ready to be
+ * read with `gsp_decoder_frame`, and the remaining bytes should be pushed again afterwards. Any
+ * previously completed frame is discarded.
+ *
+ * # Safety
+ *
+ * `decoder` must be a valid decoder, and `bytes` must point to `length` readable bytes. `bytes`
+ * may be null if `length` is 0.
+ */
+size_t gsp_decoder_push(GspDecoder *decoder, const uint8_t *bytes, size_t length);
+
+/**
+ * Gets the frame completed by the last call to `gsp_decoder_push`.
+ *
+ * Returns false if no frame was completed. Otherwise the message type and payload are written to
+ * the out parameters, and the payload stays valid until the next call to `gsp_decoder_push` or
+ * `gsp_decoder_free`.
+ *
+ * # Safety
+ *
+ * `decoder` must be a valid decoder, and the out parameters must be valid for writes.
+ */
+bool gsp_decoder_frame(const GspDecoder *decoder,
+                       uint16_t *message_type,
+                       const uint8_t **payload,
+                       size_t *payload_length);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GSP_FFI_H */
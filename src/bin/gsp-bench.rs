@@ -0,0 +1,429 @@
+use generic_serial_protocol::testing::NoisyChannel;
+use generic_serial_protocol::{Framing, Observer, RateLimit, ReceiveError, SerialManager};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage:
+  gsp-bench [options] loopback
+  gsp-bench [options] <sender> <receiver>
+
+Sends frames from one target to the other for a while and reports the frames and bytes a
+second received, the CPU time used and the errors seen.
+
+Targets:
+  loopback       Connect two in-process managers through a simulated link
+  unix:<path>    Connect to a Unix domain socket
+  tcp:<address>  Connect to a TCP server such as ser2net
+  <path>         Open a serial device such as /dev/ttyUSB0 or COM3 (configure the baud rate
+                 beforehand, e.g. with stty or mode)
+
+Options:
+  --size <bytes>             Payload bytes in each frame, at least 4 (default 64)
+  --rate <frames>            Frames sent a second (default as fast as possible)
+  --duration <seconds>       How long to send for (default 5)
+  --framing <framing>        native, slip, cobs, compact, extended, binary or
+                             checked-header (default native)
+  --baud <rate>              Baud rate of the loopback link (default unlimited)
+  --bit-error-rate <rate>    Probability of each bit flipping on the loopback link (default 0)";
+
+/// The message type of the frames sent, which every framing can carry
+const MESSAGE_TYPE: u16 = 0;
+
+/// How long to wait for frames still in flight once sending stops
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Options {
+    size: usize,
+    rate: Option<u32>,
+    duration: Duration,
+    framing: Framing,
+    baud_rate: Option<u32>,
+    bit_error_rate: f64,
+    targets: Vec<String>,
+}
+
+enum Connection {
+    Loopback(generic_serial_protocol::testing::NoisyEnd),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Device(File),
+    Tcp(TcpStream),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Loopback(end) => end.read(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+            Connection::Device(file) => file.read(buf),
+            Connection::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Loopback(end) => end.write(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+            Connection::Device(file) => file.write(buf),
+            Connection::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Loopback(end) => end.flush(),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+            Connection::Device(file) => file.flush(),
+            Connection::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+/// What the receiver has seen so far, shared with the main thread as it may never finish
+#[derive(Default)]
+struct Counters {
+    frames: AtomicU64,
+    payload_bytes: AtomicU64,
+    /// Frames whose sequence number went backwards
+    out_of_order: AtomicU64,
+    /// Frames received with the wrong length or contents, which the framing did not catch
+    corrupted: AtomicU64,
+    decode_errors: AtomicU64,
+    resyncs: AtomicU64,
+    bytes_skipped: AtomicU64,
+}
+
+impl Counters {
+    fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+
+    fn add(counter: &AtomicU64, count: u64) {
+        counter.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// Counts resyncs and skipped bytes on the receiver
+struct ErrorCounter(Arc<Counters>);
+
+impl Observer for ErrorCounter {
+    fn on_resync(&mut self) {
+        Counters::add(&self.0.resyncs, 1);
+    }
+
+    fn on_bytes_skipped(&mut self, count: usize) {
+        Counters::add(&self.0.bytes_skipped, count as u64);
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match parse_options(&args).and_then(|options| run(&options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        size: 64,
+        rate: None,
+        duration: Duration::from_secs(5),
+        framing: Framing::Native,
+        baud_rate: None,
+        bit_error_rate: 0.0,
+        targets: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            options.targets.push(arg.clone());
+            continue;
+        }
+        let value = args.next().ok_or_else(|| USAGE.to_string())?;
+        match arg.as_str() {
+            "--size" => options.size = parse(value)?,
+            "--rate" => options.rate = Some(parse(value)?),
+            "--duration" => options.duration = Duration::from_secs_f64(parse(value)?),
+            "--framing" => options.framing = parse_framing(value)?,
+            "--baud" => options.baud_rate = Some(parse(value)?),
+            "--bit-error-rate" => options.bit_error_rate = parse(value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let is_loopback = options.targets == ["loopback"];
+    if !is_loopback && options.targets.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+    if !is_loopback && (options.baud_rate.is_some() || options.bit_error_rate > 0.0) {
+        return Err("--baud and --bit-error-rate only apply to loopback".to_string());
+    }
+    if options.size < 4 {
+        return Err("--size must be at least 4, to hold the sequence number".to_string());
+    }
+    if !options.framing.fits(MESSAGE_TYPE, options.size) {
+        return Err(format!(
+            "{:?} framing carries at most {} bytes of payload",
+            options.framing,
+            options.framing.max_payload_length()
+        ));
+    }
+    Ok(options)
+}
+
+fn parse<N: std::str::FromStr>(value: &str) -> Result<N, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number: {value}"))
+}
+
+fn parse_framing(value: &str) -> Result<Framing, String> {
+    Ok(match value {
+        "native" => Framing::Native,
+        "slip" => Framing::Slip,
+        "cobs" => Framing::Cobs,
+        "compact" => Framing::Compact,
+        "extended" => Framing::Extended,
+        "binary" => Framing::Binary,
+        "checked-header" => Framing::CheckedHeader,
+        _ => return Err(format!("unknown framing: {value}")),
+    })
+}
+
+fn open(target: &str) -> Result<Connection, String> {
+    if let Some(address) = target.strip_prefix("tcp:") {
+        return TcpStream::connect(address)
+            .map(Connection::Tcp)
+            .map_err(|e| format!("{address}: {e}"));
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = target.strip_prefix("unix:") {
+        return UnixStream::connect(path)
+            .map(Connection::Unix)
+            .map_err(|e| format!("{path}: {e}"));
+    }
+
+    #[cfg(windows)]
+    let path = &if target.starts_with(r"\\") {
+        target.to_string()
+    } else {
+        // COM10 and above can only be opened through the device namespace
+        format!(r"\\.\{target}")
+    };
+    #[cfg(not(windows))]
+    let path = target;
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map(Connection::Device)
+        .map_err(|e| format!("{target}: {e}"))
+}
+
+/// The payload of frame `sequence`: the sequence number followed by bytes derived from it, so
+/// that corruption the framing misses can be spotted
+fn payload(sequence: u32, size: usize) -> Vec<u8> {
+    let mut payload = sequence.to_le_bytes().to_vec();
+    payload.extend((0..size - 4).map(|i| sequence.wrapping_add(i as u32) as u8));
+    payload
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let (sender, receiver) = if options.targets == ["loopback"] {
+        let mut channel = NoisyChannel::new().with_bit_error_probability(options.bit_error_rate);
+        if let Some(baud_rate) = options.baud_rate {
+            channel = channel.with_baud_rate(baud_rate);
+        }
+        let (sender, receiver) = channel.pair();
+        (Connection::Loopback(sender), Connection::Loopback(receiver))
+    } else {
+        (open(&options.targets[0])?, open(&options.targets[1])?)
+    };
+
+    let mut sender = SerialManager::new(sender).with_framing(options.framing);
+    let mut limit = RateLimit::new();
+    if let Some(rate) = options.rate {
+        limit = limit.with_frames_per_second(rate);
+    }
+    // The simulated link never blocks writes, so the sender is held to its baud rate as a UART
+    // would be, rather than queueing far more than it can carry
+    if let Some(baud_rate) = options.baud_rate {
+        limit = limit.with_bytes_per_second(baud_rate / 10);
+    }
+    if limit != RateLimit::new() {
+        sender = sender.with_rate_limit(limit);
+    }
+    let counters = Arc::new(Counters::default());
+    let mut receiver = SerialManager::new(receiver).with_framing(options.framing);
+    receiver.set_observer(ErrorCounter(Arc::clone(&counters)));
+    let size = options.size;
+    {
+        let counters = Arc::clone(&counters);
+        // Left blocked on a read if frames stop arriving, which exiting the process ends
+        thread::spawn(move || receive(&mut receiver, size, &counters));
+    }
+
+    let cpu_start = cpu_time();
+    let start = Instant::now();
+    let mut sequence: u32 = 0;
+    while start.elapsed() < options.duration {
+        sender
+            .send_raw(MESSAGE_TYPE, &payload(sequence, size))
+            .map_err(|e| format!("send failed: {e}"))?;
+        sequence = sequence.wrapping_add(1);
+    }
+    let sent = sender.stats();
+
+    // Waits for frames in flight until they stop arriving
+    let mut received = Counters::get(&counters.frames);
+    let mut last_progress = Instant::now();
+    while received < sent.frames_sent && last_progress.elapsed() < DRAIN_TIMEOUT {
+        thread::sleep(Duration::from_millis(10));
+        let now_received = Counters::get(&counters.frames);
+        if now_received != received {
+            received = now_received;
+            last_progress = Instant::now();
+        }
+    }
+    let elapsed = start.elapsed();
+    let cpu = cpu_time()
+        .zip(cpu_start)
+        .map(|(end, start)| end.saturating_sub(start));
+
+    report(options, &sent, &counters, elapsed, cpu);
+    Ok(())
+}
+
+/// Receives frames until the connection fails, checking each against the expected sequence
+fn receive(receiver: &mut SerialManager<Connection>, size: usize, counters: &Counters) {
+    let mut next_sequence: u32 = 0;
+    loop {
+        let frame = match receiver.receive_raw() {
+            Ok(frame) => frame,
+            Err(ReceiveError::Decode { .. }) => {
+                Counters::add(&counters.decode_errors, 1);
+                continue;
+            }
+            Err(_) => return,
+        };
+        Counters::add(&counters.frames, 1);
+        Counters::add(&counters.payload_bytes, frame.payload.len() as u64);
+        let Some(sequence) = frame
+            .payload
+            .get(..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_le_bytes)
+        else {
+            Counters::add(&counters.corrupted, 1);
+            continue;
+        };
+        if frame.message_type != MESSAGE_TYPE || frame.payload != payload(sequence, size) {
+            Counters::add(&counters.corrupted, 1);
+            continue;
+        }
+        if sequence < next_sequence {
+            Counters::add(&counters.out_of_order, 1);
+        } else {
+            next_sequence = sequence.wrapping_add(1);
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn report(
+    options: &Options,
+    sent: &generic_serial_protocol::Stats,
+    counters: &Counters,
+    elapsed: Duration,
+    cpu: Option<Duration>,
+) {
+    let seconds = elapsed.as_secs_f64();
+    let frames = Counters::get(&counters.frames);
+    let payload_bytes = Counters::get(&counters.payload_bytes);
+    println!(
+        "{:?} framing, {} byte payloads, {:.1} s",
+        options.framing, options.size, seconds
+    );
+    println!("frames sent:      {}", sent.frames_sent);
+    println!("frames received:  {frames}");
+    println!("frames/s:         {:.0}", frames as f64 / seconds);
+    println!(
+        "payload MB/s:     {:.3}",
+        payload_bytes as f64 / seconds / 1_000_000.0
+    );
+    println!(
+        "wire MB/s:        {:.3}",
+        sent.bytes_sent as f64 / seconds / 1_000_000.0
+    );
+    match cpu {
+        Some(cpu) => println!(
+            "cpu:              {:.2} s ({:.0}%)",
+            cpu.as_secs_f64(),
+            cpu.as_secs_f64() / seconds * 100.0
+        ),
+        None => println!("cpu:              unavailable"),
+    }
+    println!(
+        "lost:             {}",
+        sent.frames_sent.saturating_sub(frames)
+    );
+    println!(
+        "out of order:     {}",
+        Counters::get(&counters.out_of_order)
+    );
+    println!("corrupted:        {}", Counters::get(&counters.corrupted));
+    println!(
+        "decode errors:    {}",
+        Counters::get(&counters.decode_errors)
+    );
+    println!("resyncs:          {}", Counters::get(&counters.resyncs));
+    println!(
+        "bytes skipped:    {}",
+        Counters::get(&counters.bytes_skipped)
+    );
+}
+
+/// Returns the user and system CPU time used by the process so far
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the struct it is given
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    // SAFETY: getrusage succeeded, so it filled in the struct
+    let usage = unsafe { usage.assume_init() };
+    let time = |time: libc::timeval| -> Option<Duration> {
+        Some(Duration::new(
+            u64::try_from(time.tv_sec).ok()?,
+            u32::try_from(time.tv_usec).ok()? * 1000,
+        ))
+    };
+    Some(time(usage.ru_utime)? + time(usage.ru_stime)?)
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,381 @@
+use generic_serial_protocol::testing::{SimulatedDevice, SimulatedDeviceHandle};
+use generic_serial_protocol::{
+    message_types, Capabilities, FirmwareReceiver, Message, SerialManager, TryClone, Varint,
+};
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage:
+  gsp-emulate [options] [<target>]
+
+Pretends to be a device, so that host applications can be developed and tested without
+hardware. The device answers Identify with its DeviceInfo, Ping with Pong and Command with a
+Response, accepts firmware updates and streams synthetic telemetry: an F32 temperature and a
+Counter of the readings sent.
+
+Targets:
+  pty            Create a pseudo-terminal and print the path for the host to open (default,
+                 Unix only)
+  unix:<path>    Listen on a Unix domain socket, serving one host at a time
+  tcp:<address>  Listen on a TCP address such as 127.0.0.1:5000, serving one host at a time
+
+Options:
+  --name <name>              Device name reported in DeviceInfo (default gsp-emulate)
+  --telemetry <ms>           Interval between readings, 0 for none (default 1000)
+  --reply-delay <ms>         Delay before answering commands and firmware messages (default 0)
+  --drop-rate <rate>         Probability of each frame sent being dropped (default 0)
+  --bit-error-rate <rate>    Probability of each bit sent flipping (default 0)
+  --reject-firmware          Answer every firmware commit with Status::Error";
+
+/// The device ID reported in DeviceInfo
+const DEVICE_ID: u32 = 0xE401;
+
+struct Options {
+    name: String,
+    telemetry: Duration,
+    reply_delay: Duration,
+    drop_rate: f64,
+    bit_error_rate: f64,
+    reject_firmware: bool,
+    target: String,
+}
+
+/// A connection that drops whole writes and flips bits of the bytes written with the given
+/// probabilities, so that the host's handling of a noisy link can be tested
+struct Faulty<T> {
+    connection: T,
+    drop_rate: f64,
+    bit_error_rate: f64,
+    random: u64,
+}
+
+impl<T> Faulty<T> {
+    fn new(connection: T, options: &Options) -> Self {
+        Self {
+            connection,
+            drop_rate: options.drop_rate,
+            bit_error_rate: options.bit_error_rate,
+            random: 0x4753_5045_4D55_4C41,
+        }
+    }
+
+    /// Returns a uniformly distributed number in `[0, 1)`, from a xorshift generator
+    fn next_random(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        #[allow(clippy::cast_precision_loss)]
+        let fraction = (self.random >> 11) as f64 / (1u64 << 53) as f64;
+        fraction
+    }
+}
+
+impl<T: Read> Read for Faulty<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.connection.read(buf)
+    }
+}
+
+impl<T: Write> Write for Faulty<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.drop_rate > 0.0 && self.next_random() < self.drop_rate {
+            return Ok(buf.len());
+        }
+        if self.bit_error_rate == 0.0 {
+            return self.connection.write(buf);
+        }
+        let mut bytes = buf.to_vec();
+        for byte in &mut bytes {
+            for bit in 0..8 {
+                if self.next_random() < self.bit_error_rate {
+                    *byte ^= 1 << bit;
+                }
+            }
+        }
+        self.connection.write_all(&bytes)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush()
+    }
+}
+
+impl<T: TryClone> TryClone for Faulty<T> {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            connection: self.connection.try_clone()?,
+            drop_rate: self.drop_rate,
+            bit_error_rate: self.bit_error_rate,
+            // A different sequence, so that the clone does not repeat the errors of the original
+            random: self.random.rotate_left(32) ^ 0x9E37_79B9_7F4A_7C15,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match parse_options(&args).and_then(|options| run(&options)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        name: "gsp-emulate".to_string(),
+        telemetry: Duration::from_secs(1),
+        reply_delay: Duration::ZERO,
+        drop_rate: 0.0,
+        bit_error_rate: 0.0,
+        reject_firmware: false,
+        target: "pty".to_string(),
+    };
+    let mut targets = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            targets.push(arg.clone());
+            continue;
+        }
+        if arg == "--reject-firmware" {
+            options.reject_firmware = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| USAGE.to_string())?;
+        match arg.as_str() {
+            "--name" => options.name.clone_from(value),
+            "--telemetry" => options.telemetry = Duration::from_millis(parse(value)?),
+            "--reply-delay" => options.reply_delay = Duration::from_millis(parse(value)?),
+            "--drop-rate" => options.drop_rate = parse(value)?,
+            "--bit-error-rate" => options.bit_error_rate = parse(value)?,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    match targets.as_slice() {
+        [] => (),
+        [target] => options.target.clone_from(target),
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(options)
+}
+
+fn parse<N: std::str::FromStr>(value: &str) -> Result<N, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number: {value}"))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    if options.target == "pty" {
+        return run_pty(options);
+    }
+    if let Some(address) = options.target.strip_prefix("tcp:") {
+        let listener = TcpListener::bind(address).map_err(|e| format!("{address}: {e}"))?;
+        println!("Listening on {}", options.target);
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| format!("{address}: {e}"))?;
+            serve(stream, options)?;
+        }
+        return Ok(());
+    }
+    #[cfg(unix)]
+    if let Some(path) = options.target.strip_prefix("unix:") {
+        let listener = UnixListener::bind(path).map_err(|e| format!("{path}: {e}"))?;
+        println!("Listening on {}", options.target);
+        for stream in listener.incoming() {
+            let stream = stream.map_err(|e| format!("{path}: {e}"))?;
+            serve(stream, options)?;
+        }
+        return Ok(());
+    }
+    Err(USAGE.to_string())
+}
+
+/// Emulates the device on a pseudo-terminal until interrupted
+#[cfg(unix)]
+fn run_pty(options: &Options) -> Result<(), String> {
+    let (master, path) = pty::open().map_err(|e| format!("openpty: {e}"))?;
+    println!("Emulating {} on {}", options.name, path.display());
+    // The slave end stays open here as well, so that hosts can close and reopen it
+    let handle = spawn(master, options).map_err(|e| format!("pty: {e}"))?;
+    handle.join().map_err(|e| format!("pty: {e}"))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn run_pty(_options: &Options) -> Result<(), String> {
+    Err("pseudo-terminals are only available on Unix, use a tcp: target".to_string())
+}
+
+/// Emulates the device for one host, until it closes the connection
+fn serve<T>(connection: T, options: &Options) -> Result<(), String>
+where
+    T: Read + Write + TryClone + Send + 'static,
+{
+    println!("Host connected");
+    let received = spawn(connection, options)
+        .and_then(SimulatedDeviceHandle::join)
+        .map_err(|e| e.to_string())?;
+    println!(
+        "Host disconnected after sending {} messages",
+        received.len()
+    );
+    Ok(())
+}
+
+fn spawn<T>(connection: T, options: &Options) -> io::Result<SimulatedDeviceHandle>
+where
+    T: Read + Write + TryClone + Send + 'static,
+{
+    let info = message_types::DeviceInfo {
+        device_id: DEVICE_ID,
+        name: options.name.clone(),
+        hw_rev: 1,
+        fw_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: Capabilities::PING | Capabilities::FIRMWARE_UPDATE,
+    };
+    let mut firmware = FirmwareReceiver::new();
+    let reject_firmware = options.reject_firmware;
+    let mut device = SimulatedDevice::new()
+        .with_device_info(info)
+        .respond(
+            |message| matches!(message, Message::Command(_)),
+            options.reply_delay,
+            |message| {
+                let Message::Command(command) = message else {
+                    unreachable!("only commands match");
+                };
+                println!("Command {} with {} bytes", command.id, command.args.len());
+                Message::Response(message_types::Response {
+                    id: command.id,
+                    status: message_types::Status::Ok,
+                    payload: Vec::new(),
+                })
+            },
+        )
+        .respond(
+            |message| {
+                matches!(
+                    message,
+                    Message::FirmwareBegin(_)
+                        | Message::FirmwareChunk(_)
+                        | Message::FirmwareCommit(_)
+                )
+            },
+            options.reply_delay,
+            move |message| handle_firmware(&mut firmware, message, reject_firmware),
+        );
+
+    if !options.telemetry.is_zero() {
+        let interval = options.telemetry.as_secs_f32();
+        let mut elapsed = 0.0f32;
+        let mut readings = 0u64;
+        device = device
+            .emit_every(options.telemetry, move || {
+                // Swings by two degrees a minute
+                elapsed += interval;
+                Message::F32(message_types::F32 {
+                    num: 25.0 + 2.0 * (elapsed / 60.0 * std::f32::consts::TAU).sin(),
+                })
+            })
+            .emit_every(options.telemetry, move || {
+                readings += 1;
+                Message::Counter(message_types::Counter {
+                    count: Varint(readings),
+                })
+            });
+    }
+    device.spawn(SerialManager::new(Faulty::new(connection, options)))
+}
+
+/// Passes a firmware message to the receiver, rejecting commits if asked to
+fn handle_firmware(firmware: &mut FirmwareReceiver, message: &Message, reject: bool) -> Message {
+    if let (Message::FirmwareCommit(_), true) = (message, reject) {
+        println!("Firmware rejected");
+        return Message::Status(message_types::Status::Error);
+    }
+    let reply = firmware
+        .handle(message)
+        .expect("only firmware messages are passed on");
+    match (message, &reply) {
+        (Message::FirmwareBegin(begin), _) => {
+            println!("Firmware update of {} bytes started", begin.size);
+        }
+        (Message::FirmwareCommit(_), Message::Status(message_types::Status::Ok)) => {
+            let size = firmware.image().map_or(0, <[u8]>::len);
+            println!("Firmware image of {size} bytes committed");
+        }
+        (Message::FirmwareCommit(_), _) => println!("Firmware image incomplete or corrupt"),
+        _ => (),
+    }
+    reply
+}
+
+#[cfg(unix)]
+mod pty {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::os::fd::{FromRawFd, RawFd};
+    use std::path::PathBuf;
+    use std::ptr;
+
+    /// Opens a pseudo-terminal pair in raw mode, returning the master, the slave and the
+    /// slave's path
+    pub fn open() -> io::Result<(File, PathBuf)> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        // SAFETY: the name, termios and window size arguments may be null
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty succeeded, so both descriptors are open and owned by nothing else
+        let (master_file, slave_file) =
+            unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) };
+
+        // SAFETY: the termios is initialized by tcgetattr before it is read
+        unsafe {
+            let mut termios = MaybeUninit::uninit();
+            if libc::tcgetattr(slave, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut termios = termios.assume_init();
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(slave, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        // SAFETY: ttyname returns null or a pointer to a nul-terminated string, which is
+        // copied before anything else can call it
+        let name = unsafe { libc::ttyname(slave) };
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: checked to be non-null above
+        let path = PathBuf::from(unsafe { CStr::from_ptr(name) }.to_string_lossy().as_ref());
+        // Kept open for as long as the process runs
+        std::mem::forget(slave_file);
+        Ok((master_file, path))
+    }
+}
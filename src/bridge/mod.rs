@@ -0,0 +1,256 @@
+use crate::codec::Frame;
+use crate::errors::{BridgeError, ReceiveError};
+use crate::serial_manager::{SerialManager, Transport, TryClone};
+use std::fmt;
+use std::io;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Reader = Box<dyn FnMut() -> Result<Frame, ReceiveError> + Send>;
+type Writer = Arc<Mutex<dyn FnMut(&Frame) -> io::Result<()> + Send>>;
+type Filter = Box<dyn Fn(&Frame) -> bool + Send + Sync>;
+
+/// The length of the header frames carry over trunks, the origin ID and hop count
+const TRUNK_HEADER_LENGTH: usize = 3;
+
+/// How many trunks a frame crosses by default before it is no longer forwarded to others
+const DEFAULT_MAX_HOPS: u8 = 8;
+
+/// Identifies a link added to a [`Bridge`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LinkId(usize);
+
+impl fmt::Display for LinkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "link {}", self.0)
+    }
+}
+
+struct Route {
+    from: LinkId,
+    to: LinkId,
+    filter: Filter,
+}
+
+/// Forwards frames between links according to routes, such as in a serial-to-serial gateway.
+///
+/// Frames are forwarded with [`receive_raw`](SerialManager::receive_raw) and
+/// [`send_raw`](SerialManager::send_raw), so payloads are never decoded and message types the
+/// bridge does not know about are forwarded too. Payloads are forwarded as they are, so links
+/// that use a different [`Endianness`](crate::Endianness) only agree on the message type.
+///
+/// ```no_run
+/// # use generic_serial_protocol::{message_types, Bridge, MessageType, SerialManager};
+/// # let (host, device) = (std::fs::File::open("").unwrap(), std::fs::File::open("").unwrap());
+/// let mut bridge = Bridge::new();
+/// let host = bridge.add_link(SerialManager::new(host)).unwrap();
+/// let device = bridge.add_link(SerialManager::new(device)).unwrap();
+/// bridge.route(host, device, |_| true);
+/// bridge.route(device, host, |frame| frame.message_type != message_types::Log::ID);
+/// bridge.run().unwrap();
+/// ```
+///
+/// Bridges linked into a ring or mesh would forward broadcasts around it forever, so links to
+/// other bridges are marked as [trunks](Self::trunk), on which frames carry the ID of the bridge
+/// they entered through and how many trunks they have crossed. A bridge drops the frames that
+/// return to it, and stops forwarding a frame to trunks once it has crossed
+/// [`max_hops`](Self::with_max_hops) of them.
+pub struct Bridge {
+    readers: Vec<Reader>,
+    writers: Vec<Writer>,
+    trunks: Vec<bool>,
+    routes: Vec<Route>,
+    origin: u16,
+    max_hops: u8,
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Self {
+            readers: Vec::new(),
+            writers: Vec::new(),
+            trunks: Vec::new(),
+            routes: Vec::new(),
+            origin: 0,
+            max_hops: DEFAULT_MAX_HOPS,
+        }
+    }
+}
+
+/// Where a frame entered the bridged topology and how many trunks it has crossed
+#[derive(Debug, Clone, Copy)]
+struct Hops {
+    origin: u16,
+    count: u8,
+}
+
+impl Hops {
+    /// Splits the header off a frame received over a trunk, or returns `None` if it is too short
+    fn strip(frame: &mut Frame) -> Option<Self> {
+        if frame.payload.len() < TRUNK_HEADER_LENGTH {
+            return None;
+        }
+        let header: Vec<u8> = frame.payload.drain(..TRUNK_HEADER_LENGTH).collect();
+        Some(Self {
+            origin: u16::from_le_bytes([header[0], header[1]]),
+            count: header[2],
+        })
+    }
+
+    /// Returns `frame` with the header for crossing one more trunk
+    fn tag(self, frame: &Frame) -> Frame {
+        let mut payload = Vec::with_capacity(TRUNK_HEADER_LENGTH + frame.payload.len());
+        payload.extend_from_slice(&self.origin.to_le_bytes());
+        payload.push(self.count.saturating_add(1));
+        payload.extend_from_slice(&frame.payload);
+        Frame {
+            message_type: frame.message_type,
+            payload,
+        }
+    }
+}
+
+impl Bridge {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Identifies this bridge in the frames it forwards to [trunks](Self::trunk), 0 by default,
+    /// which must differ from every other bridge's for loops to be detected
+    #[must_use]
+    pub fn with_origin(mut self, origin: u16) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Forwards frames to trunks until they have crossed `max_hops` trunks, 8 by default, beyond
+    /// which they are only forwarded to other links
+    #[must_use]
+    pub fn with_max_hops(mut self, max_hops: u8) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Adds a link, which receives with `manager` and its configuration and observer, and sends
+    /// through a second handle to its connection
+    pub fn add_link<T>(&mut self, mut manager: SerialManager<T>) -> io::Result<LinkId>
+    where
+        T: Transport + TryClone + Send + 'static,
+    {
+        let mut writer = manager.try_clone_writer()?;
+        self.readers.push(Box::new(move || manager.receive_raw()));
+        self.writers.push(Arc::new(Mutex::new(move |frame: &Frame| {
+            writer.send_raw(frame.message_type, &frame.payload)
+        })));
+        self.trunks.push(false);
+        Ok(LinkId(self.writers.len() - 1))
+    }
+
+    /// Marks `link` as a trunk to another bridge, on which every frame is prefixed with the ID
+    /// of the bridge it entered through and the number of trunks it has crossed, for loop
+    /// detection
+    ///
+    /// Both ends of a trunk must be marked. Route filters see frames without the prefix, and
+    /// frames too short to hold one are dropped as malformed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `link` was not added to this bridge.
+    pub fn trunk(&mut self, link: LinkId) {
+        self.trunks[link.0] = true;
+    }
+
+    /// Forwards the frames received on `from` for which `filter` returns true to `to`
+    ///
+    /// A frame is forwarded once by every matching route, so frames can be copied to several
+    /// links.
+    pub fn route(
+        &mut self,
+        from: LinkId,
+        to: LinkId,
+        filter: impl Fn(&Frame) -> bool + Send + Sync + 'static,
+    ) {
+        self.routes.push(Route {
+            from,
+            to,
+            filter: Box::new(filter),
+        });
+    }
+
+    /// Receives on every link in its own thread and forwards frames until a link fails
+    ///
+    /// Malformed frames are dropped. The error of the first link to fail is returned, and the
+    /// other links keep forwarding until they fail too. A link whose receiving is cancelled
+    /// through a [`CancelToken`](crate::CancelToken) stops without an error, so once every link
+    /// has been cancelled this returns `Ok`. Without any links, this returns immediately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread panicked while sending.
+    pub fn run(self) -> Result<(), BridgeError> {
+        let routes = Arc::new(self.routes);
+        let trunks = Arc::new(self.trunks);
+        let (origin, max_hops) = (self.origin, self.max_hops);
+        let (error_sender, error_receiver) = mpsc::channel();
+
+        for (index, mut reader) in self.readers.into_iter().enumerate() {
+            let link = LinkId(index);
+            let routes = Arc::clone(&routes);
+            let trunks = Arc::clone(&trunks);
+            let writers = self.writers.clone();
+            let errors = error_sender.clone();
+            thread::spawn(move || loop {
+                let mut frame = match reader() {
+                    Ok(frame) => frame,
+                    Err(ReceiveError::Io(source)) => {
+                        let _ = errors.send(BridgeError::Receive { link, source });
+                        break;
+                    }
+                    // Cancelled on purpose, so the link stops without an error
+                    Err(ReceiveError::Cancelled) => break,
+                    Err(_) => continue,
+                };
+                let hops = if trunks[index] {
+                    match Hops::strip(&mut frame) {
+                        // Back where it entered, so it has gone around a loop
+                        Some(hops) if hops.origin == origin => continue,
+                        Some(hops) => hops,
+                        None => continue,
+                    }
+                } else {
+                    Hops { origin, count: 0 }
+                };
+                for route in routes.iter().filter(|route| route.from == link) {
+                    if !(route.filter)(&frame) {
+                        continue;
+                    }
+                    let result = if trunks[route.to.0] {
+                        if hops.count >= max_hops {
+                            continue;
+                        }
+                        writers[route.to.0].lock().unwrap()(&hops.tag(&frame))
+                    } else {
+                        writers[route.to.0].lock().unwrap()(&frame)
+                    };
+                    if let Err(source) = result {
+                        let _ = errors.send(BridgeError::Send {
+                            link: route.to,
+                            source,
+                        });
+                    }
+                }
+            });
+        }
+        drop(error_sender);
+
+        match error_receiver.recv() {
+            Ok(error) => Err(error),
+            Err(mpsc::RecvError) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
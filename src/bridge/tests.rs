@@ -0,0 +1,103 @@
+use super::*;
+use crate::message::{message_types, Message};
+use crate::payload::MessageType;
+use crate::test_util::{stream_pair, TestStream};
+
+/// Adds a link to `bridge`, returning its ID and a manager for the peer at the other end
+fn add_link(bridge: &mut Bridge) -> (LinkId, SerialManager<TestStream>) {
+    let (stream1, stream2) = stream_pair();
+    let link = bridge.add_link(SerialManager::new(stream1)).unwrap();
+    (link, SerialManager::new(stream2))
+}
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+#[test]
+fn test_forwards_by_route() {
+    let mut bridge = Bridge::new();
+    let (host, mut host_peer) = add_link(&mut bridge);
+    let (device, mut device_peer) = add_link(&mut bridge);
+    bridge.route(host, device, |_| true);
+    bridge.route(device, host, |frame| {
+        frame.message_type == message_types::Status::ID
+    });
+    let bridge = thread::spawn(move || bridge.run());
+
+    host_peer.send(u8_message(1)).unwrap();
+    assert_eq!(device_peer.receive().unwrap(), u8_message(1));
+
+    device_peer.send(u8_message(2)).unwrap();
+    let status = Message::Status(message_types::Status::Ok);
+    device_peer.send(status.clone()).unwrap();
+    assert_eq!(host_peer.receive().unwrap(), status);
+
+    drop(host_peer);
+    let error = bridge.join().unwrap().unwrap_err();
+    assert!(matches!(error, BridgeError::Receive { link, .. } if link == host));
+}
+
+#[test]
+fn test_forwards_raw_frames() {
+    let mut bridge = Bridge::new();
+    let (first, mut first_peer) = add_link(&mut bridge);
+    let (second, second_peer) = add_link(&mut bridge);
+    let (third, third_peer) = add_link(&mut bridge);
+    bridge.route(first, second, |_| true);
+    bridge.route(first, third, |_| true);
+    thread::spawn(move || bridge.run());
+
+    // Neither a built-in message type nor a valid payload for one
+    first_peer.send_raw(0x1234, &[0x58, 0x42]).unwrap();
+    first_peer.send_raw(message_types::U8::ID, &[]).unwrap();
+    for mut peer in [second_peer, third_peer] {
+        let frame = peer.receive_raw().unwrap();
+        assert_eq!(
+            (frame.message_type, frame.payload),
+            (0x1234, vec![0x58, 0x42])
+        );
+        let frame = peer.receive_raw().unwrap();
+        assert_eq!(
+            (frame.message_type, frame.payload),
+            (message_types::U8::ID, vec![])
+        );
+    }
+}
+
+#[test]
+fn test_run_without_links() {
+    assert!(Bridge::new().run().is_ok());
+}
+
+#[test]
+fn test_trunks_break_loops() {
+    let mut bridge = Bridge::new().with_origin(1).with_max_hops(2);
+    let (local, mut local_peer) = add_link(&mut bridge);
+    let (trunk_in, mut trunk_in_peer) = add_link(&mut bridge);
+    let (trunk_out, mut trunk_out_peer) = add_link(&mut bridge);
+    bridge.trunk(trunk_in);
+    bridge.trunk(trunk_out);
+    bridge.route(local, trunk_out, |_| true);
+    bridge.route(trunk_in, local, |_| true);
+    bridge.route(trunk_in, trunk_out, |_| true);
+    thread::spawn(move || bridge.run());
+
+    // Entering through this bridge
+    local_peer.send(u8_message(1)).unwrap();
+    let frame = trunk_out_peer.receive_raw().unwrap();
+    assert_eq!(frame.payload, [1, 0, 1, 1]);
+
+    // Origin 2 after one hop, then at the hop limit, then back at this bridge
+    for (origin, hops, num) in [(2, 1, 2), (2, 2, 3), (1, 1, 4), (2, 0, 5)] {
+        trunk_in_peer
+            .send_raw(message_types::U8::ID, &[origin, 0, hops, num])
+            .unwrap();
+    }
+    for num in [2, 3, 5] {
+        assert_eq!(local_peer.receive().unwrap(), u8_message(num));
+    }
+    for payload in [[2, 0, 2, 2], [2, 0, 1, 5]] {
+        assert_eq!(trunk_out_peer.receive_raw().unwrap().payload, payload);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::capture::{CapturedFrame, Direction};
+use crate::rotation::RotatingFiles;
+use crate::time::{SystemTime, UNIX_EPOCH};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The most bytes written on each line of a log
+const BYTES_PER_LINE: usize = 32;
+
+/// A record of every byte a [`SerialManager`](crate::SerialManager) sends and receives, before
+/// any decoding, set with
+/// [`SerialManager::with_byte_log`](crate::SerialManager::with_byte_log), kept in files that are
+/// rotated by size so that long-running gateways keep a bounded record.
+///
+/// Each write and each read is logged on lines of up to 32 bytes in hex, starting with the time
+/// in seconds since the Unix epoch and `TX` or `RX`:
+///
+/// ```text
+/// 1718000000.123456 TX 58 04 00 23 00 07 00
+/// ```
+///
+/// Files are named `<prefix>-<microseconds since the Unix epoch>.log` after the time they were
+/// started. Once the current file would grow beyond the maximum size, a new one is started, and
+/// the oldest files with the prefix in the directory are deleted to keep at most the maximum
+/// number, including files left by earlier runs.
+///
+/// As with a [`PcapngWriter`](crate::PcapngWriter) observer, the first IO error stops the log,
+/// as there is no way to report it to the caller.
+#[derive(Debug)]
+pub struct ByteLog {
+    files: RotatingFiles,
+    max_file_size: u64,
+    file: Option<File>,
+    file_size: u64,
+    failed: bool,
+}
+
+impl ByteLog {
+    /// Creates a log in `directory`, which is created when the first bytes are logged if it does
+    /// not exist, keeping up to 10 files of 1 MiB named with the prefix `gsp`
+    #[must_use]
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            files: RotatingFiles::new(directory.into(), "gsp", "log", 10),
+            max_file_size: 1024 * 1024,
+            file: None,
+            file_size: 0,
+            failed: false,
+        }
+    }
+
+    /// Names files starting with `prefix`, so that several logs can share a directory
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.files.prefix = prefix.into();
+        self
+    }
+
+    /// Starts a new file once the current one would grow beyond `max_file_size` bytes
+    #[must_use]
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Keeps at most `max_files` files, at least 1, deleting the oldest
+    #[must_use]
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.files.max_files = max_files.max(1);
+        self
+    }
+
+    /// Logs bytes travelling in `direction`, timestamped with the current time
+    pub fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let direction = match direction {
+            Direction::Sent => "TX",
+            Direction::Received => "RX",
+        };
+        let mut text = String::new();
+        for chunk in bytes.chunks(BYTES_PER_LINE) {
+            let _ = write!(
+                text,
+                "{}.{:06} {direction}",
+                timestamp.as_secs(),
+                timestamp.subsec_micros()
+            );
+            for byte in chunk {
+                let _ = write!(text, " {byte:02X}");
+            }
+            text.push('\n');
+        }
+
+        let length = text.len() as u64;
+        if self.file.is_none()
+            || (self.file_size > 0 && self.file_size + length > self.max_file_size)
+        {
+            self.file = Some(self.files.start()?.1);
+            self.file_size = 0;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(text.as_bytes())?;
+            self.file_size += length;
+        }
+        Ok(())
+    }
+
+    /// Logs bytes, stopping the log on the first error
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn observe(&mut self, direction: Direction, bytes: &[u8]) {
+        if self.failed || bytes.is_empty() {
+            return;
+        }
+        if let Err(e) = self.record(direction, bytes) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %e, "failed to write byte log, stopping log");
+            self.failed = true;
+        }
+    }
+}
+
+/// Reads back the lines of a [`ByteLog`], each as the bytes of a [`CapturedFrame`] with its time
+/// and direction
+///
+/// Unlike a pcapng capture's, the bytes of a line need not be a whole frame, as they are logged
+/// as they were written and read. Fails with [`io::ErrorKind::InvalidData`] on a line that is
+/// not in the log's format. Blank lines are skipped.
+pub fn read_byte_log(reader: impl BufRead) -> io::Result<Vec<CapturedFrame>> {
+    let invalid = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid byte log line: {line}"),
+        )
+    };
+    let mut frames = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut fields = line.split_ascii_whitespace();
+        let Some(time) = fields.next() else {
+            continue;
+        };
+        let timestamp = time
+            .split_once('.')
+            .and_then(|(seconds, micros)| {
+                let micros: u32 = micros.parse().ok().filter(|_| micros.len() == 6)?;
+                Some(Duration::new(seconds.parse().ok()?, micros * 1000))
+            })
+            .ok_or_else(|| invalid(&line))?;
+        let direction = match fields.next() {
+            Some("TX") => Direction::Sent,
+            Some("RX") => Direction::Received,
+            _ => return Err(invalid(&line)),
+        };
+        let data = fields
+            .map(|byte| {
+                u8::from_str_radix(byte, 16)
+                    .ok()
+                    .filter(|_| byte.len() == 2)
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| invalid(&line))?;
+        frames.push(CapturedFrame {
+            timestamp,
+            direction: Some(direction),
+            data,
+        });
+    }
+    Ok(frames)
+}
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle that aborts a [`SerialManager`](crate::SerialManager) receiving from any thread,
+/// returned by [`SerialManager::cancel_token`](crate::SerialManager::cancel_token)
+///
+/// Once cancelled, receives fail with [`ReceiveError::Cancelled`](crate::ReceiveError::Cancelled)
+/// at the next read from the connection, until the token is reset. A read that is already
+/// blocked cannot be interrupted portably, so a receive waiting on a connection without a read
+/// timeout only returns once the next bytes arrive. Give the connection a read timeout as short
+/// as shutdown must be prompt: a read that times out after the token is cancelled fails with
+/// `Cancelled` rather than the timeout.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Makes receives fail with [`ReceiveError::Cancelled`](crate::ReceiveError::Cancelled)
+    /// until [`reset`](Self::reset)
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Lets receives continue, picking up any frame that was cut short by the cancellation
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
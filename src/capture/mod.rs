@@ -0,0 +1,302 @@
+use crate::observer::Observer;
+use crate::time::{SystemTime, UNIX_EPOCH};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// `LINKTYPE_USER0`, reserved for private use so Wireshark can be configured with a custom dissector
+const LINKTYPE_USER0: u16 = 147;
+
+const OPT_END_OF_OPT: u16 = 0;
+const OPT_EPB_FLAGS: u16 = 2;
+
+const EPB_FLAGS_INBOUND: u32 = 1;
+const EPB_FLAGS_OUTBOUND: u32 = 2;
+
+/// The direction a captured frame travelled in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A frame read back from a pcapng capture
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CapturedFrame {
+    /// Time the frame was captured, since the Unix epoch
+    pub timestamp: Duration,
+    /// The direction recorded in the `epb_flags` option, if any
+    pub direction: Option<Direction>,
+    /// The frame exactly as it appeared on the wire
+    pub data: Vec<u8>,
+}
+
+/// Records frames to a pcapng capture that can be opened with Wireshark.
+///
+/// Every frame is written as an Enhanced Packet Block with a microsecond timestamp and an
+/// `epb_flags` option holding its direction. Frames are stored exactly as they appear on the
+/// wire, using the `USER0` link type.
+///
+/// `PcapngWriter` implements [`Observer`], so it can be registered on a
+/// [`SerialManager`](crate::SerialManager) to capture all of its traffic:
+///
+/// ```no_run
+/// # use generic_serial_protocol::{PcapngWriter, SerialManager};
+/// # let stream = std::io::Cursor::new(Vec::new());
+/// let mut manager = SerialManager::new(stream);
+/// manager.set_observer(PcapngWriter::create("capture.pcapng").unwrap());
+/// ```
+///
+/// Each frame is written with a single call to `write_all` so that a capture file is as complete
+/// as possible if the process dies. When used as an observer, the first IO error stops the
+/// capture, as there is no way to report it to the caller.
+pub struct PcapngWriter<W>
+where
+    W: Write,
+{
+    writer: W,
+    failed: bool,
+}
+
+impl PcapngWriter<File> {
+    /// Creates a capture file at `path`, truncating it if it already exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+
+impl<W> PcapngWriter<W>
+where
+    W: Write,
+{
+    /// Writes the section header and interface description to `writer`
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut body = Vec::new();
+        body.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend(1u16.to_le_bytes()); // Major version
+        body.extend(0u16.to_le_bytes()); // Minor version
+        body.extend((-1i64).to_le_bytes()); // Section length not specified
+        writer.write_all(&block(SECTION_HEADER_BLOCK, &body))?;
+
+        let mut body = Vec::new();
+        body.extend(LINKTYPE_USER0.to_le_bytes());
+        body.extend(0u16.to_le_bytes()); // Reserved
+        body.extend(0u32.to_le_bytes()); // No snapshot length limit
+        writer.write_all(&block(INTERFACE_DESCRIPTION_BLOCK, &body))?;
+        writer.flush()?;
+
+        Ok(Self {
+            writer,
+            failed: false,
+        })
+    }
+
+    /// Writes a single frame to the capture, timestamped with the current time
+    pub fn write_frame(&mut self, direction: Direction, frame: &[u8]) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        #[allow(clippy::cast_possible_truncation)]
+        let micros = timestamp.as_micros() as u64;
+        #[allow(clippy::cast_possible_truncation)]
+        let length = frame.len() as u32;
+
+        let mut body = Vec::new();
+        body.extend(0u32.to_le_bytes()); // Interface ID
+        #[allow(clippy::cast_possible_truncation)]
+        body.extend(((micros >> 32) as u32).to_le_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        body.extend((micros as u32).to_le_bytes());
+        body.extend(length.to_le_bytes()); // Captured length
+        body.extend(length.to_le_bytes()); // Original length
+        body.extend(frame);
+        pad_to_u32(&mut body);
+
+        let flags = match direction {
+            Direction::Received => EPB_FLAGS_INBOUND,
+            Direction::Sent => EPB_FLAGS_OUTBOUND,
+        };
+        body.extend(OPT_EPB_FLAGS.to_le_bytes());
+        body.extend(4u16.to_le_bytes());
+        body.extend(flags.to_le_bytes());
+        body.extend(OPT_END_OF_OPT.to_le_bytes());
+        body.extend(0u16.to_le_bytes());
+
+        self.writer
+            .write_all(&block(ENHANCED_PACKET_BLOCK, &body))?;
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn observe(&mut self, direction: Direction, frame: &[u8]) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = self.write_frame(direction, frame) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %e, "failed to write capture, stopping capture");
+            self.failed = true;
+        }
+    }
+}
+
+impl<W> Observer for PcapngWriter<W>
+where
+    W: Write,
+{
+    fn on_raw_frame_sent(&mut self, frame: &[u8]) {
+        self.observe(Direction::Sent, frame);
+    }
+
+    fn on_raw_frame_received(&mut self, frame: &[u8]) {
+        self.observe(Direction::Received, frame);
+    }
+}
+
+/// Reads all frames from a pcapng capture, such as one written by [`PcapngWriter`]
+///
+/// Captures in either byte order are supported. Blocks other than Enhanced Packet Blocks are
+/// skipped, and timestamps are assumed to use the default microsecond resolution.
+pub fn read_pcapng(mut reader: impl Read) -> io::Result<Vec<CapturedFrame>> {
+    let mut frames = Vec::new();
+    let mut little_endian = true;
+
+    loop {
+        let mut header = [0u8; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(frames),
+            Err(e) => return Err(e),
+        }
+
+        // The section header block type is a palindrome, but its length can only be read once
+        // the byte order magic is known
+        if u32::from_le_bytes(bytes_at(&header, 0)?) == SECTION_HEADER_BLOCK {
+            let mut magic = [0u8; 4];
+            reader.read_exact(&mut magic)?;
+            little_endian = match u32::from_le_bytes(magic) {
+                BYTE_ORDER_MAGIC => true,
+                magic if magic == BYTE_ORDER_MAGIC.swap_bytes() => false,
+                _ => return Err(invalid_data("invalid byte order magic")),
+            };
+            let total_length = block_length(&header, little_endian)?;
+            io::copy(
+                &mut (&mut reader).take(total_length as u64 - 12),
+                &mut io::sink(),
+            )?;
+            continue;
+        }
+
+        let block_type = u32_at(&header, 0, little_endian)?;
+        let total_length = block_length(&header, little_endian)?;
+        let mut body = vec![0u8; total_length - 8];
+        reader.read_exact(&mut body)?;
+
+        if block_type == ENHANCED_PACKET_BLOCK {
+            // Drop the trailing copy of the total length
+            body.truncate(body.len() - 4);
+            frames.push(parse_enhanced_packet(&body, little_endian)?);
+        }
+    }
+}
+
+fn parse_enhanced_packet(body: &[u8], little_endian: bool) -> io::Result<CapturedFrame> {
+    let timestamp_high = u64::from(u32_at(body, 4, little_endian)?);
+    let timestamp_low = u64::from(u32_at(body, 8, little_endian)?);
+    let captured_length = u32_at(body, 12, little_endian)? as usize;
+    let data = body
+        .get(20..20 + captured_length)
+        .ok_or_else(|| invalid_data("truncated packet data"))?
+        .to_vec();
+
+    let mut direction = None;
+    let mut offset = 20 + captured_length.next_multiple_of(4);
+    while offset + 4 <= body.len() {
+        let code = u16_at(body, offset, little_endian)?;
+        let length = u16_at(body, offset + 2, little_endian)? as usize;
+        if code == OPT_END_OF_OPT {
+            break;
+        }
+        if code == OPT_EPB_FLAGS && length == 4 {
+            direction = match u32_at(body, offset + 4, little_endian)? & 0b11 {
+                EPB_FLAGS_INBOUND => Some(Direction::Received),
+                EPB_FLAGS_OUTBOUND => Some(Direction::Sent),
+                _ => None,
+            };
+        }
+        offset += 4 + length.next_multiple_of(4);
+    }
+
+    Ok(CapturedFrame {
+        timestamp: Duration::from_micros(timestamp_high << 32 | timestamp_low),
+        direction,
+        data,
+    })
+}
+
+fn block_length(header: &[u8], little_endian: bool) -> io::Result<usize> {
+    let length = u32_at(header, 4, little_endian)? as usize;
+    if length < 12 || !length.is_multiple_of(4) {
+        return Err(invalid_data("invalid block length"));
+    }
+    Ok(length)
+}
+
+fn bytes_at<const N: usize>(bytes: &[u8], offset: usize) -> io::Result<[u8; N]> {
+    bytes
+        .get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid_data("truncated block"))
+}
+
+fn u16_at(bytes: &[u8], offset: usize, little_endian: bool) -> io::Result<u16> {
+    let bytes = bytes_at(bytes, offset)?;
+    Ok(if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    })
+}
+
+fn u32_at(bytes: &[u8], offset: usize, little_endian: bool) -> io::Result<u32> {
+    let bytes = bytes_at(bytes, offset)?;
+    Ok(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn pad_to_u32(bytes: &mut Vec<u8>) {
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+}
+
+/// Wraps a block body with its type and the leading and trailing total length
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    #[allow(clippy::cast_possible_truncation)]
+    let total_length = (body.len() + 12) as u32;
+    let mut bytes = Vec::with_capacity(body.len() + 12);
+    bytes.extend(block_type.to_le_bytes());
+    bytes.extend(total_length.to_le_bytes());
+    bytes.extend(body);
+    bytes.extend(total_length.to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests;
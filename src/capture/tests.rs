@@ -0,0 +1,82 @@
+use super::*;
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn test_header_blocks() {
+    let capture = PcapngWriter::new(Vec::new()).unwrap().into_inner();
+
+    // Section header block
+    assert_eq!(read_u32(&capture, 0), SECTION_HEADER_BLOCK);
+    assert_eq!(read_u32(&capture, 4), 28);
+    assert_eq!(read_u32(&capture, 8), BYTE_ORDER_MAGIC);
+    assert_eq!(read_u32(&capture, 24), 28);
+
+    // Interface description block
+    assert_eq!(read_u32(&capture, 28), INTERFACE_DESCRIPTION_BLOCK);
+    assert_eq!(read_u32(&capture, 32), 20);
+    assert_eq!(&capture[36..38], &LINKTYPE_USER0.to_le_bytes());
+    assert_eq!(read_u32(&capture, 44), 20);
+
+    assert_eq!(capture.len(), 48);
+}
+
+#[test]
+fn test_enhanced_packet_blocks() {
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    let frame = [0x58, 0x02, 0x00, 0x04, 0x00];
+    writer.on_raw_frame_sent(&frame);
+    writer.on_raw_frame_received(&frame);
+    let capture = writer.into_inner();
+
+    // 5 byte frame padded to 8, plus the flags and end of options
+    let block_length = 12 + 20 + 8 + 8 + 4;
+    let mut offset = 48;
+    for expected_flags in [EPB_FLAGS_OUTBOUND, EPB_FLAGS_INBOUND] {
+        assert_eq!(read_u32(&capture, offset), ENHANCED_PACKET_BLOCK);
+        assert_eq!(read_u32(&capture, offset + 4), block_length);
+        assert_eq!(read_u32(&capture, offset + 20), 5); // Captured length
+        assert_eq!(read_u32(&capture, offset + 24), 5); // Original length
+        assert_eq!(&capture[offset + 28..offset + 33], &frame);
+        assert_eq!(&capture[offset + 33..offset + 36], &[0, 0, 0]); // Padding
+        assert_eq!(
+            &capture[offset + 36..offset + 38],
+            &OPT_EPB_FLAGS.to_le_bytes()
+        );
+        assert_eq!(read_u32(&capture, offset + 40), expected_flags);
+        assert_eq!(read_u32(&capture, offset + 48), block_length);
+        offset += block_length as usize;
+    }
+    assert_eq!(capture.len(), offset);
+}
+
+#[test]
+fn test_read_pcapng_roundtrip() {
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    writer
+        .write_frame(Direction::Sent, &[0x58, 0x02, 0x00, 0x04, 0x00])
+        .unwrap();
+    writer
+        .write_frame(Direction::Received, &[0x58, 0x03, 0x00, 0x01, 0x00, 0x57])
+        .unwrap();
+    let capture = writer.into_inner();
+
+    let frames = read_pcapng(capture.as_slice()).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].direction, Some(Direction::Sent));
+    assert_eq!(frames[0].data, vec![0x58, 0x02, 0x00, 0x04, 0x00]);
+    assert_eq!(frames[1].direction, Some(Direction::Received));
+    assert_eq!(frames[1].data, vec![0x58, 0x03, 0x00, 0x01, 0x00, 0x57]);
+    assert!(frames[0].timestamp <= frames[1].timestamp);
+}
+
+#[test]
+fn test_read_pcapng_invalid_magic() {
+    let mut capture = PcapngWriter::new(Vec::new()).unwrap().into_inner();
+    capture[8] ^= 0xFF;
+
+    let error = read_pcapng(capture.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
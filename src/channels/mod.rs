@@ -0,0 +1,484 @@
+use crate::cancel::CancelToken;
+use crate::codec::MAX_PAYLOAD_LENGTH;
+use crate::errors::{ChannelError, ReceiveError};
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport, TryClone};
+use crate::time::Instant;
+use message_types::{ChannelCredit, ChannelData};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The most bytes a [`ChannelData`] can carry, leaving room for its channel
+pub const MAX_CHANNEL_DATA: usize = MAX_PAYLOAD_LENGTH - 1;
+
+/// The flow control of one channel
+#[derive(Debug)]
+struct ChannelState {
+    /// Chunks received and not yet taken
+    inbox: VecDeque<Vec<u8>>,
+    /// The bytes the peer may have unread, granted when the channel was opened
+    window: u32,
+    /// Bytes this side may send before the peer grants more
+    credits: u64,
+    /// Bytes the peer may send before more are granted to it
+    granted: u64,
+    /// Bytes taken from the inbox since credit was last granted for them
+    consumed: u32,
+    overruns: u64,
+    /// Frames sent in a row before other channels get a turn
+    weight: u32,
+    /// Sends in progress, waiting for turns to send their frames
+    senders: u32,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            inbox: VecDeque::new(),
+            window: 0,
+            credits: 0,
+            granted: 0,
+            consumed: 0,
+            overruns: 0,
+            weight: 1,
+            senders: 0,
+        }
+    }
+}
+
+/// Which channel's turn it is to send, as weighted round-robin
+#[derive(Debug, Default)]
+struct Schedule {
+    /// Channels with sends in progress, the first's turn first
+    turns: VecDeque<u8>,
+    /// Frames sent in the current turn
+    sent: u32,
+    /// Whether a channel is writing a frame
+    busy: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    channels: BTreeMap<u8, ChannelState>,
+    schedule: Schedule,
+    /// Messages received that are not for a channel
+    messages: VecDeque<Message>,
+    /// Whether the reader has stopped
+    closed: bool,
+}
+
+impl State {
+    fn channel(&mut self, channel: u8) -> &mut ChannelState {
+        self.channels.entry(channel).or_default()
+    }
+
+    /// Queues `channel` for turns to send, for the duration of a send
+    fn start_sending(&mut self, channel: u8) {
+        self.channel(channel).senders += 1;
+        if !self.schedule.turns.contains(&channel) {
+            self.schedule.turns.push_back(channel);
+        }
+    }
+
+    fn stop_sending(&mut self, channel: u8) {
+        let state = self.channel(channel);
+        state.senders -= 1;
+        if state.senders > 0 {
+            return;
+        }
+        if self.schedule.turns.front() == Some(&channel) {
+            self.schedule.sent = 0;
+        }
+        self.schedule.turns.retain(|&queued| queued != channel);
+    }
+
+    /// Takes the link and up to `wanted` bytes of credit for `channel`, if it is its turn
+    ///
+    /// Channels without credit pass their turn on, so that they do not hold up the others.
+    fn take_turn(&mut self, channel: u8, wanted: usize) -> Option<usize> {
+        if self.schedule.busy {
+            return None;
+        }
+        for _ in 0..self.schedule.turns.len() {
+            let &front = self.schedule.turns.front()?;
+            if self.channel(front).credits > 0 {
+                break;
+            }
+            self.schedule.turns.rotate_left(1);
+            self.schedule.sent = 0;
+        }
+        if self.schedule.turns.front() != Some(&channel) {
+            return None;
+        }
+        let state = self.channel(channel);
+        if state.credits == 0 {
+            return None;
+        }
+        let length = wanted.min(usize::try_from(state.credits).unwrap_or(usize::MAX));
+        state.credits -= length as u64;
+        self.schedule.busy = true;
+        Some(length)
+    }
+
+    /// Gives up the link after `channel` wrote a frame, passing the turn on once it has sent its
+    /// weight in frames
+    fn end_turn(&mut self, channel: u8) {
+        self.schedule.busy = false;
+        self.schedule.sent += 1;
+        if self.schedule.sent >= self.channel(channel).weight {
+            self.schedule.turns.rotate_left(1);
+            self.schedule.sent = 0;
+        }
+    }
+}
+
+struct Shared<T>
+where
+    T: Transport,
+{
+    writer: Mutex<SerialManager<T>>,
+    state: Mutex<State>,
+    /// Notified when data, credit or a message arrives, or the reader stops
+    changed: Condvar,
+}
+
+fn lock<S>(state: &Mutex<S>) -> MutexGuard<'_, S> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T> Shared<T>
+where
+    T: Transport,
+{
+    /// Waits until `ready` returns a value or the `deadline` passes, returning `None` then
+    fn wait_until<R>(
+        &self,
+        deadline: Instant,
+        mut ready: impl FnMut(&mut State) -> Option<R>,
+    ) -> Result<Option<R>, ChannelError> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(result) = ready(&mut state) {
+                return Ok(Some(result));
+            }
+            if state.closed {
+                return Err(ChannelError::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = self
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn send(&self, message: Message) -> io::Result<()> {
+        lock(&self.writer).send(message)
+    }
+
+    /// Hands a message received to its channel
+    fn deliver(&self, message: Message) {
+        let mut state = lock(&self.state);
+        match message {
+            Message::ChannelData(ChannelData { channel, data }) => {
+                let channel = state.channel(channel);
+                let length = data.len() as u64;
+                // Data beyond the credit granted would let the peer fill memory
+                if length > channel.granted {
+                    channel.overruns += 1;
+                    return;
+                }
+                channel.granted -= length;
+                channel.inbox.push_back(data);
+            }
+            Message::ChannelCredit(ChannelCredit { channel, credits }) => {
+                state.channel(channel).credits += u64::from(credits);
+            }
+            message => state.messages.push_back(message),
+        }
+        self.changed.notify_all();
+    }
+}
+
+/// Splits one connection into virtual channels, each with its own credit-based flow control, so
+/// that a slow consumer of bulk data on one channel does not hold up the others
+///
+/// Each side opens a channel with a window, the bytes it is willing to hold unread, and grants
+/// the peer that much credit with a `ChannelCredit` frame. Every `ChannelData` frame sent uses
+/// up credit, and sending waits while there is none left. As the application takes data from a
+/// channel, credit for it is granted back, once half the window has been taken. The reader
+/// never waits for a channel, so frames on other channels, including the control frames
+/// granting credit, keep flowing while one channel's data goes unread. Data beyond the credit
+/// granted is dropped and counted as an overrun.
+///
+/// Channels sending at the same time take turns at the link, each sending its
+/// [weight](Channel::set_weight) in frames per turn, so that bulk transfers cannot starve a
+/// control channel. Channels waiting for credit pass their turns on. Credit and messages outside
+/// channels are sent straight away.
+///
+/// Messages that are not for a channel are received with [`receive`](Self::receive).
+///
+/// ```no_run
+/// use generic_serial_protocol::{Multiplexer, SerialManager};
+/// use std::time::Duration;
+///
+/// let mux = Multiplexer::new(SerialManager::connect_tcp("192.168.1.20:5000").unwrap()).unwrap();
+/// let control = mux.open(0, 1024).unwrap();
+/// let bulk = mux.open(1, 64 * 1024).unwrap();
+/// let image = std::fs::read("image.bin").unwrap();
+/// std::thread::spawn(move || bulk.send(&image, Duration::from_secs(10)));
+/// control.send(b"status", Duration::from_secs(1)).unwrap();
+/// let reply = control.receive(Duration::from_secs(1)).unwrap();
+/// ```
+pub struct Multiplexer<T>
+where
+    T: Transport,
+{
+    shared: Arc<Shared<T>>,
+    cancel: CancelToken,
+    reader: JoinHandle<ReceiveError>,
+}
+
+impl<T> fmt::Debug for Multiplexer<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multiplexer").finish_non_exhaustive()
+    }
+}
+
+impl<T> Multiplexer<T>
+where
+    T: Transport + TryClone + Send + 'static,
+{
+    /// Starts multiplexing `manager`'s connection, receiving on a new thread
+    ///
+    /// The reader keeps the manager, with its configuration and observer, and receives until the
+    /// multiplexer is stopped, an IO error occurs or the connection is closed. Frames that fail
+    /// to decode are skipped.
+    pub fn new(mut manager: SerialManager<T>) -> io::Result<Self> {
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(manager.try_clone_writer()?),
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        });
+        let cancel = manager.cancel_token();
+        let reader = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let error = loop {
+                    match manager.receive() {
+                        Ok(message) => shared.deliver(message),
+                        Err(e @ (ReceiveError::PeerClosed | ReceiveError::Cancelled)) => break e,
+                        Err(ReceiveError::Io(e)) if e.kind() != io::ErrorKind::TimedOut => {
+                            break ReceiveError::Io(e)
+                        }
+                        Err(_) => (),
+                    }
+                };
+                lock(&shared.state).closed = true;
+                shared.changed.notify_all();
+                error
+            })
+        };
+        Ok(Self {
+            shared,
+            cancel,
+            reader,
+        })
+    }
+}
+
+impl<T> Multiplexer<T>
+where
+    T: Transport,
+{
+    /// Opens `channel`, granting the peer credit to send up to `window` bytes on it before they
+    /// are read
+    ///
+    /// The peer must open the channel too before sending on it. Data the peer sent within the
+    /// credit before the channel was opened here is kept, as is credit it granted.
+    pub fn open(&self, channel: u8, window: u32) -> io::Result<Channel<T>> {
+        {
+            let mut state = lock(&self.shared.state);
+            let state = state.channel(channel);
+            state.window = window;
+            state.granted += u64::from(window);
+        }
+        self.shared.send(Message::ChannelCredit(ChannelCredit {
+            channel,
+            credits: window,
+        }))?;
+        Ok(Channel {
+            channel,
+            shared: Arc::clone(&self.shared),
+        })
+    }
+
+    /// Sends a message outside any channel, with no flow control
+    pub fn send(&self, message: Message) -> io::Result<()> {
+        self.shared.send(message)
+    }
+
+    /// Receives the next message that is not for a channel, waiting up to `timeout`
+    pub fn receive(&self, timeout: Duration) -> Result<Message, ChannelError> {
+        self.shared
+            .wait_until(Instant::now() + timeout, |state| state.messages.pop_front())?
+            .ok_or(ChannelError::Timeout)
+    }
+
+    /// Stops receiving, returning the error that stopped the reader if it was not this
+    ///
+    /// The reader stops at its next read from the connection, once bytes arrive or a read times
+    /// out. Channels fail with [`ChannelError::Closed`] afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader panicked.
+    pub fn stop(self) -> Result<(), ReceiveError> {
+        self.cancel.cancel();
+        match self.reader.join().expect("multiplexer reader panicked") {
+            ReceiveError::Cancelled => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+/// A virtual channel of a [`Multiplexer`], which can be cloned and moved to any thread
+pub struct Channel<T>
+where
+    T: Transport,
+{
+    channel: u8,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Channel<T>
+where
+    T: Transport,
+{
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel,
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Channel<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Channel")
+            .field("channel", &self.channel)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Channel<T>
+where
+    T: Transport,
+{
+    #[must_use]
+    pub fn id(&self) -> u8 {
+        self.channel
+    }
+
+    /// Sends `data`, in frames of up to [`MAX_CHANNEL_DATA`] bytes as the peer grants credit,
+    /// waiting up to `timeout` in all
+    ///
+    /// Channels sending at the same time take turns, each sending its
+    /// [weight](Self::set_weight) in frames before the next, so that a busy channel cannot hold
+    /// the link. Fails with [`ChannelError::Stalled`] if the peer grants too little credit or
+    /// other channels hold the link for too long, having sent the bytes it did.
+    pub fn send(&self, data: &[u8], timeout: Duration) -> Result<(), ChannelError> {
+        lock(&self.shared.state).start_sending(self.channel);
+        let result = self.send_frames(data, Instant::now() + timeout);
+        lock(&self.shared.state).stop_sending(self.channel);
+        self.shared.changed.notify_all();
+        result
+    }
+
+    fn send_frames(&self, data: &[u8], deadline: Instant) -> Result<(), ChannelError> {
+        let mut sent = 0;
+        while sent < data.len() {
+            let wanted = (data.len() - sent).min(MAX_CHANNEL_DATA);
+            let length = self
+                .shared
+                .wait_until(deadline, |state| state.take_turn(self.channel, wanted))?;
+            let Some(length) = length else {
+                return Err(ChannelError::Stalled { sent });
+            };
+            let result = self.shared.send(Message::ChannelData(ChannelData {
+                channel: self.channel,
+                data: data[sent..sent + length].to_vec(),
+            }));
+            lock(&self.shared.state).end_turn(self.channel);
+            self.shared.changed.notify_all();
+            result?;
+            sent += length;
+        }
+        Ok(())
+    }
+
+    /// Receives the data of the next frame on the channel, waiting up to `timeout`
+    ///
+    /// Credit for the data is granted back to the peer once half the window has been received.
+    pub fn receive(&self, timeout: Duration) -> Result<Vec<u8>, ChannelError> {
+        let (data, credits) = self
+            .shared
+            .wait_until(Instant::now() + timeout, |state| {
+                let channel = state.channel(self.channel);
+                let data = channel.inbox.pop_front()?;
+                // Frames hold less than 64 KiB
+                channel.consumed += u32::try_from(data.len()).unwrap_or(u32::MAX);
+                if channel.consumed < (channel.window / 2).max(1) {
+                    return Some((data, 0));
+                }
+                let credits = channel.consumed;
+                channel.consumed = 0;
+                channel.granted += u64::from(credits);
+                Some((data, credits))
+            })?
+            .ok_or(ChannelError::Timeout)?;
+        if credits > 0 {
+            self.shared.send(Message::ChannelCredit(ChannelCredit {
+                channel: self.channel,
+                credits,
+            }))?;
+        }
+        Ok(data)
+    }
+
+    /// Sets how many frames the channel sends in a row before other channels sending at the same
+    /// time get a turn, at least 1 and 1 by default
+    pub fn set_weight(&self, weight: u32) {
+        lock(&self.shared.state).channel(self.channel).weight = weight.max(1);
+        self.shared.changed.notify_all();
+    }
+
+    /// Returns the bytes that can be sent before the peer grants more credit
+    #[must_use]
+    pub fn credits(&self) -> u64 {
+        lock(&self.shared.state).channel(self.channel).credits
+    }
+
+    /// Returns the number of frames dropped for carrying more data than the peer had credit for
+    #[must_use]
+    pub fn overruns(&self) -> u64 {
+        lock(&self.shared.state).channel(self.channel).overruns
+    }
+}
+
+#[cfg(test)]
+mod tests;
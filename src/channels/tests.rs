@@ -0,0 +1,127 @@
+use super::*;
+use crate::test_util::{stream_pair, TestStream};
+
+fn pair() -> (Multiplexer<TestStream>, Multiplexer<TestStream>) {
+    let (stream1, stream2) = stream_pair();
+    (
+        Multiplexer::new(SerialManager::new(stream1)).unwrap(),
+        Multiplexer::new(SerialManager::new(stream2)).unwrap(),
+    )
+}
+
+#[test]
+fn test_slow_bulk_consumer_does_not_stall_control() {
+    let (host, device) = pair();
+    let timeout = Duration::from_secs(1);
+    let host_control = host.open(0, 256).unwrap();
+    let host_bulk = host.open(1, 64).unwrap();
+    let device_control = device.open(0, 256).unwrap();
+    let device_bulk = device.open(1, 64).unwrap();
+
+    // The host reads no bulk data, so the device can only send one window of it
+    let image: Vec<u8> = (0..=255).collect();
+    assert!(matches!(
+        device_bulk.send(&image, Duration::from_millis(50)),
+        Err(ChannelError::Stalled { sent: 64 })
+    ));
+    device_control.send(b"status", timeout).unwrap();
+    assert_eq!(host_control.receive(timeout).unwrap(), b"status");
+    host_control.send(b"ok", timeout).unwrap();
+    assert_eq!(device_control.receive(timeout).unwrap(), b"ok");
+
+    // Reading the bulk data grants credit for the rest
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        while received.len() < 256 {
+            received.extend(host_bulk.receive(timeout).unwrap());
+        }
+        received
+    });
+    device_bulk.send(&image[64..], timeout).unwrap();
+    assert_eq!(reader.join().unwrap(), image);
+    assert_eq!(device_bulk.overruns(), 0);
+}
+
+#[test]
+fn test_data_beyond_credit_is_dropped() {
+    let (stream1, stream2) = stream_pair();
+    let mux = Multiplexer::new(SerialManager::new(stream1)).unwrap();
+    let mut peer = SerialManager::new(stream2);
+    let channel = mux.open(3, 4).unwrap();
+    assert_eq!(
+        peer.receive().unwrap(),
+        Message::ChannelCredit(ChannelCredit {
+            channel: 3,
+            credits: 4
+        })
+    );
+
+    let data = |data: &[u8]| {
+        Message::ChannelData(ChannelData {
+            channel: 3,
+            data: data.to_vec(),
+        })
+    };
+    peer.send(data(b"abc")).unwrap();
+    peer.send(data(b"de")).unwrap();
+    peer.send(data(b"f")).unwrap();
+    peer.send(Message::Status(message_types::Status::Ok))
+        .unwrap();
+
+    let timeout = Duration::from_secs(1);
+    assert_eq!(
+        mux.receive(timeout).unwrap(),
+        Message::Status(message_types::Status::Ok)
+    );
+    assert_eq!(channel.receive(timeout).unwrap(), b"abc");
+    assert_eq!(channel.receive(timeout).unwrap(), b"f");
+    assert_eq!(channel.overruns(), 1);
+    // Credit is granted back once half the window has been read
+    assert_eq!(
+        peer.receive().unwrap(),
+        Message::ChannelCredit(ChannelCredit {
+            channel: 3,
+            credits: 3
+        })
+    );
+    assert_eq!(channel.credits(), 0);
+}
+
+#[test]
+fn test_channels_take_weighted_turns() {
+    let mut state = State::default();
+    state.channel(1).credits = 100;
+    state.channel(1).weight = 2;
+    state.channel(2).credits = 100;
+    state.start_sending(1);
+    state.start_sending(2);
+
+    let mut order = Vec::new();
+    for _ in 0..6 {
+        let channel = if state.take_turn(1, 10).is_some() {
+            1
+        } else {
+            assert_eq!(state.take_turn(2, 10), Some(10));
+            2
+        };
+        // Only one frame is written at a time
+        assert_eq!(state.take_turn(channel, 10), None);
+        state.end_turn(channel);
+        order.push(channel);
+    }
+    assert_eq!(order, [1, 1, 2, 1, 1, 2]);
+
+    // A channel without credit passes its turns on
+    state.channel(1).credits = 0;
+    for _ in 0..3 {
+        assert_eq!(state.take_turn(1, 10), None);
+        assert_eq!(state.take_turn(2, 10), Some(10));
+        state.end_turn(2);
+    }
+
+    // As does one that has finished sending
+    state.channel(1).credits = 100;
+    state.stop_sending(2);
+    assert_eq!(state.take_turn(2, 10), None);
+    assert_eq!(state.take_turn(1, 10), Some(10));
+}
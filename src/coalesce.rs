@@ -0,0 +1,108 @@
+use crate::queue::Priority;
+use crate::time::Instant;
+use std::time::Duration;
+
+/// How long frames of one [`Priority`] may wait to be written together, set with
+/// [`SerialManager::with_coalescing`](crate::SerialManager::with_coalescing)
+///
+/// Like Nagle's algorithm, frames are held back and written with a single write and flush once
+/// `max_bytes` bytes are waiting or the oldest has waited `max_delay`, whichever comes first, so
+/// that a burst of tiny frames pays the scheduling latency of connections such as USB CDC
+/// devices once rather than for every frame.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Coalescing {
+    max_bytes: usize,
+    max_delay: Duration,
+}
+
+impl Coalescing {
+    /// Holds frames back until `max_bytes` bytes are waiting or the oldest has waited
+    /// `max_delay`
+    #[must_use]
+    pub fn new(max_bytes: usize, max_delay: Duration) -> Self {
+        Self {
+            max_bytes,
+            max_delay,
+        }
+    }
+}
+
+/// An encoded frame held back by a [`Coalescer`], with what is needed to record it as sent
+#[derive(Debug)]
+pub(crate) struct CoalescedFrame {
+    pub(crate) message_type: u16,
+    pub(crate) data: Vec<u8>,
+    pub(crate) sent_length: usize,
+    pub(crate) padding: usize,
+    /// Where the frame ends in the coalesced bytes
+    pub(crate) end: usize,
+}
+
+/// The frames held back for coalescing, in the order they were sent
+#[derive(Debug, Default)]
+pub(crate) struct Coalesced {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) frames: Vec<CoalescedFrame>,
+}
+
+/// The coalescing of each priority, and the frames held back under it
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    settings: [Option<Coalescing>; 3],
+    pending: Coalesced,
+    /// The fewest bytes any held back frame may wait behind
+    max_bytes: usize,
+    /// When the held back frame due first is to be written
+    deadline: Option<Instant>,
+}
+
+impl Coalescer {
+    pub(crate) fn set(&mut self, priority: Priority, coalescing: Coalescing) {
+        self.settings[priority as usize] = Some(coalescing);
+    }
+
+    pub(crate) fn coalescing(&self, priority: Priority) -> Option<Coalescing> {
+        self.settings[priority as usize]
+    }
+
+    /// Holds back an encoded frame under `coalescing`, sent at `now`
+    pub(crate) fn push(
+        &mut self,
+        coalescing: Coalescing,
+        now: Instant,
+        frame: &[u8],
+        mut record: CoalescedFrame,
+    ) {
+        let deadline = now + coalescing.max_delay;
+        if self.pending.frames.is_empty() {
+            self.max_bytes = coalescing.max_bytes;
+            self.deadline = Some(deadline);
+        } else {
+            self.max_bytes = self.max_bytes.min(coalescing.max_bytes);
+            self.deadline = self.deadline.map(|due| due.min(deadline));
+        }
+        self.pending.bytes.extend_from_slice(frame);
+        record.end = self.pending.bytes.len();
+        self.pending.frames.push(record);
+    }
+
+    /// Returns whether the held back frames are to be written at `now`
+    pub(crate) fn is_due(&self, now: Instant) -> bool {
+        self.deadline
+            .is_some_and(|deadline| self.pending.bytes.len() >= self.max_bytes || now >= deadline)
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.frames.is_empty()
+    }
+
+    /// Takes the held back frames to be written
+    pub(crate) fn take(&mut self) -> Coalesced {
+        self.deadline = None;
+        std::mem::take(&mut self.pending)
+    }
+}
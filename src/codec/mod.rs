@@ -0,0 +1,1212 @@
+use crate::firmware::crc32;
+use crate::layer::Crc32;
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub(crate) const START_BYTE: u8 = 0x58;
+pub(crate) const ESCAPE_BYTE: u8 = 0x42;
+pub(crate) const XOR_BYTE: u8 = 0x69;
+
+pub(crate) const SLIP_END: u8 = 0xC0;
+pub(crate) const SLIP_ESC: u8 = 0xDB;
+pub(crate) const SLIP_ESC_END: u8 = 0xDC;
+pub(crate) const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// The code byte of a COBS block of 254 bytes, which is not followed by a zero
+pub(crate) const COBS_MAX_CODE: u8 = 0xFF;
+
+/// The byte frames with a start byte are padded with, which is not part of any frame
+pub(crate) const PADDING_BYTE: u8 = 0x00;
+
+/// The number of bytes the message type adds to the length field
+const MESSAGE_TYPE_LENGTH: usize = 2;
+
+/// The number of bytes of the CRC-32 ending a [`Framing::Binary`] frame
+const CHECKSUM_LENGTH: usize = 4;
+
+/// The number of bytes of the CRC-16 ending the header of a [`Framing::CheckedHeader`] frame
+const HEADER_CHECKSUM_LENGTH: usize = 2;
+
+/// The largest payload a frame can carry, as the length field also counts the message type
+pub const MAX_PAYLOAD_LENGTH: usize = u16::MAX as usize - MESSAGE_TYPE_LENGTH;
+
+/// The largest payload a [`Framing::Compact`] frame can carry, as its length field also counts
+/// the message type
+pub const MAX_COMPACT_PAYLOAD_LENGTH: usize = u8::MAX as usize - 1;
+
+/// The largest payload a [`Framing::Extended`] frame can carry, as its length field also counts
+/// the message type
+pub const MAX_EXTENDED_PAYLOAD_LENGTH: usize = u32::MAX as usize - MESSAGE_TYPE_LENGTH;
+
+/// The byte order of multi-byte fields on the wire: the frame header and numbers in payloads
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    pub(crate) fn u16_to_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub(crate) fn u16_from_bytes(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        }
+    }
+
+    pub(crate) fn u32_to_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    pub(crate) fn u32_from_bytes(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// How messages are delimited on the wire
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Framing {
+    /// A start byte, then the escaped length, message type and payload
+    #[default]
+    Native,
+    /// [RFC 1055](https://www.rfc-editor.org/rfc/rfc1055) SLIP, for devices and tools that already
+    /// speak it: the message type and payload are escaped with SLIP's ESC byte and sent between
+    /// END bytes, with no length field
+    Slip,
+    /// [COBS](https://en.wikipedia.org/wiki/Consistent_Overhead_Byte_Stuffing): the message type
+    /// and payload are stuffed so that they contain no zero bytes and sent before a zero byte,
+    /// with no length field. Unlike escaping, this adds at most one byte per 254, so binary
+    /// payloads cannot double in size.
+    Cobs,
+    /// Like [`Native`](Self::Native), with a single length byte and a single message type byte,
+    /// saving two bytes a frame on slow links where most frames are short. Frames can only carry
+    /// message types up to 255 and payloads up to [`MAX_COMPACT_PAYLOAD_LENGTH`] bytes.
+    ///
+    /// Peers supporting it advertise
+    /// [`Capabilities::COMPACT_FRAMING`](crate::Capabilities::COMPACT_FRAMING), and both ends
+    /// must select it.
+    Compact,
+    /// Like [`Native`](Self::Native), with a four byte length field, so that fast links can
+    /// carry single frames larger than 64 KiB, up to [`MAX_EXTENDED_PAYLOAD_LENGTH`] bytes,
+    /// without splitting them up
+    ///
+    /// Peers supporting it advertise
+    /// [`Capabilities::EXTENDED_FRAMING`](crate::Capabilities::EXTENDED_FRAMING), and both ends
+    /// must select it.
+    Extended,
+    /// A start byte, then the length, message type and payload as they are, with no escaping,
+    /// followed by a CRC-32 of the header and payload, for clean point-to-point links where
+    /// escaping would double the size of the worst-case payload
+    ///
+    /// The receiver trusts the length field, and checks the CRC once the frame is complete. A
+    /// frame that fails it is discarded and the bytes after its start byte are scanned again
+    /// for the next start byte, so a false start in the middle of a payload or a corrupted
+    /// length field only costs the frames received while it was believed.
+    ///
+    /// Peers supporting it advertise
+    /// [`Capabilities::BINARY_FRAMING`](crate::Capabilities::BINARY_FRAMING), and both ends
+    /// must select it.
+    Binary,
+    /// Like [`Binary`](Self::Binary), with a CRC-16 of the length and message type following
+    /// them, which the CRC-32 ending the frame also covers
+    ///
+    /// A corrupted length field is caught as soon as the header has arrived, rather than once
+    /// the receiver has waited for a payload of up to 64 KiB that never comes, so a noisy line
+    /// resyncs after a few bytes. The header CRC is CRC-16/CCITT-FALSE: polynomial 0x1021,
+    /// initial value 0xFFFF, not reflected.
+    ///
+    /// Peers supporting it advertise
+    /// [`Capabilities::CHECKED_HEADER_FRAMING`](crate::Capabilities::CHECKED_HEADER_FRAMING),
+    /// and both ends must select it.
+    CheckedHeader,
+}
+
+impl Framing {
+    /// Encodes a frame with an already encoded payload, with the length and message type in the
+    /// given byte order
+    ///
+    /// The message type and payload must [`fit`](Self::fits) the framing.
+    #[must_use]
+    pub fn encode(self, message_type: u16, payload: &[u8], endianness: Endianness) -> Vec<u8> {
+        match self {
+            Framing::Native => encode_frame_with(message_type, payload, endianness),
+            Framing::Binary | Framing::CheckedHeader => {
+                #[allow(clippy::cast_possible_truncation)]
+                let length = (MESSAGE_TYPE_LENGTH + payload.len()) as u16;
+                let mut frame =
+                    Vec::with_capacity(1 + self.header_length() + payload.len() + CHECKSUM_LENGTH);
+                frame.push(START_BYTE);
+                frame.extend_from_slice(&endianness.u16_to_bytes(length));
+                frame.extend_from_slice(&endianness.u16_to_bytes(message_type));
+                if self == Framing::CheckedHeader {
+                    let checksum = crc16(&frame[1..]);
+                    frame.extend_from_slice(&endianness.u16_to_bytes(checksum));
+                }
+                frame.extend_from_slice(payload);
+                let checksum = crc32(&frame[1..]);
+                frame.extend_from_slice(&endianness.u32_to_bytes(checksum));
+                frame
+            }
+            Framing::Compact | Framing::Extended => {
+                let mut frame = self.encode_header(message_type, payload.len(), endianness);
+                frame.reserve(2 * payload.len());
+                escape_into(&mut frame, payload);
+                frame
+            }
+            Framing::Slip => {
+                let mut frame = Vec::with_capacity(2 + 2 * (2 + payload.len()));
+                // Leading END flushes any line noise into a packet of its own, as RFC 1055
+                // recommends
+                frame.push(SLIP_END);
+                slip_escape_into(&mut frame, &endianness.u16_to_bytes(message_type));
+                slip_escape_into(&mut frame, payload);
+                frame.push(SLIP_END);
+                frame
+            }
+            Framing::Cobs => {
+                let length = MESSAGE_TYPE_LENGTH + payload.len();
+                let mut frame = Vec::with_capacity(length + length / 254 + 2);
+                let message_type = endianness.u16_to_bytes(message_type);
+                cobs_encode_into(&mut frame, message_type.iter().chain(payload));
+                frame.push(0);
+                frame
+            }
+        }
+    }
+
+    /// Encodes the start byte and escaped header of a frame with a payload of `payload_length`
+    /// bytes, for framings with a start byte that are escaped
+    pub(crate) fn encode_header(
+        self,
+        message_type: u16,
+        payload_length: usize,
+        endianness: Endianness,
+    ) -> Vec<u8> {
+        let length = self.message_type_length() + payload_length;
+        let mut frame = Vec::with_capacity(1 + 2 * self.header_length());
+        frame.push(START_BYTE);
+        #[allow(clippy::cast_possible_truncation)]
+        match self {
+            Framing::Compact => escape_into(&mut frame, &[length as u8, message_type as u8]),
+            Framing::Extended => {
+                escape_into(&mut frame, &endianness.u32_to_bytes(length as u32));
+                escape_into(&mut frame, &endianness.u16_to_bytes(message_type));
+            }
+            Framing::Native
+            | Framing::Binary
+            | Framing::CheckedHeader
+            | Framing::Slip
+            | Framing::Cobs => {
+                escape_into(&mut frame, &endianness.u16_to_bytes(length as u16));
+                escape_into(&mut frame, &endianness.u16_to_bytes(message_type));
+            }
+        }
+        frame
+    }
+
+    /// Encodes a frame like [`encode`](Self::encode), followed by `trailer` if there is one and
+    /// the framing is escaped
+    pub(crate) fn encode_with_trailer(
+        self,
+        message_type: u16,
+        payload: &[u8],
+        endianness: Endianness,
+        trailer: Option<u8>,
+    ) -> Vec<u8> {
+        let mut frame = self.encode(message_type, payload, endianness);
+        if let (Some(trailer), true) = (trailer, self.is_escaped()) {
+            escape_into(&mut frame, &[trailer]);
+        }
+        frame
+    }
+
+    /// Returns the largest payload a frame can carry
+    #[must_use]
+    pub fn max_payload_length(self) -> usize {
+        match self {
+            Framing::Native
+            | Framing::Binary
+            | Framing::CheckedHeader
+            | Framing::Slip
+            | Framing::Cobs => MAX_PAYLOAD_LENGTH,
+            Framing::Compact => MAX_COMPACT_PAYLOAD_LENGTH,
+            Framing::Extended => MAX_EXTENDED_PAYLOAD_LENGTH,
+        }
+    }
+
+    /// Returns whether a frame can carry `message_type` and a payload of `payload_length` bytes
+    #[must_use]
+    pub fn fits(self, message_type: u16, payload_length: usize) -> bool {
+        let type_fits = self != Framing::Compact || u8::try_from(message_type).is_ok();
+        type_fits && payload_length <= self.max_payload_length()
+    }
+
+    /// The bytes a frame adds around its payload, other than escape bytes
+    pub(crate) fn overhead(self) -> usize {
+        match self {
+            Framing::Native | Framing::Compact | Framing::Extended => 1 + self.header_length(),
+            Framing::Binary | Framing::CheckedHeader => 1 + self.header_length() + CHECKSUM_LENGTH,
+            Framing::Slip => 2 + MESSAGE_TYPE_LENGTH,
+            Framing::Cobs => 1 + MESSAGE_TYPE_LENGTH,
+        }
+    }
+
+    /// The byte frames are padded with: one that is skipped between frames with a start byte,
+    /// or the delimiter of SLIP and COBS, which makes empty packets that are ignored
+    pub(crate) fn padding_byte(self) -> u8 {
+        match self {
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader => PADDING_BYTE,
+            Framing::Slip => SLIP_END,
+            Framing::Cobs => 0,
+        }
+    }
+
+    /// Pads the end of an encoded frame in `frame`, of which `written` bytes were written
+    /// already, up to a multiple of `block_size`, returning the number of padding bytes added
+    pub(crate) fn pad(self, frame: &mut Vec<u8>, written: usize, block_size: usize) -> usize {
+        let length = written + frame.len();
+        let padding = (block_size - length % block_size) % block_size;
+        frame.resize(frame.len() + padding, self.padding_byte());
+        padding
+    }
+
+    /// Returns whether frames start with a start byte, rather than being delimited like SLIP
+    /// and COBS packets
+    pub(crate) fn has_start_byte(self) -> bool {
+        matches!(
+            self,
+            Framing::Native
+                | Framing::Compact
+                | Framing::Extended
+                | Framing::Binary
+                | Framing::CheckedHeader
+        )
+    }
+
+    /// Returns whether frames start with a start byte and escape it everywhere else, so that a
+    /// trailer can follow them and they can be streamed
+    pub(crate) fn is_escaped(self) -> bool {
+        matches!(self, Framing::Native | Framing::Compact | Framing::Extended)
+    }
+
+    /// The number of bytes the message type takes up
+    pub(crate) fn message_type_length(self) -> usize {
+        match self {
+            Framing::Compact => 1,
+            Framing::Native
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader
+            | Framing::Slip
+            | Framing::Cobs => MESSAGE_TYPE_LENGTH,
+        }
+    }
+
+    /// Returns whether frames are not escaped and end with a CRC-32, trusting the length field
+    pub(crate) fn is_binary(self) -> bool {
+        matches!(self, Framing::Binary | Framing::CheckedHeader)
+    }
+
+    /// The number of bytes of the header CRC after the message type
+    pub(crate) fn header_checksum_length(self) -> usize {
+        if self == Framing::CheckedHeader {
+            HEADER_CHECKSUM_LENGTH
+        } else {
+            0
+        }
+    }
+
+    /// The length of the header after the start byte: the length field, message type and any
+    /// header CRC
+    pub(crate) fn header_length(self) -> usize {
+        match self {
+            Framing::Native | Framing::Binary => 2 + MESSAGE_TYPE_LENGTH,
+            Framing::CheckedHeader => 2 + MESSAGE_TYPE_LENGTH + HEADER_CHECKSUM_LENGTH,
+            Framing::Compact => 1 + 1,
+            Framing::Extended => 4 + MESSAGE_TYPE_LENGTH,
+            Framing::Slip | Framing::Cobs => MESSAGE_TYPE_LENGTH,
+        }
+    }
+}
+
+/// How the decoder finds the start of the next frame when a frame with a start byte goes wrong
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Resync {
+    /// A start byte inside a frame discards it and starts a new one, which is quick to recover
+    /// but relies on the peer escaping every start byte it sends
+    ///
+    /// [`Framing::Binary`] and [`Framing::CheckedHeader`] are not escaped, so always resync as
+    /// with [`Checksum`](Self::Checksum) instead.
+    #[default]
+    StartByte,
+    /// Start bytes inside a frame are taken as part of it, trusting the length field, and each
+    /// frame is checked by the CRC-32 that a [`Crc32`] layer closest to the wire appends to its
+    /// payload. A frame that fails it is discarded, and the bytes after its start byte are
+    /// scanned again for the next start byte.
+    ///
+    /// The CRC is left in the payload for the layer to remove, so both peers must stack
+    /// [`Crc32`] last.
+    Checksum,
+    /// Start bytes inside a frame are taken as part of it, trusting the length field, and a
+    /// frame is discarded if the line goes idle for this long before it is complete, so that
+    /// the next byte must be a start byte
+    ///
+    /// Gaps are measured between bytes pushed with [`Decoder::push_at`].
+    IdleGap(#[cfg_attr(feature = "serde", serde(with = "crate::profile::millis"))] Duration),
+}
+
+/// A complete frame, with its payload unescaped but not yet decoded into a message
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Frame {
+    pub message_type: u16,
+    pub payload: Vec<u8>,
+}
+
+/// Something of interest that happened while decoding
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DecoderEvent {
+    /// A complete frame was received
+    Frame(Frame),
+    /// A start byte interrupted a partially received frame, which was discarded. With
+    /// [`Resync::IdleGap`] or [`Decoder::with_inter_byte_timeout`], the line may have gone idle
+    /// in the middle of the frame instead.
+    Resync,
+    /// A start byte ended a run of bytes outside any frame, which were skipped. Holds the number
+    /// of bytes skipped. With SLIP or COBS framing, a delimiter ended a packet that was too long
+    /// to be a frame or, with COBS, was cut short, and holds the packet's length.
+    Skipped(usize),
+    /// A frame's length field was too short to hold the message type, and with
+    /// [`Resync::Checksum`] its checksum, or longer than [`Decoder::with_max_payload_length`]
+    /// allows, so the frame was discarded and the decoder waits for the next start byte. With
+    /// SLIP or COBS framing, holds the length of a packet too short to hold the message type.
+    InvalidLength(u16),
+    /// A frame was followed by this byte instead of the trailer set with
+    /// [`Decoder::with_trailer`], so the frame was discarded and the decoder waits for the next
+    /// start byte
+    InvalidTrailer(u8),
+    /// A [`Framing::Binary`] or [`Framing::CheckedHeader`] frame, or any frame with
+    /// [`Resync::Checksum`], failed its CRC, so
+    /// it was discarded and the bytes after its start byte are scanned again for the next one
+    ChecksumMismatch { expected: u32, received: u32 },
+    /// A [`Framing::CheckedHeader`] frame's header failed its CRC, so it was discarded and the
+    /// bytes after its start byte are scanned again for the next one
+    HeaderChecksumMismatch { expected: u16, received: u16 },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    WaitingForStart,
+    Header,
+    Payload,
+    Trailer,
+    Checksum,
+}
+
+/// A sans-IO frame decoder.
+///
+/// Bytes are pushed in one at a time as they are received from any source, and complete frames
+/// come out. The decoder resyncs in the same way as [`SerialManager`](crate::SerialManager):
+/// bytes before a start byte are skipped, and by default a start byte in the middle of a frame
+/// discards the partial frame and starts a new one. See [`Resync`] for other strategies.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    state: State,
+    escaped: bool,
+    header: [u8; 6],
+    header_length: usize,
+    message_type: u16,
+    payload_length: usize,
+    payload: Vec<u8>,
+    trailer: Option<u8>,
+    padding: bool,
+    skipped: usize,
+    /// Bytes received since the last SLIP END or COBS delimiter
+    packet_length: usize,
+    discarding: bool,
+    cobs_code: u8,
+    cobs_remaining: u8,
+    /// The bytes of a frame received so far after its start byte, as they were received, kept
+    /// to scan again if it fails its checksum
+    candidate: Vec<u8>,
+    /// Bytes of a discarded frame waiting to be pushed again
+    replay: VecDeque<u8>,
+    /// Events held back because pushing a single byte produced several
+    pending: VecDeque<DecoderEvent>,
+    resync: Resync,
+    inter_byte_timeout: Option<Duration>,
+    /// The longest payload accepted, if less than the framing can carry
+    max_payload_length: Option<usize>,
+    /// When the last byte was pushed with [`push_at`](Decoder::push_at)
+    last_byte: Option<Instant>,
+    endianness: Endianness,
+    framing: Framing,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: State::WaitingForStart,
+            escaped: false,
+            header: [0; 6],
+            header_length: 0,
+            message_type: 0,
+            payload_length: 0,
+            payload: Vec::new(),
+            trailer: None,
+            padding: false,
+            skipped: 0,
+            packet_length: 0,
+            discarding: false,
+            cobs_code: 0,
+            cobs_remaining: 0,
+            candidate: Vec::new(),
+            replay: VecDeque::new(),
+            pending: VecDeque::new(),
+            resync: Resync::StartByte,
+            inter_byte_timeout: None,
+            max_payload_length: None,
+            last_byte: None,
+            endianness: Endianness::Little,
+            framing: Framing::Native,
+        }
+    }
+
+    /// Sets the byte order of the length and message type fields, which is little-endian by
+    /// default
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Sets how frames are delimited, which is [`Framing::Native`] by default
+    #[must_use]
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Expects every frame to be followed by `trailer`, as encoded by
+    /// [`encode_frame_with_trailer`], and discards frames that are not
+    ///
+    /// A frame whose length field was corrupted to be too short is then caught when its end is
+    /// reached, instead of the rest of it being skipped or taken for the next frame. Only used
+    /// with framings that have a start byte, as SLIP and COBS delimit frames already.
+    #[must_use]
+    pub fn with_trailer(mut self, trailer: u8) -> Self {
+        self.trailer = Some(trailer);
+        self
+    }
+
+    /// Sets how the decoder resyncs after a frame with a start byte goes wrong, which is
+    /// [`Resync::StartByte`] by default
+    #[must_use]
+    pub fn with_resync(mut self, resync: Resync) -> Self {
+        self.resync = resync;
+        self
+    }
+
+    /// Discards a partially received frame once no byte has arrived for `timeout`, rather than
+    /// waiting indefinitely for the rest of a frame whose sender died in the middle of it
+    ///
+    /// Gaps are measured between bytes pushed with [`push_at`](Self::push_at), and up to the
+    /// time passed to [`expire`](Self::expire).
+    #[must_use]
+    pub fn with_inter_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.inter_byte_timeout = Some(timeout);
+        self
+    }
+
+    /// Rejects frames whose payload is longer than `length` bytes, rather than buffering up to
+    /// the largest payload the framing can carry
+    ///
+    /// A frame whose length field counts a longer payload is discarded with
+    /// [`DecoderEvent::InvalidLength`] as soon as its header is received, which keeps a corrupted
+    /// or hostile [`Framing::Extended`] length from making the decoder buffer up to 4 GiB. SLIP
+    /// and COBS packets that grow too long are skipped up to their end instead.
+    #[must_use]
+    pub fn with_max_payload_length(mut self, length: usize) -> Self {
+        self.max_payload_length = Some(length);
+        self
+    }
+
+    /// Ignores the padding bytes sent between frames by
+    /// [`SerialManager::with_padding`](crate::SerialManager::with_padding), rather than
+    /// reporting them as skipped
+    ///
+    /// Only used with framings that have a start byte, as SLIP and COBS frames are padded with
+    /// delimiters, which are ignored anyway.
+    #[must_use]
+    pub fn with_padding(mut self) -> Self {
+        self.padding = true;
+        self
+    }
+
+    /// Pushes a single received byte into the decoder
+    ///
+    /// Returns an event if the byte completed a frame or caused a resync. A single byte can
+    /// complete several frames when a frame that failed its checksum is scanned again, in which
+    /// case the events after the first are returned by [`poll`](Self::poll).
+    pub fn push(&mut self, byte: u8) -> Option<DecoderEvent> {
+        match self.framing {
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader => {}
+            Framing::Slip => return self.push_slip(byte),
+            Framing::Cobs => return self.push_cobs(byte),
+        }
+        if !self.keeps_candidate() {
+            let event = self.push_framed(byte);
+            return self.queue(event);
+        }
+
+        self.replay.push_back(byte);
+        while let Some(byte) = self.replay.pop_front() {
+            if let Some(event) = self.push_framed(byte) {
+                self.pending.push_back(event);
+            }
+        }
+        self.pending.pop_front()
+    }
+
+    /// Pushes a byte like [`push`](Self::push), received at `now`, so that a frame cut short by
+    /// the line going idle for the gap of [`Resync::IdleGap`] is discarded with
+    /// [`DecoderEvent::Resync`]
+    pub fn push_at(&mut self, byte: u8, now: Instant) -> Option<DecoderEvent> {
+        if let Some(event) = self.expire(now) {
+            self.pending.push_back(event);
+        }
+        self.last_byte = Some(now);
+        self.push(byte)
+    }
+
+    /// Discards a partially received frame with [`DecoderEvent::Resync`] if no byte has been
+    /// pushed with [`push_at`](Self::push_at) since longer ago than the inter-byte timeout or
+    /// the gap of [`Resync::IdleGap`], as of `now`
+    ///
+    /// This lets a frame whose sender died in the middle of it be given up on while waiting for
+    /// bytes that never come, such as when a read times out.
+    pub fn expire(&mut self, now: Instant) -> Option<DecoderEvent> {
+        let timeout = match (self.resync, self.inter_byte_timeout) {
+            (Resync::IdleGap(gap), Some(timeout)) => gap.min(timeout),
+            (Resync::IdleGap(timeout), None) | (_, Some(timeout)) => timeout,
+            _ => return None,
+        };
+        let last = self.last_byte?;
+        if !self.is_in_frame() || now.saturating_duration_since(last) < timeout {
+            return None;
+        }
+        self.reset();
+        Some(DecoderEvent::Resync)
+    }
+
+    /// Returns an event held back by [`push`](Self::push), if any, without pushing a byte
+    pub fn poll(&mut self) -> Option<DecoderEvent> {
+        self.pending.pop_front()
+    }
+
+    /// Returns how many received bytes the decoder holds for frames in progress
+    ///
+    /// However many bytes are pushed, this stays below three times the largest frame the decoder
+    /// accepts, so a corrupted or hostile stream cannot make the decoder grow without limit. With
+    /// [`Framing::Extended`], that is a frame of up to 4 GiB unless a smaller one is set with
+    /// [`with_max_payload_length`](Self::with_max_payload_length).
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.payload.len() + self.candidate.len() + self.replay.len()
+    }
+
+    /// Returns `event` unless events are held back already, which come first
+    fn queue(&mut self, event: Option<DecoderEvent>) -> Option<DecoderEvent> {
+        if self.pending.is_empty() {
+            return event;
+        }
+        self.pending.extend(event);
+        self.pending.pop_front()
+    }
+
+    /// Whether the bytes of a frame after its start byte are kept, to be scanned again if it
+    /// fails its checksum
+    fn keeps_candidate(&self) -> bool {
+        self.framing.is_binary() || self.resync == Resync::Checksum
+    }
+
+    /// Whether a start byte inside a frame discards it and starts a new one, rather than being
+    /// taken as part of the frame
+    fn resyncs_on_start_byte(&self) -> bool {
+        self.framing.is_escaped() && self.resync == Resync::StartByte
+    }
+
+    /// The longest payload accepted, which is at most the largest the framing can carry
+    fn max_payload_length(&self) -> usize {
+        let max = self.framing.max_payload_length();
+        self.max_payload_length
+            .map_or(max, |length| length.min(max))
+    }
+
+    /// The least a frame's length field can count: the message type, and the CRC-32 ending the
+    /// payload with [`Resync::Checksum`]
+    fn minimum_length(&self) -> usize {
+        let checksum = if self.framing.is_escaped() && self.resync == Resync::Checksum {
+            CHECKSUM_LENGTH
+        } else {
+            0
+        };
+        self.framing.message_type_length() + checksum
+    }
+
+    /// Pushes a byte into a framing with a start byte
+    fn push_framed(&mut self, byte: u8) -> Option<DecoderEvent> {
+        if byte == START_BYTE
+            && (self.state == State::WaitingForStart || self.resyncs_on_start_byte())
+        {
+            let event = if self.state != State::WaitingForStart {
+                Some(DecoderEvent::Resync)
+            } else if self.skipped > 0 {
+                Some(DecoderEvent::Skipped(self.skipped))
+            } else {
+                None
+            };
+            self.start_frame();
+            return event;
+        }
+
+        if self.state == State::WaitingForStart {
+            if !self.is_padding(byte) {
+                self.skipped += 1;
+            }
+            return None;
+        }
+
+        if self.keeps_candidate() {
+            self.candidate.push(byte);
+        }
+        let byte = if !self.framing.is_escaped() {
+            byte
+        } else if self.escaped {
+            self.escaped = false;
+            byte ^ XOR_BYTE
+        } else if byte == ESCAPE_BYTE {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+
+        match self.state {
+            State::WaitingForStart => return None,
+            State::Trailer => {
+                if Some(byte) != self.trailer {
+                    self.discard();
+                    return Some(DecoderEvent::InvalidTrailer(byte));
+                }
+                return Some(self.complete_frame());
+            }
+            State::Checksum => return self.check_binary_checksum(),
+            State::Header => {
+                if let Some(event) = self.push_header(byte) {
+                    return Some(event);
+                }
+                if self.state != State::Payload {
+                    return None;
+                }
+            }
+            State::Payload => self.payload.push(byte),
+        }
+
+        if self.payload.len() < self.payload_length {
+            return None;
+        }
+        if self.framing.is_binary() {
+            self.state = State::Checksum;
+            return None;
+        }
+        if self.trailer.is_some() {
+            self.state = State::Trailer;
+            return None;
+        }
+        Some(self.complete_frame())
+    }
+
+    /// Adds a byte to the header, and once it is complete moves on to the payload, or discards
+    /// the frame if its length field is too short
+    fn push_header(&mut self, byte: u8) -> Option<DecoderEvent> {
+        self.header[self.header_length] = byte;
+        self.header_length += 1;
+        if self.header_length < self.framing.header_length() {
+            return None;
+        }
+
+        let header = self.header;
+        if self.framing == Framing::CheckedHeader {
+            let received = self.endianness.u16_from_bytes([header[4], header[5]]);
+            let expected = crc16(&header[..4]);
+            if received != expected {
+                self.discard();
+                return Some(DecoderEvent::HeaderChecksumMismatch { expected, received });
+            }
+        }
+        let length = match self.framing {
+            Framing::Compact => {
+                self.message_type = u16::from(header[1]);
+                u32::from(header[0])
+            }
+            Framing::Extended => {
+                self.message_type = self.endianness.u16_from_bytes([header[4], header[5]]);
+                self.endianness
+                    .u32_from_bytes([header[0], header[1], header[2], header[3]])
+            }
+            Framing::Native
+            | Framing::Binary
+            | Framing::CheckedHeader
+            | Framing::Slip
+            | Framing::Cobs => {
+                self.message_type = self.endianness.u16_from_bytes([header[2], header[3]]);
+                u32::from(self.endianness.u16_from_bytes([header[0], header[1]]))
+            }
+        };
+        if (length as usize) < self.minimum_length() {
+            self.discard();
+            // Too short to hold the message type and any checksum, so at most 5
+            #[allow(clippy::cast_possible_truncation)]
+            return Some(DecoderEvent::InvalidLength(length as u16));
+        }
+        self.payload_length = length as usize - self.framing.message_type_length();
+        if self.payload_length > self.max_payload_length() {
+            self.discard();
+            // Extended lengths beyond the field of the event are reported as its largest value
+            return Some(DecoderEvent::InvalidLength(
+                u16::try_from(length).unwrap_or(u16::MAX),
+            ));
+        }
+        // An extended length may be corrupted, so the payload grows as it arrives beyond the
+        // largest native one
+        self.payload = Vec::with_capacity(self.payload_length.min(MAX_PAYLOAD_LENGTH));
+        self.state = State::Payload;
+        None
+    }
+
+    /// Ends a binary frame once its checksum has been received, if it matches
+    fn check_binary_checksum(&mut self) -> Option<DecoderEvent> {
+        let end = self.framing.header_length() + self.payload_length + CHECKSUM_LENGTH;
+        if self.candidate.len() < end {
+            return None;
+        }
+        let split = end - CHECKSUM_LENGTH;
+        let checksum = &self.candidate[split..];
+        let received =
+            self.endianness
+                .u32_from_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let expected = crc32(&self.candidate[..split]);
+        if received != expected {
+            self.discard();
+            return Some(DecoderEvent::ChecksumMismatch { expected, received });
+        }
+        Some(self.complete_frame())
+    }
+
+    /// Ends a frame whose payload and any trailer or checksum have been received, checking the
+    /// CRC-32 ending its payload with [`Resync::Checksum`]
+    fn complete_frame(&mut self) -> DecoderEvent {
+        if self.framing.is_escaped() && self.resync == Resync::Checksum {
+            let split = self.payload.len() - CHECKSUM_LENGTH;
+            let checksum = &self.payload[split..];
+            let received = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+            let expected = Crc32::checksum(self.message_type, &self.payload[..split]);
+            if received != expected {
+                self.discard();
+                return DecoderEvent::ChecksumMismatch { expected, received };
+            }
+        }
+        self.state = State::WaitingForStart;
+        self.candidate.clear();
+        DecoderEvent::Frame(Frame {
+            message_type: self.message_type,
+            payload: std::mem::take(&mut self.payload),
+        })
+    }
+
+    /// Discards the frame being received, queueing its bytes from the first start byte after
+    /// its own to be pushed again if they were kept, as that may be where the next frame really
+    /// starts
+    fn discard(&mut self) {
+        self.state = State::WaitingForStart;
+        self.escaped = false;
+        self.payload.clear();
+        let candidate = std::mem::take(&mut self.candidate);
+        if let Some(index) = candidate.iter().position(|&byte| byte == START_BYTE) {
+            for &byte in candidate[index..].iter().rev() {
+                self.replay.push_front(byte);
+            }
+        }
+    }
+
+    /// Every byte up to an END is part of a packet, as SLIP has no start byte
+    fn push_slip(&mut self, byte: u8) -> Option<DecoderEvent> {
+        if byte == SLIP_END {
+            self.escaped = false;
+            return self.end_packet(true);
+        }
+
+        self.packet_length += 1;
+        let byte = if self.escaped {
+            self.escaped = false;
+            match byte {
+                SLIP_ESC_END => SLIP_END,
+                SLIP_ESC_ESC => SLIP_ESC,
+                // A protocol violation, which RFC 1055 leaves in the packet
+                byte => byte,
+            }
+        } else if byte == SLIP_ESC {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+        self.append(byte);
+        None
+    }
+
+    /// Every byte up to a zero is part of a packet, which starts with the first code byte
+    fn push_cobs(&mut self, byte: u8) -> Option<DecoderEvent> {
+        if byte == 0 {
+            // A packet ending inside a block was cut short
+            let complete = self.cobs_remaining == 0;
+            self.cobs_code = 0;
+            self.cobs_remaining = 0;
+            return self.end_packet(complete);
+        }
+
+        self.packet_length += 1;
+        if self.cobs_remaining > 0 {
+            self.cobs_remaining -= 1;
+            self.append(byte);
+            return None;
+        }
+        // Every block but the longest is followed by a zero, unless it ends the packet
+        if self.cobs_code != 0 && self.cobs_code != COBS_MAX_CODE {
+            self.append(0);
+        }
+        self.cobs_code = byte;
+        self.cobs_remaining = byte - 1;
+        None
+    }
+
+    /// Adds a byte to the packet being received, discarding a packet that grows too long to be
+    /// a frame up to its end
+    fn append(&mut self, byte: u8) {
+        if self.discarding {
+            return;
+        }
+        if self.payload.len() == MESSAGE_TYPE_LENGTH + self.max_payload_length() {
+            self.discarding = true;
+            self.payload.clear();
+            return;
+        }
+        self.payload.push(byte);
+    }
+
+    /// Ends the packet being received at a SLIP END or COBS delimiter
+    fn end_packet(&mut self, complete: bool) -> Option<DecoderEvent> {
+        let length = std::mem::take(&mut self.packet_length);
+        let mut payload = std::mem::take(&mut self.payload);
+        if std::mem::take(&mut self.discarding) || !complete {
+            return Some(DecoderEvent::Skipped(length + 1));
+        }
+        // Empty packets come from delimiters sent to flush line noise
+        if length == 0 {
+            return None;
+        }
+        if payload.len() < MESSAGE_TYPE_LENGTH {
+            #[allow(clippy::cast_possible_truncation)]
+            return Some(DecoderEvent::InvalidLength(payload.len() as u16));
+        }
+        let message_type = self.endianness.u16_from_bytes([payload[0], payload[1]]);
+        payload.drain(..MESSAGE_TYPE_LENGTH);
+        Some(DecoderEvent::Frame(Frame {
+            message_type,
+            payload,
+        }))
+    }
+
+    /// Returns whether the decoder is outside any frame, so that bytes other than the start byte
+    /// are skipped
+    pub(crate) fn is_waiting_for_start(&self) -> bool {
+        match self.framing {
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader => self.state == State::WaitingForStart,
+            Framing::Slip | Framing::Cobs => self.discarding,
+        }
+    }
+
+    /// Returns whether `byte` would be ignored as padding between frames if pushed next
+    pub(crate) fn is_padding(&self, byte: u8) -> bool {
+        self.padding
+            && byte == PADDING_BYTE
+            && self.framing.has_start_byte()
+            && self.state == State::WaitingForStart
+    }
+
+    /// Returns whether `byte` would be consumed as an escape byte if pushed next
+    ///
+    /// With COBS framing, this is a code byte that does not stand for a zero.
+    pub(crate) fn is_escape(&self, byte: u8) -> bool {
+        match self.framing {
+            Framing::Native | Framing::Compact | Framing::Extended => {
+                byte == ESCAPE_BYTE && self.state != State::WaitingForStart && !self.escaped
+            }
+            Framing::Binary | Framing::CheckedHeader => false,
+            Framing::Slip => byte == SLIP_ESC && !self.discarding && !self.escaped,
+            Framing::Cobs => {
+                byte != 0
+                    && !self.discarding
+                    && self.cobs_remaining == 0
+                    && (self.cobs_code == 0 || self.cobs_code == COBS_MAX_CODE)
+            }
+        }
+    }
+
+    /// Returns whether `byte` would be the first byte of a new frame if pushed next
+    ///
+    /// With SLIP and COBS framing, this is the first byte after a delimiter, so a frame starts
+    /// after the delimiter that preceded it. Unless resyncing on start bytes, start bytes inside
+    /// a frame are taken as part of it.
+    pub(crate) fn is_frame_start(&self, byte: u8) -> bool {
+        match self.framing {
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader => {
+                byte == START_BYTE
+                    && (self.state == State::WaitingForStart || self.resyncs_on_start_byte())
+            }
+            Framing::Slip => byte != SLIP_END && self.packet_length == 0,
+            Framing::Cobs => byte != 0 && self.packet_length == 0,
+        }
+    }
+
+    /// Returns the message type and payload length of a frame whose header has just been
+    /// received, before any of its payload
+    pub(crate) fn pending_payload(&self) -> Option<(u16, usize)> {
+        (self.state == State::Payload && self.payload.is_empty())
+            .then_some((self.message_type, self.payload_length))
+    }
+
+    /// Appends the payload bytes at the start of `bytes` that need no unescaping, as pushing them
+    /// one at a time with [`push_at`](Self::push_at) would, returning how many were taken
+    ///
+    /// The last byte of the payload is left to be pushed, as it completes the frame. Nothing is
+    /// taken outside an escaped frame's payload, or where bytes are kept to be scanned again.
+    pub(crate) fn push_payload_run(&mut self, bytes: &[u8], now: Instant) -> usize {
+        if self.state != State::Payload
+            || self.escaped
+            || !self.framing.is_escaped()
+            || self.keeps_candidate()
+            || !self.pending.is_empty()
+        {
+            return 0;
+        }
+        let remaining = (self.payload_length - self.payload.len()).saturating_sub(1);
+        let run = unescaped_run(&bytes[..bytes.len().min(remaining)]);
+        if run == 0 {
+            return 0;
+        }
+        if let Some(event) = self.expire(now) {
+            self.pending.push_back(event);
+            return 0;
+        }
+        self.last_byte = Some(now);
+        self.payload.extend_from_slice(&bytes[..run]);
+        run
+    }
+
+    /// Moves on from a payload that was received without being pushed into the decoder, to the
+    /// trailer if there is one or else to waiting for the next start byte
+    pub(crate) fn finish_payload(&mut self) {
+        self.escaped = false;
+        self.state = if self.trailer.is_some() {
+            State::Trailer
+        } else {
+            State::WaitingForStart
+        };
+    }
+
+    /// Returns whether part of a frame, or of a SLIP or COBS packet, has been received
+    fn is_in_frame(&self) -> bool {
+        match self.framing {
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader => self.state != State::WaitingForStart,
+            Framing::Slip | Framing::Cobs => self.packet_length > 0,
+        }
+    }
+
+    /// Abandons the frame being received, waiting for the next start byte, or with SLIP and COBS
+    /// for the next packet
+    pub(crate) fn reset(&mut self) {
+        self.state = State::WaitingForStart;
+        self.escaped = false;
+        self.payload.clear();
+        self.candidate.clear();
+        self.packet_length = 0;
+        self.discarding = false;
+        self.cobs_code = 0;
+        self.cobs_remaining = 0;
+    }
+
+    fn start_frame(&mut self) {
+        self.state = State::Header;
+        self.escaped = false;
+        self.skipped = 0;
+        self.header_length = 0;
+        self.payload.clear();
+        self.candidate.clear();
+    }
+}
+
+/// Encodes a frame with an already encoded payload, escaping everything after the start byte
+///
+/// The payload must be at most [`MAX_PAYLOAD_LENGTH`] bytes long.
+#[must_use]
+pub fn encode_frame(message_type: u16, payload: &[u8]) -> Vec<u8> {
+    encode_frame_with(message_type, payload, Endianness::Little)
+}
+
+/// Encodes a frame like [`encode_frame`], with the length and message type in the given byte
+/// order
+#[must_use]
+pub fn encode_frame_with(message_type: u16, payload: &[u8], endianness: Endianness) -> Vec<u8> {
+    #[allow(clippy::cast_possible_truncation)]
+    let length = (MESSAGE_TYPE_LENGTH + payload.len()) as u16;
+
+    let mut frame = Vec::with_capacity(1 + 2 * (4 + payload.len()));
+    frame.push(START_BYTE);
+    escape_into(&mut frame, &endianness.u16_to_bytes(length));
+    escape_into(&mut frame, &endianness.u16_to_bytes(message_type));
+    escape_into(&mut frame, payload);
+    frame
+}
+
+/// Computes the CRC-16/CCITT-FALSE of `bytes`, as used for [`Framing::CheckedHeader`] headers
+pub(crate) fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in bytes {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x1021
+            };
+        }
+    }
+    crc
+}
+
+/// Returns the number of bytes at the start of `bytes` before the first start or escape byte,
+/// which are the same escaped and unescaped
+///
+/// Searches with `memchr`, which checks many bytes at once, so that runs of bytes in a bulk
+/// payload can be copied without a branch per byte.
+pub(crate) fn unescaped_run(bytes: &[u8]) -> usize {
+    memchr::memchr2(START_BYTE, ESCAPE_BYTE, bytes).unwrap_or(bytes.len())
+}
+
+/// Appends `bytes` to `frame`, escaping start and escape bytes
+///
+/// Runs of bytes that need no escaping, usually most of a payload, are copied in bulk.
+pub(crate) fn escape_into(frame: &mut Vec<u8>, mut bytes: &[u8]) {
+    while let Some(index) = memchr::memchr2(START_BYTE, ESCAPE_BYTE, bytes) {
+        frame.extend_from_slice(&bytes[..index]);
+        frame.push(ESCAPE_BYTE);
+        frame.push(bytes[index] ^ XOR_BYTE);
+        bytes = &bytes[index + 1..];
+    }
+    frame.extend_from_slice(bytes);
+}
+
+/// Encodes a frame like [`encode_frame_with`], followed by `trailer`, for a
+/// [`Decoder::with_trailer`] to check
+///
+/// The trailer is escaped like the rest of the frame, and not counted by the length field.
+#[must_use]
+pub fn encode_frame_with_trailer(
+    message_type: u16,
+    payload: &[u8],
+    endianness: Endianness,
+    trailer: u8,
+) -> Vec<u8> {
+    let mut frame = encode_frame_with(message_type, payload, endianness);
+    escape_into(&mut frame, &[trailer]);
+    frame
+}
+
+/// Appends `bytes` to `frame`, replacing END and ESC bytes with SLIP escape sequences
+fn slip_escape_into(frame: &mut Vec<u8>, bytes: &[u8]) {
+    for &byte in bytes {
+        match byte {
+            SLIP_END => frame.extend([SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => frame.extend([SLIP_ESC, SLIP_ESC_ESC]),
+            byte => frame.push(byte),
+        }
+    }
+}
+
+/// Appends `bytes` to `frame` as COBS blocks, each starting with a code byte one more than the
+/// number of bytes before the next zero
+fn cobs_encode_into<'a>(frame: &mut Vec<u8>, bytes: impl IntoIterator<Item = &'a u8>) {
+    let mut code_index = frame.len();
+    frame.push(1);
+    for &byte in bytes {
+        if byte != 0 {
+            frame.push(byte);
+            frame[code_index] += 1;
+        }
+        if byte == 0 || frame[code_index] == COBS_MAX_CODE {
+            code_index = frame.len();
+            frame.push(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,661 @@
+use super::*;
+use crate::firmware::crc32;
+
+fn push_all(decoder: &mut Decoder, bytes: &[u8]) -> Vec<DecoderEvent> {
+    bytes
+        .iter()
+        .filter_map(|&byte| decoder.push(byte))
+        .collect()
+}
+
+#[test]
+fn test_encode_frame() {
+    assert_eq!(
+        encode_frame(0, &[START_BYTE, 0x01, ESCAPE_BYTE]),
+        vec![
+            START_BYTE,
+            0x05,
+            0x00,
+            0x00,
+            0x00,
+            ESCAPE_BYTE,
+            START_BYTE ^ XOR_BYTE,
+            0x01,
+            ESCAPE_BYTE,
+            ESCAPE_BYTE ^ XOR_BYTE,
+        ]
+    );
+}
+
+#[test]
+fn test_escape_runs() {
+    let mut frame = Vec::new();
+    escape_into(
+        &mut frame,
+        &[START_BYTE, START_BYTE, 0x01, 0x02, ESCAPE_BYTE, 0x03],
+    );
+    assert_eq!(
+        frame,
+        vec![
+            ESCAPE_BYTE,
+            START_BYTE ^ XOR_BYTE,
+            ESCAPE_BYTE,
+            START_BYTE ^ XOR_BYTE,
+            0x01,
+            0x02,
+            ESCAPE_BYTE,
+            ESCAPE_BYTE ^ XOR_BYTE,
+            0x03,
+        ]
+    );
+}
+
+#[test]
+fn test_decode_roundtrip() {
+    let payloads: [&[u8]; 4] = [&[], &[0x57], &[START_BYTE, ESCAPE_BYTE, 0x00], &[0; 0x40]];
+    let mut decoder = Decoder::new();
+
+    for (message_type, payload) in (0x4140..).zip(payloads) {
+        let events = push_all(&mut decoder, &encode_frame(message_type, payload));
+        assert_eq!(
+            events,
+            vec![DecoderEvent::Frame(Frame {
+                message_type,
+                payload: payload.to_vec(),
+            })]
+        );
+    }
+}
+
+#[test]
+fn test_big_endian_frame() {
+    let frame = encode_frame_with(0x0102, &[0x57], Endianness::Big);
+    assert_eq!(frame, vec![START_BYTE, 0x00, 0x03, 0x01, 0x02, 0x57]);
+
+    let mut decoder = Decoder::new().with_endianness(Endianness::Big);
+    assert_eq!(
+        push_all(&mut decoder, &frame),
+        vec![DecoderEvent::Frame(Frame {
+            message_type: 0x0102,
+            payload: vec![0x57],
+        })]
+    );
+}
+
+#[test]
+fn test_decode_skips_garbage() {
+    let mut decoder = Decoder::new();
+    let mut bytes = vec![0x00, 0xFF, ESCAPE_BYTE, 0x13];
+    bytes.extend(encode_frame(4, &[]));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Skipped(4),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_resync() {
+    let mut decoder = Decoder::new();
+    let mut bytes = vec![START_BYTE, 0x07, 0x00, 0x00, 0x00, 0x01];
+    bytes.extend(encode_frame(1, &[0x57]));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Resync,
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x57],
+            })
+        ]
+    );
+}
+
+#[test]
+fn test_decode_length_too_short() {
+    let mut decoder = Decoder::new();
+    let mut bytes = vec![START_BYTE, 0x01, 0x00, 0x04, 0x00];
+    bytes.extend(encode_frame(4, &[]));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::InvalidLength(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_slip_encode() {
+    assert_eq!(
+        Framing::Slip.encode(0x00C0, &[0x01, 0xDB, 0xC0], Endianness::Little),
+        vec![0xC0, 0xDB, 0xDC, 0x00, 0x01, 0xDB, 0xDD, 0xDB, 0xDC, 0xC0]
+    );
+}
+
+#[test]
+fn test_slip_decode() {
+    let mut decoder = Decoder::new().with_framing(Framing::Slip);
+    let mut bytes = Framing::Slip.encode(0x00C0, &[0x01, 0xDB, 0xC0], Endianness::Little);
+    // A packet too short for a message type, then a frame sent without a leading END
+    bytes.extend([0x05, 0xC0, 0x01, 0x00, 0x57, 0xC0]);
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Frame(Frame {
+                message_type: 0x00C0,
+                payload: vec![0x01, 0xDB, 0xC0],
+            }),
+            DecoderEvent::InvalidLength(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x57],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_slip_decode_skips_oversized_packet() {
+    let mut decoder = Decoder::new().with_framing(Framing::Slip);
+    let mut bytes = vec![0x01; MESSAGE_TYPE_LENGTH + MAX_PAYLOAD_LENGTH + 1];
+    bytes.push(0xC0);
+    bytes.extend(Framing::Slip.encode(1, &[0x57], Endianness::Little));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Skipped(MESSAGE_TYPE_LENGTH + MAX_PAYLOAD_LENGTH + 2),
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x57],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_cobs_encode() {
+    assert_eq!(
+        Framing::Cobs.encode(1, &[0x00, 0x11], Endianness::Little),
+        vec![0x02, 0x01, 0x01, 0x02, 0x11, 0x00]
+    );
+
+    // A run of 254 bytes without a zero fills a block, so needs no zero after it
+    let payload = [START_BYTE; 252];
+    let frame = Framing::Cobs.encode(0x0101, &payload, Endianness::Little);
+    assert_eq!(frame.len(), 1 + 254 + 1 + 1);
+    assert_eq!(frame[0], 0xFF);
+    assert_eq!(frame[255..], [0x01, 0x00]);
+}
+
+#[test]
+fn test_cobs_decode() {
+    let mut decoder = Decoder::new().with_framing(Framing::Cobs);
+    let mut bytes = vec![0x00];
+    bytes.extend(Framing::Cobs.encode(1, &[0x00, 0x11], Endianness::Little));
+    bytes.extend(Framing::Cobs.encode(0x0101, &[START_BYTE; 252], Endianness::Little));
+    // A packet cut short, then one too short for a message type
+    bytes.extend([0x05, 0x01, 0x00, 0x01, 0x00]);
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x00, 0x11],
+            }),
+            DecoderEvent::Frame(Frame {
+                message_type: 0x0101,
+                payload: vec![START_BYTE; 252],
+            }),
+            DecoderEvent::Skipped(3),
+            DecoderEvent::InvalidLength(0),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_trailer() {
+    let mut decoder = Decoder::new().with_trailer(START_BYTE);
+    let mut bytes = encode_frame_with_trailer(1, &[0x57], Endianness::Little, START_BYTE);
+    assert_eq!(bytes[6..], [ESCAPE_BYTE, START_BYTE ^ XOR_BYTE]);
+    bytes.extend(encode_frame_with_trailer(
+        4,
+        &[],
+        Endianness::Little,
+        START_BYTE,
+    ));
+    // A length field one too short leaves the last payload byte where the trailer should be
+    bytes.extend([START_BYTE, 0x02, 0x00, 0x01, 0x00, 0x57]);
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x57],
+            }),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+            DecoderEvent::InvalidTrailer(0x57),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_padding() {
+    let mut bytes = encode_frame(1, &[0x00]);
+    bytes.extend([0x00, 0x00, 0x00]);
+    bytes.extend(encode_frame(4, &[]));
+    let frames = vec![
+        DecoderEvent::Frame(Frame {
+            message_type: 1,
+            payload: vec![0x00],
+        }),
+        DecoderEvent::Frame(Frame {
+            message_type: 4,
+            payload: vec![],
+        }),
+    ];
+
+    let mut decoder = Decoder::new().with_padding();
+    assert_eq!(push_all(&mut decoder, &bytes), frames);
+
+    // Without padding, the same bytes are skipped
+    let mut decoder = Decoder::new();
+    let events = push_all(&mut decoder, &bytes);
+    assert_eq!(events[1], DecoderEvent::Skipped(3));
+}
+
+#[test]
+fn test_compact_encode() {
+    assert_eq!(
+        Framing::Compact.encode(0x23, &[0x07, START_BYTE], Endianness::Little),
+        vec![
+            START_BYTE,
+            0x03,
+            0x23,
+            0x07,
+            ESCAPE_BYTE,
+            START_BYTE ^ XOR_BYTE
+        ]
+    );
+    assert!(Framing::Compact.fits(0xFF, MAX_COMPACT_PAYLOAD_LENGTH));
+    assert!(!Framing::Compact.fits(0x0100, 0));
+    assert!(!Framing::Compact.fits(1, MAX_COMPACT_PAYLOAD_LENGTH + 1));
+    assert!(Framing::Native.fits(0x0100, MAX_PAYLOAD_LENGTH));
+}
+
+#[test]
+fn test_compact_decode() {
+    let mut decoder = Decoder::new().with_framing(Framing::Compact);
+    let mut bytes = vec![0x11];
+    bytes.extend(Framing::Compact.encode(0x23, &[0x07, 0x00], Endianness::Big));
+    bytes.extend(Framing::Compact.encode(
+        0xFF,
+        &[ESCAPE_BYTE; MAX_COMPACT_PAYLOAD_LENGTH],
+        Endianness::Little,
+    ));
+    // A length too short for the message type
+    bytes.extend([START_BYTE, 0x00, 0x01]);
+    bytes.extend(Framing::Compact.encode(4, &[], Endianness::Little));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Skipped(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 0x23,
+                payload: vec![0x07, 0x00],
+            }),
+            DecoderEvent::Frame(Frame {
+                message_type: 0xFF,
+                payload: vec![ESCAPE_BYTE; MAX_COMPACT_PAYLOAD_LENGTH],
+            }),
+            DecoderEvent::InvalidLength(0),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_extended_encode() {
+    assert_eq!(
+        Framing::Extended.encode(0x0123, &[0x07], Endianness::Big),
+        vec![START_BYTE, 0x00, 0x00, 0x00, 0x03, 0x01, 0x23, 0x07]
+    );
+    assert!(Framing::Extended.fits(0xFFFF, MAX_PAYLOAD_LENGTH + 1));
+}
+
+#[test]
+fn test_extended_decode() {
+    let payload: Vec<u8> = (0..=u8::MAX).cycle().take(100_000).collect();
+    let mut decoder = Decoder::new().with_framing(Framing::Extended);
+    let mut bytes = Framing::Extended.encode(0x0101, &payload, Endianness::Little);
+    // A length too short for the message type
+    bytes.extend([START_BYTE, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    bytes.extend(Framing::Extended.encode(4, &[], Endianness::Little));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Frame(Frame {
+                message_type: 0x0101,
+                payload,
+            }),
+            DecoderEvent::InvalidLength(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_extended_decode_max_payload_length() {
+    let mut decoder = Decoder::new()
+        .with_framing(Framing::Extended)
+        .with_max_payload_length(1024);
+    // A hostile length of 4 GiB, followed by a frame of the largest accepted payload
+    let mut bytes = vec![START_BYTE, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00];
+    bytes.extend([0x11; 64]);
+    bytes.extend(Framing::Extended.encode(2, &[0x22; 1024], Endianness::Little));
+    bytes.extend(Framing::Extended.encode(3, &[0x33; 1025], Endianness::Little));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::InvalidLength(u16::MAX),
+            DecoderEvent::Skipped(64),
+            DecoderEvent::Frame(Frame {
+                message_type: 2,
+                payload: vec![0x22; 1024],
+            }),
+            DecoderEvent::InvalidLength(1027),
+        ]
+    );
+    // Nothing of the rejected frames is kept
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn test_binary_encode() {
+    let frame = Framing::Binary.encode(0x0123, &[START_BYTE, ESCAPE_BYTE], Endianness::Little);
+    let checksum = crc32(&[0x04, 0x00, 0x23, 0x01, START_BYTE, ESCAPE_BYTE]);
+    let mut expected = vec![START_BYTE, 0x04, 0x00, 0x23, 0x01, START_BYTE, ESCAPE_BYTE];
+    expected.extend(checksum.to_le_bytes());
+    assert_eq!(frame, expected);
+}
+
+#[test]
+fn test_binary_decode() {
+    let mut decoder = Decoder::new().with_framing(Framing::Binary);
+    let mut bytes = vec![0x11];
+    bytes.extend(Framing::Binary.encode(0x23, &[START_BYTE; 3], Endianness::Little));
+    // A length too short for the message type
+    bytes.extend([START_BYTE, 0x01, 0x00]);
+    bytes.extend(Framing::Binary.encode(4, &[], Endianness::Little));
+
+    assert_eq!(
+        push_all(&mut decoder, &bytes),
+        vec![
+            DecoderEvent::Skipped(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 0x23,
+                payload: vec![START_BYTE; 3],
+            }),
+            DecoderEvent::InvalidLength(1),
+            DecoderEvent::Frame(Frame {
+                message_type: 4,
+                payload: vec![],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_binary_decode_rescans_after_checksum_mismatch() {
+    let mut decoder = Decoder::new().with_framing(Framing::Binary);
+    let first = Framing::Binary.encode(1, &[0x01, 0x02], Endianness::Little);
+    let second = Framing::Binary.encode(2, &[0x03], Endianness::Little);
+    // A false start byte whose length field swallows both frames
+    let mut bytes = vec![START_BYTE, 0x20, 0x00, 0x07, 0x00];
+    bytes.extend(&first);
+    bytes.extend(&second);
+    bytes.resize(1 + 0x20 + 2 + 4, 0x00);
+
+    let mut events = push_all(&mut decoder, &bytes);
+    events.extend(std::iter::from_fn(|| decoder.poll()));
+    assert!(matches!(events[0], DecoderEvent::ChecksumMismatch { .. }));
+    assert_eq!(
+        events[1..],
+        [
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![0x01, 0x02],
+            }),
+            DecoderEvent::Frame(Frame {
+                message_type: 2,
+                payload: vec![0x03],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_crc16() {
+    assert_eq!(crc16(b"123456789"), 0x29B1);
+}
+
+#[test]
+fn test_checked_header_encode() {
+    let frame = Framing::CheckedHeader.encode(0x0201, &[0xAA], Endianness::Little);
+    let header_crc = crc16(&[0x03, 0x00, 0x01, 0x02]).to_le_bytes();
+    assert_eq!(frame[..5], [START_BYTE, 0x03, 0x00, 0x01, 0x02]);
+    assert_eq!(frame[5..7], header_crc);
+    assert_eq!(frame[7], 0xAA);
+    assert_eq!(frame[8..], crc32(&frame[1..8]).to_le_bytes());
+
+    let mut decoder = Decoder::new().with_framing(Framing::CheckedHeader);
+    assert_eq!(
+        push_all(&mut decoder, &frame),
+        [DecoderEvent::Frame(Frame {
+            message_type: 0x0201,
+            payload: vec![0xAA],
+        })]
+    );
+}
+
+#[test]
+fn test_checked_header_rejects_corrupted_length_at_once() {
+    let mut decoder = Decoder::new().with_framing(Framing::CheckedHeader);
+    let mut corrupted = Framing::CheckedHeader.encode(1, &[0x01, 0x02], Endianness::Little);
+    // A length of 0x4004 would otherwise wait for 16 KiB of payload
+    corrupted[2] = 0x40;
+    let next = Framing::CheckedHeader.encode(2, &[0x03], Endianness::Little);
+
+    // The mismatch is reported as soon as the header CRC arrives
+    let events = push_all(&mut decoder, &corrupted[..7]);
+    assert!(matches!(
+        events[..],
+        [DecoderEvent::HeaderChecksumMismatch { .. }]
+    ));
+    let mut bytes = corrupted[7..].to_vec();
+    bytes.extend(&next);
+    let events = push_all(&mut decoder, &bytes);
+    assert_eq!(
+        events.last(),
+        Some(&DecoderEvent::Frame(Frame {
+            message_type: 2,
+            payload: vec![0x03],
+        }))
+    );
+}
+
+#[test]
+fn test_decode_checksum_resync() {
+    use crate::layer::Layer;
+
+    let mut decoder = Decoder::new().with_resync(Resync::Checksum);
+    let encode = |message_type, payload: &[u8]| {
+        let frame = crate::layer::Crc32.encode(Frame {
+            message_type,
+            payload: payload.to_vec(),
+        });
+        let mut bytes = encode_frame(frame.message_type, &frame.payload);
+        // As sent by a peer that does not escape start bytes
+        bytes.retain(|&byte| byte != ESCAPE_BYTE);
+        bytes
+    };
+    let first = encode(1, &[0x01]);
+    let second = encode(2, &[0x02]);
+    // A false start byte whose length field swallows the first frame
+    let mut bytes = vec![START_BYTE, 0x09, 0x00, 0x00, 0x00];
+    bytes.extend(&first);
+    bytes.extend(&second);
+    let mut events = push_all(&mut decoder, &bytes);
+    events.extend(std::iter::from_fn(|| decoder.poll()));
+
+    assert!(matches!(events[0], DecoderEvent::ChecksumMismatch { .. }));
+    assert!(matches!(
+        &events[1..],
+        [
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                ..
+            }),
+            DecoderEvent::Frame(Frame {
+                message_type: 2,
+                ..
+            })
+        ]
+    ));
+}
+
+#[test]
+fn test_decode_idle_gap_resync() {
+    let gap = Duration::from_millis(10);
+    let mut decoder = Decoder::new().with_resync(Resync::IdleGap(gap));
+    let start = Instant::now();
+    let mut events = Vec::new();
+    // A frame cut off after its start byte is taken as data, then the line goes quiet
+    for &byte in &[START_BYTE, 0x05, 0x00, 0x01, 0x00, START_BYTE] {
+        events.extend(decoder.push_at(byte, start));
+    }
+    for &byte in &encode_frame(1, &[START_BYTE]) {
+        events.extend(decoder.push_at(byte, start + gap));
+    }
+
+    assert_eq!(
+        events,
+        vec![
+            DecoderEvent::Resync,
+            DecoderEvent::Frame(Frame {
+                message_type: 1,
+                payload: vec![START_BYTE],
+            }),
+        ]
+    );
+}
+
+#[test]
+fn test_decode_inter_byte_timeout() {
+    let timeout = Duration::from_millis(10);
+    let mut decoder = Decoder::new().with_inter_byte_timeout(timeout);
+    let start = Instant::now();
+    for &byte in &[START_BYTE, 0x05, 0x00] {
+        assert_eq!(decoder.push_at(byte, start), None);
+    }
+
+    assert_eq!(decoder.expire(start + timeout / 2), None);
+    assert_eq!(decoder.expire(start + timeout), Some(DecoderEvent::Resync));
+    // Nothing is left to discard
+    assert_eq!(decoder.expire(start + timeout * 2), None);
+    assert_eq!(
+        push_all(&mut decoder, &encode_frame(1, &[0x07])),
+        vec![DecoderEvent::Frame(Frame {
+            message_type: 1,
+            payload: vec![0x07],
+        })]
+    );
+}
+
+#[test]
+fn test_decode_buffered_is_bounded() {
+    let decoders = [
+        Decoder::new(),
+        Decoder::new().with_resync(Resync::Checksum),
+        Decoder::new().with_framing(Framing::Slip),
+        Decoder::new().with_framing(Framing::Cobs),
+        Decoder::new().with_framing(Framing::Compact),
+        Decoder::new().with_framing(Framing::Extended),
+        Decoder::new().with_framing(Framing::Binary),
+    ];
+    for mut decoder in decoders {
+        let limit = 3 * (decoder.framing.max_payload_length() + decoder.framing.overhead());
+        // Every length field reads as its largest value, and no frame ever ends
+        for byte in [START_BYTE]
+            .into_iter()
+            .chain(std::iter::repeat_n(0xFF, 200_000))
+        {
+            decoder.push(byte);
+            while decoder.poll().is_some() {}
+            assert!(decoder.buffered() <= limit, "{:?}", decoder.framing);
+        }
+    }
+}
+
+#[test]
+fn test_payload_runs_decode_like_single_bytes() {
+    let mut stream = encode_frame(1, &(0..=u8::MAX).cycle().take(1000).collect::<Vec<_>>());
+    // A frame cut short by a start byte in the middle of a run, then one with a trailer
+    stream.extend(&encode_frame(2, &[0x11; 300])[..100]);
+    stream.extend(encode_frame_with_trailer(
+        3,
+        &[0x22; 50],
+        Endianness::Little,
+        0x0A,
+    ));
+    let decoders = [
+        Decoder::new(),
+        Decoder::new().with_trailer(0x0A),
+        Decoder::new().with_framing(Framing::Compact),
+        Decoder::new().with_resync(Resync::Checksum),
+    ];
+    for decoder in decoders {
+        let expected = push_all(&mut decoder.clone(), &stream);
+
+        let mut decoder = decoder;
+        let now = Instant::now();
+        let mut events = Vec::new();
+        let mut position = 0;
+        while position < stream.len() {
+            // Runs end at the end of each read of 64 bytes
+            let end = (position / 64 + 1) * 64;
+            position += decoder.push_payload_run(&stream[position..end.min(stream.len())], now);
+            events.extend(decoder.poll());
+            events.extend(decoder.push_at(stream[position], now));
+            position += 1;
+        }
+        assert_eq!(events, expected);
+    }
+}
@@ -0,0 +1,169 @@
+//! Arduino library generation for device peers.
+//!
+//! The library is the C implementation from [`c`](super::c) plus `GspSerial.h`, a header-only
+//! C++ class that sends and receives messages over any Arduino `Stream`, such as `Serial`. It
+//! has a `send<Name>` method for every message type and a `decode<Name>` method for every message
+//! type with a payload, taking the C structs declared in `gsp.h`.
+//!
+//! Received payloads and encoded frames are kept in fixed-size buffers inside the class, sized
+//! for payloads of up to `GSP_SERIAL_BUFFER_SIZE` bytes. Define it before including the header
+//! to change it.
+
+use super::c::{self, type_name};
+use super::{screaming_snake_case, snake_case};
+use crate::schema::{self, MessageDescriptor, PayloadDescriptor, StructDescriptor};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The file name the class header is expected to be saved as
+pub const HEADER_FILE_NAME: &str = "GspSerial.h";
+
+const GENERATED_NOTICE: &str =
+    "/* Generated by generic-serial-protocol. Do not edit, regenerate instead. */\n";
+
+/// Generates the C++ header defining the `GspSerial` class
+#[must_use]
+pub fn header() -> String {
+    let mut out = String::new();
+    out.push_str(GENERATED_NOTICE);
+    writeln!(
+        out,
+        "
+#ifndef GSP_SERIAL_H
+#define GSP_SERIAL_H
+
+#include <Arduino.h>
+
+#include \"{}\"
+
+/* The largest payload that can be received or sent */
+#ifndef GSP_SERIAL_BUFFER_SIZE
+#define GSP_SERIAL_BUFFER_SIZE 256
+#endif
+
+/* The longest frame a payload of GSP_SERIAL_BUFFER_SIZE bytes can be escaped into */
+#define GSP_SERIAL_FRAME_SIZE (1 + 2 * (4 + GSP_SERIAL_BUFFER_SIZE))
+
+class GspSerial {{
+public:
+    explicit GspSerial(Stream &stream) : stream_(stream) {{
+        gsp_decoder_init(&decoder_, payload_, sizeof payload_);
+    }}
+
+    /*
+     * Reads the bytes available from the stream, stopping once a whole frame has been received.
+     * Returns true if one has, after which messageType() and the decode methods refer to it
+     * until the next call.
+     */
+    bool poll() {{
+        while (stream_.available() > 0) {{
+            if (gsp_decoder_feed(&decoder_, (uint8_t)stream_.read()) == GSP_DECODER_COMPLETE) {{
+                return true;
+            }}
+        }}
+        return false;
+    }}
+
+    /* The message type of the frame received by the last successful poll() */
+    uint16_t messageType() const {{
+        return decoder_.message_type;
+    }}
+
+    const uint8_t *payload() const {{
+        return payload_;
+    }}
+
+    size_t payloadLength() const {{
+        return decoder_.payload_length;
+    }}
+
+    /* Sends a frame with an already encoded payload. Returns false if it could not be sent. */
+    bool sendFrame(uint16_t messageType, const uint8_t *payload, size_t payloadLength) {{
+        return write(gsp_encode_frame(messageType, payload, payloadLength, frame_, sizeof frame_));
+    }}",
+        c::HEADER_FILE_NAME
+    )
+    .unwrap();
+
+    for message in schema::messages() {
+        out.push('\n');
+        define_send(&mut out, message);
+        if has_payload(message) {
+            out.push('\n');
+            define_decode(&mut out, message);
+        }
+    }
+
+    out.push_str(
+        "
+private:
+    bool write(size_t length) {
+        return length > 0 && stream_.write(frame_, length) == length;
+    }
+
+    Stream &stream_;
+    gsp_decoder decoder_;
+    uint8_t payload_[GSP_SERIAL_BUFFER_SIZE];
+    uint8_t frame_[GSP_SERIAL_FRAME_SIZE];
+};
+
+#endif /* GSP_SERIAL_H */
+",
+    );
+    out
+}
+
+/// Writes the C header and source and the class header into `directory`, which can be an
+/// Arduino library's `src` directory or a sketch directory
+pub fn write_files(directory: impl AsRef<Path>) -> io::Result<()> {
+    let directory = directory.as_ref();
+    c::write_files(directory)?;
+    fs::write(directory.join(HEADER_FILE_NAME), header())
+}
+
+/// Messages without a payload have nothing to decode
+fn has_payload(message: &MessageDescriptor) -> bool {
+    !matches!(
+        message.payload,
+        PayloadDescriptor::Struct(StructDescriptor { fields: [], .. })
+    )
+}
+
+fn define_send(out: &mut String, message: &MessageDescriptor) {
+    let name = message.name;
+    let function = format!("gsp_encode_{}", snake_case(name));
+    let (parameter, arguments) = match message.payload {
+        PayloadDescriptor::Struct(_) if !has_payload(message) => (String::new(), String::new()),
+        PayloadDescriptor::Struct(_) => (
+            format!("const {} &message", type_name(message)),
+            "&message, ".to_string(),
+        ),
+        PayloadDescriptor::Enum(_) => (
+            format!("{} value", type_name(message)),
+            "value, ".to_string(),
+        ),
+    };
+    writeln!(
+        out,
+        "    bool send{name}({parameter}) {{\n        return write({function}({arguments}frame_, sizeof frame_));\n    }}"
+    )
+    .unwrap();
+}
+
+fn define_decode(out: &mut String, message: &MessageDescriptor) {
+    let name = message.name;
+    let parameter = match message.payload {
+        PayloadDescriptor::Struct(_) => "message",
+        PayloadDescriptor::Enum(_) => "value",
+    };
+    writeln!(
+        out,
+        "    /* Decodes the frame received if it is a {name}. Returns false if it is not or is invalid. */\n    bool decode{name}({} &{parameter}) const {{\n        return messageType() == GSP_MESSAGE_{} &&\n               gsp_decode_{}(payload_, payloadLength(), &{parameter}) == 0;\n    }}",
+        type_name(message),
+        screaming_snake_case(name),
+        snake_case(name)
+    )
+    .unwrap();
+}
@@ -0,0 +1,1088 @@
+//! C header and source generation for embedded peers.
+//!
+//! The generated `gsp.h`/`gsp.c` pair has no dependencies beyond the C99 standard headers and
+//! never allocates. Frames are encoded into a caller-supplied buffer, and received bytes are fed
+//! one at a time into a `gsp_decoder` that unescapes the payload into a caller-supplied buffer.
+//! Variable-length fields are decoded as pointers into the payload buffer.
+
+use super::{enums, screaming_snake_case, snake_case, structs};
+use crate::codec::{ESCAPE_BYTE, START_BYTE, XOR_BYTE};
+use crate::schema::{
+    self, EnumDescriptor, FieldDescriptor, FieldType, MessageDescriptor, PayloadDescriptor,
+    StructDescriptor, StructEncoding,
+};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The file name the header is expected to be saved as, since the source includes it
+pub const HEADER_FILE_NAME: &str = "gsp.h";
+/// The file name conventionally used for the source
+pub const SOURCE_FILE_NAME: &str = "gsp.c";
+
+const GENERATED_NOTICE: &str =
+    "/* Generated by generic-serial-protocol. Do not edit, regenerate instead. */\n";
+
+/// The message-independent part of the source: frame encoding and the decoder
+const FRAMING_SOURCE: &str = "
+typedef struct {
+    uint8_t *out;
+    size_t capacity;
+    size_t length;
+    int overflow;
+} gsp_writer;
+
+static gsp_writer gsp_writer_new(uint8_t *out, size_t capacity) {
+    gsp_writer writer;
+    writer.out = out;
+    writer.capacity = capacity;
+    writer.length = 0;
+    writer.overflow = 0;
+    return writer;
+}
+
+static void gsp_put(gsp_writer *writer, uint8_t byte) {
+    if (writer->length < writer->capacity) {
+        writer->out[writer->length++] = byte;
+    } else {
+        writer->overflow = 1;
+    }
+}
+
+static void gsp_put_escaped(gsp_writer *writer, uint8_t byte) {
+    if (byte == GSP_START_BYTE || byte == GSP_ESCAPE_BYTE) {
+        gsp_put(writer, GSP_ESCAPE_BYTE);
+        gsp_put(writer, (uint8_t)(byte ^ GSP_XOR_BYTE));
+    } else {
+        gsp_put(writer, byte);
+    }
+}
+
+static void gsp_put_escaped_bytes(gsp_writer *writer, const uint8_t *bytes, size_t length) {
+    size_t i;
+    for (i = 0; i < length; i++) {
+        gsp_put_escaped(writer, bytes[i]);
+    }
+}
+
+static void gsp_put_escaped_u16(gsp_writer *writer, uint16_t value) {
+    gsp_put_escaped(writer, (uint8_t)(value & 0xFFu));
+    gsp_put_escaped(writer, (uint8_t)(value >> 8));
+}
+
+static void gsp_put_escaped_uint(gsp_writer *writer, uint64_t value, size_t size) {
+    size_t i;
+    for (i = 0; i < size; i++) {
+        gsp_put_escaped(writer, (uint8_t)(value >> (8u * i)));
+    }
+}
+
+static void gsp_put_escaped_f32(gsp_writer *writer, float value) {
+    uint32_t bits;
+    memcpy(&bits, &value, sizeof bits);
+    gsp_put_escaped_uint(writer, bits, 4);
+}
+
+static void gsp_put_escaped_f64(gsp_writer *writer, double value) {
+    uint64_t bits;
+    memcpy(&bits, &value, sizeof bits);
+    gsp_put_escaped_uint(writer, bits, 8);
+}
+
+static uint64_t gsp_get_uint(const uint8_t *bytes, size_t size) {
+    uint64_t value = 0;
+    size_t i;
+    for (i = 0; i < size; i++) {
+        value |= (uint64_t)bytes[i] << (8u * i);
+    }
+    return value;
+}
+
+static int64_t gsp_get_int(const uint8_t *bytes, size_t size) {
+    uint64_t value = gsp_get_uint(bytes, size);
+    uint64_t sign = (uint64_t)1 << (8u * size - 1u);
+    if (value & sign) {
+        /* Negate in unsigned arithmetic to avoid implementation-defined conversions */
+        uint64_t magnitude = (~value & (sign | (sign - 1u))) + 1u;
+        return -(int64_t)(magnitude - 1u) - 1;
+    }
+    return (int64_t)value;
+}
+
+static float gsp_get_f32(const uint8_t *bytes) {
+    uint32_t bits = (uint32_t)gsp_get_uint(bytes, 4);
+    float value;
+    memcpy(&value, &bits, sizeof value);
+    return value;
+}
+
+static double gsp_get_f64(const uint8_t *bytes) {
+    uint64_t bits = gsp_get_uint(bytes, 8);
+    double value;
+    memcpy(&value, &bits, sizeof value);
+    return value;
+}
+
+static void gsp_begin_frame(gsp_writer *writer, uint16_t message_type, size_t payload_length) {
+    gsp_put(writer, GSP_START_BYTE);
+    gsp_put_escaped_u16(writer, (uint16_t)(payload_length + 2u));
+    gsp_put_escaped_u16(writer, message_type);
+}
+
+static size_t gsp_end_frame(const gsp_writer *writer) {
+    return writer->overflow ? 0 : writer->length;
+}
+
+size_t gsp_encode_frame(uint16_t message_type, const uint8_t *payload, size_t payload_length,
+                        uint8_t *out, size_t out_capacity) {
+    gsp_writer writer = gsp_writer_new(out, out_capacity);
+    if (payload_length > GSP_MAX_PAYLOAD_LENGTH) {
+        return 0;
+    }
+    gsp_begin_frame(&writer, message_type, payload_length);
+    gsp_put_escaped_bytes(&writer, payload, payload_length);
+    return gsp_end_frame(&writer);
+}
+
+void gsp_decoder_init(gsp_decoder *decoder, uint8_t *buffer, size_t capacity) {
+    decoder->buffer = buffer;
+    decoder->capacity = capacity;
+    decoder->in_frame = 0;
+    decoder->escaped = 0;
+    decoder->header_length = 0;
+    decoder->message_type = 0;
+    decoder->payload_length = 0;
+    decoder->received = 0;
+}
+
+int gsp_decoder_feed(gsp_decoder *decoder, uint8_t byte) {
+    if (byte == GSP_START_BYTE) {
+        /* Always the start of a new frame, abandoning any partial one */
+        decoder->in_frame = 1;
+        decoder->escaped = 0;
+        decoder->header_length = 0;
+        decoder->received = 0;
+        return GSP_DECODER_PENDING;
+    }
+    if (!decoder->in_frame) {
+        return GSP_DECODER_PENDING;
+    }
+    if (decoder->escaped) {
+        byte = (uint8_t)(byte ^ GSP_XOR_BYTE);
+        decoder->escaped = 0;
+    } else if (byte == GSP_ESCAPE_BYTE) {
+        decoder->escaped = 1;
+        return GSP_DECODER_PENDING;
+    }
+
+    if (decoder->header_length < sizeof(decoder->header)) {
+        uint16_t length;
+        decoder->header[decoder->header_length++] = byte;
+        if (decoder->header_length < sizeof(decoder->header)) {
+            return GSP_DECODER_PENDING;
+        }
+        length = (uint16_t)(decoder->header[0] | (decoder->header[1] << 8));
+        decoder->message_type = (uint16_t)(decoder->header[2] | (decoder->header[3] << 8));
+        if (length < 2u || (size_t)(length - 2u) > decoder->capacity) {
+            decoder->in_frame = 0;
+            return GSP_DECODER_ERROR;
+        }
+        decoder->payload_length = (size_t)(length - 2u);
+    } else {
+        decoder->buffer[decoder->received++] = byte;
+    }
+
+    if (decoder->received == decoder->payload_length) {
+        decoder->in_frame = 0;
+        return GSP_DECODER_COMPLETE;
+    }
+    return GSP_DECODER_PENDING;
+}
+";
+
+/// Varint helpers, only included if a field needs them so that none are unused
+const VARINT_SOURCE: &str = "
+static size_t gsp_varint_length(uint64_t value) {
+    size_t length = 1;
+    while (value >= 0x80u) {
+        value >>= 7;
+        length++;
+    }
+    return length;
+}
+
+static void gsp_put_escaped_varint(gsp_writer *writer, uint64_t value) {
+    while (value >= 0x80u) {
+        gsp_put_escaped(writer, (uint8_t)((value & 0x7Fu) | 0x80u));
+        value >>= 7;
+    }
+    gsp_put_escaped(writer, (uint8_t)value);
+}
+
+/* Reads the varint at *offset, advancing past it. Returns -1 if it is truncated or exceeds max. */
+static int gsp_get_varint(const uint8_t *payload, size_t payload_length, size_t *offset,
+                          uint64_t max, uint64_t *value) {
+    unsigned shift;
+    *value = 0;
+    for (shift = 0; shift < 64u; shift += 7u) {
+        uint64_t bits;
+        if (*offset >= payload_length) {
+            return -1;
+        }
+        bits = (uint64_t)(payload[*offset] & 0x7Fu);
+        if ((bits << shift) >> shift != bits) {
+            return -1;
+        }
+        *value |= bits << shift;
+        if ((payload[(*offset)++] & 0x80u) == 0) {
+            return *value > max ? -1 : 0;
+        }
+    }
+    return -1;
+}
+";
+
+/// Zigzag helpers for signed varints, only included if a field needs them
+const ZIGZAG_SOURCE: &str = "
+static uint64_t gsp_zigzag(int64_t value) {
+    /* Shift in unsigned arithmetic, as shifting negative values is undefined */
+    return value < 0 ? ~((uint64_t)value << 1) : (uint64_t)value << 1;
+}
+
+static int64_t gsp_unzigzag(uint64_t value) {
+    return (value & 1u) ? -(int64_t)(value >> 1) - 1 : (int64_t)(value >> 1);
+}
+";
+
+/// Generates the C header declaring the framing functions and message types
+#[must_use]
+pub fn header() -> String {
+    let mut out = String::new();
+    out.push_str(GENERATED_NOTICE);
+    out.push_str(
+        "
+#ifndef GSP_H
+#define GSP_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+
+",
+    );
+    writeln!(out, "#define GSP_START_BYTE 0x{START_BYTE:02X}").unwrap();
+    writeln!(out, "#define GSP_ESCAPE_BYTE 0x{ESCAPE_BYTE:02X}").unwrap();
+    writeln!(out, "#define GSP_XOR_BYTE 0x{XOR_BYTE:02X}").unwrap();
+    out.push_str(
+        "
+/* The largest payload a frame can carry, as the length field includes the message type */
+#define GSP_MAX_PAYLOAD_LENGTH (0xFFFFu - 2u)
+
+/* Return values of gsp_decoder_feed */
+#define GSP_DECODER_PENDING 0
+#define GSP_DECODER_COMPLETE 1
+#define GSP_DECODER_ERROR (-1)
+
+",
+    );
+
+    out.push_str("typedef enum {\n");
+    for message in schema::messages() {
+        writeln!(
+            out,
+            "    GSP_MESSAGE_{} = {},",
+            screaming_snake_case(message.name),
+            message.id
+        )
+        .unwrap();
+    }
+    out.push_str("} gsp_message_type;\n");
+
+    for descriptor in enums() {
+        out.push('\n');
+        declare_enum(&mut out, descriptor);
+    }
+
+    for descriptor in structs() {
+        out.push('\n');
+        declare_struct(&mut out, &descriptor);
+    }
+
+    for message in schema::messages() {
+        out.push('\n');
+        declare_message(&mut out, message);
+    }
+
+    out.push_str(
+        "
+typedef struct {
+    uint8_t *buffer;
+    size_t capacity;
+    int in_frame;
+    int escaped;
+    uint8_t header[4];
+    size_t header_length;
+    /* Valid once gsp_decoder_feed has returned GSP_DECODER_COMPLETE */
+    uint16_t message_type;
+    size_t payload_length;
+    size_t received;
+} gsp_decoder;
+
+/*
+ * Encodes a frame with an already encoded payload into out.
+ * Returns the frame length, or 0 if out is too small or the payload too long.
+ */
+size_t gsp_encode_frame(uint16_t message_type, const uint8_t *payload, size_t payload_length,
+                        uint8_t *out, size_t out_capacity);
+
+/* Prepares a decoder that unescapes payloads into buffer */
+void gsp_decoder_init(gsp_decoder *decoder, uint8_t *buffer, size_t capacity);
+
+/*
+ * Feeds a single received byte into the decoder.
+ * Returns GSP_DECODER_COMPLETE once a whole frame has been received, in which case the message
+ * type and payload_length fields are set and the payload is in the buffer. Returns
+ * GSP_DECODER_ERROR if a frame has an invalid length or does not fit in the buffer, after which
+ * the decoder waits for the next start byte.
+ */
+int gsp_decoder_feed(gsp_decoder *decoder, uint8_t byte);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GSP_H */
+",
+    );
+    out
+}
+
+/// Generates the C source implementing the functions declared in [`header`]
+#[must_use]
+pub fn source() -> String {
+    let mut out = String::new();
+    out.push_str(GENERATED_NOTICE);
+    writeln!(out, "\n#include \"{HEADER_FILE_NAME}\"").unwrap();
+    out.push_str("\n#include <string.h>\n");
+    out.push_str(FRAMING_SOURCE);
+    let varints = varints();
+    if !varints.is_empty() {
+        out.push_str(VARINT_SOURCE);
+    }
+    if varints.iter().any(|integer| is_signed(*integer)) {
+        out.push_str(ZIGZAG_SOURCE);
+    }
+
+    for descriptor in enums() {
+        out.push('\n');
+        define_enum_check(&mut out, descriptor);
+    }
+
+    for descriptor in structs() {
+        out.push('\n');
+        define_struct_functions(&mut out, &descriptor);
+    }
+
+    for message in schema::messages() {
+        out.push('\n');
+        define_encode(&mut out, message);
+        if let Some(signature) = decode_signature(message) {
+            out.push('\n');
+            define_decode(&mut out, message, &signature);
+        }
+    }
+    out
+}
+
+/// Writes the header and source into `directory` as [`HEADER_FILE_NAME`] and [`SOURCE_FILE_NAME`]
+pub fn write_files(directory: impl AsRef<Path>) -> io::Result<()> {
+    let directory = directory.as_ref();
+    fs::write(directory.join(HEADER_FILE_NAME), header())?;
+    fs::write(directory.join(SOURCE_FILE_NAME), source())
+}
+
+/// The integer type of every varint field
+fn varints() -> Vec<FieldType> {
+    structs()
+        .into_iter()
+        .flat_map(|descriptor| descriptor.fields)
+        .filter_map(|field| match field.field_type {
+            FieldType::Varint(integer) => Some(*integer),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_signed(integer: FieldType) -> bool {
+    matches!(
+        integer,
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64
+    )
+}
+
+/// The unsigned value encoded as a varint for the integer `value`
+fn varint_value(integer: FieldType, value: &str) -> String {
+    if is_signed(integer) {
+        format!("gsp_zigzag({value})")
+    } else {
+        format!("(uint64_t){value}")
+    }
+}
+
+pub(super) fn type_name(message: &MessageDescriptor) -> String {
+    match message.payload {
+        PayloadDescriptor::Struct(_) => struct_type_name(message.name),
+        PayloadDescriptor::Enum(descriptor) => enum_type_name(descriptor),
+    }
+}
+
+fn struct_type_name(name: &str) -> String {
+    format!("gsp_{}", snake_case(name))
+}
+
+fn enum_type_name(descriptor: &EnumDescriptor) -> String {
+    format!("gsp_{}", snake_case(descriptor.name))
+}
+
+fn enum_check_name(descriptor: &EnumDescriptor) -> String {
+    format!("gsp_is_{}", snake_case(descriptor.name))
+}
+
+fn encode_signature(message: &MessageDescriptor) -> String {
+    let name = snake_case(message.name);
+    match message.payload {
+        PayloadDescriptor::Struct(StructDescriptor { fields: [], .. }) => {
+            format!("size_t gsp_encode_{name}(uint8_t *out, size_t out_capacity)")
+        }
+        PayloadDescriptor::Struct(_) => format!(
+            "size_t gsp_encode_{name}(const {} *message, uint8_t *out, size_t out_capacity)",
+            type_name(message)
+        ),
+        PayloadDescriptor::Enum(_) => format!(
+            "size_t gsp_encode_{name}({} value, uint8_t *out, size_t out_capacity)",
+            type_name(message)
+        ),
+    }
+}
+
+/// Messages without a payload have nothing to decode
+fn decode_signature(message: &MessageDescriptor) -> Option<String> {
+    let name = snake_case(message.name);
+    match message.payload {
+        PayloadDescriptor::Struct(StructDescriptor { fields: [], .. }) => None,
+        PayloadDescriptor::Struct(_) => Some(format!(
+            "int gsp_decode_{name}(const uint8_t *payload, size_t payload_length, {} *message)",
+            type_name(message)
+        )),
+        PayloadDescriptor::Enum(_) => Some(format!(
+            "int gsp_decode_{name}(const uint8_t *payload, size_t payload_length, {} *value)",
+            type_name(message)
+        )),
+    }
+}
+
+/// The C type of a field stored by value: a number, bool or enum
+fn c_type(field_type: FieldType) -> String {
+    match field_type {
+        FieldType::U8 => "uint8_t".to_string(),
+        FieldType::U16 => "uint16_t".to_string(),
+        FieldType::U32 => "uint32_t".to_string(),
+        FieldType::U64 => "uint64_t".to_string(),
+        FieldType::I8 => "int8_t".to_string(),
+        FieldType::I16 => "int16_t".to_string(),
+        FieldType::I32 => "int32_t".to_string(),
+        FieldType::I64 => "int64_t".to_string(),
+        FieldType::F32 => "float".to_string(),
+        FieldType::F64 => "double".to_string(),
+        FieldType::Bool => "bool".to_string(),
+        FieldType::Enum(descriptor) => enum_type_name(descriptor),
+        FieldType::Varint(integer) => c_type(*integer),
+        FieldType::Bytes
+        | FieldType::String
+        | FieldType::Array(_)
+        | FieldType::Struct(_)
+        | FieldType::Option(_) => unreachable!("not a fixed-size field"),
+    }
+}
+
+/// A statement writing the fixed-size `value` with `writer`
+fn encode_fixed(field_type: FieldType, value: &str) -> String {
+    match field_type {
+        FieldType::U8 => format!("gsp_put_escaped(writer, {value})"),
+        FieldType::U16 => format!("gsp_put_escaped_u16(writer, {value})"),
+        FieldType::F32 => format!("gsp_put_escaped_f32(writer, {value})"),
+        FieldType::F64 => format!("gsp_put_escaped_f64(writer, {value})"),
+        FieldType::Bool => format!("gsp_put_escaped(writer, (uint8_t)({value} ? 1 : 0))"),
+        FieldType::Enum(_) => format!("gsp_put_escaped(writer, (uint8_t){value})"),
+        field_type => format!(
+            "gsp_put_escaped_uint(writer, (uint64_t){value}, {})",
+            field_type.size().unwrap()
+        ),
+    }
+}
+
+/// Statements reading the fixed-size field at `bytes` into `target`, returning -1 if it is invalid
+fn decode_fixed(field_type: FieldType, target: &str, bytes: &str) -> String {
+    let size = field_type.size().unwrap();
+    match field_type {
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => format!(
+            "{target} = ({})gsp_get_uint({bytes}, {size});",
+            c_type(field_type)
+        ),
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => format!(
+            "{target} = ({})gsp_get_int({bytes}, {size});",
+            c_type(field_type)
+        ),
+        FieldType::F32 => format!("{target} = gsp_get_f32({bytes});"),
+        FieldType::F64 => format!("{target} = gsp_get_f64({bytes});"),
+        FieldType::Bool => {
+            format!("if (*{bytes} > 1) {{\n    return -1;\n}}\n{target} = *{bytes} == 1;")
+        }
+        FieldType::Enum(descriptor) => format!(
+            "if (!{}(*{bytes})) {{\n    return -1;\n}}\n{target} = ({})*{bytes};",
+            enum_check_name(descriptor),
+            enum_type_name(descriptor)
+        ),
+        _ => unreachable!("not a fixed-size field"),
+    }
+}
+
+/// Writes each line of `code` indented by `depth` levels
+fn write_indented(out: &mut String, code: &str, depth: usize) {
+    for line in code.lines() {
+        writeln!(out, "{}{line}", "    ".repeat(depth)).unwrap();
+    }
+}
+
+/// The field inside an optional field, which must be fixed-size
+fn optional_inner(inner: &FieldType) -> FieldType {
+    assert!(
+        inner.size().is_some(),
+        "the C generator only supports fixed-size optional fields"
+    );
+    *inner
+}
+
+fn has_field(fields: &[FieldDescriptor], predicate: fn(&FieldType) -> bool) -> bool {
+    fields.iter().any(|field| predicate(&field.field_type))
+}
+
+fn declare_enum(out: &mut String, descriptor: &EnumDescriptor) {
+    out.push_str("typedef enum {\n");
+    for (variant, value) in descriptor.variants {
+        writeln!(
+            out,
+            "    GSP_{}_{} = {value},",
+            screaming_snake_case(descriptor.name),
+            screaming_snake_case(variant)
+        )
+        .unwrap();
+    }
+    writeln!(out, "}} {};", enum_type_name(descriptor)).unwrap();
+}
+
+/// Defines a function returning whether a received byte is one of the enum's values
+fn define_enum_check(out: &mut String, descriptor: &EnumDescriptor) {
+    writeln!(
+        out,
+        "static int {}(uint8_t value) {{\n    switch (value) {{",
+        enum_check_name(descriptor)
+    )
+    .unwrap();
+    for (variant, _) in descriptor.variants {
+        writeln!(
+            out,
+            "    case GSP_{}_{}:",
+            screaming_snake_case(descriptor.name),
+            screaming_snake_case(variant)
+        )
+        .unwrap();
+    }
+    out.push_str("        return 1;\n    default:\n        return 0;\n    }\n}\n");
+}
+
+fn declare_struct(out: &mut String, descriptor: &StructDescriptor) {
+    out.push_str("typedef struct {\n");
+    for field in descriptor.fields {
+        match field.field_type {
+            FieldType::Bytes => writeln!(
+                out,
+                "    const uint8_t *{0};\n    size_t {0}_length;",
+                field.name
+            )
+            .unwrap(),
+            FieldType::String => writeln!(
+                out,
+                "    /* Not NUL-terminated */\n    const char *{0};\n    size_t {0}_length;",
+                field.name
+            )
+            .unwrap(),
+            FieldType::Array(element) => writeln!(
+                out,
+                "    /* Decoding copies up to {0}_capacity elements into {0} */\n    {1} *{0};\n    size_t {0}_length;\n    size_t {0}_capacity;",
+                field.name,
+                c_type(*element)
+            )
+            .unwrap(),
+            FieldType::Struct(nested) => {
+                writeln!(out, "    {} {};", struct_type_name(nested.name), field.name).unwrap();
+            }
+            FieldType::Option(inner) => writeln!(
+                out,
+                "    bool has_{0};\n    {1} {0};",
+                field.name,
+                c_type(optional_inner(inner))
+            )
+            .unwrap(),
+            field_type => writeln!(out, "    {} {};", c_type(field_type), field.name).unwrap(),
+        }
+    }
+    writeln!(out, "}} {};", struct_type_name(descriptor.name)).unwrap();
+}
+
+fn declare_message(out: &mut String, message: &MessageDescriptor) {
+    writeln!(
+        out,
+        "/* Encodes a {} frame into out. Returns the frame length, or 0 if out is too small. */",
+        message.name
+    )
+    .unwrap();
+    writeln!(out, "{};", encode_signature(message)).unwrap();
+    if let Some(signature) = decode_signature(message) {
+        writeln!(
+            out,
+            "/* Decodes a {} payload. Returns 0 on success or -1 if the payload is invalid. */",
+            message.name
+        )
+        .unwrap();
+        writeln!(out, "{signature};").unwrap();
+    }
+}
+
+/// Defines the functions computing the encoded length of a struct, writing it and reading it
+fn define_struct_functions(out: &mut String, descriptor: &StructDescriptor) {
+    let name = snake_case(descriptor.name);
+    let type_name = struct_type_name(descriptor.name);
+
+    if descriptor.encoding == StructEncoding::Tlv {
+        // Each field is read by its own function, called for the entry with the field's tag
+        for field in descriptor.fields {
+            writeln!(
+                out,
+                "static int gsp_{name}_read_{}(const uint8_t *payload, size_t payload_length, {type_name} *message) {{",
+                field.name
+            )
+            .unwrap();
+            define_get(out, &[tlv_value(field)]);
+            out.push_str("}\n\n");
+        }
+    }
+
+    writeln!(
+        out,
+        "static size_t gsp_{name}_length(const {type_name} *message) {{"
+    )
+    .unwrap();
+    match descriptor.encoding {
+        StructEncoding::Positional => define_length(out, descriptor.fields),
+        StructEncoding::Tlv => define_tlv_length(out, descriptor.fields),
+    }
+    out.push_str("}\n\n");
+
+    writeln!(
+        out,
+        "static void gsp_{name}_write(gsp_writer *writer, const {type_name} *message) {{"
+    )
+    .unwrap();
+    match descriptor.encoding {
+        StructEncoding::Positional => define_put(out, descriptor.fields),
+        StructEncoding::Tlv => define_tlv_put(out, descriptor.fields),
+    }
+    out.push_str("}\n\n");
+
+    writeln!(
+        out,
+        "static int gsp_{name}_read(const uint8_t *payload, size_t payload_length, {type_name} *message) {{"
+    )
+    .unwrap();
+    match descriptor.encoding {
+        StructEncoding::Positional => define_get(out, descriptor.fields),
+        StructEncoding::Tlv => define_tlv_get(out, descriptor),
+    }
+    out.push_str("}\n");
+}
+
+fn define_length(out: &mut String, fields: &[FieldDescriptor]) {
+    let fixed_length: usize = fields
+        .iter()
+        .filter_map(|field| field.field_type.size())
+        .sum();
+    let mut length = Vec::new();
+    if fixed_length > 0 || fields.iter().all(|field| field.field_type.size().is_some()) {
+        length.push(fixed_length.to_string());
+    }
+    for field in fields {
+        let value = format!("message->{}", field.name);
+        match field.field_type {
+            FieldType::Bytes | FieldType::String => length.push(format!("{value}_length")),
+            FieldType::Array(element) => {
+                length.push(format!("2 + {value}_length * {}", element.size().unwrap()));
+            }
+            FieldType::Struct(nested) => length.push(format!(
+                "2 + gsp_{}_length(&{value})",
+                snake_case(nested.name)
+            )),
+            FieldType::Option(inner) => length.push(format!(
+                "(message->has_{} ? {} : 1)",
+                field.name,
+                1 + optional_inner(inner).size().unwrap()
+            )),
+            FieldType::Varint(integer) => length.push(format!(
+                "gsp_varint_length({})",
+                varint_value(*integer, &value)
+            )),
+            _ => (),
+        }
+    }
+    if length.len() == 1 && fixed_length > 0 {
+        out.push_str("    (void)message;\n");
+    }
+    writeln!(out, "    return {};", length.join(" + ")).unwrap();
+}
+
+fn define_put(out: &mut String, fields: &[FieldDescriptor]) {
+    if has_field(fields, |field_type| {
+        matches!(field_type, FieldType::Array(_))
+    }) {
+        out.push_str("    size_t i;\n");
+    }
+    for field in fields {
+        put_field(out, field);
+    }
+}
+
+/// Writes a single field, using `i` as the index of any array
+fn put_field(out: &mut String, field: &FieldDescriptor) {
+    let value = format!("message->{}", field.name);
+    match field.field_type {
+            FieldType::Bytes => writeln!(
+                out,
+                "    gsp_put_escaped_bytes(writer, {value}, {value}_length);"
+            )
+            .unwrap(),
+            FieldType::String => writeln!(
+                out,
+                "    gsp_put_escaped_bytes(writer, (const uint8_t *){value}, {value}_length);"
+            )
+            .unwrap(),
+            FieldType::Array(element) => writeln!(
+                out,
+                "    gsp_put_escaped_u16(writer, (uint16_t){value}_length);\n    for (i = 0; i < {value}_length; i++) {{\n        {};\n    }}",
+                encode_fixed(*element, &format!("{value}[i]"))
+            )
+            .unwrap(),
+            FieldType::Struct(nested) => writeln!(
+                out,
+                "    gsp_put_escaped_u16(writer, (uint16_t)gsp_{0}_length(&{value}));\n    gsp_{0}_write(writer, &{value});",
+                snake_case(nested.name)
+            )
+            .unwrap(),
+            FieldType::Option(inner) => writeln!(
+                out,
+                "    gsp_put_escaped(writer, (uint8_t)(message->has_{0} ? 1 : 0));\n    if (message->has_{0}) {{\n        {1};\n    }}",
+                field.name,
+                encode_fixed(optional_inner(inner), &value)
+            )
+            .unwrap(),
+        FieldType::Varint(integer) => writeln!(
+            out,
+            "    gsp_put_escaped_varint(writer, {});",
+            varint_value(*integer, &value)
+        )
+        .unwrap(),
+        field_type => writeln!(out, "    {};", encode_fixed(field_type, &value)).unwrap(),
+    }
+}
+
+fn define_get(out: &mut String, fields: &[FieldDescriptor]) {
+    out.push_str("    size_t offset = 0;\n");
+    if has_field(fields, |field_type| {
+        matches!(field_type, FieldType::Array(_))
+    }) {
+        out.push_str("    size_t i;\n");
+    }
+    if has_field(fields, |field_type| {
+        matches!(field_type, FieldType::Struct(_))
+    }) {
+        out.push_str("    size_t length;\n");
+    }
+    if has_field(fields, |field_type| {
+        matches!(field_type, FieldType::Varint(_))
+    }) {
+        out.push_str("    uint64_t varint;\n");
+    }
+    for field in fields {
+        let target = format!("message->{}", field.name);
+        match field.field_type {
+            FieldType::Bytes => writeln!(
+                out,
+                "    {target} = &payload[offset];\n    {target}_length = payload_length - offset;\n    offset = payload_length;"
+            )
+            .unwrap(),
+            FieldType::String => writeln!(
+                out,
+                "    {target} = (const char *)&payload[offset];\n    {target}_length = payload_length - offset;\n    offset = payload_length;"
+            )
+            .unwrap(),
+            FieldType::Array(element) => {
+                let size = element.size().unwrap();
+                writeln!(
+                    out,
+                    "    if (payload_length < offset + 2) {{\n        return -1;\n    }}\n    {target}_length = (size_t)gsp_get_uint(&payload[offset], 2);\n    offset += 2;\n    if ({target}_length > {target}_capacity || payload_length - offset < {target}_length * {size}) {{\n        return -1;\n    }}\n    for (i = 0; i < {target}_length; i++) {{"
+                )
+                .unwrap();
+                write_indented(
+                    out,
+                    &decode_fixed(*element, &format!("{target}[i]"), "&payload[offset]"),
+                    2,
+                );
+                writeln!(out, "        offset += {size};\n    }}").unwrap();
+            }
+            FieldType::Struct(nested) => writeln!(
+                out,
+                "    if (payload_length < offset + 2) {{\n        return -1;\n    }}\n    length = (size_t)gsp_get_uint(&payload[offset], 2);\n    offset += 2;\n    if (payload_length - offset < length || gsp_{}_read(&payload[offset], length, &{target}) != 0) {{\n        return -1;\n    }}\n    offset += length;",
+                snake_case(nested.name)
+            )
+            .unwrap(),
+            FieldType::Option(inner) => {
+                let inner = optional_inner(inner);
+                let size = inner.size().unwrap();
+                // Absent altogether if the payload ends first, as sent by older peers
+                writeln!(
+                    out,
+                    "    message->has_{0} = false;\n    if (offset < payload_length) {{\n        if (payload[offset] > 1) {{\n            return -1;\n        }}\n        message->has_{0} = payload[offset] == 1;\n        offset += 1;\n        if (message->has_{0}) {{\n            if (payload_length < offset + {size}) {{\n                return -1;\n            }}",
+                    field.name
+                )
+                .unwrap();
+                write_indented(out, &decode_fixed(inner, &target, "&payload[offset]"), 3);
+                writeln!(out, "            offset += {size};\n        }}\n    }}").unwrap();
+            }
+            FieldType::Varint(integer) => {
+                let value = if is_signed(*integer) {
+                    "gsp_unzigzag(varint)"
+                } else {
+                    "varint"
+                };
+                writeln!(
+                    out,
+                    "    if (gsp_get_varint(payload, payload_length, &offset, UINT{}_MAX, &varint) != 0) {{\n        return -1;\n    }}\n    {target} = ({}){value};",
+                    integer.size().unwrap() * 8,
+                    c_type(*integer)
+                )
+                .unwrap();
+            }
+            field_type => {
+                let size = field_type.size().unwrap();
+                writeln!(
+                    out,
+                    "    if (payload_length < offset + {size}) {{\n        return -1;\n    }}"
+                )
+                .unwrap();
+                write_indented(
+                    out,
+                    &decode_fixed(field_type, &target, "&payload[offset]"),
+                    1,
+                );
+                writeln!(out, "    offset += {size};").unwrap();
+            }
+        }
+    }
+    out.push_str("    (void)offset;\n    return 0;\n");
+}
+
+/// The value of a TLV entry, which for an optional field is only present alongside the value
+fn tlv_value(field: &FieldDescriptor) -> FieldDescriptor {
+    match field.field_type {
+        FieldType::Option(inner) => FieldDescriptor {
+            field_type: optional_inner(inner),
+            ..*field
+        },
+        _ => *field,
+    }
+}
+
+/// The encoded length of a field's TLV entry, excluding the tag and length
+fn tlv_value_length(field: &FieldDescriptor) -> String {
+    let value = format!("message->{}", field.name);
+    match tlv_value(field).field_type {
+        FieldType::Bytes | FieldType::String => format!("{value}_length"),
+        FieldType::Array(element) => format!("2 + {value}_length * {}", element.size().unwrap()),
+        FieldType::Struct(nested) => {
+            format!("2 + gsp_{}_length(&{value})", snake_case(nested.name))
+        }
+        FieldType::Varint(integer) => {
+            format!("gsp_varint_length({})", varint_value(*integer, &value))
+        }
+        field_type => field_type.size().unwrap().to_string(),
+    }
+}
+
+fn define_tlv_length(out: &mut String, fields: &[FieldDescriptor]) {
+    let length: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            let entry_length = format!("3 + {}", tlv_value_length(field));
+            if let FieldType::Option(_) = field.field_type {
+                format!("(message->has_{} ? {entry_length} : 0)", field.name)
+            } else {
+                entry_length
+            }
+        })
+        .collect();
+    if !length
+        .iter()
+        .any(|entry_length| entry_length.contains("message->"))
+    {
+        out.push_str("    (void)message;\n");
+    }
+    writeln!(out, "    return {};", length.join(" + ")).unwrap();
+}
+
+fn define_tlv_put(out: &mut String, fields: &[FieldDescriptor]) {
+    if has_field(fields, |field_type| {
+        matches!(field_type, FieldType::Array(_))
+    }) {
+        out.push_str("    size_t i;\n");
+    }
+    for field in fields {
+        let length = match tlv_value_length(field) {
+            length if length.contains(' ') => format!("({length})"),
+            length => length,
+        };
+        let mut entry = String::new();
+        writeln!(
+            entry,
+            "gsp_put_escaped(writer, {});\ngsp_put_escaped_u16(writer, (uint16_t){});",
+            field.tag.unwrap(),
+            length
+        )
+        .unwrap();
+        let mut value = String::new();
+        put_field(&mut value, &tlv_value(field));
+        for line in value.lines() {
+            writeln!(entry, "{}", line.strip_prefix("    ").unwrap_or(line)).unwrap();
+        }
+        if let FieldType::Option(_) = field.field_type {
+            writeln!(out, "    if (message->has_{}) {{", field.name).unwrap();
+            write_indented(out, &entry, 2);
+            out.push_str("    }\n");
+        } else {
+            write_indented(out, &entry, 1);
+        }
+    }
+}
+
+fn define_tlv_get(out: &mut String, descriptor: &StructDescriptor) {
+    let name = snake_case(descriptor.name);
+    let required: Vec<&str> = descriptor
+        .fields
+        .iter()
+        .filter(|field| !matches!(field.field_type, FieldType::Option(_)))
+        .map(|field| field.name)
+        .collect();
+
+    out.push_str("    size_t offset = 0;\n    size_t length;\n    uint8_t tag;\n");
+    for field in &required {
+        writeln!(out, "    bool seen_{field} = false;").unwrap();
+    }
+    for field in descriptor.fields {
+        if let FieldType::Option(_) = field.field_type {
+            writeln!(out, "    message->has_{} = false;", field.name).unwrap();
+        }
+    }
+    out.push_str("    while (offset < payload_length) {\n        if (payload_length - offset < 3) {\n            return -1;\n        }\n        tag = payload[offset];\n        length = (size_t)gsp_get_uint(&payload[offset + 1], 2);\n        offset += 3;\n        if (payload_length - offset < length) {\n            return -1;\n        }\n        switch (tag) {\n");
+    for field in descriptor.fields {
+        let seen = if let FieldType::Option(_) = field.field_type {
+            format!("message->has_{}", field.name)
+        } else {
+            format!("seen_{}", field.name)
+        };
+        writeln!(
+            out,
+            "        case {}:\n            if (gsp_{name}_read_{}(&payload[offset], length, message) != 0) {{\n                return -1;\n            }}\n            {seen} = true;\n            break;",
+            field.tag.unwrap(),
+            field.name
+        )
+        .unwrap();
+    }
+    // Entries with unknown tags come from newer peers, and are skipped
+    out.push_str(
+        "        default:\n            break;\n        }\n        offset += length;\n    }\n",
+    );
+    if !required.is_empty() {
+        let missing: Vec<String> = required
+            .iter()
+            .map(|field| format!("!seen_{field}"))
+            .collect();
+        writeln!(
+            out,
+            "    if ({}) {{\n        return -1;\n    }}",
+            missing.join(" || ")
+        )
+        .unwrap();
+    }
+    out.push_str("    return 0;\n");
+}
+
+fn define_encode(out: &mut String, message: &MessageDescriptor) {
+    let message_type = format!("GSP_MESSAGE_{}", screaming_snake_case(message.name));
+    writeln!(out, "{} {{", encode_signature(message)).unwrap();
+    out.push_str("    gsp_writer writer = gsp_writer_new(out, out_capacity);\n");
+
+    match message.payload {
+        PayloadDescriptor::Struct(StructDescriptor { fields: [], .. }) => {
+            writeln!(out, "    gsp_begin_frame(&writer, {message_type}, 0);").unwrap();
+        }
+        PayloadDescriptor::Struct(_) => {
+            let name = snake_case(message.name);
+            writeln!(
+                out,
+                "    size_t payload_length = gsp_{name}_length(message);\n    if (payload_length > GSP_MAX_PAYLOAD_LENGTH) {{\n        return 0;\n    }}\n    gsp_begin_frame(&writer, {message_type}, payload_length);\n    gsp_{name}_write(&writer, message);"
+            )
+            .unwrap();
+        }
+        PayloadDescriptor::Enum(_) => {
+            writeln!(out, "    gsp_begin_frame(&writer, {message_type}, 1);").unwrap();
+            out.push_str("    gsp_put_escaped(&writer, (uint8_t)value);\n");
+        }
+    }
+
+    out.push_str("    return gsp_end_frame(&writer);\n}\n");
+}
+
+fn define_decode(out: &mut String, message: &MessageDescriptor, signature: &str) {
+    writeln!(out, "{signature} {{").unwrap();
+
+    match message.payload {
+        PayloadDescriptor::Struct(_) => writeln!(
+            out,
+            "    return gsp_{}_read(payload, payload_length, message);\n}}",
+            snake_case(message.name)
+        )
+        .unwrap(),
+        PayloadDescriptor::Enum(descriptor) => {
+            writeln!(
+                out,
+                "    if (payload_length < 1 || !{}(payload[0])) {{\n        return -1;\n    }}\n    *value = ({})payload[0];\n    return 0;\n}}",
+                enum_check_name(descriptor),
+                type_name(message)
+            )
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Generators for implementations of the protocol in other languages.
+//!
+//! The generated code implements the framing, escaping and every message type described in
+//! [`schema`](crate::schema), so peers stay in sync with the Rust definitions. [`c`] targets
+//! firmware, [`arduino`] wraps it in a C++ class for Arduino boards, and [`typescript`] targets
+//! host UIs running in a browser or Electron.
+
+pub mod arduino;
+pub mod c;
+pub mod typescript;
+
+use crate::schema::{self, EnumDescriptor, FieldType, PayloadDescriptor, StructDescriptor};
+
+/// Converts a `CamelCase` name to `snake_case`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let word_ended = chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit();
+            let next_lowercase = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if word_ended || (next_lowercase && chars[i - 1].is_alphabetic()) {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// Converts a `snake_case` name to `camelCase`
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut capitalize = false;
+    for c in name.chars() {
+        if c == '_' {
+            capitalize = !camel.is_empty();
+        } else if capitalize {
+            camel.push(c.to_ascii_uppercase());
+            capitalize = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+/// Converts a `CamelCase` name to `SCREAMING_SNAKE_CASE`
+fn screaming_snake_case(name: &str) -> String {
+    snake_case(name).to_ascii_uppercase()
+}
+
+/// Every struct with fields, whether a message payload or nested, each listed once and after any
+/// structs nested in it
+pub(super) fn structs() -> Vec<StructDescriptor> {
+    fn visit(descriptor: StructDescriptor, structs: &mut Vec<StructDescriptor>) {
+        for field in descriptor.fields {
+            if let FieldType::Struct(nested) = field.field_type {
+                visit(*nested, structs);
+            }
+        }
+        if !structs.iter().any(|known| known.name == descriptor.name) {
+            structs.push(descriptor);
+        }
+    }
+
+    let mut structs = Vec::new();
+    for message in schema::messages() {
+        if let PayloadDescriptor::Struct(
+            descriptor @ StructDescriptor {
+                fields: [_, ..], ..
+            },
+        ) = message.payload
+        {
+            visit(*descriptor, &mut structs);
+        }
+    }
+    structs
+}
+
+/// Every enum used as a payload or field, each listed once
+pub(super) fn enums() -> Vec<&'static EnumDescriptor> {
+    let mut enums: Vec<&'static EnumDescriptor> = Vec::new();
+    let payload_enums = schema::messages()
+        .iter()
+        .filter_map(|message| match message.payload {
+            PayloadDescriptor::Enum(descriptor) => Some(descriptor),
+            PayloadDescriptor::Struct(_) => None,
+        });
+    let field_enums = structs()
+        .into_iter()
+        .flat_map(|descriptor| descriptor.fields)
+        .filter_map(|field| match field.field_type {
+            FieldType::Enum(descriptor) => Some(descriptor),
+            FieldType::Option(FieldType::Enum(descriptor)) => Some(*descriptor),
+            _ => None,
+        });
+    for descriptor in payload_enums.chain(field_enums) {
+        if !enums.iter().any(|known| known.name == descriptor.name) {
+            enums.push(descriptor);
+        }
+    }
+    enums
+}
+
+#[cfg(test)]
+mod tests;
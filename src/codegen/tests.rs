@@ -0,0 +1,82 @@
+use super::*;
+
+#[test]
+fn test_snake_case() {
+    assert_eq!(snake_case("U8"), "u8");
+    assert_eq!(snake_case("U16"), "u16");
+    assert_eq!(snake_case("MyString"), "my_string");
+    assert_eq!(snake_case("NoOp"), "no_op");
+    assert_eq!(snake_case("DeviceInfo"), "device_info");
+    assert_eq!(snake_case("U16Array"), "u16_array");
+    assert_eq!(screaming_snake_case("MyString"), "MY_STRING");
+    assert_eq!(camel_case("baud_rate"), "baudRate");
+    assert_eq!(camel_case("fw_version"), "fwVersion");
+    assert_eq!(camel_case("num"), "num");
+}
+
+#[test]
+fn test_c_header_declares_all_messages() {
+    let header = c::header();
+    for message in crate::schema::messages() {
+        assert!(header.contains(&format!(
+            "GSP_MESSAGE_{} = {},",
+            screaming_snake_case(message.name),
+            message.id
+        )));
+        assert!(header.contains(&format!("gsp_encode_{}(", snake_case(message.name))));
+    }
+    assert!(header.contains("#define GSP_START_BYTE 0x58"));
+    assert!(header.contains("int gsp_decoder_feed(gsp_decoder *decoder, uint8_t byte);"));
+}
+
+#[test]
+fn test_c_source_defines_all_messages() {
+    let source = c::source();
+    assert!(source.contains("#include \"gsp.h\""));
+    assert!(source.contains(
+        "size_t gsp_encode_multi(const gsp_multi *message, uint8_t *out, size_t out_capacity) {"
+    ));
+    assert!(source.contains(
+        "int gsp_decode_status(const uint8_t *payload, size_t payload_length, gsp_status *value) {"
+    ));
+    // Messages without a payload only get an encoder
+    assert!(source.contains("size_t gsp_encode_no_op(uint8_t *out, size_t out_capacity) {"));
+    assert!(!source.contains("gsp_decode_no_op"));
+}
+
+#[test]
+fn test_typescript_defines_all_messages() {
+    let source = typescript::source();
+    for message in crate::schema::messages() {
+        assert!(source.contains(&format!("  {} = {},", message.name, message.id)));
+        assert!(source.contains(&format!("export function encode{}(", message.name)));
+        assert!(source.contains(&format!("export function decode{}(", message.name)));
+    }
+    assert!(source.contains("export const START_BYTE = 0x58;"));
+    assert!(source.contains("export class Decoder {"));
+    assert!(source
+        .contains("export interface Settings {\n  baudRate?: number;\n  retries?: number;\n}"));
+    assert!(source.contains("    baudRate: optionalEntry(entries, 1, (r) => r.u32()),"));
+    assert!(source.contains("    count: r.varint(64),"));
+    assert!(source.contains(
+        "export function encodeStatus(message: Status, littleEndian = true): Uint8Array {"
+    ));
+    // Messages without a payload take no message to encode
+    assert!(source.contains("export function encodeNoOp(littleEndian = true): Uint8Array {"));
+}
+
+#[test]
+fn test_arduino_header_wraps_all_messages() {
+    let header = arduino::header();
+    assert!(header.contains("#include \"gsp.h\""));
+    assert!(header.contains("    explicit GspSerial(Stream &stream) : stream_(stream) {"));
+    for message in crate::schema::messages() {
+        assert!(header.contains(&format!("    bool send{}(", message.name)));
+    }
+    assert!(header.contains("    bool sendU8(const gsp_u8 &message) {"));
+    assert!(header.contains("    bool sendStatus(gsp_status value) {"));
+    assert!(header.contains("    bool decodeStatus(gsp_status &value) const {"));
+    // Messages without a payload only get a send method
+    assert!(header.contains("    bool sendNoOp() {"));
+    assert!(!header.contains("decodeNoOp"));
+}
@@ -0,0 +1,788 @@
+//! TypeScript generation for browser and Electron host UIs.
+//!
+//! The generated `gsp.ts` module has no dependencies. It defines an interface for every struct
+//! and an `enum` for every enum, with fields in `camelCase`, and encode and decode functions for
+//! every message type. `Decoder` unescapes frames from bytes pushed in as they arrive, such as
+//! the frames forwarded over a WebSocket, and `decodeMessage` decodes them. 64-bit integers are
+//! `bigint`s and other numbers are `number`s.
+
+use super::{camel_case, enums, structs};
+use crate::codec::{ESCAPE_BYTE, MAX_PAYLOAD_LENGTH, START_BYTE, XOR_BYTE};
+use crate::schema::{
+    self, EnumDescriptor, FieldDescriptor, FieldType, MessageDescriptor, PayloadDescriptor,
+    StructDescriptor, StructEncoding,
+};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The file name conventionally used for the module
+pub const FILE_NAME: &str = "gsp.ts";
+
+const GENERATED_NOTICE: &str =
+    "// Generated by generic-serial-protocol. Do not edit, regenerate instead.\n";
+
+/// The message-independent part of the module: framing, the decoder and payload encoding
+const RUNTIME_SOURCE: &str = r#"
+export class DecodeError extends Error {}
+
+export interface Frame {
+  messageType: number;
+  payload: Uint8Array;
+}
+
+function escape(bytes: Uint8Array, out: number[]): void {
+  for (const byte of bytes) {
+    if (byte === START_BYTE || byte === ESCAPE_BYTE) {
+      out.push(ESCAPE_BYTE, byte ^ XOR_BYTE);
+    } else {
+      out.push(byte);
+    }
+  }
+}
+
+/** Encodes a frame with an already encoded payload */
+export function encodeFrame(
+  messageType: number,
+  payload: Uint8Array,
+  littleEndian = true,
+): Uint8Array {
+  if (payload.length > MAX_PAYLOAD_LENGTH) {
+    throw new RangeError(`payload of ${payload.length} bytes is too long`);
+  }
+  const header = new DataView(new ArrayBuffer(4));
+  header.setUint16(0, payload.length + 2, littleEndian);
+  header.setUint16(2, messageType, littleEndian);
+  const out = [START_BYTE];
+  escape(new Uint8Array(header.buffer), out);
+  escape(payload, out);
+  return Uint8Array.from(out);
+}
+
+/**
+ * Decodes frames from bytes pushed in as they are received. Bytes before a start byte are
+ * skipped, and a start byte in the middle of a frame discards the partial frame.
+ */
+export class Decoder {
+  private inFrame = false;
+  private escaped = false;
+  private header: number[] = [];
+  private payload: number[] = [];
+  private payloadLength = 0;
+
+  constructor(private readonly littleEndian = true) {}
+
+  /** Pushes a single received byte, returning the frame it completes, if any */
+  push(byte: number): Frame | undefined {
+    if (byte === START_BYTE) {
+      this.inFrame = true;
+      this.escaped = false;
+      this.header = [];
+      this.payload = [];
+      return undefined;
+    }
+    if (!this.inFrame) {
+      return undefined;
+    }
+    if (this.escaped) {
+      byte ^= XOR_BYTE;
+      this.escaped = false;
+    } else if (byte === ESCAPE_BYTE) {
+      this.escaped = true;
+      return undefined;
+    }
+
+    if (this.header.length < 4) {
+      this.header.push(byte);
+      if (this.header.length < 4) {
+        return undefined;
+      }
+      const length = new DataView(Uint8Array.from(this.header).buffer).getUint16(
+        0,
+        this.littleEndian,
+      );
+      if (length < 2) {
+        this.inFrame = false;
+        return undefined;
+      }
+      this.payloadLength = length - 2;
+    } else {
+      this.payload.push(byte);
+    }
+
+    if (this.payload.length < this.payloadLength) {
+      return undefined;
+    }
+    this.inFrame = false;
+    return {
+      messageType: new DataView(Uint8Array.from(this.header).buffer).getUint16(
+        2,
+        this.littleEndian,
+      ),
+      payload: Uint8Array.from(this.payload),
+    };
+  }
+
+  /** Pushes every byte of a chunk, such as a WebSocket message, returning the frames completed */
+  pushAll(bytes: Uint8Array): Frame[] {
+    const frames: Frame[] = [];
+    for (const byte of bytes) {
+      const frame = this.push(byte);
+      if (frame !== undefined) {
+        frames.push(frame);
+      }
+    }
+    return frames;
+  }
+}
+
+class Writer {
+  private readonly out: number[] = [];
+  private readonly view = new DataView(new ArrayBuffer(8));
+
+  constructor(private readonly littleEndian: boolean) {}
+
+  private fixed(size: number): void {
+    for (let i = 0; i < size; i++) {
+      this.out.push(this.view.getUint8(i));
+    }
+  }
+
+  u8(value: number): void {
+    this.view.setUint8(0, value);
+    this.fixed(1);
+  }
+
+  u16(value: number): void {
+    this.view.setUint16(0, value, this.littleEndian);
+    this.fixed(2);
+  }
+
+  u32(value: number): void {
+    this.view.setUint32(0, value, this.littleEndian);
+    this.fixed(4);
+  }
+
+  u64(value: bigint): void {
+    this.view.setBigUint64(0, value, this.littleEndian);
+    this.fixed(8);
+  }
+
+  i8(value: number): void {
+    this.view.setInt8(0, value);
+    this.fixed(1);
+  }
+
+  i16(value: number): void {
+    this.view.setInt16(0, value, this.littleEndian);
+    this.fixed(2);
+  }
+
+  i32(value: number): void {
+    this.view.setInt32(0, value, this.littleEndian);
+    this.fixed(4);
+  }
+
+  i64(value: bigint): void {
+    this.view.setBigInt64(0, value, this.littleEndian);
+    this.fixed(8);
+  }
+
+  f32(value: number): void {
+    this.view.setFloat32(0, value, this.littleEndian);
+    this.fixed(4);
+  }
+
+  f64(value: number): void {
+    this.view.setFloat64(0, value, this.littleEndian);
+    this.fixed(8);
+  }
+
+  bool(value: boolean): void {
+    this.u8(value ? 1 : 0);
+  }
+
+  bytes(value: Uint8Array): void {
+    for (const byte of value) {
+      this.out.push(byte);
+    }
+  }
+
+  string(value: string): void {
+    this.bytes(new TextEncoder().encode(value));
+  }
+
+  varint(value: bigint): void {
+    while (value >= 0x80n) {
+      this.out.push(Number(value & 0x7fn) | 0x80);
+      value >>= 7n;
+    }
+    this.out.push(Number(value));
+  }
+
+  /** Writes a signed integer as a zigzag-encoded varint */
+  svarint(value: bigint): void {
+    this.varint(value < 0n ? (-value << 1n) - 1n : value << 1n);
+  }
+
+  array<T>(items: T[], write: (writer: Writer, item: T) => void): void {
+    this.u16(items.length);
+    for (const item of items) {
+      write(this, item);
+    }
+  }
+
+  /** Writes the length of what `write` writes, followed by it */
+  nested<T>(value: T, write: (writer: Writer, value: T) => void): void {
+    const writer = new Writer(this.littleEndian);
+    write(writer, value);
+    this.u16(writer.out.length);
+    this.bytes(writer.finish());
+  }
+
+  optional<T>(value: T | undefined, write: (writer: Writer, value: T) => void): void {
+    if (value === undefined) {
+      this.u8(0);
+    } else {
+      this.u8(1);
+      write(this, value);
+    }
+  }
+
+  /** Writes a TLV entry, unless the value of an optional field is absent */
+  entry<T>(tag: number, value: T | undefined, write: (writer: Writer, value: T) => void): void {
+    if (value !== undefined) {
+      this.u8(tag);
+      this.nested(value, write);
+    }
+  }
+
+  finish(): Uint8Array {
+    return Uint8Array.from(this.out);
+  }
+}
+
+class Reader {
+  private offset = 0;
+  private readonly view: DataView;
+
+  constructor(
+    private readonly payload: Uint8Array,
+    private readonly littleEndian: boolean,
+  ) {
+    this.view = new DataView(payload.buffer, payload.byteOffset, payload.byteLength);
+  }
+
+  /** Returns the offset of the next `size` bytes, advancing past them */
+  private take(size: number): number {
+    const remaining = this.payload.length - this.offset;
+    if (remaining < size) {
+      throw new DecodeError(`payload truncated: expected ${size} more bytes, found ${remaining}`);
+    }
+    this.offset += size;
+    return this.offset - size;
+  }
+
+  done(): boolean {
+    return this.offset === this.payload.length;
+  }
+
+  u8(): number {
+    return this.view.getUint8(this.take(1));
+  }
+
+  u16(): number {
+    return this.view.getUint16(this.take(2), this.littleEndian);
+  }
+
+  u32(): number {
+    return this.view.getUint32(this.take(4), this.littleEndian);
+  }
+
+  u64(): bigint {
+    return this.view.getBigUint64(this.take(8), this.littleEndian);
+  }
+
+  i8(): number {
+    return this.view.getInt8(this.take(1));
+  }
+
+  i16(): number {
+    return this.view.getInt16(this.take(2), this.littleEndian);
+  }
+
+  i32(): number {
+    return this.view.getInt32(this.take(4), this.littleEndian);
+  }
+
+  i64(): bigint {
+    return this.view.getBigInt64(this.take(8), this.littleEndian);
+  }
+
+  f32(): number {
+    return this.view.getFloat32(this.take(4), this.littleEndian);
+  }
+
+  f64(): number {
+    return this.view.getFloat64(this.take(8), this.littleEndian);
+  }
+
+  bool(): boolean {
+    const value = this.u8();
+    if (value > 1) {
+      throw new DecodeError(`invalid bool value: ${value}`);
+    }
+    return value === 1;
+  }
+
+  /** Reads a byte holding one of the values of the TypeScript `enum` `values` */
+  enumValue(values: object): number {
+    const value = this.u8();
+    if (!(value in values)) {
+      throw new DecodeError(`invalid enum value: ${value}`);
+    }
+    return value;
+  }
+
+  /** Reads the rest of the payload */
+  rest(): Uint8Array {
+    const start = this.take(this.payload.length - this.offset);
+    return this.payload.slice(start);
+  }
+
+  string(): string {
+    try {
+      return new TextDecoder("utf-8", { fatal: true }).decode(this.rest());
+    } catch {
+      throw new DecodeError("invalid UTF-8");
+    }
+  }
+
+  /** Reads a varint holding an unsigned integer of `bits` bits */
+  varint(bits: number): bigint {
+    let value = 0n;
+    for (let shift = 0n; ; shift += 7n) {
+      const byte = this.u8();
+      value |= BigInt(byte & 0x7f) << shift;
+      if (value >> BigInt(bits) !== 0n) {
+        throw new DecodeError("invalid varint");
+      }
+      if ((byte & 0x80) === 0) {
+        return value;
+      }
+    }
+  }
+
+  /** Reads a zigzag-encoded varint holding a signed integer of `bits` bits */
+  svarint(bits: number): bigint {
+    const value = this.varint(bits);
+    return value & 1n ? -(value >> 1n) - 1n : value >> 1n;
+  }
+
+  array<T>(read: (reader: Reader) => T): T[] {
+    const items: T[] = [];
+    for (let length = this.u16(); length > 0; length--) {
+      items.push(read(this));
+    }
+    return items;
+  }
+
+  /** Reads a length followed by a value that `read` must consume entirely */
+  nested<T>(read: (reader: Reader) => T): T {
+    const length = this.u16();
+    const start = this.take(length);
+    const reader = new Reader(this.payload.subarray(start, start + length), this.littleEndian);
+    const value = read(reader);
+    reader.finish();
+    return value;
+  }
+
+  /** Reads an optional field, which is absent altogether if the payload ends before it */
+  optional<T>(read: (reader: Reader) => T): T | undefined {
+    if (this.done()) {
+      return undefined;
+    }
+    const flag = this.u8();
+    if (flag > 1) {
+      throw new DecodeError(`invalid presence flag: ${flag}`);
+    }
+    return flag === 1 ? read(this) : undefined;
+  }
+
+  /** Reads the rest of the payload as TLV entries, by tag */
+  entries(): Map<number, Reader> {
+    const entries = new Map<number, Reader>();
+    while (!this.done()) {
+      const tag = this.u8();
+      const length = this.u16();
+      const start = this.take(length);
+      entries.set(tag, new Reader(this.payload.subarray(start, start + length), this.littleEndian));
+    }
+    return entries;
+  }
+
+  /** Fails if any of the payload is left over */
+  finish(): void {
+    if (!this.done()) {
+      throw new DecodeError(`${this.payload.length - this.offset} bytes left over`);
+    }
+  }
+}
+
+function optionalEntry<T>(
+  entries: Map<number, Reader>,
+  tag: number,
+  read: (reader: Reader) => T,
+): T | undefined {
+  const reader = entries.get(tag);
+  if (reader === undefined) {
+    return undefined;
+  }
+  const value = read(reader);
+  reader.finish();
+  return value;
+}
+
+function requiredEntry<T>(
+  entries: Map<number, Reader>,
+  tag: number,
+  read: (reader: Reader) => T,
+): T {
+  const value = optionalEntry(entries, tag, read);
+  if (value === undefined) {
+    throw new DecodeError(`missing field with tag ${tag}`);
+  }
+  return value;
+}
+"#;
+
+/// Generates the TypeScript module
+#[must_use]
+pub fn source() -> String {
+    let mut out = String::new();
+    out.push_str(GENERATED_NOTICE);
+    out.push('\n');
+    writeln!(out, "export const START_BYTE = 0x{START_BYTE:02x};").unwrap();
+    writeln!(out, "export const ESCAPE_BYTE = 0x{ESCAPE_BYTE:02x};").unwrap();
+    writeln!(out, "export const XOR_BYTE = 0x{XOR_BYTE:02x};").unwrap();
+    out.push_str(
+        "/** The largest payload a frame can carry, as the length field includes the message type */\n",
+    );
+    writeln!(
+        out,
+        "export const MAX_PAYLOAD_LENGTH = {MAX_PAYLOAD_LENGTH};"
+    )
+    .unwrap();
+    out.push_str(RUNTIME_SOURCE);
+
+    out.push_str("\nexport enum MessageType {\n");
+    for message in schema::messages() {
+        writeln!(out, "  {} = {},", message.name, message.id).unwrap();
+    }
+    out.push_str("}\n");
+
+    for descriptor in enums() {
+        out.push('\n');
+        declare_enum(&mut out, descriptor);
+    }
+
+    for descriptor in structs() {
+        out.push('\n');
+        declare_struct(&mut out, &descriptor);
+        out.push('\n');
+        define_struct_functions(&mut out, &descriptor);
+    }
+
+    for message in schema::messages() {
+        out.push('\n');
+        define_message_functions(&mut out, message);
+    }
+
+    out.push('\n');
+    define_message_union(&mut out);
+    out
+}
+
+/// Writes the module into `directory` as [`FILE_NAME`]
+pub fn write_file(directory: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(directory.as_ref().join(FILE_NAME), source())
+}
+
+/// The TypeScript type of a field, which for an optional field is the type of its value
+fn ts_type(field_type: FieldType) -> String {
+    match field_type {
+        FieldType::U8
+        | FieldType::U16
+        | FieldType::U32
+        | FieldType::I8
+        | FieldType::I16
+        | FieldType::I32
+        | FieldType::F32
+        | FieldType::F64 => "number".to_string(),
+        FieldType::U64 | FieldType::I64 => "bigint".to_string(),
+        FieldType::Bool => "boolean".to_string(),
+        FieldType::Bytes => "Uint8Array".to_string(),
+        FieldType::String => "string".to_string(),
+        FieldType::Enum(descriptor) => descriptor.name.to_string(),
+        FieldType::Array(element) => format!("{}[]", ts_type(*element)),
+        FieldType::Struct(nested) => nested.name.to_string(),
+        FieldType::Option(inner) | FieldType::Varint(inner) => ts_type(*inner),
+    }
+}
+
+/// The name of the `Writer` or `Reader` method for a fixed-size number
+fn number_method(field_type: FieldType) -> &'static str {
+    match field_type {
+        FieldType::U8 => "u8",
+        FieldType::U16 => "u16",
+        FieldType::U32 => "u32",
+        FieldType::U64 => "u64",
+        FieldType::I8 => "i8",
+        FieldType::I16 => "i16",
+        FieldType::I32 => "i32",
+        FieldType::I64 => "i64",
+        FieldType::F32 => "f32",
+        FieldType::F64 => "f64",
+        _ => unreachable!("not a number"),
+    }
+}
+
+fn is_signed(integer: FieldType) -> bool {
+    matches!(
+        integer,
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64
+    )
+}
+
+/// An expression writing `value` with the writer `w`
+fn write_value(field_type: FieldType, value: &str) -> String {
+    match field_type {
+        FieldType::Bool => format!("w.bool({value})"),
+        FieldType::Bytes => format!("w.bytes({value})"),
+        FieldType::String => format!("w.string({value})"),
+        FieldType::Enum(_) => format!("w.u8({value})"),
+        FieldType::Array(element) => format!(
+            "w.array({value}, (w, item) => {})",
+            write_value(*element, "item")
+        ),
+        FieldType::Struct(nested) => format!("w.nested({value}, write{})", nested.name),
+        FieldType::Option(inner) => format!(
+            "w.optional({value}, (w, value) => {})",
+            write_value(*inner, "value")
+        ),
+        FieldType::Varint(integer) => {
+            let method = if is_signed(*integer) {
+                "svarint"
+            } else {
+                "varint"
+            };
+            if integer.size() == Some(8) {
+                format!("w.{method}({value})")
+            } else {
+                format!("w.{method}(BigInt({value}))")
+            }
+        }
+        number => format!("w.{}({value})", number_method(number)),
+    }
+}
+
+/// An expression reading a value with the reader `r`
+fn read_value(field_type: FieldType) -> String {
+    match field_type {
+        FieldType::Bool => "r.bool()".to_string(),
+        FieldType::Bytes => "r.rest()".to_string(),
+        FieldType::String => "r.string()".to_string(),
+        FieldType::Enum(descriptor) => {
+            format!("r.enumValue({0}) as {0}", descriptor.name)
+        }
+        FieldType::Array(element) => format!("r.array((r) => {})", read_value(*element)),
+        FieldType::Struct(nested) => format!("r.nested(read{})", nested.name),
+        FieldType::Option(inner) => format!("r.optional((r) => {})", read_value(*inner)),
+        FieldType::Varint(integer) => {
+            let method = if is_signed(*integer) {
+                "svarint"
+            } else {
+                "varint"
+            };
+            let bits = integer.size().unwrap() * 8;
+            if bits == 64 {
+                format!("r.{method}(64)")
+            } else {
+                format!("Number(r.{method}({bits}))")
+            }
+        }
+        number => format!("r.{}()", number_method(number)),
+    }
+}
+
+fn declare_enum(out: &mut String, descriptor: &EnumDescriptor) {
+    writeln!(out, "export enum {} {{", descriptor.name).unwrap();
+    for (variant, value) in descriptor.variants {
+        writeln!(out, "  {variant} = {value},").unwrap();
+    }
+    out.push_str("}\n");
+}
+
+fn declare_struct(out: &mut String, descriptor: &StructDescriptor) {
+    writeln!(out, "export interface {} {{", descriptor.name).unwrap();
+    for field in descriptor.fields {
+        let optional = if matches!(field.field_type, FieldType::Option(_)) {
+            "?"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "  {}{optional}: {};",
+            camel_case(field.name),
+            ts_type(field.field_type)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+}
+
+/// Defines the functions writing a struct's fields and reading them
+fn define_struct_functions(out: &mut String, descriptor: &StructDescriptor) {
+    let name = descriptor.name;
+    writeln!(out, "function write{name}(w: Writer, m: {name}): void {{").unwrap();
+    for field in descriptor.fields {
+        let value = format!("m.{}", camel_case(field.name));
+        match (descriptor.encoding, field.tag) {
+            (StructEncoding::Tlv, Some(tag)) => writeln!(
+                out,
+                "  w.entry({tag}, {value}, (w, value) => {});",
+                write_value(tlv_value(field), "value")
+            )
+            .unwrap(),
+            _ => writeln!(out, "  {};", write_value(field.field_type, &value)).unwrap(),
+        }
+    }
+    out.push_str("}\n\n");
+
+    writeln!(out, "function read{name}(r: Reader): {name} {{").unwrap();
+    if descriptor.encoding == StructEncoding::Tlv {
+        out.push_str("  const entries = r.entries();\n");
+    }
+    out.push_str("  return {\n");
+    for field in descriptor.fields {
+        let read = match (descriptor.encoding, field.tag) {
+            (StructEncoding::Tlv, Some(tag)) => {
+                let function = if matches!(field.field_type, FieldType::Option(_)) {
+                    "optionalEntry"
+                } else {
+                    "requiredEntry"
+                };
+                format!(
+                    "{function}(entries, {tag}, (r) => {})",
+                    read_value(tlv_value(field))
+                )
+            }
+            _ => read_value(field.field_type),
+        };
+        writeln!(out, "    {}: {read},", camel_case(field.name)).unwrap();
+    }
+    out.push_str("  };\n}\n");
+}
+
+/// The type of the value of a TLV entry, which for an optional field is only present alongside
+/// the value
+fn tlv_value(field: &FieldDescriptor) -> FieldType {
+    match field.field_type {
+        FieldType::Option(inner) => *inner,
+        field_type => field_type,
+    }
+}
+
+fn has_fields(message: &MessageDescriptor) -> bool {
+    !matches!(
+        message.payload,
+        PayloadDescriptor::Struct(StructDescriptor { fields: [], .. })
+    )
+}
+
+/// The type of a message's payload
+fn payload_type(message: &MessageDescriptor) -> String {
+    match message.payload {
+        PayloadDescriptor::Struct(_) if !has_fields(message) => "Record<string, never>".to_string(),
+        PayloadDescriptor::Struct(descriptor) => descriptor.name.to_string(),
+        PayloadDescriptor::Enum(descriptor) => descriptor.name.to_string(),
+    }
+}
+
+fn define_message_functions(out: &mut String, message: &MessageDescriptor) {
+    let name = message.name;
+    let payload_type = payload_type(message);
+    let write = match message.payload {
+        PayloadDescriptor::Struct(_) if !has_fields(message) => None,
+        PayloadDescriptor::Struct(descriptor) => {
+            Some(format!("write{}(w, message)", descriptor.name))
+        }
+        PayloadDescriptor::Enum(_) => Some("w.u8(message)".to_string()),
+    };
+    let read = match message.payload {
+        PayloadDescriptor::Struct(_) if !has_fields(message) => "{}".to_string(),
+        PayloadDescriptor::Struct(descriptor) => format!("read{}(r)", descriptor.name),
+        PayloadDescriptor::Enum(descriptor) => read_value(FieldType::Enum(descriptor)),
+    };
+
+    writeln!(out, "/** Encodes a {name} message into a frame */").unwrap();
+    match write {
+        Some(write) => writeln!(
+            out,
+            "export function encode{name}(message: {payload_type}, littleEndian = true): Uint8Array {{\n  const w = new Writer(littleEndian);\n  {write};\n  return encodeFrame(MessageType.{name}, w.finish(), littleEndian);\n}}"
+        )
+        .unwrap(),
+        None => writeln!(
+            out,
+            "export function encode{name}(littleEndian = true): Uint8Array {{\n  return encodeFrame(MessageType.{name}, new Uint8Array(0), littleEndian);\n}}"
+        )
+        .unwrap(),
+    }
+    writeln!(
+        out,
+        "\n/** Decodes the payload of a {name} message, throwing a DecodeError if it is invalid */\nexport function decode{name}(payload: Uint8Array, littleEndian = true): {payload_type} {{\n  const r = new Reader(payload, littleEndian);\n  const message: {payload_type} = {read};\n  r.finish();\n  return message;\n}}"
+    )
+    .unwrap();
+}
+
+/// Defines the type of every received message and `decodeMessage`
+fn define_message_union(out: &mut String) {
+    out.push_str("export type Message =\n");
+    for message in schema::messages() {
+        writeln!(
+            out,
+            "  | {{ type: \"{}\"; message: {} }}",
+            message.name,
+            payload_type(message)
+        )
+        .unwrap();
+    }
+    out.push_str("  | { type: \"Unknown\"; messageType: number; payload: Uint8Array };\n\n");
+
+    out.push_str(
+        "/**
+ * Decodes a received frame, throwing a DecodeError if its payload is invalid. Frames of message
+ * types that are not built in are returned as `Unknown`.
+ */
+export function decodeMessage(frame: Frame, littleEndian = true): Message {
+  switch (frame.messageType) {
+",
+    );
+    for message in schema::messages() {
+        writeln!(
+            out,
+            "    case MessageType.{0}:\n      return {{ type: \"{0}\", message: decode{0}(frame.payload, littleEndian) }};",
+            message.name
+        )
+        .unwrap();
+    }
+    out.push_str(
+        "    default:
+      return { type: \"Unknown\", messageType: frame.messageType, payload: frame.payload };
+  }
+}
+",
+    );
+}
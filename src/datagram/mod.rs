@@ -0,0 +1,249 @@
+use crate::codec::{Endianness, Frame};
+use crate::errors::{DecodeError, ReceiveError};
+use crate::events::SerialManagerEvents;
+use crate::fmt;
+use crate::message::Message;
+use crate::schema;
+use crate::stats::Stats;
+use std::io;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+/// The largest datagram that can be received, which is the largest UDP payload
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+/// A packet-oriented transport that preserves message boundaries, such as a connected UDP socket
+pub trait Datagram {
+    /// Sends `datagram` to the peer as a single packet
+    fn send_datagram(&mut self, datagram: &[u8]) -> io::Result<()>;
+
+    /// Receives a single packet from the peer into `buffer`, returning its length
+    fn receive_datagram(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+}
+
+impl Datagram for UdpSocket {
+    fn send_datagram(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.send(datagram).map(|_| ())
+    }
+
+    fn receive_datagram(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.recv(buffer)
+    }
+}
+
+#[cfg(unix)]
+impl Datagram for UnixDatagram {
+    fn send_datagram(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.send(datagram).map(|_| ())
+    }
+
+    fn receive_datagram(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.recv(buffer)
+    }
+}
+
+/// Sends and receives messages over a packet-oriented transport, one message per datagram.
+///
+/// Datagram Format:
+/// +--------------------+------------------+
+/// | Msg Type (2 bytes) |      Data        |
+/// |      LE u16        | Variable length  |
+/// +--------------------+------------------+
+///
+/// As the transport keeps datagrams apart, there is no start byte, length field or escaping.
+/// Payloads are encoded exactly as by [`SerialManager`](crate::SerialManager), including the
+/// byte order selected with [`with_endianness`](Self::with_endianness).
+///
+/// UDP sockets must be [connected](UdpSocket::connect) to the peer first.
+pub struct DatagramManager<T>
+where
+    T: Datagram,
+{
+    connection: T,
+    stats: Stats,
+    endianness: Endianness,
+    deliver_unknown: bool,
+    buffer: Vec<u8>,
+    events: Option<Box<dyn SerialManagerEvents + Send>>,
+}
+
+impl<T> DatagramManager<T>
+where
+    T: Datagram,
+{
+    pub fn new(connection: T) -> Self {
+        Self {
+            connection,
+            stats: Stats::default(),
+            endianness: Endianness::Little,
+            deliver_unknown: false,
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+            events: None,
+        }
+    }
+
+    /// Sets the byte order of the message type and numbers in payloads
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Delivers datagrams with unregistered message types as [`Message::Unknown`] instead of
+    /// failing with [`DecodeError::InvalidMessageType`]
+    #[must_use]
+    pub fn with_unknown_messages(mut self, deliver_unknown: bool) -> Self {
+        self.deliver_unknown = deliver_unknown;
+        self
+    }
+
+    /// Returns a snapshot of the traffic counters
+    ///
+    /// Only the frame and byte counters and `decode_errors` apply to datagrams.
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Resets all traffic counters to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    /// Registers a handler for traffic events, such as datagrams sent and received and errors
+    ///
+    /// Any previously registered handler is replaced.
+    pub fn set_events(&mut self, events: impl SerialManagerEvents + Send + 'static) {
+        self.events = Some(Box::new(events));
+    }
+
+    /// Removes the registered event handler, if any
+    pub fn clear_events(&mut self) {
+        self.events = None;
+    }
+
+    /// Sends a message as a single datagram
+    pub fn send(&mut self, message: Message) -> io::Result<()> {
+        self.send_raw(
+            message.message_type(),
+            &message.to_bytes_with(self.endianness)?,
+        )
+    }
+
+    /// Sends a message type and payload as a single datagram, without going through [`Message`]
+    pub fn send_raw(&mut self, message_type: u16, payload: &[u8]) -> io::Result<()> {
+        let mut datagram = Vec::with_capacity(2 + payload.len());
+        datagram.extend_from_slice(&self.endianness.u16_to_bytes(message_type));
+        datagram.extend_from_slice(payload);
+        if let Err(e) = self.connection.send_datagram(&datagram) {
+            if let Some(events) = &mut self.events {
+                events.on_send_error(&e);
+            }
+            return Err(e);
+        }
+        self.stats.frames_sent += 1;
+        self.stats.bytes_sent += datagram.len() as u64;
+        if let Some(events) = &mut self.events {
+            events.on_send(message_type, payload);
+        }
+        Ok(())
+    }
+
+    /// Receives a single datagram and decodes it into a message
+    ///
+    /// This function will block until a datagram is received. A datagram that cannot be decoded
+    /// fails with a [`ReceiveError::Decode`], and the next call receives the next datagram.
+    pub fn receive(&mut self) -> Result<Message, ReceiveError> {
+        let frame = self.read_frame()?;
+        let length = 2 + frame.payload.len();
+        let registered = schema::message(frame.message_type).is_some();
+        let result = if self.deliver_unknown && !registered {
+            Ok(Message::Unknown {
+                message_type: frame.message_type,
+                data: frame.payload,
+            })
+        } else {
+            Message::from_bytes_with(frame.message_type, frame.payload, self.endianness)
+        };
+        match result {
+            Ok(message) => {
+                self.stats.frames_received += 1;
+                Ok(message)
+            }
+            Err(e) => {
+                self.stats.decode_errors += 1;
+                let error = self.decode_error(e, length);
+                self.notify_error(&error);
+                Err(error)
+            }
+        }
+    }
+
+    /// Receives a single datagram without decoding its payload into a [`Message`]
+    pub fn receive_raw(&mut self) -> Result<Frame, ReceiveError> {
+        let frame = self.read_frame()?;
+        self.stats.frames_received += 1;
+        Ok(frame)
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, ReceiveError> {
+        let result = self.next_frame();
+        match &result {
+            Ok(frame) => {
+                if let Some(events) = &mut self.events {
+                    events.on_receive(frame.message_type, &frame.payload);
+                }
+            }
+            Err(e) => self.notify_error(e),
+        }
+        result
+    }
+
+    /// Passes a receive error on to the event handler, unless it is only a read timing out
+    fn notify_error(&mut self, error: &ReceiveError) {
+        if let Some(events) = &mut self.events {
+            if !error.is_timeout() {
+                events.on_error(error);
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Frame, ReceiveError> {
+        let length = self.connection.receive_datagram(&mut self.buffer)?;
+        self.stats.bytes_received += length as u64;
+        let datagram = &self.buffer[..length];
+        let Some((message_type, payload)) = datagram.split_first_chunk() else {
+            self.stats.decode_errors += 1;
+            // Reported like a length field too short to hold the message type
+            #[allow(clippy::cast_possible_truncation)]
+            return Err(self.decode_error(DecodeError::InvalidLength(length as u16), length));
+        };
+        Ok(Frame {
+            message_type: self.endianness.u16_from_bytes(*message_type),
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Attaches the last datagram received, `length` bytes long, to a decode error
+    fn decode_error(&self, source: DecodeError, length: usize) -> ReceiveError {
+        let datagram = &self.buffer[..length];
+        let offset = match (&source, datagram.split_first_chunk()) {
+            (DecodeError::InvalidMessageType(_), _) => Some(0),
+            // Datagrams have no length field to point to
+            (DecodeError::InvalidLength(_), _) | (_, None) => None,
+            (_, Some((message_type, payload))) => {
+                let message_type = self.endianness.u16_from_bytes(*message_type);
+                fmt::error_offset(message_type, payload, self.endianness).map(|offset| 2 + offset)
+            }
+        };
+        ReceiveError::Decode {
+            source,
+            frame: datagram.to_vec(),
+            offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
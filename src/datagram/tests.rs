@@ -0,0 +1,151 @@
+use super::*;
+use crate::message_types;
+
+fn socket_pair() -> (UdpSocket, UdpSocket) {
+    let socket1 = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket2 = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket1.connect(socket2.local_addr().unwrap()).unwrap();
+    socket2.connect(socket1.local_addr().unwrap()).unwrap();
+    (socket1, socket2)
+}
+
+#[test]
+fn test_send_and_receive() {
+    let (socket1, socket2) = socket_pair();
+    let mut sender = DatagramManager::new(socket1);
+    let mut receiver = DatagramManager::new(socket2);
+
+    // Start and escape bytes are sent as they are
+    let message = Message::Multi(message_types::Multi {
+        num: 0x58,
+        string: "\x42".to_string(),
+    });
+    sender.send(message.clone()).unwrap();
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+
+    assert_eq!(receiver.receive().unwrap(), message);
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::NoOp(message_types::NoOp {})
+    );
+    assert_eq!(sender.stats().frames_sent, 2);
+    assert_eq!(receiver.stats().bytes_received, sender.stats().bytes_sent);
+}
+
+#[test]
+fn test_datagram_format() {
+    let (socket1, socket2) = socket_pair();
+    let mut sender = DatagramManager::new(socket1).with_endianness(Endianness::Big);
+    sender
+        .send(Message::U16(message_types::U16 { num: 0x1234 }))
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    let length = socket2.recv(&mut buffer).unwrap();
+    assert_eq!(buffer[..length], [0x00, 0x05, 0x12, 0x34]);
+}
+
+#[test]
+fn test_receive_raw() {
+    let (socket1, socket2) = socket_pair();
+    let mut receiver = DatagramManager::new(socket2);
+    socket1.send(&[0x34, 0x12, 0xAB]).unwrap();
+
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x1234);
+    assert_eq!(frame.payload, vec![0xAB]);
+}
+
+#[test]
+fn test_bad_datagram_does_not_affect_the_next() {
+    let (socket1, socket2) = socket_pair();
+    let mut receiver = DatagramManager::new(socket2);
+    socket1.send(&[0x01]).unwrap();
+    socket1.send(&[0x01, 0x00]).unwrap();
+    socket1.send(&[0x01, 0x00, 0x57]).unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidLength(1),
+            ..
+        })
+    ));
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidLength(2),
+            ..
+        })
+    ));
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eq!(receiver.stats().decode_errors, 2);
+}
+
+#[test]
+fn test_unknown_messages() {
+    let (socket1, socket2) = socket_pair();
+    let mut receiver = DatagramManager::new(socket2).with_unknown_messages(true);
+    socket1.send(&[0x34, 0x12, 0xAB]).unwrap();
+
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::Unknown {
+            message_type: 0x1234,
+            data: vec![0xAB]
+        }
+    );
+}
+
+#[test]
+fn test_decode_error_context() {
+    let (socket1, socket2) = socket_pair();
+    let mut receiver = DatagramManager::new(socket2);
+    socket1.send(&[0x1B, 0x00, 0x34, 0x12, 0x07]).unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert_eq!(
+        error.frame(),
+        Some([0x1B, 0x00, 0x34, 0x12, 0x07].as_slice())
+    );
+    assert_eq!(error.offset(), Some(4));
+}
+
+#[derive(Clone, Default)]
+struct CountingEvents {
+    counts: std::sync::Arc<std::sync::Mutex<(usize, usize, usize)>>,
+}
+
+impl SerialManagerEvents for CountingEvents {
+    fn on_send(&mut self, _message_type: u16, _payload: &[u8]) {
+        self.counts.lock().unwrap().0 += 1;
+    }
+
+    fn on_receive(&mut self, _message_type: u16, _payload: &[u8]) {
+        self.counts.lock().unwrap().1 += 1;
+    }
+
+    fn on_error(&mut self, _error: &ReceiveError) {
+        self.counts.lock().unwrap().2 += 1;
+    }
+}
+
+#[test]
+fn test_events() {
+    let (socket1, socket2) = socket_pair();
+    let mut sender = DatagramManager::new(socket1);
+    let mut receiver = DatagramManager::new(socket2);
+    let events = CountingEvents::default();
+    sender.set_events(events.clone());
+    receiver.set_events(events.clone());
+
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    sender.send_raw(1, &[]).unwrap();
+    receiver.receive().unwrap();
+    assert!(receiver.receive().is_err());
+    // Sent, received (including the one that failed to decode) and errors
+    assert_eq!(*events.counts.lock().unwrap(), (2, 2, 1));
+}
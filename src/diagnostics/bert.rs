@@ -0,0 +1,402 @@
+//! Bit error rate testing, streaming pseudo-random frames to qualify cabling and baud rates.
+
+use crate::codec::Frame;
+use crate::errors::ReceiveError;
+use crate::serial_manager::{SerialManager, Transport};
+use crate::stats::Stats;
+use crate::time::Instant;
+use std::io;
+use std::time::Duration;
+
+/// The message type of bit error rate test frames
+pub const BERT_MESSAGE_TYPE: u16 = 0x0101;
+
+/// How far ahead of the expected sequence number a frame may be for the frames in between to
+/// count as lost, rather than the sequence number counting as corrupted
+const LOSS_WINDOW: u32 = 1024;
+
+/// Settings for a bit error rate test, run with [`SerialManager::run_bert`]
+#[derive(Debug, Clone)]
+pub struct Bert {
+    duration: Duration,
+    interval: Duration,
+    payload_length: usize,
+    rate: Option<u32>,
+    seed: u32,
+    echo: bool,
+    timeout: Duration,
+}
+
+impl Default for Bert {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bert {
+    /// Creates a test that sends 64 byte payloads as fast as possible for 10 seconds, expecting
+    /// them to be echoed back, and reports every second
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            duration: Duration::from_secs(10),
+            interval: Duration::from_secs(1),
+            payload_length: 64,
+            rate: None,
+            seed: 0x4753_5042,
+            echo: true,
+            timeout: Duration::from_millis(200),
+        }
+    }
+
+    /// Sets how long frames are sent for
+    #[must_use]
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets how often a [`BertSample`] is taken
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the length of each payload, including the 4 byte sequence number it starts with
+    ///
+    /// Lengths below 4 are raised to 4.
+    #[must_use]
+    pub fn with_payload_length(mut self, payload_length: usize) -> Self {
+        self.payload_length = payload_length.max(4);
+        self
+    }
+
+    /// Limits sending to `bytes_per_second` bytes of payload a second, rather than as fast as
+    /// the connection accepts them
+    #[must_use]
+    pub fn with_rate(mut self, bytes_per_second: u32) -> Self {
+        self.rate = Some(bytes_per_second.max(1));
+        self
+    }
+
+    /// Sets the seed of the pseudo-random payloads, which a peer checking them must share
+    #[must_use]
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets whether the peer echoes frames back to be checked, rather than checking them itself
+    /// with a [`BertChecker`]
+    #[must_use]
+    pub fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Sets how long to wait for echoes after the last frame is sent, before counting the rest
+    /// as lost
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the frame with sequence number `sequence`
+    ///
+    /// The payload is the sequence number in little endian followed by pseudo-random bytes
+    /// generated from it and the seed, so each frame can be checked on its own.
+    #[must_use]
+    pub fn frame(&self, sequence: u32) -> Frame {
+        let mut payload = Vec::with_capacity(self.payload_length);
+        payload.extend_from_slice(&sequence.to_le_bytes());
+        // xorshift32, whose state must not be zero
+        let mut state = (self.seed ^ sequence.wrapping_mul(0x9E37_79B9)) | 1;
+        while payload.len() < self.payload_length {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let remaining = self.payload_length - payload.len();
+            payload.extend_from_slice(&state.to_le_bytes()[..remaining.min(4)]);
+        }
+        Frame {
+            message_type: BERT_MESSAGE_TYPE,
+            payload,
+        }
+    }
+}
+
+/// Counts over part or all of a bit error rate test
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BertSample {
+    /// The time from the start of the test to the end of the sample
+    pub elapsed: Duration,
+    /// The time the sample covers
+    pub duration: Duration,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Frames that never arrived, detected from gaps in the sequence numbers and, when frames
+    /// are echoed, from frames still missing at the end of the test
+    pub frames_lost: u64,
+    /// Bits of received frames compared against what was sent
+    pub bits_checked: u64,
+    pub bit_errors: u64,
+    pub resyncs: u64,
+    pub decode_errors: u64,
+    pub crc_failures: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl BertSample {
+    /// Returns the fraction of checked bits that were wrong, or 0 if none were checked
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn bit_error_rate(&self) -> f64 {
+        if self.bits_checked == 0 {
+            0.0
+        } else {
+            self.bit_errors as f64 / self.bits_checked as f64
+        }
+    }
+
+    /// Returns the bytes received a second, including framing, or 0 for a sample with no
+    /// duration
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.duration.as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            self.bytes_received as f64 / seconds
+        }
+    }
+}
+
+/// The results of [`SerialManager::run_bert`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BertReport {
+    /// Samples taken every [`Bert::with_interval`], in order
+    pub samples: Vec<BertSample>,
+    /// The whole test
+    pub total: BertSample,
+}
+
+/// Checks received bit error rate test frames against those sent, on whichever end receives
+/// them
+///
+/// A peer checking frames itself, rather than echoing them, feeds each one it receives to
+/// [`check`](Self::check), with a [`Bert`] using the same payload length and seed as the sender.
+#[derive(Debug, Clone)]
+pub struct BertChecker {
+    bert: Bert,
+    next_sequence: u32,
+    frames_received: u64,
+    frames_lost: u64,
+    bits_checked: u64,
+    bit_errors: u64,
+}
+
+impl BertChecker {
+    #[must_use]
+    pub fn new(bert: Bert) -> Self {
+        Self {
+            bert,
+            next_sequence: 0,
+            frames_received: 0,
+            frames_lost: 0,
+            bits_checked: 0,
+            bit_errors: 0,
+        }
+    }
+
+    /// Checks a received frame, returning the number of bits that were wrong
+    ///
+    /// A frame up to 1024 sequence numbers ahead of the next one expected counts the frames
+    /// skipped as lost. Otherwise the sequence number itself is taken to be corrupted, and the
+    /// frame is compared against the next one expected. Missing or extra bytes count as 8 wrong
+    /// bits each.
+    pub fn check(&mut self, frame: &Frame) -> u64 {
+        let sequence = match frame.payload[..] {
+            [a, b, c, d, ..] => Some(u32::from_le_bytes([a, b, c, d])),
+            _ => None,
+        };
+        let sequence = match sequence {
+            Some(sequence) if sequence.wrapping_sub(self.next_sequence) < LOSS_WINDOW => {
+                self.frames_lost += u64::from(sequence.wrapping_sub(self.next_sequence));
+                sequence
+            }
+            _ => self.next_sequence,
+        };
+        self.next_sequence = sequence.wrapping_add(1);
+        let expected = self.bert.frame(sequence);
+
+        let type_errors = (frame.message_type ^ expected.message_type).count_ones();
+        let length = frame.payload.len().max(expected.payload.len());
+        let payload_errors: u32 = (0..length)
+            .map(|i| match (frame.payload.get(i), expected.payload.get(i)) {
+                (Some(a), Some(b)) => (a ^ b).count_ones(),
+                _ => 8,
+            })
+            .sum();
+        let errors = u64::from(type_errors + payload_errors);
+        self.frames_received += 1;
+        self.bits_checked += 16 + 8 * length as u64;
+        self.bit_errors += errors;
+        errors
+    }
+
+    /// Returns the sequence number of the next frame expected
+    #[must_use]
+    pub fn next_sequence(&self) -> u32 {
+        self.next_sequence
+    }
+
+    /// Returns the counts so far, without timing or link statistics
+    #[must_use]
+    pub fn sample(&self) -> BertSample {
+        BertSample {
+            frames_received: self.frames_received,
+            frames_lost: self.frames_lost,
+            bits_checked: self.bits_checked,
+            bit_errors: self.bit_errors,
+            ..BertSample::default()
+        }
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Streams pseudo-random frames to a peer for the configured duration, checking the echoes
+    /// that come back, and reports the bit error rate, resyncs and throughput over time
+    ///
+    /// Frames are sent at the configured rate while echoes are received in between, so reads
+    /// from the connection must time out or would block, ideally after no more than a
+    /// millisecond or so, or they hold up sending. Without echoes, frames are only sent, and
+    /// the peer checks them with a [`BertChecker`].
+    ///
+    /// Fails only if writing or reading fails, as corrupted frames are what is being measured.
+    pub fn run_bert(&mut self, bert: &Bert) -> io::Result<BertReport> {
+        let mut checker = BertChecker::new(bert.clone());
+        let mut samples = Vec::new();
+        let mut last = BertSample::default();
+        let mut sequence = 0u32;
+        let mut payload_bytes_sent = 0u64;
+        let start_stats = self.stats();
+        let start = Instant::now();
+        let mut next_sample = bert.interval;
+
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= next_sample {
+                let sample = self.bert_sample(&checker, sequence, &start_stats, start);
+                samples.push(delta(&sample, &last));
+                last = sample;
+                next_sample += bert.interval;
+            }
+
+            let sending = elapsed < bert.duration;
+            if !sending {
+                let waiting = bert.echo
+                    && checker.next_sequence() != sequence
+                    && elapsed < bert.duration + bert.timeout;
+                if !waiting {
+                    break;
+                }
+            }
+            let due = bert
+                .rate
+                .is_none_or(|rate| Duration::from_secs(payload_bytes_sent) / rate <= elapsed);
+            if sending && due {
+                let frame = bert.frame(sequence);
+                self.send_raw(frame.message_type, &frame.payload)?;
+                sequence = sequence.wrapping_add(1);
+                payload_bytes_sent += frame.payload.len() as u64;
+                if bert.echo {
+                    self.receive_bert_frames(&mut checker)?;
+                }
+            } else if bert.echo {
+                self.receive_bert_frames(&mut checker)?;
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        if bert.echo {
+            checker.frames_lost += u64::from(sequence.wrapping_sub(checker.next_sequence()));
+        }
+        let total = self.bert_sample(&checker, sequence, &start_stats, start);
+        let remainder = delta(&total, &last);
+        if remainder.duration > Duration::ZERO {
+            samples.push(remainder);
+        }
+        Ok(BertReport { samples, total })
+    }
+
+    /// Receives and checks the frames that have already arrived
+    fn receive_bert_frames(&mut self, checker: &mut BertChecker) -> io::Result<()> {
+        loop {
+            match self.receive_raw() {
+                Ok(frame) => {
+                    checker.check(&frame);
+                }
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(());
+                }
+                Err(ReceiveError::Io(e)) => return Err(e),
+                // Counted as decode errors in the stats
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Returns the counts from the start of the test
+    fn bert_sample(
+        &self,
+        checker: &BertChecker,
+        frames_sent: u32,
+        start_stats: &Stats,
+        start: Instant,
+    ) -> BertSample {
+        let stats = self.stats();
+        let elapsed = start.elapsed();
+        BertSample {
+            elapsed,
+            duration: elapsed,
+            frames_sent: u64::from(frames_sent),
+            resyncs: stats.resyncs - start_stats.resyncs,
+            decode_errors: stats.decode_errors - start_stats.decode_errors,
+            crc_failures: stats.crc_failures - start_stats.crc_failures,
+            bytes_sent: stats.bytes_sent - start_stats.bytes_sent,
+            bytes_received: stats.bytes_received - start_stats.bytes_received,
+            ..checker.sample()
+        }
+    }
+}
+
+/// Returns the counts of `current` since `last`, both counted from the start of the test
+fn delta(current: &BertSample, last: &BertSample) -> BertSample {
+    BertSample {
+        elapsed: current.elapsed,
+        duration: current.elapsed.saturating_sub(last.elapsed),
+        frames_sent: current.frames_sent - last.frames_sent,
+        frames_received: current.frames_received - last.frames_received,
+        frames_lost: current.frames_lost - last.frames_lost,
+        bits_checked: current.bits_checked - last.bits_checked,
+        bit_errors: current.bit_errors - last.bit_errors,
+        resyncs: current.resyncs - last.resyncs,
+        decode_errors: current.decode_errors - last.decode_errors,
+        crc_failures: current.crc_failures - last.crc_failures,
+        bytes_sent: current.bytes_sent - last.bytes_sent,
+        bytes_received: current.bytes_received - last.bytes_received,
+    }
+}
@@ -0,0 +1,122 @@
+//! Round trip time measurement, pinging the peer at a steady cadence.
+
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use crate::stats::LatencyStats;
+use crate::time::Instant;
+use std::io;
+use std::time::Duration;
+
+/// Settings for measuring round trip times, run with [`SerialManager::probe_latency`]
+#[derive(Debug, Clone)]
+pub struct LatencyProbe {
+    count: u32,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Default for LatencyProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyProbe {
+    /// Creates a probe that sends 100 pings 10 ms apart, each answered within a second
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            count: 100,
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the number of pings sent
+    #[must_use]
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = count;
+        self
+    }
+
+    /// Sets the time between sending pings, whether or not earlier ones have been answered
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long a ping may take to be answered before it counts as lost
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends pings at the probe's cadence and measures the round trip time of each `Pong`,
+    /// returning the round trip times of this probe alone
+    ///
+    /// The round trip times are also added to the `latency` [`stats`](Self::stats), alongside
+    /// those of [`ping`](Self::ping). Pings are sent on schedule even while earlier ones are
+    /// unanswered, so reads from the connection must time out or would block, ideally after no
+    /// more than a millisecond or so, or they delay the next ping. Any other message received in
+    /// the meantime is skipped.
+    ///
+    /// Fails only if writing or reading fails.
+    pub fn probe_latency(&mut self, probe: &LatencyProbe) -> io::Result<LatencyStats> {
+        let mut latency = LatencyStats::default();
+        let mut outstanding: Vec<(u16, Instant)> = Vec::new();
+        let mut sent = 0;
+        let start = Instant::now();
+        let mut next_send = start;
+
+        while sent < probe.count || !outstanding.is_empty() {
+            let now = Instant::now();
+            if sent < probe.count && now >= next_send {
+                let sequence = self.next_ping_sequence();
+                self.send(Message::Ping(message_types::Ping { sequence }))?;
+                outstanding.push((sequence, now));
+                sent += 1;
+                next_send += probe.interval;
+            }
+
+            match self.receive() {
+                Ok(Message::Pong(pong)) => {
+                    if let Some(i) = outstanding.iter().position(|&(s, _)| s == pong.sequence) {
+                        let (_, sent_at) = outstanding.remove(i);
+                        let round_trip = sent_at.elapsed();
+                        latency.record(round_trip);
+                        self.record_round_trip(round_trip);
+                    }
+                }
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Non-blocking connections would otherwise be polled in a busy loop
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Err(ReceiveError::Io(e)) => return Err(e),
+                // Other messages are skipped, and decode errors counted in the stats
+                Ok(_) | Err(_) => {}
+            }
+
+            let expired = outstanding.len();
+            outstanding.retain(|&(_, sent_at)| sent_at.elapsed() <= probe.timeout);
+            let lost = (expired - outstanding.len()) as u64;
+            latency.lost += lost;
+            self.latency_mut().lost += lost;
+        }
+        Ok(latency)
+    }
+}
@@ -0,0 +1,183 @@
+//! Checks of the physical link, for validating cables and level shifters.
+
+mod bert;
+mod latency;
+mod soak;
+
+pub use bert::{Bert, BertChecker, BertReport, BertSample, BERT_MESSAGE_TYPE};
+pub use latency::LatencyProbe;
+pub use soak::{Soak, SoakReport, SoakSample};
+
+use crate::codec::{Frame, ESCAPE_BYTE, START_BYTE, XOR_BYTE};
+use crate::errors::ReceiveError;
+use crate::serial_manager::{SerialManager, Transport};
+use crate::time::Instant;
+use std::io;
+use std::time::Duration;
+
+/// The message type of self-test frames without bytes of interest in the header
+const SELF_TEST_MESSAGE_TYPE: u16 = 0x0100;
+
+/// The result of a single self-test frame
+#[derive(Debug)]
+pub enum SelfTestOutcome {
+    /// The frame came back unchanged
+    Passed,
+    /// A different frame came back, such as the frame with corrupted bytes
+    Mismatch(Frame),
+    /// What came back could not be received as a frame
+    Failed(ReceiveError),
+    /// Nothing came back in time
+    TimedOut,
+}
+
+/// A self-test frame and what became of it
+#[derive(Debug)]
+pub struct SelfTestCase {
+    /// What the frame covers, such as `"escape bytes"`
+    pub name: &'static str,
+    pub sent: Frame,
+    pub outcome: SelfTestOutcome,
+}
+
+/// The results of [`SerialManager::self_test`], in the order the frames were sent
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub cases: Vec<SelfTestCase>,
+}
+
+impl SelfTestReport {
+    /// Returns whether every frame came back unchanged
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the cases whose frame did not come back unchanged
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestCase> {
+        self.cases
+            .iter()
+            .filter(|case| !matches!(case.outcome, SelfTestOutcome::Passed))
+    }
+}
+
+/// The frames sent by the self-test, each covering an edge case of framing or escaping
+#[allow(clippy::cast_possible_truncation)]
+fn self_test_frames() -> Vec<(&'static str, Frame)> {
+    let frame = |message_type, payload| Frame {
+        message_type,
+        payload,
+    };
+    vec![
+        ("empty payload", frame(SELF_TEST_MESSAGE_TYPE, Vec::new())),
+        (
+            "start bytes",
+            frame(SELF_TEST_MESSAGE_TYPE, vec![START_BYTE; 64]),
+        ),
+        (
+            "escape bytes",
+            frame(SELF_TEST_MESSAGE_TYPE, vec![ESCAPE_BYTE; 64]),
+        ),
+        (
+            "escaped bytes",
+            frame(
+                SELF_TEST_MESSAGE_TYPE,
+                [START_BYTE ^ XOR_BYTE, ESCAPE_BYTE ^ XOR_BYTE, XOR_BYTE].repeat(16),
+            ),
+        ),
+        (
+            "every byte value",
+            frame(SELF_TEST_MESSAGE_TYPE, (0..=u8::MAX).collect()),
+        ),
+        (
+            "start byte in length",
+            frame(
+                SELF_TEST_MESSAGE_TYPE,
+                vec![0x55; usize::from(START_BYTE) - 2],
+            ),
+        ),
+        (
+            "escape byte in length",
+            frame(
+                SELF_TEST_MESSAGE_TYPE,
+                vec![0x55; usize::from(ESCAPE_BYTE) - 2],
+            ),
+        ),
+        (
+            "start and escape bytes in message type",
+            frame(u16::from_le_bytes([START_BYTE, ESCAPE_BYTE]), vec![0x55]),
+        ),
+        (
+            "alternating bits",
+            frame(SELF_TEST_MESSAGE_TYPE, [0x55, 0xAA].repeat(512)),
+        ),
+        (
+            "long payload",
+            frame(
+                SELF_TEST_MESSAGE_TYPE,
+                (0..4096u32).map(|i| (i * 31 % 251) as u8).collect(),
+            ),
+        ),
+    ]
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends a battery of frames covering the edge cases of framing and escaping over a port
+    /// whose transmit line is looped back to its receive line, checking each comes back unchanged
+    ///
+    /// Each frame is given `timeout` to come back. Like [`ping`](Self::ping), a frame that never
+    /// comes back is only detected if reads from the connection time out or would block. The
+    /// port should be otherwise quiet, as any other frame received is a mismatch.
+    ///
+    /// Fails only if writing or reading fails, so that the report can show which frames were
+    /// corrupted.
+    pub fn self_test(&mut self, timeout: Duration) -> io::Result<SelfTestReport> {
+        let mut cases = Vec::new();
+        for (name, sent) in self_test_frames() {
+            self.send_raw(sent.message_type, &sent.payload)?;
+            let outcome = self.receive_self_test_frame(&sent, timeout)?;
+            cases.push(SelfTestCase {
+                name,
+                sent,
+                outcome,
+            });
+        }
+        Ok(SelfTestReport { cases })
+    }
+
+    fn receive_self_test_frame(
+        &mut self,
+        sent: &Frame,
+        timeout: Duration,
+    ) -> io::Result<SelfTestOutcome> {
+        let start = Instant::now();
+        loop {
+            match self.receive_raw() {
+                Ok(frame) if frame == *sent => return Ok(SelfTestOutcome::Passed),
+                Ok(frame) => return Ok(SelfTestOutcome::Mismatch(frame)),
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Non-blocking connections would otherwise be polled in a busy loop
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Err(ReceiveError::Io(e)) => return Err(e),
+                Err(e) => return Ok(SelfTestOutcome::Failed(e)),
+            }
+            if start.elapsed() > timeout {
+                return Ok(SelfTestOutcome::TimedOut);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,444 @@
+//! Soak testing, exchanging randomized messages for hours to qualify releases for installations
+//! that run around the clock.
+
+use crate::codec::{ESCAPE_BYTE, START_BYTE};
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use crate::stats::Stats;
+use crate::time::Instant;
+use std::io;
+use std::time::Duration;
+
+/// Settings for a soak test, run with [`SerialManager::run_soak`]
+#[derive(Debug, Clone)]
+pub struct Soak {
+    duration: Duration,
+    interval: Duration,
+    window: u32,
+    max_payload_length: usize,
+    seed: u32,
+    timeout: Duration,
+    max_memory_growth: u64,
+}
+
+impl Default for Soak {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Soak {
+    /// Creates a test that exchanges messages with payloads of up to 256 bytes for an hour,
+    /// keeping up to 16 in flight, and reports every minute
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            duration: Duration::from_hours(1),
+            interval: Duration::from_mins(1),
+            window: 16,
+            max_payload_length: 256,
+            seed: 0x4753_534B,
+            timeout: Duration::from_secs(1),
+            max_memory_growth: 16 * 1024 * 1024,
+        }
+    }
+
+    /// Sets how long messages are sent for
+    #[must_use]
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets how often a [`SoakSample`] is taken
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how many messages may be sent before the oldest comes back, at least 1
+    #[must_use]
+    pub fn with_window(mut self, window: u32) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Sets the most bytes of data in each message, which must fit the framing used
+    #[must_use]
+    pub fn with_max_payload_length(mut self, max_payload_length: usize) -> Self {
+        self.max_payload_length = max_payload_length;
+        self
+    }
+
+    /// Sets the seed of the randomized messages
+    #[must_use]
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets how long to wait for the oldest message in flight to come back before counting it as
+    /// lost
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many bytes the resident memory of the process may grow by from the first sample
+    /// to the last before the test fails
+    #[must_use]
+    pub fn with_max_memory_growth(mut self, bytes: u64) -> Self {
+        self.max_memory_growth = bytes;
+        self
+    }
+
+    /// Returns the message with sequence number `sequence`
+    ///
+    /// Messages are of a pseudo-random built-in type, with pseudo-random fields generated from
+    /// the sequence number and the seed, and bytes that need escaping are common in them, so
+    /// each message can be checked on its own.
+    #[must_use]
+    pub fn message(&self, sequence: u32) -> Message {
+        let mut random = Random((self.seed ^ sequence.wrapping_mul(0x9E37_79B9)) | 1);
+        let length = random.below(self.max_payload_length + 1);
+        match random.below(12) {
+            0 => Message::Bytes(message_types::Bytes {
+                data: random.bytes(length),
+            }),
+            1 => Message::U8(message_types::U8 {
+                num: random.bytes(1)[0],
+            }),
+            2 => Message::MyString(message_types::MyString {
+                string: random.string(length),
+            }),
+            3 => Message::Multi(message_types::Multi {
+                num: random.bytes(1)[0],
+                string: random.string(length.saturating_sub(1)),
+            }),
+            4 => Message::U16(message_types::U16 {
+                num: u16::from_le_bytes([random.bytes(1)[0], random.bytes(1)[0]]),
+            }),
+            5 => Message::U32(message_types::U32 { num: random.next() }),
+            6 => Message::I64(message_types::I64 {
+                num: i64::from(random.next()) << 32 | i64::from(random.next()),
+            }),
+            // Built from an integer, as NaN never compares equal
+            7 => Message::F64(message_types::F64 {
+                num: f64::from(random.next()) / 1024.0,
+            }),
+            8 => Message::U16Array(message_types::U16Array {
+                values: random
+                    .bytes(length / 2 * 2)
+                    .chunks(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect(),
+            }),
+            9 => Message::Ping(message_types::Ping {
+                sequence: u16::from_le_bytes([random.bytes(1)[0], random.bytes(1)[0]]),
+            }),
+            10 => Message::Bool(message_types::Bool {
+                value: random.below(2) == 1,
+            }),
+            _ => Message::NoOp(message_types::NoOp {}),
+        }
+    }
+}
+
+/// xorshift32, whose state must not be zero
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        usize::try_from(self.next()).unwrap_or(usize::MAX) % bound
+    }
+
+    /// Bytes of which a quarter are the start or escape byte
+    fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length)
+            .map(|_| {
+                let [byte, kind, ..] = self.next().to_le_bytes();
+                match kind % 8 {
+                    0 => START_BYTE,
+                    1 => ESCAPE_BYTE,
+                    _ => byte,
+                }
+            })
+            .collect()
+    }
+
+    /// Printable ASCII, where the start and escape bytes are `X` and `B`
+    fn string(&mut self, length: usize) -> String {
+        self.bytes(length)
+            .into_iter()
+            .map(|byte| {
+                let byte = if (b' '..=b'~').contains(&byte) {
+                    byte
+                } else {
+                    b' ' + byte % 95
+                };
+                char::from(byte)
+            })
+            .collect()
+    }
+}
+
+/// Counts over part or all of a soak test
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SoakSample {
+    /// The time from the start of the test to the end of the sample
+    pub elapsed: Duration,
+    /// The time the sample covers
+    pub duration: Duration,
+    pub messages_sent: u64,
+    /// Messages that came back in order and unchanged
+    pub messages_received: u64,
+    /// Messages that never came back, or were skipped by a later one coming back first
+    pub messages_lost: u64,
+    /// Messages that came back changed, but still decoded
+    pub corrupted: u64,
+    /// Messages that came back again, or after a later one
+    pub out_of_order: u64,
+    pub resyncs: u64,
+    pub decode_errors: u64,
+    pub crc_failures: u64,
+    /// The resident memory of the process at the end of the sample, where it can be measured
+    pub resident_memory: Option<u64>,
+}
+
+/// The results of [`SerialManager::run_soak`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SoakReport {
+    /// Samples taken every [`Soak::with_interval`], in order
+    pub samples: Vec<SoakSample>,
+    /// The whole test
+    pub total: SoakSample,
+    /// The growth in resident memory allowed by [`Soak::with_max_memory_growth`]
+    pub max_memory_growth: u64,
+}
+
+impl SoakReport {
+    /// Returns how many bytes the resident memory grew by from the first sample to the end of
+    /// the test, which is negative if it shrank, or `None` where it cannot be measured
+    ///
+    /// The first sample is taken once the test has run for an interval, so that buffers
+    /// allocated as it starts are not counted.
+    #[must_use]
+    pub fn memory_growth(&self) -> Option<i64> {
+        let first = i64::try_from(self.samples.first()?.resident_memory?).ok()?;
+        let last = i64::try_from(self.total.resident_memory?).ok()?;
+        Some(last - first)
+    }
+
+    /// Returns whether every message that came back was unchanged and in order, and memory grew
+    /// by no more than allowed
+    ///
+    /// Lost messages and decode errors do not fail the test, as a link may drop frames; they are
+    /// reported for comparison against what the link is expected to do.
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.total.corrupted == 0
+            && self.total.out_of_order == 0
+            && self.memory_growth().is_none_or(|growth| {
+                growth <= i64::try_from(self.max_memory_growth).unwrap_or(i64::MAX)
+            })
+    }
+}
+
+/// Checks the messages that come back against those sent
+#[derive(Debug, Default)]
+pub(super) struct SoakChecker {
+    /// The sequence number of the oldest message in flight
+    pub(super) next_sequence: u32,
+    pub(super) messages_received: u64,
+    pub(super) messages_lost: u64,
+    pub(super) corrupted: u64,
+    pub(super) out_of_order: u64,
+}
+
+impl SoakChecker {
+    /// Checks a message that came back, with `sent` messages sent so far
+    pub(super) fn check(&mut self, soak: &Soak, message: &Message, sent: u32) {
+        let in_flight = sent.wrapping_sub(self.next_sequence);
+        if let Some(skipped) =
+            (0..in_flight).find(|&i| soak.message(self.next_sequence.wrapping_add(i)) == *message)
+        {
+            self.messages_lost += u64::from(skipped);
+            self.messages_received += 1;
+            self.next_sequence = self.next_sequence.wrapping_add(skipped + 1);
+        } else if (1..=soak.window.min(self.next_sequence))
+            .any(|i| soak.message(self.next_sequence.wrapping_sub(i)) == *message)
+        {
+            self.out_of_order += 1;
+        } else {
+            self.corrupted += 1;
+        }
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Exchanges randomized messages with a peer that echoes them back for the configured
+    /// duration, checking that each comes back unchanged and in order, and reports losses,
+    /// corruption, link errors and the resident memory of the process over time
+    ///
+    /// Up to the window of messages are sent before the oldest comes back, and one that has not
+    /// come back within the timeout is counted as lost. Reads from the connection must time out
+    /// or would block, ideally after no more than a millisecond or so, or they hold up sending.
+    ///
+    /// Fails only if writing or reading fails, as lost and corrupted messages are what is being
+    /// measured.
+    pub fn run_soak(&mut self, soak: &Soak) -> io::Result<SoakReport> {
+        self.run_soak_with(soak, |_| {})
+    }
+
+    /// Runs a soak test like [`run_soak`](Self::run_soak), passing each sample to `on_sample` as
+    /// it is taken, so that progress can be shown over a test lasting hours
+    pub fn run_soak_with(
+        &mut self,
+        soak: &Soak,
+        mut on_sample: impl FnMut(&SoakSample),
+    ) -> io::Result<SoakReport> {
+        let mut checker = SoakChecker::default();
+        let mut samples = Vec::new();
+        let mut last = SoakSample::default();
+        let mut sent = 0u32;
+        let start_stats = self.stats();
+        let start = Instant::now();
+        let mut next_sample = soak.interval;
+        let mut waiting_since = start;
+
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= next_sample {
+                let sample = self.soak_sample(&checker, sent, &start_stats, start);
+                let interval = delta(&sample, &last);
+                on_sample(&interval);
+                samples.push(interval);
+                last = sample;
+                next_sample += soak.interval;
+            }
+
+            let in_flight = sent.wrapping_sub(checker.next_sequence);
+            let sending = elapsed < soak.duration;
+            if !sending && in_flight == 0 {
+                break;
+            }
+            if sending && in_flight < soak.window {
+                if in_flight == 0 {
+                    waiting_since = Instant::now();
+                }
+                self.send(soak.message(sent))?;
+                sent = sent.wrapping_add(1);
+            }
+
+            let oldest = checker.next_sequence;
+            self.receive_soak_messages(soak, &mut checker, sent)?;
+            if checker.next_sequence != oldest {
+                waiting_since = Instant::now();
+            } else if in_flight > 0 && waiting_since.elapsed() >= soak.timeout {
+                checker.messages_lost += 1;
+                checker.next_sequence = checker.next_sequence.wrapping_add(1);
+                waiting_since = Instant::now();
+            }
+        }
+
+        let total = self.soak_sample(&checker, sent, &start_stats, start);
+        let remainder = delta(&total, &last);
+        if remainder.duration > Duration::ZERO {
+            on_sample(&remainder);
+            samples.push(remainder);
+        }
+        Ok(SoakReport {
+            samples,
+            total,
+            max_memory_growth: soak.max_memory_growth,
+        })
+    }
+
+    /// Receives and checks the messages that have already come back
+    fn receive_soak_messages(
+        &mut self,
+        soak: &Soak,
+        checker: &mut SoakChecker,
+        sent: u32,
+    ) -> io::Result<()> {
+        loop {
+            match self.receive() {
+                Ok(message) => checker.check(soak, &message, sent),
+                Err(e) if e.is_timeout() => return Ok(()),
+                Err(ReceiveError::Io(e)) => return Err(e),
+                // Counted as decode errors in the stats
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Returns the counts from the start of the test
+    fn soak_sample(
+        &self,
+        checker: &SoakChecker,
+        sent: u32,
+        start_stats: &Stats,
+        start: Instant,
+    ) -> SoakSample {
+        let stats = self.stats();
+        let elapsed = start.elapsed();
+        SoakSample {
+            elapsed,
+            duration: elapsed,
+            messages_sent: u64::from(sent),
+            messages_received: checker.messages_received,
+            messages_lost: checker.messages_lost,
+            corrupted: checker.corrupted,
+            out_of_order: checker.out_of_order,
+            resyncs: stats.resyncs - start_stats.resyncs,
+            decode_errors: stats.decode_errors - start_stats.decode_errors,
+            crc_failures: stats.crc_failures - start_stats.crc_failures,
+            resident_memory: resident_memory(),
+        }
+    }
+}
+
+/// Returns the counts of `current` since `last`, both counted from the start of the test
+fn delta(current: &SoakSample, last: &SoakSample) -> SoakSample {
+    SoakSample {
+        elapsed: current.elapsed,
+        duration: current.elapsed.saturating_sub(last.elapsed),
+        messages_sent: current.messages_sent - last.messages_sent,
+        messages_received: current.messages_received - last.messages_received,
+        messages_lost: current.messages_lost - last.messages_lost,
+        corrupted: current.corrupted - last.corrupted,
+        out_of_order: current.out_of_order - last.out_of_order,
+        resyncs: current.resyncs - last.resyncs,
+        decode_errors: current.decode_errors - last.decode_errors,
+        crc_failures: current.crc_failures - last.crc_failures,
+        resident_memory: current.resident_memory,
+    }
+}
+
+/// Returns the resident memory of the process from `/proc/self/statm`
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf only reads a configuration value
+    let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+    Some(pages * page_size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+    None
+}
@@ -0,0 +1,310 @@
+use super::soak::SoakChecker;
+use super::*;
+use crate::message::{message_types, roundtrip, Message};
+use crate::stats::{LatencyStats, LATENCY_BUCKETS};
+use crate::test_util::{stream_pair, TestStream};
+use std::io::{Read, Write};
+
+/// A port with its transmit line looped back to its receive line, through a link that can
+/// corrupt bytes
+struct Loopback {
+    tx: TestStream,
+    rx: TestStream,
+    corrupt: fn(u8) -> u8,
+}
+
+impl Loopback {
+    fn new(corrupt: fn(u8) -> u8) -> Self {
+        let (tx, rx) = stream_pair();
+        rx.set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        Self { tx, rx, corrupt }
+    }
+}
+
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rx.read(buf)
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let corrupted: Vec<u8> = buf.iter().map(|&byte| (self.corrupt)(byte)).collect();
+        self.tx.write_all(&corrupted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tx.flush()
+    }
+}
+
+#[test]
+fn test_self_test_passes() {
+    let mut manager = SerialManager::new(Loopback::new(|byte| byte));
+    let report = manager.self_test(Duration::from_millis(100)).unwrap();
+    assert_eq!(report.cases.len(), self_test_frames().len());
+    assert!(report.passed(), "{report:?}");
+}
+
+#[test]
+fn test_self_test_reports_corruption() {
+    // A stuck bit clears the top bit of every byte
+    let mut manager = SerialManager::new(Loopback::new(|byte| byte & 0x7F));
+    let report = manager.self_test(Duration::from_millis(100)).unwrap();
+    assert!(!report.passed());
+    let failures: Vec<_> = report.failures().map(|case| case.name).collect();
+    assert!(failures.contains(&"every byte value"));
+    assert!(failures.contains(&"alternating bits"));
+    assert!(!failures.contains(&"start bytes"));
+    assert!(!failures.contains(&"escape bytes"));
+}
+
+#[test]
+fn test_self_test_times_out() {
+    // The receive line is disconnected, so nothing is looped back
+    let (tx, _tx_peer) = stream_pair();
+    let (rx, _rx_peer) = stream_pair();
+    rx.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+    let mut manager = SerialManager::new(Loopback {
+        tx,
+        rx,
+        corrupt: |byte| byte,
+    });
+    let report = manager.self_test(Duration::from_millis(10)).unwrap();
+    assert!(report
+        .cases
+        .iter()
+        .all(|case| matches!(case.outcome, SelfTestOutcome::TimedOut)));
+}
+
+fn bert_loopback(corrupt: fn(u8) -> u8) -> SerialManager<Loopback> {
+    let loopback = Loopback::new(corrupt);
+    loopback
+        .rx
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+    SerialManager::new(loopback)
+}
+
+#[test]
+fn test_bert_frames_are_reproducible() {
+    let bert = Bert::new().with_payload_length(10);
+    let frame = bert.frame(7);
+    assert_eq!(frame.message_type, BERT_MESSAGE_TYPE);
+    assert_eq!(frame.payload.len(), 10);
+    assert_eq!(frame.payload[..4], 7u32.to_le_bytes());
+    assert_eq!(bert.frame(7), frame);
+    assert_ne!(bert.frame(8).payload[4..], frame.payload[4..]);
+    assert_ne!(bert.with_seed(1).frame(7), frame);
+}
+
+#[test]
+fn test_bert_checker_counts_errors_and_losses() {
+    let bert = Bert::new().with_payload_length(16);
+    let mut checker = BertChecker::new(bert.clone());
+    assert_eq!(checker.check(&bert.frame(0)), 0);
+
+    // Frames 1 and 2 never arrive
+    let mut frame = bert.frame(3);
+    frame.payload[10] ^= 0b101;
+    assert_eq!(checker.check(&frame), 2);
+
+    // A corrupted sequence number is checked against the next frame expected
+    let mut frame = bert.frame(4);
+    frame.payload[3] ^= 0x80;
+    assert_eq!(checker.check(&frame), 1);
+
+    // A truncated frame counts its missing bytes as wrong
+    let mut frame = bert.frame(5);
+    frame.payload.truncate(14);
+    assert_eq!(checker.check(&frame), 16);
+
+    let sample = checker.sample();
+    assert_eq!(checker.next_sequence(), 6);
+    assert_eq!(sample.frames_received, 4);
+    assert_eq!(sample.frames_lost, 2);
+    assert_eq!(sample.bits_checked, 4 * (16 + 8 * 16));
+    assert_eq!(sample.bit_errors, 19);
+}
+
+#[test]
+fn test_bert_over_clean_link() {
+    let mut manager = bert_loopback(|byte| byte);
+    let bert = Bert::new()
+        .with_duration(Duration::from_millis(100))
+        .with_interval(Duration::from_millis(25));
+    let report = manager.run_bert(&bert).unwrap();
+    let total = report.total;
+    assert!(total.frames_sent > 0);
+    assert_eq!(total.frames_received, total.frames_sent);
+    assert_eq!(total.frames_lost, 0);
+    assert_eq!(total.bit_errors, 0);
+    assert_eq!(total.bytes_received, total.bytes_sent);
+    assert!(total.throughput() > 0.0);
+    assert!(report.samples.len() >= 4, "{report:?}");
+    assert_eq!(
+        report.samples.iter().map(|s| s.frames_sent).sum::<u64>(),
+        total.frames_sent
+    );
+}
+
+#[test]
+fn test_bert_over_corrupting_link() {
+    let mut manager = bert_loopback(|byte| byte & 0x7F);
+    let bert = Bert::new().with_duration(Duration::from_millis(50));
+    let report = manager.run_bert(&bert).unwrap();
+    assert!(report.total.bit_errors > 0);
+    assert!(report.total.bit_error_rate() > 0.0);
+}
+
+#[test]
+fn test_bert_limits_rate() {
+    let mut manager = bert_loopback(|byte| byte);
+    // 10 frames of 64 bytes a second
+    let bert = Bert::new()
+        .with_duration(Duration::from_millis(250))
+        .with_rate(640);
+    let report = manager.run_bert(&bert).unwrap();
+    assert!((2..=4).contains(&report.total.frames_sent), "{report:?}");
+}
+
+#[test]
+fn test_latency_stats_record() {
+    let mut latency = LatencyStats::default();
+    for micros in [100, 300, 200] {
+        latency.record(Duration::from_micros(micros));
+    }
+    assert_eq!(latency.samples, 3);
+    assert_eq!(latency.min, Duration::from_micros(100));
+    assert_eq!(latency.max, Duration::from_micros(300));
+    assert_eq!(latency.mean(), Duration::from_micros(200));
+    assert_eq!(latency.last, Duration::from_micros(200));
+    // 200 / 16, then 12.5 + (100 - 12.5) / 16
+    assert_eq!(latency.jitter, Duration::from_nanos(17_968));
+    // 100 µs is in [64, 128), 200 and 300 µs in [128, 256) and [256, 512)
+    assert_eq!(latency.histogram[6], 1);
+    assert_eq!(latency.histogram[7], 1);
+    assert_eq!(latency.histogram[8], 1);
+    assert_eq!(
+        LatencyStats::bucket_range(6),
+        (Duration::from_micros(64), Some(Duration::from_micros(128)))
+    );
+    assert_eq!(
+        LatencyStats::bucket_range(0),
+        (Duration::ZERO, Some(Duration::from_micros(2)))
+    );
+    assert_eq!(LatencyStats::bucket_range(LATENCY_BUCKETS - 1).1, None);
+}
+
+#[test]
+fn test_probe_latency() {
+    let (stream1, stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+    // The peer answers every other ping, then stays connected until the probe is done
+    let (done, wait) = std::sync::mpsc::channel::<()>();
+    let peer = std::thread::spawn(move || {
+        let mut peer = SerialManager::new(stream2);
+        for _ in 0..10 {
+            let Message::Ping(ping) = peer.receive().unwrap() else {
+                panic!("expected a ping");
+            };
+            if ping.sequence % 2 == 0 {
+                let sequence = ping.sequence;
+                peer.send(Message::Pong(message_types::Pong { sequence }))
+                    .unwrap();
+            }
+        }
+        wait.recv().unwrap();
+    });
+    let mut manager = SerialManager::new(stream1);
+    let probe = LatencyProbe::new()
+        .with_count(10)
+        .with_interval(Duration::from_millis(2))
+        .with_timeout(Duration::from_millis(100));
+    let latency = manager.probe_latency(&probe).unwrap();
+    done.send(()).unwrap();
+    peer.join().unwrap();
+    assert_eq!(latency.samples, 5);
+    assert_eq!(latency.lost, 5);
+    assert!(latency.max < Duration::from_millis(100));
+    assert_eq!(manager.stats().latency, latency);
+}
+
+#[test]
+fn test_soak_messages_are_reproducible() {
+    let soak = Soak::new().with_max_payload_length(64);
+    let messages: Vec<Message> = (0..200).map(|sequence| soak.message(sequence)).collect();
+    assert_eq!(soak.message(7), messages[7]);
+    assert_ne!(soak.clone().with_seed(1).message(7), messages[7]);
+    let types: std::collections::BTreeSet<u16> =
+        messages.iter().map(Message::message_type).collect();
+    assert_eq!(types.len(), 12);
+    for message in messages {
+        assert!(message.clone().to_bytes().unwrap().len() <= 64 + 1);
+        assert_eq!(roundtrip(message.clone()).unwrap(), message);
+    }
+}
+
+#[test]
+fn test_soak_checker_counts_losses_and_violations() {
+    let soak = Soak::new();
+    let mut checker = SoakChecker::default();
+    checker.check(&soak, &soak.message(0), 5);
+    // Messages 1 and 2 never come back
+    checker.check(&soak, &soak.message(3), 5);
+    // Message 3 comes back again, then message 1 after it
+    checker.check(&soak, &soak.message(3), 5);
+    checker.check(&soak, &soak.message(1), 5);
+    checker.check(&soak, &Message::U8(message_types::U8 { num: 1 }), 5);
+    assert_eq!(checker.next_sequence, 4);
+    assert_eq!(checker.messages_received, 2);
+    assert_eq!(checker.messages_lost, 2);
+    assert_eq!(checker.out_of_order, 2);
+    assert_eq!(checker.corrupted, 1);
+}
+
+#[test]
+fn test_soak_over_clean_link() {
+    let mut manager = bert_loopback(|byte| byte);
+    let soak = Soak::new()
+        .with_duration(Duration::from_millis(100))
+        .with_interval(Duration::from_millis(25));
+    let report = manager.run_soak(&soak).unwrap();
+    let total = report.total;
+    assert!(total.messages_sent > 0);
+    assert_eq!(total.messages_received, total.messages_sent);
+    assert_eq!(total.messages_lost, 0);
+    assert!(report.passed(), "{report:?}");
+    assert!(report.samples.len() >= 4, "{report:?}");
+    assert_eq!(
+        report.samples.iter().map(|s| s.messages_sent).sum::<u64>(),
+        total.messages_sent
+    );
+    #[cfg(target_os = "linux")]
+    assert!(report.memory_growth().is_some());
+}
+
+#[test]
+fn test_soak_counts_messages_that_never_come_back() {
+    // The receive line is disconnected, so nothing is looped back
+    let (tx, _tx_peer) = stream_pair();
+    let (rx, _rx_peer) = stream_pair();
+    rx.set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+    let mut manager = SerialManager::new(Loopback {
+        tx,
+        rx,
+        corrupt: |byte| byte,
+    });
+    let soak = Soak::new()
+        .with_duration(Duration::from_millis(20))
+        .with_window(4)
+        .with_timeout(Duration::from_millis(10));
+    let report = manager.run_soak(&soak).unwrap();
+    assert!(report.total.messages_sent > 0);
+    assert_eq!(report.total.messages_lost, report.total.messages_sent);
+    assert!(report.passed());
+}
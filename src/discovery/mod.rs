@@ -0,0 +1,157 @@
+//! Finding the serial ports that a peer speaking this protocol is connected to.
+//!
+//! [`serial_ports`] lists the serial ports present, and [`Discovery`] probes them by sending an
+//! `Identify` message and waiting for a `DeviceInfo` in reply, so that a desktop application can
+//! pick the right port without asking the user.
+//!
+//! ```no_run
+//! use generic_serial_protocol::discovery::Discovery;
+//!
+//! for port in Discovery::new().with_device_id(0x1234).run() {
+//!     println!("{}: {}", port.path.display(), port.info.name);
+//! }
+//! ```
+
+use crate::message::message_types;
+use crate::serial_manager::{SerialManager, Transport};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Prefixes of the names of serial devices in `/dev` that are listed: USB serial adapters, USB
+/// CDC ACM devices such as most microcontroller boards, Raspberry Pi UARTs, and on macOS the
+/// callout devices
+#[cfg(unix)]
+const DEVICE_PREFIXES: &[&str] = &["ttyUSB", "ttyACM", "ttyAMA", "cu."];
+
+/// A port where a peer replied to `Identify`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPort {
+    pub path: PathBuf,
+    pub info: message_types::DeviceInfo,
+}
+
+/// Lists the serial ports present, in order of their paths
+///
+/// On Unix, these are the USB serial adapters, USB CDC ACM devices and UARTs in `/dev`, such as
+/// `/dev/ttyUSB0` or `/dev/cu.usbmodem1101`. Built-in legacy UARTs like `/dev/ttyS0` are left
+/// out, as Linux lists dozens whether or not they exist. On Windows, these are the COM ports
+/// that can be opened, so ports already in use are left out.
+#[must_use]
+pub fn serial_ports() -> Vec<PathBuf> {
+    #[cfg(unix)]
+    let mut ports: Vec<_> = std::fs::read_dir("/dev")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| DEVICE_PREFIXES.iter().any(|p| name.starts_with(p)))
+        })
+        .collect();
+    #[cfg(windows)]
+    let mut ports: Vec<_> = (1..=256)
+        .map(|number| format!(r"\\.\COM{number}"))
+        .filter(|path| std::fs::File::open(path).is_ok())
+        .map(PathBuf::from)
+        .collect();
+    #[cfg(not(any(unix, windows)))]
+    let mut ports: Vec<PathBuf> = Vec::new();
+    ports.sort();
+    ports
+}
+
+/// Probes serial ports for peers speaking this protocol
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    timeout: Duration,
+    device_id: Option<u32>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Discovery {
+    /// Creates a discovery that accepts any peer replying within 200 ms
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_millis(200),
+            device_id: None,
+        }
+    }
+
+    /// Sets how long to wait for each port to reply
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Only accepts peers whose `DeviceInfo` has this device ID
+    #[must_use]
+    pub fn with_device_id(mut self, device_id: u32) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    /// Probes every port from [`serial_ports`] at the same time, returning those where an
+    /// accepted peer replied, in order of their paths
+    ///
+    /// Ports are opened as-is, so their baud rate must already be configured. Ports that cannot
+    /// be opened, such as those in use by another program, are skipped.
+    ///
+    /// Only available on Unix, where ports are opened in non-blocking mode so that a port that
+    /// never replies can be given up on. Elsewhere, open the ports with a read timeout and pass
+    /// them to [`probe`](Self::probe).
+    #[cfg(unix)]
+    #[must_use]
+    pub fn run(&self) -> Vec<DiscoveredPort> {
+        let ports = serial_ports();
+        std::thread::scope(|scope| {
+            let probes: Vec<_> = ports
+                .into_iter()
+                .map(|path| {
+                    scope.spawn(move || {
+                        let info = self.probe(open_nonblocking(&path).ok()?)?;
+                        Some(DiscoveredPort { path, info })
+                    })
+                })
+                .collect();
+            probes
+                .into_iter()
+                .filter_map(|probe| probe.join().ok().flatten())
+                .collect()
+        })
+    }
+
+    /// Sends an `Identify` over `connection` and returns the peer's `DeviceInfo` if it replies in
+    /// time and is accepted
+    ///
+    /// Reads from `connection` must time out or be non-blocking, or this waits until the peer
+    /// replies.
+    pub fn probe<T: Transport>(&self, connection: T) -> Option<message_types::DeviceInfo> {
+        SerialManager::new(connection)
+            .identify(self.timeout)
+            .ok()
+            .filter(|info| self.device_id.is_none_or(|id| info.device_id == id))
+    }
+}
+
+#[cfg(unix)]
+fn open_nonblocking(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests;
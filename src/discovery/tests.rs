@@ -0,0 +1,56 @@
+use super::*;
+use crate::message::{Capabilities, Message};
+use crate::test_util::{stream_pair, TestStream};
+use std::thread;
+
+fn device_info(device_id: u32) -> message_types::DeviceInfo {
+    message_types::DeviceInfo {
+        device_id,
+        name: "sensor".to_string(),
+        hw_rev: 2,
+        fw_version: "1.4.2".to_string(),
+        capabilities: Capabilities::PING | Capabilities::LOG,
+    }
+}
+
+/// Replies to an `Identify` with `info`, after sending some telemetry
+fn spawn_device(stream: TestStream, info: message_types::DeviceInfo) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut device = SerialManager::new(stream);
+        assert_eq!(
+            device.receive().unwrap(),
+            Message::Identify(message_types::Identify {})
+        );
+        device
+            .send(Message::U8(message_types::U8 { num: 1 }))
+            .unwrap();
+        device.send(Message::DeviceInfo(info)).unwrap();
+    })
+}
+
+#[test]
+fn test_probe() {
+    let (host, device) = stream_pair();
+    let device = spawn_device(device, device_info(0x1234));
+
+    assert_eq!(Discovery::new().probe(host), Some(device_info(0x1234)));
+    device.join().unwrap();
+}
+
+#[test]
+fn test_probe_filters_device_id() {
+    let (host, device) = stream_pair();
+    let device = spawn_device(device, device_info(0x1234));
+
+    assert_eq!(Discovery::new().with_device_id(0x4321).probe(host), None);
+    device.join().unwrap();
+}
+
+#[test]
+fn test_probe_times_out() {
+    let (host, _device) = stream_pair();
+    host.set_nonblocking(true).unwrap();
+
+    let discovery = Discovery::new().with_timeout(Duration::from_millis(20));
+    assert_eq!(discovery.probe(host), None);
+}
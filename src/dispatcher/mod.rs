@@ -0,0 +1,101 @@
+use crate::codec::Endianness;
+use crate::errors::ReceiveError;
+use crate::message::Message;
+use crate::payload::MessageType;
+use crate::serial_manager::{SerialManager, Transport};
+use std::collections::HashMap;
+
+/// Handles a message, or hands it back if it does not decode as the handler's type
+type Handler = Box<dyn FnMut(Message, Endianness) -> Result<(), Message>>;
+type Fallback = Box<dyn FnMut(Message)>;
+
+/// Routes received messages to handlers registered per message type.
+///
+/// ```no_run
+/// # use generic_serial_protocol::{message_types, Dispatcher, SerialManager};
+/// # let stream = std::io::Cursor::new(Vec::new());
+/// let mut manager = SerialManager::new(stream);
+/// let mut dispatcher = Dispatcher::new();
+/// dispatcher.on::<message_types::Status>(|status| println!("status: {status:?}"));
+/// dispatcher.run(&mut manager).unwrap();
+/// ```
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<u16, Handler>,
+    fallback: Option<Fallback>,
+    endianness: Endianness,
+}
+
+impl Dispatcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes application message types passed to [`dispatch`](Self::dispatch) in the given
+    /// byte order, which must be that of the connection they arrive on
+    ///
+    /// [`run`](Self::run) uses the byte order of the manager instead.
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Registers the handler for messages of type `M`, replacing any previous one
+    pub fn on<M: MessageType + 'static>(&mut self, mut handler: impl FnMut(M) + 'static) {
+        self.handlers.insert(
+            M::ID,
+            Box::new(move |message, endianness| {
+                handler(M::from_message(message, endianness)?);
+                Ok(())
+            }),
+        );
+    }
+
+    /// Registers the handler for messages without a handler of their own, including
+    /// [`Message::Unknown`] and messages that do not decode as the type of their handler
+    pub fn otherwise(&mut self, handler: impl FnMut(Message) + 'static) {
+        self.fallback = Some(Box::new(handler));
+    }
+
+    /// Passes a message to the handler for its type, returning whether there was one
+    pub fn dispatch(&mut self, message: Message) -> bool {
+        self.dispatch_with(message, self.endianness)
+    }
+
+    fn dispatch_with(&mut self, message: Message, endianness: Endianness) -> bool {
+        // Application message types arrive as unknown messages, which the handler decodes
+        let message = match self.handlers.get_mut(&message.message_type()) {
+            Some(handler) => match handler(message, endianness) {
+                Ok(()) => return true,
+                Err(message) => message,
+            },
+            None => message,
+        };
+        match &mut self.fallback {
+            Some(fallback) => {
+                fallback(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Receives messages from `manager` and dispatches them until receiving fails
+    ///
+    /// Messages without a handler are dropped. The error is returned, and the loop can be
+    /// resumed by calling `run` again, e.g. after a decode error.
+    pub fn run<T: Transport>(
+        &mut self,
+        manager: &mut SerialManager<T>,
+    ) -> Result<(), ReceiveError> {
+        loop {
+            let message = manager.receive()?;
+            self.dispatch_with(message, manager.endianness());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,105 @@
+use super::*;
+use crate::message_types;
+use crate::test_util::stream_pair;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn test_dispatch_by_type() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    let log = seen.clone();
+    dispatcher.on::<message_types::U8>(move |payload| log.borrow_mut().push(payload.num));
+    let log = seen.clone();
+    dispatcher.on::<message_types::Status>(move |status| {
+        log.borrow_mut().push(match status {
+            message_types::Status::Ok => 100,
+            _ => 101,
+        });
+    });
+
+    assert!(dispatcher.dispatch(Message::U8(message_types::U8 { num: 7 })));
+    assert!(dispatcher.dispatch(Message::Status(message_types::Status::Ok)));
+    assert!(!dispatcher.dispatch(Message::NoOp(message_types::NoOp {})));
+    assert_eq!(*seen.borrow(), [7, 100]);
+}
+
+#[test]
+fn test_fallback_handler() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.on::<message_types::U8>(|_| ());
+    let log = seen.clone();
+    dispatcher.otherwise(move |message| log.borrow_mut().push(message.message_type()));
+
+    let unknown = Message::Unknown {
+        message_type: 1,
+        data: vec![],
+    };
+    assert!(dispatcher.dispatch(Message::U8(message_types::U8 { num: 7 })));
+    assert!(dispatcher.dispatch(unknown));
+    assert!(dispatcher.dispatch(Message::NoOp(message_types::NoOp {})));
+    assert_eq!(*seen.borrow(), [1, 4]);
+}
+
+#[test]
+fn test_run() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new();
+    let log = seen.clone();
+    dispatcher.on::<message_types::U16>(move |payload| log.borrow_mut().push(payload.num));
+
+    for num in [1, 2, 3] {
+        sender.send(message_types::U16 { num }.into()).unwrap();
+    }
+    sender.send(message_types::NoOp {}.into()).unwrap();
+    drop(sender);
+
+    assert!(matches!(
+        dispatcher.run(&mut receiver),
+        Err(ReceiveError::Io(_))
+    ));
+    assert_eq!(*seen.borrow(), [1, 2, 3]);
+}
+
+#[test]
+fn test_dispatch_application_message() {
+    use crate::codec::Endianness;
+    use crate::errors::{DecodeError, EncodeError};
+    use crate::payload::{define_payload, Field, MessageType, Payload};
+    use crate::schema::{
+        FieldDescriptor, FieldType, PayloadDescriptor, StructDescriptor, StructEncoding,
+    };
+
+    define_payload!(
+        struct Reading {
+            celsius: i16,
+        }
+    );
+
+    impl MessageType for Reading {
+        const ID: u16 = 0x0100;
+    }
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut dispatcher = Dispatcher::new().with_endianness(Endianness::Big);
+    let log = seen.clone();
+    dispatcher.on::<Reading>(move |reading| log.borrow_mut().push(reading.celsius));
+    let log = seen.clone();
+    dispatcher.otherwise(move |_| log.borrow_mut().push(0));
+
+    assert!(dispatcher.dispatch(Message::Unknown {
+        message_type: 0x0100,
+        data: vec![0x01, 0x02],
+    }));
+    // A payload that does not decode goes to the fallback handler
+    assert!(dispatcher.dispatch(Message::Unknown {
+        message_type: 0x0100,
+        data: vec![0x01],
+    }));
+    assert_eq!(*seen.borrow(), [0x0102, 0]);
+}
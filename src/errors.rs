@@ -1,39 +1,368 @@
+use crate::bridge::LinkId;
+use crate::message::Message;
+use crate::router::RouteId;
+use std::fmt;
 use std::io;
 use std::string::FromUtf8Error;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Why a frame could not be received
+///
+/// New variants may be added in minor releases, so matches outside this crate need a wildcard
+/// arm. The accessors cover the common checks without matching.
 #[derive(Debug, Error)]
-pub enum MaybeResyncError<T> {
-    #[error("Resync")]
-    Resync,
-    #[error("Error: {0}")]
-    Error(#[from] T),
+#[non_exhaustive]
+pub enum ReceiveError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// A frame that could not be decoded, with the bytes it was received as and, where known, the
+    /// offset in them of the field that failed
+    #[error("Decode error: {source}{}", DecodeLocation(.frame, *.offset))]
+    Decode {
+        source: DecodeError,
+        frame: Vec<u8>,
+        offset: Option<usize>,
+    },
+    #[error("Unexpected bytes outside any frame: {0:02X?}")]
+    UnexpectedBytes(Vec<u8>),
+    /// The peer sent a `Goodbye`, closing the connection in an orderly way rather than crashing
+    /// or being disconnected
+    #[error("Peer closed the connection")]
+    PeerClosed,
+    /// The peer did not answer a liveness challenge of the [`Watchdog`](crate::Watchdog) in
+    /// time, so its protocol handling is stuck even if the line is active
+    #[error("Peer did not answer a liveness challenge")]
+    PeerUnresponsive,
+    /// Receiving was aborted through a [`CancelToken`](crate::CancelToken)
+    #[error("Receive cancelled")]
+    Cancelled,
+    /// A frame exceeded the [`ReceiveLimit`](crate::ReceiveLimit) of its message type, and was
+    /// discarded
+    #[error("Message type {message_type} exceeded its receive limit: {violation}")]
+    LimitExceeded {
+        message_type: u16,
+        violation: LimitViolation,
+    },
 }
 
-impl From<MaybeResyncError<io::Error>> for MaybeResyncError<ReceiveError> {
-    fn from(error: MaybeResyncError<io::Error>) -> Self {
-        match error {
-            MaybeResyncError::Resync => MaybeResyncError::Resync,
-            MaybeResyncError::Error(e) => MaybeResyncError::Error(e.into()),
+impl ReceiveError {
+    /// The IO error that stopped receiving
+    #[must_use]
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            ReceiveError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Returns whether nothing was received within the read timeout, so that receiving can be
+    /// retried
+    #[must_use]
+    pub fn is_timeout(&self) -> bool {
+        self.io_error().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )
+        })
+    }
+
+    /// The reason a frame could not be decoded
+    #[must_use]
+    pub fn decode_error(&self) -> Option<&DecodeError> {
+        match self {
+            ReceiveError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The raw bytes of a frame that could not be decoded, as received, including the start
+    /// byte and any escape sequences
+    #[must_use]
+    pub fn frame(&self) -> Option<&[u8]> {
+        match self {
+            ReceiveError::Decode { frame, .. } => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// The offset in [`frame`](Self::frame) of the header or payload field that could not be
+    /// decoded
+    #[must_use]
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ReceiveError::Decode { offset, .. } => *offset,
+            _ => None,
         }
     }
 }
 
-impl From<DecodeError> for MaybeResyncError<ReceiveError> {
-    fn from(error: DecodeError) -> Self {
-        MaybeResyncError::Error(error.into())
+/// Writes where in a frame decoding failed, after the reason
+struct DecodeLocation<'a>(&'a [u8], Option<usize>);
+
+impl fmt::Display for DecodeLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DecodeLocation(frame, offset) = *self;
+        if let Some(offset) = offset {
+            write!(f, " at offset {offset}")?;
+        }
+        if !frame.is_empty() {
+            write!(f, " in frame {frame:02X?}")?;
+        }
+        Ok(())
     }
 }
 
+/// A message that could not be queued by a [`QueueSender`](crate::QueueSender), handed back so
+/// that it can be retried or dropped
 #[derive(Debug, Error)]
-pub enum ReceiveError {
+pub enum QueueSendError {
+    #[error("Send queue is full")]
+    Full(Message),
+    #[error("Timed out waiting for space in the send queue")]
+    Timeout(Message),
+    #[error("Send queue writer has stopped")]
+    Disconnected(Message),
+}
+
+impl QueueSendError {
+    /// The message that could not be queued
+    #[must_use]
+    pub fn into_message(self) -> Message {
+        match self {
+            QueueSendError::Full(message)
+            | QueueSendError::Timeout(message)
+            | QueueSendError::Disconnected(message) => message,
+        }
+    }
+}
+
+/// How a received frame exceeded the [`ReceiveLimit`](crate::ReceiveLimit) of its message type
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+pub enum LimitViolation {
+    #[error("payload of {length} bytes is longer than the maximum of {max}")]
+    PayloadTooLong { length: usize, max: usize },
+    /// Holds the frames per second allowed
+    #[error("more than {0} frames a second")]
+    RateExceeded(u32),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RegisterError {
+    #[error("Message type {0} is reserved for built-in message types")]
+    Reserved(u16),
+    #[error("Message type {0} is already registered")]
+    AlreadyRegistered(u16),
+}
+
+#[derive(Debug, Error)]
+pub enum PingError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    #[error("No reply within the timeout")]
+    Timeout,
+}
+
+#[derive(Debug, Error)]
+pub enum ReceiveTypedError {
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    /// A message of another type was received, which is handed back rather than lost
+    #[error("Unexpected message type: {}", .0.message_type())]
+    UnexpectedMessage(Message),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WatchdogError {
+    #[error("No valid frame received for {0:?}")]
+    Stalled(Duration),
+}
+
+#[derive(Debug, Error)]
+pub enum IdentifyError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("No reply within the timeout")]
+    Timeout,
+}
+
+#[derive(Debug, Error)]
+pub enum TimeSyncError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    #[error("Response to a different request")]
+    OriginMismatch,
+}
+
+#[derive(Debug, Error)]
+pub enum FirmwareError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
-    #[error("Decode error: {0}")]
-    Decode(#[from] DecodeError),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Image too large: {0} bytes")]
+    ImageTooLarge(usize),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    #[error("Invalid offset acknowledged: {0}")]
+    InvalidOffset(u32),
+    #[error("Chunk at offset {0} failed too many times")]
+    TooManyRetries(u32),
+    #[error("Image rejected by peer")]
+    Rejected,
 }
 
 #[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Blob too large: {0} bytes")]
+    BlobTooLarge(usize),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    /// The peer does not hold the base the patch applies to, so the whole blob must be sent
+    #[error("Peer does not hold the base of the patch")]
+    BaseMismatch,
+    #[error("Patch rejected by peer")]
+    Rejected,
+}
+
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Payload too long for a transaction: {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("Too many messages in one transaction")]
+    TooManyMessages,
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    /// The peer had lost messages of the transaction, or discarded it, and applied none of them
+    #[error("Transaction rejected by peer")]
+    Rejected,
+}
+
+#[derive(Debug, Error)]
+pub enum BridgeError {
+    #[error("Receiving from {link} failed: {source}")]
+    Receive { link: LinkId, source: io::Error },
+    #[error("Sending to {link} failed: {source}")]
+    Send { link: LinkId, source: io::Error },
+}
+
+#[derive(Debug, Error)]
+pub enum ChannelError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Nothing received within the timeout")]
+    Timeout,
+    #[error("The peer granted no credit within the timeout, after {sent} bytes were sent")]
+    Stalled { sent: usize },
+    #[error("The multiplexer stopped receiving")]
+    Closed,
+}
+
+#[derive(Debug, Error)]
+pub enum HubError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("No reply within the timeout")]
+    Timeout,
+    #[error("The hub stopped receiving")]
+    Closed,
+}
+
+#[derive(Debug, Error)]
+pub enum RouterError {
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Delivering to {route} failed: {source}")]
+    Deliver { route: RouteId, source: io::Error },
+    #[error("Delivering to the fallback sink failed: {0}")]
+    Fallback(io::Error),
+}
+
+#[cfg(feature = "mqtt")]
+#[derive(Debug, Error)]
+pub enum MqttError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("MQTT client error: {0}")]
+    Client(#[from] rumqttc::ClientError),
+    #[error("MQTT connection error: {0}")]
+    Connection(#[from] Box<rumqttc::ConnectionError>),
+}
+
+#[cfg(feature = "postcard")]
+#[derive(Debug, Error)]
+pub enum PostcardError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    #[error("Postcard error: {0}")]
+    Postcard(#[from] postcard::Error),
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Error)]
+pub enum ProtobufError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Receive error: {0}")]
+    Receive(#[from] ReceiveError),
+    #[error("Unexpected message type: {0}")]
+    UnexpectedMessage(u16),
+    #[error("Protobuf decode error: {0}")]
+    Decode(#[from] prost::DecodeError),
+}
+
+/// Why a payload could not be encoded
+///
+/// Sending a message that fails to encode fails with [`io::ErrorKind::InvalidInput`].
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum EncodeError {
+    /// An array with more elements than its u16 count can hold
+    #[error("Array of {0} elements is too long to encode")]
+    ArrayTooLong(usize),
+    /// A nested struct whose fields take more bytes than its u16 length can hold
+    #[error("Struct of {0} bytes is too long to encode")]
+    StructTooLong(usize),
+    /// A TLV field whose value takes more bytes than its u16 length can hold
+    #[error("Field of {0} bytes is too long to encode")]
+    FieldTooLong(usize),
+}
+
+impl From<EncodeError> for io::Error {
+    fn from(error: EncodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Why a frame or payload could not be decoded
+///
+/// New variants may be added in minor releases, so matches outside this crate need a wildcard
+/// arm.
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum DecodeError {
     #[error("Invalid message type: {0}")]
     InvalidMessageType(u16),
@@ -41,4 +370,54 @@ pub enum DecodeError {
     InvalidUtf8(#[from] FromUtf8Error),
     #[error("Invalid enum value: {0}")]
     InvalidEnumValue(u8),
+    #[error("Invalid bool value: {0}")]
+    InvalidBool(u8),
+    #[error("Invalid presence flag: {0}")]
+    InvalidPresenceFlag(u8),
+    #[error("Missing field with tag {0}")]
+    MissingField(u8),
+    #[error("Invalid varint")]
+    InvalidVarint,
+    #[error("Invalid length field: {0}")]
+    InvalidLength(u16),
+    #[error("Payload truncated: expected {expected} more bytes, found {actual}")]
+    TruncatedPayload { expected: usize, actual: usize },
+    #[error("Invalid frame trailer: {0:#04x}")]
+    InvalidTrailer(u8),
+    #[error("Checksum mismatch: expected {expected:#010x}, received {received:#010x}")]
+    ChecksumMismatch { expected: u32, received: u32 },
+    #[error("Header checksum mismatch: expected {expected:#06x}, received {received:#06x}")]
+    HeaderChecksumMismatch { expected: u16, received: u16 },
+    #[error("Unsupported frame version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unsupported frame flags: {0:#04x}")]
+    UnsupportedFlags(u8),
+    #[error("Invalid padding")]
+    InvalidPadding,
+    /// A frame's payload was longer than the fixed buffer of a
+    /// [`FixedDecoder`](crate::FixedDecoder)
+    #[error("Payload of {length} bytes exceeds the buffer of {capacity}")]
+    PayloadTooLong { length: usize, capacity: usize },
+    /// A [`LazyMessage`](crate::LazyMessage) was asked for a field its message type does not
+    /// have
+    #[error("No field named {0}")]
+    UnknownField(String),
+    /// A [`LazyMessage`](crate::LazyMessage) field was requested as a type other than its own
+    #[error("Field {0} requested as another type")]
+    FieldTypeMismatch(String),
+    /// A [`Json`](crate::message_types::Json) message whose text is not valid JSON
+    #[cfg(feature = "json")]
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+}
+
+impl DecodeError {
+    /// Returns whether the frame failed a checksum, as when it was corrupted on the wire
+    #[must_use]
+    pub fn is_checksum_mismatch(&self) -> bool {
+        matches!(
+            self,
+            DecodeError::ChecksumMismatch { .. } | DecodeError::HeaderChecksumMismatch { .. }
+        )
+    }
 }
@@ -0,0 +1,44 @@
+use crate::codec::Endianness;
+use crate::errors::ReceiveError;
+use crate::message::Message;
+use crate::watchdog::LinkStatus;
+use std::io;
+
+/// Hooks for the traffic and lifecycle events of a [`SerialManager`](crate::SerialManager) or
+/// [`DatagramManager`](crate::DatagramManager), for metrics, logging or UI indicators that
+/// would otherwise wrap every call.
+///
+/// All methods have empty default implementations, so an implementation only needs the events
+/// it is interested in. Unlike an [`Observer`](crate::Observer), which sees frames as they
+/// appear on the wire, these see message types and payloads.
+pub trait SerialManagerEvents {
+    /// Called after a frame has been sent
+    fn on_send(&mut self, _message_type: u16, _payload: &[u8]) {}
+
+    /// Called after a frame has been received, before its payload is decoded into a message
+    fn on_receive(&mut self, _message_type: u16, _payload: &[u8]) {}
+
+    /// Called when receiving fails, other than a read timing out or that would block
+    fn on_error(&mut self, _error: &ReceiveError) {}
+
+    /// Called when writing a frame to the connection fails
+    fn on_send_error(&mut self, _error: &io::Error) {}
+
+    /// Called when the manager's [`Watchdog`](crate::Watchdog) finds the link has gone down or
+    /// come back up
+    fn on_state_change(&mut self, _status: LinkStatus) {}
+
+    /// Called once the line has been quiet for the idle time of the manager's
+    /// [`WakePreamble`](crate::WakePreamble), such as to let the device go to sleep
+    fn on_idle(&mut self) {}
+}
+
+/// Decodes a payload passed to an event handler, as [`Message::Unknown`] if it does not decode
+pub(crate) fn decode_message(message_type: u16, payload: &[u8], endianness: Endianness) -> Message {
+    Message::from_bytes_with(message_type, payload.to_vec(), endianness).unwrap_or_else(|_| {
+        Message::Unknown {
+            message_type,
+            data: payload.to_vec(),
+        }
+    })
+}
@@ -0,0 +1,212 @@
+use crate::codec::Endianness;
+use crate::errors::ReceiveError;
+use crate::events::{decode_message, SerialManagerEvents};
+use crate::message::Message;
+use crate::time::SystemTime;
+use crate::watchdog::LinkStatus;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Something that happened on a link, as seen by a [`Subscription`] to a [`Feed`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedEvent {
+    /// A message was sent
+    Sent { message: Message, at: SystemTime },
+    /// A message was received, decoded as [`Message::Unknown`] if it could not be
+    Received { message: Message, at: SystemTime },
+    /// Receiving failed, other than a read timing out, with the error displayed
+    ReceiveError(String),
+    /// Writing a frame failed, with the error displayed
+    SendError(String),
+    /// The manager's [`Watchdog`](crate::Watchdog) found the link has gone down or come back up
+    LinkStatus(LinkStatus),
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    sender: SyncSender<FeedEvent>,
+    lagged: Arc<AtomicU64>,
+}
+
+/// A live feed of the messages and events of a link, which any number of consumers can
+/// subscribe to, such as a diagnostics panel beside the code that receives
+///
+/// `Feed` implements [`SerialManagerEvents`], so a clone of it can be registered on a
+/// [`SerialManager`](crate::SerialManager) or [`DatagramManager`](crate::DatagramManager),
+/// while the original hands out subscriptions from any thread:
+///
+/// ```no_run
+/// # use generic_serial_protocol::{Feed, FeedEvent, SerialManager};
+/// # let stream = std::io::Cursor::new(Vec::new());
+/// let feed = Feed::new();
+/// let mut manager = SerialManager::new(stream);
+/// manager.set_events(feed.clone());
+///
+/// let panel = feed.subscribe();
+/// std::thread::spawn(move || {
+///     for event in panel.iter() {
+///         if let FeedEvent::Received { message, .. } = event {
+///             println!("{message}");
+///         }
+///     }
+/// });
+/// loop {
+///     manager.receive().unwrap();
+/// }
+/// ```
+///
+/// Each subscription has its own queue, so consumers see every event in order at their own
+/// pace. The link never waits for a consumer: once a subscription's queue is full, events are
+/// dropped for it and counted in [`Subscription::lagged`]. Subscriptions that are dropped are
+/// forgotten at the next event.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    capacity: usize,
+    endianness: Endianness,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Feed {
+    /// Creates a feed queueing up to 1024 events for each subscription
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            subscribers: Arc::default(),
+            capacity: 1024,
+            endianness: Endianness::Little,
+        }
+    }
+
+    /// Queues up to `capacity` events, at least 1, for each subscription made afterwards
+    #[must_use]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Decodes payloads with numbers in the given byte order, which should match the manager's
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Subscribes to the events from now on
+    #[must_use]
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(self.capacity);
+        let lagged = Arc::new(AtomicU64::new(0));
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Subscriber {
+                sender,
+                lagged: Arc::clone(&lagged),
+            });
+        Subscription { receiver, lagged }
+    }
+
+    /// Returns the number of subscriptions, including ones dropped since the last event
+    #[must_use]
+    pub fn subscribers(&self) -> usize {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Passes an event to every subscription
+    pub fn publish(&self, event: &FeedEvent) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(
+                |subscriber| match subscriber.sender.try_send(event.clone()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        subscriber.lagged.fetch_add(1, Ordering::Relaxed);
+                        true
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                },
+            );
+    }
+}
+
+impl SerialManagerEvents for Feed {
+    fn on_send(&mut self, message_type: u16, payload: &[u8]) {
+        self.publish(&FeedEvent::Sent {
+            message: decode_message(message_type, payload, self.endianness),
+            at: SystemTime::now(),
+        });
+    }
+
+    fn on_receive(&mut self, message_type: u16, payload: &[u8]) {
+        self.publish(&FeedEvent::Received {
+            message: decode_message(message_type, payload, self.endianness),
+            at: SystemTime::now(),
+        });
+    }
+
+    fn on_error(&mut self, error: &ReceiveError) {
+        self.publish(&FeedEvent::ReceiveError(error.to_string()));
+    }
+
+    fn on_send_error(&mut self, error: &io::Error) {
+        self.publish(&FeedEvent::SendError(error.to_string()));
+    }
+
+    fn on_state_change(&mut self, status: LinkStatus) {
+        self.publish(&FeedEvent::LinkStatus(status));
+    }
+}
+
+/// A consumer of a [`Feed`], returned by [`Feed::subscribe`]
+///
+/// Receiving fails once every clone of the feed has been dropped and the queue is empty.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: Receiver<FeedEvent>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Waits for the next event, returning `None` once the feed is gone
+    #[must_use]
+    pub fn recv(&self) -> Option<FeedEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Waits up to `timeout` for the next event
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<FeedEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Returns the next event if one is queued, such as from a GUI's frame loop
+    pub fn try_recv(&self) -> Result<FeedEvent, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Returns an iterator waiting for each event, ending once the feed is gone
+    pub fn iter(&self) -> impl Iterator<Item = FeedEvent> + '_ {
+        self.receiver.iter()
+    }
+
+    /// Returns the number of events dropped because the queue was full
+    #[must_use]
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use super::*;
+use crate::message::message_types;
+use crate::serial_manager::SerialManager;
+use crate::test_util::stream_pair;
+use std::thread;
+
+fn message(event: FeedEvent) -> (bool, Message) {
+    match event {
+        FeedEvent::Sent { message, .. } => (true, message),
+        FeedEvent::Received { message, .. } => (false, message),
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[test]
+fn test_every_subscription_sees_every_event() {
+    let (stream1, stream2) = stream_pair();
+    let mut peer = SerialManager::new(stream1);
+    let feed = Feed::new();
+    let mut manager = SerialManager::new(stream2);
+    manager.set_events(feed.clone());
+    let first = feed.subscribe();
+    let second = feed.subscribe();
+
+    let u8_message = Message::U8(message_types::U8 { num: 1 });
+    let u16_message = Message::U16(message_types::U16 { num: 2 });
+    manager.send(u8_message.clone()).unwrap();
+    peer.send(u16_message.clone()).unwrap();
+    manager.receive().unwrap();
+    peer.send_raw(0x1234, &[0xAB]).unwrap();
+    assert!(manager.receive().is_err());
+
+    let consumer = thread::spawn(move || second.iter().collect::<Vec<_>>());
+    assert_eq!(message(first.recv().unwrap()), (true, u8_message.clone()));
+    assert_eq!(message(first.recv().unwrap()), (false, u16_message.clone()));
+    let unknown = Message::Unknown {
+        message_type: 0x1234,
+        data: vec![0xAB],
+    };
+    assert_eq!(message(first.recv().unwrap()), (false, unknown.clone()));
+    assert!(matches!(
+        first.recv().unwrap(),
+        FeedEvent::ReceiveError(error) if error.contains("Invalid message type: 4660")
+    ));
+    assert!(first.try_recv().is_err());
+
+    // The second consumer sees the same events, until the feed is gone
+    drop(manager);
+    drop(feed);
+    let events = consumer.join().unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(message(events[0].clone()), (true, u8_message));
+    assert_eq!(message(events[1].clone()), (false, u16_message));
+    assert_eq!(message(events[2].clone()), (false, unknown));
+    assert!(first.recv().is_none());
+}
+
+#[test]
+fn test_slow_subscriptions_lag() {
+    let feed = Feed::new().with_capacity(2);
+    let slow = feed.subscribe();
+    let dropped = feed.subscribe();
+    drop(dropped);
+    assert_eq!(feed.subscribers(), 2);
+
+    for status in [LinkStatus::Down, LinkStatus::Up, LinkStatus::Down] {
+        feed.publish(&FeedEvent::LinkStatus(status));
+    }
+    assert_eq!(feed.subscribers(), 1);
+    assert_eq!(slow.lagged(), 1);
+    assert_eq!(slow.try_recv(), Ok(FeedEvent::LinkStatus(LinkStatus::Down)));
+    assert_eq!(slow.try_recv(), Ok(FeedEvent::LinkStatus(LinkStatus::Up)));
+    assert_eq!(slow.try_recv(), Err(TryRecvError::Empty));
+}
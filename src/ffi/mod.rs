@@ -0,0 +1,129 @@
+//! C-compatible bindings to the frame encoder and decoder.
+//!
+//! Enabled with the `ffi` feature. A matching header is kept in `include/gsp_ffi.h` and can be
+//! regenerated with `cbindgen --config cbindgen.toml --output include/gsp_ffi.h`. To link the
+//! library from C or C++, build it as a static library with
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use crate::codec::{encode_frame, Decoder, DecoderEvent, Frame, MAX_PAYLOAD_LENGTH};
+use std::ptr;
+use std::slice;
+
+/// An opaque frame decoder handle
+pub struct GspDecoder {
+    decoder: Decoder,
+    frame: Option<Frame>,
+}
+
+/// Encodes a frame with an already encoded payload into `out`.
+///
+/// Returns the length of the frame, or 0 if `out_capacity` is too small or the payload is longer
+/// than the maximum payload length.
+///
+/// # Safety
+///
+/// `payload` must point to `payload_length` readable bytes, and `out` must point to
+/// `out_capacity` writable bytes. Either may be null if its length is 0.
+#[no_mangle]
+pub unsafe extern "C" fn gsp_encode_frame(
+    message_type: u16,
+    payload: *const u8,
+    payload_length: usize,
+    out: *mut u8,
+    out_capacity: usize,
+) -> usize {
+    if payload_length > MAX_PAYLOAD_LENGTH {
+        return 0;
+    }
+    let payload = byte_slice(payload, payload_length);
+    let frame = encode_frame(message_type, payload);
+    if frame.len() > out_capacity {
+        return 0;
+    }
+    ptr::copy_nonoverlapping(frame.as_ptr(), out, frame.len());
+    frame.len()
+}
+
+/// Creates a new decoder, which must be freed with `gsp_decoder_free`
+#[no_mangle]
+pub extern "C" fn gsp_decoder_new() -> *mut GspDecoder {
+    Box::into_raw(Box::new(GspDecoder {
+        decoder: Decoder::new(),
+        frame: None,
+    }))
+}
+
+/// Frees a decoder created with `gsp_decoder_new`
+///
+/// # Safety
+///
+/// `decoder` must have been returned by `gsp_decoder_new` and not yet freed, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn gsp_decoder_free(decoder: *mut GspDecoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+/// Pushes received bytes into the decoder, stopping early if a frame is completed.
+///
+/// Returns the number of bytes consumed. If it is less than `length`, a frame is ready to be
+/// read with `gsp_decoder_frame`, and the remaining bytes should be pushed again afterwards. Any
+/// previously completed frame is discarded.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder, and `bytes` must point to `length` readable bytes. `bytes`
+/// may be null if `length` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn gsp_decoder_push(
+    decoder: *mut GspDecoder,
+    bytes: *const u8,
+    length: usize,
+) -> usize {
+    let decoder = &mut *decoder;
+    decoder.frame = None;
+    for (i, &byte) in byte_slice(bytes, length).iter().enumerate() {
+        if let Some(DecoderEvent::Frame(frame)) = decoder.decoder.push(byte) {
+            decoder.frame = Some(frame);
+            return i + 1;
+        }
+    }
+    length
+}
+
+/// Gets the frame completed by the last call to `gsp_decoder_push`.
+///
+/// Returns false if no frame was completed. Otherwise the message type and payload are written to
+/// the out parameters, and the payload stays valid until the next call to `gsp_decoder_push` or
+/// `gsp_decoder_free`.
+///
+/// # Safety
+///
+/// `decoder` must be a valid decoder, and the out parameters must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn gsp_decoder_frame(
+    decoder: *const GspDecoder,
+    message_type: *mut u16,
+    payload: *mut *const u8,
+    payload_length: *mut usize,
+) -> bool {
+    let Some(frame) = &(*decoder).frame else {
+        return false;
+    };
+    *message_type = frame.message_type;
+    *payload = frame.payload.as_ptr();
+    *payload_length = frame.payload.len();
+    true
+}
+
+unsafe fn byte_slice<'a>(bytes: *const u8, length: usize) -> &'a [u8] {
+    if length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(bytes, length)
+    }
+}
+
+#[cfg(test)]
+mod tests;
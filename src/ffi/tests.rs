@@ -0,0 +1,48 @@
+use super::*;
+
+#[test]
+fn test_encode_frame() {
+    let payload = [0x58, 0x01];
+    let mut out = [0u8; 16];
+
+    let length =
+        unsafe { gsp_encode_frame(0, payload.as_ptr(), payload.len(), out.as_mut_ptr(), 16) };
+    assert_eq!(&out[..length], encode_frame(0, &payload).as_slice());
+
+    // Too small for the escaped frame
+    let length =
+        unsafe { gsp_encode_frame(0, payload.as_ptr(), payload.len(), out.as_mut_ptr(), 7) };
+    assert_eq!(length, 0);
+}
+
+#[test]
+fn test_decoder_push() {
+    let mut bytes = vec![0x13, 0x37];
+    bytes.extend(encode_frame(1, &[0x57]));
+    bytes.extend(encode_frame(4, &[]));
+
+    let decoder = gsp_decoder_new();
+    let mut message_type = 0;
+    let mut payload = ptr::null();
+    let mut payload_length = 0;
+    let mut frames = Vec::new();
+
+    let mut remaining = bytes.as_slice();
+    while !remaining.is_empty() {
+        unsafe {
+            let consumed = gsp_decoder_push(decoder, remaining.as_ptr(), remaining.len());
+            remaining = &remaining[consumed..];
+            if gsp_decoder_frame(
+                decoder,
+                &raw mut message_type,
+                &raw mut payload,
+                &raw mut payload_length,
+            ) {
+                frames.push((message_type, byte_slice(payload, payload_length).to_vec()));
+            }
+        }
+    }
+    unsafe { gsp_decoder_free(decoder) };
+
+    assert_eq!(frames, vec![(1, vec![0x57]), (4, vec![])]);
+}
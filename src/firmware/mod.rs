@@ -0,0 +1,233 @@
+use crate::codec::MAX_PAYLOAD_LENGTH;
+use crate::errors::FirmwareError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use message_types::{FirmwareAck, FirmwareBegin, FirmwareChunk, FirmwareCommit, Status};
+
+/// The number of image bytes sent in each chunk unless configured otherwise
+pub const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// The most image bytes a chunk can carry, leaving room for its offset and CRC
+pub const MAX_CHUNK_SIZE: usize = MAX_PAYLOAD_LENGTH - 8;
+
+/// Sends a firmware image to a peer, in chunks that are each checked with a CRC.
+///
+/// The exchange is:
+/// 1. `FirmwareBegin` with the size and CRC of the image. The peer replies with a `FirmwareAck`
+///    holding the offset to continue from, which is only non-zero if it already holds part of the
+///    same image from an interrupted update.
+/// 2. A `FirmwareChunk` from that offset, which the peer acknowledges with the next offset it
+///    expects, until it holds the whole image. A chunk that fails its CRC is acknowledged with its
+///    own offset, and sent again.
+/// 3. `FirmwareCommit` with the CRC of the image. The peer checks the image it received, and
+///    replies with `Status::Ok` once it has committed it or `Status::Error` if it was rejected.
+///    It may send `Status::Pending` while it works.
+///
+/// All CRCs are CRC-32 (IEEE), as computed by [`crc32`].
+pub struct FirmwareUpdate<'a> {
+    image: &'a [u8],
+    chunk_size: usize,
+    max_retries: u32,
+    progress: Option<Box<dyn FnMut(usize, usize) + 'a>>,
+}
+
+impl<'a> FirmwareUpdate<'a> {
+    /// Creates an update sending `image` in chunks of [`DEFAULT_CHUNK_SIZE`] bytes, retrying each
+    /// chunk up to three times
+    #[must_use]
+    pub fn new(image: &'a [u8]) -> Self {
+        Self {
+            image,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            max_retries: 3,
+            progress: None,
+        }
+    }
+
+    /// Sets the number of image bytes sent in each chunk
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero or larger than [`MAX_CHUNK_SIZE`].
+    #[must_use]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(
+            (1..=MAX_CHUNK_SIZE).contains(&chunk_size),
+            "invalid chunk size: {chunk_size}"
+        );
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets how many times a chunk is sent again before giving up
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Registers a callback that is passed the number of bytes the peer holds and the image size
+    /// each time this changes
+    #[must_use]
+    pub fn on_progress(mut self, progress: impl FnMut(usize, usize) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Sends the image, returning once the peer has committed it
+    pub fn run<T>(mut self, manager: &mut SerialManager<T>) -> Result<(), FirmwareError>
+    where
+        T: Transport,
+    {
+        let size = u32::try_from(self.image.len())
+            .map_err(|_| FirmwareError::ImageTooLarge(self.image.len()))?;
+        let crc = crc32(self.image);
+
+        manager.send(Message::FirmwareBegin(FirmwareBegin { size, crc }))?;
+        let mut offset = Self::receive_ack(manager, size)?;
+        self.report_progress(offset);
+
+        let mut retries = 0;
+        while offset < size {
+            let start = offset as usize;
+            let end = self.image.len().min(start + self.chunk_size);
+            let data = self.image[start..end].to_vec();
+            manager.send(Message::FirmwareChunk(FirmwareChunk {
+                offset,
+                crc: crc32(&data),
+                data,
+            }))?;
+
+            let next = Self::receive_ack(manager, size)?;
+            if next == offset {
+                retries += 1;
+                if retries > self.max_retries {
+                    return Err(FirmwareError::TooManyRetries(offset));
+                }
+            } else {
+                retries = 0;
+                offset = next;
+                self.report_progress(offset);
+            }
+        }
+
+        manager.send(Message::FirmwareCommit(FirmwareCommit { crc }))?;
+        loop {
+            match manager.receive()? {
+                Message::Status(Status::Ok) => return Ok(()),
+                Message::Status(Status::Pending) => (),
+                Message::Status(Status::Error) => return Err(FirmwareError::Rejected),
+                message => return Err(FirmwareError::UnexpectedMessage(message.message_type())),
+            }
+        }
+    }
+
+    /// Receives the offset the peer expects next, which must be within the image
+    fn receive_ack<T>(manager: &mut SerialManager<T>, size: u32) -> Result<u32, FirmwareError>
+    where
+        T: Transport,
+    {
+        match manager.receive()? {
+            Message::FirmwareAck(FirmwareAck { offset }) if offset <= size => Ok(offset),
+            Message::FirmwareAck(FirmwareAck { offset }) => {
+                Err(FirmwareError::InvalidOffset(offset))
+            }
+            message => Err(FirmwareError::UnexpectedMessage(message.message_type())),
+        }
+    }
+
+    fn report_progress(&mut self, offset: u32) {
+        if let Some(progress) = &mut self.progress {
+            progress(offset as usize, self.image.len());
+        }
+    }
+}
+
+/// The receiving side of a [`FirmwareUpdate`], for peers implemented in Rust.
+///
+/// Received messages are passed to [`handle`](Self::handle), which returns the reply to send.
+/// A partially received image is kept, so that an interrupted update of the same image resumes
+/// where it left off.
+#[derive(Debug, Default)]
+pub struct FirmwareReceiver {
+    size: u32,
+    crc: u32,
+    received: Vec<u8>,
+    committed: Option<Vec<u8>>,
+}
+
+impl FirmwareReceiver {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles a firmware message, returning the reply, or `None` for other messages
+    pub fn handle(&mut self, message: &Message) -> Option<Message> {
+        let reply = match message {
+            Message::FirmwareBegin(begin) => {
+                if (begin.size, begin.crc) != (self.size, self.crc) {
+                    self.size = begin.size;
+                    self.crc = begin.crc;
+                    self.received.clear();
+                }
+                self.ack()
+            }
+            Message::FirmwareChunk(chunk) => {
+                let end = self.received.len() + chunk.data.len();
+                if usize::try_from(chunk.offset).ok() == Some(self.received.len())
+                    && u32::try_from(end).is_ok_and(|end| end <= self.size)
+                    && crc32(&chunk.data) == chunk.crc
+                {
+                    self.received.extend(&chunk.data);
+                }
+                self.ack()
+            }
+            Message::FirmwareCommit(commit) => {
+                let complete = u32::try_from(self.received.len()) == Ok(self.size);
+                if complete && commit.crc == self.crc && crc32(&self.received) == self.crc {
+                    self.committed = Some(std::mem::take(&mut self.received));
+                    self.size = 0;
+                    self.crc = 0;
+                    Message::Status(Status::Ok)
+                } else {
+                    Message::Status(Status::Error)
+                }
+            }
+            _ => return None,
+        };
+        Some(reply)
+    }
+
+    /// Returns the last image that was committed
+    #[must_use]
+    pub fn image(&self) -> Option<&[u8]> {
+        self.committed.as_deref()
+    }
+
+    fn ack(&self) -> Message {
+        Message::FirmwareAck(FirmwareAck {
+            offset: u32::try_from(self.received.len()).unwrap(),
+        })
+    }
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of `bytes`, as used for firmware images and chunks
+#[must_use]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+use super::*;
+use crate::errors::ReceiveError;
+use crate::test_util::{stream_pair, TestStream};
+use std::thread::{self, JoinHandle};
+
+fn image(size: usize) -> Vec<u8> {
+    (0..=u8::MAX).cycle().step_by(7).take(size).collect()
+}
+
+/// Runs `receiver` on the other end of the returned connection until it has handled `limit`
+/// messages or the connection is closed
+fn spawn_peer(
+    mut receiver: FirmwareReceiver,
+    limit: usize,
+) -> (SerialManager<TestStream>, JoinHandle<FirmwareReceiver>) {
+    let (stream1, stream2) = stream_pair();
+    let peer = thread::spawn(move || {
+        let mut manager = SerialManager::new(stream2);
+        for _ in 0..limit {
+            let Ok(message) = manager.receive() else {
+                break;
+            };
+            if let Some(reply) = receiver.handle(&message) {
+                manager.send(reply).unwrap();
+            }
+        }
+        receiver
+    });
+    (SerialManager::new(stream1), peer)
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+}
+
+#[test]
+fn test_update() {
+    let image = image(1000);
+    let (mut manager, peer) = spawn_peer(FirmwareReceiver::new(), usize::MAX);
+
+    let mut progress = Vec::new();
+    FirmwareUpdate::new(&image)
+        .with_chunk_size(300)
+        .on_progress(|done, total| progress.push((done, total)))
+        .run(&mut manager)
+        .unwrap();
+    drop(manager);
+
+    assert_eq!(
+        progress,
+        vec![
+            (0, 1000),
+            (300, 1000),
+            (600, 1000),
+            (900, 1000),
+            (1000, 1000)
+        ]
+    );
+    assert_eq!(peer.join().unwrap().image(), Some(image.as_slice()));
+}
+
+#[test]
+fn test_interrupted_update_resumes() {
+    let image = image(1000);
+
+    // The peer goes away after the begin message and two chunks, which fails either sending the
+    // third chunk or receiving its ack depending on how quickly the connection is closed
+    let (mut manager, peer) = spawn_peer(FirmwareReceiver::new(), 3);
+    let result = FirmwareUpdate::new(&image)
+        .with_chunk_size(300)
+        .run(&mut manager);
+    assert!(matches!(
+        result,
+        Err(FirmwareError::Io(_) | FirmwareError::Receive(ReceiveError::Io(_)))
+    ));
+    let receiver = peer.join().unwrap();
+
+    let (mut manager, peer) = spawn_peer(receiver, usize::MAX);
+    let mut progress = Vec::new();
+    FirmwareUpdate::new(&image)
+        .with_chunk_size(300)
+        .on_progress(|done, _| progress.push(done))
+        .run(&mut manager)
+        .unwrap();
+    drop(manager);
+
+    assert_eq!(progress, vec![600, 900, 1000]);
+    assert_eq!(peer.join().unwrap().image(), Some(image.as_slice()));
+}
+
+#[test]
+fn test_different_image_starts_over() {
+    let mut receiver = FirmwareReceiver::new();
+    let begin = |size, crc| Message::FirmwareBegin(FirmwareBegin { size, crc });
+    let chunk = Message::FirmwareChunk(FirmwareChunk {
+        offset: 0,
+        crc: crc32(&[1, 2]),
+        data: vec![1, 2],
+    });
+
+    receiver.handle(&begin(4, 0x1234));
+    receiver.handle(&chunk);
+    assert_eq!(
+        receiver.handle(&begin(4, 0x1234)),
+        Some(Message::FirmwareAck(FirmwareAck { offset: 2 }))
+    );
+    assert_eq!(
+        receiver.handle(&begin(4, 0x5678)),
+        Some(Message::FirmwareAck(FirmwareAck { offset: 0 }))
+    );
+}
+
+#[test]
+fn test_corrupt_chunk_is_not_acknowledged() {
+    let mut receiver = FirmwareReceiver::new();
+    receiver.handle(&Message::FirmwareBegin(FirmwareBegin { size: 2, crc: 0 }));
+    let reply = receiver.handle(&Message::FirmwareChunk(FirmwareChunk {
+        offset: 0,
+        crc: crc32(&[1, 2]) ^ 1,
+        data: vec![1, 2],
+    }));
+    assert_eq!(reply, Some(Message::FirmwareAck(FirmwareAck { offset: 0 })));
+}
+
+#[test]
+fn test_too_many_retries() {
+    let (stream1, stream2) = stream_pair();
+    // A peer that never accepts a chunk
+    let peer = thread::spawn(move || {
+        let mut manager = SerialManager::new(stream2);
+        while manager.receive().is_ok() {
+            let ack = Message::FirmwareAck(FirmwareAck { offset: 0 });
+            if manager.send(ack).is_err() {
+                break;
+            }
+        }
+    });
+
+    let image = image(10);
+    let result = FirmwareUpdate::new(&image)
+        .with_max_retries(2)
+        .run(&mut SerialManager::new(stream1));
+    assert!(matches!(result, Err(FirmwareError::TooManyRetries(0))));
+    peer.join().unwrap();
+}
+
+#[test]
+fn test_rejected_commit() {
+    let mut receiver = FirmwareReceiver::new();
+    receiver.handle(&Message::FirmwareBegin(FirmwareBegin { size: 2, crc: 0 }));
+    receiver.handle(&Message::FirmwareChunk(FirmwareChunk {
+        offset: 0,
+        crc: crc32(&[1, 2]),
+        data: vec![1, 2],
+    }));
+    // The image does not match the CRC it began with
+    assert_eq!(
+        receiver.handle(&Message::FirmwareCommit(FirmwareCommit { crc: 0 })),
+        Some(Message::Status(Status::Error))
+    );
+    assert_eq!(receiver.image(), None);
+}
+
+#[test]
+fn test_other_messages_are_ignored() {
+    let mut receiver = FirmwareReceiver::new();
+    assert_eq!(
+        receiver.handle(&Message::NoOp(message_types::NoOp {})),
+        None
+    );
+}
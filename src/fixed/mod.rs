@@ -0,0 +1,268 @@
+use crate::codec::{Endianness, ESCAPE_BYTE, START_BYTE, XOR_BYTE};
+use crate::errors::{DecodeError, ReceiveError};
+use crate::message::Message;
+use crate::payload::{decode_payload, MessageType};
+use std::io::{self, Read};
+
+/// The most bytes read from the connection at once
+const READ_BUFFER_SIZE: usize = 64;
+
+/// The number of bytes of the length field and message type after the start byte
+const HEADER_LENGTH: usize = 4;
+
+/// A received message whose payload is borrowed from the buffer of a [`FixedDecoder`] or
+/// [`FixedReceiver`], rather than decoded into a [`Message`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    message_type: u16,
+    payload: &'a [u8],
+    endianness: Endianness,
+}
+
+impl<'a> MessageRef<'a> {
+    #[must_use]
+    pub fn message_type(&self) -> u16 {
+        self.message_type
+    }
+
+    /// The unescaped payload
+    #[must_use]
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Decodes the payload as message type `M`, failing with
+    /// [`DecodeError::InvalidMessageType`] if the message is of another type
+    ///
+    /// Payloads with only fixed-size fields are decoded without allocating.
+    pub fn decode<M: MessageType>(&self) -> Result<M, DecodeError> {
+        if self.message_type != M::ID {
+            return Err(DecodeError::InvalidMessageType(self.message_type));
+        }
+        decode_payload(self.payload, self.endianness)
+    }
+
+    /// Decodes the payload into a [`Message`], which allocates
+    pub fn to_message(&self) -> Result<Message, DecodeError> {
+        Message::from_bytes_with(self.message_type, self.payload.to_vec(), self.endianness)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitingForStart,
+    Header,
+    Payload,
+}
+
+/// A sans-IO frame decoder that never allocates, holding payloads of up to `N` bytes in a fixed
+/// buffer
+///
+/// Frames use the native framing, resyncing like [`Decoder`](crate::Decoder): bytes before a
+/// start byte are skipped, and a start byte in the middle of a frame discards it. A frame whose
+/// payload would not fit in the buffer is discarded with [`DecodeError::PayloadTooLong`].
+#[derive(Debug, Clone)]
+pub struct FixedDecoder<const N: usize> {
+    state: State,
+    escaped: bool,
+    header: [u8; HEADER_LENGTH],
+    header_length: usize,
+    message_type: u16,
+    payload_length: usize,
+    payload: [u8; N],
+    received: usize,
+    endianness: Endianness,
+}
+
+impl<const N: usize> Default for FixedDecoder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FixedDecoder<N> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: State::WaitingForStart,
+            escaped: false,
+            header: [0; HEADER_LENGTH],
+            header_length: 0,
+            message_type: 0,
+            payload_length: 0,
+            payload: [0; N],
+            received: 0,
+            endianness: Endianness::Little,
+        }
+    }
+
+    /// Reads the frame header and payload numbers in the given byte order
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Pushes a received byte, returning the message it completed, borrowed from the buffer
+    /// until the next byte is pushed, or the reason a frame was discarded
+    pub fn push(&mut self, byte: u8) -> Option<Result<MessageRef<'_>, DecodeError>> {
+        self.push_byte(byte)
+            .map(|result| result.map(|()| self.message()))
+    }
+
+    /// Pushes a byte, returning whether it completed a frame or caused one to be discarded
+    fn push_byte(&mut self, byte: u8) -> Option<Result<(), DecodeError>> {
+        if byte == START_BYTE {
+            self.state = State::Header;
+            self.escaped = false;
+            self.header_length = 0;
+            return None;
+        }
+        if self.state == State::WaitingForStart {
+            return None;
+        }
+        let byte = if self.escaped {
+            self.escaped = false;
+            byte ^ XOR_BYTE
+        } else if byte == ESCAPE_BYTE {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+
+        if self.state == State::Header {
+            self.header[self.header_length] = byte;
+            self.header_length += 1;
+            if self.header_length < HEADER_LENGTH {
+                return None;
+            }
+            let header = self.header;
+            let length = self.endianness.u16_from_bytes([header[0], header[1]]);
+            self.message_type = self.endianness.u16_from_bytes([header[2], header[3]]);
+            let Some(payload_length) = usize::from(length).checked_sub(2) else {
+                self.state = State::WaitingForStart;
+                return Some(Err(DecodeError::InvalidLength(length)));
+            };
+            if payload_length > N {
+                self.state = State::WaitingForStart;
+                return Some(Err(DecodeError::PayloadTooLong {
+                    length: payload_length,
+                    capacity: N,
+                }));
+            }
+            self.payload_length = payload_length;
+            self.received = 0;
+            self.state = State::Payload;
+        } else {
+            self.payload[self.received] = byte;
+            self.received += 1;
+        }
+
+        if self.received < self.payload_length {
+            return None;
+        }
+        self.state = State::WaitingForStart;
+        Some(Ok(()))
+    }
+
+    /// The message last completed
+    fn message(&self) -> MessageRef<'_> {
+        MessageRef {
+            message_type: self.message_type,
+            payload: &self.payload[..self.payload_length],
+            endianness: self.endianness,
+        }
+    }
+}
+
+/// Receives messages of up to `N` payload bytes without allocating, for hard real-time
+/// receivers where the jitter of the allocator cannot be afforded
+///
+/// Each message is returned as a [`MessageRef`] borrowing the receiver's buffer, whose payload
+/// can be decoded as a message type with only fixed-size fields without allocating either:
+///
+/// ```no_run
+/// use generic_serial_protocol::{message_types, FixedReceiver, MessageType};
+/// # let connection = std::io::Cursor::new(Vec::new());
+///
+/// let mut receiver = FixedReceiver::<_, 64>::new(connection);
+/// loop {
+///     let message = receiver.receive().unwrap();
+///     if message.message_type() == message_types::I16::ID {
+///         let reading: message_types::I16 = message.decode().unwrap();
+///         println!("{}", reading.num);
+///     }
+/// }
+/// ```
+///
+/// Only the native framing is supported, without layers. Frames that cannot be received are
+/// reported as [`ReceiveError::Decode`] without the frame's bytes, which would have to be
+/// copied into a new buffer.
+#[derive(Debug)]
+pub struct FixedReceiver<T: Read, const N: usize> {
+    connection: T,
+    decoder: FixedDecoder<N>,
+    read_buffer: [u8; READ_BUFFER_SIZE],
+    read_position: usize,
+    read_length: usize,
+}
+
+impl<T: Read, const N: usize> FixedReceiver<T, N> {
+    pub fn new(connection: T) -> Self {
+        Self {
+            connection,
+            decoder: FixedDecoder::new(),
+            read_buffer: [0; READ_BUFFER_SIZE],
+            read_position: 0,
+            read_length: 0,
+        }
+    }
+
+    /// Reads the frame header and payload numbers in the given byte order
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.decoder = self.decoder.with_endianness(endianness);
+        self
+    }
+
+    /// Receives the next message, blocking until one is complete
+    ///
+    /// A frame cut short by a read failing or timing out is completed by the next receive.
+    pub fn receive(&mut self) -> Result<MessageRef<'_>, ReceiveError> {
+        loop {
+            if self.read_position == self.read_length {
+                self.read_length = read(&mut self.connection, &mut self.read_buffer)?;
+                self.read_position = 0;
+            }
+            let byte = self.read_buffer[self.read_position];
+            self.read_position += 1;
+            match self.decoder.push_byte(byte) {
+                Some(Ok(())) => return Ok(self.decoder.message()),
+                Some(Err(source)) => {
+                    return Err(ReceiveError::Decode {
+                        source,
+                        frame: Vec::new(),
+                        offset: None,
+                    })
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// Reads at least one byte into `buffer`, retrying reads that were interrupted
+fn read(connection: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match connection.read(buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(length) => return Ok(length),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
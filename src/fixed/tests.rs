@@ -0,0 +1,152 @@
+use super::*;
+use crate::codec::encode_frame_with;
+use crate::message::message_types;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::Cursor;
+
+/// Counts the allocations made by each thread, so that a test can check it made none
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// SAFETY: every call is passed on to the system allocator
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+fn frame(message: Message) -> Vec<u8> {
+    frame_with(message, Endianness::Little)
+}
+
+fn frame_with(message: Message, endianness: Endianness) -> Vec<u8> {
+    let message_type = message.message_type();
+    encode_frame_with(
+        message_type,
+        &message.to_bytes_with(endianness).unwrap(),
+        endianness,
+    )
+}
+
+#[test]
+fn test_decoder_unescapes_and_resyncs() {
+    let mut decoder = FixedDecoder::<8>::new();
+    // A partial frame interrupted by a start byte, then a frame with bytes to unescape
+    let mut bytes = vec![0x01, START_BYTE, 0x06, 0x00];
+    bytes.extend(frame(Message::U32(message_types::U32 { num: 0x5842_0058 })));
+
+    let mut messages = Vec::new();
+    for byte in bytes {
+        if let Some(result) = decoder.push(byte) {
+            let message = result.unwrap();
+            messages.push((message.message_type(), message.payload().to_vec()));
+        }
+    }
+    assert_eq!(messages, [(7, vec![0x58, 0x00, 0x42, 0x58])]);
+}
+
+#[test]
+fn test_decoder_rejects_frames_that_do_not_fit() {
+    let mut decoder = FixedDecoder::<4>::new();
+    let mut bytes = frame(Message::U64(message_types::U64 { num: 1 }));
+    // A length field too short to hold the message type
+    bytes.extend([START_BYTE, 0x01, 0x00, 0x05, 0x00]);
+    bytes.extend(frame(Message::NoOp(message_types::NoOp {})));
+
+    let mut results = bytes.into_iter().filter_map(|byte| {
+        decoder
+            .push(byte)
+            .map(|result| result.map(|message| message.message_type()))
+    });
+    assert!(matches!(
+        results.next(),
+        Some(Err(DecodeError::PayloadTooLong {
+            length: 8,
+            capacity: 4
+        }))
+    ));
+    assert!(matches!(
+        results.next(),
+        Some(Err(DecodeError::InvalidLength(1)))
+    ));
+    assert!(matches!(results.next(), Some(Ok(4))));
+    assert!(results.next().is_none());
+}
+
+#[test]
+fn test_receiver_decodes_typed_messages() {
+    let mut bytes = frame_with(
+        Message::I16(message_types::I16 { num: -2 }),
+        Endianness::Big,
+    );
+    bytes.extend(frame_with(
+        Message::MyString(message_types::MyString {
+            string: "hi".to_string(),
+        }),
+        Endianness::Big,
+    ));
+    let mut receiver =
+        FixedReceiver::<_, 16>::new(Cursor::new(bytes)).with_endianness(Endianness::Big);
+
+    let message = receiver.receive().unwrap();
+    assert_eq!(message.decode().ok(), Some(message_types::I16 { num: -2 }));
+    assert!(matches!(
+        message.decode::<message_types::U16>(),
+        Err(DecodeError::InvalidMessageType(10))
+    ));
+
+    let message = receiver.receive().unwrap();
+    assert_eq!(
+        message.to_message().unwrap(),
+        Message::MyString(message_types::MyString {
+            string: "hi".to_string()
+        })
+    );
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn test_receiving_does_not_allocate() {
+    let mut bytes = Vec::new();
+    for num in 0..100 {
+        bytes.extend(frame(Message::I32(message_types::I32 { num: -num })));
+        bytes.extend(frame(Message::F64(message_types::F64 {
+            num: f64::from(num),
+        })));
+    }
+    bytes.extend(frame(Message::U64(message_types::U64 { num: 0 })));
+    let mut receiver = FixedReceiver::<_, 4>::new(Cursor::new(bytes));
+    let mut total = 0;
+
+    let before = allocations();
+    for _ in 0..100 {
+        let reading = receiver.receive().unwrap().decode::<message_types::I32>();
+        total += reading.unwrap().num;
+        let Err(ReceiveError::Decode { source, .. }) = receiver.receive() else {
+            panic!("expected the F64 not to fit");
+        };
+        assert!(matches!(source, DecodeError::PayloadTooLong { .. }));
+    }
+    assert!(receiver.receive().is_err());
+    assert_eq!(allocations(), before);
+    assert_eq!(total, -4950);
+}
@@ -0,0 +1,635 @@
+//! Human-readable renderings of messages and frames, for CLIs and debug logs.
+//!
+//! [`dump_frame`] annotates every byte of an encoded frame with its offset, its meaning and, for
+//! payload fields, the field name and decoded value. `Message` implements `Display` on a single
+//! line, like `Debug` but with bytes in hex and long byte fields and arrays shortened.
+
+use crate::codec::{
+    encode_frame, Endianness, Framing, COBS_MAX_CODE, ESCAPE_BYTE, SLIP_END, SLIP_ESC,
+    SLIP_ESC_END, SLIP_ESC_ESC, START_BYTE, XOR_BYTE,
+};
+use crate::errors::DecodeError;
+use crate::message::Message;
+use crate::payload::{take, Field, Varint};
+use crate::schema::{self, FieldType, PayloadDescriptor, StructDescriptor, StructEncoding};
+use std::fmt::{self, Write as _};
+use std::ops::Range;
+
+/// The number of bytes or array elements shown before the rest is left out of a `Message`
+/// displayed on one line
+const DISPLAY_LIMIT: usize = 16;
+
+/// The number of bytes shown on each line of a dump
+const DUMP_WIDTH: usize = 8;
+
+/// A decoded part of a payload, with the range of payload bytes it was decoded from
+struct Node {
+    label: String,
+    range: Range<usize>,
+    value: Value,
+}
+
+enum Value {
+    /// A number, bool, enum variant, string or absent optional field
+    Scalar(String),
+    Bytes(Range<usize>),
+    Array(Vec<String>),
+    Struct(&'static str, Vec<Node>),
+}
+
+/// Decodes a payload into nodes by walking its descriptor, keeping track of byte offsets
+struct Walker<'a> {
+    payload: &'a [u8],
+    position: usize,
+    endianness: Endianness,
+    /// The offset of the innermost field that failed to decode
+    failed_at: Option<usize>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(payload: &'a [u8], endianness: Endianness) -> Self {
+        Self {
+            payload,
+            position: 0,
+            endianness,
+            failed_at: None,
+        }
+    }
+
+    /// Decodes a `Field` at the current position
+    fn decode<T: Field>(&mut self) -> Result<T, DecodeError> {
+        let mut bytes = &self.payload[self.position..];
+        let before = bytes.len();
+        let value = T::decode(&mut bytes, self.endianness)?;
+        self.position += before - bytes.len();
+        Ok(value)
+    }
+
+    fn payload(&mut self, descriptor: PayloadDescriptor) -> Result<Value, DecodeError> {
+        match descriptor {
+            PayloadDescriptor::Struct(descriptor) => self.fields(descriptor),
+            PayloadDescriptor::Enum(descriptor) => self.field(FieldType::Enum(descriptor)),
+        }
+    }
+
+    /// Decodes the fields of a struct taking the rest of the payload
+    fn fields(&mut self, descriptor: &StructDescriptor) -> Result<Value, DecodeError> {
+        let mut nodes = Vec::new();
+        match descriptor.encoding {
+            StructEncoding::Positional => {
+                for field in descriptor.fields {
+                    let start = self.position;
+                    let value = self.field(field.field_type)?;
+                    nodes.push(Node {
+                        label: field.name.to_string(),
+                        range: start..self.position,
+                        value,
+                    });
+                }
+            }
+            StructEncoding::Tlv => {
+                while self.position < self.payload.len() {
+                    let start = self.position;
+                    let tag = self.decode::<u8>()?;
+                    let length = usize::from(self.decode::<u16>()?);
+                    let end = self.position + length;
+                    let field = descriptor.fields.iter().find(|f| f.tag == Some(tag));
+                    let (label, value) = if let Some(field) = field {
+                        // Present optional fields are encoded as their value
+                        let field_type = match field.field_type {
+                            FieldType::Option(field_type) => *field_type,
+                            field_type => field_type,
+                        };
+                        let value = self.bounded(end, |walker| walker.field(field_type))?;
+                        (field.name.to_string(), value)
+                    } else {
+                        let value = self.bounded(end, |walker| walker.field(FieldType::Bytes))?;
+                        (format!("tag {tag}"), value)
+                    };
+                    self.position = end;
+                    nodes.push(Node {
+                        label,
+                        range: start..end,
+                        value,
+                    });
+                }
+            }
+        }
+        Ok(Value::Struct(descriptor.name, nodes))
+    }
+
+    /// Runs `decode` on the payload up to `end` only, so that fields taking the rest of the
+    /// payload stop at the end of the nested struct or TLV entry they are in
+    fn bounded(
+        &mut self,
+        end: usize,
+        decode: impl FnOnce(&mut Self) -> Result<Value, DecodeError>,
+    ) -> Result<Value, DecodeError> {
+        let mut rest = &self.payload[self.position..];
+        take(&mut rest, end - self.position)?;
+        let payload = self.payload;
+        self.payload = &payload[..end];
+        let result = decode(self);
+        self.payload = payload;
+        result
+    }
+
+    /// Decodes a field, remembering where it started if it fails
+    fn field(&mut self, field_type: FieldType) -> Result<Value, DecodeError> {
+        let start = self.position;
+        let result = self.value(field_type);
+        if result.is_err() {
+            self.failed_at.get_or_insert(start);
+        }
+        result
+    }
+
+    fn value(&mut self, field_type: FieldType) -> Result<Value, DecodeError> {
+        let scalar = |value: String| Ok(Value::Scalar(value));
+        match field_type {
+            FieldType::U8 => scalar(self.decode::<u8>()?.to_string()),
+            FieldType::U16 => scalar(self.decode::<u16>()?.to_string()),
+            FieldType::U32 => scalar(self.decode::<u32>()?.to_string()),
+            FieldType::U64 => scalar(self.decode::<u64>()?.to_string()),
+            FieldType::I8 => scalar(self.decode::<i8>()?.to_string()),
+            FieldType::I16 => scalar(self.decode::<i16>()?.to_string()),
+            FieldType::I32 => scalar(self.decode::<i32>()?.to_string()),
+            FieldType::I64 => scalar(self.decode::<i64>()?.to_string()),
+            FieldType::F32 => scalar(format!("{:?}", self.decode::<f32>()?)),
+            FieldType::F64 => scalar(format!("{:?}", self.decode::<f64>()?)),
+            FieldType::Bool => scalar(self.decode::<bool>()?.to_string()),
+            FieldType::Enum(descriptor) => {
+                let value = self.decode::<u8>()?;
+                match descriptor.variants.iter().find(|(_, v)| *v == value) {
+                    Some((name, _)) => scalar((*name).to_string()),
+                    None => Err(DecodeError::InvalidEnumValue(value)),
+                }
+            }
+            FieldType::Varint(field_type) => scalar(match field_type {
+                FieldType::U16 => self.decode::<Varint<u16>>()?.0.to_string(),
+                FieldType::U32 => self.decode::<Varint<u32>>()?.0.to_string(),
+                FieldType::I16 => self.decode::<Varint<i16>>()?.0.to_string(),
+                FieldType::I32 => self.decode::<Varint<i32>>()?.0.to_string(),
+                FieldType::I64 => self.decode::<Varint<i64>>()?.0.to_string(),
+                _ => self.decode::<Varint<u64>>()?.0.to_string(),
+            }),
+            FieldType::Bytes => {
+                let start = self.position;
+                self.position = self.payload.len();
+                Ok(Value::Bytes(start..self.position))
+            }
+            FieldType::String => scalar(format!("{:?}", self.decode::<String>()?)),
+            FieldType::Array(field_type) => {
+                let count = self.decode::<u16>()?;
+                let elements = (0..count)
+                    .map(|_| match self.field(*field_type)? {
+                        Value::Scalar(value) => Ok(value),
+                        _ => unreachable!("array elements are fixed-size"),
+                    })
+                    .collect::<Result<_, DecodeError>>()?;
+                Ok(Value::Array(elements))
+            }
+            FieldType::Struct(descriptor) => {
+                let length = usize::from(self.decode::<u16>()?);
+                let end = self.position + length;
+                let value = self.bounded(end, |walker| walker.fields(descriptor))?;
+                self.position = end;
+                Ok(value)
+            }
+            FieldType::Option(field_type) => {
+                if self.position == self.payload.len() {
+                    return scalar("None".to_string());
+                }
+                match self.decode::<u8>()? {
+                    0 => scalar("None".to_string()),
+                    1 => self.field(*field_type),
+                    invalid => Err(DecodeError::InvalidPresenceFlag(invalid)),
+                }
+            }
+        }
+    }
+}
+
+/// Decodes `payload` according to the descriptor of `message_type`, returning `None` if the
+/// message type is not built in
+fn decode_payload(
+    message_type: u16,
+    payload: &[u8],
+    endianness: Endianness,
+) -> Option<Result<Value, DecodeError>> {
+    let descriptor = schema::message(message_type)?;
+    let mut walker = Walker::new(payload, endianness);
+    Some(walker.payload(descriptor.payload).and_then(|value| {
+        if walker.position < payload.len() {
+            // Reported as the length field, like a fixed-size payload with trailing bytes
+            let length = u16::try_from(payload.len() + 2).unwrap_or(u16::MAX);
+            return Err(DecodeError::InvalidLength(length));
+        }
+        Ok(value)
+    }))
+}
+
+/// The offset in `payload` of the field that could not be decoded, or `None` if the message type
+/// is not built in or the payload decodes
+pub(crate) fn error_offset(
+    message_type: u16,
+    payload: &[u8],
+    endianness: Endianness,
+) -> Option<usize> {
+    let descriptor = schema::message(message_type)?;
+    let mut walker = Walker::new(payload, endianness);
+    match walker.payload(descriptor.payload) {
+        Ok(_) => None,
+        Err(_) => Some(walker.failed_at.unwrap_or(walker.position)),
+    }
+}
+
+/// The offset in an encoded frame of the header or payload field that failed to decode with
+/// `error`
+pub(crate) fn frame_error_offset(
+    frame: &[u8],
+    error: &DecodeError,
+    endianness: Endianness,
+    framing: Framing,
+) -> Option<usize> {
+    let (bytes, header) = match framing {
+        Framing::Native | Framing::Compact | Framing::Extended => {
+            (unescape(frame), 1 + framing.header_length())
+        }
+        // Everything but the checksum, which is not escaped either
+        Framing::Binary | Framing::CheckedHeader => {
+            if matches!(error, DecodeError::ChecksumMismatch { .. }) {
+                return frame.len().checked_sub(4);
+            }
+            if matches!(error, DecodeError::HeaderChecksumMismatch { .. }) {
+                return Some(1 + framing.header_length() - framing.header_checksum_length());
+            }
+            let end = frame.len().saturating_sub(4);
+            let bytes = (0..end).map(|index| (frame[index], index..index + 1));
+            (bytes.collect(), 1 + framing.header_length())
+        }
+        Framing::Slip => (unescape_slip(frame), 2),
+        Framing::Cobs => (unstuff_cobs(frame), 2),
+    };
+    let type_end = header - framing.header_checksum_length();
+    let type_start = type_end - framing.message_type_length();
+    // Indices of unescaped bytes after the start byte, or from the start of a SLIP or COBS
+    // packet
+    let index = match (error, framing) {
+        (
+            DecodeError::InvalidLength(_),
+            Framing::Native
+            | Framing::Compact
+            | Framing::Extended
+            | Framing::Binary
+            | Framing::CheckedHeader,
+        ) => 1,
+        (DecodeError::InvalidLength(_), Framing::Slip | Framing::Cobs) => return None,
+        (DecodeError::InvalidMessageType(_), _) => type_start,
+        (DecodeError::InvalidTrailer(_), _) => bytes.len().checked_sub(1)?,
+        _ => {
+            let unescaped: Vec<_> = bytes.iter().map(|(byte, _)| *byte).collect();
+            let message_type = match unescaped.get(type_start..type_end)? {
+                [message_type] => u16::from(*message_type),
+                [first, second] => endianness.u16_from_bytes([*first, *second]),
+                _ => return None,
+            };
+            header + error_offset(message_type, &unescaped[header..], endianness)?
+        }
+    };
+    Some(bytes.get(index).map_or(frame.len(), |(_, sent)| sent.start))
+}
+
+/// Renders an encoded frame, with little-endian numbers, one part per line
+///
+/// Each line shows the offset of the part in `frame`, its bytes as sent, with escape sequences
+/// in brackets, and what they mean. Payload fields of built-in message types are shown with
+/// their names and decoded values.
+///
+/// ```
+/// # use generic_serial_protocol::{encode_frame, fmt::dump_frame};
+/// assert_eq!(
+///     dump_frame(&encode_frame(1, &[0x58])),
+///     "\
+/// 0000  58                        start
+/// 0001  03 00                     length: 3
+/// 0003  01 00                     message type: 1 (U8)
+/// 0005  [42 31]                   num: 88
+/// "
+/// );
+/// ```
+#[must_use]
+pub fn dump_frame(frame: &[u8]) -> String {
+    dump_frame_with(frame, Endianness::Little)
+}
+
+/// Renders an encoded frame like [`dump_frame`], with numbers in the given byte order
+#[must_use]
+pub fn dump_frame_with(frame: &[u8], endianness: Endianness) -> String {
+    let mut dump = Dump {
+        out: String::new(),
+        frame,
+        bytes: unescape(frame),
+        position: 0,
+    };
+    dump.frame(endianness);
+    dump.out
+}
+
+/// Renders `message` as it would be encoded in a frame with little-endian numbers, like
+/// [`dump_frame`]
+#[must_use]
+pub fn dump_message(message: &Message) -> String {
+    match message.clone().to_bytes() {
+        Ok(payload) => dump_frame(&encode_frame(message.message_type(), &payload)),
+        Err(e) => format!("{message:?}: {e}"),
+    }
+}
+
+/// A frame being dumped
+struct Dump<'a> {
+    out: String,
+    frame: &'a [u8],
+    /// Each unescaped byte and the range of frame bytes it was sent as
+    bytes: Vec<(u8, Range<usize>)>,
+    /// The number of unescaped bytes dumped so far
+    position: usize,
+}
+
+impl Dump<'_> {
+    fn frame(&mut self, endianness: Endianness) {
+        if self.frame.first() != Some(&START_BYTE) {
+            self.rest("not a frame: no start byte");
+            return;
+        }
+        self.next(1, "start");
+
+        let Some(length) = self.u16(endianness) else {
+            self.rest("truncated length");
+            return;
+        };
+        if length < 2 {
+            self.next(2, &format!("invalid length: {length}"));
+            self.rest("payload");
+            return;
+        }
+        self.next(2, &format!("length: {length}"));
+
+        let Some(message_type) = self.u16(endianness) else {
+            self.rest("truncated message type");
+            return;
+        };
+        let name = schema::message_name(message_type).unwrap_or("unknown");
+        self.next(2, &format!("message type: {message_type} ({name})"));
+
+        let length = usize::from(length) - 2;
+        let start = self.position;
+        let end = (start + length).min(self.bytes.len());
+        let payload: Vec<_> = self.bytes[start..end]
+            .iter()
+            .map(|(byte, _)| *byte)
+            .collect();
+        if payload.len() < length {
+            self.rest(&format!(
+                "truncated payload: {} of {length} bytes",
+                payload.len()
+            ));
+            return;
+        }
+        match decode_payload(message_type, &payload, endianness) {
+            Some(Ok(Value::Struct(_, fields))) => {
+                for field in &fields {
+                    self.node(start, field, 0);
+                }
+                // Structs without fields have no bytes to show
+                self.position = end;
+            }
+            Some(Ok(value)) => self.node(
+                start,
+                &Node {
+                    label: "value".to_string(),
+                    range: 0..length,
+                    value,
+                },
+                0,
+            ),
+            Some(Err(e)) => self.next(length, &format!("payload: {e}")),
+            None => self.next(length, &format!("payload: {length} bytes")),
+        }
+
+        if self.position < self.bytes.len() {
+            self.rest("bytes after the frame");
+        }
+    }
+
+    /// The u16 at the current position, if there are two more bytes
+    fn u16(&self, endianness: Endianness) -> Option<u16> {
+        match self.bytes.get(self.position..self.position + 2)? {
+            [(first, _), (second, _)] => Some(endianness.u16_from_bytes([*first, *second])),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self, length: usize, label: &str) {
+        let start = self.position;
+        self.line(start..start + length, 0, label);
+    }
+
+    fn rest(&mut self, label: &str) {
+        self.next(self.bytes.len() - self.position, label);
+    }
+
+    /// Writes the unescaped bytes in `range` as they were sent, with `label` next to the first
+    /// line of them
+    fn line(&mut self, range: Range<usize>, depth: usize, label: &str) {
+        let tokens: Vec<_> = self.bytes[range.clone()]
+            .iter()
+            .map(|(_, sent)| match &self.frame[sent.clone()] {
+                [escape, byte] => (sent.start, format!("[{escape:02X} {byte:02X}]")),
+                [byte] => (sent.start, format!("{byte:02X}")),
+                _ => unreachable!("bytes are sent as one byte or an escape sequence"),
+            })
+            .collect();
+        self.position = range.end;
+        let indent = "  ".repeat(depth);
+        if tokens.is_empty() {
+            let offset = self
+                .bytes
+                .get(range.start)
+                .map_or(self.frame.len(), |(_, sent)| sent.start);
+            self.write_line(offset, "", &format!("{indent}{label}"));
+        }
+        for (index, chunk) in tokens.chunks(DUMP_WIDTH).enumerate() {
+            let hex: Vec<_> = chunk.iter().map(|(_, token)| token.as_str()).collect();
+            let label = if index == 0 { label } else { "" };
+            self.write_line(chunk[0].0, &hex.join(" "), &format!("{indent}{label}"));
+        }
+    }
+
+    fn write_line(&mut self, offset: usize, hex: &str, label: &str) {
+        let line = format!(
+            "{offset:04X}  {hex:<width$}  {label}",
+            width = DUMP_WIDTH * 3
+        );
+        self.out.push_str(line.trim_end());
+        self.out.push('\n');
+    }
+
+    /// Writes a decoded node, whose range is relative to the payload starting at `base`
+    fn node(&mut self, base: usize, node: &Node, depth: usize) {
+        let range = base + node.range.start..base + node.range.end;
+        let label = &node.label;
+        match &node.value {
+            Value::Scalar(value) => self.line(range, depth, &format!("{label}: {value}")),
+            Value::Bytes(bytes) => {
+                let count = bytes.len();
+                self.line(range, depth, &format!("{label}: {count} bytes"));
+            }
+            Value::Array(elements) => {
+                let elements = elements.join(", ");
+                self.line(range, depth, &format!("{label}: [{elements}]"));
+            }
+            Value::Struct(name, fields) => {
+                // The length prefix or TLV header comes before the first field
+                let header_end = fields
+                    .first()
+                    .map_or(range.end, |field| base + field.range.start);
+                self.line(range.start..header_end, depth, &format!("{label}: {name}"));
+                for field in fields {
+                    self.node(base, field, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Unescapes a frame, keeping the range of frame bytes each unescaped byte was sent as
+fn unescape(frame: &[u8]) -> Vec<(u8, Range<usize>)> {
+    let mut bytes = Vec::with_capacity(frame.len());
+    let mut index = 0;
+    while index < frame.len() {
+        if let Some(&[ESCAPE_BYTE, byte]) = frame.get(index..index + 2) {
+            bytes.push((byte ^ XOR_BYTE, index..index + 2));
+            index += 2;
+        } else {
+            bytes.push((frame[index], index..index + 1));
+            index += 1;
+        }
+    }
+    bytes
+}
+
+/// Like [`unescape`], for a SLIP packet
+fn unescape_slip(packet: &[u8]) -> Vec<(u8, Range<usize>)> {
+    let mut bytes = Vec::with_capacity(packet.len());
+    let mut index = 0;
+    while index < packet.len() {
+        match packet.get(index..index + 2) {
+            Some(&[SLIP_ESC, SLIP_ESC_END]) => bytes.push((SLIP_END, index..index + 2)),
+            Some(&[SLIP_ESC, SLIP_ESC_ESC]) => bytes.push((SLIP_ESC, index..index + 2)),
+            _ => {
+                bytes.push((packet[index], index..index + 1));
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+    bytes
+}
+
+/// Like [`unescape`], for a COBS packet, with the zero ending each block sent as the code byte
+/// of the next one
+fn unstuff_cobs(packet: &[u8]) -> Vec<(u8, Range<usize>)> {
+    let mut bytes = Vec::with_capacity(packet.len());
+    let mut index = 0;
+    let mut code = 0;
+    while let Some(&next) = packet.get(index).filter(|&&next| next != 0) {
+        if code != 0 && code != COBS_MAX_CODE {
+            bytes.push((0, index..index + 1));
+        }
+        code = next;
+        let block = packet.iter().enumerate().skip(index + 1);
+        bytes.extend(
+            block
+                .take(usize::from(code) - 1)
+                .map(|(i, &byte)| (byte, i..i + 1)),
+        );
+        index += usize::from(code);
+    }
+    bytes
+}
+
+impl fmt::Display for Message {
+    /// Writes the message on one line, like `Debug`, but with bytes in hex and long byte fields
+    /// and arrays shortened
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Ok(payload) = self.clone().to_bytes() else {
+            return write!(f, "{self:?}");
+        };
+        let Some(name) = schema::message_name(self.message_type()) else {
+            write!(f, "Unknown({:#06x}) ", self.message_type())?;
+            return display_bytes(f, &payload);
+        };
+        match decode_payload(self.message_type(), &payload, Endianness::Little) {
+            Some(Ok(value @ Value::Struct(..))) => {
+                write!(f, "{name} ")?;
+                display_value(f, &payload, &value)
+            }
+            Some(Ok(value)) => {
+                write!(f, "{name}(")?;
+                display_value(f, &payload, &value)?;
+                f.write_char(')')
+            }
+            // Messages built in Rust that encode always encode to valid payloads
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
+fn display_value(f: &mut fmt::Formatter<'_>, payload: &[u8], value: &Value) -> fmt::Result {
+    match value {
+        Value::Scalar(value) => f.write_str(value),
+        Value::Bytes(range) => display_bytes(f, &payload[range.clone()]),
+        Value::Array(elements) => {
+            let shown = elements.len().min(DISPLAY_LIMIT);
+            write!(f, "[{}", elements[..shown].join(", "))?;
+            if shown < elements.len() {
+                write!(f, ", … ({} elements)", elements.len())?;
+            }
+            f.write_char(']')
+        }
+        Value::Struct(_, fields) if fields.is_empty() => f.write_str("{}"),
+        Value::Struct(_, fields) => {
+            f.write_str("{ ")?;
+            for (index, field) in fields.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: ", field.label)?;
+                if let Value::Struct(name, _) = &field.value {
+                    write!(f, "{name} ")?;
+                }
+                display_value(f, payload, &field.value)?;
+            }
+            f.write_str(" }")
+        }
+    }
+}
+
+fn display_bytes(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    f.write_char('[')?;
+    for (index, byte) in bytes.iter().take(DISPLAY_LIMIT).enumerate() {
+        if index > 0 {
+            f.write_char(' ')?;
+        }
+        write!(f, "{byte:02X}")?;
+    }
+    if bytes.len() > DISPLAY_LIMIT {
+        write!(f, " … ({} bytes)", bytes.len())?;
+    }
+    f.write_char(']')
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,159 @@
+use super::*;
+use crate::codec::encode_frame_with;
+use crate::message_types;
+
+#[test]
+fn test_dump_message() {
+    let message = Message::Multi(message_types::Multi {
+        num: 0x42,
+        string: "hello".to_string(),
+    });
+    assert_eq!(
+        dump_message(&message),
+        "\
+0000  58                        start
+0001  08 00                     length: 8
+0003  03 00                     message type: 3 (Multi)
+0005  [42 2B]                   num: 66
+0007  68 65 6C 6C 6F            string: \"hello\"
+"
+    );
+}
+
+#[test]
+fn test_dump_long_field() {
+    let message = Message::Bytes(message_types::Bytes {
+        data: (0..10).collect(),
+    });
+    assert_eq!(
+        dump_message(&message),
+        "\
+0000  58                        start
+0001  0C 00                     length: 12
+0003  00 00                     message type: 0 (Bytes)
+0005  00 01 02 03 04 05 06 07   data: 10 bytes
+000D  08 09
+"
+    );
+}
+
+#[test]
+fn test_dump_nested_and_tlv() {
+    let message = Message::Settings(message_types::Settings {
+        baud_rate: None,
+        retries: Some(3),
+    });
+    assert_eq!(
+        dump_message(&message),
+        "\
+0000  58                        start
+0001  06 00                     length: 6
+0003  16 00                     message type: 22 (Settings)
+0005  02 01 00 03               retries: 3
+"
+    );
+
+    let message = Message::Status(message_types::Status::Pending);
+    assert!(dump_message(&message).ends_with("0005  02                        value: Pending\n"));
+}
+
+#[test]
+fn test_dump_big_endian() {
+    let frame = encode_frame_with(5, &[0x12, 0x34], Endianness::Big);
+    assert_eq!(
+        dump_frame_with(&frame, Endianness::Big),
+        "\
+0000  58                        start
+0001  00 04                     length: 4
+0003  00 05                     message type: 5 (U16)
+0005  12 34                     num: 4660
+"
+    );
+}
+
+#[test]
+fn test_dump_malformed_frames() {
+    assert_eq!(
+        dump_frame(&[0x13, 0x37]),
+        "0000  13 37                     not a frame: no start byte\n"
+    );
+    assert_eq!(
+        dump_frame(&[0x58, 0x04, 0x00, 0x01, 0x00, 0x57]),
+        "\
+0000  58                        start
+0001  04 00                     length: 4
+0003  01 00                     message type: 1 (U8)
+0005  57                        truncated payload: 1 of 2 bytes
+"
+    );
+    assert_eq!(
+        dump_frame(&[0x58, 0x04, 0x00, 0x01, 0x00, 0x57, 0x00]),
+        "\
+0000  58                        start
+0001  04 00                     length: 4
+0003  01 00                     message type: 1 (U8)
+0005  57 00                     payload: Invalid length field: 4
+"
+    );
+    assert_eq!(
+        dump_frame(&[0x58, 0x03, 0x00, 0x34, 0x12, 0xAB, 0xCD]),
+        "\
+0000  58                        start
+0001  03 00                     length: 3
+0003  34 12                     message type: 4660 (unknown)
+0005  AB                        payload: 1 bytes
+0006  CD                        bytes after the frame
+"
+    );
+}
+
+#[test]
+fn test_display() {
+    let display = |message: Message| message.to_string();
+    assert_eq!(
+        display(Message::Multi(message_types::Multi {
+            num: 1,
+            string: "hi".to_string()
+        })),
+        "Multi { num: 1, string: \"hi\" }"
+    );
+    assert_eq!(display(Message::NoOp(message_types::NoOp {})), "NoOp {}");
+    assert_eq!(
+        display(Message::Status(message_types::Status::Ok)),
+        "Status(Ok)"
+    );
+    assert_eq!(
+        display(Message::Log(message_types::Log {
+            level: message_types::Level::Warn,
+            module: "net".to_string(),
+            text: "down".to_string(),
+        })),
+        "Log { level: Warn, module: \"net\", text: \"down\" }"
+    );
+    assert_eq!(
+        display(Message::Unknown {
+            message_type: 0x1234,
+            data: vec![0xAB, 0xCD]
+        }),
+        "Unknown(0x1234) [AB CD]"
+    );
+}
+
+#[test]
+fn test_display_shortens_long_fields() {
+    let message = Message::Bytes(message_types::Bytes {
+        data: vec![0xFF; 1000],
+    });
+    assert_eq!(
+        message.to_string(),
+        "Bytes { data: [FF FF FF FF FF FF FF FF FF FF FF FF FF FF FF FF … (1000 bytes)] }"
+    );
+
+    let message = Message::U16Array(message_types::U16Array {
+        values: (0..20).collect(),
+    });
+    assert_eq!(
+        message.to_string(),
+        "U16Array { values: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, … (20 elements)] }"
+    );
+}
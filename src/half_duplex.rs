@@ -0,0 +1,130 @@
+use crate::time::Instant;
+use std::fmt;
+use std::time::Duration;
+
+type DriverEnable = Box<dyn FnMut(bool) + Send>;
+
+/// Settings for sharing a half-duplex bus, such as two-wire RS-485, set with
+/// [`SerialManager::with_half_duplex`](crate::SerialManager::with_half_duplex).
+///
+/// Before transmitting, the manager waits for the turnaround time to pass since the last bytes
+/// were received, giving the peer time to release the bus, then enables the line driver through
+/// the callback, if there is one. Once the frame has been written and flushed it waits out the
+/// disable delay, so that the last byte leaves the UART, and disables the driver again.
+pub struct HalfDuplex {
+    turnaround: Duration,
+    enable_delay: Duration,
+    disable_delay: Duration,
+    driver_enable: Option<DriverEnable>,
+    last_received: Option<Instant>,
+    transmitting: bool,
+}
+
+impl fmt::Debug for HalfDuplex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HalfDuplex")
+            .field("turnaround", &self.turnaround)
+            .field("enable_delay", &self.enable_delay)
+            .field("disable_delay", &self.disable_delay)
+            .field("last_received", &self.last_received)
+            .field("transmitting", &self.transmitting)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for HalfDuplex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HalfDuplex {
+    /// Creates settings with no delays and no driver enable callback
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            turnaround: Duration::ZERO,
+            enable_delay: Duration::ZERO,
+            disable_delay: Duration::ZERO,
+            driver_enable: None,
+            last_received: None,
+            transmitting: false,
+        }
+    }
+
+    /// Waits at least `turnaround` after the last bytes received before transmitting
+    #[must_use]
+    pub fn with_turnaround(mut self, turnaround: Duration) -> Self {
+        self.turnaround = turnaround;
+        self
+    }
+
+    /// Calls `driver_enable` with `true` before each transmission and `false` after it, such as
+    /// to drive the RTS line or a DE GPIO of an RS-485 transceiver
+    ///
+    /// It is called from the thread sending with the manager.
+    #[must_use]
+    pub fn with_driver_enable(mut self, driver_enable: impl FnMut(bool) + Send + 'static) -> Self {
+        self.driver_enable = Some(Box::new(driver_enable));
+        self
+    }
+
+    /// Waits `delay` after enabling the driver before writing, for transceivers that need time
+    /// to switch
+    #[must_use]
+    pub fn with_enable_delay(mut self, delay: Duration) -> Self {
+        self.enable_delay = delay;
+        self
+    }
+
+    /// Waits `delay` after flushing before disabling the driver, for connections whose flush
+    /// returns before the last byte has been shifted out, usually about one character time
+    #[must_use]
+    pub fn with_disable_delay(mut self, delay: Duration) -> Self {
+        self.disable_delay = delay;
+        self
+    }
+
+    /// Records bytes received at `now`
+    pub(crate) fn received(&mut self, now: Instant) {
+        self.last_received = Some(now);
+    }
+
+    /// Returns how long after `now` the turnaround time runs out, or zero while transmitting
+    pub(crate) fn turnaround_wait(&self, now: Instant) -> Duration {
+        match self.last_received {
+            Some(last) if !self.transmitting => {
+                (last + self.turnaround).saturating_duration_since(now)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Enables the driver if it is not already, returning how long to wait before writing
+    pub(crate) fn enable(&mut self) -> Duration {
+        if self.transmitting {
+            return Duration::ZERO;
+        }
+        self.transmitting = true;
+        if let Some(driver_enable) = &mut self.driver_enable {
+            driver_enable(true);
+        }
+        self.enable_delay
+    }
+
+    /// Returns how long to wait after flushing before disabling the driver
+    pub(crate) fn disable_delay(&self) -> Duration {
+        self.disable_delay
+    }
+
+    /// Disables the driver if it is enabled
+    pub(crate) fn disable(&mut self) {
+        if !self.transmitting {
+            return;
+        }
+        self.transmitting = false;
+        if let Some(driver_enable) = &mut self.driver_enable {
+            driver_enable(false);
+        }
+    }
+}
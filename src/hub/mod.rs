@@ -0,0 +1,435 @@
+use crate::cancel::CancelToken;
+use crate::codec::{Endianness, Frame};
+use crate::errors::{HubError, ReceiveError};
+use crate::layer::Addressing;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport, TryClone};
+use crate::time::Instant;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The destination and source addresses and the sequence number before each payload
+const HEADER_LENGTH: usize = 4;
+
+/// What the hub knows of one peer
+#[derive(Debug, Default)]
+struct PeerState {
+    /// Messages received and not yet taken, with the number of each in order of arrival
+    inbox: VecDeque<(u64, Message)>,
+    /// The sequence numbers of the `Pong`s received and not yet taken
+    pongs: Vec<u16>,
+    ping_sequence: u16,
+    next_sent: u16,
+    next_expected: Option<u16>,
+    lost: u64,
+    dropped: u64,
+    last_seen: Option<Instant>,
+    /// The number the reply to the request waiting on the peer will get at least, if one is
+    request: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    peers: BTreeMap<u8, PeerState>,
+    /// How many messages have been received from all peers
+    received: u64,
+    /// Whether the reader has stopped
+    closed: bool,
+}
+
+impl State {
+    fn peer(&mut self, address: u8) -> &mut PeerState {
+        self.peers.entry(address).or_default()
+    }
+}
+
+struct Shared<T>
+where
+    T: Transport,
+{
+    local: u8,
+    endianness: Endianness,
+    writer: Mutex<SerialManager<T>>,
+    state: Mutex<State>,
+    /// Notified when a message or `Pong` arrives, a request finishes or the reader stops
+    changed: Condvar,
+}
+
+fn lock<S>(state: &Mutex<S>) -> MutexGuard<'_, S> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T> Shared<T>
+where
+    T: Transport,
+{
+    /// Waits until `ready` returns a value or `timeout` passes
+    fn wait_for<R>(
+        &self,
+        timeout: Duration,
+        mut ready: impl FnMut(&mut State) -> Option<R>,
+    ) -> Result<R, HubError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(result) = ready(&mut state) {
+                return Ok(result);
+            }
+            if state.closed {
+                return Err(HubError::Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(HubError::Timeout);
+            }
+            state = self
+                .changed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    fn send(&self, address: u8, message: Message) -> io::Result<()> {
+        let message_type = message.message_type();
+        let data = message.to_bytes_with(self.endianness)?;
+        // Hold the writer while numbering, so that frames go out in sequence order
+        let mut writer = lock(&self.writer);
+        let sequence = {
+            let mut state = lock(&self.state);
+            let peer = state.peer(address);
+            let sequence = peer.next_sent;
+            peer.next_sent = sequence.wrapping_add(1);
+            sequence
+        };
+        let mut payload = Vec::with_capacity(HEADER_LENGTH + data.len());
+        payload.extend_from_slice(&[address, self.local]);
+        payload.extend_from_slice(&sequence.to_le_bytes());
+        payload.extend_from_slice(&data);
+        writer.send_raw(message_type, &payload)
+    }
+
+    /// Hands a frame received to its peer, answering `Ping`s
+    fn deliver(&self, frame: Frame) -> io::Result<()> {
+        let mut header = frame.payload;
+        if header.len() < HEADER_LENGTH {
+            return Ok(());
+        }
+        let data = header.split_off(HEADER_LENGTH);
+        let (destination, source) = (header[0], header[1]);
+        if destination != self.local && destination != Addressing::BROADCAST {
+            return Ok(());
+        }
+        let sequence = u16::from_le_bytes([header[2], header[3]]);
+        let Ok(message) = Message::from_bytes_with(frame.message_type, data, self.endianness)
+        else {
+            return Ok(());
+        };
+
+        let mut state = lock(&self.state);
+        let peer = state.peer(source);
+        if let Some(expected) = peer.next_expected {
+            // As in the Sequencing layer, numbers up to half the range behind are taken as old
+            let ahead = sequence.wrapping_sub(expected);
+            if ahead >= 0x8000 {
+                peer.dropped += 1;
+                return Ok(());
+            }
+            peer.lost += u64::from(ahead);
+        }
+        peer.next_expected = Some(sequence.wrapping_add(1));
+        peer.last_seen = Some(Instant::now());
+        match message {
+            Message::Ping(ping) => {
+                drop(state);
+                let sequence = ping.sequence;
+                return self.send(source, Message::Pong(message_types::Pong { sequence }));
+            }
+            Message::Pong(pong) => peer.pongs.push(pong.sequence),
+            message => {
+                let number = state.received;
+                state.received += 1;
+                state.peer(source).inbox.push_back((number, message));
+            }
+        }
+        self.changed.notify_all();
+        Ok(())
+    }
+}
+
+/// Tracks several peers sharing one connection, such as devices on an RS-485 bus, each with its
+/// own address, sequence numbers, requests and liveness
+///
+/// The hub receives on its own thread, sorting frames by the peer that sent them, and hands out
+/// a [`Peer`] handle for each address, which any thread can send, receive and make requests
+/// through. Frames carry the destination and source addresses and a per-peer sequence number
+/// before the payload, as sent by a peer stacking a
+/// [`Sequencing`](crate::layer::Sequencing) layer and then an [`Addressing`] layer, so the
+/// manager given to the hub must not have either of its own. Frames addressed to another device
+/// and duplicates are dropped, and every `Ping` is answered with a `Pong`.
+///
+/// ```no_run
+/// use generic_serial_protocol::{message_types, Hub, Message, SerialManager};
+/// use std::time::Duration;
+///
+/// let hub = Hub::new(SerialManager::connect_tcp("192.168.1.20:5000").unwrap(), 0x01).unwrap();
+/// let sensor = hub.peer(0x10);
+/// let valve = hub.peer(0x11);
+/// let command = Message::Command(message_types::Command { id: 1, args: vec![] });
+/// let reading = sensor.request(command, Duration::from_millis(200)).unwrap();
+/// if !valve.is_alive(Duration::from_secs(5)) {
+///     valve.ping(Duration::from_millis(200)).unwrap();
+/// }
+/// ```
+pub struct Hub<T>
+where
+    T: Transport,
+{
+    shared: Arc<Shared<T>>,
+    cancel: CancelToken,
+    reader: JoinHandle<ReceiveError>,
+}
+
+impl<T> fmt::Debug for Hub<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hub")
+            .field("local", &self.shared.local)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Hub<T>
+where
+    T: Transport + TryClone + Send + 'static,
+{
+    /// Starts a hub at address `local` on `manager`'s connection, receiving on a new thread
+    ///
+    /// The reader keeps the manager, with its configuration and observer, and receives until the
+    /// hub is stopped, an IO error occurs or the connection is closed. Frames that fail to
+    /// decode are skipped.
+    pub fn new(mut manager: SerialManager<T>, local: u8) -> io::Result<Self> {
+        let shared = Arc::new(Shared {
+            local,
+            endianness: manager.endianness(),
+            writer: Mutex::new(manager.try_clone_writer()?),
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        });
+        let cancel = manager.cancel_token();
+        let reader = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let error = loop {
+                    let frame = match manager.receive_raw() {
+                        Ok(frame) => frame,
+                        Err(e @ (ReceiveError::PeerClosed | ReceiveError::Cancelled)) => break e,
+                        Err(ReceiveError::Io(e)) if e.kind() != io::ErrorKind::TimedOut => {
+                            break ReceiveError::Io(e)
+                        }
+                        Err(_) => continue,
+                    };
+                    if let Err(e) = shared.deliver(frame) {
+                        break ReceiveError::Io(e);
+                    }
+                };
+                lock(&shared.state).closed = true;
+                shared.changed.notify_all();
+                error
+            })
+        };
+        Ok(Self {
+            shared,
+            cancel,
+            reader,
+        })
+    }
+}
+
+impl<T> Hub<T>
+where
+    T: Transport,
+{
+    /// Returns a handle to the peer at `address`
+    ///
+    /// Messages from the peer are kept from the moment the hub hears from it, whether or not a
+    /// handle was asked for yet.
+    #[must_use]
+    pub fn peer(&self, address: u8) -> Peer<T> {
+        lock(&self.shared.state).peer(address);
+        Peer {
+            address,
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Returns the addresses of the peers the hub has heard from or been asked for, in order
+    #[must_use]
+    pub fn peers(&self) -> Vec<u8> {
+        lock(&self.shared.state).peers.keys().copied().collect()
+    }
+
+    /// Stops receiving, returning the error that stopped the reader if it was not this
+    ///
+    /// The reader stops at its next read from the connection, once bytes arrive or a read times
+    /// out. Peer handles fail with [`HubError::Closed`] afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the reader panicked.
+    pub fn stop(self) -> Result<(), ReceiveError> {
+        self.cancel.cancel();
+        match self.reader.join().expect("hub reader panicked") {
+            ReceiveError::Cancelled => Ok(()),
+            e => Err(e),
+        }
+    }
+}
+
+/// A handle to one peer of a [`Hub`], which can be cloned and moved to any thread
+pub struct Peer<T>
+where
+    T: Transport,
+{
+    address: u8,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Peer<T>
+where
+    T: Transport,
+{
+    fn clone(&self) -> Self {
+        Self {
+            address: self.address,
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Peer<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Peer")
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Peer<T>
+where
+    T: Transport,
+{
+    #[must_use]
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Sends a message to the peer
+    pub fn send(&self, message: Message) -> io::Result<()> {
+        self.shared.send(self.address, message)
+    }
+
+    /// Receives the next message from the peer, waiting up to `timeout`
+    ///
+    /// While a [`request`](Self::request) waits for its reply, only messages that arrived before
+    /// it was sent are returned.
+    pub fn receive(&self, timeout: Duration) -> Result<Message, HubError> {
+        self.shared.wait_for(timeout, |state| {
+            let peer = state.peer(self.address);
+            let request = peer.request;
+            match peer.inbox.front() {
+                Some(&(number, _)) if request.is_none_or(|request| number < request) => {
+                    peer.inbox.pop_front().map(|(_, message)| message)
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Sends a request to the peer and returns its reply, the next message it sends, waiting up
+    /// to `timeout` for it
+    ///
+    /// Requests to the same peer wait for each other, so each gets its own reply, while requests
+    /// to different peers are outstanding at the same time.
+    pub fn request(&self, message: Message, timeout: Duration) -> Result<Message, HubError> {
+        let deadline = Instant::now() + timeout;
+        // Messages received from here on can be the reply
+        let start = self.shared.wait_for(timeout, |state| {
+            let received = state.received;
+            let peer = state.peer(self.address);
+            if peer.request.is_some() {
+                return None;
+            }
+            peer.request = Some(received);
+            Some(received)
+        })?;
+        let result = self.send(message).map_err(HubError::from).and_then(|()| {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            self.shared.wait_for(remaining, |state| {
+                let inbox = &mut state.peer(self.address).inbox;
+                let index = inbox.iter().position(|&(number, _)| number >= start)?;
+                inbox.remove(index).map(|(_, message)| message)
+            })
+        });
+        lock(&self.shared.state).peer(self.address).request = None;
+        self.shared.changed.notify_all();
+        result
+    }
+
+    /// Sends a `Ping` and waits up to `timeout` for the peer's `Pong`, returning the round trip
+    /// time
+    pub fn ping(&self, timeout: Duration) -> Result<Duration, HubError> {
+        let sequence = {
+            let mut state = lock(&self.shared.state);
+            let peer = state.peer(self.address);
+            peer.ping_sequence = peer.ping_sequence.wrapping_add(1);
+            peer.ping_sequence
+        };
+        let sent = Instant::now();
+        self.send(Message::Ping(message_types::Ping { sequence }))?;
+        self.shared.wait_for(timeout, |state| {
+            let pongs = &mut state.peer(self.address).pongs;
+            let index = pongs.iter().position(|&pong| pong == sequence)?;
+            pongs.swap_remove(index);
+            Some(sent.elapsed())
+        })
+    }
+
+    /// Returns when a valid frame was last received from the peer, if one was
+    #[must_use]
+    pub fn last_seen(&self) -> Option<Instant> {
+        lock(&self.shared.state).peer(self.address).last_seen
+    }
+
+    /// Whether a valid frame was received from the peer within the last `within`
+    #[must_use]
+    pub fn is_alive(&self, within: Duration) -> bool {
+        self.last_seen()
+            .is_some_and(|last_seen| last_seen.elapsed() <= within)
+    }
+
+    /// Returns the number of frames from the peer skipped over by its sequence numbers
+    #[must_use]
+    pub fn lost(&self) -> u64 {
+        lock(&self.shared.state).peer(self.address).lost
+    }
+
+    /// Returns the number of frames from the peer dropped for arriving after a later one or
+    /// twice
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        lock(&self.shared.state).peer(self.address).dropped
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+use crate::layer::Sequencing;
+use crate::payload::MessageType;
+use crate::test_util::{stream_pair, TestStream};
+use crate::testing::SimulatedDevice;
+
+const HUB: u8 = 0x01;
+
+/// Sends a frame as if from the device at `source`, numbered `sequence`
+fn send_from(bus: &mut SerialManager<TestStream>, source: u8, sequence: u16, message: Message) {
+    let mut payload = vec![HUB, source];
+    payload.extend_from_slice(&sequence.to_le_bytes());
+    let message_type = message.message_type();
+    payload.extend(message.to_bytes_with(Endianness::Little).unwrap());
+    bus.send_raw(message_type, &payload).unwrap();
+}
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+#[test]
+fn test_peers_are_tracked_separately() {
+    let (hub_end, bus_end) = stream_pair();
+    let hub = Hub::new(SerialManager::new(hub_end), HUB).unwrap();
+    let mut bus = SerialManager::new(bus_end);
+
+    send_from(&mut bus, 0x10, 0, u8_message(1));
+    send_from(&mut bus, 0x11, 0, u8_message(2));
+    // Addressed to another device
+    let mut payload = vec![0x05, 0x10, 1, 0];
+    payload.extend(u8_message(3).to_bytes_with(Endianness::Little).unwrap());
+    bus.send_raw(message_types::U8::ID, &payload).unwrap();
+    send_from(&mut bus, 0x10, 1, u8_message(4));
+
+    let timeout = Duration::from_secs(1);
+    let first = hub.peer(0x10);
+    let second = hub.peer(0x11);
+    assert_eq!(second.receive(timeout).unwrap(), u8_message(2));
+    assert_eq!(first.receive(timeout).unwrap(), u8_message(1));
+    assert_eq!(first.receive(timeout).unwrap(), u8_message(4));
+    assert!(matches!(
+        second.receive(Duration::from_millis(20)),
+        Err(HubError::Timeout)
+    ));
+    assert_eq!(hub.peers(), [0x10, 0x11]);
+    assert!(first.is_alive(timeout));
+    assert!(!hub.peer(0x12).is_alive(timeout));
+}
+
+#[test]
+fn test_sequence_numbers_per_peer() {
+    let (hub_end, bus_end) = stream_pair();
+    let hub = Hub::new(SerialManager::new(hub_end), HUB).unwrap();
+    let mut bus = SerialManager::new(bus_end);
+
+    let peer = hub.peer(0x10);
+    let other = hub.peer(0x11);
+    send_from(&mut bus, 0x10, 0, u8_message(1));
+    send_from(&mut bus, 0x10, 3, u8_message(2));
+    send_from(&mut bus, 0x10, 3, u8_message(3));
+    send_from(&mut bus, 0x11, 0, u8_message(4));
+    assert_eq!(
+        other.receive(Duration::from_secs(1)).unwrap(),
+        u8_message(4)
+    );
+    assert_eq!((peer.lost(), peer.dropped()), (2, 1));
+    assert_eq!((other.lost(), other.dropped()), (0, 0));
+
+    // Each peer is numbered from zero
+    peer.send(u8_message(5)).unwrap();
+    peer.send(u8_message(6)).unwrap();
+    other.send(u8_message(7)).unwrap();
+    let headers: Vec<Vec<u8>> = (0..3)
+        .map(|_| bus.receive_raw().unwrap().payload[..HEADER_LENGTH].to_vec())
+        .collect();
+    assert_eq!(
+        headers,
+        [[0x10, HUB, 0, 0], [0x10, HUB, 1, 0], [0x11, HUB, 0, 0]]
+    );
+}
+
+#[test]
+fn test_request_is_correlated_per_peer() {
+    let (hub_end, bus_end) = stream_pair();
+    let hub = Hub::new(SerialManager::new(hub_end), HUB).unwrap();
+    let command = |id| Message::Command(message_types::Command { id, args: vec![] });
+    let device = SimulatedDevice::new()
+        .respond(
+            |message| matches!(message, Message::Command(_)),
+            Duration::from_millis(10),
+            |message| match message {
+                Message::Command(command) => Message::U16(message_types::U16 { num: command.id }),
+                _ => unreachable!(),
+            },
+        )
+        .spawn(
+            SerialManager::new(bus_end)
+                .with_layer(Sequencing::new())
+                .with_layer(Addressing::new(0x10, HUB)),
+        )
+        .unwrap();
+
+    let peer = hub.peer(0x10);
+    let timeout = Duration::from_secs(1);
+    // A message the peer sent before the request is not its reply
+    peer.send(command(7)).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let requests: Vec<_> = [1, 2]
+        .map(|id| {
+            let peer = peer.clone();
+            thread::spawn(move || (id, peer.request(command(id), timeout).unwrap()))
+        })
+        .into_iter()
+        .collect();
+    for request in requests {
+        let (id, reply) = request.join().unwrap();
+        assert_eq!(reply, Message::U16(message_types::U16 { num: id }));
+    }
+    assert_eq!(
+        peer.receive(timeout).unwrap(),
+        Message::U16(message_types::U16 { num: 7 })
+    );
+    assert!(peer.ping(timeout).unwrap() < timeout);
+    assert!(peer.is_alive(timeout));
+
+    drop(hub);
+    device.stop().unwrap();
+}
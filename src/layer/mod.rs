@@ -0,0 +1,577 @@
+//! Composable processing of frames between messages and the wire.
+//!
+//! A [`Layer`] transforms each frame's message type and payload on the way out and undoes it on
+//! the way in, such as by adding a checksum or a header. Layers are stacked on a
+//! [`SerialManager`](crate::SerialManager) with
+//! [`with_layer`](crate::SerialManager::with_layer), each one added wrapping those before it:
+//! outgoing frames pass through the layers in the order they were added and incoming frames in
+//! the reverse order, so the last layer added is closest to the wire.
+//!
+//! ```
+//! use generic_serial_protocol::layer::{Addressing, Crc32, Sequencing};
+//! use generic_serial_protocol::SerialManager;
+//! # let stream = std::io::Cursor::new(Vec::new());
+//!
+//! // The checksum covers the sequence number and addresses too
+//! let manager = SerialManager::new(stream)
+//!     .with_layer(Sequencing::new())
+//!     .with_layer(Addressing::new(0x01, 0x02))
+//!     .with_layer(Crc32);
+//! ```
+//!
+//! Both peers must stack the same layers in the same order. Applications can write their own
+//! layers, such as for compression or encryption, by implementing [`Layer`]. A [`Flags`] layer
+//! closest to the wire tells receivers which version and features each frame uses, so that the
+//! stack can change without peers misparsing each other's frames.
+
+use crate::codec::Frame;
+use crate::errors::DecodeError;
+use crate::firmware::crc32;
+use std::collections::VecDeque;
+use std::ops::{BitOr, BitOrAssign};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A step in processing frames between messages and the wire
+pub trait Layer {
+    /// Transforms a frame on its way to the wire
+    fn encode(&mut self, frame: Frame) -> Frame;
+
+    /// Undoes [`encode`](Self::encode) on a frame received, or returns `None` to drop the frame
+    /// without an error, such as one addressed to another device
+    fn decode(&mut self, frame: Frame) -> Result<Option<Frame>, DecodeError>;
+
+    /// Returns a frame that an earlier [`decode`](Self::decode) held back and that is now ready
+    /// to be received, such as one released in order by a reordering [`Sequencing`] layer
+    ///
+    /// Called after every frame decoded until it returns `None`. Layers that never hold frames
+    /// back keep the default, which returns `None`.
+    fn poll(&mut self) -> Option<Frame> {
+        None
+    }
+}
+
+impl<L: Layer + ?Sized> Layer for Box<L> {
+    fn encode(&mut self, frame: Frame) -> Frame {
+        (**self).encode(frame)
+    }
+
+    fn decode(&mut self, frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        (**self).decode(frame)
+    }
+
+    fn poll(&mut self) -> Option<Frame> {
+        (**self).poll()
+    }
+}
+
+/// Layers stacked in the order they were added, the last being closest to the wire
+#[derive(Default)]
+pub(crate) struct LayerStack {
+    layers: Vec<Box<dyn Layer + Send>>,
+}
+
+impl LayerStack {
+    pub(crate) fn push(&mut self, layer: impl Layer + Send + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Removes the `count` layers closest to the wire
+    pub(crate) fn pop(&mut self, count: usize) {
+        self.layers
+            .truncate(self.layers.len().saturating_sub(count));
+    }
+
+    pub(crate) fn encode(&mut self, frame: Frame) -> Frame {
+        self.layers
+            .iter_mut()
+            .fold(frame, |frame, layer| layer.encode(frame))
+    }
+
+    pub(crate) fn decode(&mut self, frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        self.decode_from(self.layers.len(), frame)
+    }
+
+    /// Returns the next frame held back by a layer and now released, once through the layers
+    /// further from the wire than it
+    pub(crate) fn poll(&mut self) -> Result<Option<Frame>, DecodeError> {
+        for index in 0..self.layers.len() {
+            while let Some(frame) = self.layers[index].poll() {
+                if let Some(frame) = self.decode_from(index, frame)? {
+                    return Ok(Some(frame));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Decodes a frame with the layers below `index`, starting closest to the wire
+    fn decode_from(&mut self, index: usize, frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        let mut frame = frame;
+        for layer in self.layers[..index].iter_mut().rev() {
+            match layer.decode(frame)? {
+                Some(decoded) => frame = decoded,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(frame))
+    }
+}
+
+/// Fails with [`DecodeError::TruncatedPayload`] if `payload` is shorter than a layer's `length`
+/// bytes
+fn check_length(payload: &[u8], length: usize) -> Result<(), DecodeError> {
+    if payload.len() < length {
+        return Err(DecodeError::TruncatedPayload {
+            expected: length,
+            actual: payload.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Appends a little-endian CRC-32 of the message type and payload to each payload, and fails
+/// frames received with [`DecodeError::ChecksumMismatch`] if it does not match
+///
+/// The message type is covered in little endian, whatever the byte order of the frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32;
+
+impl Crc32 {
+    pub(crate) fn checksum(message_type: u16, payload: &[u8]) -> u32 {
+        let mut bytes = Vec::with_capacity(2 + payload.len());
+        bytes.extend_from_slice(&message_type.to_le_bytes());
+        bytes.extend_from_slice(payload);
+        crc32(&bytes)
+    }
+}
+
+impl Layer for Crc32 {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        let checksum = Self::checksum(frame.message_type, &frame.payload);
+        frame.payload.extend_from_slice(&checksum.to_le_bytes());
+        frame
+    }
+
+    fn decode(&mut self, mut frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        check_length(&frame.payload, 4)?;
+        let split = frame.payload.len() - 4;
+        let received = u32::from_le_bytes(frame.payload[split..].try_into().unwrap_or_default());
+        frame.payload.truncate(split);
+        let expected = Self::checksum(frame.message_type, &frame.payload);
+        if received != expected {
+            return Err(DecodeError::ChecksumMismatch { expected, received });
+        }
+        Ok(Some(frame))
+    }
+}
+
+/// Prepends a little-endian sequence number to each payload, dropping frames received out of
+/// order or twice and counting the frames missed in between
+///
+/// The counts can be read through a [`SequencingHandle`]. On links where frames can overtake
+/// each other, such as ones bridged over several paths, [`with_reorder_window`] holds back
+/// frames that arrive early so that they are received in order.
+///
+/// [`with_reorder_window`]: Self::with_reorder_window
+#[derive(Debug, Default)]
+pub struct Sequencing {
+    next_sent: u16,
+    next_expected: Option<u16>,
+    window: u16,
+    /// Frames held back, the first being the expected one and each holding the sequence number
+    /// after the one before it
+    held: VecDeque<Option<Frame>>,
+    /// Frames released in order, to be returned by `poll`
+    released: VecDeque<Frame>,
+    counts: Arc<SequencingCounts>,
+}
+
+#[derive(Debug, Default)]
+struct SequencingCounts {
+    lost: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Sequencing {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds back frames arriving up to `window` sequence numbers ahead of the next one
+    /// expected until the frames before them arrive, so that they are received in order
+    ///
+    /// A frame arriving further ahead gives up on the oldest missing frames, counting them as
+    /// lost and releasing the frames held after them, until it fits in the window. Frames are
+    /// only released when later ones arrive, so a frame missing at the end of a burst holds back
+    /// the rest of it until the next. A window of 0, the default, receives frames as soon as
+    /// they arrive.
+    #[must_use]
+    pub fn with_reorder_window(mut self, window: u16) -> Self {
+        self.window = window.min(0x7FFF);
+        self
+    }
+
+    /// Returns a handle for reading the counts of frames lost and dropped
+    #[must_use]
+    pub fn handle(&self) -> SequencingHandle {
+        SequencingHandle {
+            counts: Arc::clone(&self.counts),
+        }
+    }
+
+    /// Moves on to the next sequence number, releasing the frame held for the current one or
+    /// counting it as lost
+    fn advance(&mut self, expected: u16) -> u16 {
+        match self.held.pop_front().flatten() {
+            Some(frame) => self.released.push_back(frame),
+            None => {
+                self.counts.lost.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        expected.wrapping_add(1)
+    }
+}
+
+impl Layer for Sequencing {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        let sequence = self.next_sent;
+        self.next_sent = sequence.wrapping_add(1);
+        frame.payload.splice(0..0, sequence.to_le_bytes());
+        frame
+    }
+
+    fn decode(&mut self, mut frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        check_length(&frame.payload, 2)?;
+        let sequence = u16::from_le_bytes([frame.payload[0], frame.payload[1]]);
+        frame.payload.drain(..2);
+        let Some(mut expected) = self.next_expected else {
+            self.next_expected = Some(sequence.wrapping_add(1));
+            return Ok(Some(frame));
+        };
+        // Sequence numbers up to half the range behind are taken as old rather than wrapped
+        // around
+        let mut ahead = sequence.wrapping_sub(expected);
+        if ahead >= 0x8000 {
+            self.counts.dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+        if self.window == 0 {
+            self.counts
+                .lost
+                .fetch_add(u64::from(ahead), Ordering::Relaxed);
+            self.next_expected = Some(sequence.wrapping_add(1));
+            return Ok(Some(frame));
+        }
+
+        while ahead > self.window {
+            expected = self.advance(expected);
+            ahead -= 1;
+        }
+        let slot = usize::from(ahead);
+        if self.held.len() <= slot {
+            self.held.resize(slot + 1, None);
+        }
+        if self.held[slot].is_some() {
+            self.counts.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.held[slot] = Some(frame);
+        }
+        // Releases the frames now in order
+        while matches!(self.held.front(), Some(Some(_))) {
+            expected = self.advance(expected);
+        }
+        self.next_expected = Some(expected);
+        Ok(self.released.pop_front())
+    }
+
+    fn poll(&mut self) -> Option<Frame> {
+        self.released.pop_front()
+    }
+}
+
+/// A handle to the counts of a [`Sequencing`] layer, which can be read from any thread
+#[derive(Debug, Clone)]
+pub struct SequencingHandle {
+    counts: Arc<SequencingCounts>,
+}
+
+impl SequencingHandle {
+    /// Returns the number of frames skipped over by the sequence numbers received
+    #[must_use]
+    pub fn lost(&self) -> u64 {
+        self.counts.lost.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of frames dropped for arriving after a later one or twice
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.counts.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Prepends the destination and source addresses to each payload, and drops frames received
+/// that are addressed neither to this device nor to [`BROADCAST`](Self::BROADCAST)
+#[derive(Debug, Clone, Copy)]
+pub struct Addressing {
+    local: u8,
+    remote: u8,
+}
+
+impl Addressing {
+    /// The destination address of frames for every device
+    pub const BROADCAST: u8 = 0xFF;
+
+    /// Creates a layer for this device at address `local`, sending to the device at `remote`
+    #[must_use]
+    pub fn new(local: u8, remote: u8) -> Self {
+        Self { local, remote }
+    }
+}
+
+impl Layer for Addressing {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        frame.payload.splice(0..0, [self.remote, self.local]);
+        frame
+    }
+
+    fn decode(&mut self, mut frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        check_length(&frame.payload, 2)?;
+        let destination = frame.payload[0];
+        if destination != self.local && destination != Self::BROADCAST {
+            return Ok(None);
+        }
+        frame.payload.drain(..2);
+        Ok(Some(frame))
+    }
+}
+
+/// Pads each payload up to a multiple of a block size, with a `0x80` byte followed by as many
+/// zeros as needed, and strips the padding from frames received, failing them with
+/// [`DecodeError::InvalidPadding`] if it is missing
+///
+/// Added before an encryption layer, so that the padding is encrypted too, this hides the exact
+/// length of payloads from anyone watching the link. A payload that already fills its blocks
+/// gains a whole block, as there is always at least the `0x80` byte.
+#[derive(Debug, Clone, Copy)]
+pub struct Padding {
+    block_size: usize,
+}
+
+impl Padding {
+    /// Creates a layer padding payloads to a multiple of `block_size` bytes, at least 1
+    #[must_use]
+    pub fn new(block_size: usize) -> Self {
+        Self {
+            block_size: block_size.max(1),
+        }
+    }
+}
+
+impl Layer for Padding {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        frame.payload.push(0x80);
+        let length = frame.payload.len().next_multiple_of(self.block_size);
+        frame.payload.resize(length, 0);
+        frame
+    }
+
+    fn decode(&mut self, mut frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        let end = frame
+            .payload
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .filter(|&end| frame.payload[end] == 0x80)
+            .ok_or(DecodeError::InvalidPadding)?;
+        frame.payload.truncate(end);
+        Ok(Some(frame))
+    }
+}
+
+/// The features a frame uses, sent in the low 5 bits of the byte a [`Flags`] layer prepends
+///
+/// Bits 3 and 4 are reserved for features added later.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct FrameFlags(u8);
+
+impl FrameFlags {
+    /// The payload is compressed
+    pub const COMPRESSED: Self = Self(1 << 0);
+    /// The payload is one fragment of a larger message
+    pub const FRAGMENT: Self = Self(1 << 1);
+    /// The payload ends with a checksum, such as from a [`Crc32`] layer
+    pub const HAS_CRC: Self = Self(1 << 2);
+
+    /// The bits of the flags byte that hold features, below the version
+    const MASK: u8 = 0x1F;
+
+    /// No features
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates flags from the low 5 bits of `bits`, ignoring the rest
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::MASK)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether every flag in `other` is also in `self`
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitOr for FrameFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for FrameFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+/// Prepends a byte holding a format version in its top 3 bits and [`FrameFlags`] in the rest to
+/// each payload, and fails frames received with a newer version with
+/// [`DecodeError::UnsupportedVersion`], or with flags that are not supported with
+/// [`DecodeError::UnsupportedFlags`]
+///
+/// Frames with an older version are accepted, so that receivers can adapt to older senders by
+/// reading the version and flags of the last frame through a [`FlagsHandle`], which can also
+/// change the flags sent.
+#[derive(Debug)]
+pub struct Flags {
+    version: u8,
+    supported: FrameFlags,
+    state: Arc<FlagsState>,
+}
+
+#[derive(Debug, Default)]
+struct FlagsState {
+    sent: AtomicU8,
+    last_received: Mutex<Option<(u8, FrameFlags)>>,
+}
+
+impl Flags {
+    /// The highest version that fits in the flags byte
+    pub const MAX_VERSION: u8 = 7;
+
+    /// Creates a layer sending version 0 with `flags`, and supporting only those flags in
+    /// frames received
+    #[must_use]
+    pub fn new(flags: FrameFlags) -> Self {
+        let state = FlagsState {
+            sent: AtomicU8::new(flags.bits()),
+            last_received: Mutex::new(None),
+        };
+        Self {
+            version: 0,
+            supported: flags,
+            state: Arc::new(state),
+        }
+    }
+
+    /// Sends `version`, up to [`MAX_VERSION`](Self::MAX_VERSION), and accepts frames up to it
+    #[must_use]
+    pub fn with_version(mut self, version: u8) -> Self {
+        self.version = version.min(Self::MAX_VERSION);
+        self
+    }
+
+    /// Accepts frames received with any of `supported` flags, rather than only those sent
+    #[must_use]
+    pub fn with_supported(mut self, supported: FrameFlags) -> Self {
+        self.supported = supported;
+        self
+    }
+
+    /// Returns a handle for changing the flags sent and reading those received
+    #[must_use]
+    pub fn handle(&self) -> FlagsHandle {
+        FlagsHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl Layer for Flags {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        let flags = self.state.sent.load(Ordering::Relaxed);
+        frame.payload.insert(0, self.version << 5 | flags);
+        frame
+    }
+
+    fn decode(&mut self, mut frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        check_length(&frame.payload, 1)?;
+        let byte = frame.payload[0];
+        let version = byte >> 5;
+        if version > self.version {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let flags = FrameFlags::from_bits(byte);
+        let unsupported = flags.bits() & !self.supported.bits();
+        if unsupported != 0 {
+            return Err(DecodeError::UnsupportedFlags(unsupported));
+        }
+        *self
+            .state
+            .last_received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some((version, flags));
+        frame.payload.remove(0);
+        Ok(Some(frame))
+    }
+}
+
+/// A handle to the flags of a [`Flags`] layer, which can be used from any thread
+#[derive(Debug, Clone)]
+pub struct FlagsHandle {
+    state: Arc<FlagsState>,
+}
+
+impl FlagsHandle {
+    /// Sends `flags` with the frames sent from now on
+    pub fn set(&self, flags: FrameFlags) {
+        self.state.sent.store(flags.bits(), Ordering::Relaxed);
+    }
+
+    /// Returns the flags being sent
+    #[must_use]
+    pub fn sent(&self) -> FrameFlags {
+        FrameFlags::from_bits(self.state.sent.load(Ordering::Relaxed))
+    }
+
+    /// Returns the version and flags of the last frame received, if any
+    #[must_use]
+    pub fn last_received(&self) -> Option<(u8, FrameFlags)> {
+        *self
+            .state
+            .last_received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,335 @@
+use super::*;
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Message};
+use crate::payload::MessageType;
+use crate::test_util::stream_pair;
+use crate::SerialManager;
+
+fn frame(message_type: u16, payload: &[u8]) -> Frame {
+    Frame {
+        message_type,
+        payload: payload.to_vec(),
+    }
+}
+
+#[test]
+fn test_crc32_roundtrip() {
+    let encoded = Crc32.encode(frame(0x0102, b"hello"));
+    assert_eq!(encoded.payload.len(), 9);
+    assert_eq!(&encoded.payload[..5], b"hello");
+    assert_eq!(
+        Crc32.decode(encoded).unwrap(),
+        Some(frame(0x0102, b"hello"))
+    );
+}
+
+#[test]
+fn test_crc32_mismatch() {
+    let mut encoded = Crc32.encode(frame(0x0102, b"hello"));
+    encoded.payload[0] ^= 0x01;
+    assert!(matches!(
+        Crc32.decode(encoded),
+        Err(DecodeError::ChecksumMismatch { .. })
+    ));
+
+    // The message type is covered too
+    let mut encoded = Crc32.encode(frame(0x0102, b"hello"));
+    encoded.message_type = 0x0103;
+    assert!(matches!(
+        Crc32.decode(encoded),
+        Err(DecodeError::ChecksumMismatch { .. })
+    ));
+
+    assert!(matches!(
+        Crc32.decode(frame(0x0102, &[1, 2])),
+        Err(DecodeError::TruncatedPayload {
+            expected: 4,
+            actual: 2
+        })
+    ));
+}
+
+#[test]
+fn test_sequencing_counts_lost_and_dropped() {
+    let mut sender = Sequencing::new();
+    let mut receiver = Sequencing::new();
+    let handle = receiver.handle();
+    let frames: Vec<Frame> = (0..5u8).map(|i| sender.encode(frame(1, &[i]))).collect();
+    assert_eq!(&frames[1].payload, &[1, 0, 1]);
+
+    assert_eq!(
+        receiver.decode(frames[0].clone()).unwrap(),
+        Some(frame(1, &[0]))
+    );
+    // Frames 1 and 2 go missing
+    assert_eq!(
+        receiver.decode(frames[3].clone()).unwrap(),
+        Some(frame(1, &[3]))
+    );
+    assert_eq!(handle.lost(), 2);
+    // A late arrival and a duplicate are dropped
+    assert_eq!(receiver.decode(frames[1].clone()).unwrap(), None);
+    assert_eq!(receiver.decode(frames[3].clone()).unwrap(), None);
+    assert_eq!(handle.dropped(), 2);
+    assert_eq!(
+        receiver.decode(frames[4].clone()).unwrap(),
+        Some(frame(1, &[4]))
+    );
+    assert_eq!(handle.lost(), 2);
+}
+
+#[test]
+fn test_sequencing_wraps_around() {
+    let mut receiver = Sequencing::new();
+    let handle = receiver.handle();
+    for sequence in [0xFFFEu16, 0xFFFF, 0, 1] {
+        let mut payload = sequence.to_le_bytes().to_vec();
+        payload.push(0xAA);
+        assert!(receiver.decode(frame(1, &payload)).unwrap().is_some());
+    }
+    assert_eq!(handle.lost(), 0);
+    assert_eq!(handle.dropped(), 0);
+}
+
+#[test]
+fn test_sequencing_reorders_within_the_window() {
+    let mut sender = Sequencing::new();
+    let mut receiver = Sequencing::new().with_reorder_window(3);
+    let handle = receiver.handle();
+    let frames: Vec<Frame> = (0..10u8).map(|i| sender.encode(frame(1, &[i]))).collect();
+    let mut receive = |index: usize| {
+        let first = receiver.decode(frames[index].clone()).unwrap();
+        first
+            .into_iter()
+            .chain(std::iter::from_fn(|| receiver.poll()))
+            .map(|frame| frame.payload[0])
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(receive(0), [0]);
+    assert!(receive(2).is_empty());
+    assert!(receive(3).is_empty());
+    // A duplicate of a frame held back is dropped
+    assert!(receive(3).is_empty());
+    assert_eq!(receive(1), [1, 2, 3]);
+    assert_eq!(handle.dropped(), 1);
+
+    // Frame 5 never arrives, so frame 9 is too far ahead to wait for it
+    assert!(receive(6).is_empty());
+    assert_eq!(receive(4), [4]);
+    assert_eq!(receive(9), [6]);
+    assert_eq!(handle.lost(), 1);
+    assert!(receive(8).is_empty());
+    assert_eq!(receive(7), [7, 8, 9]);
+    // Too late, as frame 6 was released
+    assert!(receive(5).is_empty());
+    assert_eq!(handle.dropped(), 2);
+    assert_eq!(handle.lost(), 1);
+}
+
+#[test]
+fn test_serial_manager_reorders_released_frames() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver =
+        SerialManager::new(stream2).with_layer(Sequencing::new().with_reorder_window(4));
+    for sequence in [0u16, 2, 3, 1, 4] {
+        let mut payload = sequence.to_le_bytes().to_vec();
+        payload.push(u8::try_from(sequence).unwrap());
+        sender.send_raw(message_types::U8::ID, &payload).unwrap();
+    }
+    for num in 0..5 {
+        assert_eq!(
+            receiver.receive().unwrap(),
+            Message::U8(message_types::U8 { num })
+        );
+    }
+    assert_eq!(receiver.stats().frames_received, 5);
+}
+
+#[test]
+fn test_addressing() {
+    let mut device = Addressing::new(0x02, 0x01);
+    let mut host = Addressing::new(0x01, 0x02);
+    let encoded = host.encode(frame(1, b"hi"));
+    assert_eq!(&encoded.payload, &[0x02, 0x01, b'h', b'i']);
+    assert_eq!(
+        device.decode(encoded.clone()).unwrap(),
+        Some(frame(1, b"hi"))
+    );
+
+    let mut other = Addressing::new(0x03, 0x01);
+    assert_eq!(other.decode(encoded).unwrap(), None);
+    assert_eq!(
+        other
+            .decode(frame(1, &[Addressing::BROADCAST, 0x01, 7]))
+            .unwrap(),
+        Some(frame(1, &[7]))
+    );
+}
+
+#[test]
+fn test_stack_order() {
+    let mut stack = LayerStack::default();
+    stack.push(Addressing::new(0x01, 0x02));
+    stack.push(Crc32);
+    let encoded = stack.encode(frame(1, b"x"));
+    // The checksum is outermost, over the addresses
+    assert_eq!(&encoded.payload[..3], &[0x02, 0x01, b'x']);
+    assert_eq!(
+        u32::from_le_bytes(encoded.payload[3..].try_into().unwrap()),
+        Crc32::checksum(1, &[0x02, 0x01, b'x'])
+    );
+
+    let mut peer = LayerStack::default();
+    peer.push(Addressing::new(0x02, 0x01));
+    peer.push(Crc32);
+    assert_eq!(peer.decode(encoded).unwrap(), Some(frame(1, b"x")));
+}
+
+#[test]
+fn test_serial_manager_layers() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1)
+        .with_layer(Sequencing::new())
+        .with_layer(Addressing::new(0x01, 0x02))
+        .with_layer(Crc32);
+    let sequencing = Sequencing::new();
+    let handle = sequencing.handle();
+    let mut receiver = SerialManager::new(stream2)
+        .with_layer(sequencing)
+        .with_layer(Addressing::new(0x02, 0x01))
+        .with_layer(Crc32);
+
+    let message = Message::Ping(message_types::Ping { sequence: 7 });
+    sender.send(message.clone()).unwrap();
+    sender.send_all([message.clone(), message.clone()]).unwrap();
+    for _ in 0..3 {
+        assert_eq!(receiver.receive().unwrap(), message);
+    }
+    assert_eq!(handle.lost(), 0);
+    assert_eq!(sender.stats().escape_bytes_sent, 0);
+}
+
+#[test]
+fn test_serial_manager_layer_errors() {
+    let (stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream1).with_layer(Crc32);
+
+    // A frame sent without the checksum layer
+    let mut sender = SerialManager::new(stream2);
+    sender.send_raw(0x0101, &[1, 2, 3, 4, 5]).unwrap();
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error,
+        ReceiveError::Decode {
+            source: DecodeError::ChecksumMismatch { .. },
+            offset: None,
+            ..
+        }
+    ));
+    assert_eq!(receiver.stats().crc_failures, 1);
+    assert_eq!(receiver.stats().decode_errors, 0);
+}
+
+#[test]
+fn test_serial_manager_skips_dropped_frames() {
+    let (stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream1).with_layer(Addressing::new(0x02, 0x01));
+    let mut sender = SerialManager::new(stream2);
+
+    let ping = |sequence| Message::Ping(message_types::Ping { sequence });
+    // Addressed to another device, then to this one
+    sender.send_raw(35, &[0x03, 0x01, 1, 0]).unwrap();
+    sender.send_raw(35, &[0x02, 0x01, 2, 0]).unwrap();
+    assert_eq!(receiver.receive().unwrap(), ping(2));
+}
+
+#[test]
+fn test_flags_roundtrip() {
+    let mut sender = Flags::new(FrameFlags::HAS_CRC).with_version(2);
+    let mut receiver = Flags::new(FrameFlags::HAS_CRC).with_version(3);
+    let handle = receiver.handle();
+    assert_eq!(handle.last_received(), None);
+
+    let encoded = sender.encode(frame(1, b"hi"));
+    assert_eq!(encoded.payload, [0x44, b'h', b'i']);
+    assert_eq!(receiver.decode(encoded).unwrap(), Some(frame(1, b"hi")));
+    assert_eq!(handle.last_received(), Some((2, FrameFlags::HAS_CRC)));
+
+    // Flags changed through the handle apply to later frames
+    let sender_handle = sender.handle();
+    sender_handle.set(FrameFlags::empty());
+    assert_eq!(sender_handle.sent(), FrameFlags::empty());
+    assert_eq!(sender.encode(frame(1, b"")).payload, [0x40]);
+}
+
+#[test]
+fn test_flags_rejects_unsupported() {
+    let mut receiver = Flags::new(FrameFlags::HAS_CRC).with_version(1);
+
+    let newer = Flags::new(FrameFlags::empty())
+        .with_version(2)
+        .encode(frame(1, b""));
+    assert!(matches!(
+        receiver.decode(newer),
+        Err(DecodeError::UnsupportedVersion(2))
+    ));
+
+    let compressed = Flags::new(FrameFlags::COMPRESSED | FrameFlags::HAS_CRC).encode(frame(1, b""));
+    assert!(matches!(
+        receiver.decode(compressed.clone()),
+        Err(DecodeError::UnsupportedFlags(0x01))
+    ));
+
+    let mut receiver = receiver.with_supported(FrameFlags::COMPRESSED | FrameFlags::HAS_CRC);
+    assert_eq!(receiver.decode(compressed).unwrap(), Some(frame(1, b"")));
+    assert!(matches!(
+        receiver.decode(frame(1, b"")),
+        Err(DecodeError::TruncatedPayload { .. })
+    ));
+}
+
+#[test]
+fn test_serial_manager_flags_layer() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1)
+        .with_layer(Crc32)
+        .with_layer(Flags::new(FrameFlags::HAS_CRC).with_version(1));
+    let mut receiver = SerialManager::new(stream2)
+        .with_layer(Crc32)
+        .with_layer(Flags::new(FrameFlags::empty()));
+
+    let message = Message::Ping(message_types::Ping { sequence: 7 });
+    sender.send(message).unwrap();
+    assert!(matches!(
+        receiver.receive().unwrap_err(),
+        ReceiveError::Decode {
+            source: DecodeError::UnsupportedVersion(1),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_padding_roundtrip() {
+    let mut padding = Padding::new(8);
+    let encoded = padding.encode(frame(1, b"hello\0"));
+    assert_eq!(encoded.payload, b"hello\0\x80\0");
+    assert_eq!(padding.decode(encoded).unwrap(), Some(frame(1, b"hello\0")));
+
+    // A payload that fills its blocks gains a whole block
+    let encoded = padding.encode(frame(1, b"12345678"));
+    assert_eq!(encoded.payload.len(), 16);
+    assert_eq!(
+        padding.decode(encoded).unwrap(),
+        Some(frame(1, b"12345678"))
+    );
+
+    for payload in [&b""[..], b"\0\0", b"hello"] {
+        assert!(matches!(
+            padding.decode(frame(1, payload)),
+            Err(DecodeError::InvalidPadding)
+        ));
+    }
+}
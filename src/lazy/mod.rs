@@ -0,0 +1,185 @@
+use crate::codec::{Endianness, Frame};
+use crate::errors::DecodeError;
+use crate::message::Message;
+use crate::payload::{skip_field, take, Field};
+use crate::schema::{self, FieldDescriptor, FieldType, PayloadDescriptor, StructEncoding};
+
+/// A received message kept as its message type and encoded payload, whose fields are decoded one
+/// at a time on demand
+///
+/// High-rate consumers that only need one field of a large message, such as the `level` of every
+/// `Log`, avoid decoding the rest of it, and with [`str_field`](Self::str_field) and
+/// [`bytes_field`](Self::bytes_field) avoid allocating for text and byte fields as well:
+///
+/// ```
+/// use generic_serial_protocol::{message_types, LazyMessage, Message};
+///
+/// let log = Message::Log(message_types::Log {
+///     level: message_types::Level::Warn,
+///     module: "motor".to_string(),
+///     text: "stalled".to_string(),
+/// });
+/// let message = LazyMessage::new(log.message_type(), log.to_bytes().unwrap());
+/// assert_eq!(message.name(), Some("Log"));
+/// assert_eq!(message.field::<message_types::Level>("level").unwrap(), message_types::Level::Warn);
+/// assert_eq!(message.str_field("module").unwrap(), "motor");
+/// ```
+///
+/// Fields are looked up by name in the [`schema`] of built-in message types, and must be
+/// requested as their own type. Fields before the one requested are skipped without being
+/// decoded, and the rest of the payload is not looked at, so a payload malformed elsewhere only
+/// fails when decoded in full with [`decode`](Self::decode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LazyMessage {
+    message_type: u16,
+    payload: Vec<u8>,
+    endianness: Endianness,
+}
+
+impl From<Frame> for LazyMessage {
+    /// Keeps a frame's payload, with little-endian numbers
+    fn from(frame: Frame) -> Self {
+        Self::new(frame.message_type, frame.payload)
+    }
+}
+
+impl LazyMessage {
+    /// Wraps an encoded payload with little-endian numbers
+    #[must_use]
+    pub fn new(message_type: u16, payload: Vec<u8>) -> Self {
+        Self {
+            message_type,
+            payload,
+            endianness: Endianness::Little,
+        }
+    }
+
+    /// Reads the payload's numbers in the given byte order
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    #[must_use]
+    pub fn message_type(&self) -> u16 {
+        self.message_type
+    }
+
+    /// The name of the built-in message type, such as `Log`
+    #[must_use]
+    pub fn name(&self) -> Option<&'static str> {
+        schema::message_name(self.message_type)
+    }
+
+    /// The encoded payload
+    #[must_use]
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Returns the encoded payload, without decoding it
+    #[must_use]
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Decodes the field called `name`, which must be of type `T`
+    ///
+    /// Fails with [`DecodeError::UnknownField`] if the message type has no such field and
+    /// [`DecodeError::FieldTypeMismatch`] if it is of another type.
+    pub fn field<T: Field>(&self, name: &str) -> Result<T, DecodeError> {
+        let (field, value) = self.locate(name, T::TYPE)?;
+        match field.tag {
+            Some(tag) => T::decode_tagged(tag, value, self.endianness),
+            None => T::decode(&mut value.unwrap_or_default(), self.endianness),
+        }
+    }
+
+    /// Borrows the text of the `String` field called `name`, without allocating
+    pub fn str_field(&self, name: &str) -> Result<&str, DecodeError> {
+        let bytes = self.slice_field(name, FieldType::String)?;
+        std::str::from_utf8(bytes).map_err(|_| {
+            // Only copied to report the error, as `DecodeError` holds the owned form
+            DecodeError::from(String::from_utf8(bytes.to_vec()).unwrap_err())
+        })
+    }
+
+    /// Borrows the bytes of the `Vec<u8>` field called `name`, without allocating
+    pub fn bytes_field(&self, name: &str) -> Result<&[u8], DecodeError> {
+        self.slice_field(name, FieldType::Bytes)
+    }
+
+    /// Decodes the whole message
+    pub fn decode(&self) -> Result<Message, DecodeError> {
+        Message::from_bytes_with(self.message_type, self.payload.clone(), self.endianness)
+    }
+
+    /// Borrows a field that takes the rest of the payload, or its TLV entry
+    fn slice_field(&self, name: &str, field_type: FieldType) -> Result<&[u8], DecodeError> {
+        let (field, value) = self.locate(name, field_type)?;
+        match (value, field.tag) {
+            (Some(value), _) => Ok(value),
+            (None, Some(tag)) => Err(DecodeError::MissingField(tag)),
+            (None, None) => Ok(&[]),
+        }
+    }
+
+    /// Finds the field called `name`, which must be of type `field_type`, returning it and the
+    /// bytes it is encoded in: the rest of the payload from its start for positional structs, or
+    /// the value of its entry for TLV ones, which is `None` if the entry is absent
+    fn locate(
+        &self,
+        name: &str,
+        field_type: FieldType,
+    ) -> Result<(&'static FieldDescriptor, Option<&[u8]>), DecodeError> {
+        let message = schema::message(self.message_type)
+            .ok_or(DecodeError::InvalidMessageType(self.message_type))?;
+        let PayloadDescriptor::Struct(descriptor) = message.payload else {
+            return Err(DecodeError::UnknownField(name.to_string()));
+        };
+        let position = descriptor
+            .fields
+            .iter()
+            .position(|field| field.name == name)
+            .ok_or_else(|| DecodeError::UnknownField(name.to_string()))?;
+        let field = &descriptor.fields[position];
+        if field.field_type != field_type {
+            return Err(DecodeError::FieldTypeMismatch(name.to_string()));
+        }
+
+        let mut bytes = self.payload.as_slice();
+        match descriptor.encoding {
+            StructEncoding::Positional => {
+                // Checked as when decoding the whole message
+                if message
+                    .payload
+                    .size()
+                    .is_some_and(|size| size != bytes.len())
+                {
+                    let length = u16::try_from(bytes.len() + 2).unwrap_or(u16::MAX);
+                    return Err(DecodeError::InvalidLength(length));
+                }
+                for skipped in &descriptor.fields[..position] {
+                    skip_field(skipped.field_type, &mut bytes, self.endianness)?;
+                }
+                Ok((field, Some(bytes)))
+            }
+            StructEncoding::Tlv => {
+                let tag = field.tag.unwrap_or_default();
+                while !bytes.is_empty() {
+                    let entry_tag = u8::decode(&mut bytes, self.endianness)?;
+                    let length = u16::decode(&mut bytes, self.endianness)?;
+                    let value = take(&mut bytes, usize::from(length))?;
+                    if entry_tag == tag {
+                        return Ok((field, Some(value)));
+                    }
+                }
+                Ok((field, None))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,118 @@
+use super::*;
+use crate::message::message_types;
+
+fn lazy(message: Message) -> LazyMessage {
+    LazyMessage::new(message.message_type(), message.to_bytes().unwrap())
+}
+
+fn log() -> Message {
+    Message::Log(message_types::Log {
+        level: message_types::Level::Error,
+        module: "motor".to_string(),
+        text: "stalled".to_string(),
+    })
+}
+
+#[test]
+fn test_positional_fields_are_found_after_earlier_fields() {
+    let message = lazy(Message::FirmwareChunk(message_types::FirmwareChunk {
+        offset: 512,
+        crc: 0xDEAD_BEEF,
+        data: vec![1, 2, 3],
+    }));
+    assert_eq!(message.field::<u32>("offset").unwrap(), 512);
+    assert_eq!(message.field::<u32>("crc").unwrap(), 0xDEAD_BEEF);
+    assert_eq!(message.bytes_field("data").unwrap(), [1, 2, 3]);
+    assert_eq!(message.field::<Vec<u8>>("data").unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn test_tlv_fields_are_found_by_tag() {
+    let message = lazy(log());
+    assert_eq!(message.name(), Some("Log"));
+    assert_eq!(
+        message.field::<message_types::Level>("level").unwrap(),
+        message_types::Level::Error
+    );
+    assert_eq!(message.str_field("text").unwrap(), "stalled");
+    assert_eq!(message.decode().unwrap(), log());
+}
+
+#[test]
+fn test_str_field_borrows_the_payload() {
+    let message = lazy(log());
+    let module = message.str_field("module").unwrap();
+    assert!(message.payload().as_ptr_range().contains(&module.as_ptr()));
+}
+
+#[test]
+fn test_big_endian_payloads() {
+    let message = Message::U16(message_types::U16 { num: 0x1234 });
+    let lazy = LazyMessage::new(
+        message.message_type(),
+        message.to_bytes_with(Endianness::Big).unwrap(),
+    )
+    .with_endianness(Endianness::Big);
+    assert_eq!(lazy.field::<u16>("num").unwrap(), 0x1234);
+}
+
+#[test]
+fn test_lookup_errors() {
+    let message = lazy(log());
+    assert!(matches!(
+        message.field::<u8>("severity"),
+        Err(DecodeError::UnknownField(name)) if name == "severity"
+    ));
+    assert!(matches!(
+        message.field::<u8>("level"),
+        Err(DecodeError::FieldTypeMismatch(name)) if name == "level"
+    ));
+    assert!(matches!(
+        message.bytes_field("module"),
+        Err(DecodeError::FieldTypeMismatch(_))
+    ));
+
+    let status = lazy(Message::Status(message_types::Status::Ok));
+    assert!(matches!(
+        status.field::<u8>("value"),
+        Err(DecodeError::UnknownField(_))
+    ));
+    assert!(matches!(
+        LazyMessage::new(0x1234, Vec::new()).field::<u8>("num"),
+        Err(DecodeError::InvalidMessageType(0x1234))
+    ));
+}
+
+#[test]
+fn test_malformed_payloads() {
+    // Too long for a fixed-size payload
+    let message = LazyMessage::new(5, vec![1, 2, 3]);
+    assert!(matches!(
+        message.field::<u16>("num"),
+        Err(DecodeError::InvalidLength(5))
+    ));
+
+    let message = LazyMessage::new(lazy(log()).message_type(), vec![2, 5, 0, b'a']);
+    assert!(matches!(
+        message.str_field("module"),
+        Err(DecodeError::TruncatedPayload { .. })
+    ));
+
+    let mut bytes = Vec::new();
+    "motor"
+        .to_string()
+        .encode_tagged(2, &mut bytes, Endianness::Little)
+        .unwrap();
+    let message = LazyMessage::new(25, bytes);
+    assert_eq!(message.str_field("module").unwrap(), "motor");
+    assert!(matches!(
+        message.str_field("text"),
+        Err(DecodeError::MissingField(3))
+    ));
+
+    let message = LazyMessage::new(2, vec![0xFF]);
+    assert!(matches!(
+        message.str_field("string"),
+        Err(DecodeError::InvalidUtf8(_))
+    ));
+}
@@ -2,11 +2,152 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::doc_markdown)]
 
+// Lets the code generated by the derive macros name this crate in its own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as generic_serial_protocol;
+
+mod bridge;
+mod byte_log;
+mod cancel;
+mod capture;
+mod channels;
+mod coalesce;
+mod codec;
+pub mod codegen;
+mod datagram;
+mod diagnostics;
+pub mod discovery;
+mod dispatcher;
 mod errors;
+mod events;
+mod feed;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod firmware;
+mod fixed;
+pub mod fmt;
+mod half_duplex;
+mod hub;
+pub mod layer;
+mod lazy;
 mod message;
-mod message_types;
+#[cfg(feature = "metrics")]
+mod metrics_export;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod negotiation;
+mod observer;
+mod patch;
+mod payload;
+#[cfg(feature = "postcard")]
+mod postcard_message;
+pub mod prelude;
+mod profile;
+#[cfg(feature = "protobuf")]
+mod protobuf;
+mod queue;
+mod rate_limit;
+mod reconnect;
+mod recorder;
+mod reliable;
+mod replay;
+mod report;
+mod responder;
+mod rotation;
+mod router;
+pub mod schema;
 mod serial_manager;
+mod stats;
+#[cfg(test)]
+mod test_util;
+pub mod testing;
+mod time;
+mod time_sync;
+mod timestamps;
+mod transaction;
+pub mod vectors;
+mod wake;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod watchdog;
+#[cfg(feature = "webserial")]
+mod webserial;
+#[cfg(feature = "websocket")]
+mod websocket;
 
-pub use errors::{DecodeError, ReceiveError};
-pub use message::Message;
-pub use serial_manager::SerialManager;
+pub use bridge::{Bridge, LinkId};
+pub use byte_log::{read_byte_log, ByteLog};
+pub use cancel::CancelToken;
+pub use capture::{read_pcapng, CapturedFrame, Direction, PcapngWriter};
+pub use channels::{Channel, Multiplexer, MAX_CHANNEL_DATA};
+pub use coalesce::Coalescing;
+pub use codec::{
+    encode_frame, encode_frame_with, encode_frame_with_trailer, Decoder, DecoderEvent, Endianness,
+    Frame, Framing, Resync, MAX_COMPACT_PAYLOAD_LENGTH, MAX_EXTENDED_PAYLOAD_LENGTH,
+    MAX_PAYLOAD_LENGTH,
+};
+pub use datagram::{Datagram, DatagramManager};
+pub use diagnostics::{
+    Bert, BertChecker, BertReport, BertSample, LatencyProbe, SelfTestCase, SelfTestOutcome,
+    SelfTestReport, Soak, SoakReport, SoakSample, BERT_MESSAGE_TYPE,
+};
+pub use dispatcher::Dispatcher;
+#[cfg(feature = "mqtt")]
+pub use errors::MqttError;
+#[cfg(feature = "postcard")]
+pub use errors::PostcardError;
+#[cfg(feature = "protobuf")]
+pub use errors::ProtobufError;
+pub use errors::{
+    BridgeError, ChannelError, DecodeError, EncodeError, FirmwareError, HubError, IdentifyError,
+    LimitViolation, PatchError, PingError, QueueSendError, ReceiveError, ReceiveTypedError,
+    RegisterError, RouterError, TimeSyncError, TransactionError, WatchdogError,
+};
+pub use events::SerialManagerEvents;
+pub use feed::{Feed, FeedEvent, Subscription};
+pub use firmware::{crc32, FirmwareReceiver, FirmwareUpdate, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE};
+pub use fixed::{FixedDecoder, FixedReceiver, MessageRef};
+#[cfg(feature = "derive")]
+pub use generic_serial_protocol_derive::EnumField;
+pub use half_duplex::HalfDuplex;
+pub use hub::{Hub, Peer};
+pub use lazy::LazyMessage;
+pub use message::{message_types, roundtrip, Capabilities, Message};
+#[cfg(feature = "mqtt")]
+pub use mqtt::{MqttGateway, Topics};
+pub use negotiation::{Negotiated, Negotiation};
+pub use observer::Observer;
+pub use patch::{
+    apply_patch, diff_blocks, PatchReceiver, PatchUpdate, DEFAULT_BLOCK_SIZE, MAX_BLOCK_DATA,
+};
+pub use payload::{ArrayElement, EnumField, Field, MessageType, Payload, Varint};
+#[cfg(feature = "postcard")]
+pub use postcard_message::PostcardMessage;
+pub use profile::Profile;
+#[cfg(feature = "protobuf")]
+pub use protobuf::ProtobufMessage;
+pub use queue::Priority;
+pub use rate_limit::{LimitAction, RateLimit, ReceiveLimit};
+pub use reconnect::{Backoff, ConnectionState, ReconnectingConnection, ResilientSerialManager};
+pub use recorder::{RecordFormat, Recorder};
+pub use reliable::{Reliable, MAX_RELIABLE_PAYLOAD};
+pub use replay::ReplayConnection;
+pub use report::Timeline;
+pub use responder::Responder;
+pub use router::{Handler, RouteId, Router, Sink};
+pub use serial_manager::{
+    Buffers, DecodeErrorAction, QueueSender, SendQueue, SerialManager, SharedSender,
+    SharedSerialManager, Suspended, Transport, TransportControl, TryClone,
+};
+pub use stats::{LatencyStats, Stats, LATENCY_BUCKETS};
+pub use time_sync::TimeSync;
+#[cfg(target_os = "linux")]
+pub use timestamps::KernelTimestamps;
+pub use timestamps::ReceiveClock;
+pub use transaction::{Transaction, TransactionReceiver, MAX_TRANSACTION_PAYLOAD};
+pub use wake::WakePreamble;
+pub use watchdog::{LinkStatus, Watchdog, WatchdogHandle};
+#[cfg(feature = "webserial")]
+pub use webserial::{Readable, WebSerialPort};
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketConnection;
@@ -0,0 +1,461 @@
+#[cfg(unix)]
+use generic_serial_protocol::discovery::Discovery;
+use generic_serial_protocol::fmt::dump_frame;
+use generic_serial_protocol::{
+    codegen, message_types, read_byte_log, read_pcapng, vectors, Capabilities, Message, Observer,
+    ReceiveError, ReconnectingConnection, SerialManager, Timeline, Varint,
+};
+use std::env;
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+Usage:
+  gsp-cli listen [--raw] <target>
+  gsp-cli send <target> <message> [args...]
+  gsp-cli dump <hex>
+  gsp-cli report <capture> <file>  (a pcapng capture or byte log, to an HTML timeline)
+  gsp-cli discover [device_id]
+  gsp-cli gen c <directory>
+  gsp-cli gen ts <directory>
+  gsp-cli gen arduino <directory>
+  gsp-cli gen json <file>      (with the json feature)
+  gsp-cli gen vectors <file>
+  gsp-cli gen mutants <file>
+
+Targets:
+  unix:<path>    Connect to a Unix domain socket
+  tcp:<address>  Connect to a TCP server such as ser2net, reconnecting if the connection drops
+  pipe:<name>    Connect to a Windows named pipe
+  <path>         Open a serial device such as /dev/ttyUSB0 or COM3 (configure the baud rate
+                 beforehand, e.g. with stty or mode)
+
+Messages:
+  noop
+  u8 <num>
+  u16 <num>
+  u32 <num>
+  u64 <num>
+  i8 <num>
+  i16 <num>
+  i32 <num>
+  i64 <num>
+  f32 <num>
+  f64 <num>
+  bool <true|false>
+  u16array <num,...>
+  i16array <num,...>
+  u32array <num,...>
+  i32array <num,...>
+  f32array <num,...>
+  f64array <num,...>
+  bytes <hex>
+  string <text>
+  multi <num> <text>
+  status <ok|error|pending>
+  settings [baud_rate=<num>] [retries=<num>]
+  counter <num>
+  timestamp <micros>
+  log <trace|debug|info|warn|error> <module> <text>
+  command <id> <hex>
+  error <code> <text>
+  json <text>
+  identify
+  deviceinfo <device_id> <name> <hw_rev> <fw_version> <capabilities>
+  goodbye
+
+Unsigned numbers may be decimal or prefixed with 0x for hex.";
+
+enum Connection {
+    #[cfg(unix)]
+    Unix(UnixStream),
+    Device(File),
+    Tcp(ReconnectingConnection<TcpStream>),
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+            Connection::Device(file) => file.read(buf),
+            Connection::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+            Connection::Device(file) => file.write(buf),
+            Connection::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+            Connection::Device(file) => file.flush(),
+            Connection::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Prints every raw frame as hex
+struct HexDump;
+
+impl Observer for HexDump {
+    fn on_raw_frame_sent(&mut self, frame: &[u8]) {
+        println!("tx {}", hex(frame));
+    }
+
+    fn on_raw_frame_received(&mut self, frame: &[u8]) {
+        println!("rx {}", hex(frame));
+    }
+
+    fn on_resync(&mut self) {
+        println!("-- resync");
+    }
+
+    fn on_bytes_skipped(&mut self, count: usize) {
+        println!("-- skipped {count} bytes");
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, rest @ ..] if command == "listen" => match rest {
+            [flag, target] if flag == "--raw" => listen(target, true),
+            [target] => listen(target, false),
+            _ => Err(USAGE.to_string()),
+        },
+        [command, target, message @ ..] if command == "send" && !message.is_empty() => {
+            let message = parse_message(message)?;
+            let mut manager = SerialManager::new(open(target)?);
+            manager.send(message).map_err(|e| e.to_string())
+        }
+        [command, frame] if command == "dump" => {
+            print!("{}", dump_frame(&parse_hex(frame)?));
+            Ok(())
+        }
+        [command, capture, path] if command == "report" => report(capture, path),
+        #[cfg(unix)]
+        [command, rest @ ..] if command == "discover" && rest.len() <= 1 => {
+            let mut discovery = Discovery::new();
+            if let [device_id] = rest {
+                discovery = discovery.with_device_id(parse_number(device_id)?);
+            }
+            for port in discovery.run() {
+                let info = port.info;
+                println!(
+                    "{}  {:#010x}  {} (hardware {}, firmware {})",
+                    port.path.display(),
+                    info.device_id,
+                    info.name,
+                    info.hw_rev,
+                    info.fw_version
+                );
+            }
+            Ok(())
+        }
+        [command, language, directory] if command == "gen" && language == "c" => {
+            codegen::c::write_files(directory).map_err(|e| format!("{directory}: {e}"))
+        }
+        [command, language, directory] if command == "gen" && language == "arduino" => {
+            codegen::arduino::write_files(directory).map_err(|e| format!("{directory}: {e}"))
+        }
+        [command, language, directory] if command == "gen" && language == "ts" => {
+            codegen::typescript::write_file(directory).map_err(|e| format!("{directory}: {e}"))
+        }
+        #[cfg(feature = "json")]
+        [command, language, path] if command == "gen" && language == "json" => {
+            let schema = generic_serial_protocol::schema::export_json();
+            std::fs::write(path, schema).map_err(|e| format!("{path}: {e}"))
+        }
+        [command, kind, path] if command == "gen" && kind == "vectors" => {
+            std::fs::write(path, vectors::export()).map_err(|e| format!("{path}: {e}"))
+        }
+        [command, kind, path] if command == "gen" && kind == "mutants" => {
+            std::fs::write(path, vectors::export_mutants()).map_err(|e| format!("{path}: {e}"))
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn listen(target: &str, raw: bool) -> Result<(), String> {
+    let mut manager = SerialManager::new(open(target)?).with_unknown_messages(true);
+    if raw {
+        manager.set_observer(HexDump);
+    }
+
+    loop {
+        match manager.receive() {
+            Ok(message) => println!("{message}"),
+            Err(
+                e @ (ReceiveError::Decode { .. }
+                | ReceiveError::LimitExceeded { .. }
+                | ReceiveError::PeerUnresponsive),
+            ) => {
+                eprintln!("{e}");
+            }
+            Err(ReceiveError::UnexpectedBytes(bytes)) => {
+                eprintln!("unexpected bytes: {bytes:02X?}");
+            }
+            Err(ReceiveError::PeerClosed) => {
+                eprintln!("peer closed the connection");
+                return Ok(());
+            }
+            Err(ReceiveError::Io(e)) => return Err(e.to_string()),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+fn report(capture: &str, path: &str) -> Result<(), String> {
+    let bytes = std::fs::read(capture).map_err(|e| format!("{capture}: {e}"))?;
+    // Every pcapng file starts with a section header block
+    let captured = if bytes.starts_with(&[0x0A, 0x0D, 0x0D, 0x0A]) {
+        read_pcapng(bytes.as_slice())
+    } else {
+        read_byte_log(bytes.as_slice())
+    }
+    .map_err(|e| format!("{capture}: {e}"))?;
+    let html = Timeline::new().with_title(capture).render(&captured);
+    std::fs::write(path, html).map_err(|e| format!("{path}: {e}"))
+}
+
+fn open(target: &str) -> Result<Connection, String> {
+    if let Some(address) = target.strip_prefix("tcp:") {
+        let connection = ReconnectingConnection::tcp(address.to_string()).with_max_attempts(5);
+        return Ok(Connection::Tcp(connection));
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = target.strip_prefix("unix:") {
+        return UnixStream::connect(path)
+            .map(Connection::Unix)
+            .map_err(|e| format!("{path}: {e}"));
+    }
+
+    #[cfg(windows)]
+    let path = &if let Some(name) = target.strip_prefix("pipe:") {
+        format!(r"\\.\pipe\{name}")
+    } else if target.starts_with(r"\\") {
+        target.to_string()
+    } else {
+        // COM10 and above can only be opened through the device namespace
+        format!(r"\\.\{target}")
+    };
+    #[cfg(not(windows))]
+    let path = target;
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map(Connection::Device)
+        .map_err(|e| format!("{target}: {e}"))
+}
+
+fn parse_message(args: &[String]) -> Result<Message, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    Ok(match args.as_slice() {
+        ["noop"] => Message::NoOp(message_types::NoOp {}),
+        ["u8", num] => Message::U8(message_types::U8 {
+            num: parse_number(num)?,
+        }),
+        ["u16", num] => Message::U16(message_types::U16 {
+            num: parse_number(num)?,
+        }),
+        ["u32", num] => Message::U32(message_types::U32 {
+            num: parse_number(num)?,
+        }),
+        ["u64", num] => Message::U64(message_types::U64 {
+            num: parse_number(num)?,
+        }),
+        ["i8", num] => Message::I8(message_types::I8 {
+            num: parse_value(num)?,
+        }),
+        ["i16", num] => Message::I16(message_types::I16 {
+            num: parse_value(num)?,
+        }),
+        ["i32", num] => Message::I32(message_types::I32 {
+            num: parse_value(num)?,
+        }),
+        ["i64", num] => Message::I64(message_types::I64 {
+            num: parse_value(num)?,
+        }),
+        ["f32", num] => Message::F32(message_types::F32 {
+            num: parse_value(num)?,
+        }),
+        ["f64", num] => Message::F64(message_types::F64 {
+            num: parse_value(num)?,
+        }),
+        ["bool", value] => Message::Bool(message_types::Bool {
+            value: parse_value(value)?,
+        }),
+        ["u16array", values] => Message::U16Array(message_types::U16Array {
+            values: parse_list(values, parse_number)?,
+        }),
+        ["i16array", values] => Message::I16Array(message_types::I16Array {
+            values: parse_list(values, parse_value)?,
+        }),
+        ["u32array", values] => Message::U32Array(message_types::U32Array {
+            values: parse_list(values, parse_number)?,
+        }),
+        ["i32array", values] => Message::I32Array(message_types::I32Array {
+            values: parse_list(values, parse_value)?,
+        }),
+        ["f32array", values] => Message::F32Array(message_types::F32Array {
+            values: parse_list(values, parse_value)?,
+        }),
+        ["f64array", values] => Message::F64Array(message_types::F64Array {
+            values: parse_list(values, parse_value)?,
+        }),
+        ["bytes", data] => Message::Bytes(message_types::Bytes {
+            data: parse_hex(data)?,
+        }),
+        ["string", string] => Message::MyString(message_types::MyString {
+            string: (*string).to_string(),
+        }),
+        ["multi", num, string] => Message::Multi(message_types::Multi {
+            num: parse_number(num)?,
+            string: (*string).to_string(),
+        }),
+        ["status", status] => Message::Status(match *status {
+            "ok" => message_types::Status::Ok,
+            "error" => message_types::Status::Error,
+            "pending" => message_types::Status::Pending,
+            _ => return Err(format!("invalid status: {status}")),
+        }),
+        ["counter", count] => Message::Counter(message_types::Counter {
+            count: Varint(parse_number(count)?),
+        }),
+        ["timestamp", micros] => Message::Timestamp(message_types::Timestamp {
+            micros: parse_number(micros)?,
+        }),
+        ["log", level, module, text] => Message::Log(message_types::Log {
+            level: match *level {
+                "trace" => message_types::Level::Trace,
+                "debug" => message_types::Level::Debug,
+                "info" => message_types::Level::Info,
+                "warn" => message_types::Level::Warn,
+                "error" => message_types::Level::Error,
+                _ => return Err(format!("invalid level: {level}")),
+            },
+            module: (*module).to_string(),
+            text: (*text).to_string(),
+        }),
+        ["command", id, args] => Message::Command(message_types::Command {
+            id: parse_number(id)?,
+            args: parse_hex(args)?,
+        }),
+        ["error", code, detail] => Message::ErrorReport(message_types::ErrorReport {
+            code: parse_number(code)?,
+            detail: (*detail).to_string(),
+        }),
+        ["json", text] => Message::Json(message_types::Json {
+            text: (*text).to_string(),
+        }),
+        ["identify"] => Message::Identify(message_types::Identify {}),
+        ["goodbye"] => Message::Goodbye(message_types::Goodbye {}),
+        ["deviceinfo", device_id, name, hw_rev, fw_version, capabilities] => {
+            Message::DeviceInfo(message_types::DeviceInfo {
+                device_id: parse_number(device_id)?,
+                name: (*name).to_string(),
+                hw_rev: parse_number(hw_rev)?,
+                fw_version: (*fw_version).to_string(),
+                capabilities: Capabilities::from_bits(parse_number(capabilities)?),
+            })
+        }
+        ["settings", settings @ ..] => Message::Settings(parse_settings(settings)?),
+        _ => return Err(USAGE.to_string()),
+    })
+}
+
+/// Parses `key=value` pairs, leaving out any settings not given
+fn parse_settings(settings: &[&str]) -> Result<message_types::Settings, String> {
+    let mut parsed = message_types::Settings {
+        baud_rate: None,
+        retries: None,
+    };
+    for setting in settings {
+        match setting.split_once('=') {
+            Some(("baud_rate", value)) => parsed.baud_rate = Some(parse_number(value)?),
+            Some(("retries", value)) => parsed.retries = Some(parse_number(value)?),
+            _ => return Err(format!("invalid setting: {setting}")),
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_number<N>(text: &str) -> Result<N, String>
+where
+    N: TryFrom<u64>,
+{
+    let number = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|e| format!("invalid number {text}: {e}"))?;
+    N::try_from(number).map_err(|_| format!("number out of range: {text}"))
+}
+
+/// Parses signed numbers, floats and bools, which have no hex form
+fn parse_value<T>(text: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    text.parse()
+        .map_err(|e| format!("invalid value {text}: {e}"))
+}
+
+/// Parses a comma-separated list, where an empty string is an empty list
+fn parse_list<T>(text: &str, parse: fn(&str) -> Result<T, String>) -> Result<Vec<T>, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',').map(parse).collect()
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits: {text}"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex: {text}"))
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, byte| {
+        let _ = write!(output, "{byte:02x}");
+        output
+    })
+}
@@ -1,79 +1,496 @@
-use crate::errors::DecodeError;
-use crate::message_types;
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Message {
-    Bytes(message_types::Bytes),
-    U8(message_types::U8),
-    MyString(message_types::MyString),
-    Multi(message_types::Multi),
-    NoOp(message_types::NoOp),
-    U16(message_types::U16),
-    Status(message_types::Status),
+use crate::codec::{encode_frame, Decoder, DecoderEvent, Endianness};
+use crate::errors::{DecodeError, EncodeError};
+use crate::payload::{define_messages, Field, Payload};
+use crate::schema::{self, FieldType, MessageDescriptor};
+use std::ops::{BitOr, BitOrAssign};
+
+define_messages! {
+    0 => struct Bytes {
+        data: Vec<u8>,
+    },
+    1 => struct U8 {
+        num: u8,
+    },
+    2 => struct MyString {
+        string: String,
+    },
+    3 => struct Multi {
+        num: u8,
+        string: String,
+    },
+    4 => struct NoOp {},
+    5 => struct U16 {
+        num: u16,
+    },
+    6 => enum Status {
+        Ok = 0,
+        /// Details can be sent in an [`ErrorReport`], e.g. with [`Response::error`]
+        Error = 1,
+        Pending = 2,
+    },
+    7 => struct U32 {
+        num: u32,
+    },
+    8 => struct U64 {
+        num: u64,
+    },
+    9 => struct I8 {
+        num: i8,
+    },
+    10 => struct I16 {
+        num: i16,
+    },
+    11 => struct I32 {
+        num: i32,
+    },
+    12 => struct I64 {
+        num: i64,
+    },
+    13 => struct F32 {
+        num: f32,
+    },
+    14 => struct F64 {
+        num: f64,
+    },
+    15 => struct Bool {
+        value: bool,
+    },
+    16 => struct U16Array {
+        values: Vec<u16>,
+    },
+    17 => struct I16Array {
+        values: Vec<i16>,
+    },
+    18 => struct U32Array {
+        values: Vec<u32>,
+    },
+    19 => struct I32Array {
+        values: Vec<i32>,
+    },
+    20 => struct F32Array {
+        values: Vec<f32>,
+    },
+    21 => struct F64Array {
+        values: Vec<f64>,
+    },
+    22 => tlv Settings {
+        1 => baud_rate: Option<u32>,
+        2 => retries: Option<u8>,
+    },
+    23 => struct Counter {
+        count: Varint<u64>,
+    },
+    24 => struct Timestamp {
+        /// Microseconds, since an epoch agreed between the peers
+        micros: u64,
+    },
+    enum Level {
+        Trace = 0,
+        Debug = 1,
+        Info = 2,
+        Warn = 3,
+        Error = 4,
+    },
+    25 => tlv Log {
+        1 => level: Level,
+        2 => module: String,
+        3 => text: String,
+    },
+    26 => struct Command {
+        /// Chosen by the sender and echoed in the [`Response`]
+        id: u16,
+        args: Vec<u8>,
+    },
+    27 => struct Response {
+        /// The ID of the [`Command`] this responds to
+        id: u16,
+        status: Status,
+        payload: Vec<u8>,
+    },
+    28 => struct ErrorReport {
+        /// Application-defined
+        code: u16,
+        detail: String,
+    },
+    29 => struct FirmwareBegin {
+        /// Of the whole image, in bytes
+        size: u32,
+        crc: u32,
+    },
+    30 => struct FirmwareChunk {
+        /// Of the first byte of `data` in the image
+        offset: u32,
+        /// Of `data`
+        crc: u32,
+        data: Vec<u8>,
+    },
+    31 => struct FirmwareAck {
+        /// Of the next image byte expected
+        offset: u32,
+    },
+    32 => struct FirmwareCommit {
+        /// Of the whole image
+        crc: u32,
+    },
+    33 => struct TimeRequest {
+        /// When the request was sent, in microseconds on the requester's clock
+        origin: u64,
+    },
+    34 => struct TimeResponse {
+        /// Copied from the request
+        origin: u64,
+        /// When the request arrived, in microseconds on the responder's clock
+        receive: u64,
+        /// When the response was sent, in microseconds on the responder's clock
+        transmit: u64,
+    },
+    35 => struct Ping {
+        sequence: u16,
+    },
+    36 => struct Pong {
+        /// Copied from the [`Ping`] this replies to
+        sequence: u16,
+    },
+    /// A JSON document, for configuration and debugging traffic where readability matters more
+    /// than size
+    ///
+    /// The document is carried as text rather than a `serde_json::Value`, so that the message
+    /// type is the same with or without the `json` feature. With the feature, `Json::new` and
+    /// `From<serde_json::Value>` build it from typed values, `Json::value` and `Json::parse`
+    /// parse it, and receiving a `Json` message whose text is not valid JSON fails with
+    /// [`DecodeError::InvalidJson`](crate::DecodeError::InvalidJson). Without it, the text is
+    /// not checked.
+    37 => struct Json {
+        /// The JSON text, as sent
+        text: String,
+    },
+    /// Asks the peer to reply with its [`DeviceInfo`]
+    38 => struct Identify {},
+    /// Describes the peer, so that hosts can adapt to the connected device
+    39 => tlv DeviceInfo {
+        /// The kind of device, such as a product ID
+        1 => device_id: u32,
+        /// Human-readable, such as a product name
+        2 => name: String,
+        /// The hardware revision
+        3 => hw_rev: u16,
+        /// The firmware version, such as `1.4.2`
+        4 => fw_version: String,
+        5 => capabilities: crate::Capabilities,
+    },
+    /// Tells the peer that the connection is being closed on purpose, sent by
+    /// [`SerialManager::close`](crate::SerialManager::close)
+    40 => struct Goodbye {},
+    /// Starts a patch of a blob the peer holds, sent by [`PatchUpdate`](crate::PatchUpdate)
+    41 => struct PatchBegin {
+        /// Of the blob the peer must hold for the patch to apply
+        base_crc: u32,
+        /// Of the patched blob, in bytes
+        size: u32,
+        /// Of the patched blob
+        crc: u32,
+    },
+    /// Bytes of the patched blob that differ from the base
+    42 => struct PatchBlock {
+        /// Of the first byte of `data` in the patched blob
+        offset: u32,
+        data: Vec<u8>,
+    },
+    /// Asks the peer to check and keep the patched blob
+    43 => struct PatchCommit {},
+    /// Bytes sent on a virtual channel, within the credit the peer granted for it
+    44 => struct ChannelData {
+        channel: u8,
+        data: Vec<u8>,
+    },
+    /// Grants the peer credit to send more bytes on a virtual channel
+    45 => struct ChannelCredit {
+        channel: u8,
+        /// Bytes, added to any credit granted before
+        credits: u32,
+    },
+    /// A message sent by a [`Reliable`](crate::Reliable) sender, which the peer acknowledges
+    46 => struct ReliableData {
+        /// Numbers the message, for acknowledging it and recognising it when sent again
+        id: u32,
+        message_type: u16,
+        /// The payload of the message, in the frame's byte order
+        data: Vec<u8>,
+    },
+    /// Acknowledges the `ReliableData` with `id`
+    47 => struct ReliableAck {
+        id: u32,
+    },
+    /// Asks the peer to echo `nonce` back in a [`ChallengeResponse`], sent by a
+    /// [`Watchdog`](crate::Watchdog) checking that the peer's protocol handling is alive
+    48 => struct Challenge {
+        nonce: u32,
+    },
+    /// Answers a [`Challenge`], sent by [`SerialManager`](crate::SerialManager) when it receives
+    /// one
+    49 => struct ChallengeResponse {
+        /// Copied from the challenge
+        nonce: u32,
+    },
+    /// A message of a transaction, held by the peer until the transaction is committed, sent by
+    /// [`Transaction`](crate::Transaction)
+    50 => struct TransactionData {
+        /// Chosen by the sender, the same for every message of the transaction
+        id: u16,
+        message_type: u16,
+        /// The payload of the message, in the frame's byte order
+        data: Vec<u8>,
+    },
+    /// Asks the peer to apply the messages of a transaction together, which it answers with a
+    /// [`Status`]
+    51 => struct TransactionCommit {
+        id: u16,
+        /// How many `TransactionData` the transaction holds, so that one lost is noticed
+        count: u16,
+    },
+    /// Tells the peer to discard the messages of a transaction
+    52 => struct TransactionAbort {
+        id: u16,
+    },
+}
+
+/// Optional protocol features a peer supports, sent in
+/// [`DeviceInfo`](message_types::DeviceInfo) as a u32 of flags
+///
+/// Bits 0–15 are reserved for the features below and ones added later. Applications can use bits
+/// 16–31 for their own features. Bits a peer does not know about are kept, so that they can be
+/// passed on.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Replies to `Ping` with `Pong`
+    pub const PING: Self = Self(1 << 0);
+    /// Replies to `TimeRequest` with `TimeResponse`
+    pub const TIME_SYNC: Self = Self(1 << 1);
+    /// Accepts firmware updates with `FirmwareBegin`, `FirmwareChunk` and `FirmwareCommit`
+    pub const FIRMWARE_UPDATE: Self = Self(1 << 2);
+    /// Understands `Json` messages
+    pub const JSON: Self = Self(1 << 3);
+    /// Sends `Log` messages
+    pub const LOG: Self = Self(1 << 4);
+    /// Can switch to [`Framing::Compact`](crate::Framing::Compact)
+    pub const COMPACT_FRAMING: Self = Self(1 << 5);
+    /// Can switch to [`Framing::Extended`](crate::Framing::Extended)
+    pub const EXTENDED_FRAMING: Self = Self(1 << 6);
+    /// Can switch to [`Framing::Binary`](crate::Framing::Binary)
+    pub const BINARY_FRAMING: Self = Self(1 << 7);
+    /// Accepts patches with `PatchBegin`, `PatchBlock` and `PatchCommit`
+    pub const PATCH: Self = Self(1 << 8);
+    /// Can switch to [`Framing::CheckedHeader`](crate::Framing::CheckedHeader)
+    pub const CHECKED_HEADER_FRAMING: Self = Self(1 << 9);
+    /// Multiplexes virtual channels with `ChannelData` and `ChannelCredit`
+    pub const CHANNELS: Self = Self(1 << 10);
+    /// Acknowledges `ReliableData` with `ReliableAck`
+    pub const RELIABLE: Self = Self(1 << 11);
+    /// Agrees on framing and layers in a handshake, see
+    /// [`Negotiation`](crate::Negotiation)
+    pub const NEGOTIATION: Self = Self(1 << 12);
+    /// Can stack a [`Crc32`](crate::layer::Crc32) layer closest to the wire
+    pub const CRC32: Self = Self(1 << 13);
+    /// Can stack the application's compression layer
+    pub const COMPRESSION: Self = Self(1 << 14);
+    /// Can stack the application's encryption layer
+    pub const ENCRYPTION: Self = Self(1 << 15);
+
+    /// No capabilities
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    #[must_use]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether every capability in `other` is also in `self`
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Capabilities {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+impl Field for Capabilities {
+    const TYPE: FieldType = FieldType::U32;
+
+    fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+        self.0.encode(bytes, endianness)
+    }
+
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        u32::decode(bytes, endianness).map(Self)
+    }
 }
 
 impl Message {
+    /// The name of the message's type, such as `Ping`, if it is built in
     #[must_use]
-    pub fn message_type(&self) -> u16 {
-        match self {
-            Message::Bytes(_) => 0,
-            Message::U8(_) => 1,
-            Message::MyString(_) => 2,
-            Message::Multi(_) => 3,
-            Message::NoOp(_) => 4,
-            Message::U16(_) => 5,
-            Message::Status(_) => 6,
+    pub fn name(&self) -> Option<&'static str> {
+        schema::message_name(self.message_type())
+    }
+
+    /// The reading carried by a message holding a single number
+    pub(crate) fn reading(&self) -> Option<f64> {
+        Some(match self {
+            Message::U8(message_types::U8 { num }) => f64::from(*num),
+            Message::U16(message_types::U16 { num }) => f64::from(*num),
+            Message::U32(message_types::U32 { num }) => f64::from(*num),
+            Message::I8(message_types::I8 { num }) => f64::from(*num),
+            Message::I16(message_types::I16 { num }) => f64::from(*num),
+            Message::I32(message_types::I32 { num }) => f64::from(*num),
+            Message::F32(message_types::F32 { num }) => f64::from(*num),
+            Message::F64(message_types::F64 { num }) => *num,
+            _ => return None,
+        })
+    }
+}
+
+impl message_types::Response {
+    /// A response with [`Status::Error`](message_types::Status::Error) whose payload is the
+    /// encoded `report`, with numbers in the given byte order, which must be that of the
+    /// connection it is sent on
+    #[must_use]
+    pub fn error(id: u16, report: &message_types::ErrorReport, endianness: Endianness) -> Self {
+        let mut payload = Vec::new();
+        Payload::encode(report, &mut payload, endianness)
+            .unwrap_or_else(|_| unreachable!("an error report has no lengths to overflow"));
+        Self {
+            id,
+            status: message_types::Status::Error,
+            payload,
         }
     }
 
+    /// Decodes the error report in the payload of an error response, if there is one, with
+    /// numbers in the given byte order, which must be that of the connection it arrived on
     #[must_use]
-    pub fn to_bytes(self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-
-        match self {
-            Message::Bytes(msg) => bytes.extend(msg.data),
-            Message::U8(msg) => bytes.push(msg.num),
-            Message::MyString(msg) => bytes.extend(msg.string.as_bytes()),
-            Message::Multi(msg) => {
-                bytes.push(msg.num);
-                bytes.extend(msg.string.as_bytes());
-            }
-            Message::NoOp(_) => {}
-            Message::U16(msg) => bytes.extend(msg.num.to_le_bytes()),
-            Message::Status(status) => bytes.push(match status {
-                message_types::Status::Ok => 0,
-                message_types::Status::Error => 1,
-                message_types::Status::Pending => 2,
-            }),
+    pub fn error_report(
+        &self,
+        endianness: Endianness,
+    ) -> Option<Result<message_types::ErrorReport, DecodeError>> {
+        (self.status == message_types::Status::Error && !self.payload.is_empty())
+            .then(|| Payload::decode(&self.payload, endianness))
+    }
+}
+
+/// Encodes `message` into a frame and decodes it again, as a peer receiving it would
+///
+/// Every message should come back encoding to the same bytes, and equal to the original unless it
+/// holds a NaN. This is meant for property tests and fuzzing, such as with the `Arbitrary`
+/// implementations enabled by the `arbitrary` feature.
+///
+/// # Panics
+///
+/// Panics if the message does not encode, or if the encoded frame is not decoded as a single
+/// frame, which would be a bug in the framing.
+pub fn roundtrip(message: Message) -> Result<Message, DecodeError> {
+    let message_type = message.message_type();
+    let frame = encode_frame(
+        message_type,
+        &message.to_bytes().expect("message does not encode"),
+    );
+    let mut decoder = Decoder::new();
+    let events: Vec<_> = frame
+        .iter()
+        .filter_map(|&byte| decoder.push(byte))
+        .collect();
+    let [DecoderEvent::Frame(frame)] = events.as_slice() else {
+        panic!("encoded frame decoded as {events:?}");
+    };
+    Message::from_bytes(frame.message_type, frame.payload.clone())
+}
+
+#[cfg(feature = "json")]
+impl message_types::Json {
+    /// Serializes `value` as the JSON text
+    pub fn new<T: serde::Serialize + ?Sized>(value: &T) -> serde_json::Result<Self> {
+        serde_json::to_string(value).map(|text| Self { text })
+    }
+
+    /// Parses the JSON text as a `serde_json::Value`, failing if it is not valid JSON
+    pub fn value(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::from_str(&self.text)
+    }
+
+    /// Parses the JSON text as a `T`, failing if it is not valid JSON or not a `T`
+    pub fn parse<T: serde::de::DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_str(&self.text)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&serde_json::Value> for message_types::Json {
+    fn from(value: &serde_json::Value) -> Self {
+        Self {
+            text: value.to_string(),
         }
+    }
+}
 
-        bytes
-    }
-
-    /// Creates a Message from its raw byte representation
-    pub fn from_bytes(message_type: u16, data: Vec<u8>) -> Result<Self, DecodeError> {
-        Ok(match message_type {
-            0 => Message::Bytes(message_types::Bytes { data }),
-            1 => Message::U8(message_types::U8 { num: data[0] }),
-            2 => Message::MyString(message_types::MyString {
-                string: String::from_utf8(data)?,
-            }),
-            3 => Message::Multi(message_types::Multi {
-                num: data[0],
-                string: String::from_utf8(data[1..].to_vec())?,
-            }),
-            4 => Message::NoOp(message_types::NoOp {}),
-            5 => Message::U16(message_types::U16 {
-                num: u16::from_le_bytes([data[0], data[1]]),
-            }),
-            #[allow(clippy::match_on_vec_items)]
-            6 => Message::Status(match data[0] {
-                0 => message_types::Status::Ok,
-                1 => message_types::Status::Error,
-                2 => message_types::Status::Pending,
-                invalid => return Err(DecodeError::InvalidEnumValue(invalid)),
-            }),
-            _ => return Err(DecodeError::InvalidMessageType(message_type)),
-        })
+#[cfg(feature = "json")]
+impl From<serde_json::Value> for message_types::Json {
+    fn from(value: serde_json::Value) -> Self {
+        Self::from(&value)
+    }
+}
+
+#[cfg(feature = "json")]
+impl Message {
+    /// Fails on a `Json` message whose text is not valid JSON, so that it is rejected when
+    /// received rather than when parsed
+    pub(crate) fn check_json(&self) -> Result<(), DecodeError> {
+        if let Message::Json(json) = self {
+            serde_json::from_str::<serde::de::IgnoredAny>(&json.text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl message_types::Log {
+    /// Emits the log as a `tracing` event with the `device` target, so that logs from the peer
+    /// show up alongside the host's own
+    pub fn emit(&self) {
+        use message_types::Level;
+
+        let module = &self.module;
+        let text = &self.text;
+        match self.level {
+            Level::Trace => tracing::trace!(target: "device", %module, "{text}"),
+            Level::Debug => tracing::debug!(target: "device", %module, "{text}"),
+            Level::Info => tracing::info!(target: "device", %module, "{text}"),
+            Level::Warn => tracing::warn!(target: "device", %module, "{text}"),
+            Level::Error => tracing::error!(target: "device", %module, "{text}"),
+        }
     }
 }
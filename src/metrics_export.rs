@@ -0,0 +1,152 @@
+//! Publishing of [`Stats`] through the `metrics` facade.
+
+use crate::stats::Stats;
+use metrics::{counter, describe_counter, describe_histogram, histogram, Counter, Histogram, Unit};
+use std::time::Duration;
+
+/// A counter published for every manager, with the stats field it counts
+struct CounterMetric {
+    name: &'static str,
+    unit: Unit,
+    description: &'static str,
+    value: fn(&Stats) -> u64,
+}
+
+const COUNTERS: [CounterMetric; 13] = [
+    CounterMetric {
+        name: "gsp_frames_sent_total",
+        unit: Unit::Count,
+        description: "Frames written to the connection",
+        value: |stats| stats.frames_sent,
+    },
+    CounterMetric {
+        name: "gsp_frames_received_total",
+        unit: Unit::Count,
+        description: "Frames received and decoded",
+        value: |stats| stats.frames_received,
+    },
+    CounterMetric {
+        name: "gsp_bytes_sent_total",
+        unit: Unit::Bytes,
+        description: "Bytes written to the connection, including start and escape bytes",
+        value: |stats| stats.bytes_sent,
+    },
+    CounterMetric {
+        name: "gsp_bytes_received_total",
+        unit: Unit::Bytes,
+        description: "Bytes read from the connection, including skipped garbage",
+        value: |stats| stats.bytes_received,
+    },
+    CounterMetric {
+        name: "gsp_escape_bytes_sent_total",
+        unit: Unit::Bytes,
+        description: "Escape bytes added to outgoing frames",
+        value: |stats| stats.escape_bytes_sent,
+    },
+    CounterMetric {
+        name: "gsp_escape_bytes_received_total",
+        unit: Unit::Bytes,
+        description: "Escape bytes removed from incoming frames",
+        value: |stats| stats.escape_bytes_received,
+    },
+    CounterMetric {
+        name: "gsp_resyncs_total",
+        unit: Unit::Count,
+        description: "Partially received frames abandoned to resync",
+        value: |stats| stats.resyncs,
+    },
+    CounterMetric {
+        name: "gsp_bytes_skipped_total",
+        unit: Unit::Bytes,
+        description: "Bytes skipped outside any frame",
+        value: |stats| stats.bytes_skipped,
+    },
+    CounterMetric {
+        name: "gsp_decode_errors_total",
+        unit: Unit::Count,
+        description: "Complete frames that could not be decoded",
+        value: |stats| stats.decode_errors,
+    },
+    CounterMetric {
+        name: "gsp_crc_failures_total",
+        unit: Unit::Count,
+        description: "Frames dropped for failing a checksum",
+        value: |stats| stats.crc_failures,
+    },
+    CounterMetric {
+        name: "gsp_trailing_bytes_total",
+        unit: Unit::Bytes,
+        description: "Bytes left over after the fields of received payloads",
+        value: |stats| stats.trailing_bytes,
+    },
+    CounterMetric {
+        name: "gsp_frames_limited_total",
+        unit: Unit::Count,
+        description: "Received frames dropped for exceeding their receive limit",
+        value: |stats| stats.frames_limited,
+    },
+    CounterMetric {
+        name: "gsp_pings_lost_total",
+        unit: Unit::Count,
+        description: "Pings not answered in time",
+        value: |stats| stats.latency.lost,
+    },
+];
+
+const ROUND_TRIP: &str = "gsp_round_trip_seconds";
+
+/// Publishes a manager's stats as `metrics` counters labelled with its link name, and round trip
+/// times as a histogram
+///
+/// The handles are registered once, so the recorder must be installed before the manager is
+/// created with them. Counters are increased by what the stats counted since they were last
+/// published, so that they never go down when the stats are reset.
+#[derive(Clone)]
+pub(crate) struct MetricsExporter {
+    counters: Vec<Counter>,
+    round_trip: Histogram,
+    published: Stats,
+}
+
+impl MetricsExporter {
+    pub(crate) fn new(link: &str) -> Self {
+        for metric in &COUNTERS {
+            describe_counter!(metric.name, metric.unit, metric.description);
+        }
+        describe_histogram!(
+            ROUND_TRIP,
+            Unit::Seconds,
+            "Round trip times of answered pings"
+        );
+
+        let counters = COUNTERS
+            .iter()
+            .map(|metric| counter!(metric.name, "link" => link.to_string()))
+            .collect();
+        Self {
+            counters,
+            round_trip: histogram!(ROUND_TRIP, "link" => link.to_string()),
+            published: Stats::default(),
+        }
+    }
+
+    /// Increases the counters by what `stats` counted since the last call
+    pub(crate) fn publish(&mut self, stats: &Stats) {
+        for (metric, counter) in COUNTERS.iter().zip(&self.counters) {
+            let delta = (metric.value)(stats).saturating_sub((metric.value)(&self.published));
+            if delta > 0 {
+                counter.increment(delta);
+            }
+        }
+        self.published = *stats;
+    }
+
+    /// Starts counting again from stats that have been reset to zero
+    pub(crate) fn reset(&mut self) {
+        self.published = Stats::default();
+    }
+
+    pub(crate) fn record_round_trip(&self, round_trip: Duration) {
+        self.round_trip.record(round_trip.as_secs_f64());
+    }
+}
@@ -0,0 +1,177 @@
+use crate::errors::{DecodeError, MqttError, ReceiveError};
+use crate::message::Message;
+use crate::schema;
+use crate::serial_manager::{SerialManager, Transport, TryClone};
+use rumqttc::{Client, Connection, Event, Packet, QoS};
+use std::sync::mpsc;
+use std::thread;
+
+/// Maps message types to MQTT topics under a prefix.
+///
+/// Messages received from the device are published to `<prefix>/<name>`, where the name is the
+/// [`Message`] variant, such as `gsp/Status`. Messages published to `<prefix>/send/<name>` are
+/// sent to the device. Message types that are not built in use their ID in hex instead of a
+/// name, such as `gsp/0x1234`.
+///
+/// MQTT payloads are the encoded message payloads, with little-endian numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    prefix: String,
+}
+
+impl Topics {
+    #[must_use]
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The topic that messages of `message_type` received from the device are published to
+    #[must_use]
+    pub fn publish_topic(&self, message_type: u16) -> String {
+        let prefix = &self.prefix;
+        match schema::message_name(message_type) {
+            Some(name) => format!("{prefix}/{name}"),
+            None => format!("{prefix}/{message_type:#06x}"),
+        }
+    }
+
+    /// The topic filter for messages to send to the device
+    #[must_use]
+    pub fn subscription(&self) -> String {
+        format!("{}/send/+", self.prefix)
+    }
+
+    /// Decodes a message published to one of the topics in [`subscription`](Self::subscription)
+    ///
+    /// Returns `None` if the topic is not one of them or names no message type.
+    #[must_use]
+    pub fn message(&self, topic: &str, payload: &[u8]) -> Option<Result<Message, DecodeError>> {
+        let name = topic
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix("/send/")?;
+        if let Some(message_type) = schema::message_id(name) {
+            return Some(Message::from_bytes(message_type, payload.to_vec()));
+        }
+        let message_type = u16::from_str_radix(name.strip_prefix("0x")?, 16).ok()?;
+        Some(Ok(Message::Unknown {
+            message_type,
+            data: payload.to_vec(),
+        }))
+    }
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self::new("gsp")
+    }
+}
+
+/// Publishes the messages received from a device to MQTT, and sends the messages published to
+/// MQTT to the device, using the topics described in [`Topics`].
+///
+/// ```no_run
+/// # use generic_serial_protocol::{MqttGateway, SerialManager, Topics};
+/// # let stream = std::fs::File::open("").unwrap();
+/// use rumqttc::{Client, MqttOptions};
+///
+/// let (client, connection) = Client::new(MqttOptions::new("gateway", "localhost", 1883), 16);
+/// let manager = SerialManager::new(stream).with_unknown_messages(true);
+/// MqttGateway::new(client, Topics::new("devices/pump"))
+///     .run(manager, connection)
+///     .unwrap();
+/// ```
+pub struct MqttGateway {
+    client: Client,
+    topics: Topics,
+    qos: QoS,
+}
+
+impl MqttGateway {
+    #[must_use]
+    pub fn new(client: Client, topics: Topics) -> Self {
+        Self {
+            client,
+            topics,
+            qos: QoS::AtLeastOnce,
+        }
+    }
+
+    /// Sets the quality of service to publish and subscribe with, which is at least once by
+    /// default
+    #[must_use]
+    pub fn with_qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Subscribes to the topics to send and forwards messages in both directions until either
+    /// the device or the MQTT connection fails
+    ///
+    /// Messages that fail to decode, from either side, are dropped.
+    pub fn run<T>(
+        self,
+        manager: SerialManager<T>,
+        mut connection: Connection,
+    ) -> Result<(), MqttError>
+    where
+        T: Transport + TryClone + Send + 'static,
+    {
+        let Self {
+            client,
+            topics,
+            qos,
+        } = self;
+        client.subscribe(topics.subscription(), qos)?;
+        let (outgoing, incoming) = manager.spawn()?;
+
+        let (error_sender, error_receiver) = mpsc::channel();
+        thread::spawn({
+            let client = client.clone();
+            let topics = topics.clone();
+            move || {
+                for result in incoming {
+                    let message = match result {
+                        Ok(message) => message,
+                        Err(ReceiveError::Io(e)) => {
+                            let _ = error_sender.send(e);
+                            // Wakes up the connection loop, which then returns the error
+                            let _ = client.disconnect();
+                            break;
+                        }
+                        Err(_) => continue,
+                    };
+                    let topic = topics.publish_topic(message.message_type());
+                    let Ok(payload) = message.to_bytes() else {
+                        continue;
+                    };
+                    if client.publish(topic, qos, false, payload).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        for event in connection.iter() {
+            if let Ok(e) = error_receiver.try_recv() {
+                return Err(e.into());
+            }
+            let Event::Incoming(Packet::Publish(publish)) = event.map_err(Box::new)? else {
+                continue;
+            };
+            if let Some(Ok(message)) = topics.message(&publish.topic, &publish.payload) {
+                if outgoing.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+        match error_receiver.try_recv() {
+            Ok(e) => Err(e.into()),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,43 @@
+use super::*;
+use crate::message_types;
+use crate::payload::MessageType;
+
+#[test]
+fn test_publish_topic() {
+    let topics = Topics::new("devices/pump");
+    assert_eq!(
+        topics.publish_topic(message_types::Status::ID),
+        "devices/pump/Status"
+    );
+    assert_eq!(topics.publish_topic(0x1234), "devices/pump/0x1234");
+}
+
+#[test]
+fn test_message() {
+    let topics = Topics::default();
+    assert_eq!(topics.subscription(), "gsp/send/+");
+    assert_eq!(
+        topics.message("gsp/send/U8", &[0x57]).unwrap().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eq!(
+        topics.message("gsp/send/0x1234", &[0xAB]).unwrap().unwrap(),
+        Message::Unknown {
+            message_type: 0x1234,
+            data: vec![0xAB]
+        }
+    );
+    assert!(matches!(
+        topics.message("gsp/send/U8", &[]),
+        Some(Err(DecodeError::InvalidLength(2)))
+    ));
+}
+
+#[test]
+fn test_other_topics_are_ignored() {
+    let topics = Topics::default();
+    assert!(topics.message("gsp/U8", &[0x57]).is_none());
+    assert!(topics.message("other/send/U8", &[0x57]).is_none());
+    assert!(topics.message("gsp/send/Nonsense", &[0x57]).is_none());
+    assert!(topics.message("gsp/send/0xZZ", &[0x57]).is_none());
+}
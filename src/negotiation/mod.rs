@@ -0,0 +1,143 @@
+use crate::codec::Framing;
+use crate::layer::{Crc32, Layer};
+use crate::message::{message_types, Capabilities};
+use std::fmt;
+
+/// Creates a fresh instance of a layer each time a negotiation agrees on it
+type LayerFactory = Box<dyn Fn() -> Box<dyn Layer + Send> + Send + Sync>;
+
+/// What one end of a link offers in the handshake of
+/// [`SerialManager::negotiate`](crate::SerialManager::negotiate) and
+/// [`accept_negotiation`](crate::SerialManager::accept_negotiation): its [`DeviceInfo`], whose
+/// capabilities list the framings it can switch to, and the layers it can stack
+///
+/// Both ends agree on the same configuration from the capabilities they exchange, without any
+/// further messages: the framing is the one with the highest capability bit both support, or the
+/// current framing if there is none, and the layers are those both offer, stacked in a fixed
+/// order on top of any already added: compression, then encryption, then [`Crc32`] closest to
+/// the wire. If either end lacks [`Capabilities::NEGOTIATION`] nothing changes, so that peers
+/// which predate negotiation keep working.
+///
+/// [`DeviceInfo`]: message_types::DeviceInfo
+pub struct Negotiation {
+    info: message_types::DeviceInfo,
+    compression: Option<LayerFactory>,
+    encryption: Option<LayerFactory>,
+}
+
+impl fmt::Debug for Negotiation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Negotiation")
+            .field("info", &self.info)
+            .field("compression", &self.compression.is_some())
+            .field("encryption", &self.encryption.is_some())
+            .finish()
+    }
+}
+
+/// The configuration two ends agreed on, which both have switched to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Negotiated {
+    /// The framing both ends now use
+    pub framing: Framing,
+    /// The capabilities both ends support, including the layers stacked, such as
+    /// [`Capabilities::CRC32`], or none if either end does not negotiate
+    pub capabilities: Capabilities,
+    /// What the peer sent in the handshake
+    pub peer: message_types::DeviceInfo,
+}
+
+/// Framings switched to by negotiation, with the capability a peer advertises for each
+const FRAMINGS: [(Capabilities, Framing); 4] = [
+    (Capabilities::COMPACT_FRAMING, Framing::Compact),
+    (Capabilities::EXTENDED_FRAMING, Framing::Extended),
+    (Capabilities::BINARY_FRAMING, Framing::Binary),
+    (Capabilities::CHECKED_HEADER_FRAMING, Framing::CheckedHeader),
+];
+
+impl Negotiation {
+    /// Offers the framings in `info`'s capabilities, and a [`Crc32`] layer if it includes
+    /// [`Capabilities::CRC32`]
+    ///
+    /// [`Capabilities::NEGOTIATION`] is added to the capabilities sent.
+    #[must_use]
+    pub fn new(mut info: message_types::DeviceInfo) -> Self {
+        info.capabilities.insert(Capabilities::NEGOTIATION);
+        Self {
+            info,
+            compression: None,
+            encryption: None,
+        }
+    }
+
+    /// Offers a compression layer, created by `layer` each time it is agreed on
+    #[must_use]
+    pub fn with_compression<L>(mut self, layer: impl Fn() -> L + Send + Sync + 'static) -> Self
+    where
+        L: Layer + Send + 'static,
+    {
+        self.info.capabilities.insert(Capabilities::COMPRESSION);
+        self.compression = Some(Box::new(move || Box::new(layer())));
+        self
+    }
+
+    /// Offers an encryption layer, created by `layer` each time it is agreed on
+    #[must_use]
+    pub fn with_encryption<L>(mut self, layer: impl Fn() -> L + Send + Sync + 'static) -> Self
+    where
+        L: Layer + Send + 'static,
+    {
+        self.info.capabilities.insert(Capabilities::ENCRYPTION);
+        self.encryption = Some(Box::new(move || Box::new(layer())));
+        self
+    }
+
+    /// The `DeviceInfo` sent in the handshake
+    #[must_use]
+    pub fn info(&self) -> &message_types::DeviceInfo {
+        &self.info
+    }
+
+    /// Works out the configuration agreed with a peer that sent `peer`, keeping `framing` if no
+    /// other framing is common to both
+    pub(crate) fn agree(&self, peer: message_types::DeviceInfo, framing: Framing) -> Negotiated {
+        let local = self.info.capabilities;
+        let mut capabilities = Capabilities::from_bits(local.bits() & peer.capabilities.bits());
+        if !capabilities.contains(Capabilities::NEGOTIATION) {
+            capabilities = Capabilities::empty();
+        }
+        let framing = FRAMINGS
+            .iter()
+            .rev()
+            .find(|(capability, _)| capabilities.contains(*capability))
+            .map_or(framing, |&(_, framing)| framing);
+        Negotiated {
+            framing,
+            capabilities,
+            peer,
+        }
+    }
+
+    /// Creates the layers agreed on, in the order they are stacked
+    pub(crate) fn layers(&self, negotiated: &Negotiated) -> Vec<Box<dyn Layer + Send>> {
+        let mut layers = Vec::new();
+        let offered = [
+            (Capabilities::COMPRESSION, &self.compression),
+            (Capabilities::ENCRYPTION, &self.encryption),
+        ];
+        for (capability, factory) in offered {
+            if let Some(factory) = factory.as_ref() {
+                if negotiated.capabilities.contains(capability) {
+                    layers.push(factory());
+                }
+            }
+        }
+        if negotiated.capabilities.contains(Capabilities::CRC32) {
+            layers.push(Box::new(Crc32));
+        }
+        layers
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+use crate::codec::Frame;
+use crate::errors::DecodeError;
+use crate::message::Message;
+use crate::test_util::stream_pair;
+use crate::SerialManager;
+use std::thread;
+use std::time::Duration;
+
+fn info(capabilities: Capabilities) -> message_types::DeviceInfo {
+    message_types::DeviceInfo {
+        device_id: 7,
+        name: "device".to_string(),
+        hw_rev: 1,
+        fw_version: "1.0.0".to_string(),
+        capabilities,
+    }
+}
+
+/// XORs every payload byte with a key, standing in for an application's encryption
+struct Xor(u8);
+
+impl Layer for Xor {
+    fn encode(&mut self, mut frame: Frame) -> Frame {
+        frame.payload.iter_mut().for_each(|byte| *byte ^= self.0);
+        frame
+    }
+
+    fn decode(&mut self, frame: Frame) -> Result<Option<Frame>, DecodeError> {
+        Ok(Some(self.encode(frame)))
+    }
+}
+
+#[test]
+fn test_agrees_on_the_highest_common_framing() {
+    let host = Negotiation::new(info(
+        Capabilities::COMPACT_FRAMING | Capabilities::EXTENDED_FRAMING | Capabilities::CRC32,
+    ));
+    let device = Negotiation::new(info(
+        Capabilities::COMPACT_FRAMING
+            | Capabilities::EXTENDED_FRAMING
+            | Capabilities::BINARY_FRAMING,
+    ));
+
+    let negotiated = host.agree(device.info().clone(), Framing::Native);
+    assert_eq!(negotiated.framing, Framing::Extended);
+    assert!(!negotiated.capabilities.contains(Capabilities::CRC32));
+    let reverse = device.agree(host.info().clone(), Framing::Native);
+    assert_eq!(reverse.framing, negotiated.framing);
+    assert_eq!(reverse.capabilities, negotiated.capabilities);
+    assert!(host.layers(&negotiated).is_empty());
+}
+
+#[test]
+fn test_nothing_changes_without_a_common_framing_or_negotiation() {
+    let host = Negotiation::new(info(Capabilities::COMPACT_FRAMING | Capabilities::CRC32));
+    let device = Negotiation::new(info(Capabilities::EXTENDED_FRAMING | Capabilities::CRC32));
+    let negotiated = host.agree(device.info().clone(), Framing::Slip);
+    assert_eq!(negotiated.framing, Framing::Slip);
+    assert_eq!(host.layers(&negotiated).len(), 1);
+
+    // A peer that predates negotiation, which does not switch whatever it supports
+    let negotiated = host.agree(
+        info(Capabilities::COMPACT_FRAMING | Capabilities::CRC32),
+        Framing::Native,
+    );
+    assert_eq!(negotiated.framing, Framing::Native);
+    assert_eq!(negotiated.capabilities, Capabilities::empty());
+    assert!(host.layers(&negotiated).is_empty());
+}
+
+#[test]
+fn test_negotiate_switches_both_ends() {
+    let (stream1, stream2) = stream_pair();
+    let mut host = SerialManager::new(stream1);
+    let mut device = SerialManager::new(stream2);
+    let reading = Message::U16(message_types::U16 { num: 0x1234 });
+
+    let expected = reading.clone();
+    let device = thread::spawn(move || {
+        let offers = [
+            Negotiation::new(info(Capabilities::COMPACT_FRAMING | Capabilities::CRC32))
+                .with_encryption(|| Xor(0x5A)),
+            Negotiation::new(info(Capabilities::COMPACT_FRAMING)),
+        ];
+        let mut agreed = Vec::new();
+        for negotiation in &offers {
+            let Message::DeviceInfo(peer) = device.receive().unwrap() else {
+                panic!("expected a DeviceInfo");
+            };
+            agreed.push(device.accept_negotiation(negotiation, peer).unwrap());
+            assert_eq!(device.receive().unwrap(), expected);
+            device.send(expected.clone()).unwrap();
+        }
+        agreed
+    });
+
+    let negotiation = Negotiation::new(info(
+        Capabilities::COMPACT_FRAMING | Capabilities::EXTENDED_FRAMING | Capabilities::CRC32,
+    ))
+    .with_compression(|| Xor(0xFF))
+    .with_encryption(|| Xor(0x5A));
+    let negotiated = host
+        .negotiate(&negotiation, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(negotiated.framing, Framing::Compact);
+    assert!(negotiated
+        .capabilities
+        .contains(Capabilities::ENCRYPTION | Capabilities::CRC32));
+    assert!(!negotiated.capabilities.contains(Capabilities::COMPRESSION));
+    assert_eq!(host.negotiated(), Some(&negotiated));
+    host.send(reading.clone()).unwrap();
+    assert_eq!(host.receive().unwrap(), reading);
+
+    // Negotiating again replaces the layers stacked the first time
+    let renegotiated = host
+        .negotiate(&negotiation, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(renegotiated.framing, Framing::Compact);
+    assert!(!renegotiated.capabilities.contains(Capabilities::CRC32));
+    host.send(reading.clone()).unwrap();
+    assert_eq!(host.receive().unwrap(), reading);
+
+    let agreed = device.join().unwrap();
+    for (device, host) in agreed.iter().zip([negotiated, renegotiated]) {
+        assert_eq!(device.framing, host.framing);
+        assert_eq!(device.capabilities, host.capabilities);
+    }
+}
@@ -0,0 +1,23 @@
+/// Hooks for observing the raw traffic of a [`SerialManager`](crate::SerialManager).
+///
+/// All methods have empty default implementations, so an observer only needs to implement the
+/// events it is interested in. Frames are passed exactly as they appear on the wire, including
+/// the start byte and any escape bytes.
+pub trait Observer {
+    /// Called after a complete frame has been written and flushed
+    fn on_raw_frame_sent(&mut self, _frame: &[u8]) {}
+
+    /// Called after a complete frame has been read, before it is decoded into a message
+    fn on_raw_frame_received(&mut self, _frame: &[u8]) {}
+
+    /// Called when a start byte interrupts a partially read frame
+    fn on_resync(&mut self) {}
+
+    /// Called when a start byte ends a run of `count` bytes outside any frame, such as line
+    /// noise or the rest of a frame whose length field was invalid
+    fn on_bytes_skipped(&mut self, _count: usize) {}
+
+    /// Called when a received payload has bytes left over after the fields of its message type,
+    /// if these are allowed
+    fn on_trailing_bytes(&mut self, _message_type: u16, _bytes: &[u8]) {}
+}
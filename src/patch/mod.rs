@@ -0,0 +1,232 @@
+use crate::codec::MAX_PAYLOAD_LENGTH;
+use crate::errors::PatchError;
+use crate::firmware::crc32;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use message_types::{PatchBegin, PatchBlock, PatchCommit, Status};
+
+/// The size of the blocks compared by [`diff_blocks`] unless configured otherwise
+pub const DEFAULT_BLOCK_SIZE: usize = 16;
+
+/// The most bytes a [`PatchBlock`] can carry, leaving room for its offset
+pub const MAX_BLOCK_DATA: usize = MAX_PAYLOAD_LENGTH - 4;
+
+/// Returns the blocks of `target` that differ from `base`, comparing them `block_size` bytes at
+/// a time
+///
+/// Changed blocks next to each other are sent as one [`PatchBlock`] of up to
+/// [`MAX_BLOCK_DATA`] bytes. Bytes of `target` past the end of `base` always differ. Smaller
+/// blocks send fewer unchanged bytes around each change, at the cost of more blocks.
+///
+/// # Panics
+///
+/// Panics if `block_size` is zero, or if `target` is larger than 4 GiB.
+#[must_use]
+pub fn diff_blocks(base: &[u8], target: &[u8], block_size: usize) -> Vec<PatchBlock> {
+    assert!(block_size > 0, "invalid block size: {block_size}");
+    let mut blocks: Vec<PatchBlock> = Vec::new();
+    // The end of the last block, so that the next one can be appended to it
+    let mut end = None;
+    for start in (0..target.len()).step_by(block_size) {
+        let stop = target.len().min(start + block_size);
+        if base.get(start..stop) == Some(&target[start..stop]) {
+            continue;
+        }
+        match blocks.last_mut() {
+            Some(block)
+                if end == Some(start) && block.data.len() + (stop - start) <= MAX_BLOCK_DATA =>
+            {
+                block.data.extend(&target[start..stop]);
+            }
+            _ => blocks.push(PatchBlock {
+                offset: u32::try_from(start).expect("blob larger than 4 GiB"),
+                data: target[start..stop].to_vec(),
+            }),
+        }
+        end = Some(stop);
+    }
+    blocks
+}
+
+/// Applies `blocks` to `base` resized to `size` bytes, returning the patched blob, or `None` if
+/// a block does not fit in it
+#[must_use]
+pub fn apply_patch(base: &[u8], size: usize, blocks: &[PatchBlock]) -> Option<Vec<u8>> {
+    let mut blob = base.to_vec();
+    blob.resize(size, 0);
+    for block in blocks {
+        write_block(&mut blob, block).then_some(())?;
+    }
+    Some(blob)
+}
+
+/// Writes a block into `blob`, returning whether it fit
+fn write_block(blob: &mut [u8], block: &PatchBlock) -> bool {
+    let Ok(start) = usize::try_from(block.offset) else {
+        return false;
+    };
+    let bytes = blob
+        .get_mut(start..)
+        .and_then(|rest| rest.get_mut(..block.data.len()));
+    match bytes {
+        Some(bytes) => {
+            bytes.copy_from_slice(&block.data);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Updates a blob the peer holds, such as a configuration table, by sending only the blocks
+/// that differ from the version it has.
+///
+/// The exchange is:
+/// 1. `PatchBegin` with the CRC of the base, and the size and CRC of the patched blob. The peer
+///    replies with `Status::Ok` if it holds the base, or `Status::Error` if it does not, in
+///    which case the whole blob must be sent some other way.
+/// 2. A `PatchBlock` for each run of changed blocks, found by [`diff_blocks`], which the peer
+///    does not reply to.
+/// 3. `PatchCommit`. The peer applies the blocks to its base, checks the CRC of the result and
+///    replies with `Status::Ok` once it keeps it as its new blob, or `Status::Error` if it was
+///    rejected. It may send `Status::Pending` while it works.
+///
+/// All CRCs are CRC-32 (IEEE), as computed by [`crc32`].
+pub struct PatchUpdate<'a> {
+    base: &'a [u8],
+    target: &'a [u8],
+    block_size: usize,
+}
+
+impl<'a> PatchUpdate<'a> {
+    /// Creates an update from `base`, which the peer holds, to `target`, comparing blocks of
+    /// [`DEFAULT_BLOCK_SIZE`] bytes
+    #[must_use]
+    pub fn new(base: &'a [u8], target: &'a [u8]) -> Self {
+        Self {
+            base,
+            target,
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    /// Sets the number of bytes compared at a time
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    #[must_use]
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "invalid block size: {block_size}");
+        self.block_size = block_size;
+        self
+    }
+
+    /// Sends the patch, returning once the peer has kept the patched blob
+    pub fn run<T>(self, manager: &mut SerialManager<T>) -> Result<(), PatchError>
+    where
+        T: Transport,
+    {
+        let size = u32::try_from(self.target.len())
+            .map_err(|_| PatchError::BlobTooLarge(self.target.len()))?;
+
+        manager.send(Message::PatchBegin(PatchBegin {
+            base_crc: crc32(self.base),
+            size,
+            crc: crc32(self.target),
+        }))?;
+        match manager.receive()? {
+            Message::Status(Status::Ok) => (),
+            Message::Status(_) => return Err(PatchError::BaseMismatch),
+            message => return Err(PatchError::UnexpectedMessage(message.message_type())),
+        }
+
+        for block in diff_blocks(self.base, self.target, self.block_size) {
+            manager.send(Message::PatchBlock(block))?;
+        }
+
+        manager.send(Message::PatchCommit(PatchCommit {}))?;
+        loop {
+            match manager.receive()? {
+                Message::Status(Status::Ok) => return Ok(()),
+                Message::Status(Status::Pending) => (),
+                Message::Status(Status::Error) => return Err(PatchError::Rejected),
+                message => return Err(PatchError::UnexpectedMessage(message.message_type())),
+            }
+        }
+    }
+}
+
+/// A blob being patched, from `PatchBegin` until `PatchCommit`
+#[derive(Debug)]
+struct Pending {
+    crc: u32,
+    blob: Vec<u8>,
+    /// Whether a block did not fit in the blob
+    invalid: bool,
+}
+
+/// The receiving side of a [`PatchUpdate`], for peers implemented in Rust.
+///
+/// Received messages are passed to [`handle`](Self::handle), which returns the reply to send.
+/// The blob is only replaced once a patch is committed and its CRC checks out.
+#[derive(Debug, Default)]
+pub struct PatchReceiver {
+    blob: Vec<u8>,
+    pending: Option<Pending>,
+}
+
+impl PatchReceiver {
+    /// Creates a receiver holding `blob`, which patches are applied to
+    #[must_use]
+    pub fn new(blob: Vec<u8>) -> Self {
+        Self {
+            blob,
+            pending: None,
+        }
+    }
+
+    /// Handles a patch message, returning the reply, or `None` for `PatchBlock` and other
+    /// messages
+    pub fn handle(&mut self, message: &Message) -> Option<Message> {
+        let reply = match message {
+            Message::PatchBegin(begin) => {
+                if crc32(&self.blob) != begin.base_crc {
+                    self.pending = None;
+                    return Some(Message::Status(Status::Error));
+                }
+                let mut blob = self.blob.clone();
+                blob.resize(begin.size as usize, 0);
+                self.pending = Some(Pending {
+                    crc: begin.crc,
+                    blob,
+                    invalid: false,
+                });
+                Message::Status(Status::Ok)
+            }
+            Message::PatchBlock(block) => {
+                if let Some(pending) = &mut self.pending {
+                    pending.invalid |= !write_block(&mut pending.blob, block);
+                }
+                return None;
+            }
+            Message::PatchCommit(_) => match self.pending.take() {
+                Some(pending) if !pending.invalid && crc32(&pending.blob) == pending.crc => {
+                    self.blob = pending.blob;
+                    Message::Status(Status::Ok)
+                }
+                _ => Message::Status(Status::Error),
+            },
+            _ => return None,
+        };
+        Some(reply)
+    }
+
+    /// Returns the blob, as of the last committed patch
+    #[must_use]
+    pub fn blob(&self) -> &[u8] {
+        &self.blob
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,127 @@
+use super::*;
+use crate::test_util::{stream_pair, TestStream};
+use std::thread::{self, JoinHandle};
+
+fn blob(size: usize) -> Vec<u8> {
+    (0..=u8::MAX).cycle().step_by(7).take(size).collect()
+}
+
+/// Runs `receiver` on the other end of the returned connection until the connection is closed
+fn spawn_peer(
+    mut receiver: PatchReceiver,
+) -> (SerialManager<TestStream>, JoinHandle<PatchReceiver>) {
+    let (stream1, stream2) = stream_pair();
+    let peer = thread::spawn(move || {
+        let mut manager = SerialManager::new(stream2);
+        while let Ok(message) = manager.receive() {
+            if let Some(reply) = receiver.handle(&message) {
+                manager.send(reply).unwrap();
+            }
+        }
+        receiver
+    });
+    (SerialManager::new(stream1), peer)
+}
+
+#[test]
+fn test_diff_blocks() {
+    let base = blob(100);
+    assert!(diff_blocks(&base, &base, 16).is_empty());
+
+    let mut target = base.clone();
+    target[20] ^= 1;
+    target[40] ^= 1;
+    target[99] ^= 1;
+    target.extend([1, 2, 3]);
+    let blocks = diff_blocks(&base, &target, 16);
+    // Blocks 16..32 and 32..48 are next to each other, and the last block runs past the base
+    assert_eq!(
+        blocks,
+        [
+            PatchBlock {
+                offset: 16,
+                data: target[16..48].to_vec(),
+            },
+            PatchBlock {
+                offset: 96,
+                data: target[96..].to_vec(),
+            },
+        ]
+    );
+    assert_eq!(apply_patch(&base, target.len(), &blocks).unwrap(), target);
+
+    // Shrinking needs no blocks
+    assert!(diff_blocks(&base, &base[..50], 16).is_empty());
+    assert_eq!(apply_patch(&base, 50, &[]).unwrap(), base[..50]);
+
+    let outside = PatchBlock {
+        offset: 45,
+        data: vec![0; 10],
+    };
+    assert!(apply_patch(&base, 50, &[outside]).is_none());
+}
+
+#[test]
+fn test_diff_blocks_splits_long_runs() {
+    let target = blob(MAX_BLOCK_DATA + 100);
+    let blocks = diff_blocks(&[], &target, 1000);
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks[0].data.len() <= MAX_BLOCK_DATA);
+    assert_eq!(apply_patch(&[], target.len(), &blocks).unwrap(), target);
+}
+
+#[test]
+fn test_update() {
+    let base = blob(1000);
+    let mut target = base.clone();
+    target[500..510].fill(0);
+    let (mut manager, peer) = spawn_peer(PatchReceiver::new(base.clone()));
+
+    PatchUpdate::new(&base, &target)
+        .with_block_size(8)
+        .run(&mut manager)
+        .unwrap();
+    drop(manager);
+    assert_eq!(peer.join().unwrap().blob(), target);
+}
+
+#[test]
+fn test_update_base_mismatch() {
+    let base = blob(100);
+    let (mut manager, peer) = spawn_peer(PatchReceiver::new(blob(50)));
+
+    let result = PatchUpdate::new(&base, &blob(200)).run(&mut manager);
+    assert!(matches!(result, Err(PatchError::BaseMismatch)));
+    drop(manager);
+    assert_eq!(peer.join().unwrap().blob(), blob(50));
+}
+
+#[test]
+fn test_receiver_rejects_corrupted_patch() {
+    let base = blob(100);
+    let mut receiver = PatchReceiver::new(base.clone());
+    let begin = PatchBegin {
+        base_crc: crc32(&base),
+        size: 100,
+        crc: crc32(&[0; 100]),
+    };
+    assert_eq!(
+        receiver.handle(&Message::PatchBegin(begin)),
+        Some(Message::Status(Status::Ok))
+    );
+    let block = PatchBlock {
+        offset: 0,
+        data: vec![0; 50],
+    };
+    assert_eq!(receiver.handle(&Message::PatchBlock(block)), None);
+    // Half of the blob was never patched, so its CRC does not match
+    assert_eq!(
+        receiver.handle(&Message::PatchCommit(PatchCommit {})),
+        Some(Message::Status(Status::Error))
+    );
+    assert_eq!(receiver.blob(), base);
+    assert_eq!(
+        receiver.handle(&Message::U8(message_types::U8 { num: 1 })),
+        None
+    );
+}
@@ -0,0 +1,918 @@
+use crate::codec::Endianness;
+use crate::errors::{DecodeError, EncodeError};
+use crate::schema::{EnumDescriptor, FieldType, PayloadDescriptor};
+
+/// A value that can be encoded as a field of a message payload
+pub trait Field: Sized {
+    /// Describes the wire encoding of the field
+    const TYPE: FieldType;
+
+    /// Appends the encoded field to `bytes`, with multi-byte numbers in the given byte order
+    ///
+    /// Fails if the field is too long for a length it is encoded with. `bytes` may then hold
+    /// part of the field.
+    fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError>;
+
+    /// Decodes the field from the start of `bytes`, advancing `bytes` past it
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError>;
+
+    /// Appends the field as a TLV entry: its tag, a u16 length and the encoded field
+    fn encode_tagged(
+        &self,
+        tag: u8,
+        bytes: &mut Vec<u8>,
+        endianness: Endianness,
+    ) -> Result<(), EncodeError> {
+        let mut value = Vec::new();
+        self.encode(&mut value, endianness)?;
+        let length =
+            u16::try_from(value.len()).map_err(|_| EncodeError::FieldTooLong(value.len()))?;
+        bytes.push(tag);
+        length.encode(bytes, endianness)?;
+        bytes.extend(value);
+        Ok(())
+    }
+
+    /// Decodes the field from the value of its TLV entry, which is `None` if there was no entry
+    fn decode_tagged(
+        tag: u8,
+        value: Option<&[u8]>,
+        endianness: Endianness,
+    ) -> Result<Self, DecodeError> {
+        let mut value = value.ok_or(DecodeError::MissingField(tag))?;
+        Self::decode(&mut value, endianness)
+    }
+}
+
+/// A fixed-size field that can be sent in arrays
+///
+/// `Vec<T>` of an element type is encoded as a u16 element count followed by the elements. `u8`
+/// is not an element type, as `Vec<u8>` instead takes the rest of the payload.
+pub trait ArrayElement: Field {}
+
+/// A fieldless enum encoded as a single byte holding the variant's value, which can then be used
+/// as a field or array element
+///
+/// Decoding a byte that is not the value of any variant fails with
+/// [`DecodeError::InvalidEnumValue`]. With the `derive` feature, `#[derive(EnumField)]`
+/// implements it for a `#[repr(u8)]` enum, taking each variant's value from its discriminant.
+pub trait EnumField: Sized {
+    /// Describes the variants and their wire values
+    const DESCRIPTOR: EnumDescriptor;
+
+    /// Returns the wire value of the variant
+    fn to_u8(&self) -> u8;
+
+    /// Returns the variant with the wire value `value`, if any
+    fn from_u8(value: u8) -> Option<Self>;
+}
+
+impl<T: EnumField> ArrayElement for T {}
+
+impl<T: EnumField> Field for T {
+    const TYPE: FieldType = FieldType::Enum(&T::DESCRIPTOR);
+
+    fn encode(&self, bytes: &mut Vec<u8>, _endianness: Endianness) -> Result<(), EncodeError> {
+        bytes.push(self.to_u8());
+        Ok(())
+    }
+
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        let value = u8::decode(bytes, endianness)?;
+        T::from_u8(value).ok_or(DecodeError::InvalidEnumValue(value))
+    }
+}
+
+/// The payload of a message type
+pub trait Payload: Sized {
+    /// Describes the layout of the payload
+    const PAYLOAD: PayloadDescriptor;
+
+    /// Appends the encoded payload to `bytes`, with multi-byte numbers in the given byte order
+    ///
+    /// Fails if a field is too long for a length it is encoded with.
+    fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError>;
+
+    /// Decodes the payload from the start of `bytes`, advancing `bytes` past the fields it knows
+    /// about, so that any bytes left over were added by a newer peer
+    fn decode_prefix(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError>;
+
+    /// Decodes a complete payload, ignoring any bytes left over
+    fn decode(mut bytes: &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        Self::decode_prefix(&mut bytes, endianness)
+    }
+}
+
+/// The payload of a message type with an ID, which can be wrapped in a [`Message`](crate::Message)
+///
+/// Application message types, registered with
+/// [`register_message_type`](crate::SerialManager::register_message_type), only need the ID:
+/// by default they are carried as [`Message::Unknown`](crate::Message::Unknown), with numbers
+/// in the byte order of the connection. Since that is only known once there is a connection,
+/// they convert with `into_message` and `from_message` rather than `From` and `TryFrom`, which
+/// only the built-in message types implement.
+pub trait MessageType: Payload {
+    /// The message type ID sent on the wire
+    const ID: u16;
+
+    /// Returns the payload if `message` is of this type, or hands the message back, with numbers
+    /// in application types decoded in the given byte order
+    fn from_message(
+        message: crate::Message,
+        endianness: Endianness,
+    ) -> Result<Self, crate::Message> {
+        match message {
+            crate::Message::Unknown { message_type, data } if message_type == Self::ID => {
+                match Self::decode(&data, endianness) {
+                    Ok(payload) => Ok(payload),
+                    Err(_) => Err(crate::Message::Unknown { message_type, data }),
+                }
+            }
+            message => Err(message),
+        }
+    }
+
+    /// Wraps the payload in a [`Message`](crate::Message), such as to send it, with numbers in
+    /// application types encoded in the given byte order
+    ///
+    /// Fails if an application type does not encode.
+    fn into_message(self, endianness: Endianness) -> Result<crate::Message, EncodeError> {
+        let mut data = Vec::new();
+        self.encode(&mut data, endianness)?;
+        Ok(crate::Message::Unknown {
+            message_type: Self::ID,
+            data,
+        })
+    }
+}
+
+/// Fixed-size numbers, in the configured byte order
+macro_rules! impl_number_field {
+    ($($type:ty => $field_type:ident),* $(,)?) => {
+        $(
+            impl Field for $type {
+                const TYPE: FieldType = FieldType::$field_type;
+
+                fn encode(
+                    &self,
+                    bytes: &mut Vec<u8>,
+                    endianness: Endianness,
+                ) -> Result<(), EncodeError> {
+                    match endianness {
+                        Endianness::Little => bytes.extend(self.to_le_bytes()),
+                        Endianness::Big => bytes.extend(self.to_be_bytes()),
+                    }
+                    Ok(())
+                }
+
+                fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+                    let num = take(bytes, size_of::<$type>())?;
+                    let num = num.try_into().unwrap();
+                    Ok(match endianness {
+                        Endianness::Little => <$type>::from_le_bytes(num),
+                        Endianness::Big => <$type>::from_be_bytes(num),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+impl_number_field! {
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+}
+
+impl ArrayElement for u16 {}
+impl ArrayElement for u32 {}
+impl ArrayElement for u64 {}
+impl ArrayElement for i8 {}
+impl ArrayElement for i16 {}
+impl ArrayElement for i32 {}
+impl ArrayElement for i64 {}
+impl ArrayElement for f32 {}
+impl ArrayElement for f64 {}
+impl ArrayElement for bool {}
+
+/// A single byte, 0 for false or 1 for true
+impl Field for bool {
+    const TYPE: FieldType = FieldType::Bool;
+
+    fn encode(&self, bytes: &mut Vec<u8>, _endianness: Endianness) -> Result<(), EncodeError> {
+        bytes.push(u8::from(*self));
+        Ok(())
+    }
+
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        match u8::decode(bytes, endianness)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            invalid => Err(DecodeError::InvalidBool(invalid)),
+        }
+    }
+}
+
+/// An integer encoded as a LEB128 varint, so that small values take fewer bytes
+///
+/// Each byte holds seven bits of the value, least significant first, with the top bit set on every
+/// byte but the last. Signed integers are zigzag-encoded first, so that small negative values are
+/// small too.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Varint<T>(pub T);
+
+impl<T> From<T> for Varint<T> {
+    fn from(value: T) -> Self {
+        Varint(value)
+    }
+}
+
+fn encode_varint(mut value: u64, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn decode_varint(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0;
+    for shift in (0..u64::BITS).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(DecodeError::InvalidVarint)?;
+        *bytes = rest;
+        let bits = u64::from(byte & 0x7F);
+        if (bits << shift) >> shift != bits {
+            return Err(DecodeError::InvalidVarint);
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(DecodeError::InvalidVarint)
+}
+
+macro_rules! impl_varint_field {
+    ($($type:ty => $field_type:ident),* $(,)?) => {
+        $(
+            impl Field for Varint<$type> {
+                const TYPE: FieldType = FieldType::Varint(&FieldType::$field_type);
+
+                fn encode(
+                    &self,
+                    bytes: &mut Vec<u8>,
+                    _endianness: Endianness,
+                ) -> Result<(), EncodeError> {
+                    encode_varint(u64::from(self.0), bytes);
+                    Ok(())
+                }
+
+                fn decode(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self, DecodeError> {
+                    <$type>::try_from(decode_varint(bytes)?)
+                        .map(Varint)
+                        .map_err(|_| DecodeError::InvalidVarint)
+                }
+            }
+        )*
+    };
+}
+
+impl_varint_field! {
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+}
+
+/// Zigzag-encoded, mapping 0, -1, 1, -2, ... to 0, 1, 2, 3, ...
+macro_rules! impl_signed_varint_field {
+    ($($type:ty as $unsigned:ty => $field_type:ident),* $(,)?) => {
+        $(
+            impl Field for Varint<$type> {
+                const TYPE: FieldType = FieldType::Varint(&FieldType::$field_type);
+
+                fn encode(
+                    &self,
+                    bytes: &mut Vec<u8>,
+                    _endianness: Endianness,
+                ) -> Result<(), EncodeError> {
+                    let zigzag = (self.0 << 1) ^ (self.0 >> (<$type>::BITS - 1));
+                    encode_varint(u64::from(zigzag.cast_unsigned()), bytes);
+                    Ok(())
+                }
+
+                fn decode(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self, DecodeError> {
+                    let zigzag = <$unsigned>::try_from(decode_varint(bytes)?)
+                        .map_err(|_| DecodeError::InvalidVarint)?;
+                    Ok(Varint((zigzag >> 1).cast_signed() ^ -(zigzag & 1).cast_signed()))
+                }
+            }
+        )*
+    };
+}
+
+impl_signed_varint_field! {
+    i16 as u16 => I16,
+    i32 as u32 => I32,
+    i64 as u64 => I64,
+}
+
+impl<T: ArrayElement> Field for Vec<T> {
+    const TYPE: FieldType = FieldType::Array(&T::TYPE);
+
+    fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+        let count = u16::try_from(self.len()).map_err(|_| EncodeError::ArrayTooLong(self.len()))?;
+        count.encode(bytes, endianness)?;
+        self.iter()
+            .try_for_each(|element| element.encode(bytes, endianness))
+    }
+
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        let count = u16::decode(bytes, endianness)?;
+        (0..count).map(|_| T::decode(bytes, endianness)).collect()
+    }
+}
+
+/// A presence byte, 0 for `None` or 1 for `Some`, followed by the value if present
+///
+/// A payload that ends before the field decodes it as `None`, so optional fields can be appended to
+/// a struct without breaking peers that send the older, shorter payload.
+impl<T: Field> Field for Option<T> {
+    const TYPE: FieldType = FieldType::Option(&T::TYPE);
+
+    fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+        match self {
+            None => {
+                bytes.push(0);
+                Ok(())
+            }
+            Some(value) => {
+                bytes.push(1);
+                value.encode(bytes, endianness)
+            }
+        }
+    }
+
+    fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        match u8::decode(bytes, endianness)? {
+            0 => Ok(None),
+            1 => T::decode(bytes, endianness).map(Some),
+            invalid => Err(DecodeError::InvalidPresenceFlag(invalid)),
+        }
+    }
+
+    /// Left out altogether when `None`
+    fn encode_tagged(
+        &self,
+        tag: u8,
+        bytes: &mut Vec<u8>,
+        endianness: Endianness,
+    ) -> Result<(), EncodeError> {
+        match self {
+            Some(value) => value.encode_tagged(tag, bytes, endianness),
+            None => Ok(()),
+        }
+    }
+
+    fn decode_tagged(
+        tag: u8,
+        value: Option<&[u8]>,
+        endianness: Endianness,
+    ) -> Result<Self, DecodeError> {
+        value
+            .map(|value| T::decode_tagged(tag, Some(value), endianness))
+            .transpose()
+    }
+}
+
+/// Takes the rest of the payload
+impl Field for Vec<u8> {
+    const TYPE: FieldType = FieldType::Bytes;
+
+    fn encode(&self, bytes: &mut Vec<u8>, _endianness: Endianness) -> Result<(), EncodeError> {
+        bytes.extend(self);
+        Ok(())
+    }
+
+    fn decode(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self, DecodeError> {
+        Ok(std::mem::take(bytes).to_vec())
+    }
+}
+
+/// Takes the rest of the payload
+#[cfg(feature = "bytes")]
+impl Field for ::bytes::Bytes {
+    const TYPE: FieldType = FieldType::Bytes;
+
+    fn encode(&self, bytes: &mut Vec<u8>, _endianness: Endianness) -> Result<(), EncodeError> {
+        bytes.extend_from_slice(self);
+        Ok(())
+    }
+
+    fn decode(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self, DecodeError> {
+        Ok(::bytes::Bytes::copy_from_slice(std::mem::take(bytes)))
+    }
+}
+
+/// Takes the rest of the payload
+impl Field for String {
+    const TYPE: FieldType = FieldType::String;
+
+    fn encode(&self, bytes: &mut Vec<u8>, _endianness: Endianness) -> Result<(), EncodeError> {
+        bytes.extend(self.as_bytes());
+        Ok(())
+    }
+
+    fn decode(bytes: &mut &[u8], _endianness: Endianness) -> Result<Self, DecodeError> {
+        Ok(String::from_utf8(std::mem::take(bytes).to_vec())?)
+    }
+}
+
+/// Splits the next `length` bytes off the start of `bytes`
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8], DecodeError> {
+    if bytes.len() < length {
+        return Err(DecodeError::TruncatedPayload {
+            expected: length,
+            actual: bytes.len(),
+        });
+    }
+    let (taken, rest) = bytes.split_at(length);
+    *bytes = rest;
+    Ok(taken)
+}
+
+/// Advances `bytes` past a field of type `field_type` without decoding it
+pub(crate) fn skip_field(
+    field_type: FieldType,
+    bytes: &mut &[u8],
+    endianness: Endianness,
+) -> Result<(), DecodeError> {
+    match field_type {
+        FieldType::Bytes | FieldType::String => *bytes = &[],
+        FieldType::Array(element) => {
+            let count = u16::decode(bytes, endianness)?;
+            for _ in 0..count {
+                skip_field(*element, bytes, endianness)?;
+            }
+        }
+        FieldType::Struct(_) => {
+            let length = u16::decode(bytes, endianness)?;
+            take(bytes, usize::from(length))?;
+        }
+        FieldType::Option(field_type) => match bytes.first() {
+            None | Some(0) => *bytes = bytes.get(1..).unwrap_or_default(),
+            Some(1) => {
+                *bytes = &bytes[1..];
+                skip_field(*field_type, bytes, endianness)?;
+            }
+            Some(&invalid) => return Err(DecodeError::InvalidPresenceFlag(invalid)),
+        },
+        FieldType::Varint(_) => {
+            decode_varint(bytes)?;
+        }
+        FieldType::U8
+        | FieldType::U16
+        | FieldType::U32
+        | FieldType::U64
+        | FieldType::I8
+        | FieldType::I16
+        | FieldType::I32
+        | FieldType::I64
+        | FieldType::F32
+        | FieldType::F64
+        | FieldType::Bool
+        | FieldType::Enum(_) => {
+            // Fixed-size, so the size is known
+            take(bytes, field_type.size().unwrap_or_default())?;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a complete message payload, checking its length first if the payload is fixed-size
+///
+/// A mismatch is reported as the length field the frame would have had.
+pub(crate) fn decode_payload<P: Payload>(
+    bytes: &[u8],
+    endianness: Endianness,
+) -> Result<P, DecodeError> {
+    if P::PAYLOAD.size().is_some_and(|size| size != bytes.len()) {
+        let length = u16::try_from(bytes.len() + 2).unwrap_or(u16::MAX);
+        return Err(DecodeError::InvalidLength(length));
+    }
+    P::decode(bytes, endianness)
+}
+
+/// Splits a TLV-encoded payload into the tag and value of each entry
+pub(crate) fn tlv_entries(
+    mut bytes: &[u8],
+    endianness: Endianness,
+) -> Result<Vec<(u8, &[u8])>, DecodeError> {
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+        let tag = u8::decode(&mut bytes, endianness)?;
+        let length = u16::decode(&mut bytes, endianness)?;
+        let value = take(&mut bytes, usize::from(length))?;
+        entries.push((tag, value));
+    }
+    Ok(entries)
+}
+
+/// Defines the payload types in `message_types`, the `Message` enum and the schema descriptors.
+///
+/// Each message type is a struct, whose fields are encoded in order, a `tlv` struct, whose fields
+/// are tagged, or an enum, encoded as a single byte holding the variant's value. Entries without a
+/// type ID are only used as fields of other structs.
+macro_rules! define_messages {
+    // Sorts each entry into the message types, which have an ID, and all payload types
+    (@split [$($messages:tt)*] [$($types:tt)*]) => {
+        $crate::payload::define_messages!(@define [$($messages)*] [$($types)*]);
+    };
+    (
+        @split [$($messages:tt)*] [$($types:tt)*]
+        $(#[$meta:meta])* $id:literal => $kind:ident $name:ident $body:tt $(, $($rest:tt)*)?
+    ) => {
+        $crate::payload::define_messages!(
+            @split
+            [$($messages)* ($id $name)]
+            [$($types)* ($(#[$meta])* $kind $name $body)]
+            $($($rest)*)?
+        );
+    };
+    (
+        @split [$($messages:tt)*] [$($types:tt)*]
+        $(#[$meta:meta])* $kind:ident $name:ident $body:tt $(, $($rest:tt)*)?
+    ) => {
+        $crate::payload::define_messages!(
+            @split
+            [$($messages)*]
+            [$($types)* ($(#[$meta])* $kind $name $body)]
+            $($($rest)*)?
+        );
+    };
+    (
+        @define
+        [$(($id:literal $message:ident))*]
+        [$(($(#[$meta:meta])* $kind:ident $name:ident { $($body:tt)* }))*]
+    ) => {
+        pub mod message_types {
+            use crate::codec::Endianness;
+            use crate::errors::{DecodeError, EncodeError};
+            use crate::payload::{EnumField, Field, Payload, Varint};
+            use crate::schema::{
+                EnumDescriptor, FieldDescriptor, FieldType, PayloadDescriptor, StructDescriptor,
+                StructEncoding,
+            };
+
+            $(
+                $crate::payload::define_payload!($(#[$meta])* $kind $name { $($body)* });
+            )*
+        }
+
+        /// A message of any built-in type, or of another type left undecoded
+        ///
+        /// New message types may be added in minor releases, so matches outside this crate need
+        /// a wildcard arm.
+        #[derive(Debug, PartialEq, Clone)]
+        #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+        #[non_exhaustive]
+        pub enum Message {
+            $($message(message_types::$message),)*
+            /// A message whose type is not built in, with its payload left undecoded
+            Unknown { message_type: u16, data: Vec<u8> },
+        }
+
+        impl Message {
+            #[must_use]
+            pub fn message_type(&self) -> u16 {
+                match self {
+                    $(Message::$message(_) => $id,)*
+                    Message::Unknown { message_type, .. } => *message_type,
+                }
+            }
+
+            /// Encodes the payload with little-endian numbers
+            ///
+            /// Fails if a field is too long for a length it is encoded with.
+            pub fn to_bytes(self) -> Result<Vec<u8>, EncodeError> {
+                self.to_bytes_with(Endianness::Little)
+            }
+
+            /// Encodes the payload with numbers in the given byte order
+            ///
+            /// Fails if a field is too long for a length it is encoded with.
+            pub fn to_bytes_with(self, endianness: Endianness) -> Result<Vec<u8>, EncodeError> {
+                let mut bytes = Vec::new();
+                match self {
+                    $(Message::$message(payload) => {
+                        Payload::encode(&payload, &mut bytes, endianness)?;
+                    })*
+                    Message::Unknown { data, .. } => bytes = data,
+                }
+                Ok(bytes)
+            }
+
+            /// Creates a Message from its raw byte representation like
+            /// [`from_bytes_with`](Self::from_bytes_with), but accepting payloads longer than the
+            /// message type's fields and returning the bytes left over
+            ///
+            /// This lets a peer that appended fields to a message type still talk to an older
+            /// one. Payloads ending in a field that takes the rest of the payload never have any
+            /// bytes left over.
+            pub fn from_bytes_with_trailing(
+                message_type: u16,
+                data: &[u8],
+                endianness: Endianness,
+            ) -> Result<(Self, &[u8]), DecodeError> {
+                let mut bytes = data;
+                let message = match message_type {
+                    $($id => Message::$message(Payload::decode_prefix(&mut bytes, endianness)?),)*
+                    _ => return Err(DecodeError::InvalidMessageType(message_type)),
+                };
+                #[cfg(feature = "json")]
+                message.check_json()?;
+                Ok((message, bytes))
+            }
+
+            /// Creates a Message from its raw byte representation, with little-endian numbers
+            ///
+            /// Unregistered message types are an error rather than [`Message::Unknown`].
+            pub fn from_bytes(message_type: u16, data: Vec<u8>) -> Result<Self, DecodeError> {
+                Self::from_bytes_with(message_type, data, Endianness::Little)
+            }
+
+            /// Creates a Message from its raw byte representation, with numbers in the given
+            /// byte order
+            ///
+            /// With the `json` feature, a `Json` message whose text is not valid JSON is an error.
+            pub fn from_bytes_with(
+                message_type: u16,
+                data: Vec<u8>,
+                endianness: Endianness,
+            ) -> Result<Self, DecodeError> {
+                let message = match message_type {
+                    $($id => Message::$message($crate::payload::decode_payload(&data, endianness)?),)*
+                    _ => return Err(DecodeError::InvalidMessageType(message_type)),
+                };
+                #[cfg(feature = "json")]
+                message.check_json()?;
+                Ok(message)
+            }
+        }
+
+        $(
+            impl $crate::payload::MessageType for message_types::$message {
+                const ID: u16 = $id;
+
+                fn from_message(message: Message, _: Endianness) -> Result<Self, Message> {
+                    Self::try_from(message)
+                }
+
+                fn into_message(self, _: Endianness) -> Result<Message, EncodeError> {
+                    Ok(Message::$message(self))
+                }
+            }
+
+            impl From<message_types::$message> for Message {
+                fn from(payload: message_types::$message) -> Self {
+                    Message::$message(payload)
+                }
+            }
+
+            /// Hands the message back if it is of another type
+            impl TryFrom<Message> for message_types::$message {
+                type Error = Message;
+
+                fn try_from(message: Message) -> Result<Self, Message> {
+                    match message {
+                        Message::$message(payload) => Ok(payload),
+                        message => Err(message),
+                    }
+                }
+            }
+        )*
+
+        $(
+            const _: () = assert!(
+                $id < *$crate::schema::USER_MESSAGE_TYPES.start(),
+                concat!("built-in message type ", stringify!($message), " outside the reserved range"),
+            );
+        )*
+
+        pub(crate) const MESSAGES: &[MessageDescriptor] = &[
+            $(
+                MessageDescriptor {
+                    id: $id,
+                    name: stringify!($message),
+                    payload: <message_types::$message as Payload>::PAYLOAD,
+                },
+            )*
+        ];
+    };
+    ($($entries:tt)*) => {
+        $crate::payload::define_messages!(@split [] [] $($entries)*);
+    };
+}
+
+macro_rules! define_payload {
+    (
+        $(#[$meta:meta])*
+        struct $name:ident {
+            $($(#[$field_meta:meta])* $field:ident: $type:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Clone)]
+        #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: $type,)*
+        }
+
+        impl $name {
+            /// Describes the fields and their wire encodings
+            pub const DESCRIPTOR: StructDescriptor = StructDescriptor {
+                name: stringify!($name),
+                fields: &[
+                    $(
+                        FieldDescriptor {
+                            name: stringify!($field),
+                            field_type: <$type as Field>::TYPE,
+                            tag: None,
+                        },
+                    )*
+                ],
+                encoding: StructEncoding::Positional,
+            };
+        }
+
+        $crate::payload::define_payload!(@nested $name);
+
+        impl Payload for $name {
+            const PAYLOAD: PayloadDescriptor = PayloadDescriptor::Struct(&Self::DESCRIPTOR);
+
+            #[allow(unused_variables)]
+            fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+                $(Field::encode(&self.$field, bytes, endianness)?;)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn decode_prefix(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+                Ok(Self {
+                    $($field: Field::decode(bytes, endianness)?,)*
+                })
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        tlv $name:ident {
+            $($(#[$field_meta:meta])* $tag:literal => $field:ident: $type:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Clone)]
+        #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+        pub struct $name {
+            $($(#[$field_meta])* pub $field: $type,)*
+        }
+
+        impl $name {
+            /// Describes the fields, their tags and their wire encodings
+            pub const DESCRIPTOR: StructDescriptor = StructDescriptor {
+                name: stringify!($name),
+                fields: &[
+                    $(
+                        FieldDescriptor {
+                            name: stringify!($field),
+                            field_type: <$type as Field>::TYPE,
+                            tag: Some($tag),
+                        },
+                    )*
+                ],
+                encoding: StructEncoding::Tlv,
+            };
+        }
+
+        $crate::payload::define_payload!(@nested $name);
+
+        impl Payload for $name {
+            const PAYLOAD: PayloadDescriptor = PayloadDescriptor::Struct(&Self::DESCRIPTOR);
+
+            #[allow(unused_variables)]
+            fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+                $(Field::encode_tagged(&self.$field, $tag, bytes, endianness)?;)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn decode_prefix(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+                // Every entry is consumed, as unknown tags are skipped
+                let entries = $crate::payload::tlv_entries(std::mem::take(bytes), endianness)?;
+                let value = |tag: u8| {
+                    entries
+                        .iter()
+                        .find(|(entry_tag, _)| *entry_tag == tag)
+                        .map(|(_, value)| *value)
+                };
+                Ok(Self {
+                    $($field: Field::decode_tagged($tag, value($tag), endianness)?,)*
+                })
+            }
+        }
+    };
+    // Structs nested in other structs are length-prefixed, so they may end in a field that takes
+    // the rest of the payload
+    (@nested $name:ident) => {
+        /// A u16 length followed by the fields
+        impl Field for $name {
+            const TYPE: FieldType = FieldType::Struct(&Self::DESCRIPTOR);
+
+            fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+                let mut fields = Vec::new();
+                Payload::encode(self, &mut fields, endianness)?;
+                let length = u16::try_from(fields.len())
+                    .map_err(|_| EncodeError::StructTooLong(fields.len()))?;
+                length.encode(bytes, endianness)?;
+                bytes.extend(fields);
+                Ok(())
+            }
+
+            fn decode(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+                let length = u16::decode(bytes, endianness)?;
+                let fields = $crate::payload::take(bytes, usize::from(length))?;
+                Payload::decode(fields, endianness)
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Clone)]
+        #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            /// Describes the variants and their wire values
+            pub const DESCRIPTOR: EnumDescriptor = EnumDescriptor {
+                name: stringify!($name),
+                variants: &[$((stringify!($variant), $value),)*],
+            };
+        }
+
+        impl EnumField for $name {
+            const DESCRIPTOR: EnumDescriptor = Self::DESCRIPTOR;
+
+            fn to_u8(&self) -> u8 {
+                match self {
+                    $($name::$variant => $value,)*
+                }
+            }
+
+            fn from_u8(value: u8) -> Option<Self> {
+                Some(match value {
+                    $($value => $name::$variant,)*
+                    _ => return None,
+                })
+            }
+        }
+
+        impl Payload for $name {
+            const PAYLOAD: PayloadDescriptor = PayloadDescriptor::Enum(&Self::DESCRIPTOR);
+
+            fn encode(&self, bytes: &mut Vec<u8>, endianness: Endianness) -> Result<(), EncodeError> {
+                Field::encode(self, bytes, endianness)
+            }
+
+            fn decode_prefix(bytes: &mut &[u8], endianness: Endianness) -> Result<Self, DecodeError> {
+                Field::decode(bytes, endianness)
+            }
+        }
+    };
+}
+
+pub(crate) use define_messages;
+pub(crate) use define_payload;
+
+#[cfg(test)]
+mod tests;
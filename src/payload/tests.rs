@@ -0,0 +1,551 @@
+use super::*;
+use crate::schema::{FieldDescriptor, StructDescriptor, StructEncoding};
+
+define_payload!(
+    struct ReadingV1 {
+        channel: u8,
+    }
+);
+
+define_payload!(
+    struct ReadingV2 {
+        channel: u8,
+        scale: Option<u16>,
+        offset: Option<i8>,
+    }
+);
+
+define_payload!(
+    struct Position {
+        x: i16,
+        y: i16,
+    }
+);
+
+define_payload!(
+    struct Label {
+        text: String,
+    }
+);
+
+define_payload!(
+    struct Waypoint {
+        position: Position,
+        label: Label,
+        index: u8,
+    }
+);
+
+define_payload!(
+    tlv ConfigV1 {
+        1 => id: u8,
+        2 => name: String,
+    }
+);
+
+define_payload!(
+    tlv ConfigV2 {
+        1 => id: u8,
+        2 => name: String,
+        3 => gain: Option<i16>,
+        4 => position: Position,
+    }
+);
+
+fn encode(payload: &impl Payload) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    payload.encode(&mut bytes, Endianness::Little).unwrap();
+    bytes
+}
+
+#[test]
+fn test_optional_fields_round_trip() {
+    let reading = ReadingV2 {
+        channel: 3,
+        scale: Some(0x1234),
+        offset: None,
+    };
+    let bytes = encode(&reading);
+    assert_eq!(bytes, vec![0x03, 0x01, 0x34, 0x12, 0x00]);
+    assert_eq!(
+        <ReadingV2 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        reading
+    );
+}
+
+#[test]
+fn test_missing_optional_fields_are_none() {
+    let bytes = encode(&ReadingV1 { channel: 3 });
+    assert_eq!(
+        <ReadingV2 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        ReadingV2 {
+            channel: 3,
+            scale: None,
+            offset: None,
+        }
+    );
+}
+
+#[test]
+fn test_older_peer_ignores_optional_fields() {
+    let bytes = encode(&ReadingV2 {
+        channel: 3,
+        scale: Some(7),
+        offset: Some(-1),
+    });
+    assert_eq!(
+        <ReadingV1 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        ReadingV1 { channel: 3 }
+    );
+}
+
+#[test]
+fn test_invalid_presence_flag() {
+    assert!(matches!(
+        <ReadingV2 as Payload>::decode(&[0x03, 0x02], Endianness::Little),
+        Err(DecodeError::InvalidPresenceFlag(2))
+    ));
+}
+
+#[test]
+fn test_optional_field_type() {
+    assert_eq!(
+        ReadingV2::DESCRIPTOR.fields[1].field_type,
+        FieldType::Option(&FieldType::U16)
+    );
+}
+
+#[test]
+fn test_nested_structs_are_length_prefixed() {
+    let waypoint = Waypoint {
+        position: Position { x: 1, y: -1 },
+        label: Label {
+            text: "home".to_string(),
+        },
+        index: 5,
+    };
+    let bytes = encode(&waypoint);
+    assert_eq!(
+        bytes,
+        vec![
+            0x04, 0x00, // Position length
+            0x01, 0x00, 0xFF, 0xFF, // Position
+            0x04, 0x00, // Label length
+            b'h', b'o', b'm', b'e', // Label, whose string ends with the label
+            0x05,
+        ]
+    );
+    assert_eq!(
+        <Waypoint as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        waypoint
+    );
+}
+
+#[test]
+fn test_big_endian_numbers() {
+    let waypoint = Waypoint {
+        position: Position { x: 1, y: -2 },
+        label: Label {
+            text: "a".to_string(),
+        },
+        index: 5,
+    };
+    let mut bytes = Vec::new();
+    Payload::encode(&waypoint, &mut bytes, Endianness::Big).unwrap();
+    assert_eq!(
+        bytes,
+        vec![
+            0x00, 0x04, // Position length
+            0x00, 0x01, 0xFF, 0xFE, // Position
+            0x00, 0x01, // Label length
+            b'a', 0x05,
+        ]
+    );
+    assert_eq!(
+        <Waypoint as Payload>::decode(&bytes, Endianness::Big).unwrap(),
+        waypoint
+    );
+}
+
+#[test]
+fn test_nested_struct_field_type() {
+    assert_eq!(
+        Waypoint::DESCRIPTOR.fields[0].field_type,
+        FieldType::Struct(&Position::DESCRIPTOR)
+    );
+    assert_eq!(Position::DESCRIPTOR.name, "Position");
+}
+
+#[test]
+fn test_tlv_fields_are_tagged() {
+    let config = ConfigV1 {
+        id: 7,
+        name: "ab".to_string(),
+    };
+    let bytes = encode(&config);
+    assert_eq!(
+        bytes,
+        vec![
+            0x01, 0x01, 0x00, 0x07, // id
+            0x02, 0x02, 0x00, b'a', b'b', // name
+        ]
+    );
+    assert_eq!(
+        <ConfigV1 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn test_tlv_fields_in_any_order() {
+    let bytes = [0x02, 0x02, 0x00, b'a', b'b', 0x01, 0x01, 0x00, 0x07];
+    assert_eq!(
+        <ConfigV1 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        ConfigV1 {
+            id: 7,
+            name: "ab".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_tlv_none_fields_are_left_out() {
+    let config = ConfigV2 {
+        id: 7,
+        name: String::new(),
+        gain: None,
+        position: Position { x: 1, y: 2 },
+    };
+    let bytes = encode(&config);
+    assert_eq!(
+        bytes,
+        vec![
+            0x01, 0x01, 0x00, 0x07, // id
+            0x02, 0x00, 0x00, // name
+            0x04, 0x06, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02, 0x00, // position
+        ]
+    );
+    assert_eq!(
+        <ConfigV2 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn test_tlv_unknown_tags_are_skipped() {
+    let bytes = encode(&ConfigV2 {
+        id: 7,
+        name: "ab".to_string(),
+        gain: Some(-3),
+        position: Position { x: 1, y: 2 },
+    });
+    assert_eq!(
+        <ConfigV1 as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        ConfigV1 {
+            id: 7,
+            name: "ab".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_tlv_missing_field() {
+    let bytes = encode(&ConfigV1 {
+        id: 7,
+        name: "ab".to_string(),
+    });
+    assert!(matches!(
+        <ConfigV2 as Payload>::decode(&bytes, Endianness::Little),
+        Err(DecodeError::MissingField(4))
+    ));
+}
+
+#[test]
+fn test_tlv_descriptor() {
+    assert_eq!(ConfigV2::DESCRIPTOR.encoding, StructEncoding::Tlv);
+    assert_eq!(ConfigV2::DESCRIPTOR.fields[2].tag, Some(3));
+    assert_eq!(Position::DESCRIPTOR.encoding, StructEncoding::Positional);
+    assert_eq!(Position::DESCRIPTOR.fields[0].tag, None);
+}
+
+fn encode_field(field: &impl Field) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    field.encode(&mut bytes, Endianness::Little).unwrap();
+    bytes
+}
+
+fn decode_field<T: Field>(mut bytes: &[u8]) -> Result<T, DecodeError> {
+    let field = T::decode(&mut bytes, Endianness::Little)?;
+    assert!(bytes.is_empty());
+    Ok(field)
+}
+
+#[test]
+fn test_varint_round_trip() {
+    let cases: [(u64, &[u8]); 5] = [
+        (0, &[0x00]),
+        (127, &[0x7F]),
+        (128, &[0x80, 0x01]),
+        (300, &[0xAC, 0x02]),
+        (
+            u64::MAX,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        ),
+    ];
+    for (value, bytes) in cases {
+        assert_eq!(encode_field(&Varint(value)), bytes);
+        assert_eq!(decode_field::<Varint<u64>>(bytes).unwrap(), Varint(value));
+    }
+}
+
+#[test]
+fn test_signed_varints_are_zigzag_encoded() {
+    let cases: [(i32, &[u8]); 5] = [
+        (0, &[0x00]),
+        (-1, &[0x01]),
+        (1, &[0x02]),
+        (-64, &[0x7F]),
+        (i32::MIN, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+    ];
+    for (value, bytes) in cases {
+        assert_eq!(encode_field(&Varint(value)), bytes);
+        assert_eq!(decode_field::<Varint<i32>>(bytes).unwrap(), Varint(value));
+    }
+}
+
+#[test]
+fn test_invalid_varints() {
+    // Too large for the integer type
+    assert!(matches!(
+        decode_field::<Varint<u16>>(&[0x80, 0x80, 0x04]),
+        Err(DecodeError::InvalidVarint)
+    ));
+    // Too large for a u64
+    assert!(matches!(
+        decode_field::<Varint<u64>>(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x02]),
+        Err(DecodeError::InvalidVarint)
+    ));
+    // Ends with the continuation bit set
+    assert!(matches!(
+        decode_field::<Varint<u32>>(&[0x80]),
+        Err(DecodeError::InvalidVarint)
+    ));
+}
+
+#[test]
+fn test_truncated_payloads() {
+    // Number cut short
+    assert!(matches!(
+        decode_field::<u32>(&[0x01, 0x02]),
+        Err(DecodeError::TruncatedPayload {
+            expected: 4,
+            actual: 2
+        })
+    ));
+    // Nested struct shorter than its length
+    assert!(matches!(
+        <Waypoint as Payload>::decode(&[0x04, 0x00, 0x01, 0x00], Endianness::Little),
+        Err(DecodeError::TruncatedPayload {
+            expected: 4,
+            actual: 2
+        })
+    ));
+    // TLV entry shorter than its length
+    assert!(matches!(
+        <ConfigV1 as Payload>::decode(&[0x01, 0x02, 0x00, 0x07], Endianness::Little),
+        Err(DecodeError::TruncatedPayload {
+            expected: 2,
+            actual: 1
+        })
+    ));
+    // Array with fewer elements than its count
+    assert!(matches!(
+        decode_field::<Vec<u16>>(&[0x02, 0x00, 0x01, 0x00]),
+        Err(DecodeError::TruncatedPayload {
+            expected: 2,
+            actual: 0
+        })
+    ));
+}
+
+#[test]
+fn test_encode_array_too_long() {
+    let mut bytes = Vec::new();
+    assert_eq!(
+        vec![0u16; 0x1_0000].encode(&mut bytes, Endianness::Little),
+        Err(EncodeError::ArrayTooLong(0x1_0000))
+    );
+}
+
+#[test]
+fn test_encode_nested_struct_too_long() {
+    let waypoint = Waypoint {
+        position: Position { x: 0, y: 0 },
+        label: Label {
+            text: "a".repeat(0x1_0000),
+        },
+        index: 0,
+    };
+    let mut bytes = Vec::new();
+    assert_eq!(
+        Payload::encode(&waypoint, &mut bytes, Endianness::Little),
+        Err(EncodeError::StructTooLong(0x1_0000))
+    );
+}
+
+#[test]
+fn test_encode_tagged_field_too_long() {
+    let mut bytes = Vec::new();
+    assert_eq!(
+        "a".repeat(0x1_0000)
+            .encode_tagged(1, &mut bytes, Endianness::Little),
+        Err(EncodeError::FieldTooLong(0x1_0000))
+    );
+}
+
+#[test]
+fn test_skip_field_stops_at_the_next_field() {
+    let mut bytes = Vec::new();
+    vec![1.5f32, 2.5]
+        .encode(&mut bytes, Endianness::Big)
+        .unwrap();
+    Some(7u16).encode(&mut bytes, Endianness::Big).unwrap();
+    Varint(300u64).encode(&mut bytes, Endianness::Big).unwrap();
+    bytes.push(0xAA);
+
+    let mut rest = bytes.as_slice();
+    skip_field(<Vec<f32>>::TYPE, &mut rest, Endianness::Big).unwrap();
+    skip_field(<Option<u16>>::TYPE, &mut rest, Endianness::Big).unwrap();
+    skip_field(<Varint<u64>>::TYPE, &mut rest, Endianness::Big).unwrap();
+    assert_eq!(rest, [0xAA]);
+
+    let mut invalid: &[u8] = &[2, 0, 0];
+    assert!(matches!(
+        skip_field(<Option<u16>>::TYPE, &mut invalid, Endianness::Big),
+        Err(DecodeError::InvalidPresenceFlag(2))
+    ));
+}
+
+impl MessageType for Position {
+    const ID: u16 = 0x0100;
+}
+
+#[test]
+fn test_message_conversions() {
+    use crate::message::{message_types, Message};
+
+    let message: Message = message_types::U8 { num: 5 }.into();
+    assert_eq!(message, Message::U8(message_types::U8 { num: 5 }));
+    assert_eq!(
+        message_types::U8::try_from(message.clone()),
+        Ok(message_types::U8 { num: 5 })
+    );
+    // The message is handed back to be tried as another type
+    assert_eq!(message_types::U16::try_from(message.clone()), Err(message));
+
+    // Application message types are carried as unknown messages
+    let position = Position { x: 1, y: -1 };
+    let message = position.clone().into_message(Endianness::Little).unwrap();
+    assert_eq!(
+        message,
+        Message::Unknown {
+            message_type: 0x0100,
+            data: vec![0x01, 0x00, 0xFF, 0xFF],
+        }
+    );
+    assert_eq!(
+        Position::from_message(message, Endianness::Little),
+        Ok(position.clone())
+    );
+    let message = position.clone().into_message(Endianness::Big).unwrap();
+    assert_eq!(
+        message,
+        Message::Unknown {
+            message_type: 0x0100,
+            data: vec![0x00, 0x01, 0xFF, 0xFF],
+        }
+    );
+    assert_eq!(
+        Position::from_message(message, Endianness::Big),
+        Ok(position)
+    );
+    let message = Message::U8(message_types::U8 { num: 5 });
+    assert_eq!(
+        Position::from_message(message.clone(), Endianness::Little),
+        Err(message)
+    );
+    // A payload that does not decode is handed back too
+    let message = Message::Unknown {
+        message_type: 0x0100,
+        data: vec![0x01],
+    };
+    assert_eq!(
+        Position::from_message(message.clone(), Endianness::Little),
+        Err(message)
+    );
+}
+
+#[test]
+fn test_enum_fields() {
+    use crate::message::message_types::Status;
+
+    assert_eq!(Status::Pending.to_u8(), 2);
+    assert_eq!(Status::from_u8(1), Some(Status::Error));
+    assert_eq!(Status::from_u8(3), None);
+    assert_eq!(<Status as EnumField>::DESCRIPTOR, Status::DESCRIPTOR);
+
+    assert_eq!(
+        encode_field(&vec![Status::Ok, Status::Pending]),
+        [2, 0, 0, 2]
+    );
+    assert!(matches!(
+        decode_field::<Status>(&[3]),
+        Err(DecodeError::InvalidEnumValue(3))
+    ));
+}
+
+#[cfg(feature = "derive")]
+#[derive(Debug, PartialEq, Clone, crate::EnumField)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(u8)]
+pub enum Mode {
+    Idle,
+    Running = 4,
+    Stopped,
+}
+
+#[cfg(feature = "derive")]
+define_payload!(
+    struct ModeChange {
+        from: Mode,
+        to: Option<Mode>,
+        history: Vec<Mode>,
+    }
+);
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derived_enum_fields() {
+    assert_eq!(
+        Mode::DESCRIPTOR.variants,
+        [("Idle", 0), ("Running", 4), ("Stopped", 5)]
+    );
+    assert_eq!(<Mode as Field>::TYPE, FieldType::Enum(&Mode::DESCRIPTOR));
+
+    let change = ModeChange {
+        from: Mode::Running,
+        to: Some(Mode::Stopped),
+        history: vec![Mode::Idle, Mode::Running],
+    };
+    let bytes = encode(&change);
+    assert_eq!(bytes, [4, 1, 5, 2, 0, 0, 4]);
+    assert_eq!(
+        <ModeChange as Payload>::decode(&bytes, Endianness::Little).unwrap(),
+        change
+    );
+
+    assert!(matches!(
+        <ModeChange as Payload>::decode(&[1, 0, 2, 0, 0, 4], Endianness::Little),
+        Err(DecodeError::InvalidEnumValue(1))
+    ));
+}
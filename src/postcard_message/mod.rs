@@ -0,0 +1,66 @@
+use crate::codec::Frame;
+use crate::errors::PostcardError;
+use crate::serial_manager::{SerialManager, Transport};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A `serde` type sent as a postcard-encoded payload with its own message type ID, for peers
+/// written in embedded Rust.
+///
+/// The ID should be in [`USER_MESSAGE_TYPES`](crate::schema::USER_MESSAGE_TYPES). To receive
+/// these alongside built-in messages with [`receive`](SerialManager::receive), register the ID
+/// with [`register_message_type`](SerialManager::register_message_type) and decode the payload
+/// of the [`Message::Unknown`](crate::Message::Unknown) with [`postcard::from_bytes`].
+///
+/// ```
+/// use generic_serial_protocol::PostcardMessage;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Reading {
+///     sensor: u8,
+///     celsius: f32,
+/// }
+///
+/// impl PostcardMessage for Reading {
+///     const ID: u16 = 0x0100;
+/// }
+/// ```
+pub trait PostcardMessage: Serialize + DeserializeOwned {
+    const ID: u16;
+}
+
+impl Frame {
+    /// Decodes the payload as `M`, if the frame has its message type
+    #[must_use]
+    pub fn postcard<M: PostcardMessage>(&self) -> Option<Result<M, postcard::Error>> {
+        (self.message_type == M::ID).then(|| postcard::from_bytes(&self.payload))
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends `message` encoded with postcard, with the message type `M::ID`
+    pub fn send_postcard<M: PostcardMessage>(&mut self, message: &M) -> Result<(), PostcardError> {
+        let payload = postcard::to_allocvec(message)?;
+        self.send_raw(M::ID, &payload)?;
+        Ok(())
+    }
+
+    /// Receives the next frame and decodes it as `M`
+    ///
+    /// Fails with [`PostcardError::UnexpectedMessage`] if the frame has a different message
+    /// type.
+    pub fn receive_postcard<M: PostcardMessage>(&mut self) -> Result<M, PostcardError> {
+        let frame = self.receive_raw()?;
+        match frame.postcard() {
+            Some(result) => Ok(result?),
+            None => Err(PostcardError::UnexpectedMessage(frame.message_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
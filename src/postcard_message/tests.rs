@@ -0,0 +1,90 @@
+use super::*;
+use crate::errors::PostcardError;
+use crate::test_util::stream_pair;
+use serde::Deserialize;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading {
+    sensor: u8,
+    celsius: f32,
+    label: String,
+}
+
+impl PostcardMessage for Reading {
+    const ID: u16 = 0x0100;
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Command {
+    Reset,
+    SetRate(u32),
+}
+
+impl PostcardMessage for Command {
+    const ID: u16 = 0x0101;
+}
+
+#[test]
+fn test_send_and_receive() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    let reading = Reading {
+        sensor: 3,
+        celsius: 21.5,
+        label: "X".to_string(),
+    };
+    sender.send_postcard(&reading).unwrap();
+    sender.send_postcard(&Command::SetRate(115_200)).unwrap();
+
+    assert_eq!(receiver.receive_postcard::<Reading>().unwrap(), reading);
+    assert_eq!(
+        receiver.receive_postcard::<Command>().unwrap(),
+        Command::SetRate(115_200)
+    );
+}
+
+#[test]
+fn test_payload_is_postcard() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    sender.send_postcard(&Command::SetRate(300)).unwrap();
+
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x0101);
+    // Variant index then the varint-encoded rate
+    assert_eq!(frame.payload, vec![0x01, 0xAC, 0x02]);
+    assert!(frame.postcard::<Reading>().is_none());
+    assert_eq!(
+        frame.postcard::<Command>().unwrap().unwrap(),
+        Command::SetRate(300)
+    );
+}
+
+#[test]
+fn test_unexpected_message() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    sender.send_postcard(&Command::Reset).unwrap();
+
+    assert!(matches!(
+        receiver.receive_postcard::<Reading>(),
+        Err(PostcardError::UnexpectedMessage(0x0101))
+    ));
+}
+
+#[test]
+fn test_invalid_payload() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    sender.send_raw(Command::ID, &[0x07]).unwrap();
+
+    assert!(matches!(
+        receiver.receive_postcard::<Command>(),
+        Err(PostcardError::Postcard(_))
+    ));
+}
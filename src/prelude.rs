@@ -0,0 +1,21 @@
+//! The types most applications need, to be glob imported:
+//!
+//! ```
+//! use generic_serial_protocol::prelude::*;
+//!
+//! fn reading(message: &Message) -> Option<u32> {
+//!     match message {
+//!         Message::U32(message_types::U32 { num }) => Some(*num),
+//!         _ => None,
+//!     }
+//! }
+//! ```
+//!
+//! Items are only added to the prelude in major releases, as a new name could clash with one
+//! the application defines.
+
+pub use crate::codec::{Endianness, Framing};
+pub use crate::errors::{DecodeError, ReceiveError};
+pub use crate::message::{message_types, Message};
+pub use crate::payload::{MessageType, Payload};
+pub use crate::serial_manager::{SerialManager, Transport};
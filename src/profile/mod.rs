@@ -0,0 +1,166 @@
+use crate::codec::{Endianness, Framing, Resync};
+use crate::rate_limit::{RateLimit, ReceiveLimit};
+use crate::serial_manager::DecodeErrorAction;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The protocol settings of one connection, applied to a
+/// [`SerialManager`](crate::SerialManager) with
+/// [`with_profile`](crate::SerialManager::with_profile)
+///
+/// With the `serde` feature, profiles can be loaded from configuration files such as TOML, so
+/// that a fleet's protocol settings can change without code changes. Every field is optional in
+/// the file, defaulting to the manager's own default, and durations are given in milliseconds:
+///
+/// ```toml
+/// framing = "compact"
+/// endianness = "big"
+/// resync = { idle_gap = 5 }
+/// crc32 = true
+/// inter_byte_timeout = 50
+///
+/// [rate_limit]
+/// bytes_per_second = 11520
+///
+/// [receive_limits.Log]
+/// frames_per_second = 100
+/// action = "drop"
+/// ```
+// The flags mirror the manager's builders, for configuration files to set independently
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Profile {
+    /// See [`with_framing`](crate::SerialManager::with_framing)
+    pub framing: Framing,
+    /// See [`with_endianness`](crate::SerialManager::with_endianness)
+    pub endianness: Endianness,
+    /// See [`with_resync`](crate::SerialManager::with_resync)
+    pub resync: Resync,
+    /// See [`with_trailer`](crate::SerialManager::with_trailer)
+    pub trailer: Option<u8>,
+    /// See [`with_padding`](crate::SerialManager::with_padding)
+    pub padding: Option<usize>,
+    /// Stacks a [`Crc32`](crate::layer::Crc32) layer closest to the wire
+    pub crc32: bool,
+    /// See [`with_inter_byte_timeout`](crate::SerialManager::with_inter_byte_timeout)
+    #[cfg_attr(feature = "serde", serde(with = "millis_option"))]
+    pub inter_byte_timeout: Option<Duration>,
+    /// See [`with_rate_limit`](crate::SerialManager::with_rate_limit)
+    pub rate_limit: Option<RateLimit>,
+    /// Receive limits by message type, see
+    /// [`with_receive_limit`](crate::SerialManager::with_receive_limit)
+    ///
+    /// In configuration files, message types are keyed by ID or by the name of a built-in
+    /// message type, such as `Log`.
+    #[cfg_attr(feature = "serde", serde(with = "message_type_keys"))]
+    pub receive_limits: BTreeMap<u16, ReceiveLimit>,
+    /// See [`with_unknown_messages`](crate::SerialManager::with_unknown_messages)
+    pub unknown_messages: bool,
+    /// See [`with_trailing_bytes`](crate::SerialManager::with_trailing_bytes)
+    pub trailing_bytes: bool,
+    /// See [`with_strict`](crate::SerialManager::with_strict)
+    pub strict: bool,
+    /// See [`with_decode_errors`](crate::SerialManager::with_decode_errors)
+    pub decode_errors: DecodeErrorAction,
+}
+
+/// Writes a duration as a whole number of milliseconds
+#[cfg(feature = "serde")]
+pub(crate) mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// Writes an optional duration as a whole number of milliseconds
+#[cfg(feature = "serde")]
+mod millis_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    // The signature is dictated by `serde(with)`
+    #[allow(clippy::ref_option)]
+    pub(super) fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::millis::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<u64>::deserialize(deserializer).map(|millis| millis.map(Duration::from_millis))
+    }
+}
+
+/// Writes a map keyed by message type with the keys as strings, as formats such as TOML require,
+/// and reads them as IDs or names of built-in message types
+#[cfg(feature = "serde")]
+mod message_type_keys {
+    use crate::rate_limit::ReceiveLimit;
+    use crate::schema;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub(super) fn serialize<S: Serializer>(
+        limits: &BTreeMap<u16, ReceiveLimit>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        limits
+            .iter()
+            .map(|(message_type, limit)| (message_type.to_string(), limit))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<u16, ReceiveLimit>, D::Error> {
+        BTreeMap::<String, ReceiveLimit>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, limit)| {
+                let message_type = key
+                    .parse()
+                    .ok()
+                    .or_else(|| schema::message_id(&key))
+                    .ok_or_else(|| D::Error::custom(format!("unknown message type {key}")))?;
+                Ok((message_type, limit))
+            })
+            .collect()
+    }
+}
+
+/// Reads a rate of frames or bytes a second, which must not be 0, as the builders of the limits
+/// ensure
+#[cfg(feature = "serde")]
+pub(crate) fn rate<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u32>, D::Error> {
+    use serde::Deserialize;
+    match Option::<u32>::deserialize(deserializer)? {
+        Some(0) => Err(serde::de::Error::custom("rate must be at least 1 a second")),
+        rate => Ok(rate),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,86 @@
+use super::*;
+use crate::layer::Crc32;
+use crate::message::{message_types, Message};
+use crate::test_util::stream_pair;
+use crate::SerialManager;
+
+#[test]
+fn test_profile_matches_the_builders() {
+    let (stream1, stream2) = stream_pair();
+    let profile = Profile {
+        framing: Framing::Compact,
+        endianness: Endianness::Big,
+        trailer: Some(0xAA),
+        crc32: true,
+        ..Profile::default()
+    };
+    let mut sender = SerialManager::new(stream1).with_profile(&profile);
+    let mut receiver = SerialManager::new(stream2)
+        .with_framing(Framing::Compact)
+        .with_endianness(Endianness::Big)
+        .with_trailer(0xAA)
+        .with_layer(Crc32);
+
+    let message = Message::U16(message_types::U16 { num: 0x1234 });
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_profile_loads_from_toml() {
+    let profile: Profile = toml::from_str(
+        r#"
+        framing = "compact"
+        endianness = "big"
+        resync = { idle_gap = 5 }
+        crc32 = true
+        inter_byte_timeout = 50
+
+        [rate_limit]
+        bytes_per_second = 11520
+
+        [receive_limits.25]
+        frames_per_second = 100
+        action = "error"
+
+        [receive_limits.Json]
+        max_payload_length = 1024
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        profile,
+        Profile {
+            framing: Framing::Compact,
+            endianness: Endianness::Big,
+            resync: Resync::IdleGap(Duration::from_millis(5)),
+            crc32: true,
+            inter_byte_timeout: Some(Duration::from_millis(50)),
+            rate_limit: Some(RateLimit::new().with_bytes_per_second(11520)),
+            receive_limits: BTreeMap::from([
+                (
+                    25,
+                    ReceiveLimit::new()
+                        .with_frames_per_second(100)
+                        .with_action(crate::LimitAction::Error)
+                ),
+                (37, ReceiveLimit::new().with_max_payload_length(1024)),
+            ]),
+            ..Profile::default()
+        }
+    );
+
+    let text = toml::to_string(&profile).unwrap();
+    assert_eq!(toml::from_str::<Profile>(&text).unwrap(), profile);
+    assert_eq!(toml::from_str::<Profile>("").unwrap(), Profile::default());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_invalid_profiles_are_rejected() {
+    assert!(toml::from_str::<Profile>("framing = \"morse\"").is_err());
+    assert!(toml::from_str::<Profile>("checksum = true").is_err());
+    assert!(toml::from_str::<Profile>("[rate_limit]\nframes_per_second = 0").is_err());
+    assert!(toml::from_str::<Profile>("[receive_limits.Telemetry]").is_err());
+}
@@ -0,0 +1,65 @@
+use crate::codec::Frame;
+use crate::errors::ProtobufError;
+use crate::serial_manager::{SerialManager, Transport};
+
+/// A protobuf message type generated by `prost`, sent as a payload with its own message type
+/// ID, so that device APIs defined in `.proto` files can be reused over this framing.
+///
+/// The ID should be in [`USER_MESSAGE_TYPES`](crate::schema::USER_MESSAGE_TYPES). To receive
+/// these alongside built-in messages with [`receive`](SerialManager::receive), register the ID
+/// with [`register_message_type`](SerialManager::register_message_type) and decode the payload
+/// of the [`Message::Unknown`](crate::Message::Unknown) with [`prost::Message::decode`].
+///
+/// ```
+/// use generic_serial_protocol::ProtobufMessage;
+///
+/// // Generated by prost-build from a .proto file
+/// #[derive(Clone, PartialEq, prost::Message)]
+/// pub struct Reading {
+///     #[prost(uint32, tag = "1")]
+///     pub sensor: u32,
+///     #[prost(float, tag = "2")]
+///     pub celsius: f32,
+/// }
+///
+/// impl ProtobufMessage for Reading {
+///     const ID: u16 = 0x0100;
+/// }
+/// ```
+pub trait ProtobufMessage: prost::Message + Default {
+    const ID: u16;
+}
+
+impl Frame {
+    /// Decodes the payload as the protobuf message `M`, if the frame has its message type
+    #[must_use]
+    pub fn protobuf<M: ProtobufMessage>(&self) -> Option<Result<M, prost::DecodeError>> {
+        (self.message_type == M::ID).then(|| M::decode(self.payload.as_slice()))
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends `message` encoded as protobuf, with the message type `M::ID`
+    pub fn send_protobuf<M: ProtobufMessage>(&mut self, message: &M) -> Result<(), ProtobufError> {
+        self.send_raw(M::ID, &message.encode_to_vec())?;
+        Ok(())
+    }
+
+    /// Receives the next frame and decodes it as the protobuf message `M`
+    ///
+    /// Fails with [`ProtobufError::UnexpectedMessage`] if the frame has a different message
+    /// type.
+    pub fn receive_protobuf<M: ProtobufMessage>(&mut self) -> Result<M, ProtobufError> {
+        let frame = self.receive_raw()?;
+        match frame.protobuf() {
+            Some(result) => Ok(result?),
+            None => Err(ProtobufError::UnexpectedMessage(frame.message_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
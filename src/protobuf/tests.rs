@@ -0,0 +1,92 @@
+use super::*;
+use crate::test_util::stream_pair;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct Reading {
+    #[prost(uint32, tag = "1")]
+    sensor: u32,
+    #[prost(float, tag = "2")]
+    celsius: f32,
+    #[prost(string, tag = "3")]
+    label: String,
+}
+
+impl ProtobufMessage for Reading {
+    const ID: u16 = 0x0100;
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SetRate {
+    #[prost(uint32, tag = "1")]
+    rate: u32,
+}
+
+impl ProtobufMessage for SetRate {
+    const ID: u16 = 0x0101;
+}
+
+#[test]
+fn test_send_and_receive() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    let reading = Reading {
+        sensor: 3,
+        celsius: 21.5,
+        label: "X".to_string(),
+    };
+    sender.send_protobuf(&reading).unwrap();
+    sender.send_protobuf(&SetRate { rate: 115_200 }).unwrap();
+
+    assert_eq!(receiver.receive_protobuf::<Reading>().unwrap(), reading);
+    assert_eq!(
+        receiver.receive_protobuf::<SetRate>().unwrap(),
+        SetRate { rate: 115_200 }
+    );
+}
+
+#[test]
+fn test_payload_is_protobuf() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    sender.send_protobuf(&SetRate { rate: 300 }).unwrap();
+
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x0101);
+    // Field 1 as a varint
+    assert_eq!(frame.payload, vec![0x08, 0xAC, 0x02]);
+    assert!(frame.protobuf::<Reading>().is_none());
+    assert_eq!(
+        frame.protobuf::<SetRate>().unwrap().unwrap(),
+        SetRate { rate: 300 }
+    );
+}
+
+#[test]
+fn test_unexpected_message() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    sender.send_protobuf(&SetRate { rate: 300 }).unwrap();
+
+    assert!(matches!(
+        receiver.receive_protobuf::<Reading>(),
+        Err(ProtobufError::UnexpectedMessage(0x0101))
+    ));
+}
+
+#[test]
+fn test_invalid_payload() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    // A varint field that is cut off
+    sender.send_raw(SetRate::ID, &[0x08, 0xAC]).unwrap();
+
+    assert!(matches!(
+        receiver.receive_protobuf::<SetRate>(),
+        Err(ProtobufError::Decode(_))
+    ));
+}
@@ -0,0 +1,86 @@
+use crate::message::Message;
+use std::collections::VecDeque;
+
+/// The priority of a message queued with
+/// [`SerialManager::send_queued`](crate::SerialManager::send_queued).
+///
+/// Higher priorities are sent first, and messages of the same priority in the order they were
+/// queued.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Priority {
+    /// Bulk data such as firmware chunks, sent when nothing else is waiting
+    Bulk,
+    /// Periodic telemetry
+    Telemetry,
+    /// Commands and other urgent messages
+    Control,
+}
+
+/// Outgoing messages waiting to be sent, one FIFO queue per priority
+#[derive(Debug, Default)]
+pub(crate) struct OutgoingQueue {
+    queues: [VecDeque<Message>; 3],
+}
+
+impl OutgoingQueue {
+    pub(crate) fn push(&mut self, message: Message, priority: Priority) {
+        self.queues[priority as usize].push_back(message);
+    }
+
+    /// Puts a message that could not be sent back at the front of its queue
+    pub(crate) fn push_front(&mut self, message: Message, priority: Priority) {
+        self.queues[priority as usize].push_front(message);
+    }
+
+    /// Removes the oldest message of the highest priority
+    pub(crate) fn pop(&mut self) -> Option<(Message, Priority)> {
+        [Priority::Control, Priority::Telemetry, Priority::Bulk]
+            .into_iter()
+            .find_map(|priority| {
+                self.queues[priority as usize]
+                    .pop_front()
+                    .map(|message| (message, priority))
+            })
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// The most requests whose priority is remembered until they are responded to, beyond which the
+/// oldest are forgotten, so that requests never responded to do not pile up
+const MAX_PENDING_REQUESTS: usize = 64;
+
+/// The priorities of `Command`s received and not yet responded to, by ID, oldest first
+#[derive(Debug, Default)]
+pub(crate) struct PendingRequests {
+    requests: VecDeque<(u16, Priority)>,
+}
+
+impl PendingRequests {
+    /// Remembers the priority of a received request, replacing that of an earlier one with the
+    /// same ID
+    pub(crate) fn insert(&mut self, id: u16, priority: Priority) {
+        self.remove(id);
+        if self.requests.len() == MAX_PENDING_REQUESTS {
+            self.requests.pop_front();
+        }
+        self.requests.push_back((id, priority));
+    }
+
+    pub(crate) fn get(&self, id: u16) -> Option<Priority> {
+        self.requests
+            .iter()
+            .find_map(|&(pending, priority)| (pending == id).then_some(priority))
+    }
+
+    /// Forgets a request that has been responded to, returning its priority
+    pub(crate) fn remove(&mut self, id: u16) -> Option<Priority> {
+        let index = self
+            .requests
+            .iter()
+            .position(|&(pending, _)| pending == id)?;
+        self.requests.remove(index).map(|(_, priority)| priority)
+    }
+}
@@ -0,0 +1,243 @@
+use crate::errors::LimitViolation;
+use crate::time::Instant;
+use std::time::Duration;
+
+/// Limits on how fast a [`SerialManager`](crate::SerialManager) sends, set with
+/// [`SerialManager::with_rate_limit`](crate::SerialManager::with_rate_limit), for peers whose
+/// receive buffers overflow when frames arrive in bursts.
+///
+/// Each limit is a token bucket: up to the burst is sent at once, after which sending waits for
+/// tokens to refill at the given rate. With no burst, the default, frames are evenly paced.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct RateLimit {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::profile::rate"))]
+    bytes_per_second: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::profile::rate"))]
+    frames_per_second: Option<u32>,
+    burst_bytes: u32,
+    burst_frames: u32,
+}
+
+impl RateLimit {
+    /// Creates a limit that does not restrict sending until a rate is set
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits sending to `bytes_per_second` bytes a second, counting every byte of each frame
+    #[must_use]
+    pub fn with_bytes_per_second(mut self, bytes_per_second: u32) -> Self {
+        self.bytes_per_second = Some(bytes_per_second.max(1));
+        self
+    }
+
+    /// Limits sending to `frames_per_second` frames a second
+    #[must_use]
+    pub fn with_frames_per_second(mut self, frames_per_second: u32) -> Self {
+        self.frames_per_second = Some(frames_per_second.max(1));
+        self
+    }
+
+    /// Allows up to `burst_bytes` bytes beyond the byte rate to be sent at once after an idle
+    /// period, such as the size of the peer's receive buffer
+    #[must_use]
+    pub fn with_burst_bytes(mut self, burst_bytes: u32) -> Self {
+        self.burst_bytes = burst_bytes;
+        self
+    }
+
+    /// Allows up to `burst_frames` frames beyond the frame rate to be sent at once after an idle
+    /// period
+    #[must_use]
+    pub fn with_burst_frames(mut self, burst_frames: u32) -> Self {
+        self.burst_frames = burst_frames;
+        self
+    }
+}
+
+/// The state of the token buckets of a [`RateLimit`], kept as the time each bucket is next
+/// full, which saves refilling them on a timer
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    bytes_full_at: Option<Instant>,
+    frames_full_at: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bytes_full_at: None,
+            frames_full_at: None,
+        }
+    }
+
+    pub(crate) fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Takes the tokens for sending `bytes` bytes in `frames` frames, returning how long after
+    /// `now` they may be sent
+    ///
+    /// Sending may overdraw the buckets, so a write larger than the burst still goes through once
+    /// the buckets are full, with later writes waiting for the debt to be repaid.
+    pub(crate) fn reserve(&mut self, now: Instant, bytes: usize, frames: usize) -> Duration {
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX);
+        let frames = u64::try_from(frames).unwrap_or(u64::MAX);
+        let byte_delay = Self::take(
+            &mut self.bytes_full_at,
+            now,
+            self.limit.bytes_per_second,
+            self.limit.burst_bytes,
+            bytes,
+        );
+        let frame_delay = Self::take(
+            &mut self.frames_full_at,
+            now,
+            self.limit.frames_per_second,
+            self.limit.burst_frames,
+            frames,
+        );
+        byte_delay.max(frame_delay)
+    }
+
+    /// Takes `tokens` from a bucket refilled at `rate` a second holding `burst` tokens, returning
+    /// how long until the bucket has any tokens to take
+    fn take(
+        full_at: &mut Option<Instant>,
+        now: Instant,
+        rate: Option<u32>,
+        burst: u32,
+        tokens: u64,
+    ) -> Duration {
+        let Some(rate) = rate else {
+            return Duration::ZERO;
+        };
+        let full_at = full_at.get_or_insert(now);
+        // The time one burst of tokens takes to refill
+        let burst = Duration::from_secs(u64::from(burst)) / rate;
+        let delay = full_at.saturating_duration_since(now).saturating_sub(burst);
+        *full_at = (*full_at).max(now) + Duration::from_secs(tokens) / rate;
+        delay
+    }
+}
+
+/// What a [`SerialManager`](crate::SerialManager) does with a received frame that exceeds the
+/// [`ReceiveLimit`] of its message type
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum LimitAction {
+    /// Drops the frame, counting it in [`Stats::frames_limited`](crate::Stats::frames_limited),
+    /// and receives the next one
+    #[default]
+    Drop,
+    /// Fails the receive with [`ReceiveError::LimitExceeded`](crate::ReceiveError::LimitExceeded)
+    Error,
+}
+
+/// Limits on the frames of one message type a [`SerialManager`](crate::SerialManager) accepts,
+/// set with [`SerialManager::with_receive_limit`](crate::SerialManager::with_receive_limit), so
+/// that a peer flooding one message type cannot starve the processing of others.
+///
+/// Frames are checked as soon as they are complete, before their payload is decoded. The rate
+/// is a token bucket like those of [`RateLimit`], except that frames beyond it are not waited
+/// for but dropped or failed, and take no tokens.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct ReceiveLimit {
+    max_payload_length: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "crate::profile::rate"))]
+    frames_per_second: Option<u32>,
+    burst_frames: u32,
+    action: LimitAction,
+}
+
+impl ReceiveLimit {
+    /// Creates a limit that accepts every frame until a limit is set
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits payloads to `max_payload_length` bytes, not counting the message type
+    #[must_use]
+    pub fn with_max_payload_length(mut self, max_payload_length: usize) -> Self {
+        self.max_payload_length = Some(max_payload_length);
+        self
+    }
+
+    /// Limits receiving to `frames_per_second` frames a second
+    #[must_use]
+    pub fn with_frames_per_second(mut self, frames_per_second: u32) -> Self {
+        self.frames_per_second = Some(frames_per_second.max(1));
+        self
+    }
+
+    /// Accepts up to `burst_frames` frames beyond the frame rate at once after an idle period
+    #[must_use]
+    pub fn with_burst_frames(mut self, burst_frames: u32) -> Self {
+        self.burst_frames = burst_frames;
+        self
+    }
+
+    /// Sets what is done with frames that exceed the limit, which are dropped by default
+    #[must_use]
+    pub fn with_action(mut self, action: LimitAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// The state of the token bucket of a [`ReceiveLimit`], kept as the time it is next full
+#[derive(Debug)]
+pub(crate) struct ReceiveLimiter {
+    limit: ReceiveLimit,
+    full_at: Option<Instant>,
+}
+
+impl ReceiveLimiter {
+    pub(crate) fn new(limit: ReceiveLimit) -> Self {
+        Self {
+            limit,
+            full_at: None,
+        }
+    }
+
+    pub(crate) fn action(&self) -> LimitAction {
+        self.limit.action
+    }
+
+    /// Checks a frame with a payload of `payload_length` bytes received at `now`, taking a token
+    /// for it if it is accepted
+    pub(crate) fn check(
+        &mut self,
+        now: Instant,
+        payload_length: usize,
+    ) -> Result<(), LimitViolation> {
+        if let Some(max) = self.limit.max_payload_length {
+            if payload_length > max {
+                return Err(LimitViolation::PayloadTooLong {
+                    length: payload_length,
+                    max,
+                });
+            }
+        }
+        let Some(rate) = self.limit.frames_per_second else {
+            return Ok(());
+        };
+        let full_at = self.full_at.get_or_insert(now);
+        // The time one burst of tokens takes to refill
+        let burst = Duration::from_secs(u64::from(self.limit.burst_frames)) / rate;
+        if full_at.saturating_duration_since(now) > burst {
+            return Err(LimitViolation::RateExceeded(rate));
+        }
+        *full_at = (*full_at).max(now) + Duration::from_secs(1) / rate;
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait between failed connection attempts
+///
+/// The delay starts at `initial` and doubles after every failed attempt, up to `max`. It starts
+/// over once a connection has been established.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    #[must_use]
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(5))
+    }
+}
+
+/// A change in the state of a [`ReconnectingConnection`], passed to its state handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// A connection was established, for the first time or again
+    Connected,
+    /// The connection failed or was closed by the peer, and was dropped
+    Disconnected,
+    /// Connecting is about to be tried again after waiting, following a failed attempt or a
+    /// dropped connection
+    Reconnecting,
+}
+
+type Connect<T> = Box<dyn FnMut() -> io::Result<T> + Send>;
+type StateHandler = Box<dyn FnMut(ConnectionState) + Send>;
+
+/// A [`SerialManager`](crate::SerialManager) over a connection that reconnects after IO errors,
+/// such as one made with [`SerialManager::resilient`](crate::SerialManager::resilient)
+pub type ResilientSerialManager<T> = crate::SerialManager<ReconnectingConnection<T>>;
+
+/// A connection that transparently reconnects after IO errors.
+///
+/// Passing a `ReconnectingConnection` to [`SerialManager::new`](crate::SerialManager::new) keeps
+/// a link such as a ser2net TCP bridge usable across drops. When reading or writing fails, or the
+/// peer closes the connection, the connection is dropped and `connect` is called again, waiting
+/// according to the [`Backoff`] in between.
+///
+/// Writes are buffered until `flush`, which `SerialManager` calls after every frame, so a frame
+/// that could not be sent is resent whole on the new connection. A frame that was partly
+/// received when the connection dropped is discarded by the decoder when the first frame arrives
+/// on the new connection, and counted as a resync.
+///
+/// Timeouts are returned without reconnecting, so [`ping`](crate::SerialManager::ping) keeps
+/// working with a read timeout set by `connect`.
+pub struct ReconnectingConnection<T> {
+    connect: Connect<T>,
+    connection: Option<T>,
+    backoff: Backoff,
+    max_attempts: Option<u32>,
+    delay: Duration,
+    failures: u32,
+    connected_before: bool,
+    reconnects: u64,
+    write_buffer: Vec<u8>,
+    state_handler: Option<StateHandler>,
+}
+
+impl<T> ReconnectingConnection<T>
+where
+    T: Read + Write,
+{
+    /// Creates a connection that calls `connect` whenever it needs a new underlying connection
+    ///
+    /// The first connection is only made when the connection is first read or flushed.
+    pub fn new(connect: impl FnMut() -> io::Result<T> + Send + 'static) -> Self {
+        let backoff = Backoff::default();
+        Self {
+            connect: Box::new(connect),
+            connection: None,
+            backoff,
+            max_attempts: None,
+            delay: backoff.initial,
+            failures: 0,
+            connected_before: false,
+            reconnects: 0,
+            write_buffer: Vec::new(),
+            state_handler: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self.delay = backoff.initial;
+        self
+    }
+
+    /// Gives up after `max_attempts` consecutive failed connection attempts, returning the error
+    /// of the last one, instead of retrying forever
+    #[must_use]
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Calls `handler` whenever the connection is established, dropped or about to be retried,
+    /// e.g. to show the link status or to resend configuration after a device reboots
+    ///
+    /// The handler runs on the thread reading or writing, in the middle of the read or flush
+    /// that noticed the change, so it must not block for long.
+    #[must_use]
+    pub fn with_state_handler(
+        mut self,
+        handler: impl FnMut(ConnectionState) + Send + 'static,
+    ) -> Self {
+        self.state_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// How many times the connection has been re-established after the first one
+    #[must_use]
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Whether there is currently an underlying connection
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Returns the underlying connection, connecting first if there is none
+    fn connection(&mut self) -> io::Result<&mut T> {
+        if self.connection.is_none() {
+            let mut attempts = 0;
+            let connection = loop {
+                if self.failures > 0 {
+                    self.notify(ConnectionState::Reconnecting);
+                    thread::sleep(self.delay);
+                    self.delay = (self.delay * 2).min(self.backoff.max);
+                }
+                match (self.connect)() {
+                    Ok(connection) => break connection,
+                    Err(e) => {
+                        self.failures += 1;
+                        attempts += 1;
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(error = %e, attempts, "failed to connect");
+                        if self.max_attempts.is_some_and(|max| attempts >= max) {
+                            self.reset_backoff();
+                            return Err(e);
+                        }
+                    }
+                }
+            };
+            if self.connected_before {
+                self.reconnects += 1;
+                #[cfg(feature = "tracing")]
+                tracing::info!(reconnects = self.reconnects, "reconnected");
+            }
+            self.connected_before = true;
+            self.connection = Some(connection);
+            self.notify(ConnectionState::Connected);
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// Drops the underlying connection after it failed, so that the next read or flush
+    /// reconnects after waiting
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn disconnect(&mut self, reason: &dyn std::fmt::Display) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(%reason, "connection lost");
+        self.connection = None;
+        self.failures += 1;
+        self.notify(ConnectionState::Disconnected);
+    }
+
+    fn notify(&mut self, state: ConnectionState) {
+        if let Some(handler) = &mut self.state_handler {
+            handler(state);
+        }
+    }
+
+    /// Called once data has made it across, as the connection evidently works
+    fn reset_backoff(&mut self) {
+        self.failures = 0;
+        self.delay = self.backoff.initial;
+    }
+}
+
+impl ReconnectingConnection<TcpStream> {
+    /// Creates a connection to a TCP server, such as a ser2net bridge, with Nagle's algorithm
+    /// disabled so that frames are sent without delay
+    ///
+    /// `addr` is resolved again for every connection attempt.
+    pub fn tcp(addr: impl ToSocketAddrs + Send + 'static) -> Self {
+        Self::new(move || {
+            let stream = TcpStream::connect(&addr)?;
+            stream.set_nodelay(true)?;
+            Ok(stream)
+        })
+    }
+}
+
+/// Whether an error leaves the connection usable, so that it is returned instead of reconnecting
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+impl<T> Read for ReconnectingConnection<T>
+where
+    T: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.connection()?.read(buf) {
+                Ok(0) => self.disconnect(&"closed by peer"),
+                Ok(count) => {
+                    self.reset_backoff();
+                    return Ok(count);
+                }
+                Err(e) if is_transient(&e) => return Err(e),
+                Err(e) => self.disconnect(&e),
+            }
+        }
+    }
+}
+
+impl<T> Write for ReconnectingConnection<T>
+where
+    T: Read + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Taken so that a failed flush does not resend the same bytes with the next one
+        let bytes = std::mem::take(&mut self.write_buffer);
+        loop {
+            let connection = self.connection()?;
+            match connection
+                .write_all(&bytes)
+                .and_then(|()| connection.flush())
+            {
+                Ok(()) => {
+                    self.reset_backoff();
+                    return Ok(());
+                }
+                Err(e) if is_transient(&e) => return Err(e),
+                Err(e) => self.disconnect(&e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,185 @@
+use super::*;
+use crate::{message_types, Message, SerialManager};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+fn fast_backoff() -> Backoff {
+    Backoff::new(Duration::from_millis(1), Duration::from_millis(4))
+}
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+/// A connection that has nothing to read and records what is written to it, or fails every write
+struct Recorder {
+    written: Arc<Mutex<Vec<u8>>>,
+    fail_writes: bool,
+}
+
+impl Read for Recorder {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.fail_writes {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.written.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reconnects_after_peer_closes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        for num in [1, 2] {
+            let (stream, _) = listener.accept().unwrap();
+            SerialManager::new(stream).send(u8_message(num)).unwrap();
+        }
+    });
+
+    let connection = ReconnectingConnection::tcp(addr).with_backoff(fast_backoff());
+    let mut manager = SerialManager::new(connection);
+    assert_eq!(manager.receive().unwrap(), u8_message(1));
+    assert_eq!(manager.receive().unwrap(), u8_message(2));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_state_changes() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        for num in [1, 2] {
+            let (stream, _) = listener.accept().unwrap();
+            SerialManager::new(stream).send(u8_message(num)).unwrap();
+        }
+    });
+
+    let states = Arc::new(Mutex::new(Vec::new()));
+    let connection = ReconnectingConnection::tcp(addr)
+        .with_backoff(fast_backoff())
+        .with_state_handler({
+            let states = Arc::clone(&states);
+            move |state| states.lock().unwrap().push(state)
+        });
+    let mut manager = SerialManager::new(connection);
+    assert_eq!(manager.receive().unwrap(), u8_message(1));
+    assert_eq!(manager.receive().unwrap(), u8_message(2));
+    server.join().unwrap();
+
+    assert_eq!(
+        *states.lock().unwrap(),
+        [
+            ConnectionState::Connected,
+            ConnectionState::Disconnected,
+            ConnectionState::Reconnecting,
+            ConnectionState::Connected,
+        ]
+    );
+}
+
+#[test]
+fn test_resilient_manager() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        for num in [1, 2] {
+            let (stream, _) = listener.accept().unwrap();
+            SerialManager::new(stream).send(u8_message(num)).unwrap();
+        }
+    });
+
+    let mut manager: ResilientSerialManager<_> =
+        SerialManager::resilient(move || TcpStream::connect(addr));
+    assert_eq!(manager.receive().unwrap(), u8_message(1));
+    assert_eq!(manager.receive().unwrap(), u8_message(2));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_partial_frame_is_resynced() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        // The start of a U8 frame, cut off before its payload
+        stream.write_all(&[0x58, 0x03, 0x00, 0x01]).unwrap();
+        drop(stream);
+        let (stream, _) = listener.accept().unwrap();
+        SerialManager::new(stream).send(u8_message(2)).unwrap();
+    });
+
+    let connection = ReconnectingConnection::tcp(addr).with_backoff(fast_backoff());
+    let mut manager = SerialManager::new(connection);
+    assert_eq!(manager.receive().unwrap(), u8_message(2));
+    assert_eq!(manager.stats().resyncs, 1);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_failed_frame_is_resent_whole() {
+    let written = Arc::new(Mutex::new(Vec::new()));
+    let mut attempt = 0;
+    let connection = ReconnectingConnection::new({
+        let written = Arc::clone(&written);
+        move || {
+            attempt += 1;
+            Ok(Recorder {
+                written: Arc::clone(&written),
+                fail_writes: attempt == 1,
+            })
+        }
+    })
+    .with_backoff(fast_backoff());
+    let mut manager = SerialManager::new(connection);
+
+    manager.send(u8_message(1)).unwrap();
+    assert_eq!(
+        *written.lock().unwrap(),
+        [0x58, 0x03, 0x00, 0x01, 0x00, 0x01]
+    );
+}
+
+#[test]
+fn test_gives_up_after_max_attempts() {
+    let mut attempts = 0;
+    let mut connection = ReconnectingConnection::<Recorder>::new(move || {
+        attempts += 1;
+        assert!(attempts <= 3);
+        Err(io::ErrorKind::ConnectionRefused.into())
+    })
+    .with_backoff(fast_backoff())
+    .with_max_attempts(3);
+
+    let error = connection.read(&mut [0]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    assert!(!connection.is_connected());
+}
+
+#[test]
+fn test_timeouts_do_not_reconnect() {
+    let mut connection = ReconnectingConnection::new(|| {
+        Ok(Recorder {
+            written: Arc::default(),
+            fail_writes: false,
+        })
+    });
+
+    for _ in 0..2 {
+        let error = connection.read(&mut [0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+    }
+    assert!(connection.is_connected());
+    assert_eq!(connection.reconnects(), 0);
+}
@@ -0,0 +1,314 @@
+use crate::codec::Endianness;
+use crate::events::{decode_message, SerialManagerEvents};
+use crate::message::Message;
+use crate::rotation::RotatingFiles;
+use crate::schema;
+use crate::time::{SystemTime, UNIX_EPOCH};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// The file format a [`Recorder`] writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// One JSON object per line, in `.jsonl` files
+    JsonLines,
+    /// Comma-separated values with a header line, in `.csv` files
+    Csv,
+    /// A `messages` table in SQLite databases, in `.sqlite` files
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl RecordFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::JsonLines => "jsonl",
+            RecordFormat::Csv => "csv",
+            #[cfg(feature = "sqlite")]
+            RecordFormat::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// The file being written
+#[derive(Debug)]
+enum Output {
+    Text(File),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Connection),
+}
+
+/// A data logger writing every message received to files that are rotated by size, as JSON
+/// lines, CSV or, with the `sqlite` feature, SQLite databases
+///
+/// Each message is recorded with the time it was received in seconds since the Unix epoch, its
+/// message type and name, its reading if it holds a single number, the message as displayed on
+/// one line and its payload in hex:
+///
+/// ```text
+/// {"time":1718000000.123456,"message_type":7,"name":"U32","value":7,"message":"U32 { num: 7 }","payload":"07000000"}
+/// ```
+///
+/// Message types that are not built in have no name, and are displayed as
+/// [`Message::Unknown`]. SQLite databases hold the same columns in a `messages` table, with the
+/// payload as a blob.
+///
+/// `Recorder` implements [`SerialManagerEvents`], so it can be registered on a
+/// [`SerialManager`](crate::SerialManager) to record every message it receives:
+///
+/// ```no_run
+/// # use generic_serial_protocol::{RecordFormat, Recorder, SerialManager};
+/// # let stream = std::io::Cursor::new(Vec::new());
+/// let mut manager = SerialManager::new(stream);
+/// manager.set_events(Recorder::new("telemetry", RecordFormat::Csv));
+/// loop {
+///     manager.receive().unwrap();
+/// }
+/// ```
+///
+/// Files are named `<prefix>-<microseconds since the Unix epoch>.<extension>` and rotated like
+/// a [`ByteLog`](crate::ByteLog)'s. Each record is written with a single call to `write_all`,
+/// so that a file is as complete as possible if the process dies. When used as an event
+/// handler, the first IO error stops the recording, as there is no way to report it to the
+/// caller.
+#[derive(Debug)]
+pub struct Recorder {
+    files: RotatingFiles,
+    format: RecordFormat,
+    endianness: Endianness,
+    max_file_size: u64,
+    output: Option<Output>,
+    file_size: u64,
+    failed: bool,
+}
+
+impl Recorder {
+    /// Creates a recorder writing `format` to `directory`, which is created when the first
+    /// message is recorded if it does not exist, keeping up to 10 files of 16 MiB named with the
+    /// prefix `telemetry`
+    #[must_use]
+    pub fn new(directory: impl Into<PathBuf>, format: RecordFormat) -> Self {
+        Self {
+            files: RotatingFiles::new(directory.into(), "telemetry", format.extension(), 10),
+            format,
+            endianness: Endianness::Little,
+            max_file_size: 16 * 1024 * 1024,
+            output: None,
+            file_size: 0,
+            failed: false,
+        }
+    }
+
+    /// Names files starting with `prefix`, so that several recorders can share a directory
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.files.prefix = prefix.into();
+        self
+    }
+
+    /// Starts a new file once the current one would grow beyond `max_file_size` bytes
+    ///
+    /// SQLite databases are checked once they have grown beyond it instead, as the size of a
+    /// row on disk is not known before it is inserted.
+    #[must_use]
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Keeps at most `max_files` files, at least 1, deleting the oldest
+    #[must_use]
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.files.max_files = max_files.max(1);
+        self
+    }
+
+    /// Decodes payloads received as an event handler with numbers in the given byte order,
+    /// which should match the manager's
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Records a message received at `received_at`
+    pub fn record(&mut self, message: &Message, received_at: SystemTime) -> io::Result<()> {
+        let payload = message.clone().to_bytes_with(self.endianness)?;
+        self.write(message, &payload, received_at)
+    }
+
+    fn write(&mut self, message: &Message, payload: &[u8], time: SystemTime) -> io::Result<()> {
+        let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let message_type = message.message_type();
+        let name = schema::message_name(message_type).filter(|_| !is_unknown(message));
+        let value = message.reading();
+        match self.format {
+            RecordFormat::JsonLines => {
+                let mut line = format!(
+                    "{{\"time\":{}.{:06},\"message_type\":{message_type},\"name\":",
+                    time.as_secs(),
+                    time.subsec_micros()
+                );
+                match name {
+                    Some(name) => json_string(&mut line, name),
+                    None => line.push_str("null"),
+                }
+                line.push_str(",\"value\":");
+                match value.filter(|value| value.is_finite()) {
+                    Some(value) => {
+                        let _ = write!(line, "{value}");
+                    }
+                    None => line.push_str("null"),
+                }
+                line.push_str(",\"message\":");
+                json_string(&mut line, &message.to_string());
+                let _ = writeln!(line, ",\"payload\":\"{}\"}}", hex(payload));
+                self.write_text(&line, None)
+            }
+            RecordFormat::Csv => {
+                let line = format!(
+                    "{}.{:06},{message_type},{},{},{},{}\n",
+                    time.as_secs(),
+                    time.subsec_micros(),
+                    name.unwrap_or_default(),
+                    value.map(|value| value.to_string()).unwrap_or_default(),
+                    csv_field(&message.to_string()),
+                    hex(payload)
+                );
+                self.write_text(&line, Some(CSV_HEADER))
+            }
+            #[cfg(feature = "sqlite")]
+            RecordFormat::Sqlite => {
+                if self.output.is_none() || self.file_size > self.max_file_size {
+                    self.start()?;
+                }
+                let Some(Output::Sqlite(connection)) = &self.output else {
+                    return Ok(());
+                };
+                connection
+                    .execute(
+                        "INSERT INTO messages (time, message_type, name, value, message, payload) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        rusqlite::params![
+                            time.as_secs_f64(),
+                            message_type,
+                            name,
+                            value,
+                            message.to_string(),
+                            payload
+                        ],
+                    )
+                    .map_err(io::Error::other)?;
+                if let Some(path) = connection.path() {
+                    self.file_size = std::fs::metadata(path)?.len();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a line to the current text file, starting a new one with `header` if it would
+    /// grow beyond the maximum size
+    fn write_text(&mut self, line: &str, header: Option<&str>) -> io::Result<()> {
+        let length = line.len() as u64;
+        if self.output.is_none()
+            || (self.file_size > 0 && self.file_size + length > self.max_file_size)
+        {
+            self.start()?;
+            if let Some(header) = header {
+                self.write_text(header, None)?;
+            }
+        }
+        if let Some(Output::Text(file)) = &mut self.output {
+            file.write_all(line.as_bytes())?;
+            self.file_size += length;
+        }
+        Ok(())
+    }
+
+    /// Starts a new file and deletes the oldest beyond the maximum
+    fn start(&mut self) -> io::Result<()> {
+        self.output = None;
+        #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+        let (path, file) = self.files.start()?;
+        self.file_size = 0;
+        self.output = Some(match self.format {
+            RecordFormat::JsonLines | RecordFormat::Csv => Output::Text(file),
+            #[cfg(feature = "sqlite")]
+            RecordFormat::Sqlite => {
+                let connection = rusqlite::Connection::open(path).map_err(io::Error::other)?;
+                connection
+                    .execute(
+                        "CREATE TABLE messages (time REAL NOT NULL, message_type INTEGER NOT \
+                         NULL, name TEXT, value REAL, message TEXT NOT NULL, payload BLOB NOT \
+                         NULL)",
+                        [],
+                    )
+                    .map_err(io::Error::other)?;
+                Output::Sqlite(connection)
+            }
+        });
+        Ok(())
+    }
+}
+
+impl SerialManagerEvents for Recorder {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn on_receive(&mut self, message_type: u16, payload: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let message = decode_message(message_type, payload, self.endianness);
+        if let Err(e) = self.write(&message, payload, SystemTime::now()) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %e, "failed to write recording, stopping recording");
+            self.failed = true;
+        }
+    }
+}
+
+const CSV_HEADER: &str = "time,message_type,name,value,message,payload\n";
+
+fn is_unknown(message: &Message) -> bool {
+    matches!(message, Message::Unknown { .. })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{byte:02x}");
+        text
+    })
+}
+
+/// Appends `text` as a JSON string
+fn json_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Quotes `text` as a CSV field if it holds a comma, quote or line break
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,173 @@
+use super::*;
+use crate::message::message_types;
+use crate::serial_manager::SerialManager;
+use crate::test_util::stream_pair;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Returns a directory for a test, removing anything left by an earlier run
+fn test_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("gsp-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+/// Returns the files in `directory`, oldest first
+fn files(directory: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+}
+
+fn at(secs: u64, micros: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::new(secs, micros * 1000)
+}
+
+#[test]
+fn test_json_lines() {
+    let directory = test_directory("recorder-jsonl");
+    let mut recorder = Recorder::new(&directory, RecordFormat::JsonLines);
+    recorder
+        .record(
+            &Message::U32(message_types::U32 { num: 7 }),
+            at(1_718_000_000, 123_456),
+        )
+        .unwrap();
+    recorder
+        .record(
+            &Message::Unknown {
+                message_type: 0x1234,
+                data: vec![0xAB],
+            },
+            at(1_718_000_001, 5),
+        )
+        .unwrap();
+    recorder
+        .record(
+            &Message::MyString(message_types::MyString {
+                string: "say \"hi\"\n".to_string(),
+            }),
+            at(1_718_000_002, 0),
+        )
+        .unwrap();
+
+    let files = files(&directory);
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].extension().unwrap(), "jsonl");
+    let lines = fs::read_to_string(&files[0]).unwrap();
+    let lines: Vec<&str> = lines.lines().collect();
+    assert_eq!(
+        lines[0],
+        r#"{"time":1718000000.123456,"message_type":7,"name":"U32","value":7,"message":"U32 { num: 7 }","payload":"07000000"}"#
+    );
+    assert_eq!(
+        lines[1],
+        r#"{"time":1718000001.000005,"message_type":4660,"name":null,"value":null,"message":"Unknown(0x1234) [AB]","payload":"ab"}"#
+    );
+    assert!(lines[2].contains(r#""message":"MyString { string: \"say \\\"hi\\\"\\n\" }""#));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_csv_rotates() {
+    let directory = test_directory("recorder-csv");
+    let mut recorder = Recorder::new(&directory, RecordFormat::Csv)
+        .with_prefix("readings")
+        .with_max_file_size(100)
+        .with_max_files(2);
+    for num in 0..6i16 {
+        recorder
+            .record(
+                &Message::I16(message_types::I16 { num: -num }),
+                at(1_718_000_000 + u64::from(num.unsigned_abs()), 0),
+            )
+            .unwrap();
+    }
+
+    let files = files(&directory);
+    assert_eq!(files.len(), 2);
+    for file in &files {
+        let name = file.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("readings-"));
+        assert_eq!(file.extension().unwrap(), "csv");
+        let text = fs::read_to_string(file).unwrap();
+        assert!(text.starts_with(CSV_HEADER));
+        assert!(text.len() <= 100);
+    }
+    let last = fs::read_to_string(&files[1]).unwrap();
+    assert!(last.ends_with("1718000005.000000,10,I16,-5,I16 { num: -5 },fbff\n"));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_records_messages_received() {
+    let directory = test_directory("recorder-events");
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2).with_unknown_messages(true);
+    receiver.set_events(Recorder::new(&directory, RecordFormat::Csv));
+
+    sender
+        .send(Message::MyString(message_types::MyString {
+            string: "a, b".to_string(),
+        }))
+        .unwrap();
+    sender.send_raw(0x1234, &[1, 2]).unwrap();
+    receiver.receive().unwrap();
+    receiver.receive().unwrap();
+
+    let text = fs::read_to_string(&files(&directory)[0]).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].ends_with(",2,MyString,,\"MyString { string: \"\"a, b\"\" }\",612c2062"));
+    assert!(lines[2].ends_with(",4660,,,Unknown(0x1234) [01 02],0102"));
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite() {
+    let directory = test_directory("recorder-sqlite");
+    let mut recorder = Recorder::new(&directory, RecordFormat::Sqlite);
+    recorder
+        .record(
+            &Message::F32(message_types::F32 { num: 1.5 }),
+            at(1_718_000_000, 500_000),
+        )
+        .unwrap();
+    drop(recorder);
+
+    let files = files(&directory);
+    assert_eq!(files.len(), 1);
+    let connection = rusqlite::Connection::open(&files[0]).unwrap();
+    let row: (f64, u16, String, f64, Vec<u8>) = connection
+        .query_row(
+            "SELECT time, message_type, name, value, payload FROM messages",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        row,
+        (
+            1_718_000_000.5,
+            Message::F32(message_types::F32 { num: 0.0 }).message_type(),
+            "F32".to_string(),
+            1.5,
+            1.5f32.to_le_bytes().to_vec()
+        )
+    );
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,161 @@
+use crate::codec::Frame;
+use crate::firmware::crc32;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A record of a message queued, with its id, message type, payload length and payload
+const QUEUED: u8 = 1;
+/// A record of a message acknowledged, with its id
+const ACKNOWLEDGED: u8 = 2;
+
+/// An append-only file recording the messages queued by a [`Reliable`](super::Reliable) sender
+/// and their acknowledgements
+///
+/// Every record ends with a CRC-32 of the rest of it, and is synced to disk before the message
+/// is sent. A record cut short by a crash ends the journal, and is dropped when it is opened.
+#[derive(Debug)]
+pub(super) struct Journal {
+    file: File,
+    /// The id of the last message queued
+    last_id: Option<u32>,
+}
+
+/// What a journal held when it was opened
+#[derive(Debug, Default)]
+pub(super) struct Contents {
+    /// Messages queued and not acknowledged, with their ids, in the order they were queued
+    pub(super) pending: Vec<(u32, Frame)>,
+    /// The id of the last message queued, which is the last id used
+    pub(super) last_id: Option<u32>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if needed, and compacts it down to the messages
+    /// still pending
+    pub(super) fn open(path: &Path) -> io::Result<(Self, Contents)> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let contents = parse(&bytes);
+
+        // Rewritten beside the journal and renamed over it, so that a crash leaves one or the
+        // other whole
+        let mut compacted = path.as_os_str().to_owned();
+        compacted.push(".tmp");
+        let compacted = PathBuf::from(compacted);
+        let mut file = File::create(&compacted)?;
+        file.write_all(&compact(&contents))?;
+        file.sync_all()?;
+        fs::rename(&compacted, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let journal = Self {
+            file,
+            last_id: contents.last_id,
+        };
+        Ok((journal, contents))
+    }
+
+    pub(super) fn queued(&mut self, id: u32, frame: &Frame) -> io::Result<()> {
+        self.append(&queued_record(id, frame))?;
+        self.last_id = Some(id);
+        Ok(())
+    }
+
+    /// Records that `id` was acknowledged, starting the journal over if nothing else is pending
+    pub(super) fn acknowledged(&mut self, id: u32, pending: usize) -> io::Result<()> {
+        if pending == 0 {
+            // Started over with the last id acknowledged, so that ids are not used again
+            self.file.set_len(0)?;
+            let last_id = self.last_id.unwrap_or(id);
+            return self.append(&record(ACKNOWLEDGED, &last_id.to_le_bytes()));
+        }
+        self.append(&record(ACKNOWLEDGED, &id.to_le_bytes()))
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+        self.file.sync_data()
+    }
+}
+
+fn record(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(1 + body.len() + 4);
+    record.push(kind);
+    record.extend_from_slice(body);
+    let crc = crc32(&record);
+    record.extend_from_slice(&crc.to_le_bytes());
+    record
+}
+
+fn queued_record(id: u32, frame: &Frame) -> Vec<u8> {
+    let mut body = Vec::with_capacity(10 + frame.payload.len());
+    body.extend_from_slice(&id.to_le_bytes());
+    body.extend_from_slice(&frame.message_type.to_le_bytes());
+    // Payloads fit in a frame, so well under 4 GiB
+    let length = u32::try_from(frame.payload.len()).unwrap_or(u32::MAX);
+    body.extend_from_slice(&length.to_le_bytes());
+    body.extend_from_slice(&frame.payload);
+    record(QUEUED, &body)
+}
+
+/// The records needed to restore `contents`
+fn compact(contents: &Contents) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (id, frame) in &contents.pending {
+        bytes.extend(queued_record(*id, frame));
+    }
+    if let Some(last_id) = contents.last_id {
+        // Otherwise the last message queued is still pending
+        if contents.pending.last().map(|&(id, _)| id) != Some(last_id) {
+            bytes.extend(record(ACKNOWLEDGED, &last_id.to_le_bytes()));
+        }
+    }
+    bytes
+}
+
+/// Reads records up to the end of the journal or the first one cut short or corrupted
+fn parse(mut bytes: &[u8]) -> Contents {
+    let mut contents = Contents::default();
+    while let Some((length, kind, id)) = next_record(bytes) {
+        let body = &bytes[5..length - 4];
+        if kind == QUEUED {
+            let frame = Frame {
+                message_type: u16::from_le_bytes([body[0], body[1]]),
+                payload: body[6..].to_vec(),
+            };
+            contents.pending.push((id, frame));
+            contents.last_id = Some(id);
+        } else {
+            let pending = contents.pending.len();
+            contents.pending.retain(|&(pending, _)| pending != id);
+            // An acknowledgement of nothing pending records the last id of a journal started over
+            if contents.pending.len() == pending {
+                contents.last_id = Some(id);
+            }
+        }
+        bytes = &bytes[length..];
+    }
+    contents
+}
+
+/// Returns the length, kind and id of the record at the start of `bytes`, if it is whole and
+/// its CRC matches
+fn next_record(bytes: &[u8]) -> Option<(usize, u8, u32)> {
+    let kind = *bytes.first()?;
+    let id = u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?);
+    let length = match kind {
+        QUEUED => {
+            let payload = u32::from_le_bytes(bytes.get(7..11)?.try_into().ok()?);
+            11 + usize::try_from(payload).ok()? + 4
+        }
+        ACKNOWLEDGED => 9,
+        _ => return None,
+    };
+    let record = bytes.get(..length)?;
+    let (record, crc) = record.split_at(length - 4);
+    (crc32(record) == u32::from_le_bytes(crc.try_into().ok()?)).then_some((length, kind, id))
+}
@@ -0,0 +1,253 @@
+use crate::codec::{Frame, MAX_PAYLOAD_LENGTH};
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use crate::time::{Instant, SystemTime};
+use journal::Journal;
+use message_types::{ReliableAck, ReliableData};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+mod journal;
+
+/// The most payload bytes a message sent reliably can have, leaving room for its id and type
+pub const MAX_RELIABLE_PAYLOAD: usize = MAX_PAYLOAD_LENGTH - 6;
+
+/// How many ids of messages received are remembered to recognise ones sent again
+const RECENT_IDS: usize = 1024;
+
+#[derive(Debug)]
+struct Pending {
+    frame: Frame,
+    /// When the message was last sent, or `None` if it has not been since it was queued or
+    /// loaded from the journal
+    sent_at: Option<Instant>,
+}
+
+/// Delivers messages at least once, sending each one again until the peer acknowledges it
+///
+/// Messages sent through [`send`](Self::send) are numbered and wrapped in `ReliableData`, which
+/// the peer's `Reliable` answers with a `ReliableAck` in [`receive`](Self::receive) before
+/// handing the message on. Messages not acknowledged within the retransmit interval are sent
+/// again by `receive` or [`retransmit`](Self::retransmit), including after a reconnect. The
+/// receiver remembers the last 1024 ids it saw, so a message sent again after its
+/// acknowledgement was lost is not handed on twice.
+///
+/// Created with [`open`](Self::open), the queue of unacknowledged messages is kept in an
+/// append-only journal file, so that it survives the process restarting. Messages from the
+/// journal are sent again by the first `receive` or `retransmit`:
+///
+/// ```no_run
+/// use generic_serial_protocol::{message_types, Message, Reliable, SerialManager};
+///
+/// let mut manager = SerialManager::connect_tcp("192.168.1.20:5000").unwrap();
+/// let mut reliable = Reliable::open("outbox.journal").unwrap();
+/// reliable.retransmit(&mut manager).unwrap();
+/// reliable.send(&mut manager, Message::U32(message_types::U32 { num: 1 })).unwrap();
+/// loop {
+///     let message = reliable.receive(&mut manager).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Reliable {
+    pending: BTreeMap<u32, Pending>,
+    next_id: u32,
+    retransmit_interval: Duration,
+    journal: Option<Journal>,
+    /// The ids of the messages last received, oldest first
+    recent: VecDeque<u32>,
+}
+
+impl Default for Reliable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reliable {
+    /// Creates a sender keeping unacknowledged messages in memory, sending them again after a
+    /// second
+    #[must_use]
+    pub fn new() -> Self {
+        // Ids start somewhere new every run, so that a peer still remembering the ids of the
+        // last run does not take new messages for ones sent again
+        let start = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        Self {
+            pending: BTreeMap::new(),
+            next_id: start,
+            retransmit_interval: Duration::from_secs(1),
+            journal: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Creates a sender keeping unacknowledged messages in the journal at `path`, loading any
+    /// left there by an earlier run
+    ///
+    /// A record cut short by a crash is dropped, along with anything after it. The journal is
+    /// compacted down to the messages still pending.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let (journal, contents) = Journal::open(path.as_ref())?;
+        let mut reliable = Self::new();
+        if let Some(last_id) = contents.last_id {
+            reliable.next_id = last_id.wrapping_add(1);
+        }
+        reliable.pending = contents
+            .pending
+            .into_iter()
+            .map(|(id, frame)| {
+                let pending = Pending {
+                    frame,
+                    sent_at: None,
+                };
+                (id, pending)
+            })
+            .collect();
+        reliable.journal = Some(journal);
+        Ok(reliable)
+    }
+
+    /// Sets how long to wait for an acknowledgement before sending a message again
+    #[must_use]
+    pub fn with_retransmit_interval(mut self, interval: Duration) -> Self {
+        self.retransmit_interval = interval;
+        self
+    }
+
+    /// Returns the number of messages not yet acknowledged
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues a message, recording it in the journal if there is one, and sends it, returning
+    /// its id
+    ///
+    /// A message that was queued but could not be sent stays queued and is sent again later,
+    /// while the error is still returned. Fails with [`io::ErrorKind::InvalidInput`] without
+    /// queuing the message if its payload is longer than [`MAX_RELIABLE_PAYLOAD`].
+    pub fn send<T>(&mut self, manager: &mut SerialManager<T>, message: Message) -> io::Result<u32>
+    where
+        T: Transport,
+    {
+        let frame = Frame {
+            message_type: message.message_type(),
+            payload: message.to_bytes_with(manager.endianness())?,
+        };
+        if frame.payload.len() > MAX_RELIABLE_PAYLOAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes of payload is too long to send reliably",
+                    frame.payload.len()
+                ),
+            ));
+        }
+        let id = self.next_id;
+        if let Some(journal) = &mut self.journal {
+            journal.queued(id, &frame)?;
+        }
+        self.next_id = id.wrapping_add(1);
+        self.pending.insert(
+            id,
+            Pending {
+                frame,
+                sent_at: None,
+            },
+        );
+        self.transmit(manager, id)?;
+        Ok(id)
+    }
+
+    fn transmit<T>(&mut self, manager: &mut SerialManager<T>, id: u32) -> io::Result<()>
+    where
+        T: Transport,
+    {
+        let Some(pending) = self.pending.get_mut(&id) else {
+            return Ok(());
+        };
+        pending.sent_at = Some(Instant::now());
+        manager.send(Message::ReliableData(ReliableData {
+            id,
+            message_type: pending.frame.message_type,
+            data: pending.frame.payload.clone(),
+        }))
+    }
+
+    /// Sends again every message not acknowledged within the retransmit interval, along with
+    /// those loaded from the journal, returning how many were sent
+    pub fn retransmit<T>(&mut self, manager: &mut SerialManager<T>) -> io::Result<usize>
+    where
+        T: Transport,
+    {
+        let now = Instant::now();
+        let due: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| {
+                pending
+                    .sent_at
+                    .is_none_or(|sent_at| now.duration_since(sent_at) >= self.retransmit_interval)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for &id in &due {
+            self.transmit(manager, id)?;
+        }
+        Ok(due.len())
+    }
+
+    /// Receives the next message, acknowledging and unwrapping `ReliableData` and taking
+    /// `ReliableAck`s, after sending again the messages that are due
+    ///
+    /// Messages not sent reliably are returned as they are, and ones sent again after being
+    /// received are skipped. Unacknowledged messages are only sent again when this is called, so
+    /// the connection needs a read timeout for them to be sent while nothing is received; the
+    /// timeout is returned as usual.
+    pub fn receive<T>(&mut self, manager: &mut SerialManager<T>) -> Result<Message, ReceiveError>
+    where
+        T: Transport,
+    {
+        loop {
+            self.retransmit(manager)?;
+            match manager.receive()? {
+                Message::ReliableAck(ReliableAck { id }) => {
+                    if self.pending.remove(&id).is_some() {
+                        if let Some(journal) = &mut self.journal {
+                            journal.acknowledged(id, self.pending.len())?;
+                        }
+                    }
+                }
+                Message::ReliableData(data) => {
+                    manager.send(Message::ReliableAck(ReliableAck { id: data.id }))?;
+                    if self.recent.contains(&data.id) {
+                        continue;
+                    }
+                    if self.recent.len() == RECENT_IDS {
+                        self.recent.pop_front();
+                    }
+                    self.recent.push_back(data.id);
+                    // The error carries the wrapped payload, as the frame it came in is gone
+                    return Message::from_bytes_with(
+                        data.message_type,
+                        data.data.clone(),
+                        manager.endianness(),
+                    )
+                    .map_err(|source| ReceiveError::Decode {
+                        source,
+                        frame: data.data,
+                        offset: None,
+                    });
+                }
+                message => return Ok(message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,142 @@
+use super::*;
+use crate::codec::Endianness;
+use crate::test_util::{stream_pair, TestStream};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+
+fn pair() -> (SerialManager<TestStream>, SerialManager<TestStream>) {
+    let (stream1, stream2) = stream_pair();
+    for stream in [&stream1, &stream2] {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+    }
+    (SerialManager::new(stream1), SerialManager::new(stream2))
+}
+
+fn u32_message(num: u32) -> Message {
+    Message::U32(message_types::U32 { num })
+}
+
+fn is_timeout(result: Result<Message, ReceiveError>) -> bool {
+    matches!(result, Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut)
+}
+
+/// Returns a journal path for a test, removing anything left by an earlier run
+fn journal_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("gsp-{}-{name}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_delivery_is_acknowledged() {
+    let (mut sender, mut receiver) = pair();
+    let mut reliable_sender = Reliable::new();
+    let mut reliable_receiver = Reliable::new();
+
+    reliable_sender.send(&mut sender, u32_message(7)).unwrap();
+    assert_eq!(reliable_sender.pending(), 1);
+    assert_eq!(
+        reliable_receiver.receive(&mut receiver).unwrap(),
+        u32_message(7)
+    );
+    // Other messages pass through as they are
+    receiver.send(u32_message(8)).unwrap();
+    assert_eq!(
+        reliable_sender.receive(&mut sender).unwrap(),
+        u32_message(8)
+    );
+    assert_eq!(reliable_sender.pending(), 0);
+}
+
+#[test]
+fn test_unacknowledged_messages_are_sent_again_once() {
+    let (mut sender, mut receiver) = pair();
+    let mut reliable_sender = Reliable::new().with_retransmit_interval(Duration::from_millis(20));
+    let id = reliable_sender.send(&mut sender, u32_message(7)).unwrap();
+
+    // The first copy is lost
+    let Message::ReliableData(data) = receiver.receive().unwrap() else {
+        panic!("expected ReliableData");
+    };
+    assert_eq!(data.id, id);
+    assert_eq!(reliable_sender.retransmit(&mut sender).unwrap(), 0);
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(reliable_sender.retransmit(&mut sender).unwrap(), 1);
+
+    // The receiver hands the message on once, but acknowledges both copies
+    let mut reliable_receiver = Reliable::new();
+    assert_eq!(
+        reliable_receiver.receive(&mut receiver).unwrap(),
+        u32_message(7)
+    );
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(reliable_sender.retransmit(&mut sender).unwrap(), 1);
+    assert!(is_timeout(reliable_receiver.receive(&mut receiver)));
+    assert!(is_timeout(reliable_sender.receive(&mut sender)));
+    assert_eq!(reliable_sender.pending(), 0);
+}
+
+#[test]
+fn test_journal_survives_restart() {
+    let path = journal_path("restart");
+    let (mut sender, mut receiver) = pair();
+
+    let mut reliable = Reliable::open(&path).unwrap();
+    let ids: Vec<u32> = (1..=3)
+        .map(|num| reliable.send(&mut sender, u32_message(num)).unwrap())
+        .collect();
+    receiver
+        .send(Message::ReliableAck(ReliableAck { id: ids[0] }))
+        .unwrap();
+    assert!(is_timeout(reliable.receive(&mut sender)));
+    drop(reliable);
+    // A crash while appending leaves a record cut short
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[1, 0xAA, 0xBB])
+        .unwrap();
+
+    let mut reliable = Reliable::open(&path).unwrap();
+    assert_eq!(reliable.pending(), 2);
+    assert_eq!(reliable.retransmit(&mut sender).unwrap(), 2);
+    let next = reliable.send(&mut sender, u32_message(4)).unwrap();
+    assert_eq!(next, ids[2].wrapping_add(1));
+
+    let sent: Vec<(u32, Message)> = std::iter::from_fn(|| match receiver.receive() {
+        Ok(Message::ReliableData(data)) => Some((
+            data.id,
+            Message::from_bytes_with(data.message_type, data.data, Endianness::Little).unwrap(),
+        )),
+        _ => None,
+    })
+    .collect();
+    let expected: Vec<(u32, Message)> = (1..=3)
+        .map(|num| (ids[num as usize - 1], u32_message(num)))
+        .chain([
+            (ids[1], u32_message(2)),
+            (ids[2], u32_message(3)),
+            (next, u32_message(4)),
+        ])
+        .collect();
+    assert_eq!(sent, expected);
+
+    // Once everything is acknowledged, the journal keeps only the last id
+    for id in [ids[1], ids[2], next] {
+        receiver
+            .send(Message::ReliableAck(ReliableAck { id }))
+            .unwrap();
+    }
+    assert!(is_timeout(reliable.receive(&mut sender)));
+    drop(reliable);
+    let reliable = Reliable::open(&path).unwrap();
+    assert_eq!(reliable.pending(), 0);
+    assert_eq!(reliable.next_id, next.wrapping_add(1));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 9);
+    std::fs::remove_file(&path).unwrap();
+}
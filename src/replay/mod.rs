@@ -0,0 +1,114 @@
+use crate::capture::{read_pcapng, Direction};
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+struct Chunk {
+    offset: Duration,
+    data: Vec<u8>,
+}
+
+/// A connection that replays a previously captured byte stream.
+///
+/// Passing a `ReplayConnection` to [`SerialManager::new`](crate::SerialManager::new) feeds the
+/// captured bytes through `receive`, so problems seen in the field can be reproduced
+/// deterministically. Once all bytes have been replayed, reads return end of file. Anything
+/// written to the connection is discarded.
+///
+/// When timing is enabled, each captured frame is only made available once the same amount of
+/// time has passed since the first read as passed between the first captured frame and it.
+pub struct ReplayConnection {
+    chunks: VecDeque<Chunk>,
+    position: usize,
+    timing: bool,
+    started: Option<Instant>,
+}
+
+impl ReplayConnection {
+    /// Creates a connection that replays `bytes` without any timing information
+    #[must_use]
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self::from_chunks(vec![Chunk {
+            offset: Duration::ZERO,
+            data: bytes,
+        }])
+    }
+
+    /// Creates a connection that replays the received frames of a pcapng capture
+    ///
+    /// Frames recorded as sent are skipped. Frames without a recorded direction are replayed.
+    pub fn from_pcapng(reader: impl Read) -> io::Result<Self> {
+        let frames: Vec<_> = read_pcapng(reader)?
+            .into_iter()
+            .filter(|frame| frame.direction != Some(Direction::Sent))
+            .collect();
+        let first = frames
+            .first()
+            .map_or(Duration::ZERO, |frame| frame.timestamp);
+
+        Ok(Self::from_chunks(
+            frames
+                .into_iter()
+                .map(|frame| Chunk {
+                    offset: frame.timestamp.saturating_sub(first),
+                    data: frame.data,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Enables or disables replaying frames with their original timing
+    #[must_use]
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    fn from_chunks(chunks: Vec<Chunk>) -> Self {
+        Self {
+            chunks: chunks.into(),
+            position: 0,
+            timing: false,
+            started: None,
+        }
+    }
+}
+
+impl Read for ReplayConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(chunk) = self.chunks.front() else {
+            return Ok(0);
+        };
+
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if self.timing && self.position == 0 {
+            thread::sleep((started + chunk.offset).saturating_duration_since(Instant::now()));
+        }
+
+        let remaining = &chunk.data[self.position..];
+        let count = remaining.len().min(buf.len());
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.position += count;
+
+        if self.position == chunk.data.len() {
+            self.chunks.pop_front();
+            self.position = 0;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for ReplayConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
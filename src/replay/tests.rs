@@ -0,0 +1,88 @@
+use super::*;
+use crate::capture::PcapngWriter;
+use crate::errors::ReceiveError;
+use crate::message_types;
+use crate::{Message, SerialManager};
+use std::time::Instant;
+
+fn noop_frame() -> Vec<u8> {
+    vec![0x58, 0x02, 0x00, 0x04, 0x00]
+}
+
+fn u8_frame() -> Vec<u8> {
+    vec![0x58, 0x03, 0x00, 0x01, 0x00, 0x57]
+}
+
+fn assert_eof(result: Result<Message, ReceiveError>) {
+    assert!(matches!(
+        result,
+        Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn test_replay_bytes() {
+    let mut bytes = vec![0x13, 0x37];
+    bytes.extend(noop_frame());
+    bytes.extend(u8_frame());
+    let mut receiver = SerialManager::new(ReplayConnection::new(bytes));
+
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::NoOp(message_types::NoOp {})
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eof(receiver.receive());
+}
+
+#[test]
+fn test_replay_pcapng_skips_sent_frames() {
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    writer
+        .write_frame(Direction::Received, &noop_frame())
+        .unwrap();
+    writer.write_frame(Direction::Sent, &u8_frame()).unwrap();
+    writer
+        .write_frame(Direction::Received, &u8_frame())
+        .unwrap();
+    let capture = writer.into_inner();
+
+    let connection = ReplayConnection::from_pcapng(capture.as_slice()).unwrap();
+    let mut receiver = SerialManager::new(connection);
+
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::NoOp(message_types::NoOp {})
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eof(receiver.receive());
+}
+
+#[test]
+fn test_replay_pcapng_with_timing() {
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    writer
+        .write_frame(Direction::Received, &noop_frame())
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    writer
+        .write_frame(Direction::Received, &u8_frame())
+        .unwrap();
+    let capture = writer.into_inner();
+
+    let connection = ReplayConnection::from_pcapng(capture.as_slice())
+        .unwrap()
+        .with_timing(true);
+    let mut receiver = SerialManager::new(connection);
+
+    let start = Instant::now();
+    receiver.receive().unwrap();
+    receiver.receive().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
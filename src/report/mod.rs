@@ -0,0 +1,336 @@
+use crate::capture::{CapturedFrame, Direction};
+use crate::codec::{Decoder, DecoderEvent, Endianness, Framing};
+use crate::message::Message;
+use crate::schema;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// What a row of a [`Timeline`] shows
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Message(Message),
+    /// A frame whose payload failed to decode
+    Invalid {
+        message_type: u16,
+        payload: Vec<u8>,
+        error: String,
+    },
+    /// Something the decoder discarded
+    Framing(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    timestamp: Duration,
+    direction: Option<Direction>,
+    event: Event,
+    /// The time since the request this answers was captured
+    round_trip: Option<Duration>,
+}
+
+/// A request whose answer is timed, keyed by its kind and ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Request {
+    Ping(u16),
+    Challenge(u32),
+    Command(u16),
+}
+
+impl Request {
+    fn of(message: &Message) -> Option<Self> {
+        match message {
+            Message::Ping(ping) => Some(Self::Ping(ping.sequence)),
+            Message::Challenge(challenge) => Some(Self::Challenge(challenge.nonce)),
+            Message::Command(command) => Some(Self::Command(command.id)),
+            _ => None,
+        }
+    }
+
+    fn answered_by(message: &Message) -> Option<Self> {
+        match message {
+            Message::Pong(pong) => Some(Self::Ping(pong.sequence)),
+            Message::ChallengeResponse(response) => Some(Self::Challenge(response.nonce)),
+            Message::Response(response) => Some(Self::Command(response.id)),
+            _ => None,
+        }
+    }
+}
+
+/// A chronological report of the traffic in a capture, as a self-contained HTML page that can be
+/// attached to a support ticket in place of a hex dump
+///
+/// The bytes captured in each direction are decoded, from a pcapng capture read with
+/// [`read_pcapng`](crate::read_pcapng) or a [`ByteLog`](crate::ByteLog) read with
+/// [`read_byte_log`](crate::read_byte_log), and every message, payload that failed to decode,
+/// resync and skipped run of bytes is listed with its time and direction. Answers to `Ping`,
+/// `Challenge` and `Command` messages in the other direction are shown with their round-trip
+/// time, and a summary at the top counts the frames and errors and gives the range of round-trip
+/// times.
+///
+/// ```no_run
+/// use generic_serial_protocol::{read_pcapng, Timeline};
+/// use std::fs::File;
+///
+/// let captured = read_pcapng(File::open("link.pcapng").unwrap()).unwrap();
+/// let html = Timeline::new().with_title("Ticket 1234").render(&captured);
+/// std::fs::write("link.html", html).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    framing: Framing,
+    endianness: Endianness,
+    title: String,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timeline {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            framing: Framing::default(),
+            endianness: Endianness::Little,
+            title: "Serial traffic".to_string(),
+        }
+    }
+
+    /// Decodes frames of the given framing
+    #[must_use]
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Decodes frame headers and payloads in the given byte order
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Heads the report with `title`
+    #[must_use]
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Decodes `captured`, in the order it was captured, into the entries of the timeline
+    fn entries(&self, captured: &[CapturedFrame]) -> Vec<Entry> {
+        let mut decoders: [Decoder; 3] = std::array::from_fn(|_| {
+            Decoder::new()
+                .with_framing(self.framing)
+                .with_endianness(self.endianness)
+        });
+        let mut requests: HashMap<(usize, Request), Duration> = HashMap::new();
+        let mut entries = Vec::new();
+
+        for frame in captured {
+            let decoder = &mut decoders[direction_index(frame.direction)];
+            let mut events = Vec::new();
+            for &byte in &frame.data {
+                events.extend(decoder.push(byte));
+                events.extend(std::iter::from_fn(|| decoder.poll()));
+            }
+            for event in events {
+                let event = match event {
+                    DecoderEvent::Frame(received) => match Message::from_bytes_with(
+                        received.message_type,
+                        received.payload.clone(),
+                        self.endianness,
+                    ) {
+                        Ok(message) => Event::Message(message),
+                        Err(e) => Event::Invalid {
+                            message_type: received.message_type,
+                            payload: received.payload,
+                            error: e.to_string(),
+                        },
+                    },
+                    DecoderEvent::Resync => Event::Framing("Resync".to_string()),
+                    DecoderEvent::Skipped(count) => {
+                        Event::Framing(format!("Skipped {count} bytes"))
+                    }
+                    DecoderEvent::InvalidLength(length) => {
+                        Event::Framing(format!("Invalid length {length}"))
+                    }
+                    DecoderEvent::InvalidTrailer(byte) => {
+                        Event::Framing(format!("Invalid trailer {byte:#04x}"))
+                    }
+                    DecoderEvent::ChecksumMismatch { expected, received } => Event::Framing(
+                        format!("Checksum {received:#010x}, expected {expected:#010x}"),
+                    ),
+                    DecoderEvent::HeaderChecksumMismatch { expected, received } => Event::Framing(
+                        format!("Header checksum {received:#06x}, expected {expected:#06x}"),
+                    ),
+                };
+
+                let mut round_trip = None;
+                if let Event::Message(message) = &event {
+                    if let Some(request) = Request::of(message) {
+                        requests
+                            .insert((direction_index(frame.direction), request), frame.timestamp);
+                    }
+                    if let Some(request) = Request::answered_by(message) {
+                        round_trip = requests
+                            .remove(&(direction_index(opposite(frame.direction)), request))
+                            .map(|sent| frame.timestamp.saturating_sub(sent));
+                    }
+                }
+                entries.push(Entry {
+                    timestamp: frame.timestamp,
+                    direction: frame.direction,
+                    event,
+                    round_trip,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Renders the timeline of `captured` as an HTML page
+    #[must_use]
+    pub fn render(&self, captured: &[CapturedFrame]) -> String {
+        let entries = self.entries(captured);
+        let start = entries
+            .first()
+            .map_or(Duration::ZERO, |entry| entry.timestamp);
+        let messages = entries
+            .iter()
+            .filter(|entry| matches!(entry.event, Event::Message(_)))
+            .count();
+        let errors = entries.len() - messages;
+        let round_trips: Vec<Duration> = entries.iter().filter_map(|e| e.round_trip).collect();
+
+        let mut html = String::new();
+        let title = escape(&self.title);
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>"
+        );
+        let _ = write!(
+            html,
+            "Started at {}.{:06} seconds since the Unix epoch. {messages} messages, {errors} errors.",
+            start.as_secs(),
+            start.subsec_micros()
+        );
+        if let (Some(min), Some(max)) = (round_trips.iter().min(), round_trips.iter().max()) {
+            #[allow(clippy::cast_possible_truncation)]
+            let mean = round_trips.iter().sum::<Duration>() / round_trips.len() as u32;
+            let _ = write!(
+                html,
+                " {} round trips, {} to {}, {} on average.",
+                round_trips.len(),
+                millis(*min),
+                millis(*max),
+                millis(mean)
+            );
+        }
+        html.push_str(
+            "</p>\n<table>\n<tr><th>Time (s)</th><th>Direction</th><th>Event</th>\
+             <th>Details</th><th>Round trip</th></tr>\n",
+        );
+
+        for entry in &entries {
+            let offset = entry.timestamp.saturating_sub(start);
+            let direction = match entry.direction {
+                Some(Direction::Sent) => "TX",
+                Some(Direction::Received) => "RX",
+                None => "",
+            };
+            let (class, name, details) = match &entry.event {
+                Event::Message(message) => {
+                    let message_type = message.message_type();
+                    let name = schema::message_name(message_type)
+                        .map_or_else(|| format!("{message_type:#06x}"), str::to_string);
+                    (direction.to_ascii_lowercase(), name, message.to_string())
+                }
+                Event::Invalid {
+                    message_type,
+                    payload,
+                    error,
+                } => (
+                    "error".to_string(),
+                    schema::message_name(*message_type)
+                        .map_or_else(|| format!("{message_type:#06x}"), str::to_string),
+                    format!("{error}: {}", hex(payload)),
+                ),
+                Event::Framing(description) => {
+                    ("error".to_string(), description.clone(), String::new())
+                }
+            };
+            let _ = writeln!(
+                html,
+                "<tr class=\"{class}\"><td>{}.{:06}</td><td>{direction}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td></tr>",
+                offset.as_secs(),
+                offset.subsec_micros(),
+                escape(&name),
+                escape(&details),
+                entry.round_trip.map(millis).unwrap_or_default(),
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+const STYLE: &str = "\
+body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;font-size:0.9em}\
+th,td{border:1px solid #ccc;padding:0.2em 0.6em;text-align:left;vertical-align:top}\
+td:nth-child(4){font-family:monospace;word-break:break-all}\
+tr.tx{background:#eef6ff}\
+tr.rx{background:#f2fbf2}\
+tr.error{background:#fdecea}";
+
+/// Indexes the decoder of a direction
+fn direction_index(direction: Option<Direction>) -> usize {
+    match direction {
+        Some(Direction::Sent) => 0,
+        Some(Direction::Received) => 1,
+        None => 2,
+    }
+}
+
+/// The direction answers travel in, where captures without directions hold both
+fn opposite(direction: Option<Direction>) -> Option<Direction> {
+    match direction {
+        Some(Direction::Sent) => Some(Direction::Received),
+        Some(Direction::Received) => Some(Direction::Sent),
+        None => None,
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, byte| {
+        let _ = write!(output, "{byte:02x}");
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+use super::*;
+use crate::codec::encode_frame_with;
+use crate::message::message_types;
+use crate::payload::MessageType;
+
+fn captured(millis: u64, direction: Direction, message: Message) -> CapturedFrame {
+    let message_type = message.message_type();
+    CapturedFrame {
+        timestamp: Duration::from_millis(millis),
+        direction: Some(direction),
+        data: encode_frame_with(
+            message_type,
+            &message.to_bytes_with(Endianness::Little).unwrap(),
+            Endianness::Little,
+        ),
+    }
+}
+
+#[test]
+fn test_timeline_times_round_trips() {
+    let request = Message::Ping(message_types::Ping { sequence: 4 });
+    let answer = Message::Pong(message_types::Pong { sequence: 4 });
+    let mut split = captured(1000, Direction::Received, answer.clone());
+    // Bytes read in two chunks, with a byte outside any frame before them
+    let rest = split.data.split_off(3);
+    split.data.insert(0, 0xAA);
+    let captured = [
+        captured(990, Direction::Sent, request.clone()),
+        split,
+        CapturedFrame {
+            timestamp: Duration::from_millis(1005),
+            direction: Some(Direction::Received),
+            data: rest,
+        },
+    ];
+
+    let entries = Timeline::new().entries(&captured);
+    let events: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry.direction, &entry.event, entry.round_trip))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (Some(Direction::Sent), &Event::Message(request), None),
+            (
+                Some(Direction::Received),
+                &Event::Framing("Skipped 1 bytes".to_string()),
+                None
+            ),
+            (
+                Some(Direction::Received),
+                &Event::Message(answer),
+                Some(Duration::from_millis(15))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_timeline_renders_escaped_html() {
+    let captured = [
+        captured(
+            0,
+            Direction::Received,
+            Message::Json(message_types::Json {
+                text: "\"<script>\"".to_string(),
+            }),
+        ),
+        CapturedFrame {
+            timestamp: Duration::from_millis(2),
+            direction: Some(Direction::Received),
+            data: encode_frame_with(message_types::U32::ID, &[1], Endianness::Little),
+        },
+    ];
+    let html = Timeline::new().with_title("A & B").render(&captured);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>A &amp; B</title>"));
+    assert!(html.contains("&lt;script&gt;"));
+    assert!(!html.contains("<script>"));
+    assert!(html.contains("1 messages, 1 errors."));
+    assert!(html.contains("<tr class=\"error\"><td>0.002000</td><td>RX</td><td>U32</td>"));
+}
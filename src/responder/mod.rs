@@ -0,0 +1,240 @@
+use crate::codec::{encode_frame_with, Endianness};
+use crate::errors::DecodeError;
+use crate::fixed::{FixedDecoder, MessageRef};
+use crate::message::{message_types, Message};
+use crate::payload::MessageType;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+/// The most bytes of encoded responses queued by default
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+type Handler = Box<dyn FnMut(MessageRef<'_>) -> Result<Option<Message>, DecodeError>>;
+
+/// The device end of a link: a poll-driven runtime that decodes frames from received bytes,
+/// passes each message to the handler registered for its type and queues the responses to be
+/// transmitted
+///
+/// It is meant for firmware, where bytes come from a UART's receive FIFO or DMA buffer rather
+/// than an OS connection. There are no threads, clocks or IO traits: bytes are pushed in with
+/// [`poll`](Self::poll) from the main loop or an interrupt's buffer, and responses taken out
+/// with [`transmit`](Self::transmit), so that both ends of a link can be built from this
+/// crate. Frames are received into a buffer of up to `N` payload bytes, as by a
+/// [`FixedDecoder`], and every `Ping`, `Challenge` and, once given a
+/// [`DeviceInfo`](message_types::DeviceInfo), `Identify` is answered without a handler:
+///
+/// ```
+/// use generic_serial_protocol::{message_types, Message, Responder};
+///
+/// let mut responder = Responder::<64>::new();
+/// responder.on(|command: message_types::Command| {
+///     Some(Message::Response(message_types::Response {
+///         id: command.id,
+///         status: message_types::Status::Ok,
+///         payload: vec![],
+///     }))
+/// });
+///
+/// # let mut uart_rx = std::iter::empty();
+/// # let mut uart_tx = |_: &[u8]| {};
+/// // In the main loop
+/// responder.poll(&mut uart_rx);
+/// let mut buffer = [0; 64];
+/// let length = responder.transmit(&mut buffer);
+/// uart_tx(&buffer[..length]);
+/// ```
+///
+/// Only the native framing is supported, without layers.
+pub struct Responder<const N: usize> {
+    decoder: FixedDecoder<N>,
+    endianness: Endianness,
+    handlers: BTreeMap<u16, Handler>,
+    info: Option<message_types::DeviceInfo>,
+    outgoing: VecDeque<u8>,
+    queue_capacity: usize,
+    decode_errors: u64,
+    responses_dropped: u64,
+}
+
+impl<const N: usize> fmt::Debug for Responder<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Responder")
+            .field("endianness", &self.endianness)
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("queued", &self.outgoing.len())
+            .field("decode_errors", &self.decode_errors)
+            .field("responses_dropped", &self.responses_dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<const N: usize> Default for Responder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Responder<N> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            decoder: FixedDecoder::new(),
+            endianness: Endianness::Little,
+            handlers: BTreeMap::new(),
+            info: None,
+            outgoing: VecDeque::new(),
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            decode_errors: 0,
+            responses_dropped: 0,
+        }
+    }
+
+    /// Reads and writes the frame header and payload numbers in the given byte order
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.decoder = self.decoder.with_endianness(endianness);
+        self.endianness = endianness;
+        self
+    }
+
+    /// Answers `Identify` with `info`
+    #[must_use]
+    pub fn with_device_info(mut self, info: message_types::DeviceInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Queues up to `capacity` bytes of encoded responses waiting to be transmitted, beyond
+    /// which responses are dropped, 1024 by default
+    #[must_use]
+    pub fn with_queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// Registers the handler for messages of type `M`, replacing any previous one, which returns
+    /// the response to queue, if any
+    ///
+    /// A handler for `Ping`, `Challenge` or `Identify` replaces the built-in answer.
+    /// Messages that do not decode as `M` are counted as [decode errors](Self::decode_errors).
+    pub fn on<M: MessageType + 'static>(
+        &mut self,
+        mut handler: impl FnMut(M) -> Option<Message> + 'static,
+    ) {
+        self.handlers.insert(
+            M::ID,
+            Box::new(move |message| message.decode::<M>().map(&mut handler)),
+        );
+    }
+
+    /// Decodes the bytes `source` yields until it runs out, handling every message completed,
+    /// and returns how many were handled
+    ///
+    /// A frame cut short by `source` running out is completed by the next poll. Messages without
+    /// a handler are dropped.
+    pub fn poll(&mut self, source: impl IntoIterator<Item = u8>) -> usize {
+        let mut handled = 0;
+        for byte in source {
+            let response = match self.decoder.push(byte) {
+                None => continue,
+                Some(Ok(message)) => match respond(&mut self.handlers, self.info.as_ref(), message)
+                {
+                    Some(response) => {
+                        handled += 1;
+                        response
+                    }
+                    None => continue,
+                },
+                Some(Err(e)) => Err(e),
+            };
+            match response {
+                Ok(Some(response)) => self.queue(response),
+                Ok(None) => (),
+                Err(_) => self.decode_errors += 1,
+            }
+        }
+        handled
+    }
+
+    /// Queues `message` to be transmitted, unless that would exceed the queue's capacity or it
+    /// does not encode
+    ///
+    /// Handlers return their responses, but messages can also be queued unprompted, such as
+    /// telemetry.
+    pub fn queue(&mut self, message: Message) {
+        let message_type = message.message_type();
+        let Ok(payload) = message.to_bytes_with(self.endianness) else {
+            self.responses_dropped += 1;
+            return;
+        };
+        let frame = encode_frame_with(message_type, &payload, self.endianness);
+        if self.outgoing.len() + frame.len() > self.queue_capacity {
+            self.responses_dropped += 1;
+            return;
+        }
+        self.outgoing.extend(frame);
+    }
+
+    /// Moves as many queued bytes as fit into `buffer`, returning how many were moved, for
+    /// writing to the link
+    pub fn transmit(&mut self, buffer: &mut [u8]) -> usize {
+        let length = buffer.len().min(self.outgoing.len());
+        for (byte, queued) in buffer.iter_mut().zip(self.outgoing.drain(..length)) {
+            *byte = queued;
+        }
+        length
+    }
+
+    /// Returns the number of bytes queued to be transmitted
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Returns the number of frames discarded and messages that failed to decode
+    #[must_use]
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
+
+    /// Returns the number of messages not queued for lack of space or because they did not
+    /// encode
+    #[must_use]
+    pub fn responses_dropped(&self) -> u64 {
+        self.responses_dropped
+    }
+}
+
+/// Passes `message` to its handler, or answers the messages a device answers without one,
+/// returning `None` if there is neither
+fn respond(
+    handlers: &mut BTreeMap<u16, Handler>,
+    info: Option<&message_types::DeviceInfo>,
+    message: MessageRef<'_>,
+) -> Option<Result<Option<Message>, DecodeError>> {
+    if let Some(handler) = handlers.get_mut(&message.message_type()) {
+        return Some(handler(message));
+    }
+    let response = match message.message_type() {
+        message_types::Ping::ID => message.decode().map(|ping: message_types::Ping| {
+            Some(Message::Pong(message_types::Pong {
+                sequence: ping.sequence,
+            }))
+        }),
+        message_types::Challenge::ID => {
+            message.decode().map(|challenge: message_types::Challenge| {
+                Some(Message::ChallengeResponse(
+                    message_types::ChallengeResponse {
+                        nonce: challenge.nonce,
+                    },
+                ))
+            })
+        }
+        message_types::Identify::ID if info.is_some() => Ok(info.cloned().map(Message::DeviceInfo)),
+        _ => return None,
+    };
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests;
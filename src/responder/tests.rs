@@ -0,0 +1,132 @@
+use super::*;
+use crate::message::Capabilities;
+use crate::test_util::stream_pair;
+use crate::SerialManager;
+use std::io::{Read, Write};
+
+fn frame(message: Message) -> Vec<u8> {
+    let message_type = message.message_type();
+    encode_frame_with(
+        message_type,
+        &message.to_bytes_with(Endianness::Little).unwrap(),
+        Endianness::Little,
+    )
+}
+
+/// Decodes the responses a responder has queued
+fn transmitted<const N: usize>(responder: &mut Responder<N>) -> Vec<Message> {
+    let mut buffer = [0; 256];
+    let length = responder.transmit(&mut buffer);
+    let mut decoder = FixedDecoder::<64>::new();
+    buffer[..length]
+        .iter()
+        .filter_map(|&byte| {
+            decoder
+                .push(byte)
+                .map(|message| message.unwrap().to_message())
+        })
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_responder_answers_a_host() {
+    let (stream1, mut stream2) = stream_pair();
+    let mut host = SerialManager::new(stream1);
+    let mut responder = Responder::<64>::new();
+    responder.on(|command: message_types::Command| {
+        Some(Message::Response(message_types::Response {
+            id: command.id,
+            status: message_types::Status::Ok,
+            payload: command.args.iter().rev().copied().collect(),
+        }))
+    });
+
+    host.send(Message::Command(message_types::Command {
+        id: 3,
+        args: vec![1, 2, 3],
+    }))
+    .unwrap();
+    let mut buffer = [0; 64];
+    let length = stream2.read(&mut buffer).unwrap();
+    assert_eq!(responder.poll(buffer[..length].iter().copied()), 1);
+
+    let length = responder.transmit(&mut buffer);
+    stream2.write_all(&buffer[..length]).unwrap();
+    assert_eq!(
+        host.receive().unwrap(),
+        Message::Response(message_types::Response {
+            id: 3,
+            status: message_types::Status::Ok,
+            payload: vec![3, 2, 1],
+        })
+    );
+    assert_eq!(responder.pending(), 0);
+}
+
+#[test]
+fn test_responder_answers_built_in_messages() {
+    let info = message_types::DeviceInfo {
+        device_id: 7,
+        name: "sensor".to_string(),
+        hw_rev: 1,
+        fw_version: "1.0".to_string(),
+        capabilities: Capabilities::PING,
+    };
+    let mut responder = Responder::<64>::new();
+    let mut bytes = frame(Message::Ping(message_types::Ping { sequence: 5 }));
+    bytes.extend(frame(Message::Identify(message_types::Identify {})));
+    assert_eq!(responder.poll(bytes.iter().copied()), 1);
+    assert_eq!(
+        transmitted(&mut responder),
+        [Message::Pong(message_types::Pong { sequence: 5 })]
+    );
+
+    let mut responder = Responder::<64>::new().with_device_info(info.clone());
+    let mut bytes = frame(Message::Challenge(message_types::Challenge { nonce: 9 }));
+    bytes.extend(frame(Message::Identify(message_types::Identify {})));
+    // A frame cut short is completed by the next poll
+    let (first, second) = bytes.split_at(bytes.len() - 2);
+    assert_eq!(responder.poll(first.iter().copied()), 1);
+    assert_eq!(responder.poll(second.iter().copied()), 1);
+    assert_eq!(
+        transmitted(&mut responder),
+        [
+            Message::ChallengeResponse(message_types::ChallengeResponse { nonce: 9 }),
+            Message::DeviceInfo(info),
+        ]
+    );
+}
+
+#[test]
+fn test_responder_counts_errors_and_drops() {
+    let mut responder = Responder::<64>::new().with_queue_capacity(8);
+    responder.on(|command: message_types::Command| {
+        Some(Message::Response(message_types::Response {
+            id: command.id,
+            status: message_types::Status::Ok,
+            payload: vec![0; 16],
+        }))
+    });
+
+    // A Command too short to decode, and one whose response does not fit the queue
+    let mut bytes = encode_frame_with(message_types::Command::ID, &[1], Endianness::Little);
+    bytes.extend(frame(Message::Command(message_types::Command {
+        id: 1,
+        args: vec![],
+    })));
+    // Without a handler
+    bytes.extend(frame(Message::Identify(message_types::Identify {})));
+    assert_eq!(responder.poll(bytes), 2);
+    assert_eq!(responder.decode_errors(), 1);
+    assert_eq!(responder.responses_dropped(), 1);
+    assert_eq!(responder.pending(), 0);
+
+    // Handlers replace built-in answers
+    responder.on(|_: message_types::Ping| None);
+    assert_eq!(
+        responder.poll(frame(Message::Ping(message_types::Ping { sequence: 1 }))),
+        1
+    );
+    assert_eq!(responder.pending(), 0);
+}
@@ -0,0 +1,100 @@
+use crate::time::{SystemTime, UNIX_EPOCH};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+
+/// Log files in a directory named `<prefix>-<microseconds since the Unix epoch>.<extension>`
+/// after the time they were started, of which only the newest are kept
+#[derive(Debug)]
+pub(crate) struct RotatingFiles {
+    pub(crate) directory: PathBuf,
+    pub(crate) prefix: String,
+    extension: &'static str,
+    pub(crate) max_files: usize,
+    /// The files with the prefix in the directory, oldest first, once the first has been started
+    files: Option<VecDeque<PathBuf>>,
+}
+
+impl RotatingFiles {
+    pub(crate) fn new(
+        directory: PathBuf,
+        prefix: &str,
+        extension: &'static str,
+        max_files: usize,
+    ) -> Self {
+        Self {
+            directory,
+            prefix: prefix.to_string(),
+            extension,
+            max_files,
+            files: None,
+        }
+    }
+
+    /// Creates a new empty file and deletes the oldest beyond the maximum, including files left
+    /// by earlier runs
+    ///
+    /// The directory is created if it does not exist.
+    pub(crate) fn start(&mut self) -> io::Result<(PathBuf, File)> {
+        if self.files.is_none() {
+            fs::create_dir_all(&self.directory)?;
+            self.files = Some(self.existing_files()?);
+        }
+        let files = self.files.get_or_insert_default();
+
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        // Files started within the same microsecond are kept apart by bumping the time
+        let mut path;
+        let mut offset = 0;
+        let file = loop {
+            path = self.directory.join(format!(
+                "{}-{}.{}",
+                self.prefix,
+                micros + offset,
+                self.extension
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => offset += 1,
+                Err(e) => return Err(e),
+            }
+        };
+        files.push_back(path.clone());
+
+        while files.len() > self.max_files {
+            if let Some(oldest) = files.pop_front() {
+                match fs::remove_file(oldest) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok((path, file))
+    }
+
+    /// Lists the files with the prefix already in the directory, oldest first
+    fn existing_files(&self) -> io::Result<VecDeque<PathBuf>> {
+        let mut files: Vec<(u128, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let started = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&self.prefix))
+                .and_then(|name| name.strip_prefix('-'))
+                .and_then(|name| name.strip_suffix(self.extension))
+                .and_then(|name| name.strip_suffix('.'))
+                .and_then(|micros| micros.parse().ok());
+            if let Some(started) = started {
+                files.push((started, path));
+            }
+        }
+        files.sort();
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+}
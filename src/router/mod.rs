@@ -0,0 +1,193 @@
+use crate::errors::RouterError;
+use crate::message::Message;
+use crate::serial_manager::{QueueSender, SerialManager, SharedSender, Transport};
+use std::fmt;
+use std::io;
+use std::ops::RangeBounds;
+use std::sync::mpsc::{Sender, SyncSender};
+
+/// Where a [`Router`] delivers the messages of a route
+///
+/// Implemented for channels, for links to send the messages on, and for closures wrapped in a
+/// [`Handler`].
+pub trait Sink {
+    fn deliver(&mut self, message: Message) -> io::Result<()>;
+}
+
+/// A sink calling a closure with every message, such as one appending to a log file
+pub struct Handler<F>(pub F);
+
+impl<F: FnMut(Message) -> io::Result<()>> Sink for Handler<F> {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        (self.0)(message)
+    }
+}
+
+impl Sink for Sender<Message> {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        self.send(message)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "channel receiver dropped"))
+    }
+}
+
+impl Sink for SyncSender<Message> {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        self.send(message)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "channel receiver dropped"))
+    }
+}
+
+/// Sends every message on the link
+impl<T: Transport> Sink for SerialManager<T> {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        self.send(message)
+    }
+}
+
+/// Sends every message on the shared link
+impl<T: Transport> Sink for SharedSender<T> {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        self.send(message)
+    }
+}
+
+/// Queues every message, waiting for space in the queue
+impl Sink for QueueSender {
+    fn deliver(&mut self, message: Message) -> io::Result<()> {
+        self.send(message).map_err(io::Error::other)
+    }
+}
+
+/// Identifies a route added to a [`Router`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RouteId(usize);
+
+impl fmt::Display for RouteId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "route {}", self.0)
+    }
+}
+
+struct Route {
+    matches: Box<dyn Fn(&Message) -> bool>,
+    sink: Box<dyn Sink>,
+}
+
+/// Delivers received messages to sinks by message type range or predicate, such as in a gateway
+/// fanning out telemetry to a recorder, commands to a controller and logs to a file.
+///
+/// Unlike a [`Dispatcher`](crate::Dispatcher), which calls one handler per message type, a
+/// message is delivered to every route it matches, so it can be copied to several sinks.
+///
+/// ```no_run
+/// # use generic_serial_protocol::{message_types, Handler, MessageType, Router, SerialManager};
+/// # use std::io::Write;
+/// # let (device, controller) = (std::fs::File::open("").unwrap(), std::fs::File::open("").unwrap());
+/// let (telemetry, recorder) = std::sync::mpsc::channel();
+/// let mut log = std::fs::File::create("device.log").unwrap();
+/// let mut router = Router::new();
+/// router.route(0x0100..0x0200, telemetry);
+/// router.route(
+///     message_types::Command::ID..=message_types::Command::ID,
+///     SerialManager::new(controller),
+/// );
+/// router.route_if(
+///     |message| matches!(message, generic_serial_protocol::Message::Log(_)),
+///     Handler(move |message| writeln!(log, "{message}")),
+/// );
+/// router.run(&mut SerialManager::new(device)).unwrap();
+/// # drop(recorder);
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Option<Box<dyn Sink>>,
+}
+
+impl Router {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delivers messages whose type is in `message_types` to `sink`
+    pub fn route(
+        &mut self,
+        message_types: impl RangeBounds<u16> + 'static,
+        sink: impl Sink + 'static,
+    ) -> RouteId {
+        self.route_if(
+            move |message| message_types.contains(&message.message_type()),
+            sink,
+        )
+    }
+
+    /// Delivers messages for which `predicate` returns true to `sink`
+    pub fn route_if(
+        &mut self,
+        predicate: impl Fn(&Message) -> bool + 'static,
+        sink: impl Sink + 'static,
+    ) -> RouteId {
+        self.routes.push(Route {
+            matches: Box::new(predicate),
+            sink: Box::new(sink),
+        });
+        RouteId(self.routes.len() - 1)
+    }
+
+    /// Delivers messages that match no route to `sink`, instead of dropping them
+    pub fn otherwise(&mut self, sink: impl Sink + 'static) {
+        self.fallback = Some(Box::new(sink));
+    }
+
+    /// Delivers a message to every route it matches, or else to the fallback sink, returning
+    /// whether any sink took it
+    ///
+    /// A sink that fails does not stop the message being delivered to the other routes. The
+    /// first failure is returned once every route has been tried.
+    pub fn dispatch(&mut self, message: Message) -> Result<bool, RouterError> {
+        let matched: Vec<usize> = (0..self.routes.len())
+            .filter(|&index| (self.routes[index].matches)(&message))
+            .collect();
+        let Some((&last, others)) = matched.split_last() else {
+            let Some(fallback) = &mut self.fallback else {
+                return Ok(false);
+            };
+            fallback.deliver(message).map_err(RouterError::Fallback)?;
+            return Ok(true);
+        };
+
+        let mut result = Ok(());
+        for &index in others {
+            let delivered = self.deliver(index, message.clone());
+            result = result.and(delivered);
+        }
+        // The last sink can take the message itself
+        let delivered = self.deliver(last, message);
+        result.and(delivered).map(|()| true)
+    }
+
+    fn deliver(&mut self, index: usize, message: Message) -> Result<(), RouterError> {
+        self.routes[index]
+            .sink
+            .deliver(message)
+            .map_err(|source| RouterError::Deliver {
+                route: RouteId(index),
+                source,
+            })
+    }
+
+    /// Receives messages from `manager` and dispatches them until receiving or delivering fails
+    ///
+    /// The error is returned, and the loop can be resumed by calling `run` again, e.g. after a
+    /// decode error.
+    pub fn run<T: Transport>(&mut self, manager: &mut SerialManager<T>) -> Result<(), RouterError> {
+        loop {
+            let message = manager.receive()?;
+            self.dispatch(message)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,109 @@
+use super::*;
+use crate::errors::ReceiveError;
+use crate::message_types;
+use crate::test_util::stream_pair;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+/// A handler sink recording the message type of everything delivered to it
+fn recorder() -> (impl Sink, Rc<RefCell<Vec<u16>>>) {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    let sink = Handler(move |message: Message| {
+        log.borrow_mut().push(message.message_type());
+        Ok(())
+    });
+    (sink, seen)
+}
+
+#[test]
+fn test_route_by_range() {
+    let (sender, receiver) = mpsc::channel();
+    let (handler, seen) = recorder();
+    let mut router = Router::new();
+    router.route(0..=4, sender);
+    router.route(5.., handler);
+
+    assert!(router.dispatch(u8_message(1)).unwrap());
+    assert!(router
+        .dispatch(Message::U16(message_types::U16 { num: 2 }))
+        .unwrap());
+    assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [u8_message(1)]);
+    assert_eq!(*seen.borrow(), [5]);
+}
+
+#[test]
+fn test_route_fans_out() {
+    let (telemetry, seen_telemetry) = recorder();
+    let (everything, seen_everything) = recorder();
+    let (fallback, seen_fallback) = recorder();
+    let mut router = Router::new();
+    router.route_if(|message| matches!(message, Message::U8(_)), telemetry);
+    router.route(.., everything);
+    router.otherwise(fallback);
+
+    assert!(router.dispatch(u8_message(1)).unwrap());
+    assert!(router
+        .dispatch(Message::Goodbye(message_types::Goodbye {}))
+        .unwrap());
+    assert_eq!(*seen_telemetry.borrow(), [1]);
+    assert_eq!(*seen_everything.borrow(), [1, 40]);
+    assert!(seen_fallback.borrow().is_empty());
+}
+
+#[test]
+fn test_route_fallback() {
+    let (fallback, seen) = recorder();
+    let mut router = Router::new();
+    router.route(0x0100.., Handler(|_| Ok(())));
+    assert!(!router.dispatch(u8_message(1)).unwrap());
+
+    router.otherwise(fallback);
+    assert!(router.dispatch(u8_message(1)).unwrap());
+    assert_eq!(*seen.borrow(), [1]);
+}
+
+#[test]
+fn test_failed_sink_does_not_stop_others() {
+    let (sender, receiver) = mpsc::channel();
+    drop(receiver);
+    let (handler, seen) = recorder();
+    let mut router = Router::new();
+    let closed = router.route(.., sender);
+    router.route(.., handler);
+
+    match router.dispatch(u8_message(1)) {
+        Err(RouterError::Deliver { route, source }) => {
+            assert_eq!(route, closed);
+            assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+        }
+        result => panic!("unexpected result: {result:?}"),
+    }
+    assert_eq!(*seen.borrow(), [1]);
+}
+
+#[test]
+fn test_run_forwards_to_link() {
+    let (device, host) = stream_pair();
+    let (link, controller) = stream_pair();
+    let mut device = SerialManager::new(device);
+    let mut controller = SerialManager::new(controller);
+    let mut router = Router::new();
+    router.route(0..=1, SerialManager::new(link));
+
+    device.send(u8_message(7)).unwrap();
+    device.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    device.close().unwrap();
+
+    let mut host = SerialManager::new(host);
+    assert!(matches!(
+        router.run(&mut host),
+        Err(RouterError::Receive(ReceiveError::PeerClosed))
+    ));
+    assert_eq!(controller.receive().unwrap(), u8_message(7));
+}
@@ -0,0 +1,144 @@
+//! Export of the protocol description as JSON.
+
+use super::{
+    messages, EnumDescriptor, FieldType, PayloadDescriptor, StructDescriptor, StructEncoding,
+    RESERVED_MESSAGE_TYPES, USER_MESSAGE_TYPES,
+};
+use crate::codec::{
+    COBS_MAX_CODE, ESCAPE_BYTE, MAX_COMPACT_PAYLOAD_LENGTH, MAX_EXTENDED_PAYLOAD_LENGTH,
+    MAX_PAYLOAD_LENGTH, SLIP_END, SLIP_ESC, SLIP_ESC_END, SLIP_ESC_ESC, START_BYTE, XOR_BYTE,
+};
+use serde_json::{json, Value};
+
+/// Returns the full protocol description as pretty-printed JSON, so that implementations in other
+/// languages and documentation can be generated from it
+///
+/// This holds the framing constants and every built-in message type with its ID and payload
+/// layout. Field types are strings such as `"u16"` or `"string"`, or objects such as
+/// `{"array": "u8"}` for the types that wrap others. Numbers are little-endian unless a peer
+/// selects big-endian.
+///
+/// ```
+/// # use generic_serial_protocol::schema::export_json;
+/// let schema: serde_json::Value = serde_json::from_str(&export_json()).unwrap();
+/// assert_eq!(schema["framing"]["native"]["start_byte"], 0x58);
+/// assert_eq!(schema["messages"][1]["name"], "U8");
+/// ```
+#[must_use]
+pub fn export_json() -> String {
+    let schema = json!({
+        "framing": {
+            "native": {
+                "start_byte": START_BYTE,
+                "escape_byte": ESCAPE_BYTE,
+                "xor_byte": XOR_BYTE,
+            },
+            "slip": {
+                "end": SLIP_END,
+                "esc": SLIP_ESC,
+                "esc_end": SLIP_ESC_END,
+                "esc_esc": SLIP_ESC_ESC,
+            },
+            "cobs": {
+                "delimiter": 0,
+                "max_code": COBS_MAX_CODE,
+            },
+            "compact": {
+                "start_byte": START_BYTE,
+                "max_payload_length": MAX_COMPACT_PAYLOAD_LENGTH,
+            },
+            "extended": {
+                "start_byte": START_BYTE,
+                "max_payload_length": MAX_EXTENDED_PAYLOAD_LENGTH,
+            },
+            "binary": {
+                "start_byte": START_BYTE,
+                "checksum": "crc32",
+            },
+            "max_payload_length": MAX_PAYLOAD_LENGTH,
+        },
+        "reserved_message_types": [RESERVED_MESSAGE_TYPES.start(), RESERVED_MESSAGE_TYPES.end()],
+        "user_message_types": [USER_MESSAGE_TYPES.start(), USER_MESSAGE_TYPES.end()],
+        "messages": messages()
+            .iter()
+            .map(|message| json!({
+                "id": message.id,
+                "name": message.name,
+                "payload": payload_json(message.payload),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    // Serializing a `Value` cannot fail
+    serde_json::to_string_pretty(&schema).unwrap_or_default()
+}
+
+fn payload_json(payload: PayloadDescriptor) -> Value {
+    match payload {
+        PayloadDescriptor::Struct(descriptor) => struct_json(descriptor),
+        PayloadDescriptor::Enum(descriptor) => enum_json(descriptor),
+    }
+}
+
+fn struct_json(descriptor: &StructDescriptor) -> Value {
+    let fields: Vec<_> = descriptor
+        .fields
+        .iter()
+        .map(|field| {
+            let mut value = json!({
+                "name": field.name,
+                "type": type_json(field.field_type),
+            });
+            if let Some(tag) = field.tag {
+                value["tag"] = json!(tag);
+            }
+            value
+        })
+        .collect();
+    let encoding = match descriptor.encoding {
+        StructEncoding::Positional => "positional",
+        StructEncoding::Tlv => "tlv",
+    };
+    json!({
+        "kind": "struct",
+        "name": descriptor.name,
+        "encoding": encoding,
+        "fields": fields,
+    })
+}
+
+fn enum_json(descriptor: &EnumDescriptor) -> Value {
+    let variants: Vec<_> = descriptor
+        .variants
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+    json!({
+        "kind": "enum",
+        "name": descriptor.name,
+        "variants": variants,
+    })
+}
+
+fn type_json(field_type: FieldType) -> Value {
+    let name = match field_type {
+        FieldType::U8 => "u8",
+        FieldType::U16 => "u16",
+        FieldType::U32 => "u32",
+        FieldType::U64 => "u64",
+        FieldType::I8 => "i8",
+        FieldType::I16 => "i16",
+        FieldType::I32 => "i32",
+        FieldType::I64 => "i64",
+        FieldType::F32 => "f32",
+        FieldType::F64 => "f64",
+        FieldType::Bool => "bool",
+        FieldType::Bytes => "bytes",
+        FieldType::String => "string",
+        FieldType::Enum(descriptor) => return json!({ "enum": enum_json(descriptor) }),
+        FieldType::Array(element) => return json!({ "array": type_json(*element) }),
+        FieldType::Struct(descriptor) => return json!({ "struct": struct_json(descriptor) }),
+        FieldType::Option(inner) => return json!({ "option": type_json(*inner) }),
+        FieldType::Varint(inner) => return json!({ "varint": type_json(*inner) }),
+    };
+    json!(name)
+}
@@ -0,0 +1,201 @@
+//! Descriptions of the built-in message types.
+//!
+//! These describe the payload layout of every message type. They are generated from the same
+//! definitions as the Rust implementation, so tooling such as the code generators stays in sync
+//! with it.
+
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "json")]
+mod json;
+
+#[cfg(feature = "json")]
+pub use json::export_json;
+
+/// Message type IDs reserved for the built-in message types
+pub const RESERVED_MESSAGE_TYPES: RangeInclusive<u16> = 0x0000..=0x00FF;
+
+/// Message type IDs available to applications, which built-in message types never use
+pub const USER_MESSAGE_TYPES: RangeInclusive<u16> = 0x0100..=u16::MAX;
+
+/// Describes a message type and the layout of its payload
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MessageDescriptor {
+    /// The message type ID sent on the wire
+    pub id: u16,
+    /// The name of the `Message` variant
+    pub name: &'static str,
+    pub payload: PayloadDescriptor,
+}
+
+/// Describes how a message payload is laid out
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PayloadDescriptor {
+    /// Fields laid out according to the struct's encoding
+    Struct(&'static StructDescriptor),
+    /// A single byte holding one of the enum's values
+    Enum(&'static EnumDescriptor),
+}
+
+/// Describes a single field of a struct payload
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FieldDescriptor {
+    pub name: &'static str,
+    pub field_type: FieldType,
+    /// Identifies the field in TLV-encoded structs, and is `None` in positional ones
+    pub tag: Option<u8>,
+}
+
+/// The wire encoding of a field
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FieldType {
+    U8,
+    /// Little-endian u16
+    U16,
+    /// Little-endian u32
+    U32,
+    /// Little-endian u64
+    U64,
+    I8,
+    /// Little-endian i16
+    I16,
+    /// Little-endian i32
+    I32,
+    /// Little-endian i64
+    I64,
+    /// Little-endian IEEE 754 single precision
+    F32,
+    /// Little-endian IEEE 754 double precision
+    F64,
+    /// A single byte, 0 for false or 1 for true
+    Bool,
+    /// The remaining bytes of the payload, so only valid as the last field
+    Bytes,
+    /// The remaining bytes of the payload as UTF-8, so only valid as the last field
+    String,
+    /// A single byte holding one of the enum's values
+    Enum(&'static EnumDescriptor),
+    /// A little-endian u16 element count followed by the elements, which are always fixed-size
+    Array(&'static FieldType),
+    /// A little-endian u16 length followed by the struct's fields
+    Struct(&'static StructDescriptor),
+    /// A presence byte, 0 or 1, followed by the field if present. The field is absent altogether
+    /// if the payload ends before it.
+    Option(&'static FieldType),
+    /// A LEB128 varint holding the integer, zigzag-encoded first if signed
+    Varint(&'static FieldType),
+}
+
+/// Describes a struct, whether sent as a message payload or nested in another struct
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StructDescriptor {
+    pub name: &'static str,
+    pub fields: &'static [FieldDescriptor],
+    pub encoding: StructEncoding,
+}
+
+/// How the fields of a struct are laid out
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StructEncoding {
+    /// Fields encoded one after another in declaration order
+    Positional,
+    /// Each field encoded as its tag, a little-endian u16 length and the field, in any order.
+    /// Unknown tags are skipped and `None` optional fields are left out.
+    Tlv,
+}
+
+/// Describes an enum encoded as a single byte
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EnumDescriptor {
+    pub name: &'static str,
+    /// The name and wire value of every variant
+    pub variants: &'static [(&'static str, u8)],
+}
+
+impl PayloadDescriptor {
+    /// The number of bytes the payload occupies, or `None` if it varies
+    #[must_use]
+    pub fn size(self) -> Option<usize> {
+        match self {
+            PayloadDescriptor::Struct(descriptor) => descriptor.size(),
+            PayloadDescriptor::Enum(_) => Some(1),
+        }
+    }
+}
+
+impl StructDescriptor {
+    /// The number of bytes the encoded struct occupies, or `None` if it varies
+    ///
+    /// Only positional structs whose fields are all fixed-size have a fixed size.
+    #[must_use]
+    pub fn size(&self) -> Option<usize> {
+        match self.encoding {
+            StructEncoding::Positional => self
+                .fields
+                .iter()
+                .map(|field| field.field_type.size())
+                .sum(),
+            StructEncoding::Tlv => None,
+        }
+    }
+}
+
+impl FieldType {
+    /// The number of bytes the field occupies, or `None` if it varies
+    #[must_use]
+    pub fn size(self) -> Option<usize> {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool | FieldType::Enum(_) => Some(1),
+            FieldType::U16 | FieldType::I16 => Some(2),
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => Some(4),
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => Some(8),
+            FieldType::Bytes
+            | FieldType::String
+            | FieldType::Array(_)
+            | FieldType::Struct(_)
+            | FieldType::Option(_)
+            | FieldType::Varint(_) => None,
+        }
+    }
+}
+
+/// Returns descriptors for all built-in message types, ordered by ID
+#[must_use]
+pub fn messages() -> &'static [MessageDescriptor] {
+    crate::message::MESSAGES
+}
+
+/// Returns the descriptor of the built-in message type with this ID
+#[must_use]
+pub fn message(id: u16) -> Option<&'static MessageDescriptor> {
+    let messages = messages();
+    let index = messages
+        .binary_search_by_key(&id, |message| message.id)
+        .ok()?;
+    Some(&messages[index])
+}
+
+/// Returns the name of the built-in message type with this ID, for showing instead of the number
+///
+/// ```
+/// # use generic_serial_protocol::schema::{message_id, message_name};
+/// assert_eq!(message_name(27), Some("Response"));
+/// assert_eq!(message_id("Response"), Some(27));
+/// assert_eq!(message_name(0x1234), None);
+/// ```
+#[must_use]
+pub fn message_name(id: u16) -> Option<&'static str> {
+    message(id).map(|message| message.name)
+}
+
+/// Returns the ID of the built-in message type with this name, which is its `Message` variant
+#[must_use]
+pub fn message_id(name: &str) -> Option<u16> {
+    messages()
+        .iter()
+        .find(|message| message.name == name)
+        .map(|message| message.id)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,53 @@
+use super::*;
+use crate::message::{message_types, Message};
+
+#[test]
+fn test_message_lookup() {
+    for message in messages() {
+        assert_eq!(self::message(message.id), Some(message));
+        assert_eq!(message_name(message.id), Some(message.name));
+        assert_eq!(message_id(message.name), Some(message.id));
+    }
+    assert_eq!(message_name(*USER_MESSAGE_TYPES.start()), None);
+    assert_eq!(message_id("NoSuchMessage"), None);
+    let ping = Message::Ping(message_types::Ping { sequence: 1 });
+    assert_eq!(ping.name(), Some("Ping"));
+    let unknown = Message::Unknown {
+        message_type: *USER_MESSAGE_TYPES.start(),
+        data: vec![],
+    };
+    assert_eq!(unknown.name(), None);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_export_json() {
+    let schema: serde_json::Value = serde_json::from_str(&export_json()).unwrap();
+    assert_eq!(schema["framing"]["native"]["escape_byte"], 0x42);
+    assert_eq!(
+        schema["user_message_types"],
+        serde_json::json!([256, 65535])
+    );
+
+    let exported = schema["messages"].as_array().unwrap();
+    assert_eq!(exported.len(), messages().len());
+    let response = &exported[messages()
+        .iter()
+        .position(|m| m.name == "Response")
+        .unwrap()];
+    assert_eq!(response["id"], 27);
+    assert_eq!(response["payload"]["encoding"], "positional");
+    assert_eq!(response["payload"]["fields"][0]["type"], "u16");
+    assert_eq!(
+        response["payload"]["fields"][1]["type"]["enum"]["kind"],
+        "enum"
+    );
+
+    let device_info = &exported[messages()
+        .iter()
+        .position(|m| m.name == "DeviceInfo")
+        .unwrap()];
+    assert_eq!(device_info["payload"]["encoding"], "tlv");
+    assert_eq!(device_info["payload"]["fields"][1]["tag"], 2);
+    assert_eq!(device_info["payload"]["fields"][1]["type"], "string");
+}
@@ -0,0 +1,148 @@
+use super::{SerialManager, Transport};
+#[cfg(unix)]
+use std::fs::File;
+use std::io;
+use std::time::Duration;
+
+/// The buffers of a connection discarded by [`TransportControl::flush_buffers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buffers {
+    /// Bytes received and not yet read
+    Input,
+    /// Bytes written and not yet transmitted
+    Output,
+    Both,
+}
+
+/// Line control of a connection, such as a serial port's break condition and modem control
+/// lines, exposed by [`SerialManager`] for connections that implement it
+///
+/// Every method fails with [`io::ErrorKind::Unsupported`] unless the connection implements it,
+/// so that connections can implement only what they support.
+pub trait TransportControl {
+    /// Holds the transmit line low for `duration`, then releases it
+    fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        let _ = duration;
+        Err(unsupported("break conditions"))
+    }
+
+    /// Asserts or deasserts the RTS (request to send) line
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        let _ = level;
+        Err(unsupported("the RTS line"))
+    }
+
+    /// Asserts or deasserts the DTR (data terminal ready) line
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        let _ = level;
+        Err(unsupported("the DTR line"))
+    }
+
+    /// Discards the bytes waiting in the connection's buffers
+    fn flush_buffers(&mut self, buffers: Buffers) -> io::Result<()> {
+        let _ = buffers;
+        Err(unsupported("flushing buffers"))
+    }
+}
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("the connection does not support {what}"),
+    )
+}
+
+/// Serial ports opened as files, with `TIOCSBRK`/`TIOCCBRK`, `TIOCMBIS`/`TIOCMBIC` and
+/// `tcflush`
+#[cfg(unix)]
+impl TransportControl for File {
+    fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: neither request takes an argument
+        check(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCSBRK) })?;
+        std::thread::sleep(duration);
+        // SAFETY: as above
+        check(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCCBRK) })
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        set_modem_line(self, libc::TIOCM_RTS, level)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        set_modem_line(self, libc::TIOCM_DTR, level)
+    }
+
+    fn flush_buffers(&mut self, buffers: Buffers) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let queue = match buffers {
+            Buffers::Input => libc::TCIFLUSH,
+            Buffers::Output => libc::TCOFLUSH,
+            Buffers::Both => libc::TCIOFLUSH,
+        };
+        // SAFETY: tcflush only takes the descriptor and a queue selector
+        check(unsafe { libc::tcflush(self.as_raw_fd(), queue) })
+    }
+}
+
+#[cfg(unix)]
+fn set_modem_line(file: &File, line: libc::c_int, level: bool) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let request = if level {
+        libc::TIOCMBIS
+    } else {
+        libc::TIOCMBIC
+    };
+    // SAFETY: the request reads the line bits from the int pointed to
+    check(unsafe { libc::ioctl(file.as_raw_fd(), request, &raw const line) })
+}
+
+#[cfg(unix)]
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport + TransportControl,
+{
+    /// Holds the transmit line in the break condition for `duration`, such as to reset a device
+    /// into its bootloader
+    pub fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        self.begin_transmit();
+        let result = self.connection.send_break(duration);
+        self.end_transmit();
+        result
+    }
+
+    /// Asserts or deasserts the RTS line
+    pub fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.connection.set_rts(level)
+    }
+
+    /// Asserts or deasserts the DTR line, which resets boards such as Arduinos when it drops
+    pub fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.connection.set_dtr(level)
+    }
+
+    /// Discards the bytes waiting in the connection's buffers
+    ///
+    /// Discarding input also discards the bytes read but not yet decoded and any frame partly
+    /// received, so the next frame is decoded from its start byte.
+    pub fn flush_buffers(&mut self, buffers: Buffers) -> io::Result<()> {
+        self.connection.flush_buffers(buffers)?;
+        if buffers != Buffers::Output {
+            self.read_buffer.clear();
+            self.read_position = 0;
+            self.decoder.reset();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use super::{SerialManager, Transport};
+use crate::capture::Direction;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends `sequence` as is, outside any frame, with `guard` of silence before and after it,
+    /// such as `+++` to drop a radio modem into command mode
+    ///
+    /// Nothing else is sent through this manager during the guard times.
+    pub fn send_escape(&mut self, sequence: &[u8], guard: Duration) -> io::Result<()> {
+        std::thread::sleep(guard);
+        self.write_and_flush(sequence)?;
+        std::thread::sleep(guard);
+        Ok(())
+    }
+
+    /// Suspends framing, returning a handle that reads and writes the connection directly, such
+    /// as to talk to a device's ROM bootloader before a firmware update
+    ///
+    /// Bytes already read from the connection but not yet decoded are read first, and any frame
+    /// partly received is abandoned. Framing resumes once the handle is dropped, with anything
+    /// read but not taken through it discarded, so the next frame is decoded from its start
+    /// byte.
+    pub fn suspend_framing(&mut self) -> Suspended<'_, T> {
+        self.decoder.reset();
+        Suspended { manager: self }
+    }
+}
+
+/// The connection of a [`SerialManager`] with framing suspended, returned by
+/// [`SerialManager::suspend_framing`]
+///
+/// Bytes go through the byte log and half-duplex bus control, but not the layers, stats,
+/// observer or rate limit.
+pub struct Suspended<'a, T>
+where
+    T: Transport,
+{
+    manager: &'a mut SerialManager<T>,
+}
+
+impl<T> Read for Suspended<'_, T>
+where
+    T: Transport,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let manager = &mut *self.manager;
+        let buffered = &manager.read_buffer[manager.read_position..];
+        if !buffered.is_empty() {
+            let length = buffered.len().min(buf.len());
+            buf[..length].copy_from_slice(&buffered[..length]);
+            manager.read_position += length;
+            return Ok(length);
+        }
+        let length = manager.connection.read_bytes(buf)?;
+        manager.log_bytes(Direction::Received, &buf[..length]);
+        Ok(length)
+    }
+}
+
+impl<T> Write for Suspended<'_, T>
+where
+    T: Transport,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.manager.write_and_flush(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.manager.connection.flush_output()
+    }
+}
+
+impl<T> Drop for Suspended<'_, T>
+where
+    T: Transport,
+{
+    fn drop(&mut self) {
+        self.manager.read_buffer.clear();
+        self.manager.read_position = 0;
+        self.manager.decoder.reset();
+    }
+}
@@ -0,0 +1,84 @@
+use super::{SerialManager, Transport};
+use crate::errors::ReceiveError;
+use crate::message::Message;
+use crate::payload::MessageType;
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Receives messages until one for which `matches` returns `true`, and returns it
+    ///
+    /// Messages skipped on the way, such as telemetry arriving while waiting for an ack, are
+    /// kept and returned in order by the next receives, or passed to the handler set with
+    /// [`set_unmatched_handler`](Self::set_unmatched_handler) if there is one. Messages kept by
+    /// earlier calls are searched first. An error is returned as it is, leaving the messages
+    /// skipped so far kept.
+    pub fn receive_matching(
+        &mut self,
+        mut matches: impl FnMut(&Message) -> bool,
+    ) -> Result<Message, ReceiveError> {
+        // The message peeked is the first of those received but not yet returned
+        if let Some(peeked) = self.peeked.take() {
+            self.unmatched.push_front(peeked);
+        }
+        if let Some(index) = self
+            .unmatched
+            .iter()
+            .position(|(message, _)| matches(message))
+        {
+            if let Some((message, _)) = self.unmatched.remove(index) {
+                return Ok(message);
+            }
+        }
+        loop {
+            let (message, received_at) = self.receive_next()?;
+            if matches(&message) {
+                return Ok(message);
+            }
+            match &mut self.unmatched_handler {
+                Some(handler) => handler(message),
+                None => self.unmatched.push_back((message, received_at)),
+            }
+        }
+    }
+
+    /// Receives messages until one of type `M`, and returns its payload
+    ///
+    /// Messages of other types are skipped as with [`receive_matching`](Self::receive_matching).
+    /// Application messages with the ID of `M` are decoded in the byte order of the connection,
+    /// and dropped if that fails.
+    pub fn receive_type<M: MessageType>(&mut self) -> Result<M, ReceiveError> {
+        loop {
+            // Registered application types arrive as unknown messages, which `from_message`
+            // decodes
+            let message = self.receive_matching(|message| message.message_type() == M::ID)?;
+            if let Ok(payload) = M::from_message(message, self.endianness) {
+                return Ok(payload);
+            }
+        }
+    }
+
+    /// Passes messages skipped by [`receive_matching`](Self::receive_matching) to `handler`
+    /// instead of keeping them for the next receives, such as to hand them to a
+    /// [`Dispatcher`](crate::Dispatcher)
+    ///
+    /// Any previously set handler is replaced. Messages already kept are still returned by the
+    /// next receives.
+    pub fn set_unmatched_handler(&mut self, handler: impl FnMut(Message) + Send + 'static) {
+        self.unmatched_handler = Some(Box::new(handler));
+    }
+
+    /// Removes the handler set with [`set_unmatched_handler`](Self::set_unmatched_handler), so
+    /// that skipped messages are kept again
+    pub fn clear_unmatched_handler(&mut self) {
+        self.unmatched_handler = None;
+    }
+
+    /// Returns the number of messages skipped by [`receive_matching`](Self::receive_matching)
+    /// that are kept for the next receives
+    #[must_use]
+    pub fn unmatched(&self) -> usize {
+        self.unmatched.len()
+    }
+}
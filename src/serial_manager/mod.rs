@@ -1,10 +1,81 @@
-use crate::errors::{MaybeResyncError, ReceiveError};
-use crate::message::Message;
+use crate::byte_log::ByteLog;
+use crate::cancel::CancelToken;
+use crate::capture::Direction;
+use crate::coalesce::{CoalescedFrame, Coalescer, Coalescing};
+use crate::codec::{unescaped_run, Decoder, DecoderEvent, Endianness, Frame, Framing, Resync};
+use crate::errors::{
+    DecodeError, IdentifyError, PingError, ReceiveError, ReceiveTypedError, RegisterError,
+    TimeSyncError,
+};
+use crate::events::SerialManagerEvents;
+use crate::fmt;
+use crate::half_duplex::HalfDuplex;
+use crate::layer::{Crc32, Layer, LayerStack};
+use crate::lazy::LazyMessage;
+use crate::message::{message_types, Message};
+#[cfg(feature = "metrics")]
+use crate::metrics_export::MetricsExporter;
+use crate::negotiation::{Negotiated, Negotiation};
+use crate::observer::Observer;
+use crate::payload::{MessageType, Payload};
+use crate::profile::Profile;
+use crate::queue::{OutgoingQueue, PendingRequests, Priority};
+use crate::rate_limit::{LimitAction, RateLimit, RateLimiter, ReceiveLimit, ReceiveLimiter};
+use crate::reconnect::ReconnectingConnection;
+use crate::schema;
+use crate::stats::{LatencyStats, Stats};
+use crate::time::{Instant, SystemTime};
+use crate::time_sync::{now_micros, TimeSync};
+use crate::timestamps::ReceiveClock;
+use crate::wake::WakePreamble;
+use crate::watchdog::{LinkStatus, Watchdog};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 
-const START_BYTE: u8 = 0x58;
-const ESCAPE_BYTE: u8 = 0x42;
-const XOR_BYTE: u8 = 0x69;
+mod control;
+mod escape;
+mod filtered;
+mod send_queue;
+mod shared;
+mod stream;
+mod transport;
+mod worker;
+
+/// The most bytes read from the connection at once
+const READ_BUFFER_SIZE: usize = 4096;
+
+pub use control::{Buffers, TransportControl};
+pub use escape::Suspended;
+pub use send_queue::{QueueSender, SendQueue};
+pub use shared::{SharedSender, SharedSerialManager};
+pub use transport::Transport;
+pub use worker::TryClone;
+
+type RequestClassifier = Box<dyn Fn(&message_types::Command) -> Priority + Send>;
+type DecodeErrorHandler = Box<dyn FnMut(&ReceiveError) + Send>;
+
+/// What a [`SerialManager`] does when a received frame fails to decode, set with
+/// [`with_decode_errors`](SerialManager::with_decode_errors)
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DecodeErrorAction {
+    /// Fails the receive with [`ReceiveError::Decode`]
+    #[default]
+    Fail,
+    /// Passes the error to the handler set with
+    /// [`set_decode_error_handler`](SerialManager::set_decode_error_handler), if any, and
+    /// receives the next frame
+    Skip,
+}
+
+fn lock<S>(state: &Mutex<S>) -> MutexGuard<'_, S> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
 
 /// An implementation of a custom serial protocol.
 ///
@@ -30,38 +101,933 @@ const XOR_BYTE: u8 = 0x69;
 /// It is the length *before* escaping, so that the actual number of bytes transmitted may be greater than
 /// this number.
 ///
-/// All multi-byte fields are transmitted in little-endian format.
+/// All multi-byte fields are transmitted in little-endian format, unless big-endian is selected
+/// with [`with_endianness`](Self::with_endianness).
+///
+/// [`with_framing`](Self::with_framing) selects SLIP framing instead, for peers that already
+/// speak it, with the same message type and payload encoding.
 pub struct SerialManager<T>
 where
-    T: Read + Write,
+    T: Transport,
 {
     connection: T,
+    stats: Stats,
+    observer: Option<Box<dyn Observer + Send>>,
+    events: Option<Box<dyn SerialManagerEvents + Send>>,
+    decoder: Decoder,
+    raw_frame: Vec<u8>,
+    ping_sequence: u16,
+    endianness: Endianness,
+    framing: Framing,
+    trailer: Option<u8>,
+    /// The block size frames are padded up to a multiple of, if any
+    padding: Option<usize>,
+    resync: Resync,
+    inter_byte_timeout: Option<Duration>,
+    max_payload_length: Option<usize>,
+    deliver_unknown: bool,
+    strict: bool,
+    decode_errors: DecodeErrorAction,
+    /// Takes the decode errors skipped under `DecodeErrorAction::Skip`
+    decode_error_handler: Option<DecodeErrorHandler>,
+    unexpected_bytes: Vec<u8>,
+    queue: OutgoingQueue,
+    /// Decides the priority of received `Command`s, which is `Control` without it
+    request_classifier: Option<RequestClassifier>,
+    pending_requests: PendingRequests,
+    read_buffer: Vec<u8>,
+    read_position: usize,
+    /// When the bytes in the read buffer were read
+    read_at: Instant,
+    /// When the transport received the bytes in the read buffer, if it timestamps them
+    received_at: Option<SystemTime>,
+    receive_clock: Option<ReceiveClock>,
+    user_message_types: BTreeSet<u16>,
+    allow_trailing_bytes: bool,
+    /// An error hit by `drain` after it had received messages, returned by the next receive
+    pending_error: Option<ReceiveError>,
+    /// A message received by `peek`, which the next receive returns
+    peeked: Option<(Message, SystemTime)>,
+    /// Messages skipped by `receive_matching`, which the next receives return in order
+    unmatched: VecDeque<(Message, SystemTime)>,
+    /// Takes messages skipped by `receive_matching` instead of them being kept
+    unmatched_handler: Option<Box<dyn FnMut(Message) + Send>>,
+    rate_limiter: Option<RateLimiter>,
+    coalescer: Coalescer,
+    receive_limiters: BTreeMap<u16, ReceiveLimiter>,
+    watchdog: Option<Watchdog>,
+    /// Shared with the writer of `spawn`, so that both ends go through the same layers
+    layers: Arc<Mutex<LayerStack>>,
+    /// Frames that layers held back and have since released, or the errors decoding them with
+    /// the layers further from the wire, received before any more bytes are read
+    released: VecDeque<Result<Frame, DecodeError>>,
+    /// The configuration agreed in the last negotiation, if any
+    negotiated: Option<Negotiated>,
+    /// How many of the layers closest to the wire the last negotiation stacked
+    negotiated_layers: usize,
+    /// Shared with the writer of `spawn`, so that both directions go into the same files
+    byte_log: Option<Arc<Mutex<ByteLog>>>,
+    /// Shared with the writer of `spawn`, so that it waits for bytes received by the reader
+    half_duplex: Option<Arc<Mutex<HalfDuplex>>>,
+    /// Shared with the writer of `spawn`, so that the line is quiet only if both ends are
+    wake_preamble: Option<Arc<Mutex<WakePreamble>>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<MetricsExporter>,
+    cancel: CancelToken,
 }
 
 impl<T> SerialManager<T>
 where
-    T: Read + Write,
+    T: Transport,
 {
     pub fn new(connection: T) -> Self {
-        Self { connection }
+        Self {
+            connection,
+            stats: Stats::default(),
+            observer: None,
+            events: None,
+            decoder: Decoder::new(),
+            raw_frame: Vec::new(),
+            ping_sequence: 0,
+            endianness: Endianness::Little,
+            framing: Framing::Native,
+            trailer: None,
+            padding: None,
+            resync: Resync::StartByte,
+            inter_byte_timeout: None,
+            max_payload_length: None,
+            deliver_unknown: false,
+            strict: false,
+            decode_errors: DecodeErrorAction::Fail,
+            decode_error_handler: None,
+            unexpected_bytes: Vec::new(),
+            queue: OutgoingQueue::default(),
+            request_classifier: None,
+            pending_requests: PendingRequests::default(),
+            read_buffer: Vec::new(),
+            read_position: 0,
+            read_at: Instant::now(),
+            received_at: None,
+            receive_clock: None,
+            user_message_types: BTreeSet::new(),
+            allow_trailing_bytes: false,
+            pending_error: None,
+            peeked: None,
+            unmatched: VecDeque::new(),
+            unmatched_handler: None,
+            rate_limiter: None,
+            coalescer: Coalescer::default(),
+            receive_limiters: BTreeMap::new(),
+            watchdog: None,
+            layers: Arc::default(),
+            released: VecDeque::new(),
+            negotiated: None,
+            negotiated_layers: 0,
+            byte_log: None,
+            half_duplex: None,
+            wake_preamble: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// Sets the byte order of the length, message type and numbers in payloads, for peers that
+    /// use big-endian (network byte order) instead of the default little-endian
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Sets how frames are delimited, for peers that use [`Framing::Slip`] instead of the
+    /// default framing
+    #[must_use]
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Sends `trailer` after every frame and expects it after every frame received, so that a
+    /// frame whose length field was corrupted to be too short fails with
+    /// [`DecodeError::InvalidTrailer`] when its end is reached
+    ///
+    /// Only used with escaped framings, as SLIP and COBS delimit frames already and
+    /// [`Framing::Binary`] and [`Framing::CheckedHeader`] end them with a checksum.
+    #[must_use]
+    pub fn with_trailer(mut self, trailer: u8) -> Self {
+        self.trailer = Some(trailer);
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Pads every frame sent with bytes that are not part of any frame, so that each write is
+    /// a multiple of `block_size` bytes, and ignores the padding of frames received
+    ///
+    /// This suits receivers that use DMA with fixed-size transfers. Frames with a start byte are
+    /// padded with `0x00`, which is skipped between frames, and SLIP and COBS frames with their
+    /// delimiter, which makes empty packets that are ignored. A `block_size` of 0 or 1 turns
+    /// padding off.
+    #[must_use]
+    pub fn with_padding(mut self, block_size: usize) -> Self {
+        self.padding = (block_size > 1).then_some(block_size);
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Sets how the receiver resyncs after a frame goes wrong, for peers that do not escape
+    /// every start byte they send
+    ///
+    /// With [`Resync::Checksum`], both peers must stack a [`Crc32`](crate::layer::Crc32) layer
+    /// last. [`Resync::IdleGap`] measures gaps between reads from the connection, so bytes
+    /// that arrive together are never split by one.
+    #[must_use]
+    pub fn with_resync(mut self, resync: Resync) -> Self {
+        self.resync = resync;
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Discards a partially received frame once no byte of it has arrived for `timeout`, so
+    /// that a frame whose sender died in the middle of it is counted as a resync instead of
+    /// being completed with the bytes of the next one
+    ///
+    /// The gap is checked when bytes arrive and when a read fails, so reads from the connection
+    /// must time out for a frame to be given up on while the line stays quiet.
+    #[must_use]
+    pub fn with_inter_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.inter_byte_timeout = Some(timeout);
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Fails to receive frames whose payload is longer than `length` bytes with
+    /// [`DecodeError::InvalidLength`], as soon as their header arrives
+    ///
+    /// This bounds the memory a corrupted or hostile length field can make the receiver buffer,
+    /// which with [`Framing::Extended`] is otherwise up to 4 GiB.
+    #[must_use]
+    pub fn with_max_payload_length(mut self, length: usize) -> Self {
+        self.max_payload_length = Some(length);
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Applies the settings of `profile`, such as one loaded from a configuration file
+    ///
+    /// Settings the profile leaves at their defaults are applied too, replacing any set before.
+    /// Its receive limits are added to those set before, and its `Crc32` layer, if any, wraps
+    /// the layers added before.
+    #[must_use]
+    pub fn with_profile(mut self, profile: &Profile) -> Self {
+        self.framing = profile.framing;
+        self.endianness = profile.endianness;
+        self.resync = profile.resync;
+        self.trailer = profile.trailer;
+        self.padding = profile.padding.filter(|&block_size| block_size > 1);
+        self.inter_byte_timeout = profile.inter_byte_timeout;
+        self.decoder = self.new_decoder();
+        self.rate_limiter = profile.rate_limit.map(RateLimiter::new);
+        for (&message_type, &limit) in &profile.receive_limits {
+            self = self.with_receive_limit(message_type, limit);
+        }
+        self.deliver_unknown = profile.unknown_messages;
+        self.allow_trailing_bytes = profile.trailing_bytes;
+        self.strict = profile.strict;
+        self.decode_errors = profile.decode_errors;
+        if profile.crc32 {
+            self = self.with_layer(Crc32);
+        }
+        self
+    }
+
+    fn new_decoder(&self) -> Decoder {
+        let mut decoder = Decoder::new()
+            .with_endianness(self.endianness)
+            .with_framing(self.framing)
+            .with_resync(self.resync);
+        if let Some(trailer) = self.trailer {
+            decoder = decoder.with_trailer(trailer);
+        }
+        if self.padding.is_some() || self.wake_preamble.is_some() {
+            decoder = decoder.with_padding();
+        }
+        if let Some(timeout) = self.inter_byte_timeout {
+            decoder = decoder.with_inter_byte_timeout(timeout);
+        }
+        if let Some(length) = self.max_payload_length {
+            decoder = decoder.with_max_payload_length(length);
+        }
+        decoder
+    }
+
+    /// Paces sending to stay within `limit`, sleeping before a write that would exceed it, for
+    /// peers whose receive buffers overflow when frames arrive in bursts
+    #[must_use]
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limiter = Some(RateLimiter::new(limit));
+        self
+    }
+
+    /// Holds back frames of `priority` sent with [`send_with_priority`](Self::send_with_priority)
+    /// or [`pump`](Self::pump) to write them together as set by `coalescing`, so that bursts of
+    /// tiny frames do not each pay the latency of a flush
+    ///
+    /// Frames of priorities without coalescing, and those sent with [`send`](Self::send) and the
+    /// like, are written at once along with any held back before them. Held back frames are also
+    /// written by [`flush`](Self::flush) and before waiting to receive, so that a request is not
+    /// held back while waiting for its response.
+    #[must_use]
+    pub fn with_coalescing(mut self, priority: Priority, coalescing: Coalescing) -> Self {
+        self.coalescer.set(priority, coalescing);
+        self
+    }
+
+    /// Limits the size and rate of received frames of `message_type`, so that a peer flooding
+    /// one message type cannot starve the processing of others
+    ///
+    /// Frames exceeding the limit are dropped or fail the receive, as set by the limit's
+    /// [`LimitAction`]. Setting a limit for a message type again replaces the previous one.
+    #[must_use]
+    pub fn with_receive_limit(mut self, message_type: u16, limit: ReceiveLimit) -> Self {
+        self.receive_limiters
+            .insert(message_type, ReceiveLimiter::new(limit));
+        self
+    }
+
+    /// Watches for the link stalling, with no valid frame received for the watchdog's timeout
+    ///
+    /// The watchdog's handler is called and its probes and challenges sent when a read returns,
+    /// so reads from the connection must time out or would block for these to happen on a quiet
+    /// link. Its [`handle`](Watchdog::handle) is up to date regardless.
+    #[must_use]
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Adds a layer processing frames between messages and the wire, wrapping any layers added
+    /// before it
+    ///
+    /// See the [`layer`](crate::layer) module for how layers are stacked.
+    #[must_use]
+    pub fn with_layer(self, layer: impl Layer + Send + 'static) -> Self {
+        self.layers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(layer);
+        self
+    }
+
+    /// Logs every byte sent and received, before any decoding, to rotating files
+    #[must_use]
+    pub fn with_byte_log(mut self, log: ByteLog) -> Self {
+        self.byte_log = Some(Arc::new(Mutex::new(log)));
+        self
+    }
+
+    /// Shares a half-duplex bus, such as two-wire RS-485, waiting for a turnaround time after
+    /// receiving before transmitting and controlling the line driver
+    #[must_use]
+    pub fn with_half_duplex(mut self, half_duplex: HalfDuplex) -> Self {
+        self.half_duplex = Some(Arc::new(Mutex::new(half_duplex)));
+        self
+    }
+
+    /// Wakes a peer that sleeps while the line is quiet by sending a preamble before the first
+    /// frame after the idle time, and ignores the preamble of frames received
+    ///
+    /// Both peers need a wake preamble, so that the receiver does not count the preamble as
+    /// skipped bytes. See [`WakePreamble`] for how the idle time is also reported to the event
+    /// handler.
+    #[must_use]
+    pub fn with_wake_preamble(mut self, wake_preamble: WakePreamble) -> Self {
+        self.wake_preamble = Some(Arc::new(Mutex::new(wake_preamble)));
+        self.decoder = self.new_decoder();
+        self
+    }
+
+    /// Timestamps received frames with the times the transport records in `clock`, such as the
+    /// kernel's receive timestamps of a [`KernelTimestamps`](crate::KernelTimestamps) socket,
+    /// instead of the local time they were completed
+    ///
+    /// Frames completed by a read the transport recorded no time for keep the local time.
+    #[must_use]
+    pub fn with_receive_clock(mut self, clock: ReceiveClock) -> Self {
+        self.receive_clock = Some(clock);
+        self
+    }
+
+    /// Publishes the [`stats`](Self::stats) through the `metrics` facade, as counters such as
+    /// `gsp_frames_sent_total` labelled with `link`, and round trip times as the
+    /// `gsp_round_trip_seconds` histogram, so that they can be scraped by Prometheus or any other
+    /// `metrics` exporter
+    ///
+    /// The counters are brought up to date after every send and before every read from the
+    /// connection. They keep counting up when the stats are reset. The `metrics` recorder must
+    /// be installed before this is called.
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn with_metrics(mut self, link: &str) -> Self {
+        self.metrics = Some(MetricsExporter::new(link));
+        self
+    }
+
+    /// Delivers frames with unregistered message types as [`Message::Unknown`] instead of
+    /// failing with [`DecodeError::InvalidMessageType`], so that a peer with newer message types
+    /// can still be talked to
+    #[must_use]
+    pub fn with_unknown_messages(mut self, deliver_unknown: bool) -> Self {
+        self.deliver_unknown = deliver_unknown;
+        self
+    }
+
+    /// Accepts payloads with bytes left over after the fields of their message type, as sent by
+    /// a newer peer that appended fields, instead of failing fixed-size message types with
+    /// [`DecodeError::InvalidLength`]
+    ///
+    /// The bytes left over are counted in [`Stats::trailing_bytes`] and passed to
+    /// [`Observer::on_trailing_bytes`].
+    #[must_use]
+    pub fn with_trailing_bytes(mut self, allow_trailing_bytes: bool) -> Self {
+        self.allow_trailing_bytes = allow_trailing_bytes;
+        self
+    }
+
+    /// Registers an application message type, which is then delivered as [`Message::Unknown`]
+    /// for the application to decode
+    ///
+    /// The ID must be in [`USER_MESSAGE_TYPES`](crate::schema::USER_MESSAGE_TYPES), so that
+    /// built-in message types added later never clash with it, and not already registered.
+    pub fn register_message_type(&mut self, message_type: u16) -> Result<(), RegisterError> {
+        if !schema::USER_MESSAGE_TYPES.contains(&message_type) {
+            return Err(RegisterError::Reserved(message_type));
+        }
+        if !self.user_message_types.insert(message_type) {
+            return Err(RegisterError::AlreadyRegistered(message_type));
+        }
+        Ok(())
+    }
+
+    /// Fails `receive` with [`ReceiveError::UnexpectedBytes`] when bytes outside any frame are
+    /// skipped, instead of skipping them silently, for links where any garbage indicates a bug
+    ///
+    /// The error is returned when the start byte after the garbage arrives, and the frame it
+    /// starts is received as normal by the next call.
+    #[must_use]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets what receiving does when a frame fails to decode, such as a payload with invalid
+    /// UTF-8 or a checksum mismatch, which is to fail with [`ReceiveError::Decode`] by default
+    ///
+    /// With [`DecodeErrorAction::Skip`], the frame is dropped and the next one received instead,
+    /// so that a long-running receive loop is not ended by one corrupt frame. Skipped frames are
+    /// still counted in [`Stats::decode_errors`] and passed to the event handler, and the errors
+    /// can be taken with [`set_decode_error_handler`](Self::set_decode_error_handler).
+    #[must_use]
+    pub fn with_decode_errors(mut self, action: DecodeErrorAction) -> Self {
+        self.decode_errors = action;
+        self
+    }
+
+    /// Passes the errors of frames skipped under [`DecodeErrorAction::Skip`] to `handler`, such
+    /// as to log them
+    ///
+    /// Any previously set handler is replaced.
+    pub fn set_decode_error_handler(
+        &mut self,
+        handler: impl FnMut(&ReceiveError) + Send + 'static,
+    ) {
+        self.decode_error_handler = Some(Box::new(handler));
+    }
+
+    /// Removes the handler set with
+    /// [`set_decode_error_handler`](Self::set_decode_error_handler)
+    pub fn clear_decode_error_handler(&mut self) {
+        self.decode_error_handler = None;
+    }
+
+    /// Registers an observer that is notified of raw frames and resyncs
+    ///
+    /// Any previously registered observer is replaced.
+    pub fn set_observer(&mut self, observer: impl Observer + Send + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Removes the registered observer, if any
+    pub fn clear_observer(&mut self) {
+        self.observer = None;
+    }
+
+    /// Registers a handler for traffic and lifecycle events, such as frames sent and received,
+    /// errors and the watchdog's link status changing
+    ///
+    /// Any previously registered handler is replaced.
+    pub fn set_events(&mut self, events: impl SerialManagerEvents + Send + 'static) {
+        self.events = Some(Box::new(events));
+    }
+
+    /// Removes the registered event handler, if any
+    pub fn clear_events(&mut self) {
+        self.events = None;
+    }
+
+    /// Returns a token that aborts receiving from another thread, such as to shut down
+    /// promptly, with [`ReceiveError::Cancelled`]
+    ///
+    /// See [`CancelToken`] for how soon a blocked receive notices.
+    #[must_use]
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub(crate) fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    /// Returns a snapshot of the traffic counters
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Resets all traffic counters to zero
+    pub fn reset_stats(&mut self) {
+        self.publish_metrics();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reset();
+        }
+        self.stats = Stats::default();
+    }
+
+    /// Brings the counters published with [`with_metrics`](Self::with_metrics) up to date
+    #[cfg_attr(not(feature = "metrics"), allow(clippy::unused_self))]
+    fn publish_metrics(&mut self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut self.metrics {
+            metrics.publish(&self.stats);
+        }
     }
 
     /// Sends a message over the serial connection
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn send(&mut self, message: Message) -> io::Result<()> {
-        let message_type_bytes = message.message_type().to_le_bytes();
-        let data = message.to_bytes();
-        #[allow(clippy::cast_possible_truncation)]
-        let length = (message_type_bytes.len() + data.len()) as u16;
-        let length_bytes = length.to_le_bytes();
-
-        self.connection.write_all(&[START_BYTE])?;
-        self.write_escaped_bytes(&length_bytes)?;
-        self.write_escaped_bytes(&message_type_bytes)?;
-        self.write_escaped_bytes(&data)?;
-        self.connection.flush()?;
+        self.responded(&message);
+        let message_type = message.message_type();
+        self.send_frame(message_type, &message.to_bytes_with(self.endianness)?)
+    }
+
+    /// Sends an already encoded payload with the given message type, bypassing [`Message`]
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the message type or payload does not
+    /// [`fit`](Framing::fits) the framing.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn send_raw(&mut self, message_type: u16, payload: &[u8]) -> io::Result<()> {
+        self.send_frame(message_type, payload)
+    }
+
+    /// Sends the payload of a message type, with its ID, without wrapping it in a [`Message`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn send_typed<M: MessageType>(&mut self, payload: &M) -> io::Result<()> {
+        let mut data = Vec::new();
+        payload.encode(&mut data, self.endianness)?;
+        self.send_frame(M::ID, &data)
+    }
+
+    /// Sends several messages with a single write and flush
+    ///
+    /// All the frames are encoded into one buffer first, which avoids the latency of flushing
+    /// after every message on connections such as USB CDC devices.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn send_all(&mut self, messages: impl IntoIterator<Item = Message>) -> io::Result<()> {
+        let frames: Vec<_> = messages
+            .into_iter()
+            .map(|message| {
+                let message_type = message.message_type();
+                let data = message.to_bytes_with(self.endianness)?;
+                let (frame, sent_length, padding) = self.encode_frame(message_type, &data)?;
+                Ok((message_type, data, sent_length, padding, frame))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let buffer: Vec<u8> = frames
+            .iter()
+            .flat_map(|(_, _, _, _, frame)| frame)
+            .copied()
+            .collect();
+        self.wait_for_rate_limit(buffer.len(), frames.len());
+        self.write_and_flush(&buffer)?;
+        for (message_type, data, sent_length, padding, frame) in &frames {
+            self.record_sent(*message_type, data, *sent_length, *padding, frame);
+        }
+        Ok(())
+    }
+
+    /// Sends a message, holding it back to be written along with others if its priority has
+    /// [`coalescing`](Self::with_coalescing), and otherwise at once like [`send`](Self::send)
+    ///
+    /// A `Response` to a received `Command` is sent at least at the
+    /// [priority of the request](Self::request_priority).
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn send_with_priority(&mut self, message: Message, priority: Priority) -> io::Result<()> {
+        let priority = self
+            .responded(&message)
+            .map_or(priority, |request| priority.max(request));
+        let message_type = message.message_type();
+        let data = message.to_bytes_with(self.endianness)?;
+        let Some(coalescing) = self.coalescer.coalescing(priority) else {
+            return self.send_frame(message_type, &data);
+        };
+        let (frame, sent_length, padding) = self.encode_frame(message_type, &data)?;
+        self.wait_for_rate_limit(frame.len(), 1);
+        let record = CoalescedFrame {
+            message_type,
+            data,
+            sent_length,
+            padding,
+            end: 0,
+        };
+        self.coalescer
+            .push(coalescing, Instant::now(), &frame, record);
+        self.flush_due()
+    }
+
+    /// Writes any frames held back for [`coalescing`](Self::with_coalescing) now
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.coalescer.is_empty() {
+            return Ok(());
+        }
+        self.write_and_flush(&[])
+    }
+
+    /// Returns when the frames held back for [`coalescing`](Self::with_coalescing) are due to
+    /// be written, if any are
+    ///
+    /// They are only written when something is next sent or received, or by
+    /// [`flush`](Self::flush) or [`pump`](Self::pump), so an event loop should call one of these
+    /// by then.
+    #[must_use]
+    pub fn flush_deadline(&self) -> Option<Instant> {
+        self.coalescer.deadline()
+    }
+
+    /// Writes the frames held back for coalescing if they are due
+    fn flush_due(&mut self) -> io::Result<()> {
+        if self.coalescer.is_due(Instant::now()) {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Queues a message to be sent by [`pump`](Self::pump) once every message of a higher
+    /// priority has been sent
+    ///
+    /// A `Response` to a received `Command` is queued at least at the
+    /// [priority of the request](Self::request_priority), so that the reply to an urgent command
+    /// is not stuck behind queued bulk data.
+    pub fn send_queued(&mut self, message: Message, priority: Priority) {
+        let priority = self
+            .responded(&message)
+            .map_or(priority, |request| priority.max(request));
+        self.queue.push(message, priority);
+    }
+
+    /// Decides the priority of each received `Command` with `classify`, which responses to it
+    /// are then sent at no lower than, instead of treating every command as
+    /// [`Priority::Control`]
+    #[must_use]
+    pub fn with_request_priority(
+        mut self,
+        classify: impl Fn(&message_types::Command) -> Priority + Send + 'static,
+    ) -> Self {
+        self.request_classifier = Some(Box::new(classify));
+        self
+    }
+
+    /// Returns the priority of the received `Command` with `id`, if it has not been responded to
+    ///
+    /// The priorities of the last 64 commands not responded to are kept.
+    #[must_use]
+    pub fn request_priority(&self, id: u16) -> Option<Priority> {
+        self.pending_requests.get(id)
+    }
+
+    /// Forgets the request `message` responds to, if it is a `Response`, returning the request's
+    /// priority
+    fn responded(&mut self, message: &Message) -> Option<Priority> {
+        let Message::Response(response) = message else {
+            return None;
+        };
+        self.pending_requests.remove(response.id)
+    }
+
+    /// Remembers the priority of a received `Command`, for its response to inherit
+    fn record_request(&mut self, frame: &Frame) {
+        if frame.message_type != message_types::Command::ID {
+            return;
+        }
+        if let Ok(command) = message_types::Command::decode(&frame.payload, self.endianness) {
+            let priority = self
+                .request_classifier
+                .as_ref()
+                .map_or(Priority::Control, |classify| classify(&command));
+            self.pending_requests.insert(command.id, priority);
+        }
+    }
+
+    /// Returns the number of messages waiting in the outgoing queue
+    #[must_use]
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Sends the highest priority queued message, if any, returning whether one was sent
+    ///
+    /// Calling this between other work lets urgent messages overtake queued bulk data at frame
+    /// boundaries. A message that fails to send stays at the front of its queue. Messages are
+    /// sent as by [`send_with_priority`](Self::send_with_priority), and with none queued, frames
+    /// held back for coalescing are written if they are due.
+    pub fn pump(&mut self) -> io::Result<bool> {
+        let Some((message, priority)) = self.queue.pop() else {
+            self.flush_due()?;
+            return Ok(false);
+        };
+        if let Err(e) = self.send_with_priority(message.clone(), priority) {
+            self.queue.push_front(message, priority);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Sends every queued message in priority order
+    pub fn pump_all(&mut self) -> io::Result<()> {
+        while self.pump()? {}
+        Ok(())
+    }
+
+    /// Closes the connection in an orderly way, sending every queued message and then a
+    /// `Goodbye`, which the peer receives as [`ReceiveError::PeerClosed`], and flushing the
+    /// connection before dropping it
+    ///
+    /// Frames held back for coalescing are written along with the `Goodbye`, and the last flush
+    /// comes after it. Input is not drained: messages the peer sent that have not been received,
+    /// including any kept by [`peek`](Self::peek) or
+    /// [`receive_matching`](Self::receive_matching), are dropped with the connection, so
+    /// receive them first to keep them.
+    pub fn close(mut self) -> io::Result<()> {
+        self.pump_all()?;
+        self.send(Message::Goodbye(message_types::Goodbye {}))
+    }
+
+    fn send_frame(&mut self, message_type: u16, data: &[u8]) -> io::Result<()> {
+        let (frame, sent_length, padding) = self.encode_frame(message_type, data)?;
+
+        self.wait_for_rate_limit(frame.len(), 1);
+        self.write_and_flush(&frame)?;
+        self.record_sent(message_type, data, sent_length, padding, &frame);
         Ok(())
     }
 
+    /// Writes and flushes encoded frames, passing any error on to the event handler
+    fn write_and_flush(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_bytes(bytes, true)
+    }
+
+    /// Writes encoded bytes, flushing them if `flush` is set, and passes any error on to the
+    /// event handler
+    ///
+    /// Any frames held back for coalescing are written first, in the same write.
+    fn write_bytes(&mut self, bytes: &[u8], flush: bool) -> io::Result<()> {
+        let coalesced = self.coalescer.take();
+        let mut buffer = coalesced.bytes;
+        let bytes = if buffer.is_empty() {
+            bytes
+        } else {
+            buffer.extend_from_slice(bytes);
+            &buffer
+        };
+        self.begin_transmit();
+        let preamble = self.preamble();
+        let result = self
+            .connection
+            .write_all_bytes(&preamble)
+            .and_then(|()| self.connection.write_all_bytes(bytes))
+            .and_then(|()| {
+                if flush {
+                    self.connection.flush_output()
+                } else {
+                    Ok(())
+                }
+            });
+        // An unflushed write is followed by the rest of its frame, so keeps the bus
+        if flush || result.is_err() {
+            self.end_transmit();
+        }
+        match &result {
+            Ok(()) => {
+                if !preamble.is_empty() {
+                    self.stats.bytes_sent += preamble.len() as u64;
+                    self.log_bytes(Direction::Sent, &preamble);
+                }
+                self.log_bytes(Direction::Sent, bytes);
+                if let Some(wake_preamble) = &self.wake_preamble {
+                    lock(wake_preamble).sent(Instant::now(), !flush);
+                }
+            }
+            Err(e) => {
+                if let Some(events) = &mut self.events {
+                    events.on_send_error(e);
+                }
+            }
+        }
+        if result.is_ok() {
+            let mut start = 0;
+            for frame in &coalesced.frames {
+                self.record_sent(
+                    frame.message_type,
+                    &frame.data,
+                    frame.sent_length,
+                    frame.padding,
+                    &bytes[start..frame.end],
+                );
+                start = frame.end;
+            }
+        }
+        result
+    }
+
+    /// Returns the wake preamble to send before the next bytes, which is empty unless the peer
+    /// may have gone to sleep
+    fn preamble(&self) -> Vec<u8> {
+        let Some(wake_preamble) = &self.wake_preamble else {
+            return Vec::new();
+        };
+        let wake_preamble = lock(wake_preamble);
+        if !wake_preamble.preamble_due(Instant::now()) {
+            return Vec::new();
+        }
+        vec![self.framing.padding_byte(); wake_preamble.length()]
+    }
+
+    /// Tells the event handler if the line has gone quiet for the idle time of the wake preamble
+    fn notify_idle(&mut self) {
+        let idle = self
+            .wake_preamble
+            .as_ref()
+            .is_some_and(|wake_preamble| lock(wake_preamble).went_idle(Instant::now()));
+        if let (true, Some(events)) = (idle, &mut self.events) {
+            events.on_idle();
+        }
+    }
+
+    /// Waits for the turnaround time and enables the line driver of a half-duplex bus
+    fn begin_transmit(&self) {
+        let Some(half_duplex) = &self.half_duplex else {
+            return;
+        };
+        let wait = lock(half_duplex).turnaround_wait(Instant::now());
+        std::thread::sleep(wait);
+        let delay = lock(half_duplex).enable();
+        std::thread::sleep(delay);
+    }
+
+    /// Disables the line driver of a half-duplex bus once the last byte has left
+    fn end_transmit(&self) {
+        let Some(half_duplex) = &self.half_duplex else {
+            return;
+        };
+        let delay = lock(half_duplex).disable_delay();
+        std::thread::sleep(delay);
+        lock(half_duplex).disable();
+    }
+
+    fn log_bytes(&self, direction: Direction, bytes: &[u8]) {
+        if let Some(log) = &self.byte_log {
+            log.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .observe(direction, bytes);
+        }
+    }
+
+    /// Sleeps until `bytes` bytes in `frames` frames may be sent under the rate limit
+    fn wait_for_rate_limit(&mut self, bytes: usize, frames: usize) {
+        if let Some(limiter) = &mut self.rate_limiter {
+            let delay = limiter.reserve(Instant::now(), bytes, frames);
+            if !delay.is_zero() {
+                std::thread::sleep(delay);
+            }
+        }
+    }
+
+    /// Encodes a frame, passing it through the layers first and padding it, and returns it along
+    /// with the length of its payload as sent and the padding added, or fails if it does not fit
+    /// the framing
+    fn encode_frame(&self, message_type: u16, data: &[u8]) -> io::Result<(Vec<u8>, usize, usize)> {
+        let mut layers = self.layers.lock().unwrap_or_else(PoisonError::into_inner);
+        let layered;
+        let (message_type, data) = if layers.is_empty() {
+            (message_type, data)
+        } else {
+            layered = layers.encode(Frame {
+                message_type,
+                payload: data.to_vec(),
+            });
+            (layered.message_type, layered.payload.as_slice())
+        };
+        if !self.framing.fits(message_type, data.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message type {message_type} with {} bytes of payload does not fit {:?} framing",
+                    data.len(),
+                    self.framing
+                ),
+            ));
+        }
+        let mut frame =
+            self.framing
+                .encode_with_trailer(message_type, data, self.endianness, self.trailer);
+        let padding = self
+            .padding
+            .map_or(0, |block_size| self.framing.pad(&mut frame, 0, block_size));
+        Ok((frame, data.len(), padding))
+    }
+
+    /// Updates the stats and notifies the observer and event handler of a frame that has been
+    /// written and flushed, whose payload `data` was `sent_length` bytes once through the layers
+    fn record_sent(
+        &mut self,
+        message_type: u16,
+        data: &[u8],
+        sent_length: usize,
+        padding: usize,
+        frame: &[u8],
+    ) {
+        self.stats.frames_sent += 1;
+        self.stats.bytes_sent += frame.len() as u64;
+        // Everything beyond the framing bytes, message type, payload, trailer and padding is an
+        // escape byte
+        let trailer = usize::from(self.framing.is_escaped() && self.trailer.is_some());
+        self.stats.escape_bytes_sent +=
+            (frame.len() - self.framing.overhead() - trailer - sent_length - padding) as u64;
+        if let Some(observer) = &mut self.observer {
+            observer.on_raw_frame_sent(frame);
+        }
+        if let Some(events) = &mut self.events {
+            events.on_send(message_type, data);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message_type, length = sent_length + 2, "frame sent");
+        self.publish_metrics();
+    }
+
     /// Receives a message from the serial connection
     ///
     /// This function will block until a message is received.
@@ -69,91 +1035,854 @@ where
     /// If the start byte is encountered mid-packet, the function will resync to the next packet.
     ///
     /// An error is returned if there is an IO error or if the message is malformed.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn receive(&mut self) -> Result<Message, ReceiveError> {
-        self.wait_for_start_byte()?;
+        self.receive_frame().map(|(message, _)| message)
+    }
+
+    /// Receives the next message like [`receive`](Self::receive), and returns its payload if it
+    /// is of type `M`
+    ///
+    /// Fails with [`ReceiveTypedError::UnexpectedMessage`], holding the message, if it is of
+    /// another type or does not decode as `M`. To skip messages of other types instead, use
+    /// [`receive_type`](Self::receive_type).
+    pub fn receive_typed<M: MessageType>(&mut self) -> Result<M, ReceiveTypedError> {
+        let message = self.receive()?;
+        if message.message_type() != M::ID {
+            return Err(ReceiveTypedError::UnexpectedMessage(message));
+        }
+        M::from_message(message, self.endianness).map_err(ReceiveTypedError::UnexpectedMessage)
+    }
+
+    /// Receives the next message without consuming it, so that the next call to `peek` or any
+    /// `receive` returns it again
+    ///
+    /// This blocks like [`receive`](Self::receive) if no message has been peeked yet. An error
+    /// is not kept, so the next receive moves on to the next frame.
+    pub fn peek(&mut self) -> Result<&Message, ReceiveError> {
+        // receive_frame returns the message already peeked, if there is one
+        let peeked = self.receive_frame()?;
+        Ok(&self.peeked.insert(peeked).0)
+    }
 
+    /// Receives every message that can be received without waiting for more bytes, which may be
+    /// none
+    ///
+    /// Receiving stops once a read from the connection would block or times out, so the
+    /// connection must be non-blocking, e.g. after `UnixStream::set_nonblocking(true)`, or have a
+    /// read timeout. Otherwise this blocks until the connection fails. A frame that has only
+    /// partly arrived is completed by a later receive.
+    ///
+    /// An error is only returned if no message was received before it. Otherwise the messages
+    /// are returned, and the error is returned by the next call to `drain` or any `receive`.
+    pub fn drain(&mut self) -> Result<Vec<Message>, ReceiveError> {
+        let mut messages = Vec::new();
         loop {
-            match self.read_message() {
-                Ok(message) => return Ok(message),
-                Err(MaybeResyncError::Resync) => (),
-                Err(MaybeResyncError::Error(e)) => return Err(e),
+            match self.receive() {
+                Ok(message) => messages.push(message),
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(messages);
+                }
+                Err(e) if messages.is_empty() => return Err(e),
+                Err(e) => {
+                    self.pending_error = Some(e);
+                    return Ok(messages);
+                }
             }
         }
     }
 
-    fn needs_escaping(byte: u8) -> bool {
-        byte == START_BYTE || byte == ESCAPE_BYTE
+    /// Receives the next frame without decoding its payload into a [`Message`]
+    ///
+    /// Framing, escaping and resyncing work as in [`receive`](Self::receive), and any message
+    /// type is accepted. A message already received by [`peek`](Self::peek) or skipped by
+    /// [`receive_matching`](Self::receive_matching) is encoded again.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn receive_raw(&mut self) -> Result<Frame, ReceiveError> {
+        if let Some((message, _)) = self.take_received() {
+            return Ok(Frame {
+                message_type: message.message_type(),
+                payload: message
+                    .to_bytes_with(self.endianness)
+                    .map_err(io::Error::from)?,
+            });
+        }
+        let (frame, _) = loop {
+            match self.read_frame() {
+                Err(e) if self.skips(&e) => {}
+                result => break result?,
+            }
+        };
+        self.stats.frames_received += 1;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message_type = frame.message_type, "frame received");
+        Ok(frame)
     }
 
-    fn write_escaped_byte(&mut self, byte: u8) -> io::Result<()> {
-        if Self::needs_escaping(byte) {
-            self.connection.write_all(&[ESCAPE_BYTE])?;
-            self.connection.write_all(&[byte ^ XOR_BYTE])?;
-        } else {
-            self.connection.write_all(&[byte])?;
+    /// Receives the next frame as a [`LazyMessage`], whose fields are decoded on demand in the
+    /// manager's byte order
+    ///
+    /// As with [`receive_raw`](Self::receive_raw), any message type is accepted.
+    pub fn receive_lazy(&mut self) -> Result<LazyMessage, ReceiveError> {
+        let endianness = self.endianness;
+        self.receive_raw()
+            .map(|frame| LazyMessage::from(frame).with_endianness(endianness))
+    }
+
+    /// Receives a message like [`receive`](Self::receive), along with the local time its frame
+    /// was completed, or the time the transport received it if it was given a
+    /// [`with_receive_clock`](Self::with_receive_clock)
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn receive_timestamped(&mut self) -> Result<(Message, SystemTime), ReceiveError> {
+        self.receive_frame()
+    }
+
+    /// Sends a `Ping` and waits for the peer's `Pong`, returning the round trip time
+    ///
+    /// The round trip time is added to the `latency` stats, or counted as lost on timeout.
+    /// Replies to earlier pings are skipped, and any other message received in the meantime is an
+    /// error. A reply that never arrives is only detected if reads from the connection time out,
+    /// e.g. after `UnixStream::set_read_timeout`, as this blocks until the next read returns.
+    pub fn ping(&mut self, timeout: Duration) -> Result<Duration, PingError> {
+        let sequence = self.next_ping_sequence();
+        let sent = Instant::now();
+        self.send(Message::Ping(message_types::Ping { sequence }))?;
+        loop {
+            match self.receive() {
+                Ok(Message::Pong(pong)) if pong.sequence == sequence => {
+                    let round_trip = sent.elapsed();
+                    return if round_trip > timeout {
+                        self.stats.latency.lost += 1;
+                        Err(PingError::Timeout)
+                    } else {
+                        self.record_round_trip(round_trip);
+                        Ok(round_trip)
+                    };
+                }
+                Ok(Message::Pong(_)) => (),
+                Ok(message) => return Err(PingError::UnexpectedMessage(message.message_type())),
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => return Err(e.into()),
+            }
+            if sent.elapsed() > timeout {
+                self.stats.latency.lost += 1;
+                return Err(PingError::Timeout);
+            }
         }
-        Ok(())
     }
 
-    fn write_escaped_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
-        for &byte in bytes {
-            self.write_escaped_byte(byte)?;
+    /// Returns the sequence number for the next `Ping`
+    pub(crate) fn next_ping_sequence(&mut self) -> u16 {
+        self.ping_sequence = self.ping_sequence.wrapping_add(1);
+        self.ping_sequence
+    }
+
+    /// Returns the round trip times measured so far, to add to
+    pub(crate) fn latency_mut(&mut self) -> &mut LatencyStats {
+        &mut self.stats.latency
+    }
+
+    /// Adds a measured round trip time to the `latency` stats and any published histogram
+    pub(crate) fn record_round_trip(&mut self, round_trip: Duration) {
+        self.stats.latency.record(round_trip);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_round_trip(round_trip);
         }
-        Ok(())
     }
 
-    fn read_byte(&mut self) -> Result<u8, MaybeResyncError<io::Error>> {
-        let mut byte = [0u8; 1];
-        self.connection.read_exact(&mut byte)?;
+    /// Sends an `Identify` and waits for the peer's `DeviceInfo`, describing the connected device
+    ///
+    /// Any other message received in the meantime is skipped, as the peer may be sending
+    /// telemetry. Like [`ping`](Self::ping), a reply that never arrives is only detected if reads
+    /// from the connection time out or would block.
+    pub fn identify(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<message_types::DeviceInfo, IdentifyError> {
+        let sent = Instant::now();
+        self.send(Message::Identify(message_types::Identify {}))?;
+        self.receive_device_info(sent, timeout)
+    }
+
+    /// Agrees with the peer on a framing and layers, and switches to them
+    ///
+    /// Sends the [`DeviceInfo`](message_types::DeviceInfo) of `negotiation` and waits for the
+    /// peer's, which it sends from [`accept_negotiation`](Self::accept_negotiation), skipping any
+    /// other message received in the meantime as [`identify`](Self::identify) does. Both ends
+    /// then switch to the configuration worked out from the capabilities exchanged, which is kept
+    /// and returned by [`negotiated`](Self::negotiated). Layers stacked by an earlier negotiation
+    /// are replaced.
+    pub fn negotiate(
+        &mut self,
+        negotiation: &Negotiation,
+        timeout: Duration,
+    ) -> Result<Negotiated, IdentifyError> {
+        let sent = Instant::now();
+        self.send(Message::DeviceInfo(negotiation.info().clone()))?;
+        let peer = self.receive_device_info(sent, timeout)?;
+        Ok(self.apply_negotiation(negotiation, peer))
+    }
+
+    /// Answers the [`DeviceInfo`](message_types::DeviceInfo) a peer sent from
+    /// [`negotiate`](Self::negotiate) with that of `negotiation`, and switches to the
+    /// configuration agreed
+    ///
+    /// The reply is sent with the configuration in use so far, which applies from the next frame
+    /// sent or received.
+    pub fn accept_negotiation(
+        &mut self,
+        negotiation: &Negotiation,
+        peer: message_types::DeviceInfo,
+    ) -> io::Result<Negotiated> {
+        self.send(Message::DeviceInfo(negotiation.info().clone()))?;
+        Ok(self.apply_negotiation(negotiation, peer))
+    }
+
+    /// Returns the configuration agreed in the last negotiation, if any
+    #[must_use]
+    pub fn negotiated(&self) -> Option<&Negotiated> {
+        self.negotiated.as_ref()
+    }
+
+    fn apply_negotiation(
+        &mut self,
+        negotiation: &Negotiation,
+        peer: message_types::DeviceInfo,
+    ) -> Negotiated {
+        let negotiated = negotiation.agree(peer, self.framing);
+        let layers = negotiation.layers(&negotiated);
+        {
+            let mut stack = self.layers.lock().unwrap_or_else(PoisonError::into_inner);
+            stack.pop(self.negotiated_layers);
+            self.negotiated_layers = layers.len();
+            for layer in layers {
+                stack.push(layer);
+            }
+        }
+        if negotiated.framing != self.framing {
+            self.framing = negotiated.framing;
+            self.decoder = self.new_decoder();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(framing = ?negotiated.framing, capabilities = negotiated.capabilities.bits(), "negotiated");
+        self.negotiated = Some(negotiated.clone());
+        negotiated
+    }
+
+    /// Waits for the peer's `DeviceInfo`, skipping any other message, until `timeout` after
+    /// `sent`
+    fn receive_device_info(
+        &mut self,
+        sent: Instant,
+        timeout: Duration,
+    ) -> Result<message_types::DeviceInfo, IdentifyError> {
+        loop {
+            match self.receive() {
+                Ok(Message::DeviceInfo(info)) => return Ok(info),
+                Ok(_) => (),
+                Err(ReceiveError::Io(e))
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // Non-blocking connections would otherwise be polled in a busy loop
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if sent.elapsed() > timeout {
+                return Err(IdentifyError::Timeout);
+            }
+        }
+    }
 
-        if byte[0] == START_BYTE {
-            return Err(MaybeResyncError::Resync);
+    /// Estimates the offset between the peer's clock and the local wall clock
+    ///
+    /// Sends a `TimeRequest` holding the local time in microseconds since the Unix epoch, and
+    /// waits for the peer's `TimeResponse`. Any other message received in the meantime is an
+    /// error.
+    pub fn sync_time(&mut self) -> Result<TimeSync, TimeSyncError> {
+        let origin = now_micros();
+        let sent = Instant::now();
+        self.send(Message::TimeRequest(message_types::TimeRequest { origin }))?;
+        let response = match self.receive()? {
+            Message::TimeResponse(response) => response,
+            message => return Err(TimeSyncError::UnexpectedMessage(message.message_type())),
+        };
+        // Measured with the monotonic clock, so that the wall clock changing has no effect
+        let elapsed = u64::try_from(sent.elapsed().as_micros()).unwrap_or(u64::MAX);
+        if response.origin != origin {
+            return Err(TimeSyncError::OriginMismatch);
         }
-        Ok(byte[0])
+        Ok(TimeSync::from_timestamps(
+            origin,
+            response.receive,
+            response.transmit,
+            origin.saturating_add(elapsed),
+        ))
     }
 
-    fn read_escaped_byte(&mut self) -> Result<u8, MaybeResyncError<io::Error>> {
-        let byte = self.read_byte()?;
+    fn receive_frame(&mut self) -> Result<(Message, SystemTime), ReceiveError> {
+        if let Some(received) = self.take_received() {
+            return Ok(received);
+        }
+        self.receive_next()
+    }
 
-        if byte == ESCAPE_BYTE {
-            let next_byte = self.read_byte()?;
-            Ok(next_byte ^ XOR_BYTE)
+    /// Takes the message received by `peek` or the first one skipped by `receive_matching`,
+    /// if any, which come before any message still to be read
+    fn take_received(&mut self) -> Option<(Message, SystemTime)> {
+        self.peeked.take().or_else(|| self.unmatched.pop_front())
+    }
+
+    /// Reads and decodes the next frame from the connection, skipping those that fail to decode
+    /// if the decode error action says so
+    fn receive_next(&mut self) -> Result<(Message, SystemTime), ReceiveError> {
+        loop {
+            match self.decode_next() {
+                Err(e) if self.skips(&e) => {}
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether a receive that failed with `error` moves on to the next frame, passing the error
+    /// to the decode error handler if so
+    fn skips(&mut self, error: &ReceiveError) -> bool {
+        if self.decode_errors != DecodeErrorAction::Skip
+            || !matches!(error, ReceiveError::Decode { .. })
+        {
+            return false;
+        }
+        if let Some(handler) = &mut self.decode_error_handler {
+            handler(error);
+        }
+        true
+    }
+
+    /// Reads and decodes the next frame from the connection
+    fn decode_next(&mut self) -> Result<(Message, SystemTime), ReceiveError> {
+        let (frame, received_at) = self.read_frame()?;
+        let registered = schema::message(frame.message_type).is_some();
+        let user = self.user_message_types.contains(&frame.message_type);
+        let result = if user || (self.deliver_unknown && !registered) {
+            Ok(Message::Unknown {
+                message_type: frame.message_type,
+                data: frame.payload,
+            })
+        } else if self.allow_trailing_bytes {
+            Message::from_bytes_with_trailing(frame.message_type, &frame.payload, self.endianness)
+                .map(|(message, trailing)| {
+                    if !trailing.is_empty() {
+                        self.stats.trailing_bytes += trailing.len() as u64;
+                        if let Some(observer) = &mut self.observer {
+                            observer.on_trailing_bytes(frame.message_type, trailing);
+                        }
+                    }
+                    message
+                })
         } else {
-            Ok(byte)
+            Message::from_bytes_with(frame.message_type, frame.payload, self.endianness)
+        };
+        match result {
+            Ok(message) => {
+                self.stats.frames_received += 1;
+                #[cfg(feature = "tracing")]
+                tracing::debug!(message_type = frame.message_type, "frame received");
+                Ok((message, received_at))
+            }
+            Err(e) => {
+                self.stats.decode_errors += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %e, "failed to decode frame");
+                let error = self.decode_error(e);
+                self.notify_error(&error);
+                Err(error)
+            }
         }
     }
 
-    fn read_escaped_bytes(
-        &mut self,
-        length: usize,
-    ) -> Result<Vec<u8>, MaybeResyncError<io::Error>> {
-        let mut result = Vec::with_capacity(length);
-        for _ in 0..length {
-            result.push(self.read_escaped_byte()?);
+    /// Reads bytes until a complete frame has been received, returning it with the local time it
+    /// was completed
+    fn read_frame(&mut self) -> Result<(Frame, SystemTime), ReceiveError> {
+        // Already passed on to the event handler by the receive that hit it
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+        let result = self.next_frame();
+        if let Err(e) = &result {
+            self.notify_error(e);
+        }
+        result
+    }
+
+    /// Passes a receive error on to the event handler, unless it is only a read timing out or
+    /// receiving being cancelled
+    fn notify_error(&mut self, error: &ReceiveError) {
+        if let Some(events) = &mut self.events {
+            if !error.is_timeout() && !matches!(error, ReceiveError::Cancelled) {
+                events.on_error(error);
+            }
         }
-        Ok(result)
     }
 
-    fn read_u16(&mut self) -> Result<u16, MaybeResyncError<io::Error>> {
-        let bytes = self.read_escaped_bytes(2)?;
-        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    /// Passes a change in the watchdog's link status on to the event handler
+    fn notify_state_change(&mut self, status: Option<LinkStatus>) {
+        if let (Some(status), Some(events)) = (status, &mut self.events) {
+            events.on_state_change(status);
+        }
     }
 
-    fn wait_for_start_byte(&mut self) -> io::Result<()> {
+    fn next_frame(&mut self) -> Result<(Frame, SystemTime), ReceiveError> {
         loop {
-            match self.read_byte() {
-                Ok(_) => (),
-                Err(MaybeResyncError::Resync) => return Ok(()),
-                Err(MaybeResyncError::Error(e)) => return Err(e),
+            if let Some(frame) = self.released_frame()? {
+                if self.within_limit(&frame.0)? {
+                    return Ok(frame);
+                }
+                continue;
+            }
+            let event = if let Some(event) = self.decoder.poll() {
+                Some(event)
+            } else {
+                self.read_payload_run();
+                let byte = self.read_byte()?;
+                self.decoder.push_at(byte, self.read_at)
+            };
+            if let Some(event) = event {
+                if let Some(frame) = self.handle_event(event)? {
+                    if self.within_limit(&frame.0)? {
+                        return Ok(frame);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Checks a received frame against the receive limit of its message type, returning whether
+    /// it is accepted, or an error if it exceeds a limit that fails receiving
+    fn within_limit(&mut self, frame: &Frame) -> Result<bool, ReceiveError> {
+        let Some(limiter) = self.receive_limiters.get_mut(&frame.message_type) else {
+            return Ok(true);
+        };
+        let Err(violation) = limiter.check(self.read_at, frame.payload.len()) else {
+            return Ok(true);
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message_type = frame.message_type, %violation, "frame exceeded its receive limit");
+        match limiter.action() {
+            LimitAction::Drop => {
+                self.stats.frames_limited += 1;
+                Ok(false)
+            }
+            LimitAction::Error => Err(ReceiveError::LimitExceeded {
+                message_type: frame.message_type,
+                violation,
+            }),
+        }
+    }
+
+    /// Handles something that happened while decoding, returning a frame received and not
+    /// dropped by a layer
+    fn handle_event(
+        &mut self,
+        event: DecoderEvent,
+    ) -> Result<Option<(Frame, SystemTime)>, ReceiveError> {
+        match event {
+            DecoderEvent::Resync => {
+                self.stats.resyncs += 1;
+                if let Some(observer) = &mut self.observer {
+                    observer.on_resync();
+                }
+                #[cfg(feature = "tracing")]
+                tracing::debug!("start byte inside frame, resyncing");
+            }
+            DecoderEvent::Skipped(count) => {
+                self.stats.bytes_skipped += count as u64;
+                if let Some(observer) = &mut self.observer {
+                    observer.on_bytes_skipped(count);
+                }
+                #[cfg(feature = "tracing")]
+                tracing::debug!(count, "skipped bytes outside any frame");
+                if self.strict {
+                    let bytes = std::mem::take(&mut self.unexpected_bytes);
+                    return Err(ReceiveError::UnexpectedBytes(bytes));
+                }
+            }
+            DecoderEvent::InvalidLength(length) => {
+                self.stats.decode_errors += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!(length, "invalid length field, resyncing");
+                return Err(self.decode_error(DecodeError::InvalidLength(length)));
+            }
+            DecoderEvent::InvalidTrailer(byte) => {
+                self.stats.decode_errors += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!(byte, "invalid frame trailer, resyncing");
+                return Err(self.decode_error(DecodeError::InvalidTrailer(byte)));
+            }
+            DecoderEvent::ChecksumMismatch { expected, received } => {
+                self.stats.crc_failures += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!(expected, received, "frame checksum mismatch, rescanning");
+                return Err(self.decode_error(DecodeError::ChecksumMismatch { expected, received }));
+            }
+            DecoderEvent::HeaderChecksumMismatch { expected, received } => {
+                self.stats.crc_failures += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    expected,
+                    received,
+                    "frame header checksum mismatch, rescanning"
+                );
+                return Err(
+                    self.decode_error(DecodeError::HeaderChecksumMismatch { expected, received })
+                );
+            }
+            DecoderEvent::Frame(frame) => {
+                let received_at = self.received_at.unwrap_or_else(SystemTime::now);
+                if let Some(observer) = &mut self.observer {
+                    observer.on_raw_frame_received(&self.raw_frame);
+                }
+                let decoded = {
+                    let mut layers = self.layers.lock().unwrap_or_else(PoisonError::into_inner);
+                    let decoded = layers.decode(frame);
+                    self.released
+                        .extend(std::iter::from_fn(|| layers.poll().transpose()));
+                    decoded
+                };
+                let decoded = match decoded {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(error = %e, "layer rejected frame");
+                        return Err(self.layer_error(e));
+                    }
+                };
+                let status = self
+                    .watchdog
+                    .as_mut()
+                    .and_then(|watchdog| watchdog.feed(Instant::now()));
+                self.notify_state_change(status);
+                // Dropped by a layer, such as for being addressed to another device
+                let Some(frame) = decoded else {
+                    return Ok(None);
+                };
+                return self.deliver(frame, received_at);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next frame a layer held back and has since released, such as a reordering
+    /// [`Sequencing`](crate::layer::Sequencing) layer
+    fn released_frame(&mut self) -> Result<Option<(Frame, SystemTime)>, ReceiveError> {
+        while let Some(released) = self.released.pop_front() {
+            let frame = released.map_err(|e| self.layer_error(e))?;
+            let received_at = self.received_at.unwrap_or_else(SystemTime::now);
+            if let Some(delivered) = self.deliver(frame, received_at)? {
+                return Ok(Some(delivered));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Hands a frame that has been through the layers on to be received, unless it is a
+    /// liveness challenge or the response to one, which are handled here
+    fn deliver(
+        &mut self,
+        frame: Frame,
+        received_at: SystemTime,
+    ) -> Result<Option<(Frame, SystemTime)>, ReceiveError> {
+        if frame.message_type == message_types::Goodbye::ID {
+            self.stats.frames_received += 1;
+            #[cfg(feature = "tracing")]
+            tracing::debug!("peer closed the connection");
+            return Err(ReceiveError::PeerClosed);
+        }
+        if self.answer_challenge(&frame) {
+            self.stats.frames_received += 1;
+            return Ok(None);
+        }
+        self.record_request(&frame);
+        if let Some(events) = &mut self.events {
+            events.on_receive(frame.message_type, &frame.payload);
+        }
+        Ok(Some((frame, received_at)))
+    }
+
+    /// Echoes a liveness challenge back to the peer, or passes the response to one of the
+    /// watchdog's challenges on to it, returning whether the frame was either
+    fn answer_challenge(&mut self, frame: &Frame) -> bool {
+        match frame.message_type {
+            message_types::Challenge::ID => {
+                if let Ok(challenge) =
+                    message_types::Challenge::decode(&frame.payload, self.endianness)
+                {
+                    let response = message_types::ChallengeResponse {
+                        nonce: challenge.nonce,
+                    };
+                    // A response that cannot be sent leaves the peer to report this end
+                    let _ = self.send(Message::ChallengeResponse(response));
+                }
+                true
+            }
+            message_types::ChallengeResponse::ID => {
+                let Some(watchdog) = self
+                    .watchdog
+                    .as_mut()
+                    .filter(|watchdog| watchdog.is_challenging())
+                else {
+                    return false;
+                };
+                // Late responses to challenges already given up on are dropped too
+                if let Ok(response) =
+                    message_types::ChallengeResponse::decode(&frame.payload, self.endianness)
+                {
+                    watchdog.answer(response.nonce);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Counts a frame rejected by a layer, such as one failing a [`Crc32`](crate::layer::Crc32)
+    /// check, and attaches it to the error
+    fn layer_error(&mut self, source: DecodeError) -> ReceiveError {
+        if source.is_checksum_mismatch() {
+            self.stats.crc_failures += 1;
+        } else {
+            self.stats.decode_errors += 1;
+        }
+        self.decode_error(source)
+    }
+
+    /// Attaches the frame being received, as far as it has been read, to a decode error
+    fn decode_error(&self, source: DecodeError) -> ReceiveError {
+        // Payload offsets would be of the payload before the layers undid their changes
+        let layered = !matches!(
+            source,
+            DecodeError::InvalidLength(_)
+                | DecodeError::InvalidTrailer(_)
+                | DecodeError::HeaderChecksumMismatch { .. }
+        ) && !self
+            .layers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_empty();
+        ReceiveError::Decode {
+            offset: if layered {
+                None
+            } else {
+                fmt::frame_error_offset(&self.raw_frame, &source, self.endianness, self.framing)
+            },
+            frame: self.raw_frame.clone(),
+            source,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, ReceiveError> {
+        if self.read_position == self.read_buffer.len() {
+            let result = self.fill_read_buffer();
+            let liveness = self.poll_watchdog();
+            if result.is_err() {
+                self.expire_frame();
+                self.notify_idle();
+            }
+            liveness?;
+            result?;
+        }
+        let byte = self.read_buffer[self.read_position];
+        self.read_position += 1;
+
+        self.stats.bytes_received += 1;
+        if self.decoder.is_escape(byte) {
+            self.stats.escape_bytes_received += 1;
+        }
+        if self.decoder.is_frame_start(byte) {
+            self.raw_frame.clear();
+        } else if self.strict
+            && self.decoder.is_waiting_for_start()
+            && !self.decoder.is_padding(byte)
+        {
+            self.unexpected_bytes.push(byte);
+        }
+        self.raw_frame.push(byte);
+        Ok(byte)
+    }
+
+    /// Copies the bytes in the read buffer continuing the payload of the frame being received
+    /// with nothing to unescape into the decoder in one go, rather than pushing them one by one
+    fn read_payload_run(&mut self) {
+        let unread = &self.read_buffer[self.read_position..];
+        let count = self.decoder.push_payload_run(unread, self.read_at);
+        self.raw_frame.extend_from_slice(&unread[..count]);
+        self.read_position += count;
+        self.stats.bytes_received += count as u64;
+    }
+
+    /// Takes up to `limit` bytes from the read buffer as [`read_byte`](Self::read_byte) would,
+    /// stopping before the first start or escape byte, and returns them
+    fn read_unescaped_run(&mut self, limit: usize) -> &[u8] {
+        let start = self.read_position;
+        let unread = &self.read_buffer[start..];
+        let count = unescaped_run(&unread[..unread.len().min(limit)]);
+        self.raw_frame.extend_from_slice(&unread[..count]);
+        self.read_position += count;
+        self.stats.bytes_received += count as u64;
+        &self.read_buffer[start..start + count]
+    }
+
+    /// Discards a partially received frame the line has been quiet in the middle of for too
+    /// long, while no bytes arrive to push into the decoder
+    fn expire_frame(&mut self) {
+        if let Some(event) = self.decoder.expire(Instant::now()) {
+            // Handling a resync cannot fail
+            let _ = self.handle_event(event);
+        }
+    }
+
+    /// Lets the watchdog check for the link going down, and sends its probe and challenge if
+    /// they are due
+    ///
+    /// Fails with [`ReceiveError::PeerUnresponsive`] if a challenge has gone unanswered.
+    fn poll_watchdog(&mut self) -> Result<(), ReceiveError> {
+        let Some(watchdog) = &mut self.watchdog else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let status = watchdog.poll(now);
+        let probe = watchdog.probe_due(now);
+        let unanswered = watchdog.unanswered(now);
+        let challenge = watchdog.challenge_due(now);
+        self.notify_state_change(status);
+        if probe {
+            let sequence = self.next_ping_sequence();
+            // A probe that cannot be sent leaves the link to go down
+            let _ = self.send(Message::Ping(message_types::Ping { sequence }));
+        }
+        if let Some(nonce) = challenge {
+            // A challenge that cannot be sent goes unanswered
+            let _ = self.send(Message::Challenge(message_types::Challenge { nonce }));
+        }
+        if unanswered {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("peer did not answer a liveness challenge");
+            return Err(ReceiveError::PeerUnresponsive);
+        }
+        Ok(())
+    }
+
+    /// Reads whatever is available from the connection, up to the buffer size, so that bytes
+    /// arriving together are not read one at a time
+    ///
+    /// Frames held back for coalescing are written first. The cancel token is checked before
+    /// reading, and again when a read fails, such as by timing out or being interrupted.
+    fn fill_read_buffer(&mut self) -> Result<(), ReceiveError> {
+        self.flush()?;
+        self.publish_metrics();
+        if self.cancel.is_cancelled() {
+            self.read_buffer.clear();
+            self.read_position = 0;
+            return Err(ReceiveError::Cancelled);
+        }
+        self.read_buffer.resize(READ_BUFFER_SIZE, 0);
+        let result = loop {
+            match self.connection.read_bytes(&mut self.read_buffer) {
+                Ok(0) => break Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Ok(count) => break Ok(count),
+                Err(_) if self.cancel.is_cancelled() => break Err(ReceiveError::Cancelled),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => break Err(e.into()),
             }
+        };
+        self.read_buffer.truncate(*result.as_ref().unwrap_or(&0));
+        self.read_position = 0;
+        self.read_at = Instant::now();
+        self.received_at = self.receive_clock.as_ref().and_then(ReceiveClock::take);
+        if let (Ok(_), Some(half_duplex)) = (&result, &self.half_duplex) {
+            lock(half_duplex).received(Instant::now());
+        }
+        if let (Ok(_), Some(wake_preamble)) = (&result, &self.wake_preamble) {
+            lock(wake_preamble).activity(Instant::now());
         }
+        self.log_bytes(Direction::Received, &self.read_buffer);
+        result.map(|_| ())
+    }
+}
+
+impl SerialManager<File> {
+    /// Opens a serial port by name, such as `COM3` on Windows or `/dev/ttyUSB0` elsewhere
+    ///
+    /// The port is opened as-is, so configure its baud rate beforehand, e.g. with
+    /// `mode COM3 BAUD=115200` on Windows or `stty` elsewhere.
+    pub fn open_com(name: &str) -> io::Result<Self> {
+        // COM10 and above can only be opened through the device namespace
+        #[cfg(windows)]
+        let name = &if name.starts_with(r"\\") {
+            name.to_string()
+        } else {
+            format!(r"\\.\{name}")
+        };
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name)
+            .map(Self::new)
     }
 
-    fn read_message(&mut self) -> Result<Message, MaybeResyncError<ReceiveError>> {
-        let length = self.read_u16()? as usize;
-        let message_type = self.read_u16()?;
-        let data = self.read_escaped_bytes(length - 2)?;
-        Ok(Message::from_bytes(message_type, data)?)
+    /// Connects to a Windows named pipe by name, e.g. `device` for `\\.\pipe\device`
+    #[cfg(windows)]
+    pub fn open_pipe(name: &str) -> io::Result<Self> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!(r"\\.\pipe\{name}"))
+            .map(Self::new)
+    }
+}
+
+impl<T> SerialManager<ReconnectingConnection<T>>
+where
+    T: Read + Write,
+{
+    /// Creates a manager that calls `connect` for a new connection whenever the current one
+    /// fails, such as when a device reboots mid-session, waiting with the default [`Backoff`]
+    /// in between
+    ///
+    /// For a different backoff or to be told about reconnects, pass a configured
+    /// [`ReconnectingConnection`] to [`new`](Self::new) instead.
+    ///
+    /// [`Backoff`]: crate::Backoff
+    pub fn resilient(connect: impl FnMut() -> io::Result<T> + Send + 'static) -> Self {
+        Self::new(ReconnectingConnection::new(connect))
+    }
+}
+
+impl SerialManager<TcpStream> {
+    /// Connects to a TCP server, such as a ser2net bridge, with Nagle's algorithm disabled so
+    /// that frames are sent without delay
+    ///
+    /// To reconnect automatically when the connection drops, pass a
+    /// [`ReconnectingConnection::tcp`](crate::ReconnectingConnection::tcp) to
+    /// [`new`](Self::new) instead.
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
     }
 }
 
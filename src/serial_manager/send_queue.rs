@@ -0,0 +1,287 @@
+use crate::errors::QueueSendError;
+use crate::message::Message;
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+type WaterMarkCallback = Box<dyn Fn(usize) + Send + Sync>;
+
+/// Settings for the bounded outgoing queue of
+/// [`SerialManager::spawn_bounded`](crate::SerialManager::spawn_bounded)
+pub struct SendQueue {
+    capacity: usize,
+    high_water: Option<(usize, WaterMarkCallback)>,
+    low_water: Option<(usize, WaterMarkCallback)>,
+}
+
+impl fmt::Debug for SendQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendQueue")
+            .field("capacity", &self.capacity)
+            .field(
+                "high_water",
+                &self.high_water.as_ref().map(|(mark, _)| mark),
+            )
+            .field("low_water", &self.low_water.as_ref().map(|(mark, _)| mark))
+            .finish()
+    }
+}
+
+impl SendQueue {
+    /// Creates a queue holding up to `capacity` messages waiting to be written, at least 1
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            high_water: None,
+            low_water: None,
+        }
+    }
+
+    /// Calls `callback` with the depth when the queue fills up to `mark` messages, so that
+    /// producers can slow down before sends start blocking
+    ///
+    /// It is called again only after the queue has drained to the low water mark, which is
+    /// just below `mark` unless set with [`with_low_water`](Self::with_low_water).
+    #[must_use]
+    pub fn with_high_water(
+        mut self,
+        mark: usize,
+        callback: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.high_water = Some((mark, Box::new(callback)));
+        self
+    }
+
+    /// Calls `callback` with the depth when the queue drains to `mark` messages after reaching
+    /// the high water mark, so that producers can speed up again
+    #[must_use]
+    pub fn with_low_water(
+        mut self,
+        mark: usize,
+        callback: impl Fn(usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.low_water = Some((mark, Box::new(callback)));
+        self
+    }
+}
+
+struct State {
+    messages: VecDeque<Message>,
+    senders: usize,
+    /// Set when the writer has stopped, after which nothing more is sent
+    closed: bool,
+    /// Whether the high water mark has been reached since the queue was last at the low water
+    /// mark
+    above_high_water: bool,
+}
+
+struct Shared {
+    settings: SendQueue,
+    state: Mutex<State>,
+    /// Notified when a message is queued or the last sender is dropped
+    not_empty: Condvar,
+    /// Notified when a message is taken or the writer stops
+    not_full: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// The sending half of a bounded outgoing queue, returned by
+/// [`SerialManager::spawn_bounded`](crate::SerialManager::spawn_bounded)
+///
+/// It can be cloned to send from several threads. The writer thread stops once every clone has
+/// been dropped and the queue is empty.
+pub struct QueueSender {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for QueueSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueSender")
+            .field("depth", &self.depth())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clone for QueueSender {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+impl QueueSender {
+    /// Queues a message, waiting for space while the queue is full
+    pub fn send(&self, message: Message) -> Result<(), QueueSendError> {
+        self.push(message, None)
+    }
+
+    /// Queues a message, waiting up to `timeout` for space while the queue is full
+    pub fn send_timeout(&self, message: Message, timeout: Duration) -> Result<(), QueueSendError> {
+        self.push(message, Some(Instant::now() + timeout))
+    }
+
+    /// Queues a message if there is space, without waiting
+    pub fn try_send(&self, message: Message) -> Result<(), QueueSendError> {
+        self.push(message, Some(Instant::now()))
+            .map_err(|e| match e {
+                QueueSendError::Timeout(message) => QueueSendError::Full(message),
+                e => e,
+            })
+    }
+
+    /// Returns the number of messages waiting to be written
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.shared.lock().messages.len()
+    }
+
+    /// Returns the most messages that can wait to be written
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.shared.settings.capacity
+    }
+
+    fn push(&self, message: Message, deadline: Option<Instant>) -> Result<(), QueueSendError> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        loop {
+            if state.closed {
+                return Err(QueueSendError::Disconnected(message));
+            }
+            if state.messages.len() < shared.settings.capacity {
+                break;
+            }
+            state = match deadline {
+                None => shared
+                    .not_full
+                    .wait(state)
+                    .unwrap_or_else(std::sync::PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(QueueSendError::Timeout(message));
+                    }
+                    shared
+                        .not_full
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(std::sync::PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+        state.messages.push_back(message);
+        let depth = state.messages.len();
+        let high_water = match &shared.settings.high_water {
+            Some((mark, callback)) if !state.above_high_water && depth >= *mark => {
+                state.above_high_water = true;
+                Some(callback)
+            }
+            _ => None,
+        };
+        drop(state);
+        shared.not_empty.notify_one();
+        if let Some(callback) = high_water {
+            callback(depth);
+        }
+        Ok(())
+    }
+}
+
+/// The receiving half of a bounded outgoing queue, held by the writer thread
+pub(crate) struct QueueReceiver {
+    shared: Arc<Shared>,
+}
+
+impl QueueReceiver {
+    /// Takes the oldest message, waiting while the queue is empty, or returns `None` once every
+    /// sender has been dropped and the queue is empty
+    pub(crate) fn recv(&self) -> Option<Message> {
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        let message = loop {
+            if let Some(message) = state.messages.pop_front() {
+                break message;
+            }
+            if state.senders == 0 {
+                return None;
+            }
+            state = shared
+                .not_empty
+                .wait(state)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        };
+        let depth = state.messages.len();
+        let low_water = match (&shared.settings.high_water, &shared.settings.low_water) {
+            (Some((high, _)), low) if state.above_high_water => {
+                let (mark, callback) = match low {
+                    Some((mark, callback)) => (*mark, Some(callback)),
+                    None => (high.saturating_sub(1), None),
+                };
+                if depth <= mark {
+                    state.above_high_water = false;
+                    callback
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        drop(state);
+        shared.not_full.notify_one();
+        if let Some(callback) = low_water {
+            callback(depth);
+        }
+        Some(message)
+    }
+}
+
+impl Drop for QueueReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.not_full.notify_all();
+    }
+}
+
+/// Creates the two halves of a bounded outgoing queue
+pub(crate) fn send_queue(settings: SendQueue) -> (QueueSender, QueueReceiver) {
+    let shared = Arc::new(Shared {
+        settings,
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            senders: 1,
+            closed: false,
+            above_high_water: false,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
@@ -0,0 +1,166 @@
+use super::{lock, SerialManager, Transport, TryClone};
+use crate::codec::Frame;
+use crate::errors::ReceiveError;
+use crate::message::Message;
+use crate::stats::Stats;
+use crate::time::SystemTime;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// A [`SerialManager`] that application threads can share, created with
+/// [`SerialManager::into_shared`]
+///
+/// Receiving and sending take separate locks, so a thread blocked in
+/// [`receive`](Self::receive) does not hold up threads sending. Clones share the same
+/// connection, as do the [`SharedSender`]s from [`sender`](Self::sender), which can only send.
+/// Threads receiving at the same time each get a different message.
+pub struct SharedSerialManager<T>
+where
+    T: Transport,
+{
+    reader: Arc<Mutex<SerialManager<T>>>,
+    sender: SharedSender<T>,
+}
+
+impl<T> Clone for SharedSerialManager<T>
+where
+    T: Transport,
+{
+    fn clone(&self) -> Self {
+        Self {
+            reader: Arc::clone(&self.reader),
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SharedSerialManager<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSerialManager")
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handle that sends through a [`SharedSerialManager`], which can be cloned and moved to any
+/// thread
+pub struct SharedSender<T>
+where
+    T: Transport,
+{
+    writer: Arc<Mutex<SerialManager<T>>>,
+}
+
+impl<T> Clone for SharedSender<T>
+where
+    T: Transport,
+{
+    fn clone(&self) -> Self {
+        Self {
+            writer: Arc::clone(&self.writer),
+        }
+    }
+}
+
+impl<T> fmt::Debug for SharedSender<T>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport + TryClone,
+{
+    /// Splits the connection into a [`SharedSerialManager`] that threads can receive and send
+    /// through at the same time
+    ///
+    /// Receiving keeps this manager, with its configuration and observer. Sending goes through a
+    /// second handle to the connection, as with [`spawn`](Self::spawn), so its frames are not
+    /// seen by the observer.
+    pub fn into_shared(self) -> io::Result<SharedSerialManager<T>> {
+        let writer = self.try_clone_writer()?;
+        Ok(SharedSerialManager {
+            reader: Arc::new(Mutex::new(self)),
+            sender: SharedSender {
+                writer: Arc::new(Mutex::new(writer)),
+            },
+        })
+    }
+}
+
+impl<T> SharedSerialManager<T>
+where
+    T: Transport,
+{
+    /// Returns a handle that can only send, for threads that never receive
+    #[must_use]
+    pub fn sender(&self) -> SharedSender<T> {
+        self.sender.clone()
+    }
+
+    /// Sends a message like [`SerialManager::send`]
+    pub fn send(&self, message: Message) -> io::Result<()> {
+        self.sender.send(message)
+    }
+
+    /// Sends a frame like [`SerialManager::send_raw`]
+    pub fn send_raw(&self, message_type: u16, payload: &[u8]) -> io::Result<()> {
+        self.sender.send_raw(message_type, payload)
+    }
+
+    /// Receives a message like [`SerialManager::receive`], waiting for other threads receiving
+    /// first
+    pub fn receive(&self) -> Result<Message, ReceiveError> {
+        lock(&self.reader).receive()
+    }
+
+    /// Receives a frame like [`SerialManager::receive_raw`]
+    pub fn receive_raw(&self) -> Result<Frame, ReceiveError> {
+        lock(&self.reader).receive_raw()
+    }
+
+    /// Receives a message like [`SerialManager::receive_timestamped`]
+    pub fn receive_timestamped(&self) -> Result<(Message, SystemTime), ReceiveError> {
+        lock(&self.reader).receive_timestamped()
+    }
+
+    /// Returns the stats of both directions
+    ///
+    /// This waits for any thread receiving, as the receive stats are updated as bytes arrive.
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        let sent = lock(&self.sender.writer).stats();
+        let mut stats = lock(&self.reader).stats();
+        stats.frames_sent += sent.frames_sent;
+        stats.bytes_sent += sent.bytes_sent;
+        stats.escape_bytes_sent += sent.escape_bytes_sent;
+        stats
+    }
+}
+
+impl<T> SharedSender<T>
+where
+    T: Transport,
+{
+    /// Sends a message like [`SerialManager::send`], waiting for other threads sending first
+    pub fn send(&self, message: Message) -> io::Result<()> {
+        lock(&self.writer).send(message)
+    }
+
+    /// Sends a frame like [`SerialManager::send_raw`]
+    pub fn send_raw(&self, message_type: u16, payload: &[u8]) -> io::Result<()> {
+        lock(&self.writer).send_raw(message_type, payload)
+    }
+
+    /// Sends messages like [`SerialManager::send_all`], with no other thread's frames in between
+    pub fn send_all(&self, messages: impl IntoIterator<Item = Message>) -> io::Result<()> {
+        lock(&self.writer).send_all(messages)
+    }
+}
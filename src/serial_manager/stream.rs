@@ -0,0 +1,268 @@
+use super::{SerialManager, Transport};
+use crate::codec::{escape_into, DecoderEvent, Resync, ESCAPE_BYTE, START_BYTE, XOR_BYTE};
+use crate::errors::{DecodeError, ReceiveError};
+use crate::time::Instant;
+use std::io::{self, Read, Write};
+use std::sync::PoisonError;
+
+/// The most payload bytes held in memory at once while streaming
+const STREAM_CHUNK_SIZE: usize = 4096;
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sends a frame whose `length` byte payload is read from `payload` a chunk at a time and
+    /// escaped as it is written, so that multi-megabyte payloads are never held in memory
+    ///
+    /// Only escaped framings with a length field can be streamed, with the default
+    /// [`Resync`](crate::Resync), and not through layers, which work on whole frames. A streamed frame is counted in the stats, but not passed to the observer or
+    /// event handler. If `payload` ends early, this fails with
+    /// [`io::ErrorKind::UnexpectedEof`] after sending part of the frame, which the peer discards
+    /// when the next frame starts.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn send_stream(
+        &mut self,
+        message_type: u16,
+        payload: impl Read,
+        length: usize,
+    ) -> io::Result<()> {
+        self.check_streamable()?;
+        if !self.framing.fits(message_type, length) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "message type {message_type} with {length} bytes of payload does not fit {:?} framing",
+                    self.framing
+                ),
+            ));
+        }
+
+        let mut buffer = self
+            .framing
+            .encode_header(message_type, length, self.endianness);
+        let mut payload = payload.take(length as u64);
+        let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+        let mut remaining = length;
+        let mut sent = 0;
+        let mut frames = 1;
+        while remaining > 0 {
+            let count = match payload.read(&mut chunk[..remaining.min(STREAM_CHUNK_SIZE)]) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(count) => count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            escape_into(&mut buffer, &chunk[..count]);
+            remaining -= count;
+            if remaining == 0 {
+                break;
+            }
+            self.wait_for_rate_limit(buffer.len(), frames);
+            self.write_bytes(&buffer, false)?;
+            sent += buffer.len();
+            frames = 0;
+            buffer.clear();
+        }
+        if let Some(trailer) = self.trailer {
+            escape_into(&mut buffer, &[trailer]);
+        }
+        let padding = self.padding.map_or(0, |block_size| {
+            self.framing.pad(&mut buffer, sent, block_size)
+        });
+        self.wait_for_rate_limit(buffer.len(), frames);
+        self.write_and_flush(&buffer)?;
+        sent += buffer.len();
+
+        self.stats.frames_sent += 1;
+        self.stats.bytes_sent += sent as u64;
+        let trailer = usize::from(self.trailer.is_some());
+        self.stats.escape_bytes_sent +=
+            (sent - self.framing.overhead() - trailer - length - padding) as u64;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message_type, length, "frame streamed");
+        Ok(())
+    }
+
+    /// Receives the next frame, writing its payload to `sink` a chunk at a time as it is
+    /// unescaped, and returns its message type and payload length
+    ///
+    /// As with [`send_stream`](Self::send_stream), only escaped framings with a length field can
+    /// be streamed, not through layers, and the frame is counted in the stats but not passed to the
+    /// observer or event handler. A message already received by [`peek`](Self::peek) or skipped
+    /// by [`receive_matching`](Self::receive_matching) is encoded into `sink`.
+    ///
+    /// Once the payload has started, the frame cannot be resumed: a start byte inside it fails
+    /// with [`DecodeError::TruncatedPayload`], and an IO error, including a read timing out, or
+    /// cancellation discards the rest of the frame. Either way `sink` may already hold part of the payload.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn receive_stream(&mut self, sink: &mut impl Write) -> Result<(u16, usize), ReceiveError> {
+        if let Some((message, _)) = self.take_received() {
+            let message_type = message.message_type();
+            let payload = message
+                .to_bytes_with(self.endianness)
+                .map_err(io::Error::from)?;
+            sink.write_all(&payload)?;
+            return Ok((message_type, payload.len()));
+        }
+        self.check_streamable()?;
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+
+        let (message_type, length) = loop {
+            let byte = self.read_byte()?;
+            if let Some(event) = self.decoder.push(byte) {
+                // Only a frame with an empty payload completes before its payload starts
+                if let Some((frame, _)) = self.handle_event(event)? {
+                    self.finish_stream(frame.message_type, 0);
+                    return Ok((frame.message_type, 0));
+                }
+            }
+            if let Some(header) = self.decoder.pending_payload() {
+                break header;
+            }
+        };
+
+        let result = self.stream_payload(length, sink);
+        if let Err(e) = &result {
+            self.notify_error(e);
+        }
+        result?;
+        self.finish_stream(message_type, length);
+        Ok((message_type, length))
+    }
+
+    /// Unescapes `length` payload bytes into `sink`
+    fn stream_payload(&mut self, length: usize, sink: &mut impl Write) -> Result<(), ReceiveError> {
+        let header = std::mem::take(&mut self.raw_frame);
+        let mut chunk = Vec::with_capacity(length.min(STREAM_CHUNK_SIZE));
+        let mut received = 0;
+        let mut escaped = false;
+        while received + chunk.len() < length {
+            // Bytes with nothing to unescape are copied a run at a time
+            let wanted = (length - received - chunk.len()).min(STREAM_CHUNK_SIZE - chunk.len());
+            let run = if escaped {
+                &[]
+            } else {
+                self.read_unescaped_run(wanted)
+            };
+            if run.is_empty() {
+                let byte = match self.read_byte() {
+                    Ok(byte) => byte,
+                    Err(e) => {
+                        self.decoder.reset();
+                        return Err(e);
+                    }
+                };
+                let byte = if escaped {
+                    escaped = false;
+                    byte ^ XOR_BYTE
+                } else if byte == ESCAPE_BYTE {
+                    escaped = true;
+                    continue;
+                } else if byte == START_BYTE {
+                    // The decoder resyncs to the frame this starts
+                    if let Some(event) = self.decoder.push(byte) {
+                        self.handle_event(event)?;
+                    }
+                    self.stats.decode_errors += 1;
+                    return Err(ReceiveError::Decode {
+                        source: DecodeError::TruncatedPayload {
+                            expected: length,
+                            actual: received + chunk.len(),
+                        },
+                        frame: header,
+                        offset: None,
+                    });
+                } else {
+                    byte
+                };
+                chunk.push(byte);
+            } else {
+                chunk.extend_from_slice(run);
+            }
+            if chunk.len() == STREAM_CHUNK_SIZE {
+                received += chunk.len();
+                self.write_chunk(sink, &mut chunk)?;
+                // Only the bytes of the current chunk are kept, to bound memory use
+                self.raw_frame.clear();
+            }
+        }
+        self.write_chunk(sink, &mut chunk)?;
+        self.raw_frame = header;
+
+        self.decoder.finish_payload();
+        // Pushes the trailer, if there is one, through the decoder to be checked
+        while !self.decoder.is_waiting_for_start() {
+            let byte = match self.read_byte() {
+                Ok(byte) => byte,
+                Err(e) => {
+                    self.decoder.reset();
+                    return Err(e);
+                }
+            };
+            match self.decoder.push(byte) {
+                None | Some(DecoderEvent::Frame(_)) => {}
+                Some(event) => {
+                    // A start byte in place of the trailer resyncs to the frame it starts
+                    self.handle_event(event)?;
+                    self.stats.decode_errors += 1;
+                    return Err(self.decode_error(DecodeError::InvalidTrailer(byte)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, sink: &mut impl Write, chunk: &mut Vec<u8>) -> io::Result<()> {
+        let result = sink.write_all(chunk);
+        chunk.clear();
+        if result.is_err() {
+            self.decoder.reset();
+        }
+        result
+    }
+
+    /// Updates the stats and feeds the watchdog for a frame received by `receive_stream`
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn finish_stream(&mut self, message_type: u16, length: usize) {
+        self.stats.frames_received += 1;
+        let status = self
+            .watchdog
+            .as_mut()
+            .and_then(|watchdog| watchdog.feed(Instant::now()));
+        self.notify_state_change(status);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(message_type, length, "frame streamed");
+    }
+
+    /// Fails with [`io::ErrorKind::InvalidInput`] if frames cannot be streamed with the framing
+    /// or through the layers
+    fn check_streamable(&self) -> io::Result<()> {
+        if !self.framing.is_escaped() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} framing cannot be streamed", self.framing),
+            ));
+        }
+        if self.resync != Resync::StartByte {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frames cannot be streamed with {:?} resync", self.resync),
+            ));
+        }
+        if !self
+            .layers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_empty()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frames cannot be streamed through layers",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,41 @@
 use super::*;
-use crate::errors::{DecodeError, ReceiveError};
+use crate::codec::{
+    Endianness, Frame, Framing, ESCAPE_BYTE, MAX_COMPACT_PAYLOAD_LENGTH, START_BYTE, XOR_BYTE,
+};
+use crate::errors::{DecodeError, EncodeError, LimitViolation, ReceiveError, RegisterError};
 use crate::message_types;
-use crate::Message;
-use std::{os::unix::net::UnixStream, time::Duration};
+use crate::payload::{define_payload, Field, MessageType};
+use crate::schema::{
+    FieldDescriptor, FieldType, PayloadDescriptor, StructDescriptor, StructEncoding,
+};
+use crate::test_util::{stream_pair, TestStream};
+use crate::Coalescing;
+use crate::HalfDuplex;
+use crate::ReceiveClock;
+use crate::Stats;
+use crate::Varint;
+use crate::WakePreamble;
+use crate::{read_byte_log, ByteLog};
+use crate::{Capabilities, Message};
+use crate::{
+    LinkStatus, PingError, Priority, QueueSendError, TimeSync, TimeSyncError, Watchdog,
+    WatchdogError,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+// An application message type
+define_payload!(
+    struct Reading {
+        celsius: i16,
+    }
+);
+
+impl MessageType for Reading {
+    const ID: u16 = 0x0100;
+}
 
 #[allow(clippy::too_many_lines)]
 fn get_test_cases() -> Vec<(Message, Vec<u8>)> {
@@ -135,13 +168,283 @@ fn get_test_cases() -> Vec<(Message, Vec<u8>)> {
                 0x01, // Status::Error value
             ],
         ),
+        (
+            Message::U32(message_types::U32 { num: 0x1234_5678 }),
+            vec![
+                START_BYTE, // Start byte
+                0x06, 0x00, // Length (2 bytes for message type + 4 bytes data)
+                0x07, 0x00, // Message type (7)
+                0x78, 0x56, 0x34, 0x12, // The u32 value (little-endian)
+            ],
+        ),
+        (
+            Message::U64(message_types::U64 {
+                num: 0x5842_0000_0000_0001,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x0A,
+                0x00, // Length (2 bytes for message type + 8 bytes data)
+                0x08,
+                0x00, // Message type (8)
+                0x01,
+                0x00,
+                0x00,
+                0x00,
+                0x00,
+                0x00, // The u64 value (little-endian)
+                ESCAPE_BYTE,
+                ESCAPE_BYTE ^ XOR_BYTE, // Escaped 0x42
+                ESCAPE_BYTE,
+                START_BYTE ^ XOR_BYTE, // Escaped 0x58
+            ],
+        ),
+        (
+            Message::I8(message_types::I8 { num: -128 }),
+            vec![
+                START_BYTE, // Start byte
+                0x03, 0x00, // Length (2 bytes for message type + 1 byte data)
+                0x09, 0x00, // Message type (9)
+                0x80, // The i8 value
+            ],
+        ),
+        (
+            Message::I16(message_types::I16 { num: -2 }),
+            vec![
+                START_BYTE, // Start byte
+                0x04, 0x00, // Length (2 bytes for message type + 2 bytes data)
+                0x0A, 0x00, // Message type (10)
+                0xFE, 0xFF, // The i16 value (little-endian)
+            ],
+        ),
+        (
+            Message::I32(message_types::I32 { num: -1 }),
+            vec![
+                START_BYTE, // Start byte
+                0x06, 0x00, // Length (2 bytes for message type + 4 bytes data)
+                0x0B, 0x00, // Message type (11)
+                0xFF, 0xFF, 0xFF, 0xFF, // The i32 value (little-endian)
+            ],
+        ),
+        (
+            Message::I64(message_types::I64 { num: i64::MIN }),
+            vec![
+                START_BYTE, // Start byte
+                0x0A, 0x00, // Length (2 bytes for message type + 8 bytes data)
+                0x0C, 0x00, // Message type (12)
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x80, // The i64 value (little-endian)
+            ],
+        ),
+        (
+            Message::F32(message_types::F32 { num: 1.5 }),
+            vec![
+                START_BYTE, // Start byte
+                0x06, 0x00, // Length (2 bytes for message type + 4 bytes data)
+                0x0D, 0x00, // Message type (13)
+                0x00, 0x00, 0xC0, 0x3F, // The f32 value (little-endian)
+            ],
+        ),
+        (
+            Message::F64(message_types::F64 { num: -0.5 }),
+            vec![
+                START_BYTE, // Start byte
+                0x0A, 0x00, // Length (2 bytes for message type + 8 bytes data)
+                0x0E, 0x00, // Message type (14)
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0,
+                0xBF, // The f64 value (little-endian)
+            ],
+        ),
+        (
+            Message::Bool(message_types::Bool { value: true }),
+            vec![
+                START_BYTE, // Start byte
+                0x03, 0x00, // Length (2 bytes for message type + 1 byte data)
+                0x0F, 0x00, // Message type (15)
+                0x01, // true
+            ],
+        ),
+        (
+            Message::I16Array(message_types::I16Array {
+                values: vec![1, -2, 0x4258],
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x0A,
+                0x00, // Length (2 bytes for message type + 2 byte count + 3 * 2 bytes data)
+                0x11,
+                0x00, // Message type (17)
+                0x03,
+                0x00, // Element count
+                0x01,
+                0x00, // 1
+                0xFE,
+                0xFF,        // -2
+                ESCAPE_BYTE, // 0x4258 with both bytes escaped
+                START_BYTE ^ XOR_BYTE,
+                ESCAPE_BYTE,
+                ESCAPE_BYTE ^ XOR_BYTE,
+            ],
+        ),
+        (
+            Message::F32Array(message_types::F32Array { values: vec![] }),
+            vec![
+                START_BYTE, // Start byte
+                0x04, 0x00, // Length (2 bytes for message type + 2 byte count)
+                0x14, 0x00, // Message type (20)
+                0x00, 0x00, // Element count
+            ],
+        ),
+        (
+            Message::Settings(message_types::Settings {
+                baud_rate: Some(115_200),
+                retries: None,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x09, 0x00, // Length (2 bytes for message type + 7 byte entry)
+                0x16, 0x00, // Message type (22)
+                0x01, // Tag
+                0x04, 0x00, // Value length
+                0x00, 0xC2, 0x01, 0x00, // The u32 value
+            ],
+        ),
+        (
+            Message::Settings(message_types::Settings {
+                baud_rate: None,
+                retries: None,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x02, 0x00, // Length (2 bytes for message type)
+                0x16, 0x00, // Message type (22)
+            ],
+        ),
+        (
+            Message::DeviceInfo(message_types::DeviceInfo {
+                device_id: 7,
+                name: "a".to_string(),
+                hw_rev: 2,
+                fw_version: "1".to_string(),
+                capabilities: Capabilities::PING | Capabilities::JSON,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x1D, 0x00, // Length (2 bytes for message type + 27 bytes of entries)
+                0x27, 0x00, // Message type (39)
+                0x01, 0x04, 0x00, 0x07, 0x00, 0x00, 0x00, // device_id
+                0x02, 0x01, 0x00, 0x61, // name
+                0x03, 0x02, 0x00, 0x02, 0x00, // hw_rev
+                0x04, 0x01, 0x00, 0x31, // fw_version
+                0x05, 0x04, 0x00, 0x09, 0x00, 0x00, 0x00, // capabilities
+            ],
+        ),
+        (
+            Message::Counter(message_types::Counter { count: Varint(5) }),
+            vec![
+                START_BYTE, // Start byte
+                0x03, 0x00, // Length (2 bytes for message type + 1 byte varint)
+                0x17, 0x00, // Message type (23)
+                0x05, // The varint
+            ],
+        ),
+        (
+            Message::Counter(message_types::Counter { count: Varint(300) }),
+            vec![
+                START_BYTE, // Start byte
+                0x04, 0x00, // Length (2 bytes for message type + 2 byte varint)
+                0x17, 0x00, // Message type (23)
+                0xAC, 0x02, // The varint
+            ],
+        ),
+        (
+            Message::Timestamp(message_types::Timestamp {
+                micros: 1_700_000_000_000_000,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x0A, 0x00, // Length (2 bytes for message type + 8 bytes data)
+                0x18, 0x00, // Message type (24)
+                0x00, 0x40, 0x1E, 0x18, 0x24, 0x0A, 0x06, 0x00, // The u64 value
+            ],
+        ),
+        (
+            Message::Log(message_types::Log {
+                level: message_types::Level::Warn,
+                module: "adc".to_string(),
+                text: "hot".to_string(),
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x12, 0x00, // Length (2 bytes for message type + 16 bytes entries)
+                0x19, 0x00, // Message type (25)
+                0x01, 0x01, 0x00, 0x03, // Level
+                0x02, 0x03, 0x00, b'a', b'd', b'c', // Module
+                0x03, 0x03, 0x00, b'h', b'o', b't', // Text
+            ],
+        ),
+        (
+            Message::Command(message_types::Command {
+                id: 0x0102,
+                args: vec![0xAA, 0xBB],
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x06, 0x00, // Length (2 bytes for message type + 4 bytes data)
+                0x1A, 0x00, // Message type (26)
+                0x02, 0x01, // ID
+                0xAA, 0xBB, // Arguments
+            ],
+        ),
+        (
+            Message::Response(message_types::Response {
+                id: 0x0102,
+                status: message_types::Status::Error,
+                payload: vec![],
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x05, 0x00, // Length (2 bytes for message type + 3 bytes data)
+                0x1B, 0x00, // Message type (27)
+                0x02, 0x01, // ID
+                0x01, // Status
+            ],
+        ),
+        (
+            Message::ErrorReport(message_types::ErrorReport {
+                code: 0x0203,
+                detail: "busy".to_string(),
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x08, 0x00, // Length (2 bytes for message type + 6 bytes data)
+                0x1C, 0x00, // Message type (28)
+                0x03, 0x02, // Code
+                b'b', b'u', b's', b'y', // Detail
+            ],
+        ),
+        (
+            Message::TimeResponse(message_types::TimeResponse {
+                origin: 1,
+                receive: 0x0203,
+                transmit: 0x0405,
+            }),
+            vec![
+                START_BYTE, // Start byte
+                0x1A, 0x00, // Length (2 bytes for message type + 24 bytes data)
+                0x22, 0x00, // Message type (34)
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Origin
+                0x03, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Receive
+                0x05, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Transmit
+            ],
+        ),
     ]
 }
 
 #[test]
 fn test_send_raw_bytes() {
     for (message, expected_bytes) in get_test_cases() {
-        let (stream1, mut stream2) = UnixStream::pair().unwrap();
+        let (stream1, mut stream2) = stream_pair();
         let mut sender = SerialManager::new(stream1);
         sender.send(message).unwrap();
 
@@ -161,7 +464,7 @@ fn test_send_raw_bytes() {
 #[test]
 fn test_receive_raw_bytes() {
     for (expected_message, bytes_to_send) in get_test_cases() {
-        let (mut stream1, stream2) = UnixStream::pair().unwrap();
+        let (mut stream1, stream2) = stream_pair();
         let mut receiver = SerialManager::new(stream2);
 
         stream1.write_all(&bytes_to_send).unwrap();
@@ -173,245 +476,2967 @@ fn test_receive_raw_bytes() {
 }
 
 #[test]
-fn test_send_receive() {
-    let (stream1, stream2) = UnixStream::pair().unwrap();
+fn test_peek() {
+    let (stream1, stream2) = stream_pair();
     let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
+    let first = Message::U8(message_types::U8 { num: 0x57 });
+    let second = Message::NoOp(message_types::NoOp {});
+    sender
+        .send_all([first.clone(), second.clone(), first.clone()])
+        .unwrap();
 
-    // Get just the messages from test cases, discarding the raw bytes
-    let test_messages: Vec<Message> = get_test_cases().into_iter().map(|(msg, _)| msg).collect();
+    assert_eq!(receiver.peek().unwrap(), &first);
+    assert_eq!(receiver.peek().unwrap(), &first);
+    assert_eq!(receiver.receive().unwrap(), first);
 
-    for message in test_messages {
-        sender.send(message.clone()).unwrap();
-        let result = receiver.receive().unwrap();
-        assert_eq!(message, result);
-    }
+    assert_eq!(receiver.peek().unwrap(), &second);
+    assert_eq!(
+        receiver.receive_raw().unwrap(),
+        Frame {
+            message_type: 4,
+            payload: Vec::new(),
+        }
+    );
+    assert_eq!(receiver.receive().unwrap(), first);
+    assert_eq!(receiver.stats().frames_received, 3);
 }
 
 #[test]
-fn test_receive_with_garbage_prefix() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_drain() {
+    let (mut stream1, stream2) = stream_pair();
+    stream2.set_nonblocking(true).unwrap();
     let mut receiver = SerialManager::new(stream2);
+    assert!(receiver.drain().unwrap().is_empty());
 
-    // Some random bytes that aren't the start byte
-    let garbage = vec![0x00, 0xFF, 0x42, 0x13];
+    let u8_frame = [START_BYTE, 0x03, 0x00, 0x01, 0x00, 0x57];
+    let mut bytes = Vec::new();
+    for _ in 0..3 {
+        bytes.extend(u8_frame);
+    }
+    bytes.extend(&u8_frame[..4]);
+    stream1.write_all(&bytes).unwrap();
+    stream1.flush().unwrap();
 
-    // Take a simple message from our test cases
-    let (expected_message, message_bytes) = get_test_cases()[0].clone();
+    let message = Message::U8(message_types::U8 { num: 0x57 });
+    assert_eq!(receiver.drain().unwrap(), vec![message.clone(); 3]);
 
-    // Send garbage followed by actual message
-    stream1.write_all(&garbage).unwrap();
-    stream1.write_all(&message_bytes).unwrap();
+    // The partly received frame is completed by the next drain
+    stream1.write_all(&u8_frame[4..]).unwrap();
     stream1.flush().unwrap();
-
-    // Should still receive correct message
-    let received_message = receiver.receive().unwrap();
-    assert_eq!(received_message, expected_message);
+    assert_eq!(receiver.drain().unwrap(), vec![message]);
 }
 
 #[test]
-fn test_receive_multiple_packets() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_drain_keeps_error_for_next_receive() {
+    let (mut stream1, stream2) = stream_pair();
+    stream2.set_nonblocking(true).unwrap();
     let mut receiver = SerialManager::new(stream2);
 
-    // Take two different messages from our test cases
-    let (expected_message1, message_bytes1) = get_test_cases()[0].clone(); // NoOp
-    let (expected_message2, message_bytes2) = get_test_cases()[1].clone(); // U8
-
-    // Send both messages in a single write
-    let mut combined_bytes = Vec::new();
-    combined_bytes.extend(&message_bytes1);
-    combined_bytes.extend(&message_bytes2);
-    stream1.write_all(&combined_bytes).unwrap();
+    stream1
+        .write_all(&[START_BYTE, 0x03, 0x00, 0x01, 0x00, 0x57])
+        .unwrap();
+    // Invalid Status value
+    stream1
+        .write_all(&[START_BYTE, 0x03, 0x00, 0x06, 0x00, 0x03])
+        .unwrap();
     stream1.flush().unwrap();
 
-    // Should receive first message
-    let received_message1 = receiver.receive().unwrap();
-    assert_eq!(received_message1, expected_message1);
-
-    // Should receive second message
-    let received_message2 = receiver.receive().unwrap();
-    assert_eq!(received_message2, expected_message2);
+    assert_eq!(
+        receiver.drain().unwrap(),
+        vec![Message::U8(message_types::U8 { num: 0x57 })]
+    );
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidEnumValue(3),
+            ..
+        })
+    ));
+    assert!(receiver.drain().unwrap().is_empty());
 }
 
 #[test]
-fn test_receive_with_interleaved_garbage() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_send_receive_raw() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
 
-    // Take two different messages from our test cases
-    let (expected_message1, message_bytes1) = get_test_cases()[0].clone(); // NoOp
-    let (expected_message2, message_bytes2) = get_test_cases()[1].clone(); // U8
-
-    // Some random bytes that aren't the start byte
-    let garbage = vec![0x00, 0xFF, 0x42, 0x13, 0x37];
-
-    // Send first message, then garbage, then second message
-    let mut combined_bytes = Vec::new();
-    combined_bytes.extend(&message_bytes1);
-    combined_bytes.extend(&garbage);
-    combined_bytes.extend(&message_bytes2);
-    stream1.write_all(&combined_bytes).unwrap();
-    stream1.flush().unwrap();
-
-    // Should receive first message
-    let received_message1 = receiver.receive().unwrap();
-    assert_eq!(received_message1, expected_message1);
+    // Unregistered message types and payloads that don't match the registered type both pass
+    let frames = [
+        (0x1234, vec![START_BYTE, ESCAPE_BYTE]),
+        (1, vec![0x01, 0x02]),
+    ];
+    for (message_type, payload) in frames {
+        sender.send_raw(message_type, &payload).unwrap();
+        assert_eq!(
+            receiver.receive_raw().unwrap(),
+            Frame {
+                message_type,
+                payload
+            }
+        );
+    }
+    assert_eq!(receiver.stats().frames_received, 2);
 
-    // Should receive second message
-    let received_message2 = receiver.receive().unwrap();
-    assert_eq!(received_message2, expected_message2);
+    sender.send_raw(1, &[0x57]).unwrap();
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
 }
 
 #[test]
-fn test_receive_interrupted_message() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
-    let mut receiver = SerialManager::new(stream2);
+fn test_receive_lazy_uses_the_managers_byte_order() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    let mut receiver = SerialManager::new(stream2).with_endianness(Endianness::Big);
+    sender
+        .send(Message::Response(message_types::Response {
+            id: 0x0102,
+            status: message_types::Status::Pending,
+            payload: vec![9, 8],
+        }))
+        .unwrap();
 
-    // Take a Bytes message from our test cases that will be interrupted
-    let (_, message_bytes1) = get_test_cases()[2].clone(); // Bytes message
+    let message = receiver.receive_lazy().unwrap();
+    assert_eq!(message.field::<u16>("id").unwrap(), 0x0102);
+    assert_eq!(
+        message.field::<message_types::Status>("status").unwrap(),
+        message_types::Status::Pending
+    );
+    assert_eq!(message.bytes_field("payload").unwrap(), [9, 8]);
+}
 
-    // Take another message that will be received successfully
-    let (expected_message2, message_bytes2) = get_test_cases()[0].clone(); // NoOp message
+#[test]
+fn test_send_all() {
+    let (stream1, mut stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let observer = RecordingObserver::default();
+    sender.set_observer(observer.clone());
 
-    // Send the start byte, length, message type, and first few bytes of data
-    let data_start = 4; // Skip past start byte, length bytes, and message type bytes
-    let partial_length = data_start + 2; // Send a couple bytes of the actual data
-    stream1
-        .write_all(&message_bytes1[..partial_length])
+    let cases = get_test_cases();
+    sender
+        .send_all(cases.iter().map(|(message, _)| message.clone()))
         .unwrap();
 
-    // Send the complete second message
-    stream1.write_all(&message_bytes2).unwrap();
-    stream1.flush().unwrap();
+    let expected_bytes = cases.iter().flat_map(|(_, bytes)| bytes).copied();
+    let mut received_bytes = vec![0u8; expected_bytes.clone().count()];
+    stream2.read_exact(&mut received_bytes).unwrap();
+    assert!(received_bytes.into_iter().eq(expected_bytes));
 
-    // Should receive the second message correctly, first message should be discarded
-    let received_message = receiver.receive().unwrap();
-    assert_eq!(received_message, expected_message2);
+    assert_eq!(sender.stats().frames_sent, cases.len() as u64);
+    let events: Vec<_> = observer.events.lock().unwrap().drain(..).collect();
+    let expected_events: Vec<_> = cases
+        .into_iter()
+        .map(|(_, bytes)| ObservedEvent::Sent(bytes))
+        .collect();
+    assert_eq!(events, expected_events);
 }
 
 #[test]
-fn test_receive_interrupted_at_start() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_send_queued_by_priority() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
 
-    // Take a message that will be received successfully
-    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+    let message = |num| Message::U8(message_types::U8 { num });
+    sender.send_queued(message(1), Priority::Bulk);
+    sender.send_queued(message(2), Priority::Bulk);
+    sender.send_queued(message(3), Priority::Telemetry);
+    sender.send_queued(message(4), Priority::Control);
+    assert_eq!(sender.queued(), 4);
 
-    // Send just a start byte
-    stream1.write_all(&[START_BYTE]).unwrap();
+    // A control message queued part way through overtakes the remaining bulk data
+    assert!(sender.pump().unwrap());
+    sender.send_queued(message(5), Priority::Control);
+    sender.pump_all().unwrap();
+    assert!(!sender.pump().unwrap());
+    assert_eq!(sender.queued(), 0);
 
-    // Send the complete message
-    stream1.write_all(&message_bytes).unwrap();
-    stream1.flush().unwrap();
+    for num in [4, 5, 3, 1, 2] {
+        assert_eq!(receiver.receive().unwrap(), message(num));
+    }
+}
 
-    // Should receive the complete message correctly, ignoring the stray start byte
-    let received_message = receiver.receive().unwrap();
-    assert_eq!(received_message, expected_message);
+#[test]
+fn test_responses_inherit_the_priority_of_their_request() {
+    let (stream1, stream2) = stream_pair();
+    let mut host = SerialManager::new(stream1);
+    let mut device = SerialManager::new(stream2).with_request_priority(|command| {
+        if command.args.first() == Some(&0) {
+            Priority::Bulk
+        } else {
+            Priority::Control
+        }
+    });
+
+    let command = |id, args| Message::Command(message_types::Command { id, args });
+    let response = |id| {
+        Message::Response(message_types::Response {
+            id,
+            status: message_types::Status::Ok,
+            payload: vec![],
+        })
+    };
+    host.send(command(1, vec![1])).unwrap();
+    host.send(command(2, vec![0])).unwrap();
+    device.receive().unwrap();
+    device.receive().unwrap();
+    assert_eq!(device.request_priority(1), Some(Priority::Control));
+    assert_eq!(device.request_priority(2), Some(Priority::Bulk));
+    assert_eq!(device.request_priority(3), None);
+
+    // The urgent command's response overtakes telemetry queued before it
+    let telemetry = Message::U8(message_types::U8 { num: 7 });
+    device.send_queued(telemetry.clone(), Priority::Telemetry);
+    device.send_queued(response(2), Priority::Bulk);
+    device.send_queued(response(1), Priority::Bulk);
+    assert_eq!(device.request_priority(1), None);
+    device.pump_all().unwrap();
+    for message in [response(1), telemetry, response(2)] {
+        assert_eq!(host.receive().unwrap(), message);
+    }
 }
 
 #[test]
-fn test_receive_interrupted_length() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_coalescing_holds_back_frames_of_a_priority() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1)
+        .with_coalescing(Priority::Bulk, Coalescing::new(64, Duration::from_secs(30)));
     let mut receiver = SerialManager::new(stream2);
 
-    // Take a message that will be received successfully
-    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+    let message = |num| Message::U8(message_types::U8 { num });
+    sender
+        .send_with_priority(message(1), Priority::Bulk)
+        .unwrap();
+    sender.send_queued(message(2), Priority::Bulk);
+    sender.pump_all().unwrap();
+    assert_eq!(sender.stats().frames_sent, 0);
+    assert!(sender.flush_deadline().is_some());
 
-    // Send start byte and first byte of length
-    stream1.write_all(&[START_BYTE, 0x42]).unwrap(); // Random first byte of length
+    // An urgent frame is written at once, after the frames held back before it
+    sender
+        .send_with_priority(message(3), Priority::Control)
+        .unwrap();
+    assert_eq!(sender.stats().frames_sent, 3);
+    assert_eq!(sender.flush_deadline(), None);
 
-    // Send the complete message
-    stream1.write_all(&message_bytes).unwrap();
-    stream1.flush().unwrap();
+    sender
+        .send_with_priority(message(4), Priority::Bulk)
+        .unwrap();
+    sender.flush().unwrap();
+    assert_eq!(sender.stats().frames_sent, 4);
+    for num in 1..=4 {
+        assert_eq!(receiver.receive().unwrap(), message(num));
+    }
+}
 
-    // Should receive the complete message correctly, ignoring the interrupted message
-    let received_message = receiver.receive().unwrap();
-    assert_eq!(received_message, expected_message);
+#[test]
+fn test_coalesced_frames_are_written_by_size_or_age() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1)
+        .with_coalescing(Priority::Bulk, Coalescing::new(12, Duration::from_secs(30)))
+        .with_coalescing(
+            Priority::Telemetry,
+            Coalescing::new(1024, Duration::from_millis(10)),
+        );
+    let mut receiver = SerialManager::new(stream2);
+
+    // Two 6 byte frames fill the byte limit
+    let message = |num| Message::U8(message_types::U8 { num });
+    sender
+        .send_with_priority(message(1), Priority::Bulk)
+        .unwrap();
+    assert_eq!(sender.stats().frames_sent, 0);
+    sender
+        .send_with_priority(message(2), Priority::Bulk)
+        .unwrap();
+    assert_eq!(sender.stats().frames_sent, 2);
+
+    sender
+        .send_with_priority(message(3), Priority::Telemetry)
+        .unwrap();
+    assert!(!sender.pump().unwrap());
+    assert_eq!(sender.stats().frames_sent, 2);
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!sender.pump().unwrap());
+    assert_eq!(sender.stats().frames_sent, 3);
+
+    for num in 1..=3 {
+        assert_eq!(receiver.receive().unwrap(), message(num));
+    }
+}
+
+/// A ring buffer looping written bytes back to be read, which implements only [`Transport`]
+#[derive(Default)]
+struct RingBuffer {
+    bytes: VecDeque<u8>,
+    read_timeout: Option<Duration>,
+}
+
+impl Transport for RingBuffer {
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.bytes.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let count = buffer.len().min(self.bytes.len());
+        for (byte, read) in buffer.iter_mut().zip(self.bytes.drain(..count)) {
+            *byte = read;
+        }
+        Ok(count)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.bytes.extend(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
 }
 
 #[test]
-fn test_receive_interrupted_message_type() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_transport_without_std_io() {
+    let mut manager = SerialManager::new(RingBuffer::default());
+    let message = Message::U16(message_types::U16 { num: 0x1234 });
+    manager.send(message.clone()).unwrap();
+    assert_eq!(manager.receive().unwrap(), message);
+    assert!(matches!(
+        manager.receive(),
+        Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock
+    ));
+    let error = manager.receive().unwrap_err();
+    assert!(error.is_timeout());
+    assert_eq!(error.io_error().unwrap().kind(), io::ErrorKind::WouldBlock);
+
+    manager
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    assert_eq!(
+        manager.connection.read_timeout,
+        Some(Duration::from_millis(5))
+    );
+    let (stream, _) = stream_pair();
+    let error = SerialManager::new(stream)
+        .set_read_timeout(None)
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn test_close_sends_goodbye() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
 
-    // Take a message that will be received successfully
-    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+    let message = Message::U8(message_types::U8 { num: 1 });
+    sender.send_queued(message.clone(), Priority::Bulk);
+    sender.close().unwrap();
 
-    // Send start byte, length bytes, and first byte of message type
-    stream1
-        .write_all(&[
-            START_BYTE, 0x02, 0x00, // Length bytes
-            0xFF, // First byte of message type
-        ])
+    // Queued messages are sent first, and an orderly close is told apart from the connection
+    // dropping
+    assert_eq!(receiver.receive().unwrap(), message);
+    assert!(matches!(receiver.receive(), Err(ReceiveError::PeerClosed)));
+    assert_eq!(receiver.stats().frames_received, 2);
+    assert!(matches!(receiver.receive(), Err(ReceiveError::Io(_))));
+}
+
+/// A write or flush of a [`Transport`] that records them
+#[derive(Debug, PartialEq)]
+enum TransportOp {
+    Write(Vec<u8>),
+    Flush,
+}
+
+/// Records the writes and flushes made through it, and has nothing to read
+struct RecordingTransport(Arc<Mutex<Vec<TransportOp>>>);
+
+impl Transport for RecordingTransport {
+    fn read_bytes(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .push(TransportOp::Write(bytes.to_vec()));
+        Ok(bytes.len())
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().push(TransportOp::Flush);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_close_flushes_after_goodbye() {
+    let ops = Arc::new(Mutex::new(Vec::new()));
+    let mut manager = SerialManager::new(RecordingTransport(ops.clone()));
+
+    let message = Message::U8(message_types::U8 { num: 1 });
+    manager.send_queued(message.clone(), Priority::Bulk);
+    manager.close().unwrap();
+
+    let goodbye = Message::Goodbye(message_types::Goodbye {});
+    let ops = ops.lock().unwrap();
+    assert_eq!(
+        *ops,
+        [
+            TransportOp::Write(Framing::Native.encode(
+                1,
+                &message.to_bytes().unwrap(),
+                Endianness::Little
+            )),
+            TransportOp::Flush,
+            TransportOp::Write(Framing::Native.encode(
+                goodbye.message_type(),
+                &goodbye.to_bytes().unwrap(),
+                Endianness::Little
+            )),
+            TransportOp::Flush,
+        ]
+    );
+}
+
+#[test]
+fn test_spawn() {
+    let (stream1, stream2) = stream_pair();
+    let (sender, _) = SerialManager::new(stream1).spawn().unwrap();
+    let (_, receiver) = SerialManager::new(stream2).spawn().unwrap();
+
+    let messages: Vec<Message> = get_test_cases().into_iter().map(|(msg, _)| msg).collect();
+    for message in &messages {
+        sender.send(message.clone()).unwrap();
+    }
+    for message in messages {
+        assert_eq!(receiver.recv().unwrap().unwrap(), message);
+    }
+}
+
+#[test]
+fn test_spawn_keeps_framing() {
+    let (stream1, stream2) = stream_pair();
+    let (sender, _) = SerialManager::new(stream1)
+        .with_framing(Framing::Cobs)
+        .spawn()
         .unwrap();
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Cobs);
 
-    // Send the complete message
-    stream1.write_all(&message_bytes).unwrap();
-    stream1.flush().unwrap();
+    let message = Message::U8(message_types::U8 { num: 0x58 });
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+}
 
-    // Should receive the complete message correctly, ignoring the interrupted message
-    let received_message = receiver.receive().unwrap();
-    assert_eq!(received_message, expected_message);
+#[test]
+fn test_shared_serial_manager() {
+    fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<SharedSerialManager<std::os::unix::net::UnixStream>>();
+
+    let (stream1, stream2) = stream_pair();
+    let shared = SerialManager::new(stream1).into_shared().unwrap();
+    let mut peer = SerialManager::new(stream2);
+
+    // A thread blocked receiving does not hold up the threads sending
+    let receiving = {
+        let shared = shared.clone();
+        std::thread::spawn(move || shared.receive().unwrap())
+    };
+    let senders: Vec<_> = (0..4)
+        .map(|num| {
+            let sender = shared.sender();
+            std::thread::spawn(move || {
+                sender.send(Message::U8(message_types::U8 { num })).unwrap();
+            })
+        })
+        .collect();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    let mut received: Vec<Message> = (0..4).map(|_| peer.receive().unwrap()).collect();
+    received.sort_by_key(|message| match message {
+        Message::U8(message_types::U8 { num }) => *num,
+        _ => u8::MAX,
+    });
+    let expected: Vec<Message> = (0..4)
+        .map(|num| Message::U8(message_types::U8 { num }))
+        .collect();
+    assert_eq!(received, expected);
+
+    let reply = Message::U8(message_types::U8 { num: 9 });
+    peer.send(reply.clone()).unwrap();
+    assert_eq!(receiving.join().unwrap(), reply);
+    let stats = shared.stats();
+    assert_eq!((stats.frames_sent, stats.frames_received), (4, 1));
 }
 
 #[test]
-fn test_receive_invalid_message_type() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_cancel_receive() {
+    let (stream1, stream2) = stream_pair();
+    stream2
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
+    let token = receiver.cancel_token();
 
-    let invalid_message = vec![
-        START_BYTE, // Start byte
-        0x02, 0x00, // Length (2 bytes for message type)
-        0xFF, 0x00, // Invalid message type (0xFF)
-    ];
+    let receiving = std::thread::spawn(move || loop {
+        match receiver.receive() {
+            Err(ReceiveError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            result => return (receiver, result),
+        }
+    });
+    std::thread::sleep(Duration::from_millis(20));
+    token.cancel();
+    let (mut receiver, result) = receiving.join().unwrap();
+    assert!(matches!(result, Err(ReceiveError::Cancelled)));
 
-    stream1.write_all(&invalid_message).unwrap();
-    stream1.flush().unwrap();
+    // Receives fail until the token is reset, even with bytes waiting
+    let message = Message::U8(message_types::U8 { num: 1 });
+    sender.send(message.clone()).unwrap();
+    assert!(matches!(receiver.receive(), Err(ReceiveError::Cancelled)));
+    token.reset();
+    assert_eq!(receiver.receive().unwrap(), message);
+}
+
+#[test]
+fn test_spawn_stops_when_cancelled() {
+    let (_stream1, stream2) = stream_pair();
+    let manager = SerialManager::new(stream2);
+    manager.cancel_token().cancel();
+    let (_, receiver) = manager.spawn().unwrap();
 
     assert!(matches!(
-        receiver.receive(),
-        Err(ReceiveError::Decode(DecodeError::InvalidMessageType(0xFF)))
+        receiver.recv().unwrap(),
+        Err(ReceiveError::Cancelled)
     ));
+    assert!(receiver.recv().is_err());
 }
 
 #[test]
-fn test_invalid_utf8() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_spawn_reports_closed_connection() {
+    let (stream1, stream2) = stream_pair();
+    let (_, receiver) = SerialManager::new(stream2).spawn().unwrap();
+    drop(stream1);
+
+    assert!(matches!(receiver.recv().unwrap(), Err(ReceiveError::Io(_))));
+    // The reader thread stops after an IO error
+    assert!(receiver.recv().is_err());
+}
+
+#[test]
+fn test_spawn_bounded() {
+    let (stream1, stream2) = stream_pair();
+    let (sender, _) = SerialManager::new(stream1)
+        .spawn_bounded(SendQueue::new(4))
+        .unwrap();
+    let (_, receiver) = SerialManager::new(stream2).spawn().unwrap();
+
+    let messages: Vec<Message> = get_test_cases().into_iter().map(|(msg, _)| msg).collect();
+    for message in &messages {
+        sender.send(message.clone()).unwrap();
+    }
+    assert_eq!(sender.capacity(), 4);
+    for message in messages {
+        assert_eq!(receiver.recv().unwrap().unwrap(), message);
+    }
+    assert_eq!(sender.depth(), 0);
+}
+
+#[test]
+fn test_send_queue_backpressure() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let (high, low) = (Arc::clone(&events), Arc::clone(&events));
+    let queue = SendQueue::new(3)
+        .with_high_water(2, move |depth| high.lock().unwrap().push(("high", depth)))
+        .with_low_water(0, move |depth| low.lock().unwrap().push(("low", depth)));
+    let (sender, receiver) = send_queue::send_queue(queue);
+    let message = |num| Message::U8(message_types::U8 { num });
+
+    for num in 0..3 {
+        sender.try_send(message(num)).unwrap();
+    }
+    assert_eq!(sender.depth(), 3);
+    assert!(matches!(
+        sender.try_send(message(3)),
+        Err(QueueSendError::Full(_))
+    ));
+    let start = std::time::Instant::now();
+    let error = sender
+        .send_timeout(message(3), Duration::from_millis(20))
+        .unwrap_err();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(matches!(error, QueueSendError::Timeout(_)));
+    assert_eq!(error.into_message(), message(3));
+
+    // Taking a message makes space for a blocked send
+    let blocked = {
+        let sender = sender.clone();
+        std::thread::spawn(move || sender.send(message(3)))
+    };
+    assert_eq!(receiver.recv(), Some(message(0)));
+    blocked.join().unwrap().unwrap();
+    for num in 1..4 {
+        assert_eq!(receiver.recv(), Some(message(num)));
+    }
+    assert_eq!(*events.lock().unwrap(), [("high", 2), ("low", 0)]);
+
+    // The writer stopping is reported to senders
+    drop(receiver);
+    assert!(matches!(
+        sender.send(message(4)),
+        Err(QueueSendError::Disconnected(_))
+    ));
+}
+
+#[test]
+fn test_send_queue_stops_writer_when_senders_dropped() {
+    let (sender, receiver) = send_queue::send_queue(SendQueue::new(2));
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    drop(sender);
+    // Queued messages are still written before the writer stops
+    assert!(receiver.recv().is_some());
+    assert!(receiver.recv().is_none());
+}
+
+#[test]
+fn test_roundtrip() {
+    for (message, _) in get_test_cases() {
+        assert_eq!(crate::roundtrip(message.clone()).unwrap(), message);
+    }
+    let unknown = Message::Unknown {
+        message_type: 1,
+        data: vec![0x01, 0x02],
+    };
+    assert!(matches!(
+        crate::roundtrip(unknown),
+        Err(DecodeError::InvalidLength(4))
+    ));
+}
+
+#[test]
+fn test_send_receive() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
     let mut receiver = SerialManager::new(stream2);
 
-    let invalid_string_message = vec![
-        START_BYTE, 0x04, 0x00, // Length (2 bytes type + 2 bytes data)
-        0x02, 0x00, // Message type (2 = MyString)
-        0xFF, 0xFF, // Invalid UTF-8 bytes
-    ];
-    stream1.write_all(&invalid_string_message).unwrap();
-    stream1.flush().unwrap();
+    // Get just the messages from test cases, discarding the raw bytes
+    let test_messages: Vec<Message> = get_test_cases().into_iter().map(|(msg, _)| msg).collect();
+
+    for message in test_messages {
+        sender.send(message.clone()).unwrap();
+        let result = receiver.receive().unwrap();
+        assert_eq!(message, result);
+    }
+}
+
+#[test]
+fn test_big_endian() {
+    let (stream1, mut stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    sender
+        .send(Message::U32(message_types::U32 { num: 0x0102_0304 }))
+        .unwrap();
+
+    let mut bytes = [0u8; 9];
+    stream2.read_exact(&mut bytes).unwrap();
+    assert_eq!(
+        bytes,
+        [START_BYTE, 0x00, 0x06, 0x00, 0x07, 0x01, 0x02, 0x03, 0x04]
+    );
+
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    let mut receiver = SerialManager::new(stream2).with_endianness(Endianness::Big);
+    for (message, _) in get_test_cases() {
+        sender.send(message.clone()).unwrap();
+        assert_eq!(receiver.receive().unwrap(), message);
+    }
+}
+
+#[test]
+fn test_receive_timestamped() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    let (message, _) = get_test_cases()[1].clone();
+    let before = SystemTime::now();
+    sender.send(message.clone()).unwrap();
+    let (result, received_at) = receiver.receive_timestamped().unwrap();
+    assert_eq!(result, message);
+    assert!(before <= received_at && received_at <= SystemTime::now());
+}
+
+#[test]
+fn test_receive_clock() {
+    let (stream1, stream2) = stream_pair();
+    let clock = ReceiveClock::new();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2).with_receive_clock(clock.clone());
+
+    let (message, _) = get_test_cases()[1].clone();
+    let recorded = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    sender.send(message.clone()).unwrap();
+    clock.record(recorded);
+    assert_eq!(
+        receiver.receive_timestamped().unwrap(),
+        (message.clone(), recorded)
+    );
+
+    // A read with no recorded time falls back to the local time
+    let before = SystemTime::now();
+    sender.send(message.clone()).unwrap();
+    let (result, received_at) = receiver.receive_timestamped().unwrap();
+    assert_eq!(result, message);
+    assert!(before <= received_at);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_kernel_timestamps() {
+    use crate::KernelTimestamps;
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let socket = KernelTimestamps::new(listener.accept().unwrap().0).unwrap();
+    let clock = socket.clock();
+    let mut sender = SerialManager::new(stream);
+    let mut receiver = SerialManager::new(socket).with_receive_clock(clock);
+
+    // The kernel starts timestamping packets shortly after timestamps are first enabled
+    let (message, _) = get_test_cases()[1].clone();
+    for _ in 0..20 {
+        let before = SystemTime::now();
+        sender.send(message.clone()).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        let read = SystemTime::now();
+        let (result, received_at) = receiver.receive_timestamped().unwrap();
+        assert_eq!(result, message);
+        assert!(before <= received_at);
+        // The kernel received the frame before it was read, not when it was
+        if received_at < read {
+            return;
+        }
+    }
+    panic!("no frame was timestamped by the kernel");
+}
+
+#[test]
+fn test_response_error_report() {
+    let report = message_types::ErrorReport {
+        code: 7,
+        detail: "no such command".to_string(),
+    };
+    let response = message_types::Response::error(3, &report, Endianness::Little);
+    assert_eq!(response.status, message_types::Status::Error);
+    assert_eq!(response.payload[..2], [7, 0]);
+    assert_eq!(
+        response.error_report(Endianness::Little).unwrap().unwrap(),
+        report
+    );
+
+    // Over a big-endian link
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    let mut receiver = SerialManager::new(stream2).with_endianness(Endianness::Big);
+    let response = message_types::Response::error(3, &report, sender.endianness());
+    assert_eq!(response.payload[..2], [0, 7]);
+    sender.send(Message::Response(response)).unwrap();
+    let Message::Response(response) = receiver.receive().unwrap() else {
+        panic!("expected a response");
+    };
+    assert_eq!(
+        response
+            .error_report(receiver.endianness())
+            .unwrap()
+            .unwrap(),
+        report
+    );
+
+    let response = message_types::Response {
+        id: 3,
+        status: message_types::Status::Ok,
+        payload: vec![1, 2],
+    };
+    assert!(response.error_report(Endianness::Little).is_none());
+}
+
+#[test]
+fn test_time_sync_from_timestamps() {
+    // The peer is 1000us ahead, each direction takes 50us and the peer takes 20us to reply
+    let sync = TimeSync::from_timestamps(10_000, 11_050, 11_070, 10_120);
+    assert_eq!(
+        sync,
+        TimeSync {
+            offset_micros: 1000,
+            round_trip: Duration::from_micros(100),
+        }
+    );
+}
+
+#[test]
+fn test_sync_time() {
+    let (stream1, stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+    // A peer whose clock is five seconds ahead
+    let peer = std::thread::spawn(move || {
+        let mut peer = SerialManager::new(stream2);
+        let Message::TimeRequest(request) = peer.receive().unwrap() else {
+            panic!("expected a time request");
+        };
+        let now = crate::time_sync::now_micros() + 5_000_000;
+        peer.send(Message::TimeResponse(message_types::TimeResponse {
+            origin: request.origin,
+            receive: now,
+            transmit: now,
+        }))
+        .unwrap();
+    });
 
+    let sync = manager.sync_time().unwrap();
+    peer.join().unwrap();
+    assert!((sync.offset_micros - 5_000_000).abs() < 100_000);
+    assert!(sync.round_trip < Duration::from_millis(100));
+}
+
+#[test]
+fn test_sync_time_unexpected_message() {
+    let (stream1, stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+    let mut peer = SerialManager::new(stream2);
+    peer.send(Message::NoOp(message_types::NoOp {})).unwrap();
     assert!(matches!(
-        receiver.receive(),
-        Err(ReceiveError::Decode(DecodeError::InvalidUtf8(_)))
+        manager.sync_time(),
+        Err(TimeSyncError::UnexpectedMessage(4))
     ));
 }
 
+/// Replies to `count` pings on the other end of the returned connection
+fn spawn_pong_peer(count: usize) -> (SerialManager<TestStream>, std::thread::JoinHandle<()>) {
+    let (stream1, stream2) = stream_pair();
+    let peer = std::thread::spawn(move || {
+        let mut peer = SerialManager::new(stream2);
+        for _ in 0..count {
+            let Message::Ping(request) = peer.receive().unwrap() else {
+                panic!("expected a ping");
+            };
+            let sequence = request.sequence;
+            peer.send(Message::Pong(message_types::Pong { sequence }))
+                .unwrap();
+        }
+    });
+    (SerialManager::new(stream1), peer)
+}
+
 #[test]
-fn test_invalid_enum_value() {
-    let (mut stream1, stream2) = UnixStream::pair().unwrap();
+fn test_ping() {
+    let (mut manager, peer) = spawn_pong_peer(2);
+    for _ in 0..2 {
+        let round_trip = manager.ping(Duration::from_secs(1)).unwrap();
+        assert!(round_trip < Duration::from_secs(1));
+    }
+    peer.join().unwrap();
+    let latency = manager.stats().latency;
+    assert_eq!(latency.samples, 2);
+    assert!(latency.min <= latency.max);
+    assert_eq!(latency.histogram.iter().sum::<u64>(), 2);
+}
+
+#[test]
+fn test_ping_timeout() {
+    let (stream1, _stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .unwrap();
+    let mut manager = SerialManager::new(stream1);
+
+    let start = std::time::Instant::now();
+    assert!(matches!(
+        manager.ping(Duration::from_millis(50)),
+        Err(PingError::Timeout)
+    ));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(manager.stats().latency.lost, 1);
+}
+
+#[test]
+fn test_ping_skips_late_replies() {
+    let (stream1, stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+    let mut peer = SerialManager::new(stream2);
+    // A reply to an earlier ping, followed by the expected one
+    peer.send(Message::Pong(message_types::Pong { sequence: 0 }))
+        .unwrap();
+    peer.send(Message::Pong(message_types::Pong { sequence: 1 }))
+        .unwrap();
+    manager.ping(Duration::from_secs(1)).unwrap();
+}
+
+#[test]
+fn test_receive_with_garbage_prefix() {
+    let (mut stream1, stream2) = stream_pair();
     let mut receiver = SerialManager::new(stream2);
 
-    // Create a message with an invalid Status enum value (3)
-    let invalid_message = vec![
-        START_BYTE, // Start byte
-        0x03, 0x00, // Length (2 bytes for message type + 1 byte for enum)
-        0x06, 0x00, // Message type (6 - Status)
-        0x03, // Invalid Status value
-    ];
+    // Some random bytes that aren't the start byte
+    let garbage = vec![0x00, 0xFF, 0x42, 0x13];
 
-    stream1.write_all(&invalid_message).unwrap();
+    // Take a simple message from our test cases
+    let (expected_message, message_bytes) = get_test_cases()[0].clone();
+
+    // Send garbage followed by actual message
+    stream1.write_all(&garbage).unwrap();
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should still receive correct message
+    let received_message = receiver.receive().unwrap();
+    assert_eq!(received_message, expected_message);
+    assert_eq!(receiver.stats().bytes_skipped, garbage.len() as u64);
+}
+
+#[test]
+fn test_strict_receive_with_garbage_prefix() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2).with_strict(true);
+
+    let (expected_message, message_bytes) = get_test_cases()[0].clone();
+    stream1.write_all(&[0x00, 0xFF, 0x42, 0x13]).unwrap();
+    stream1.write_all(&message_bytes).unwrap();
     stream1.flush().unwrap();
 
     assert!(matches!(
         receiver.receive(),
-        Err(ReceiveError::Decode(DecodeError::InvalidEnumValue(3)))
+        Err(ReceiveError::UnexpectedBytes(bytes)) if bytes == [0x00, 0xFF, 0x42, 0x13]
     ));
+    // The frame after the garbage is still received
+    assert_eq!(receiver.receive().unwrap(), expected_message);
+}
+
+#[test]
+fn test_receive_multiple_packets() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take two different messages from our test cases
+    let (expected_message1, message_bytes1) = get_test_cases()[0].clone(); // NoOp
+    let (expected_message2, message_bytes2) = get_test_cases()[1].clone(); // U8
+
+    // Send both messages in a single write
+    let mut combined_bytes = Vec::new();
+    combined_bytes.extend(&message_bytes1);
+    combined_bytes.extend(&message_bytes2);
+    stream1.write_all(&combined_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive first message
+    let received_message1 = receiver.receive().unwrap();
+    assert_eq!(received_message1, expected_message1);
+
+    // Should receive second message
+    let received_message2 = receiver.receive().unwrap();
+    assert_eq!(received_message2, expected_message2);
+}
+
+#[test]
+fn test_receive_with_interleaved_garbage() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take two different messages from our test cases
+    let (expected_message1, message_bytes1) = get_test_cases()[0].clone(); // NoOp
+    let (expected_message2, message_bytes2) = get_test_cases()[1].clone(); // U8
+
+    // Some random bytes that aren't the start byte
+    let garbage = vec![0x00, 0xFF, 0x42, 0x13, 0x37];
+
+    // Send first message, then garbage, then second message
+    let mut combined_bytes = Vec::new();
+    combined_bytes.extend(&message_bytes1);
+    combined_bytes.extend(&garbage);
+    combined_bytes.extend(&message_bytes2);
+    stream1.write_all(&combined_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive first message
+    let received_message1 = receiver.receive().unwrap();
+    assert_eq!(received_message1, expected_message1);
+
+    // Should receive second message
+    let received_message2 = receiver.receive().unwrap();
+    assert_eq!(received_message2, expected_message2);
+}
+
+#[test]
+fn test_receive_interrupted_message() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take a Bytes message from our test cases that will be interrupted
+    let (_, message_bytes1) = get_test_cases()[2].clone(); // Bytes message
+
+    // Take another message that will be received successfully
+    let (expected_message2, message_bytes2) = get_test_cases()[0].clone(); // NoOp message
+
+    // Send the start byte, length, message type, and first few bytes of data
+    let data_start = 4; // Skip past start byte, length bytes, and message type bytes
+    let partial_length = data_start + 2; // Send a couple bytes of the actual data
+    stream1
+        .write_all(&message_bytes1[..partial_length])
+        .unwrap();
+
+    // Send the complete second message
+    stream1.write_all(&message_bytes2).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive the second message correctly, first message should be discarded
+    let received_message = receiver.receive().unwrap();
+    assert_eq!(received_message, expected_message2);
+}
+
+#[test]
+fn test_receive_interrupted_at_start() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take a message that will be received successfully
+    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+
+    // Send just a start byte
+    stream1.write_all(&[START_BYTE]).unwrap();
+
+    // Send the complete message
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive the complete message correctly, ignoring the stray start byte
+    let received_message = receiver.receive().unwrap();
+    assert_eq!(received_message, expected_message);
+}
+
+#[test]
+fn test_receive_interrupted_length() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take a message that will be received successfully
+    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+
+    // Send start byte and first byte of length
+    stream1.write_all(&[START_BYTE, 0x42]).unwrap(); // Random first byte of length
+
+    // Send the complete message
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive the complete message correctly, ignoring the interrupted message
+    let received_message = receiver.receive().unwrap();
+    assert_eq!(received_message, expected_message);
+}
+
+#[test]
+fn test_receive_interrupted_message_type() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Take a message that will be received successfully
+    let (expected_message, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+
+    // Send start byte, length bytes, and first byte of message type
+    stream1
+        .write_all(&[
+            START_BYTE, 0x02, 0x00, // Length bytes
+            0xFF, // First byte of message type
+        ])
+        .unwrap();
+
+    // Send the complete message
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    // Should receive the complete message correctly, ignoring the interrupted message
+    let received_message = receiver.receive().unwrap();
+    assert_eq!(received_message, expected_message);
+}
+
+#[test]
+fn test_receive_invalid_message_type() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let invalid_message = vec![
+        START_BYTE, // Start byte
+        0x02, 0x00, // Length (2 bytes for message type)
+        0xFF, 0x00, // Invalid message type (0xFF)
+    ];
+
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidMessageType(0xFF),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_receive_invalid_length() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let mut bytes = vec![
+        START_BYTE, // Start byte
+        0x01, 0x00, // Length too short to hold the message type
+        0x01, 0x00, 0x57,
+    ];
+    bytes.extend([START_BYTE, 0x03, 0x00, 0x01, 0x00, 0x57]);
+    stream1.write_all(&bytes).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidLength(1),
+            ..
+        })
+    ));
+    // The decoder resyncs to the next frame
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eq!(receiver.stats().decode_errors, 1);
+}
+
+#[test]
+fn test_payload_length_mismatch() {
+    // A U16 message must have exactly 2 data bytes
+    for (length, data) in [(0x03, &[0x34][..]), (0x05, &[0x34, 0x12, 0x00])] {
+        let (mut stream1, stream2) = stream_pair();
+        let mut receiver = SerialManager::new(stream2);
+
+        let mut bytes = vec![START_BYTE, length, 0x00, 0x05, 0x00];
+        bytes.extend(data);
+        stream1.write_all(&bytes).unwrap();
+        stream1.flush().unwrap();
+
+        assert!(matches!(
+            receiver.receive(),
+            Err(ReceiveError::Decode { source: DecodeError::InvalidLength(l), .. }) if l == u16::from(length)
+        ));
+    }
+}
+
+#[test]
+fn test_receive_truncated_payload() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let truncated_message = vec![
+        START_BYTE, 0x02, 0x00, // Length (2 bytes for message type)
+        0x03, 0x00, // Message type (3 = Multi), missing its num
+    ];
+    stream1.write_all(&truncated_message).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::TruncatedPayload {
+                expected: 1,
+                actual: 0
+            },
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_receive_unknown_message_type() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2).with_unknown_messages(true);
+
+    let unknown = Message::Unknown {
+        message_type: 0x1234,
+        data: vec![START_BYTE, 0x01],
+    };
+    sender.send(unknown.clone()).unwrap();
+    sender
+        .send(Message::U8(message_types::U8 { num: 0x57 }))
+        .unwrap();
+
+    assert_eq!(receiver.receive().unwrap(), unknown);
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x57 })
+    );
+    assert_eq!(receiver.stats().decode_errors, 0);
+}
+
+#[test]
+fn test_user_message_types() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    assert_eq!(
+        receiver.register_message_type(0x00FF),
+        Err(RegisterError::Reserved(0x00FF))
+    );
+    receiver.register_message_type(0x0100).unwrap();
+    assert_eq!(
+        receiver.register_message_type(0x0100),
+        Err(RegisterError::AlreadyRegistered(0x0100))
+    );
+
+    sender.send_raw(0x0100, &[0x01]).unwrap();
+    sender.send_raw(0x0101, &[0x01]).unwrap();
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::Unknown {
+            message_type: 0x0100,
+            data: vec![0x01],
+        }
+    );
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidMessageType(0x0101),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_trailing_bytes() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2).with_trailing_bytes(true);
+
+    // A newer U16 with an extra byte, and a Multi whose string takes the rest of the payload
+    sender.send_raw(5, &[0x34, 0x12, 0x07]).unwrap();
+    sender.send_raw(3, &[0x01, b'a', b'b']).unwrap();
+
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U16(message_types::U16 { num: 0x1234 })
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::Multi(message_types::Multi {
+            num: 1,
+            string: "ab".to_string(),
+        })
+    );
+    assert_eq!(receiver.stats().trailing_bytes, 1);
+    assert_eq!(receiver.stats().decode_errors, 0);
+}
+
+#[test]
+fn test_invalid_utf8() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let invalid_string_message = vec![
+        START_BYTE, 0x04, 0x00, // Length (2 bytes type + 2 bytes data)
+        0x02, 0x00, // Message type (2 = MyString)
+        0xFF, 0xFF, // Invalid UTF-8 bytes
+    ];
+    stream1.write_all(&invalid_string_message).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidUtf8(_),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_skip_decode_errors() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2).with_decode_errors(DecodeErrorAction::Skip);
+    let skipped = Arc::new(Mutex::new(Vec::new()));
+    let handled = Arc::clone(&skipped);
+    receiver.set_decode_error_handler(move |error| {
+        let invalid_utf8 = matches!(error.decode_error(), Some(DecodeError::InvalidUtf8(_)));
+        handled.lock().unwrap().push(invalid_utf8);
+    });
+
+    let (expected_message, message_bytes) = get_test_cases()[1].clone();
+    let invalid_string_message = [START_BYTE, 0x04, 0x00, 0x02, 0x00, 0xFF, 0xFF];
+    for _ in 0..2 {
+        stream1.write_all(&invalid_string_message).unwrap();
+        stream1.write_all(&message_bytes).unwrap();
+    }
+    stream1.flush().unwrap();
+
+    // The frame after the invalid one is received
+    assert_eq!(receiver.receive().unwrap(), expected_message);
+    assert_eq!(*skipped.lock().unwrap(), [true]);
+    assert_eq!(receiver.stats().decode_errors, 1);
+
+    // receive_raw skips frames failing to decode too, which the payload of this one does not
+    receiver.clear_decode_error_handler();
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.payload, [0xFF, 0xFF]);
+    assert_eq!(receiver.receive().unwrap(), expected_message);
+    assert_eq!(skipped.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_invalid_enum_value() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    // Create a message with an invalid Status enum value (3)
+    let invalid_message = vec![
+        START_BYTE, // Start byte
+        0x03, 0x00, // Length (2 bytes for message type + 1 byte for enum)
+        0x06, 0x00, // Message type (6 - Status)
+        0x03, // Invalid Status value
+    ];
+
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidEnumValue(3),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_decode_error_context() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let invalid_message = vec![
+        START_BYTE, // Start byte
+        0x05,
+        0x00, // Length
+        0x1B,
+        0x00, // Message type (27 - Response)
+        ESCAPE_BYTE,
+        START_BYTE ^ XOR_BYTE,
+        0x00, // id (0x58, escaped)
+        0x07, // Invalid Status value
+    ];
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidEnumValue(7))
+    ));
+    assert_eq!(error.frame(), Some(invalid_message.as_slice()));
+    assert_eq!(error.offset(), Some(8));
+    assert!(error
+        .to_string()
+        .starts_with("Decode error: Invalid enum value: 7 at offset 8 in frame [58, 05, 00"));
+}
+
+#[test]
+fn test_invalid_length_context() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    stream1
+        .write_all(&[START_BYTE, 0x01, 0x00, 0x01, 0x00])
+        .unwrap();
+    stream1.flush().unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert_eq!(
+        error.frame(),
+        Some([START_BYTE, 0x01, 0x00, 0x01, 0x00].as_slice())
+    );
+    assert_eq!(error.offset(), Some(1));
+}
+
+#[test]
+fn test_invalid_bool() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let invalid_message = vec![
+        START_BYTE, // Start byte
+        0x03, 0x00, // Length (2 bytes for message type + 1 byte data)
+        0x0F, 0x00, // Message type (15 - Bool)
+        0x02, // Neither 0 nor 1
+    ];
+
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::InvalidBool(2),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_schema_matches_messages() {
+    for (message, _) in get_test_cases() {
+        let descriptor = crate::schema::messages()
+            .iter()
+            .find(|descriptor| descriptor.id == message.message_type())
+            .unwrap();
+        assert!(format!("{message:?}").starts_with(descriptor.name));
+    }
+}
+
+#[test]
+fn test_stats_send_receive() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    // Bytes message with three bytes that need escaping
+    let (message, message_bytes) = get_test_cases()[7].clone();
+    sender.send(message).unwrap();
+    receiver.receive().unwrap();
+
+    let expected = Stats {
+        frames_sent: 1,
+        bytes_sent: message_bytes.len() as u64,
+        escape_bytes_sent: 3,
+        ..Stats::default()
+    };
+    assert_eq!(sender.stats(), expected);
+
+    let expected = Stats {
+        frames_received: 1,
+        bytes_received: message_bytes.len() as u64,
+        escape_bytes_received: 3,
+        ..Stats::default()
+    };
+    assert_eq!(receiver.stats(), expected);
+
+    sender.reset_stats();
+    assert_eq!(sender.stats(), Stats::default());
+}
+
+#[test]
+fn test_receive_large_payloads() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    // Every byte value, so that runs with nothing to unescape end at escaped bytes
+    let data: Vec<u8> = (0..=u8::MAX).cycle().take(60_000).collect();
+    let message = Message::Bytes(message_types::Bytes { data: data.clone() });
+    let frame = crate::codec::encode_frame(message_types::U8::ID, &data);
+    let writer = std::thread::spawn({
+        let message = message.clone();
+        let data = data.clone();
+        move || {
+            sender.send(message).unwrap();
+            sender.send_raw(0x0101, &data).unwrap();
+            // Too long to be a U8
+            sender.send_raw(message_types::U8::ID, &data).unwrap();
+            sender.stats()
+        }
+    });
+
+    assert_eq!(receiver.receive().unwrap(), message);
+    let mut sink = Vec::new();
+    assert_eq!(
+        receiver.receive_stream(&mut sink).unwrap(),
+        (0x0101, data.len())
+    );
+    assert_eq!(sink, data);
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidLength(_))
+    ));
+    assert_eq!(error.frame(), Some(frame.as_slice()));
+
+    let stats = writer.join().unwrap();
+    assert_eq!(stats.bytes_sent, receiver.stats().bytes_received);
+    assert_eq!(
+        stats.escape_bytes_sent,
+        receiver.stats().escape_bytes_received
+    );
+}
+
+#[test]
+fn test_stats_resync_and_decode_error() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let (_, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+    let invalid_message = vec![
+        START_BYTE, // Start byte
+        0x02, 0x00, // Length (2 bytes for message type)
+        0xFF, 0x00, // Invalid message type (0xFF)
+    ];
+
+    // Interrupted frame followed by a valid one, then an undecodable one
+    stream1.write_all(&[START_BYTE, 0x02]).unwrap();
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    receiver.receive().unwrap();
+    assert!(receiver.receive().is_err());
+
+    let stats = receiver.stats();
+    assert_eq!(stats.frames_received, 1);
+    assert_eq!(stats.resyncs, 1);
+    assert_eq!(stats.decode_errors, 1);
+    assert_eq!(
+        stats.bytes_received,
+        (2 + message_bytes.len() + invalid_message.len()) as u64
+    );
+}
+
+#[derive(Debug, PartialEq)]
+enum ObservedEvent {
+    Sent(Vec<u8>),
+    Received(Vec<u8>),
+    Resync,
+    Skipped(usize),
+}
+
+#[derive(Clone, Default)]
+struct RecordingObserver {
+    events: Arc<Mutex<Vec<ObservedEvent>>>,
+}
+
+impl Observer for RecordingObserver {
+    fn on_raw_frame_sent(&mut self, frame: &[u8]) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(ObservedEvent::Sent(frame.to_vec()));
+    }
+
+    fn on_raw_frame_received(&mut self, frame: &[u8]) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(ObservedEvent::Received(frame.to_vec()));
+    }
+
+    fn on_resync(&mut self) {
+        self.events.lock().unwrap().push(ObservedEvent::Resync);
+    }
+
+    fn on_bytes_skipped(&mut self, count: usize) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(ObservedEvent::Skipped(count));
+    }
+}
+
+#[test]
+fn test_observer_sees_raw_frames() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    let observer = RecordingObserver::default();
+    sender.set_observer(observer.clone());
+    receiver.set_observer(observer.clone());
+
+    for (message, message_bytes) in get_test_cases() {
+        sender.send(message).unwrap();
+        receiver.receive().unwrap();
+
+        let events: Vec<_> = observer.events.lock().unwrap().drain(..).collect();
+        assert_eq!(
+            events,
+            vec![
+                ObservedEvent::Sent(message_bytes.clone()),
+                ObservedEvent::Received(message_bytes),
+            ]
+        );
+    }
+}
+
+#[test]
+fn test_observer_sees_resync() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2);
+
+    let observer = RecordingObserver::default();
+    receiver.set_observer(observer.clone());
+
+    let (_, message_bytes) = get_test_cases()[0].clone(); // NoOp message
+
+    // Garbage, an interrupted frame, then a complete frame
+    stream1.write_all(&[0x13, 0x37, START_BYTE, 0x02]).unwrap();
+    stream1.write_all(&message_bytes).unwrap();
+    stream1.flush().unwrap();
+
+    receiver.receive().unwrap();
+
+    let events: Vec<_> = observer.events.lock().unwrap().drain(..).collect();
+    assert_eq!(
+        events,
+        vec![
+            ObservedEvent::Skipped(2),
+            ObservedEvent::Resync,
+            ObservedEvent::Received(message_bytes)
+        ]
+    );
+}
+
+#[test]
+fn test_connect_tcp() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut manager = SerialManager::connect_tcp(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    let mut peer = SerialManager::new(stream);
+
+    let message = Message::U8(message_types::U8 { num: 0x57 });
+    manager.send(message.clone()).unwrap();
+    assert_eq!(peer.receive().unwrap(), message);
+}
+
+#[test]
+fn test_slip_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Slip);
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Slip);
+
+    let message = Message::Bytes(message_types::Bytes {
+        data: vec![0xC0, 0xDB, START_BYTE],
+    });
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+    assert_eq!(sender.stats().escape_bytes_sent, 2);
+    assert_eq!(receiver.stats().escape_bytes_received, 2);
+}
+
+#[test]
+fn test_slip_decode_error_context() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Slip);
+
+    let invalid_message = vec![
+        0x1B, 0x00, // Message type (27 - Response)
+        0xDB, 0xDC, 0x00, // id (0xC0, escaped)
+        0x07, // Invalid Status value
+        0xC0, // End
+    ];
+    stream1.write_all(&[0xC0]).unwrap();
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidEnumValue(7))
+    ));
+    assert_eq!(error.frame(), Some(invalid_message.as_slice()));
+    assert_eq!(error.offset(), Some(5));
+}
+
+#[test]
+fn test_cobs_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Cobs);
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Cobs);
+
+    let message = Message::Bytes(message_types::Bytes {
+        data: [START_BYTE, ESCAPE_BYTE].repeat(50),
+    });
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+    // The zeros of the message type become code bytes, so only the first is added
+    assert_eq!(sender.stats().escape_bytes_sent, 1);
+    assert_eq!(receiver.stats().escape_bytes_received, 1);
+}
+
+#[test]
+fn test_cobs_decode_error_context() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Cobs);
+
+    let invalid_message = vec![
+        0x02, 0x1B, // Message type (27 - Response)
+        0x02, 0x58, // id (0x0058)
+        0x02, 0x07, // Invalid Status value
+        0x00, // Delimiter
+    ];
+    stream1.write_all(&invalid_message).unwrap();
+    stream1.flush().unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidEnumValue(7))
+    ));
+    assert_eq!(error.frame(), Some(invalid_message.as_slice()));
+    assert_eq!(error.offset(), Some(5));
+}
+
+#[test]
+fn test_trailer() {
+    let (mut stream1, stream2) = stream_pair();
+    let mut receiver = SerialManager::new(stream2).with_trailer(0x0A);
+
+    // The length field is one too short, so the last payload byte is taken for the trailer
+    let truncated = [START_BYTE, 0x02, 0x00, 0x01, 0x00, 0x57, 0x0A];
+    stream1.write_all(&truncated).unwrap();
+    let mut sender = SerialManager::new(stream1).with_trailer(0x0A);
+    let message = Message::U8(message_types::U8 { num: 0x57 });
+    sender.send(message.clone()).unwrap();
+
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidTrailer(0x57))
+    ));
+    assert_eq!(error.offset(), Some(5));
+    assert_eq!(receiver.receive().unwrap(), message);
+    assert_eq!(sender.stats().escape_bytes_sent, 0);
+}
+
+#[test]
+fn test_padding() {
+    for framing in [
+        Framing::Native,
+        Framing::Slip,
+        Framing::Cobs,
+        Framing::Compact,
+    ] {
+        let (stream1, stream2) = stream_pair();
+        let mut sender = SerialManager::new(stream1)
+            .with_framing(framing)
+            .with_padding(16);
+        let mut receiver = SerialManager::new(stream2)
+            .with_framing(framing)
+            .with_padding(16)
+            .with_strict(true);
+
+        let message = |num| Message::U8(message_types::U8 { num });
+        sender.send(message(START_BYTE)).unwrap();
+        assert_eq!(sender.stats().bytes_sent, 16);
+        sender.send_all([message(0), message(1)]).unwrap();
+        assert_eq!(sender.stats().bytes_sent, 48);
+        // Padding is not counted as escaping
+        let (stream, _peer) = stream_pair();
+        let mut unpadded = SerialManager::new(stream).with_framing(framing);
+        unpadded.send(message(START_BYTE)).unwrap();
+        unpadded.send_all([message(0), message(1)]).unwrap();
+        assert_eq!(
+            sender.stats().escape_bytes_sent,
+            unpadded.stats().escape_bytes_sent
+        );
+
+        for num in [START_BYTE, 0, 1] {
+            assert_eq!(receiver.receive().unwrap(), message(num));
+        }
+        assert_eq!(receiver.stats().bytes_skipped, 0);
+    }
+}
+
+#[test]
+fn test_rate_limiter_paces_frames() {
+    let start = std::time::Instant::now();
+    let mut limiter = RateLimiter::new(RateLimit::new().with_frames_per_second(10));
+    assert_eq!(limiter.reserve(start, 100, 1), Duration::ZERO);
+    assert_eq!(limiter.reserve(start, 100, 1), Duration::from_millis(100));
+    assert_eq!(limiter.reserve(start, 100, 1), Duration::from_millis(200));
+    // After an idle period, the next frame goes straight out
+    let later = start + Duration::from_secs(1);
+    assert_eq!(limiter.reserve(later, 100, 1), Duration::ZERO);
+    assert_eq!(limiter.reserve(later, 100, 1), Duration::from_millis(100));
+}
+
+#[test]
+fn test_rate_limiter_bytes_and_burst() {
+    let start = std::time::Instant::now();
+    let limit = RateLimit::new()
+        .with_bytes_per_second(1000)
+        .with_burst_bytes(250);
+    let mut limiter = RateLimiter::new(limit);
+    // 100, 200 and 300 bytes into the burst, which may be overdrawn by one write
+    for _ in 0..3 {
+        assert_eq!(limiter.reserve(start, 100, 1), Duration::ZERO);
+    }
+    // The bucket is 50 bytes in debt, which takes 50 ms to repay
+    assert_eq!(limiter.reserve(start, 100, 1), Duration::from_millis(50));
+    assert_eq!(limiter.reserve(start, 100, 1), Duration::from_millis(150));
+}
+
+#[test]
+fn test_send_with_rate_limit() {
+    let (stream1, stream2) = stream_pair();
+    let limit = RateLimit::new().with_frames_per_second(100);
+    let mut sender = SerialManager::new(stream1).with_rate_limit(limit);
+    let mut receiver = SerialManager::new(stream2);
+
+    let start = std::time::Instant::now();
+    for num in 0..5 {
+        sender.send(Message::U8(message_types::U8 { num })).unwrap();
+    }
+    sender
+        .send_all((5..8).map(|num| Message::U8(message_types::U8 { num })))
+        .unwrap();
+    // The first frame goes straight out, and each write after it waits 10 ms for every frame before
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    for num in 0..8 {
+        assert_eq!(
+            receiver.receive().unwrap(),
+            Message::U8(message_types::U8 { num })
+        );
+    }
+}
+
+#[test]
+fn test_receive_limiter() {
+    let start = std::time::Instant::now();
+    let limit = ReceiveLimit::new()
+        .with_max_payload_length(4)
+        .with_frames_per_second(10)
+        .with_burst_frames(1);
+    let mut limiter = ReceiveLimiter::new(limit);
+    assert_eq!(
+        limiter.check(start, 5),
+        Err(LimitViolation::PayloadTooLong { length: 5, max: 4 })
+    );
+    // One frame at the rate and one of burst, and frames beyond them take no tokens
+    assert_eq!(limiter.check(start, 4), Ok(()));
+    assert_eq!(limiter.check(start, 4), Ok(()));
+    assert_eq!(
+        limiter.check(start, 4),
+        Err(LimitViolation::RateExceeded(10))
+    );
+    let later = start + Duration::from_millis(100);
+    assert_eq!(limiter.check(later, 4), Ok(()));
+    assert_eq!(
+        limiter.check(later, 4),
+        Err(LimitViolation::RateExceeded(10))
+    );
+}
+
+#[test]
+fn test_receive_limit() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let bytes_limit = ReceiveLimit::new()
+        .with_max_payload_length(8)
+        .with_frames_per_second(1);
+    let status_limit = ReceiveLimit::new()
+        .with_max_payload_length(0)
+        .with_action(LimitAction::Error);
+    let mut receiver = SerialManager::new(stream2)
+        .with_receive_limit(message_types::Bytes::ID, bytes_limit)
+        .with_receive_limit(message_types::Status::ID, status_limit);
+
+    let bytes = |length| {
+        Message::Bytes(message_types::Bytes {
+            data: vec![0; length],
+        })
+    };
+    // Too long, within the limits, then over the rate
+    sender.send(bytes(16)).unwrap();
+    sender.send(bytes(4)).unwrap();
+    sender.send(bytes(4)).unwrap();
+    sender
+        .send(Message::Status(message_types::Status::Ok))
+        .unwrap();
+    sender
+        .send(Message::U8(message_types::U8 { num: 1 }))
+        .unwrap();
+
+    assert_eq!(receiver.receive().unwrap(), bytes(4));
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::LimitExceeded {
+            message_type: message_types::Status::ID,
+            violation: LimitViolation::PayloadTooLong { length: 1, max: 0 },
+        })
+    ));
+    // Other message types are not limited
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 1 })
+    );
+    assert_eq!(receiver.stats().frames_limited, 2);
+}
+
+/// Receives until `duration` has passed, expecting every read to time out
+fn receive_timing_out(manager: &mut SerialManager<TestStream>, duration: Duration) {
+    let start = std::time::Instant::now();
+    while start.elapsed() < duration {
+        assert!(matches!(manager.receive(), Err(ReceiveError::Io(_))));
+    }
+}
+
+#[test]
+fn test_watchdog_reports_stalled_link() {
+    let (stream1, stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+    let handler_statuses = Arc::clone(&statuses);
+    let watchdog = Watchdog::new(Duration::from_millis(30))
+        .with_handler(move |status| handler_statuses.lock().unwrap().push(status));
+    let handle = watchdog.handle();
+    let mut manager = SerialManager::new(stream1).with_watchdog(watchdog);
+    let mut peer = SerialManager::new(stream2);
+
+    assert_eq!(handle.status(), LinkStatus::Up);
+    receive_timing_out(&mut manager, Duration::from_millis(50));
+    assert_eq!(*statuses.lock().unwrap(), [LinkStatus::Down]);
+    assert_eq!(handle.status(), LinkStatus::Down);
+    assert!(matches!(handle.check(), Err(WatchdogError::Stalled(quiet))
+        if quiet >= Duration::from_millis(50)));
+
+    peer.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    manager.receive().unwrap();
+    assert_eq!(
+        *statuses.lock().unwrap(),
+        [LinkStatus::Down, LinkStatus::Up]
+    );
+    assert_eq!(handle.status(), LinkStatus::Up);
+    assert!(handle.check().is_ok());
+    assert!(handle.since_last_frame() < Duration::from_millis(30));
+}
+
+#[test]
+fn test_watchdog_probes_quiet_link() {
+    let (stream1, stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let watchdog = Watchdog::new(Duration::from_secs(1)).with_probe(Duration::from_millis(20));
+    let mut manager = SerialManager::new(stream1).with_watchdog(watchdog);
+    let mut peer = SerialManager::new(stream2);
+
+    receive_timing_out(&mut manager, Duration::from_millis(45));
+    // One probe once the link has been quiet for the interval, and another an interval later
+    for _ in 0..2 {
+        let Message::Ping(ping) = peer.receive().unwrap() else {
+            panic!("expected a probe");
+        };
+        peer.send(Message::Pong(message_types::Pong {
+            sequence: ping.sequence,
+        }))
+        .unwrap();
+    }
+    assert!(matches!(manager.receive().unwrap(), Message::Pong(_)));
+}
+
+#[test]
+fn test_watchdog_challenges_are_answered() {
+    let (stream1, stream2) = stream_pair();
+    for stream in [&stream1, &stream2] {
+        stream
+            .set_read_timeout(Some(Duration::from_millis(5)))
+            .unwrap();
+    }
+    let watchdog = Watchdog::new(Duration::from_secs(1))
+        .with_challenge(Duration::from_millis(10), Duration::from_millis(500));
+    let mut manager = SerialManager::new(stream1).with_watchdog(watchdog);
+    let mut peer = SerialManager::new(stream2);
+
+    // The peer answers challenges as it receives, without them being delivered
+    let peer = std::thread::spawn(move || {
+        receive_timing_out(&mut peer, Duration::from_millis(100));
+        peer.stats().frames_sent
+    });
+    receive_timing_out(&mut manager, Duration::from_millis(60));
+    assert!(peer.join().unwrap() > 0);
+}
+
+#[test]
+fn test_watchdog_reports_peer_stuck_sending() {
+    let (stream1, mut stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let watchdog = Watchdog::new(Duration::from_secs(1))
+        .with_challenge(Duration::from_millis(10), Duration::from_millis(30));
+    let handle = watchdog.handle();
+    let mut manager = SerialManager::new(stream1).with_watchdog(watchdog);
+
+    // A peer in a boot loop, repeating the same frame and never reading
+    let frame = crate::codec::encode_frame(message_types::U8::ID, &[1]);
+    std::thread::spawn(move || {
+        while stream2.write_all(&frame).is_ok() {
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    });
+    let start = std::time::Instant::now();
+    loop {
+        match manager.receive() {
+            Ok(message) => assert_eq!(message, Message::U8(message_types::U8 { num: 1 })),
+            Err(ReceiveError::PeerUnresponsive) => break,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+    assert_eq!(handle.status(), LinkStatus::Up);
+}
+
+#[derive(Debug, PartialEq)]
+enum ManagerEvent {
+    Sent(u16, Vec<u8>),
+    Received(u16, Vec<u8>),
+    Error(String),
+    SendError,
+    StateChange(LinkStatus),
+    Idle,
+}
+
+#[derive(Clone, Default)]
+struct RecordingEvents {
+    events: Arc<Mutex<Vec<ManagerEvent>>>,
+}
+
+impl RecordingEvents {
+    fn take(&self) -> Vec<ManagerEvent> {
+        self.events.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl SerialManagerEvents for RecordingEvents {
+    fn on_send(&mut self, message_type: u16, payload: &[u8]) {
+        let event = ManagerEvent::Sent(message_type, payload.to_vec());
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_receive(&mut self, message_type: u16, payload: &[u8]) {
+        let event = ManagerEvent::Received(message_type, payload.to_vec());
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_error(&mut self, error: &ReceiveError) {
+        let event = ManagerEvent::Error(error.to_string());
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_send_error(&mut self, _error: &io::Error) {
+        self.events.lock().unwrap().push(ManagerEvent::SendError);
+    }
+
+    fn on_state_change(&mut self, status: LinkStatus) {
+        let event = ManagerEvent::StateChange(status);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn on_idle(&mut self) {
+        self.events.lock().unwrap().push(ManagerEvent::Idle);
+    }
+}
+
+#[test]
+fn test_events_see_traffic_and_errors() {
+    let (stream1, mut stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let events = RecordingEvents::default();
+    let mut manager = SerialManager::new(stream1);
+    manager.set_events(events.clone());
+
+    manager
+        .send(Message::U8(message_types::U8 { num: 7 }))
+        .unwrap();
+    manager
+        .send_all([Message::NoOp(message_types::NoOp {})])
+        .unwrap();
+    assert_eq!(
+        events.take(),
+        [
+            ManagerEvent::Sent(1, vec![7]),
+            ManagerEvent::Sent(4, vec![])
+        ]
+    );
+
+    // A U8 frame missing its payload, then a valid one
+    stream2
+        .write_all(&[START_BYTE, 0x02, 0x00, 0x01, 0x00])
+        .unwrap();
+    stream2
+        .write_all(&[START_BYTE, 0x03, 0x00, 0x01, 0x00, 0x09])
+        .unwrap();
+    assert!(manager.receive().is_err());
+    manager.receive().unwrap();
+    // Timeouts are not errors
+    assert!(manager.receive().is_err());
+    let received = events.take();
+    assert_eq!(received[0], ManagerEvent::Received(1, vec![]));
+    assert!(matches!(&received[1], ManagerEvent::Error(e) if e.starts_with("Decode error")));
+    assert_eq!(received[2..], [ManagerEvent::Received(1, vec![9])]);
+}
+
+#[test]
+fn test_events_see_send_errors_and_state_changes() {
+    let (stream1, stream2) = stream_pair();
+    stream1
+        .set_read_timeout(Some(Duration::from_millis(5)))
+        .unwrap();
+    let events = RecordingEvents::default();
+    let watchdog = Watchdog::new(Duration::from_millis(10));
+    let mut manager = SerialManager::new(stream1).with_watchdog(watchdog);
+    manager.set_events(events.clone());
+    let mut peer = SerialManager::new(stream2);
+
+    receive_timing_out(&mut manager, Duration::from_millis(20));
+    peer.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    manager.receive().unwrap();
+    assert_eq!(
+        events.take(),
+        [
+            ManagerEvent::StateChange(LinkStatus::Down),
+            ManagerEvent::StateChange(LinkStatus::Up),
+            ManagerEvent::Received(4, vec![]),
+        ]
+    );
+
+    drop(peer);
+    // Writes to a closed socket can succeed until the peer's reset arrives
+    while manager.send(Message::NoOp(message_types::NoOp {})).is_ok() {}
+    assert_eq!(events.take().last(), Some(&ManagerEvent::SendError));
+}
+
+/// Returns an empty directory for a test, removing anything left by an earlier run
+fn test_directory(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("gsp-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+fn log_files(directory: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut files: Vec<_> = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_byte_log_records_both_directions() {
+    let directory = test_directory("byte-log");
+    let (stream1, stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1).with_byte_log(ByteLog::new(&directory));
+    let mut peer = SerialManager::new(stream2);
+
+    manager
+        .send(Message::Ping(message_types::Ping { sequence: 7 }))
+        .unwrap();
+    peer.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    manager.receive().unwrap();
+
+    let files = log_files(&directory);
+    assert_eq!(files.len(), 1);
+    let log = std::fs::read_to_string(&files[0]).unwrap();
+    let lines: Vec<Vec<&str>> = log
+        .lines()
+        .map(|line| line.split(' ').skip(1).collect())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            vec!["TX", "58", "04", "00", "23", "00", "07", "00"],
+            vec!["RX", "58", "02", "00", "04", "00"],
+        ]
+    );
+    let captured = read_byte_log(log.as_bytes()).unwrap();
+    assert_eq!(
+        captured
+            .iter()
+            .map(|frame| (frame.direction, frame.data.len()))
+            .collect::<Vec<_>>(),
+        [(Some(Direction::Sent), 7), (Some(Direction::Received), 5)]
+    );
+    assert!(read_byte_log(&b"1718000000.1 TX 58"[..]).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_byte_log_rotates() {
+    let directory = test_directory("byte-log-rotate");
+    std::fs::create_dir_all(&directory).unwrap();
+    // Left by an earlier run, so the oldest and the first deleted
+    std::fs::write(directory.join("gsp-1.log"), "").unwrap();
+    std::fs::write(directory.join("other.log"), "").unwrap();
+
+    let log = ByteLog::new(&directory)
+        .with_max_file_size(100)
+        .with_max_files(3);
+    let (stream1, _stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1).with_byte_log(log);
+    for _ in 0..20 {
+        manager.send_raw(1, &[0; 8]).unwrap();
+    }
+
+    let files = log_files(&directory);
+    assert_eq!(files.len(), 4);
+    assert!(!files.contains(&directory.join("gsp-1.log")));
+    assert!(files.contains(&directory.join("other.log")));
+    for file in files {
+        assert!(std::fs::metadata(file).unwrap().len() <= 100);
+    }
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_compact_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Compact);
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Compact);
+
+    let message = Message::Ping(message_types::Ping { sequence: 7 });
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+    assert_eq!(sender.stats().bytes_sent, 5);
+
+    // Neither is sent
+    let error = sender.send_raw(0x0100, &[]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    let error = sender
+        .send_raw(1, &[0; MAX_COMPACT_PAYLOAD_LENGTH + 1])
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(sender.stats().frames_sent, 1);
+}
+
+#[test]
+fn test_extended_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Extended);
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Extended);
+
+    let payload = vec![0x11; 100_000];
+    let writer = std::thread::spawn(move || {
+        sender.send_raw(0x0101, &payload).unwrap();
+        sender.stats()
+    });
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x0101);
+    assert_eq!(frame.payload.len(), 100_000);
+    assert_eq!(writer.join().unwrap().bytes_sent, 1 + 6 + 100_000);
+}
+
+#[test]
+fn test_extended_framing_max_payload_length() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Extended);
+    let mut receiver = SerialManager::new(stream2)
+        .with_framing(Framing::Extended)
+        .with_max_payload_length(1024);
+
+    sender.send_raw(0x0101, &[0x11; 1025]).unwrap();
+    sender.send_raw(0x0102, &[0x22; 1024]).unwrap();
+    let error = receiver.receive_raw().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidLength(1027))
+    ));
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x0102);
+    assert_eq!(frame.payload.len(), 1024);
+    assert_eq!(receiver.stats().decode_errors, 1);
+}
+
+#[test]
+fn test_send_array_too_long() {
+    let (stream1, _stream2) = stream_pair();
+    // Extended frames could carry the payload, but the array count cannot
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Extended);
+
+    let message = Message::U16Array(message_types::U16Array {
+        values: vec![0; usize::from(u16::MAX) + 1],
+    });
+    let error = sender.send(message).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(sender.stats().frames_sent, 0);
+}
+
+#[test]
+fn test_stream_roundtrip() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1)
+        .with_framing(Framing::Extended)
+        .with_trailer(0x11);
+    let mut receiver = SerialManager::new(stream2)
+        .with_framing(Framing::Extended)
+        .with_trailer(0x11);
+
+    // Every byte value, so that some need escaping
+    let payload: Vec<u8> = (0..=u8::MAX).cycle().take(100_000).collect();
+    let expected = payload.clone();
+    let writer = std::thread::spawn(move || {
+        sender
+            .send_stream(0x0101, io::Cursor::new(payload), 100_000)
+            .unwrap();
+        sender.send_stream(0x0102, io::empty(), 0).unwrap();
+        sender
+            .send(Message::Ping(message_types::Ping { sequence: 7 }))
+            .unwrap();
+        sender.stats()
+    });
+
+    let mut sink = Vec::new();
+    assert_eq!(
+        receiver.receive_stream(&mut sink).unwrap(),
+        (0x0101, 100_000)
+    );
+    assert_eq!(sink, expected);
+    let mut sink = Vec::new();
+    assert_eq!(receiver.receive_stream(&mut sink).unwrap(), (0x0102, 0));
+    assert!(sink.is_empty());
+    // Frames sent either way can be received either way
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::Ping(message_types::Ping { sequence: 7 })
+    );
+
+    let stats = writer.join().unwrap();
+    assert_eq!(stats.frames_sent, 3);
+    assert_eq!(receiver.stats().frames_received, 3);
+    assert_eq!(stats.bytes_sent, receiver.stats().bytes_received);
+    assert_eq!(
+        stats.escape_bytes_sent,
+        receiver.stats().escape_bytes_received
+    );
+}
+
+#[test]
+fn test_stream_errors() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    // A payload shorter than its length, once part of it has been sent
+    let error = sender
+        .send_stream(1, io::Cursor::new([0x11; 5000]), 10_000)
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    sender.send_raw(1, &[0x22]).unwrap();
+    let mut sink = Vec::new();
+    let error = receiver.receive_stream(&mut sink).unwrap_err();
+    assert!(matches!(
+        error,
+        ReceiveError::Decode {
+            source: DecodeError::TruncatedPayload {
+                expected: 10_000,
+                actual: 5000
+            },
+            ..
+        }
+    ));
+    // The frame that interrupted it is received next
+    let mut sink = Vec::new();
+    assert_eq!(receiver.receive_stream(&mut sink).unwrap(), (1, 1));
+    assert_eq!(sink, [0x22]);
+    assert_eq!(receiver.stats().resyncs, 1);
+
+    let mut sender = sender.with_framing(Framing::Cobs);
+    let error = sender.send_stream(1, io::empty(), 0).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_half_duplex() {
+    let (stream1, stream2) = stream_pair();
+    let driver = Arc::new(Mutex::new(Vec::new()));
+    let half_duplex = HalfDuplex::new()
+        .with_turnaround(Duration::from_millis(50))
+        .with_driver_enable({
+            let driver = Arc::clone(&driver);
+            move |enabled| driver.lock().unwrap().push(enabled)
+        });
+    let mut manager = SerialManager::new(stream1).with_half_duplex(half_duplex);
+    let mut peer = SerialManager::new(stream2);
+
+    // Nothing has been received, so there is nothing to wait for
+    let started = Instant::now();
+    manager.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    assert!(started.elapsed() < Duration::from_millis(50));
+    assert_eq!(*driver.lock().unwrap(), [true, false]);
+    peer.receive().unwrap();
+
+    peer.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    manager.receive().unwrap();
+    let received = Instant::now();
+    manager
+        .send_all(vec![Message::NoOp(message_types::NoOp {}); 2])
+        .unwrap();
+    assert!(received.elapsed() >= Duration::from_millis(50));
+    // The driver is enabled once for both frames
+    assert_eq!(*driver.lock().unwrap(), [true, false, true, false]);
+    peer.receive().unwrap();
+    peer.receive().unwrap();
+}
+
+#[test]
+fn test_wake_preamble() {
+    let (stream1, mut stream2) = stream_pair();
+    let wake_preamble = WakePreamble::new(4).with_idle(Duration::from_millis(50));
+    let mut sender = SerialManager::new(stream1).with_wake_preamble(wake_preamble.clone());
+    let frame = crate::codec::encode_frame(4, &[]);
+    let mut read = |length| {
+        let mut bytes = vec![0; length];
+        stream2.read_exact(&mut bytes).unwrap();
+        bytes
+    };
+
+    // Nothing has been sent yet, so the peer may be asleep
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    assert_eq!(read(4), [0; 4]);
+    assert_eq!(read(frame.len()), frame);
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    assert_eq!(read(frame.len()), frame);
+    std::thread::sleep(Duration::from_millis(60));
+    sender.send(Message::NoOp(message_types::NoOp {})).unwrap();
+    assert_eq!(read(4), [0; 4]);
+    assert_eq!(read(frame.len()), frame);
+    assert_eq!(sender.stats().bytes_sent, 8 + 3 * frame.len() as u64);
+
+    // The receiver ignores the preamble, and reports the line going quiet once
+    let (stream1, stream2) = stream_pair();
+    stream2
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let mut sender = SerialManager::new(stream1).with_wake_preamble(wake_preamble.clone());
+    let mut receiver = SerialManager::new(stream2)
+        .with_strict(true)
+        .with_wake_preamble(wake_preamble);
+    let events = RecordingEvents::default();
+    receiver.set_events(events.clone());
+    sender
+        .send(Message::U8(message_types::U8 { num: 1 }))
+        .unwrap();
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 1 })
+    );
+    assert_eq!(receiver.stats().bytes_skipped, 0);
+    events.take();
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(150) {
+        assert!(receiver.receive().unwrap_err().is_timeout());
+    }
+    assert_eq!(events.take(), [ManagerEvent::Idle]);
+}
+
+#[test]
+fn test_binary_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_framing(Framing::Binary);
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Binary);
+
+    sender.send_raw(0x0101, &[START_BYTE; 100]).unwrap();
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.message_type, 0x0101);
+    assert_eq!(frame.payload, vec![START_BYTE; 100]);
+    let stats = sender.stats();
+    assert_eq!(stats.bytes_sent, 1 + 4 + 100 + 4);
+    assert_eq!(stats.escape_bytes_sent, 0);
+}
+
+#[test]
+fn test_binary_framing_checksum_mismatch() {
+    let mut frame = Framing::Binary.encode(1, &[0x01], Endianness::Little);
+    frame[5] ^= 0x01;
+    frame.extend(Framing::Binary.encode(1, &[0x02], Endianness::Little));
+    let (mut stream1, stream2) = stream_pair();
+    stream1.write_all(&frame).unwrap();
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::Binary);
+
+    assert!(matches!(
+        receiver.receive(),
+        Err(ReceiveError::Decode {
+            source: DecodeError::ChecksumMismatch { .. },
+            ..
+        })
+    ));
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x02 })
+    );
+    // Line noise is told apart from payloads that do not decode
+    assert_eq!(receiver.stats().crc_failures, 1);
+    assert_eq!(receiver.stats().decode_errors, 0);
+}
+
+#[test]
+fn test_checked_header_framing() {
+    let mut frame = Framing::CheckedHeader.encode(1, &[0x01], Endianness::Little);
+    frame[1] ^= 0x80;
+    frame.extend(Framing::CheckedHeader.encode(1, &[0x02], Endianness::Little));
+    let (mut stream1, stream2) = stream_pair();
+    stream1.write_all(&frame).unwrap();
+    let mut receiver = SerialManager::new(stream2).with_framing(Framing::CheckedHeader);
+
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::HeaderChecksumMismatch { .. })
+    ));
+    assert!(error.decode_error().unwrap().is_checksum_mismatch());
+    assert!(!error.is_timeout());
+    // The header CRC follows the length and message type
+    assert_eq!(error.offset(), Some(5));
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x02 })
+    );
+    assert_eq!(receiver.stats().crc_failures, 1);
+    assert_eq!(receiver.stats().decode_errors, 0);
+}
+
+#[test]
+fn test_checksum_resync_accepts_unescaped_start_bytes() {
+    use crate::layer::{Crc32, Layer};
+
+    // As sent by a peer that does not escape start bytes
+    let frame = Crc32.encode(Frame {
+        message_type: 0x0101,
+        payload: vec![START_BYTE; 8],
+    });
+    let mut bytes = vec![START_BYTE];
+    #[allow(clippy::cast_possible_truncation)]
+    bytes.extend((frame.payload.len() as u16 + 2).to_le_bytes());
+    bytes.extend(frame.message_type.to_le_bytes());
+    bytes.extend(&frame.payload);
+    let (mut stream1, stream2) = stream_pair();
+    stream1.write_all(&bytes).unwrap();
+    let mut receiver = SerialManager::new(stream2)
+        .with_resync(Resync::Checksum)
+        .with_layer(Crc32);
+
+    let frame = receiver.receive_raw().unwrap();
+    assert_eq!(frame.payload, vec![START_BYTE; 8]);
+    assert_eq!(receiver.stats().resyncs, 0);
+}
+
+#[test]
+fn test_inter_byte_timeout_discards_partial_frame() {
+    let (mut stream1, stream2) = stream_pair();
+    stream2
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    let mut receiver =
+        SerialManager::new(stream2).with_inter_byte_timeout(Duration::from_millis(10));
+
+    // The sender dies after the header of a frame
+    stream1
+        .write_all(&[START_BYTE, 0x05, 0x00, 0x01, 0x00])
+        .unwrap();
+    assert!(matches!(receiver.receive(), Err(ReceiveError::Io(_))));
+
+    stream1
+        .write_all(&Framing::Native.encode(1, &[0x07], Endianness::Little))
+        .unwrap();
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 0x07 })
+    );
+    assert_eq!(receiver.stats().resyncs, 1);
+}
+
+#[test]
+fn test_receive_matching_keeps_skipped_messages() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    sender
+        .send(Message::U8(message_types::U8 { num: 1 }))
+        .unwrap();
+    sender
+        .send(Message::U16(message_types::U16 { num: 2 }))
+        .unwrap();
+    sender
+        .send(Message::Status(message_types::Status::Ok))
+        .unwrap();
+    sender
+        .send(Message::U8(message_types::U8 { num: 3 }))
+        .unwrap();
+
+    assert_eq!(
+        receiver.receive_type::<message_types::Status>().unwrap(),
+        message_types::Status::Ok
+    );
+    assert_eq!(receiver.unmatched(), 2);
+    assert_eq!(
+        receiver
+            .receive_matching(|message| matches!(message, Message::U16(_)))
+            .unwrap(),
+        Message::U16(message_types::U16 { num: 2 })
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 1 })
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 3 })
+    );
+}
+
+#[test]
+fn test_receive_matching_passes_skipped_messages_to_handler() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    let skipped = Arc::new(Mutex::new(Vec::new()));
+    let handler_skipped = skipped.clone();
+    receiver.set_unmatched_handler(move |message| handler_skipped.lock().unwrap().push(message));
+
+    sender
+        .send(Message::U8(message_types::U8 { num: 1 }))
+        .unwrap();
+    sender
+        .send(Message::U16(message_types::U16 { num: 2 }))
+        .unwrap();
+
+    assert_eq!(
+        receiver.receive_type::<message_types::U16>().unwrap(),
+        message_types::U16 { num: 2 }
+    );
+    assert_eq!(receiver.unmatched(), 0);
+    assert_eq!(
+        *skipped.lock().unwrap(),
+        vec![Message::U8(message_types::U8 { num: 1 })]
+    );
+}
+
+#[test]
+fn test_receive_type_application_message() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+    receiver.register_message_type(Reading::ID).unwrap();
+
+    sender
+        .send(Message::U8(message_types::U8 { num: 1 }))
+        .unwrap();
+    sender.send_typed(&Reading { celsius: -4 }).unwrap();
+
+    assert_eq!(
+        receiver.receive_type::<Reading>().unwrap(),
+        Reading { celsius: -4 }
+    );
+    assert_eq!(
+        receiver.receive().unwrap(),
+        Message::U8(message_types::U8 { num: 1 })
+    );
+}
+
+#[test]
+fn test_typed_roundtrip() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    let mut receiver = SerialManager::new(stream2).with_endianness(Endianness::Big);
+
+    sender.send_typed(&message_types::U16 { num: 7 }).unwrap();
+    sender.send_typed(&message_types::Status::Pending).unwrap();
+
+    let payload: message_types::U16 = receiver.receive_typed().unwrap();
+    assert_eq!(payload, message_types::U16 { num: 7 });
+    match receiver.receive_typed::<message_types::U16>() {
+        Err(ReceiveTypedError::UnexpectedMessage(message)) => {
+            assert_eq!(message, Message::Status(message_types::Status::Pending));
+        }
+        result => panic!("unexpected result: {result:?}"),
+    }
+}
+
+#[test]
+fn test_receive_typed_application_message() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1).with_endianness(Endianness::Big);
+    let mut receiver = SerialManager::new(stream2).with_endianness(Endianness::Big);
+    receiver.register_message_type(Reading::ID).unwrap();
+
+    sender.send_typed(&Reading { celsius: 0x0102 }).unwrap();
+    sender
+        .send(Message::Unknown {
+            message_type: Reading::ID,
+            data: vec![0x01],
+        })
+        .unwrap();
+
+    let reading: Reading = receiver.receive_typed().unwrap();
+    assert_eq!(reading, Reading { celsius: 0x0102 });
+    // A payload that does not decode is handed back
+    match receiver.receive_typed::<Reading>() {
+        Err(ReceiveTypedError::UnexpectedMessage(message)) => assert_eq!(
+            message,
+            Message::Unknown {
+                message_type: Reading::ID,
+                data: vec![0x01],
+            }
+        ),
+        result => panic!("unexpected result: {result:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_passes_every_byte_value() {
+    let (master, slave) = crate::test_util::pty_pair();
+    let mut host = SerialManager::new(master);
+    let mut device = SerialManager::new(slave);
+
+    // Carriage returns, line feeds, XON/XOFF and interrupt characters would all be altered or
+    // swallowed by a terminal that is not in raw mode
+    let data: Vec<u8> = (0..=u8::MAX).collect();
+    let message = Message::Bytes(message_types::Bytes { data });
+    host.send(message.clone()).unwrap();
+    assert_eq!(device.receive().unwrap(), message);
+    device.send(message.clone()).unwrap();
+    assert_eq!(host.receive().unwrap(), message);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_partial_reads() {
+    let (mut master, slave) = crate::test_util::pty_pair();
+    let mut device = SerialManager::new(slave);
+    let message = Message::Bytes(message_types::Bytes {
+        data: vec![0x5A; 4096],
+    });
+    let frame = Framing::Native.encode(0, &message.clone().to_bytes().unwrap(), Endianness::Little);
+
+    // With VMIN at 1, each read returns whatever has arrived so far
+    let writer = std::thread::spawn(move || {
+        for chunk in frame.chunks(1000) {
+            master.write_all(chunk).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        master
+    });
+    assert_eq!(device.receive().unwrap(), message);
+    writer.join().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pty_read_timeout_keeps_partial_frame() {
+    let (mut master, slave) = crate::test_util::pty_pair();
+    crate::test_util::set_tty_timing(&slave, 0, 1);
+    let mut device = SerialManager::new(slave);
+    let message = Message::MyString(message_types::MyString {
+        string: "split".to_string(),
+    });
+    let frame = Framing::Native.encode(2, &message.clone().to_bytes().unwrap(), Endianness::Little);
+
+    // With VMIN at 0, a read returns nothing once VTIME passes without a byte arriving
+    master.write_all(&frame[..4]).unwrap();
+    assert!(matches!(device.receive(), Err(ReceiveError::Io(_))));
+
+    master.write_all(&frame[4..]).unwrap();
+    assert_eq!(device.receive().unwrap(), message);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_metrics() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let (stream1, stream2) = stream_pair();
+    let (mut sender, mut receiver) = metrics::with_local_recorder(&recorder, || {
+        (
+            SerialManager::new(stream1).with_metrics("host"),
+            SerialManager::new(stream2).with_metrics("device"),
+        )
+    });
+
+    let message = Message::U8(message_types::U8 { num: 1 });
+    sender.send(message.clone()).unwrap();
+    sender.reset_stats();
+    sender.send(message.clone()).unwrap();
+    assert_eq!(receiver.receive().unwrap(), message);
+    // Published before the next read
+    receiver.reset_stats();
+
+    // Taking a snapshot clears the counters, so a single one is taken
+    let snapshot = snapshotter.snapshot().into_vec();
+    let counter = |name: &str, link: &str| {
+        snapshot.iter().find_map(|(key, _, _, value)| {
+            let key = key.key();
+            let matches = key.name() == name
+                && key
+                    .labels()
+                    .any(|label| label.key() == "link" && label.value() == link);
+            match value {
+                DebugValue::Counter(count) if matches => Some(*count),
+                _ => None,
+            }
+        })
+    };
+    // Counting carries on past a reset
+    assert_eq!(counter("gsp_frames_sent_total", "host"), Some(2));
+    assert_eq!(counter("gsp_bytes_sent_total", "host"), Some(12));
+    assert_eq!(counter("gsp_frames_received_total", "device"), Some(1));
+    assert_eq!(counter("gsp_frames_sent_total", "device"), Some(0));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_receive_invalid_json() {
+    let (stream1, stream2) = stream_pair();
+    let mut sender = SerialManager::new(stream1);
+    let mut receiver = SerialManager::new(stream2);
+
+    // The text is only checked by the receiver
+    sender
+        .send(Message::Json(message_types::Json {
+            text: "{\"mode\":".to_string(),
+        }))
+        .unwrap();
+    let error = receiver.receive().unwrap_err();
+    assert!(matches!(
+        error.decode_error(),
+        Some(DecodeError::InvalidJson(_))
+    ));
+    assert_eq!(receiver.stats().decode_errors, 1);
+
+    let sent = message_types::Json::from(serde_json::json!({ "mode": 2 }));
+    sender.send(Message::Json(sent.clone())).unwrap();
+    let Message::Json(json) = receiver.receive().unwrap() else {
+        panic!("expected a Json message");
+    };
+    assert_eq!(json, sent);
+    assert_eq!(json.value().unwrap()["mode"], 2);
+}
+
+#[test]
+fn test_send_escape() {
+    let (stream1, mut stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+
+    let guard = Duration::from_millis(20);
+    let started = std::time::Instant::now();
+    manager.send_escape(b"+++", guard).unwrap();
+    assert!(started.elapsed() >= guard * 2);
+    let mut escape = [0; 3];
+    stream2.read_exact(&mut escape).unwrap();
+    assert_eq!(&escape, b"+++");
+}
+
+#[test]
+fn test_suspend_framing() {
+    let (stream1, stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+    let mut peer = SerialManager::new(stream2);
+
+    // A frame followed at once by a bootloader's greeting and the start of a frame it cut off
+    let (message, mut bytes) = get_test_cases()[1].clone();
+    bytes.extend_from_slice(b"BOOT");
+    bytes.extend_from_slice(&[START_BYTE, 0x05]);
+    peer.send_escape(&bytes, Duration::ZERO).unwrap();
+    assert_eq!(manager.receive().unwrap(), message);
+
+    {
+        let mut raw = manager.suspend_framing();
+        let mut greeting = [0; 4];
+        raw.read_exact(&mut greeting).unwrap();
+        assert_eq!(&greeting, b"BOOT");
+        raw.write_all(&[0x7F]).unwrap();
+    }
+    let mut sync = [0; 1];
+    peer.suspend_framing().read_exact(&mut sync).unwrap();
+    assert_eq!(sync, [0x7F]);
+
+    // The cut off frame is discarded once framing resumes
+    peer.send(message.clone()).unwrap();
+    assert_eq!(manager.receive().unwrap(), message);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_send_break() {
+    let (_master, slave) = crate::test_util::pty_pair();
+    let mut manager = SerialManager::new(slave);
+    manager.send_break(Duration::from_millis(10)).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_flush_input_buffers() {
+    use crate::Buffers;
+
+    let (mut master, slave) = crate::test_util::pty_pair();
+    let mut manager = SerialManager::new(slave);
+
+    // The start of a frame long enough to swallow the next one
+    master.write_all(&[START_BYTE, 0x40, 0x00]).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    manager.flush_buffers(Buffers::Input).unwrap();
+
+    let (message, bytes) = get_test_cases()[1].clone();
+    master.write_all(&bytes).unwrap();
+    assert_eq!(manager.receive().unwrap(), message);
+}
+
+#[test]
+fn test_transport_control_defaults_to_unsupported() {
+    use crate::TransportControl;
+
+    /// A connection that can only drive DTR
+    struct DtrOnly {
+        stream: TestStream,
+        dtr: bool,
+    }
+
+    impl Read for DtrOnly {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.stream.read(buf)
+        }
+    }
+
+    impl Write for DtrOnly {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.stream.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.stream.flush()
+        }
+    }
+
+    impl TransportControl for DtrOnly {
+        fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+            self.dtr = level;
+            Ok(())
+        }
+    }
+
+    let (stream, _) = stream_pair();
+    let mut manager = SerialManager::new(DtrOnly { stream, dtr: true });
+    manager.set_dtr(false).unwrap();
+    assert!(!manager.connection.dtr);
+    let error = manager.set_rts(true).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
 }
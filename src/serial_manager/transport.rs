@@ -0,0 +1,86 @@
+use super::SerialManager;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// A byte stream that a [`SerialManager`](crate::SerialManager) sends and receives frames over
+///
+/// Every [`Read`] + [`Write`] type is a transport. Others, such as DMA buffers, FFI handles or
+/// ring buffers, can implement this directly instead of faking the semantics of `std::io`, such
+/// as by returning `Ok(0)` for a read with nothing available. Line control is a separate
+/// extension, [`TransportControl`](crate::TransportControl).
+///
+/// The methods are named apart from those of [`Read`] and [`Write`], so that calls on streams
+/// are not ambiguous with both traits in scope.
+pub trait Transport {
+    /// Reads bytes into `buffer`, returning how many were read, or 0 once the transport has
+    /// closed
+    ///
+    /// This may block until bytes arrive, or fail with [`io::ErrorKind::TimedOut`] or
+    /// [`io::ErrorKind::WouldBlock`] if none do in time.
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Writes some of `bytes`, returning how many were written
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<usize>;
+
+    /// Waits for the bytes written to be passed on to the device
+    fn flush_output(&mut self) -> io::Result<()>;
+
+    /// Writes all of `bytes`, retrying writes that are partial or interrupted
+    fn write_all_bytes(&mut self, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            match self.write_bytes(bytes) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(count) => bytes = &bytes[count..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets how long a read waits for bytes before failing, or that it waits indefinitely
+    ///
+    /// Fails with [`io::ErrorKind::Unsupported`] unless the transport implements it. `Read` +
+    /// `Write` types do not, so set their timeouts before creating the manager, such as with
+    /// `TcpStream::set_read_timeout`.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let _ = timeout;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the transport does not support read timeouts",
+        ))
+    }
+}
+
+impl<T> Transport for T
+where
+    T: Read + Write,
+{
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        Read::read(self, buffer)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        Write::write(self, bytes)
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+
+    fn write_all_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        Write::write_all(self, bytes)
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport,
+{
+    /// Sets how long a receive waits for bytes from the connection before failing with the
+    /// connection's timeout error, for transports that implement
+    /// [`Transport::set_read_timeout`]
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+}
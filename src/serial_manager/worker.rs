@@ -0,0 +1,143 @@
+use super::send_queue::{send_queue, QueueSender, SendQueue};
+use super::{SerialManager, Transport};
+use crate::errors::ReceiveError;
+use crate::message::Message;
+use crate::rate_limit::RateLimiter;
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// A connection that can be duplicated into a second handle to the same underlying stream, so
+/// that one thread can read while another writes
+pub trait TryClone: Sized {
+    fn try_clone(&self) -> io::Result<Self>;
+}
+
+impl TryClone for File {
+    fn try_clone(&self) -> io::Result<Self> {
+        File::try_clone(self)
+    }
+}
+
+impl TryClone for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+}
+
+#[cfg(unix)]
+impl TryClone for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport + TryClone + Send + 'static,
+{
+    /// Moves the connection into a reader thread and a writer thread, returning channels to send
+    /// messages through and to receive them from
+    ///
+    /// The reader thread keeps this manager, with its configuration and observer, and receives
+    /// until the channel is dropped, an IO error occurs, the peer closes the connection or
+    /// receiving is cancelled through its [`cancel_token`](Self::cancel_token), which is passed
+    /// on before it stops. Decode errors are passed on without stopping. The writer thread sends every message from
+    /// the channel with the same endianness until the channel is dropped or a send fails. Its
+    /// frames are not seen by the observer.
+    #[allow(clippy::type_complexity)]
+    pub fn spawn(self) -> io::Result<(Sender<Message>, Receiver<Result<Message, ReceiveError>>)> {
+        let mut writer = self.try_clone_writer()?;
+        let (outgoing_sender, outgoing_receiver) = mpsc::channel::<Message>();
+
+        thread::spawn(move || {
+            for message in outgoing_receiver {
+                if writer.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok((outgoing_sender, self.spawn_reader()))
+    }
+
+    /// Like [`spawn`](Self::spawn), but messages to send wait in a bounded [`SendQueue`], so that
+    /// producers see backpressure when the connection cannot keep up instead of the queue growing
+    /// without limit
+    ///
+    /// The returned [`QueueSender`] reports the queue's depth, and can wait for space with or
+    /// without a timeout, or fail at once when the queue is full. Once the writer thread stops
+    /// after a failed send, queuing fails with [`QueueSendError::Disconnected`].
+    ///
+    /// [`QueueSendError::Disconnected`]: crate::QueueSendError::Disconnected
+    pub fn spawn_bounded(
+        self,
+        queue: SendQueue,
+    ) -> io::Result<(QueueSender, Receiver<Result<Message, ReceiveError>>)> {
+        let mut writer = self.try_clone_writer()?;
+        let (outgoing_sender, outgoing_receiver) = send_queue(queue);
+
+        thread::spawn(move || {
+            while let Some(message) = outgoing_receiver.recv() {
+                if writer.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok((outgoing_sender, self.spawn_reader()))
+    }
+
+    /// Moves this manager into a thread receiving until the returned channel is dropped, an IO
+    /// error occurs, the peer closes the connection or receiving is cancelled
+    fn spawn_reader(mut self) -> Receiver<Result<Message, ReceiveError>> {
+        let (incoming_sender, incoming_receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let result = self.receive();
+            let stop = matches!(
+                result,
+                Err(ReceiveError::Io(_) | ReceiveError::PeerClosed | ReceiveError::Cancelled)
+            );
+            if incoming_sender.send(result).is_err() || stop {
+                break;
+            }
+        });
+        incoming_receiver
+    }
+}
+
+impl<T> SerialManager<T>
+where
+    T: Transport + TryClone,
+{
+    /// Creates a manager for writing to a second handle to the connection, with the same
+    /// endianness, framing, trailer, padding, rate limit, layers, byte log, half-duplex bus, wake
+    /// preamble and metrics but none of the receiving configuration or the observer
+    pub(crate) fn try_clone_writer(&self) -> io::Result<SerialManager<T>> {
+        let mut writer = SerialManager::new(self.connection.try_clone()?)
+            .with_endianness(self.endianness)
+            .with_framing(self.framing);
+        writer.trailer = self.trailer;
+        writer.padding = self.padding;
+        writer.rate_limiter = self
+            .rate_limiter
+            .as_ref()
+            .map(|limiter| RateLimiter::new(limiter.limit()));
+        writer.layers = Arc::clone(&self.layers);
+        writer.byte_log.clone_from(&self.byte_log);
+        writer.half_duplex.clone_from(&self.half_duplex);
+        writer.wake_preamble.clone_from(&self.wake_preamble);
+        #[cfg(feature = "metrics")]
+        {
+            // The writer's own stats start from zero
+            writer.metrics.clone_from(&self.metrics);
+            if let Some(metrics) = &mut writer.metrics {
+                metrics.reset();
+            }
+        }
+        Ok(writer)
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+/// Number of buckets in [`LatencyStats::histogram`]
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// Counters describing the traffic seen by a [`SerialManager`](crate::SerialManager).
+///
+/// A snapshot is returned by `SerialManager::stats` and the counters can be cleared with
+/// `SerialManager::reset_stats`. New counters may be added in minor releases, so it cannot be
+/// built outside this crate.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Number of frames written to the connection
+    pub frames_sent: u64,
+    /// Number of frames successfully decoded into a message, or received with `receive_raw`
+    pub frames_received: u64,
+    /// Number of bytes written to the connection, including start and escape bytes
+    pub bytes_sent: u64,
+    /// Number of bytes read from the connection, including skipped garbage
+    pub bytes_received: u64,
+    /// Number of escape bytes, or bytes added by COBS, in outgoing frames
+    pub escape_bytes_sent: u64,
+    /// Number of escape bytes, or bytes added by COBS, removed from incoming frames
+    pub escape_bytes_received: u64,
+    /// Number of partially read frames abandoned because a start byte was encountered
+    pub resyncs: u64,
+    /// Number of bytes skipped outside any frame
+    pub bytes_skipped: u64,
+    /// Number of complete frames that could not be decoded into a message, not including
+    /// checksum failures, which are counted in `crc_failures`
+    pub decode_errors: u64,
+    /// Number of frames dropped for failing a checksum, as when corrupted by line noise
+    pub crc_failures: u64,
+    /// Number of bytes left over after the fields of received payloads, when these are allowed
+    pub trailing_bytes: u64,
+    /// Number of received frames dropped for exceeding the receive limit of their message type
+    pub frames_limited: u64,
+    /// Round trip times measured by `ping` and `probe_latency`
+    pub latency: LatencyStats,
+}
+
+/// Round trip times of pings answered by the peer
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct LatencyStats {
+    /// Number of round trip times measured
+    pub samples: u64,
+    /// Number of pings that were not answered in time
+    pub lost: u64,
+    /// The shortest round trip time, or zero before any are measured
+    pub min: Duration,
+    /// The longest round trip time
+    pub max: Duration,
+    /// The sum of all round trip times, for [`mean`](Self::mean)
+    pub total: Duration,
+    /// The most recent round trip time
+    pub last: Duration,
+    /// The smoothed mean difference between consecutive round trip times, as for interarrival
+    /// jitter in RTP (RFC 3550)
+    pub jitter: Duration,
+    /// Counts of round trip times by their power of two in microseconds: bucket 0 holds those
+    /// under 2 µs, bucket `i` those from 2<sup>`i`</sup> up to 2<sup>`i`+1</sup> µs, and the last
+    /// bucket everything longer
+    pub histogram: [u64; LATENCY_BUCKETS],
+}
+
+impl LatencyStats {
+    /// Returns the mean round trip time, or zero before any are measured
+    #[must_use]
+    pub fn mean(&self) -> Duration {
+        if self.samples == 0 {
+            return Duration::ZERO;
+        }
+        let nanos = self.total.as_nanos() / u128::from(self.samples);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Returns the range of round trip times counted by `histogram[bucket]`, with no upper bound
+    /// for the last bucket
+    #[must_use]
+    pub fn bucket_range(bucket: usize) -> (Duration, Option<Duration>) {
+        let lower = if bucket == 0 { 0 } else { 1 << bucket };
+        let upper = (bucket + 1 < LATENCY_BUCKETS).then(|| Duration::from_micros(2 << bucket));
+        (Duration::from_micros(lower), upper)
+    }
+
+    /// Adds a measured round trip time
+    pub(crate) fn record(&mut self, round_trip: Duration) {
+        if self.samples == 0 {
+            self.min = round_trip;
+        } else {
+            // J += (|D| - J) / 16, from RFC 3550
+            let difference = round_trip.abs_diff(self.last);
+            self.jitter = if difference > self.jitter {
+                self.jitter + difference.saturating_sub(self.jitter) / 16
+            } else {
+                self.jitter
+                    .saturating_sub(self.jitter.saturating_sub(difference) / 16)
+            };
+        }
+        self.samples += 1;
+        self.min = self.min.min(round_trip);
+        self.max = self.max.max(round_trip);
+        self.total += round_trip;
+        self.last = round_trip;
+        let micros = round_trip.as_micros();
+        let bucket = (u128::BITS - micros.leading_zeros()).saturating_sub(1) as usize;
+        self.histogram[bucket.min(LATENCY_BUCKETS - 1)] += 1;
+    }
+}
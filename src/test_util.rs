@@ -0,0 +1,83 @@
+//! Helpers shared by the unit tests.
+
+#[cfg(not(unix))]
+use std::net::{TcpListener, TcpStream};
+
+/// A connected stream type available on every platform the tests run on
+#[cfg(unix)]
+pub(crate) type TestStream = std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+pub(crate) type TestStream = TcpStream;
+
+/// Returns a pair of streams connected to each other
+#[cfg(unix)]
+pub(crate) fn stream_pair() -> (TestStream, TestStream) {
+    TestStream::pair().unwrap()
+}
+
+/// Returns a pair of streams connected to each other, over loopback TCP as Windows has no
+/// socket pairs
+#[cfg(not(unix))]
+pub(crate) fn stream_pair() -> (TestStream, TestStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    client.set_nodelay(true).unwrap();
+    server.set_nodelay(true).unwrap();
+    (client, server)
+}
+
+/// Returns the master and slave ends of a pseudo-terminal pair, with the slave in raw mode and its
+/// reads returning as soon as a byte arrives
+///
+/// Unlike socket pairs, data passes through the terminal line discipline, so this covers
+/// behaviour specific to real serial ports.
+#[cfg(unix)]
+pub(crate) fn pty_pair() -> (std::fs::File, std::fs::File) {
+    use std::os::fd::FromRawFd;
+
+    let mut master = -1;
+    let mut slave = -1;
+    // SAFETY: the name, termios and window size arguments may be null
+    let result = unsafe {
+        libc::openpty(
+            &raw mut master,
+            &raw mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    assert_eq!(result, 0, "openpty: {}", std::io::Error::last_os_error());
+    // SAFETY: openpty succeeded, so both are open file descriptors owned by nothing else
+    let (master, slave) = unsafe {
+        (
+            std::fs::File::from_raw_fd(master),
+            std::fs::File::from_raw_fd(slave),
+        )
+    };
+    set_tty_timing(&slave, 1, 0);
+    (master, slave)
+}
+
+/// Puts a terminal into raw mode, with reads waiting for `vmin` bytes, or returning `vtime`
+/// tenths of a second after the last byte arrived, as `termios(3)` describes for `VMIN` and
+/// `VTIME`
+#[cfg(unix)]
+pub(crate) fn set_tty_timing(tty: &std::fs::File, vmin: u8, vtime: u8) {
+    use std::os::fd::AsRawFd;
+
+    let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: tcgetattr initialises the termios on success, which is checked before it is read
+    unsafe {
+        assert_eq!(libc::tcgetattr(tty.as_raw_fd(), termios.as_mut_ptr()), 0);
+        let mut termios = termios.assume_init();
+        libc::cfmakeraw(&raw mut termios);
+        termios.c_cc[libc::VMIN] = vmin;
+        termios.c_cc[libc::VTIME] = vtime;
+        assert_eq!(
+            libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &raw const termios),
+            0
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport, TryClone};
+use crate::time::Instant;
+use crate::CancelToken;
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+type Matcher = Box<dyn Fn(&Message) -> bool + Send>;
+type Reply = Box<dyn FnMut(&Message) -> Message + Send>;
+type Telemetry = Box<dyn FnMut() -> Message + Send>;
+
+struct Rule {
+    matches: Matcher,
+    delay: Duration,
+    reply: Reply,
+}
+
+struct Periodic {
+    interval: Duration,
+    next: Instant,
+    message: Telemetry,
+}
+
+enum Event {
+    Received(Message),
+    /// The host closed the connection, or receiving failed
+    Closed,
+    Stop,
+}
+
+/// A scriptable peer standing in for a device, so that host applications can be tested without
+/// hardware
+///
+/// The device replies to requests matching its rules after their delay, emits telemetry
+/// periodically, answers every `Ping` with a `Pong` and, once given a [`DeviceInfo`], answers
+/// `Identify`. It runs in its own threads over any connection a [`SerialManager`] can be
+/// spawned on, such as one end of a [`NoisyChannel`](super::NoisyChannel):
+///
+/// ```
+/// use generic_serial_protocol::testing::{NoisyChannel, SimulatedDevice};
+/// use generic_serial_protocol::{message_types, Message, SerialManager};
+/// use std::time::Duration;
+///
+/// let (host, device) = NoisyChannel::new().pair();
+/// let device = SimulatedDevice::new()
+///     .reply(
+///         Message::Command(message_types::Command { id: 1, args: vec![] }),
+///         Message::Status(message_types::Status::Ok),
+///         Duration::from_millis(5),
+///     )
+///     .spawn(SerialManager::new(device))
+///     .unwrap();
+///
+/// let mut host = SerialManager::new(host);
+/// host.send(Message::Command(message_types::Command { id: 1, args: vec![] })).unwrap();
+/// assert_eq!(host.receive().unwrap(), Message::Status(message_types::Status::Ok));
+/// let received = device.stop().unwrap();
+/// assert_eq!(received.len(), 1);
+/// ```
+///
+/// [`DeviceInfo`]: message_types::DeviceInfo
+#[derive(Default)]
+pub struct SimulatedDevice {
+    rules: Vec<Rule>,
+    periodic: Vec<Periodic>,
+    info: Option<message_types::DeviceInfo>,
+}
+
+impl SimulatedDevice {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replies to every message equal to `request` with `reply`, after `delay`
+    #[must_use]
+    pub fn reply(self, request: Message, reply: Message, delay: Duration) -> Self {
+        self.respond(
+            move |message| *message == request,
+            delay,
+            move |_| reply.clone(),
+        )
+    }
+
+    /// Replies to every message for which `matches` returns true with the message `reply`
+    /// returns for it, after `delay`
+    ///
+    /// Only the first rule matching a message replies to it.
+    #[must_use]
+    pub fn respond(
+        mut self,
+        matches: impl Fn(&Message) -> bool + Send + 'static,
+        delay: Duration,
+        reply: impl FnMut(&Message) -> Message + Send + 'static,
+    ) -> Self {
+        self.rules.push(Rule {
+            matches: Box::new(matches),
+            delay,
+            reply: Box::new(reply),
+        });
+        self
+    }
+
+    /// Sends the message `telemetry` returns every `interval`, starting one interval after the
+    /// device is spawned
+    #[must_use]
+    pub fn emit_every(
+        mut self,
+        interval: Duration,
+        telemetry: impl FnMut() -> Message + Send + 'static,
+    ) -> Self {
+        self.periodic.push(Periodic {
+            interval,
+            next: Instant::now(),
+            message: Box::new(telemetry),
+        });
+        self
+    }
+
+    /// Answers `Identify` with `info`
+    #[must_use]
+    pub fn with_device_info(mut self, info: message_types::DeviceInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Starts the device on `manager`'s connection, receiving in one thread and replying and
+    /// emitting telemetry in another
+    ///
+    /// The device runs until it is stopped, the host closes the connection or sending fails.
+    pub fn spawn<T>(mut self, mut manager: SerialManager<T>) -> io::Result<SimulatedDeviceHandle>
+    where
+        T: Transport + TryClone + Send + 'static,
+    {
+        let mut writer = manager.try_clone_writer()?;
+        let cancel = manager.cancel_token();
+        let (events, incoming) = mpsc::channel();
+
+        let received = events.clone();
+        thread::spawn(move || loop {
+            let event = match manager.receive() {
+                Ok(message) => Event::Received(message),
+                // Reading again notices a cancellation
+                Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::TimedOut => continue,
+                Err(ReceiveError::Io(_) | ReceiveError::PeerClosed | ReceiveError::Cancelled) => {
+                    Event::Closed
+                }
+                // A host sending malformed frames is for the test to notice, not the device
+                Err(_) => continue,
+            };
+            let closed = matches!(event, Event::Closed);
+            if received.send(event).is_err() || closed {
+                break;
+            }
+        });
+
+        let started = Instant::now();
+        for periodic in &mut self.periodic {
+            periodic.next = started + periodic.interval;
+        }
+        let thread = thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut replies: Vec<(Instant, Message)> = Vec::new();
+            loop {
+                let now = Instant::now();
+                self.send_due(&mut writer, &mut replies, now)?;
+
+                let next = replies
+                    .iter()
+                    .map(|&(due, _)| due)
+                    .chain(self.periodic.iter().map(|periodic| periodic.next))
+                    .min();
+                let event = match next {
+                    Some(next) => {
+                        match incoming.recv_timeout(next.saturating_duration_since(now)) {
+                            Ok(event) => event,
+                            Err(RecvTimeoutError::Timeout) => continue,
+                            Err(RecvTimeoutError::Disconnected) => Event::Closed,
+                        }
+                    }
+                    None => incoming.recv().unwrap_or(Event::Closed),
+                };
+                match event {
+                    Event::Received(message) => {
+                        if let Some(reply) = self.reply_to(&message) {
+                            replies.push(reply);
+                        }
+                        received.push(message);
+                    }
+                    Event::Closed | Event::Stop => return Ok(received),
+                }
+            }
+        });
+
+        Ok(SimulatedDeviceHandle {
+            events,
+            cancel,
+            thread,
+        })
+    }
+
+    /// Returns the reply to `message` and when it is due, if there is one
+    fn reply_to(&mut self, message: &Message) -> Option<(Instant, Message)> {
+        let now = Instant::now();
+        match message {
+            Message::Ping(ping) => {
+                let sequence = ping.sequence;
+                return Some((now, Message::Pong(message_types::Pong { sequence })));
+            }
+            Message::Identify(_) => {
+                if let Some(info) = &self.info {
+                    return Some((now, Message::DeviceInfo(info.clone())));
+                }
+            }
+            _ => (),
+        }
+        let rule = self.rules.iter_mut().find(|rule| (rule.matches)(message))?;
+        Some((now + rule.delay, (rule.reply)(message)))
+    }
+
+    /// Sends the replies and telemetry due by `now`, earliest first
+    fn send_due<T: Transport>(
+        &mut self,
+        writer: &mut SerialManager<T>,
+        replies: &mut Vec<(Instant, Message)>,
+        now: Instant,
+    ) -> io::Result<()> {
+        replies.sort_by_key(|&(due, _)| due);
+        let due = replies.partition_point(|&(due, _)| due <= now);
+        for (_, reply) in replies.drain(..due) {
+            writer.send(reply)?;
+        }
+        for periodic in &mut self.periodic {
+            while periodic.next <= now {
+                writer.send((periodic.message)())?;
+                periodic.next += periodic.interval;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A running [`SimulatedDevice`]
+pub struct SimulatedDeviceHandle {
+    events: Sender<Event>,
+    cancel: CancelToken,
+    thread: JoinHandle<io::Result<Vec<Message>>>,
+}
+
+impl SimulatedDeviceHandle {
+    /// Stops the device, returning every message it received
+    ///
+    /// The device stops replying and emitting telemetry at once. Its receiving thread stops at
+    /// its next read from the connection, once bytes arrive or a read times out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a rule or telemetry closure panicked.
+    pub fn stop(self) -> io::Result<Vec<Message>> {
+        self.cancel.cancel();
+        // The device has stopped already if it is gone
+        let _ = self.events.send(Event::Stop);
+        self.thread.join().expect("simulated device panicked")
+    }
+
+    /// Waits for the host to close the connection, returning every message the device received
+    ///
+    /// # Panics
+    ///
+    /// Panics if a rule or telemetry closure panicked.
+    pub fn join(self) -> io::Result<Vec<Message>> {
+        drop(self.events);
+        self.thread.join().expect("simulated device panicked")
+    }
+}
@@ -0,0 +1,345 @@
+use crate::codec::{Decoder, DecoderEvent, Endianness, Framing};
+use crate::message::Message;
+use crate::serial_manager::TryClone;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+#[derive(Debug, Clone)]
+enum Step {
+    Send(Message),
+    Receive(Message),
+}
+
+/// An exchange of messages an application is expected to have, step by step
+///
+/// The exchange is played by a [`ScriptedLink`] that a [`SerialManager`](crate::SerialManager)
+/// is created on in place of a serial port. Each message the application sends is compared with
+/// the next expected one, and the messages to receive after it become readable once it matches:
+///
+/// ```
+/// use generic_serial_protocol::testing::Exchange;
+/// use generic_serial_protocol::{message_types, Message, SerialManager};
+///
+/// let link = Exchange::new()
+///     .expect_send(Message::U8(message_types::U8 { num: 5 }))
+///     .then_receive(Message::Status(message_types::Status::Ok))
+///     .link();
+/// let mut manager = SerialManager::new(link.clone());
+///
+/// manager.send(Message::U8(message_types::U8 { num: 5 })).unwrap();
+/// assert_eq!(manager.receive().unwrap(), Message::Status(message_types::Status::Ok));
+/// link.assert_done();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Exchange {
+    steps: Vec<Step>,
+    framing: Framing,
+    endianness: Endianness,
+}
+
+impl Exchange {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the framing the application's manager uses, which is [`Framing::Native`] by default
+    #[must_use]
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Sets the byte order the application's manager uses, which is little-endian by default
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Expects the application to send `message` next
+    #[must_use]
+    pub fn expect_send(mut self, message: Message) -> Self {
+        self.steps.push(Step::Send(message));
+        self
+    }
+
+    /// Makes `message` readable by the application once the messages expected before it have
+    /// been sent
+    ///
+    /// The link panics on reaching a message that does not encode.
+    #[must_use]
+    pub fn then_receive(mut self, message: Message) -> Self {
+        self.steps.push(Step::Receive(message));
+        self
+    }
+
+    /// Returns a link playing the exchange
+    #[must_use]
+    pub fn link(self) -> ScriptedLink {
+        let mut state = State {
+            decoder: Decoder::new()
+                .with_framing(self.framing)
+                .with_endianness(self.endianness),
+            steps: self.steps,
+            framing: self.framing,
+            endianness: self.endianness,
+            next: 0,
+            incoming: VecDeque::new(),
+            read: 0,
+            received: 0,
+            deviation: None,
+        };
+        state.queue_receives();
+        ScriptedLink {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    steps: Vec<Step>,
+    framing: Framing,
+    endianness: Endianness,
+    decoder: Decoder,
+    /// The first step not yet sent or queued to be read
+    next: usize,
+    /// The frames of queued steps not yet read in full
+    incoming: VecDeque<Vec<u8>>,
+    /// How much of the first incoming frame has been read
+    read: usize,
+    /// How many queued steps have been read in full
+    received: usize,
+    deviation: Option<String>,
+}
+
+impl State {
+    /// Queues the frames of the steps to receive before the next one to send
+    fn queue_receives(&mut self) {
+        while let Some(Step::Receive(message)) = self.steps.get(self.next) {
+            let payload = message
+                .clone()
+                .to_bytes_with(self.endianness)
+                .expect("scripted message does not encode");
+            let frame = self
+                .framing
+                .encode(message.message_type(), &payload, self.endianness);
+            self.incoming.push_back(frame);
+            self.next += 1;
+        }
+    }
+
+    fn check_sent(&mut self, event: DecoderEvent) -> Result<(), String> {
+        let DecoderEvent::Frame(frame) = event else {
+            return Err(self.report(&format!(
+                "sent bytes that were not a valid frame: {event:?}"
+            )));
+        };
+        let sent =
+            match Message::from_bytes_with(frame.message_type, frame.payload, self.endianness) {
+                Ok(sent) => sent,
+                Err(e) => {
+                    return Err(self.report(&format!("sent a frame that failed to decode: {e}")))
+                }
+            };
+        // Steps to receive are queued as soon as they are reached, so the next step is one to send
+        let Some(Step::Send(expected)) = self.steps.get(self.next) else {
+            return Err(self.report(&format!("sent {sent:?} after the exchange ended")));
+        };
+        if *expected == sent {
+            self.next += 1;
+            self.queue_receives();
+            return Ok(());
+        }
+        let diff = diff(&format!("{expected:#?}"), &format!("{sent:#?}"));
+        Err(self.report(&format!(
+            "sent a different message (- expected, + sent):\n{}",
+            diff.trim_end()
+        )))
+    }
+
+    /// Describes how the application deviated at the next step, with the steps so far
+    fn report(&self, deviation: &str) -> String {
+        let mut text = format!(
+            "the application deviated from the expected exchange at step {}: it {deviation}\n\n{}",
+            self.next + 1,
+            self.transcript(),
+        );
+        text.truncate(text.trim_end().len());
+        text
+    }
+
+    /// Lists the steps, marking the next one
+    fn transcript(&self) -> String {
+        let mut text = String::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let marker = if index == self.next { '>' } else { ' ' };
+            let (action, message) = match step {
+                Step::Send(message) => ("send   ", message),
+                Step::Receive(message) => ("receive", message),
+            };
+            // Writing to a String cannot fail
+            let _ = writeln!(text, "{marker} {}. {action} {message:?}", index + 1);
+        }
+        text
+    }
+
+    /// The steps that were neither sent nor read in full, if any
+    fn unfinished(&self) -> Option<String> {
+        let queued = self.steps[..self.next]
+            .iter()
+            .filter(|step| matches!(step, Step::Receive(_)))
+            .count();
+        if self.next == self.steps.len() && self.received == queued {
+            return None;
+        }
+        let mut text = String::from("the exchange did not finish");
+        if self.received < queued {
+            let _ = write!(
+                text,
+                ": {} message(s) were never read",
+                queued - self.received
+            );
+        }
+        let _ = write!(text, "\n\n{}", self.transcript());
+        text.truncate(text.trim_end().len());
+        Some(text)
+    }
+}
+
+/// A stand-in for a serial port, playing an [`Exchange`]
+///
+/// Clones share the exchange, so one can be kept to check it with
+/// [`assert_done`](Self::assert_done) once the application's manager has been given another. The
+/// first message that differs from the expected one makes the write fail with
+/// [`io::ErrorKind::InvalidData`], carrying the same report, and so does every write after it. Reading when no message is ready fails with
+/// [`io::ErrorKind::TimedOut`] rather than blocking.
+#[derive(Debug, Clone)]
+pub struct ScriptedLink {
+    state: Arc<Mutex<State>>,
+}
+
+impl ScriptedLink {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a report of how the application deviated from the exchange or did not finish it,
+    /// if it did
+    #[must_use]
+    pub fn mismatch(&self) -> Option<String> {
+        let state = self.lock();
+        state.deviation.clone().or_else(|| state.unfinished())
+    }
+
+    /// Checks that the application had the whole exchange: it sent every expected message, and
+    /// nothing else, and read every message queued for it
+    ///
+    /// # Panics
+    ///
+    /// Panics with a report of the steps and, for a message that differs from the expected
+    /// one, a diff of the two, if the application did not.
+    #[track_caller]
+    pub fn assert_done(&self) {
+        if let Some(mismatch) = self.mismatch() {
+            panic!("{mismatch}");
+        }
+    }
+}
+
+impl Read for ScriptedLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        let read = state.read;
+        let Some(frame) = state.incoming.front() else {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no message is expected to be received before the next one is sent",
+            ));
+        };
+        let length = buf.len().min(frame.len() - read);
+        buf[..length].copy_from_slice(&frame[read..read + length]);
+        let finished = read + length == frame.len();
+        if finished {
+            state.incoming.pop_front();
+            state.read = 0;
+            state.received += 1;
+        } else {
+            state.read += length;
+        }
+        Ok(length)
+    }
+}
+
+impl Write for ScriptedLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        for &byte in buf {
+            if let Some(deviation) = &state.deviation {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    deviation.clone(),
+                ));
+            }
+            let Some(event) = state.decoder.push(byte) else {
+                continue;
+            };
+            if let Err(deviation) = state.check_sent(event) {
+                state.deviation = Some(deviation.clone());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, deviation));
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TryClone for ScriptedLink {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(self.clone())
+    }
+}
+
+/// Diffs two texts line by line, marking lines only in `expected` with `-` and lines only in
+/// `actual` with `+`
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // common[i][j] is the length of the longest common subsequence of expected[i..] and
+    // actual[j..]
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut text = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        let line = if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+            format!("  {}", expected[i - 1])
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            i += 1;
+            format!("- {}", expected[i - 1])
+        } else {
+            j += 1;
+            format!("+ {}", actual[j - 1])
+        };
+        let _ = writeln!(text, "{line}");
+    }
+    text
+}
@@ -0,0 +1,377 @@
+//! Utilities for testing protocol features without hardware.
+//!
+//! A [`NoisyChannel`] connects two in-process [`SerialManager`](crate::SerialManager)s through
+//! a simulated serial link with a baud rate, latency, jitter, bit errors and a limited receive
+//! buffer, so that retries, windows and timeouts can be tuned against realistic conditions:
+//!
+//! ```
+//! use generic_serial_protocol::testing::NoisyChannel;
+//! use generic_serial_protocol::{message_types, Message, SerialManager};
+//! use std::time::Duration;
+//!
+//! let (host, device) = NoisyChannel::new()
+//!     .with_baud_rate(115_200)
+//!     .with_latency(Duration::from_millis(2))
+//!     .with_bit_error_probability(1e-6)
+//!     .pair();
+//! let mut host = SerialManager::new(host);
+//! let mut device = SerialManager::new(device);
+//! host.send(Message::Ping(message_types::Ping { sequence: 1 })).unwrap();
+//! let message = device.receive();
+//! ```
+//!
+//! A [`SimulatedDevice`] stands in for the device itself, replying to commands after a delay and
+//! emitting telemetry periodically, so that host applications can be tested end to end. An
+//! [`Exchange`] goes the other way, checking step by step that an application sends the
+//! messages it should, and failing with a diff of the first one that differs.
+
+mod device;
+mod exchange;
+
+pub use device::{SimulatedDevice, SimulatedDeviceHandle};
+pub use exchange::{Exchange, ScriptedLink};
+
+use crate::serial_manager::TryClone;
+use crate::time::Instant;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// The bits sent for each byte: a start bit, eight data bits and a stop bit
+const BITS_PER_BYTE: u32 = 10;
+
+/// Settings for a simulated serial link, from which [`pair`](Self::pair) creates its two ends
+///
+/// By default the link is perfect: bytes arrive at once, unchanged, with no limit on how many
+/// wait to be read. Both directions share the settings, but each has its own timing and random
+/// errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoisyChannel {
+    baud_rate: Option<u32>,
+    latency: Duration,
+    jitter: Duration,
+    bit_error_probability: f64,
+    buffer_size: Option<usize>,
+    seed: u32,
+}
+
+impl Default for NoisyChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NoisyChannel {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            baud_rate: None,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bit_error_probability: 0.0,
+            buffer_size: None,
+            seed: 0x4E4F_4953,
+        }
+    }
+
+    /// Sends bytes no faster than `baud_rate` bits a second allows, at 10 bits a byte
+    #[must_use]
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = Some(baud_rate.max(1));
+        self
+    }
+
+    /// Delays every byte by `latency` on top of the time taken to send it
+    #[must_use]
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Delays each write by a further random time up to `jitter`, without reordering bytes
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Flips each bit sent with probability `probability`, between 0 and 1
+    #[must_use]
+    pub fn with_bit_error_probability(mut self, probability: f64) -> Self {
+        self.bit_error_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Holds at most `buffer_size` bytes that have arrived but not been read, dropping any more
+    /// like an overrun UART
+    #[must_use]
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+
+    /// Sets the seed of the random jitter and bit errors, so that a run can be repeated
+    #[must_use]
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Creates the two connected ends of the link
+    #[must_use]
+    pub fn pair(&self) -> (NoisyEnd, NoisyEnd) {
+        let forward = Arc::new(Link::new(*self, self.seed));
+        let backward = Arc::new(Link::new(*self, self.seed.rotate_left(16) ^ 0x5A5A_5A5A));
+        (
+            NoisyEnd::new(Arc::clone(&forward), Arc::clone(&backward)),
+            NoisyEnd::new(backward, forward),
+        )
+    }
+}
+
+/// What happened to the bytes written to one end of a [`NoisyChannel`]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct ChannelStats {
+    /// Number of bytes written
+    pub bytes_sent: u64,
+    /// Number of bytes with at least one bit flipped
+    pub bytes_corrupted: u64,
+    /// Number of bytes dropped because the receive buffer was full
+    pub bytes_dropped: u64,
+}
+
+#[derive(Debug)]
+struct LinkState {
+    /// Bytes on their way, with the time each arrives
+    in_flight: VecDeque<(Instant, u8)>,
+    /// Bytes that have arrived and wait to be read
+    arrived: VecDeque<u8>,
+    /// When the last byte written has been sent, at the baud rate
+    line_free_at: Instant,
+    /// When the last byte written arrives, which later bytes cannot overtake
+    last_arrival: Instant,
+    random: u32,
+    writers: usize,
+    readers: usize,
+    stats: ChannelStats,
+}
+
+/// One direction of a channel
+#[derive(Debug)]
+struct Link {
+    settings: NoisyChannel,
+    state: Mutex<LinkState>,
+    /// Notified when bytes are written or the last writer is dropped
+    changed: Condvar,
+}
+
+impl Link {
+    fn new(settings: NoisyChannel, seed: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            settings,
+            state: Mutex::new(LinkState {
+                in_flight: VecDeque::new(),
+                arrived: VecDeque::new(),
+                line_free_at: now,
+                last_arrival: now,
+                // xorshift32, whose state must not be zero
+                random: seed | 1,
+                writers: 1,
+                readers: 1,
+                stats: ChannelStats::default(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LinkState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl LinkState {
+    fn next_random(&mut self) -> u32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random
+    }
+
+    /// Returns whether an event of `probability` happens
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn chance(&mut self, probability: f64) -> bool {
+        let threshold = (probability * (1u64 << 32) as f64) as u64;
+        u64::from(self.next_random()) < threshold
+    }
+
+    /// Moves the bytes that have arrived by `now` into the receive buffer
+    fn deliver(&mut self, now: Instant, buffer_size: Option<usize>) {
+        while let Some(&(arrival, byte)) = self.in_flight.front() {
+            if arrival > now {
+                break;
+            }
+            self.in_flight.pop_front();
+            if buffer_size.is_some_and(|size| self.arrived.len() >= size) {
+                self.stats.bytes_dropped += 1;
+            } else {
+                self.arrived.push_back(byte);
+            }
+        }
+    }
+}
+
+/// One end of a [`NoisyChannel`], which reads what the other end writes and the other way
+/// around
+///
+/// Reads block until bytes arrive, the read timeout passes, or the other end is dropped, after
+/// which they return end of file once every byte has been read.
+#[derive(Debug)]
+pub struct NoisyEnd {
+    tx: Arc<Link>,
+    rx: Arc<Link>,
+    read_timeout: Option<Duration>,
+}
+
+impl NoisyEnd {
+    fn new(tx: Arc<Link>, rx: Arc<Link>) -> Self {
+        Self {
+            tx,
+            rx,
+            read_timeout: None,
+        }
+    }
+
+    /// Makes reads that wait longer than `timeout` fail with [`io::ErrorKind::TimedOut`], or
+    /// wait forever if `None`
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Returns what has happened to the bytes written to this end so far
+    #[must_use]
+    pub fn stats(&self) -> ChannelStats {
+        self.tx.lock().stats
+    }
+}
+
+impl Read for NoisyEnd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        let link = &*self.rx;
+        let mut state = link.lock();
+        loop {
+            let now = Instant::now();
+            state.deliver(now, link.settings.buffer_size);
+            if !state.arrived.is_empty() {
+                let count = buf.len().min(state.arrived.len());
+                for (slot, byte) in buf.iter_mut().zip(state.arrived.drain(..count)) {
+                    *slot = byte;
+                }
+                return Ok(count);
+            }
+            if state.in_flight.is_empty() && state.writers == 0 {
+                return Ok(0);
+            }
+            // Wakes for the next arrival, a new write or the deadline, whichever is first
+            let next_arrival = state.in_flight.front().map(|&(arrival, _)| arrival);
+            let wake = match (next_arrival, deadline) {
+                (Some(arrival), Some(deadline)) => Some(arrival.min(deadline)),
+                (arrival, deadline) => arrival.or(deadline),
+            };
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            state = match wake {
+                Some(wake) => {
+                    link.changed
+                        .wait_timeout(state, wake.saturating_duration_since(now))
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => link
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+}
+
+impl Write for NoisyEnd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let link = &*self.tx;
+        let settings = link.settings;
+        let mut state = link.lock();
+        if state.readers == 0 {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let now = Instant::now();
+        let byte_time = settings.baud_rate.map_or(Duration::ZERO, |baud_rate| {
+            Duration::from_secs(u64::from(BITS_PER_BYTE)) / baud_rate
+        });
+        let jitter = if settings.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            settings
+                .jitter
+                .mul_f64(f64::from(state.next_random()) / f64::from(u32::MAX))
+        };
+        for &byte in buf {
+            let mut corrupted = byte;
+            if settings.bit_error_probability > 0.0 {
+                for bit in 0..8 {
+                    if state.chance(settings.bit_error_probability) {
+                        corrupted ^= 1 << bit;
+                    }
+                }
+            }
+            state.line_free_at = state.line_free_at.max(now) + byte_time;
+            let arrival = (state.line_free_at + settings.latency + jitter).max(state.last_arrival);
+            state.last_arrival = arrival;
+            state.in_flight.push_back((arrival, corrupted));
+            state.stats.bytes_sent += 1;
+            if corrupted != byte {
+                state.stats.bytes_corrupted += 1;
+            }
+        }
+        drop(state);
+        link.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TryClone for NoisyEnd {
+    fn try_clone(&self) -> io::Result<Self> {
+        self.tx.lock().writers += 1;
+        self.rx.lock().readers += 1;
+        Ok(Self {
+            tx: Arc::clone(&self.tx),
+            rx: Arc::clone(&self.rx),
+            read_timeout: self.read_timeout,
+        })
+    }
+}
+
+impl Drop for NoisyEnd {
+    fn drop(&mut self) {
+        self.tx.lock().writers -= 1;
+        self.tx.changed.notify_all();
+        self.rx.lock().readers -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,302 @@
+use super::*;
+use crate::errors::ReceiveError;
+use crate::message::{message_types, Capabilities, Message};
+use crate::SerialManager;
+
+#[test]
+fn test_perfect_channel() {
+    let (host, device) = NoisyChannel::new().pair();
+    let mut host = SerialManager::new(host);
+    let mut device = SerialManager::new(device);
+
+    let message = Message::Ping(message_types::Ping { sequence: 7 });
+    host.send(message.clone()).unwrap();
+    assert_eq!(device.receive().unwrap(), message);
+    device.send(message.clone()).unwrap();
+    assert_eq!(host.receive().unwrap(), message);
+
+    // The other end closing is the end of the stream
+    drop(device);
+    assert!(matches!(
+        host.receive(),
+        Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn test_baud_rate_and_latency() {
+    let (mut host, mut device) = NoisyChannel::new()
+        .with_baud_rate(100_000)
+        .with_latency(Duration::from_millis(20))
+        .pair();
+    let started = Instant::now();
+    host.write_all(&[0x11; 500]).unwrap();
+    let mut received = [0; 500];
+    device.read_exact(&mut received).unwrap();
+    // 500 bytes of 10 bits at 100 kBd take 50ms
+    assert!(started.elapsed() >= Duration::from_millis(70));
+    assert_eq!(received, [0x11; 500]);
+}
+
+#[test]
+fn test_read_timeout() {
+    let (_host, mut device) = NoisyChannel::new().pair();
+    device.set_read_timeout(Some(Duration::from_millis(10)));
+    let error = device.read(&mut [0; 4]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn test_bit_errors() {
+    let (mut host, mut device) = NoisyChannel::new().with_bit_error_probability(1.0).pair();
+    host.write_all(&[0x00, 0x0F]).unwrap();
+    let mut received = [0; 2];
+    device.read_exact(&mut received).unwrap();
+    assert_eq!(received, [0xFF, 0xF0]);
+    assert_eq!(host.stats().bytes_corrupted, 2);
+
+    // Some frames get through a noisy link, and the rest are detected
+    let (host, mut device) = NoisyChannel::new()
+        .with_bit_error_probability(0.002)
+        .with_seed(7)
+        .pair();
+    device.set_read_timeout(Some(Duration::from_millis(20)));
+    let mut host = SerialManager::new(host);
+    let mut device = SerialManager::new(device);
+    let message = Message::Ping(message_types::Ping { sequence: 7 });
+    for _ in 0..200 {
+        host.send(message.clone()).unwrap();
+    }
+    let mut received = 0;
+    loop {
+        match device.receive() {
+            Ok(_) => received += 1,
+            Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::TimedOut => break,
+            Err(_) => {}
+        }
+    }
+    assert!(received > 100);
+    assert!(received < 200);
+}
+
+#[test]
+fn test_buffer_overrun() {
+    let (mut host, mut device) = NoisyChannel::new().with_buffer_size(10).pair();
+    host.write_all(&[0x11; 100]).unwrap();
+    let mut received = [0; 100];
+    assert_eq!(device.read(&mut received).unwrap(), 10);
+    assert_eq!(host.stats().bytes_dropped, 90);
+}
+
+#[test]
+fn test_spawn_over_channel() {
+    let (host, device) = NoisyChannel::new()
+        .with_jitter(Duration::from_millis(5))
+        .pair();
+    let (sender, receiver) = SerialManager::new(host).spawn().unwrap();
+    let mut device = SerialManager::new(device);
+
+    for sequence in 0..10 {
+        sender
+            .send(Message::Ping(message_types::Ping { sequence }))
+            .unwrap();
+    }
+    for sequence in 0..10 {
+        assert_eq!(
+            device.receive().unwrap(),
+            Message::Ping(message_types::Ping { sequence })
+        );
+    }
+    device
+        .send(Message::Pong(message_types::Pong { sequence: 1 }))
+        .unwrap();
+    assert_eq!(
+        receiver.recv().unwrap().unwrap(),
+        Message::Pong(message_types::Pong { sequence: 1 })
+    );
+}
+
+#[test]
+fn test_simulated_device_replies_after_delay() {
+    let (host, device) = NoisyChannel::new().pair();
+    let command = Message::Command(message_types::Command {
+        id: 3,
+        args: vec![1],
+    });
+    let device = SimulatedDevice::new()
+        .respond(
+            |message| matches!(message, Message::Command(_)),
+            Duration::from_millis(50),
+            |message| {
+                let Message::Command(command) = message else {
+                    unreachable!()
+                };
+                Message::Response(message_types::Response {
+                    id: command.id,
+                    status: message_types::Status::Ok,
+                    payload: command.args.clone(),
+                })
+            },
+        )
+        .spawn(SerialManager::new(device))
+        .unwrap();
+
+    let mut host = SerialManager::new(host);
+    let sent = Instant::now();
+    host.send(command.clone()).unwrap();
+    let reply = host.receive().unwrap();
+    assert!(sent.elapsed() >= Duration::from_millis(50));
+    assert_eq!(
+        reply,
+        Message::Response(message_types::Response {
+            id: 3,
+            status: message_types::Status::Ok,
+            payload: vec![1],
+        })
+    );
+    assert_eq!(device.stop().unwrap(), [command]);
+}
+
+#[test]
+fn test_simulated_device_emits_telemetry() {
+    let (host, device) = NoisyChannel::new().pair();
+    let mut count = 0;
+    let device = SimulatedDevice::new()
+        .emit_every(Duration::from_millis(10), move || {
+            count += 1;
+            Message::U32(message_types::U32 { num: count })
+        })
+        .spawn(SerialManager::new(device))
+        .unwrap();
+
+    let mut host = SerialManager::new(host);
+    for num in 1..=3 {
+        assert_eq!(
+            host.receive().unwrap(),
+            Message::U32(message_types::U32 { num })
+        );
+    }
+    // Closing the connection stops the device
+    host.close().unwrap();
+    assert!(device.join().unwrap().is_empty());
+}
+
+#[test]
+fn test_simulated_device_answers_ping_and_identify() {
+    let (host, device) = NoisyChannel::new().pair();
+    let info = message_types::DeviceInfo {
+        device_id: 7,
+        name: "simulated".to_string(),
+        hw_rev: 1,
+        fw_version: "0.1.0".to_string(),
+        capabilities: Capabilities::PING,
+    };
+    let device = SimulatedDevice::new()
+        .with_device_info(info.clone())
+        .spawn(SerialManager::new(device))
+        .unwrap();
+
+    let mut host = SerialManager::new(host);
+    host.send(Message::Ping(message_types::Ping { sequence: 9 }))
+        .unwrap();
+    assert_eq!(
+        host.receive().unwrap(),
+        Message::Pong(message_types::Pong { sequence: 9 })
+    );
+    host.send(Message::Identify(message_types::Identify {}))
+        .unwrap();
+    assert_eq!(host.receive().unwrap(), Message::DeviceInfo(info));
+    assert_eq!(device.stop().unwrap().len(), 2);
+}
+
+#[test]
+fn test_exchange() {
+    let link = Exchange::new()
+        .then_receive(Message::Ping(message_types::Ping { sequence: 1 }))
+        .expect_send(Message::Pong(message_types::Pong { sequence: 1 }))
+        .expect_send(Message::U8(message_types::U8 { num: 5 }))
+        .then_receive(Message::Status(message_types::Status::Ok))
+        .link();
+    let mut manager = SerialManager::new(link.clone());
+
+    assert_eq!(
+        manager.receive().unwrap(),
+        Message::Ping(message_types::Ping { sequence: 1 })
+    );
+    // Nothing is ready until the next message is sent
+    assert!(matches!(
+        manager.receive(),
+        Err(ReceiveError::Io(e)) if e.kind() == io::ErrorKind::TimedOut
+    ));
+    manager
+        .send(Message::Pong(message_types::Pong { sequence: 1 }))
+        .unwrap();
+    manager
+        .send(Message::U8(message_types::U8 { num: 5 }))
+        .unwrap();
+    assert!(link
+        .mismatch()
+        .unwrap()
+        .contains("1 message(s) were never read"));
+    assert_eq!(
+        manager.receive().unwrap(),
+        Message::Status(message_types::Status::Ok)
+    );
+    link.assert_done();
+}
+
+#[test]
+fn test_exchange_reports_diff() {
+    let link = Exchange::new()
+        .expect_send(Message::U8(message_types::U8 { num: 5 }))
+        .then_receive(Message::Status(message_types::Status::Ok))
+        .link();
+    let mut manager = SerialManager::new(link.clone());
+
+    let error = manager
+        .send(Message::U8(message_types::U8 { num: 6 }))
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let mismatch = link.mismatch().unwrap();
+    assert_eq!(error.to_string(), mismatch);
+    assert_eq!(
+        mismatch,
+        "the application deviated from the expected exchange at step 1: it sent a different \
+         message (- expected, + sent):\n\
+         \x20 U8(\n\
+         \x20     U8 {\n\
+         -         num: 5,\n\
+         +         num: 6,\n\
+         \x20     },\n\
+         \x20 )\n\
+         \n\
+         > 1. send    U8(U8 { num: 5 })\n\
+         \x20 2. receive Status(Ok)"
+    );
+    // The deviation sticks
+    assert!(manager
+        .send(Message::U8(message_types::U8 { num: 5 }))
+        .is_err());
+}
+
+#[test]
+fn test_exchange_reports_extra_message() {
+    let link = Exchange::new().link();
+    let mut manager = SerialManager::new(link.clone());
+    assert!(link.mismatch().is_none());
+    assert!(manager.send(Message::NoOp(message_types::NoOp {})).is_err());
+    let mismatch = link.mismatch().unwrap();
+    assert!(
+        mismatch.contains("sent NoOp(NoOp) after the exchange ended"),
+        "{mismatch}"
+    );
+}
+
+#[test]
+#[should_panic(expected = "the exchange did not finish")]
+fn test_exchange_assert_done_panics() {
+    Exchange::new()
+        .expect_send(Message::NoOp(message_types::NoOp {}))
+        .link()
+        .assert_done();
+}
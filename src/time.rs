@@ -0,0 +1,10 @@
+//! The clocks read by the crate
+//!
+//! `Instant::now` and `SystemTime::now` of `std::time` panic on `wasm32-unknown-unknown`, which
+//! has no clock of its own, so there the browser's clocks are read through `web-time`, whose
+//! types work like those of `std::time`. Everywhere else these are the `std::time` types.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
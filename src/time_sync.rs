@@ -0,0 +1,41 @@
+use crate::time::{SystemTime, UNIX_EPOCH};
+use std::time::Duration;
+
+/// The outcome of a time synchronisation exchange with the peer, as returned by
+/// [`SerialManager::sync_time`](crate::SerialManager::sync_time).
+///
+/// The offset is estimated as in SNTP, assuming the request and response took equally long.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TimeSync {
+    /// The peer's clock minus the local wall clock, in microseconds
+    pub offset_micros: i64,
+    /// The time the exchange took, excluding the time the peer took to reply
+    pub round_trip: Duration,
+}
+
+impl TimeSync {
+    /// Estimates the offset from the local transmit time `origin`, the peer's `receive` and
+    /// `transmit` times and the local time `destination` the response arrived, all in
+    /// microseconds
+    #[must_use]
+    pub fn from_timestamps(origin: u64, receive: u64, transmit: u64, destination: u64) -> Self {
+        let difference = |a: u64, b: u64| a.cast_signed().saturating_sub(b.cast_signed());
+        let offset_micros =
+            difference(receive, origin).saturating_add(difference(transmit, destination)) / 2;
+        let round_trip = destination
+            .saturating_sub(origin)
+            .saturating_sub(transmit.saturating_sub(receive));
+        Self {
+            offset_micros,
+            round_trip: Duration::from_micros(round_trip),
+        }
+    }
+}
+
+/// The local wall clock, in microseconds since the Unix epoch
+pub(crate) fn now_micros() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    u64::try_from(since_epoch.as_micros()).unwrap_or(u64::MAX)
+}
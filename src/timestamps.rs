@@ -0,0 +1,207 @@
+use crate::time::SystemTime;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Receive timestamps taken by the transport, such as by the kernel or a network card, shared
+/// with a [`SerialManager`](crate::SerialManager) through
+/// [`with_receive_clock`](crate::SerialManager::with_receive_clock)
+///
+/// After every read from its connection, the manager takes the timestamp recorded for it, if
+/// any, and uses it in place of the local time for the frames completed by the bytes read, as
+/// returned by [`receive_timestamped`](crate::SerialManager::receive_timestamped). The
+/// transport records one timestamp per read, for the last bytes it returned. Transports without
+/// timestamps of their own, such as serial ports, whose drivers do not timestamp received bytes,
+/// can still record ones from their own source.
+#[derive(Debug, Clone, Default)]
+pub struct ReceiveClock {
+    last: Arc<Mutex<Option<SystemTime>>>,
+}
+
+impl ReceiveClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the time the bytes of the last read were received
+    pub fn record(&self, time: SystemTime) {
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Some(time);
+    }
+
+    /// Takes the time recorded for the last read, if one was
+    pub(crate) fn take(&self) -> Option<SystemTime> {
+        self.last
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use kernel::KernelTimestamps;
+
+#[cfg(target_os = "linux")]
+mod kernel {
+    use super::ReceiveClock;
+    use crate::serial_manager::TryClone;
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, SystemTime};
+
+    /// Timestamp sources requested from the kernel: software timestamps taken as packets arrive,
+    /// and hardware timestamps from network cards that have them enabled
+    const FLAGS: libc::c_uint = libc::SOF_TIMESTAMPING_RX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_SOFTWARE
+        | libc::SOF_TIMESTAMPING_RX_HARDWARE
+        | libc::SOF_TIMESTAMPING_RAW_HARDWARE;
+
+    /// A socket, such as a `TcpStream` or `UnixStream`, read with the kernel's receive
+    /// timestamps (`SO_TIMESTAMPING`)
+    ///
+    /// Every read records the time the kernel received the last bytes returned in the socket's
+    /// [`ReceiveClock`], which a [`SerialManager`](crate::SerialManager) on the socket uses for
+    /// [`receive_timestamped`](crate::SerialManager::receive_timestamped) once given it. A
+    /// hardware timestamp from the network card is used if there is one, and the kernel's
+    /// software timestamp otherwise. Hardware timestamping must be enabled on the interface
+    /// beforehand, e.g. with `hwstamp_ctl`. The kernel only starts timestamping packets shortly
+    /// after timestamps are first enabled on any socket, so the first frames may keep the local
+    /// time.
+    ///
+    /// ```no_run
+    /// use generic_serial_protocol::{KernelTimestamps, SerialManager};
+    /// use std::net::TcpStream;
+    ///
+    /// let socket = KernelTimestamps::new(TcpStream::connect("192.168.1.20:5000").unwrap()).unwrap();
+    /// let clock = socket.clock();
+    /// let mut manager = SerialManager::new(socket).with_receive_clock(clock);
+    /// let (message, received_at) = manager.receive_timestamped().unwrap();
+    /// ```
+    #[derive(Debug)]
+    pub struct KernelTimestamps<S> {
+        socket: S,
+        clock: ReceiveClock,
+    }
+
+    impl<S: AsRawFd> KernelTimestamps<S> {
+        /// Enables receive timestamps on `socket`
+        // The size of a c_uint fits in a socklen_t
+        #[allow(clippy::cast_possible_truncation)]
+        pub fn new(socket: S) -> io::Result<Self> {
+            let flags = FLAGS;
+            // SAFETY: the option value is a c_uint, as SO_TIMESTAMPING expects
+            let result = unsafe {
+                libc::setsockopt(
+                    socket.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_TIMESTAMPING,
+                    (&raw const flags).cast(),
+                    mem::size_of_val(&flags) as libc::socklen_t,
+                )
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {
+                socket,
+                clock: ReceiveClock::new(),
+            })
+        }
+
+        /// Returns the clock the timestamps are recorded in
+        #[must_use]
+        pub fn clock(&self) -> ReceiveClock {
+            self.clock.clone()
+        }
+
+        #[must_use]
+        pub fn get_ref(&self) -> &S {
+            &self.socket
+        }
+
+        #[must_use]
+        pub fn into_inner(self) -> S {
+            self.socket
+        }
+    }
+
+    impl<S: AsRawFd> Read for KernelTimestamps<S> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut iov = libc::iovec {
+                iov_base: buf.as_mut_ptr().cast(),
+                iov_len: buf.len(),
+            };
+            // Room for a few control messages, aligned for cmsghdr
+            let mut control = [0u64; 32];
+            // SAFETY: an all-zero msghdr is valid, with no name, buffers or control messages
+            let mut header: libc::msghdr = unsafe { mem::zeroed() };
+            header.msg_iov = &raw mut iov;
+            header.msg_iovlen = 1;
+            header.msg_control = control.as_mut_ptr().cast();
+            header.msg_controllen = mem::size_of_val(&control);
+            // SAFETY: the header points to `buf` and `control`, which outlive the call
+            let count = unsafe { libc::recvmsg(self.socket.as_raw_fd(), &raw mut header, 0) };
+            let Ok(count) = usize::try_from(count) else {
+                return Err(io::Error::last_os_error());
+            };
+            if let Some(time) = receive_time(&header) {
+                self.clock.record(time);
+            }
+            Ok(count)
+        }
+    }
+
+    /// Finds the timestamp in the control messages of a received message, preferring a hardware
+    /// one
+    fn receive_time(header: &libc::msghdr) -> Option<SystemTime> {
+        // SAFETY: the header was filled in by recvmsg, so its control messages are well formed
+        let mut message = unsafe { libc::CMSG_FIRSTHDR(header) };
+        while !message.is_null() {
+            // SAFETY: non-null control message headers returned by CMSG_* are in bounds
+            let control = unsafe { &*message };
+            if control.cmsg_level == libc::SOL_SOCKET && control.cmsg_type == libc::SO_TIMESTAMPING
+            {
+                // SAFETY: SCM_TIMESTAMPING carries three timespecs: software, deprecated and raw
+                // hardware, which may not be aligned in the buffer
+                let times: [libc::timespec; 3] = unsafe {
+                    libc::CMSG_DATA(message)
+                        .cast::<[libc::timespec; 3]>()
+                        .read_unaligned()
+                };
+                return [times[2], times[0]].into_iter().find_map(system_time);
+            }
+            // SAFETY: as above
+            message = unsafe { libc::CMSG_NXTHDR(header, message) };
+        }
+        None
+    }
+
+    /// Converts a timestamp, with zero meaning none was taken
+    fn system_time(time: libc::timespec) -> Option<SystemTime> {
+        let seconds = u64::try_from(time.tv_sec).ok()?;
+        let nanos = u32::try_from(time.tv_nsec).ok()?;
+        if seconds == 0 && nanos == 0 {
+            return None;
+        }
+        Some(SystemTime::UNIX_EPOCH + Duration::new(seconds, nanos))
+    }
+
+    impl<S: Write> Write for KernelTimestamps<S> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.socket.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.socket.flush()
+        }
+    }
+
+    /// Clones share the clock
+    impl<S: TryClone> TryClone for KernelTimestamps<S> {
+        fn try_clone(&self) -> io::Result<Self> {
+            Ok(Self {
+                socket: self.socket.try_clone()?,
+                clock: self.clock.clone(),
+            })
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::codec::{Endianness, MAX_PAYLOAD_LENGTH};
+use crate::errors::TransactionError;
+use crate::message::{message_types, Message};
+use crate::serial_manager::{SerialManager, Transport};
+use crate::time::Instant;
+use message_types::{Status, TransactionAbort, TransactionCommit, TransactionData};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::time::Duration;
+
+/// The most payload bytes a message of a transaction can have, leaving room for the transaction
+/// ID and the message type
+pub const MAX_TRANSACTION_PAYLOAD: usize = MAX_PAYLOAD_LENGTH - 4;
+
+/// Sends a group of messages that the peer applies together or not at all, such as the parts of
+/// a configuration update that only make sense as a whole.
+///
+/// The exchange is:
+/// 1. A `TransactionData` wrapping each message, sent by [`send`](Self::send), which the peer
+///    holds without applying and does not reply to.
+/// 2. `TransactionCommit` with the number of messages, sent by [`commit`](Self::commit). The
+///    peer replies with `Status::Ok` once it has every message and applies them together, or
+///    `Status::Error` if it lost any of them or no longer holds the transaction, in which case
+///    it applies none. It may send `Status::Pending` while it works.
+///
+/// [`abort`](Self::abort) sends `TransactionAbort` instead, and the peer discards the messages.
+/// So does a peer that hears nothing more of a transaction for a while, such as when the sender
+/// is dropped without committing.
+///
+/// ```no_run
+/// use generic_serial_protocol::{message_types, Message, SerialManager, Transaction};
+///
+/// let mut manager = SerialManager::connect_tcp("192.168.1.20:5000").unwrap();
+/// let mut transaction = Transaction::begin(&mut manager, 7);
+/// transaction.send(Message::U32(message_types::U32 { num: 115_200 })).unwrap();
+/// transaction.send(Message::U8(message_types::U8 { num: 3 })).unwrap();
+/// transaction.commit().unwrap();
+/// ```
+pub struct Transaction<'a, T>
+where
+    T: Transport,
+{
+    manager: &'a mut SerialManager<T>,
+    id: u16,
+    count: u16,
+}
+
+impl<'a, T> Transaction<'a, T>
+where
+    T: Transport,
+{
+    /// Starts the transaction `id` on `manager`, which must not be reused while the peer may
+    /// still hold an earlier transaction with it
+    pub fn begin(manager: &'a mut SerialManager<T>, id: u16) -> Self {
+        Self {
+            manager,
+            id,
+            count: 0,
+        }
+    }
+
+    /// Returns the ID of the transaction
+    #[must_use]
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the number of messages sent so far
+    #[must_use]
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    /// Sends a message for the peer to hold until the transaction is committed
+    ///
+    /// Fails without sending the message if its payload is longer than
+    /// [`MAX_TRANSACTION_PAYLOAD`], or if the transaction already holds 65535 messages.
+    pub fn send(&mut self, message: Message) -> Result<(), TransactionError> {
+        let count = self
+            .count
+            .checked_add(1)
+            .ok_or(TransactionError::TooManyMessages)?;
+        let message_type = message.message_type();
+        let data = message
+            .to_bytes_with(self.manager.endianness())
+            .map_err(io::Error::from)?;
+        if data.len() > MAX_TRANSACTION_PAYLOAD {
+            return Err(TransactionError::PayloadTooLarge(data.len()));
+        }
+        self.manager
+            .send(Message::TransactionData(TransactionData {
+                id: self.id,
+                message_type,
+                data,
+            }))?;
+        self.count = count;
+        Ok(())
+    }
+
+    /// Commits the transaction, returning once the peer has accepted every message
+    pub fn commit(self) -> Result<(), TransactionError> {
+        self.manager
+            .send(Message::TransactionCommit(TransactionCommit {
+                id: self.id,
+                count: self.count,
+            }))?;
+        loop {
+            match self.manager.receive()? {
+                Message::Status(Status::Ok) => return Ok(()),
+                Message::Status(Status::Pending) => (),
+                Message::Status(Status::Error) => return Err(TransactionError::Rejected),
+                message => return Err(TransactionError::UnexpectedMessage(message.message_type())),
+            }
+        }
+    }
+
+    /// Tells the peer to discard the messages sent so far
+    pub fn abort(self) -> io::Result<()> {
+        self.manager
+            .send(Message::TransactionAbort(TransactionAbort { id: self.id }))
+    }
+}
+
+/// The messages of a transaction received so far
+#[derive(Debug)]
+struct Pending {
+    messages: Vec<Message>,
+    /// Whether a message failed to decode
+    invalid: bool,
+    /// When the last message of the transaction was received
+    updated: Instant,
+}
+
+/// The receiving side of a [`Transaction`], for peers implemented in Rust.
+///
+/// Received messages are passed to [`handle`](Self::handle), which returns the reply to send.
+/// The messages of a transaction are held until it is committed with all of them received, and
+/// then returned together by [`take_committed`](Self::take_committed) for the application to
+/// apply. Transactions that are aborted, or that hear nothing more for the timeout, 5 seconds
+/// unless set otherwise, are discarded.
+#[derive(Debug)]
+pub struct TransactionReceiver {
+    endianness: Endianness,
+    timeout: Duration,
+    pending: BTreeMap<u16, Pending>,
+    committed: VecDeque<Vec<Message>>,
+}
+
+impl Default for TransactionReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransactionReceiver {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            endianness: Endianness::Little,
+            timeout: Duration::from_secs(5),
+            pending: BTreeMap::new(),
+            committed: VecDeque::new(),
+        }
+    }
+
+    /// Decodes the messages of transactions in the given byte order, which must be that of the
+    /// connection they arrive on
+    #[must_use]
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Discards transactions that hear nothing more for `timeout`
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Handles a transaction message, returning the reply, or `None` for `TransactionData`,
+    /// `TransactionAbort` and other messages
+    pub fn handle(&mut self, message: &Message) -> Option<Message> {
+        let now = Instant::now();
+        self.pending
+            .retain(|_, pending| now.duration_since(pending.updated) < self.timeout);
+        let reply = match message {
+            Message::TransactionData(data) => {
+                let pending = self.pending.entry(data.id).or_insert_with(|| Pending {
+                    messages: Vec::new(),
+                    invalid: false,
+                    updated: now,
+                });
+                pending.updated = now;
+                match Message::from_bytes_with(
+                    data.message_type,
+                    data.data.clone(),
+                    self.endianness,
+                ) {
+                    Ok(message) => pending.messages.push(message),
+                    Err(_) => pending.invalid = true,
+                }
+                return None;
+            }
+            Message::TransactionCommit(commit) => match self.pending.remove(&commit.id) {
+                Some(pending)
+                    if !pending.invalid && pending.messages.len() == usize::from(commit.count) =>
+                {
+                    self.committed.push_back(pending.messages);
+                    Message::Status(Status::Ok)
+                }
+                // An empty transaction has nothing to apply
+                None if commit.count == 0 => Message::Status(Status::Ok),
+                _ => Message::Status(Status::Error),
+            },
+            Message::TransactionAbort(abort) => {
+                self.pending.remove(&abort.id);
+                return None;
+            }
+            _ => return None,
+        };
+        Some(reply)
+    }
+
+    /// Returns the messages of the oldest committed transaction not yet taken, in the order they
+    /// were sent
+    pub fn take_committed(&mut self) -> Option<Vec<Message>> {
+        self.committed.pop_front()
+    }
+
+    /// Returns the number of transactions received but not yet committed, aborted or timed out
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests;
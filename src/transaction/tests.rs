@@ -0,0 +1,129 @@
+use super::*;
+use crate::test_util::{stream_pair, TestStream};
+use std::thread::{self, JoinHandle};
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+/// Runs `receiver` on the other end of the returned connection until the connection is closed
+fn spawn_peer(
+    mut receiver: TransactionReceiver,
+) -> (SerialManager<TestStream>, JoinHandle<TransactionReceiver>) {
+    let (stream1, stream2) = stream_pair();
+    let peer = thread::spawn(move || {
+        let mut manager = SerialManager::new(stream2);
+        while let Ok(message) = manager.receive() {
+            if let Some(reply) = receiver.handle(&message) {
+                manager.send(reply).unwrap();
+            }
+        }
+        receiver
+    });
+    (SerialManager::new(stream1), peer)
+}
+
+#[test]
+fn test_committed_messages_are_applied_together() {
+    let (mut manager, peer) = spawn_peer(TransactionReceiver::new());
+
+    let mut transaction = Transaction::begin(&mut manager, 7);
+    transaction.send(u8_message(1)).unwrap();
+    transaction.send(u8_message(2)).unwrap();
+    assert_eq!(transaction.count(), 2);
+    transaction.commit().unwrap();
+
+    let mut aborted = Transaction::begin(&mut manager, 8);
+    aborted.send(u8_message(3)).unwrap();
+    aborted.abort().unwrap();
+
+    drop(manager);
+    let mut receiver = peer.join().unwrap();
+    assert_eq!(
+        receiver.take_committed(),
+        Some(vec![u8_message(1), u8_message(2)])
+    );
+    assert_eq!(receiver.take_committed(), None);
+    assert_eq!(receiver.pending(), 0);
+}
+
+#[test]
+fn test_transaction_missing_a_message_is_rejected() {
+    let (mut manager, peer) = spawn_peer(TransactionReceiver::new());
+
+    manager
+        .send(Message::TransactionData(TransactionData {
+            id: 1,
+            message_type: 1,
+            data: vec![5],
+        }))
+        .unwrap();
+    // The commit claims a second message, which was lost
+    let transaction = Transaction {
+        manager: &mut manager,
+        id: 1,
+        count: 2,
+    };
+    assert!(matches!(
+        transaction.commit(),
+        Err(TransactionError::Rejected)
+    ));
+
+    drop(manager);
+    let mut receiver = peer.join().unwrap();
+    assert_eq!(receiver.take_committed(), None);
+    assert_eq!(receiver.pending(), 0);
+}
+
+#[test]
+fn test_receiver_discards_invalid_and_stale_transactions() {
+    let mut receiver = TransactionReceiver::new().with_timeout(Duration::from_millis(50));
+    let data = |id, data| {
+        Message::TransactionData(TransactionData {
+            id,
+            message_type: 1,
+            data,
+        })
+    };
+    let commit = |id, count| Message::TransactionCommit(TransactionCommit { id, count });
+
+    // A U8 with two bytes of payload fails to decode
+    assert_eq!(receiver.handle(&data(1, vec![1, 2])), None);
+    assert_eq!(receiver.handle(&data(1, vec![3])), None);
+    assert_eq!(
+        receiver.handle(&commit(1, 2)),
+        Some(Message::Status(Status::Error))
+    );
+
+    assert_eq!(receiver.handle(&data(2, vec![4])), None);
+    assert_eq!(receiver.pending(), 1);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        receiver.handle(&commit(2, 1)),
+        Some(Message::Status(Status::Error))
+    );
+
+    // Nothing to apply, but nothing lost either
+    assert_eq!(
+        receiver.handle(&commit(3, 0)),
+        Some(Message::Status(Status::Ok))
+    );
+    assert_eq!(receiver.handle(&u8_message(1)), None);
+    assert_eq!(receiver.take_committed(), None);
+}
+
+#[test]
+fn test_oversized_messages_are_not_sent() {
+    let (stream1, _stream2) = stream_pair();
+    let mut manager = SerialManager::new(stream1);
+    let mut transaction = Transaction::begin(&mut manager, 1);
+
+    let message = Message::Bytes(message_types::Bytes {
+        data: vec![0; MAX_TRANSACTION_PAYLOAD + 1],
+    });
+    assert!(matches!(
+        transaction.send(message),
+        Err(TransactionError::PayloadTooLarge(length)) if length == MAX_TRANSACTION_PAYLOAD + 1
+    ));
+    assert_eq!(transaction.count(), 0);
+}
@@ -0,0 +1,238 @@
+//! Golden test vectors: messages and the exact bytes they are sent as.
+//!
+//! Every vector is a built-in message encoded in one framing and byte order. The same vectors
+//! are checked by this crate's tests against what [`SerialManager`](crate::SerialManager) sends
+//! and receives, and are shipped in `vectors/golden.txt`, so that implementations in other
+//! languages can check theirs against them too. [`export`] writes them in that file's format,
+//! which `gsp-cli gen vectors <file>` also writes.
+//!
+//! [`mutate`] turns a valid frame into a corpus of damaged ones, with bits flipped, truncated,
+//! with false starts inserted and escapes doubled, each annotated with what the decoder makes of
+//! it, so that error handling can be regression tested the way this crate's tests do.
+//!
+//! ```
+//! use generic_serial_protocol::{message_types, vectors, Endianness, Framing, Message};
+//!
+//! let vector = vectors::golden()
+//!     .into_iter()
+//!     .find(|vector| vector.name == "u16" && vector.framing == Framing::Native)
+//!     .unwrap();
+//! assert_eq!(vector.endianness, Endianness::Little);
+//! assert_eq!(vector.message, Message::U16(message_types::U16 { num: 0x1234 }));
+//! assert_eq!(vector.frame, [0x58, 0x04, 0x00, 0x05, 0x00, 0x34, 0x12]);
+//! ```
+
+use crate::codec::{Endianness, Framing, ESCAPE_BYTE, SLIP_END, SLIP_ESC, START_BYTE};
+use crate::message::{message_types, Capabilities, Message};
+use crate::payload::Varint;
+use std::fmt::Write;
+
+mod mutations;
+
+pub use mutations::{export_mutants, mutate, Mutant, Mutation, Outcome};
+
+/// The framings and byte orders every message is encoded in
+const CONFIGURATIONS: [(Framing, Endianness); 8] = [
+    (Framing::Native, Endianness::Little),
+    (Framing::Native, Endianness::Big),
+    (Framing::Slip, Endianness::Little),
+    (Framing::Cobs, Endianness::Little),
+    (Framing::Compact, Endianness::Little),
+    (Framing::Extended, Endianness::Little),
+    (Framing::Binary, Endianness::Little),
+    (Framing::CheckedHeader, Endianness::Little),
+];
+
+/// A message and the frame it is sent as
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector {
+    /// Describes the message, and is the same for every framing and byte order it is encoded in
+    pub name: &'static str,
+    pub framing: Framing,
+    pub endianness: Endianness,
+    pub message: Message,
+    /// The encoded payload, without the message type
+    pub payload: Vec<u8>,
+    /// The whole frame, as sent on the wire
+    pub frame: Vec<u8>,
+}
+
+/// Returns every golden vector, each message in each framing and byte order
+#[must_use]
+pub fn golden() -> Vec<Vector> {
+    let messages = messages();
+    CONFIGURATIONS
+        .iter()
+        .flat_map(|&(framing, endianness)| {
+            messages.iter().map(move |(name, message)| {
+                let payload = message
+                    .clone()
+                    .to_bytes_with(endianness)
+                    .unwrap_or_else(|e| unreachable!("golden message does not encode: {e}"));
+                let frame = framing.encode(message.message_type(), &payload, endianness);
+                Vector {
+                    name,
+                    framing,
+                    endianness,
+                    message: message.clone(),
+                    payload,
+                    frame,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Returns the golden vectors as text, one vector a line, as shipped in `vectors/golden.txt`
+///
+/// Lines starting with `#` are comments. Every other line holds the name, framing, byte order,
+/// message type, payload and frame of a vector, separated by spaces. Framings and byte orders
+/// are written in lower case, such as `native` and `little`, message types in decimal, and
+/// payloads and frames in hex, with `-` for an empty payload.
+#[must_use]
+pub fn export() -> String {
+    let mut text = String::from(
+        "# Golden test vectors for the generic serial protocol, written by `gsp-cli gen vectors`\n\
+         #\n\
+         # name framing endianness message_type payload frame\n",
+    );
+    for vector in golden() {
+        let payload = if vector.payload.is_empty() {
+            "-".to_string()
+        } else {
+            hex(&vector.payload)
+        };
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            text,
+            "{} {} {} {} {payload} {}",
+            vector.name,
+            framing_name(vector.framing),
+            endianness_name(vector.endianness),
+            vector.message.message_type(),
+            hex(&vector.frame),
+        );
+    }
+    text
+}
+
+/// The messages encoded in every configuration, covering every field type and the bytes each
+/// framing has to escape
+fn messages() -> Vec<(&'static str, Message)> {
+    vec![
+        ("noop", Message::NoOp(message_types::NoOp {})),
+        ("u8", Message::U8(message_types::U8 { num: 0x57 })),
+        ("u16", Message::U16(message_types::U16 { num: 0x1234 })),
+        ("u32", Message::U32(message_types::U32 { num: 0x1234_5678 })),
+        (
+            "u64",
+            Message::U64(message_types::U64 {
+                num: 0x0123_4567_89AB_CDEF,
+            }),
+        ),
+        ("i16", Message::I16(message_types::I16 { num: -2 })),
+        ("i64", Message::I64(message_types::I64 { num: -0x1234 })),
+        ("f32", Message::F32(message_types::F32 { num: 1.5 })),
+        ("f64", Message::F64(message_types::F64 { num: -0.25 })),
+        ("bool", Message::Bool(message_types::Bool { value: true })),
+        (
+            "bytes",
+            Message::Bytes(message_types::Bytes {
+                data: vec![1, 2, 3, 4, 5],
+            }),
+        ),
+        (
+            "bytes-escaped",
+            Message::Bytes(message_types::Bytes {
+                data: vec![START_BYTE, ESCAPE_BYTE, SLIP_END, SLIP_ESC, 0x00],
+            }),
+        ),
+        (
+            "string",
+            Message::MyString(message_types::MyString {
+                string: "héllo".to_string(),
+            }),
+        ),
+        (
+            "multi",
+            Message::Multi(message_types::Multi {
+                num: 0x41,
+                string: "test".to_string(),
+            }),
+        ),
+        ("status", Message::Status(message_types::Status::Pending)),
+        (
+            "u16array",
+            Message::U16Array(message_types::U16Array {
+                values: vec![1, 0x0058, 0xFFFF],
+            }),
+        ),
+        (
+            "settings",
+            Message::Settings(message_types::Settings {
+                baud_rate: Some(115_200),
+                retries: None,
+            }),
+        ),
+        (
+            "counter",
+            Message::Counter(message_types::Counter { count: Varint(300) }),
+        ),
+        (
+            "log",
+            Message::Log(message_types::Log {
+                level: message_types::Level::Info,
+                module: "main".to_string(),
+                text: "ready".to_string(),
+            }),
+        ),
+        (
+            "response",
+            Message::Response(message_types::Response {
+                id: 7,
+                status: message_types::Status::Ok,
+                payload: vec![0xAA],
+            }),
+        ),
+        (
+            "deviceinfo",
+            Message::DeviceInfo(message_types::DeviceInfo {
+                device_id: 0x1234,
+                name: "sensor".to_string(),
+                hw_rev: 2,
+                fw_version: "1.4.2".to_string(),
+                capabilities: Capabilities::PING | Capabilities::LOG,
+            }),
+        ),
+        ("goodbye", Message::Goodbye(message_types::Goodbye {})),
+    ]
+}
+
+fn framing_name(framing: Framing) -> &'static str {
+    match framing {
+        Framing::Native => "native",
+        Framing::Slip => "slip",
+        Framing::Cobs => "cobs",
+        Framing::Compact => "compact",
+        Framing::Extended => "extended",
+        Framing::Binary => "binary",
+        Framing::CheckedHeader => "checked-header",
+    }
+}
+
+fn endianness_name(endianness: Endianness) -> &'static str {
+    match endianness {
+        Endianness::Little => "little",
+        Endianness::Big => "big",
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{byte:02x}");
+        text
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,256 @@
+use super::{endianness_name, framing_name, golden, hex, Vector};
+use crate::codec::{
+    Decoder, DecoderEvent, Endianness, Frame, Framing, ESCAPE_BYTE, SLIP_END, SLIP_ESC, START_BYTE,
+};
+use std::fmt::{self, Write};
+
+/// A change made to a valid frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// Bit `bit` of the byte at `offset` was flipped, as by line noise
+    FlipBit { offset: usize, bit: u8 },
+    /// Only the first `length` bytes were kept, as when a sender resets mid-frame
+    Truncate { length: usize },
+    /// The framing's delimiter, such as the start byte, was inserted at `offset`, as a false
+    /// start would be
+    InsertStart { offset: usize },
+    /// The escape byte at `offset` was sent twice
+    DoubleEscape { offset: usize },
+}
+
+impl fmt::Display for Mutation {
+    /// Formats the mutation as in [`export_mutants`], such as `flip-bit:5:3`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mutation::FlipBit { offset, bit } => write!(f, "flip-bit:{offset}:{bit}"),
+            Mutation::Truncate { length } => write!(f, "truncate:{length}"),
+            Mutation::InsertStart { offset } => write!(f, "insert-start:{offset}"),
+            Mutation::DoubleEscape { offset } => write!(f, "double-escape:{offset}"),
+        }
+    }
+}
+
+/// What became of a mutated frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The decoder reported the damage with an event, such as a resync or checksum mismatch, and
+    /// delivered no frame in its place
+    Detected,
+    /// The decoder delivered a frame differing from the original, as nothing in the framing
+    /// could catch the change
+    Undetected,
+    /// The frame vanished without any event, such as when it was swallowed by a corrupted length
+    /// field
+    Lost,
+    /// The original frame was delivered regardless, as the mutation only touched bytes the
+    /// decoder skips
+    Unaffected,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Detected => "detected",
+            Outcome::Undetected => "undetected",
+            Outcome::Lost => "lost",
+            Outcome::Unaffected => "unaffected",
+        })
+    }
+}
+
+/// A mutated frame, with what a [`Decoder`] with the framing's default settings makes of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mutant {
+    pub framing: Framing,
+    pub endianness: Endianness,
+    pub mutation: Mutation,
+    /// The mutated frame, followed by the original frame, so that recovering from the damage
+    /// can be checked too
+    pub bytes: Vec<u8>,
+    /// The events the decoder produces for `bytes`, in order
+    pub events: Vec<DecoderEvent>,
+    pub outcome: Outcome,
+    /// Whether the original frame following the mutated one was received
+    pub recovered: bool,
+}
+
+impl Vector {
+    /// Returns every mutation of the vector's frame, as [`mutate`] does
+    #[must_use]
+    pub fn mutants(&self) -> Vec<Mutant> {
+        mutate(&self.frame, self.framing, self.endianness)
+    }
+}
+
+/// Systematically mutates a valid frame sent with `framing` and `endianness`: every bit flipped
+/// in turn, every truncation, the framing's delimiter inserted before every byte but the first,
+/// and every escape byte doubled
+///
+/// Each mutant is annotated with what the decoder makes of it, so that an application can check
+/// its own error handling, such as counting errors or requesting retransmissions, against a
+/// known result:
+///
+/// ```
+/// use generic_serial_protocol::vectors::{self, Mutation, Outcome};
+/// use generic_serial_protocol::{Endianness, Framing};
+///
+/// // A U16 frame cut short before its payload
+/// let frame = Framing::Native.encode(5, &[0x34, 0x12], Endianness::Little);
+/// let mutant = vectors::mutate(&frame, Framing::Native, Endianness::Little)
+///     .into_iter()
+///     .find(|mutant| mutant.mutation == Mutation::Truncate { length: 5 })
+///     .unwrap();
+/// assert_eq!(mutant.outcome, Outcome::Detected);
+/// assert!(mutant.recovered);
+/// ```
+#[must_use]
+pub fn mutate(frame: &[u8], framing: Framing, endianness: Endianness) -> Vec<Mutant> {
+    let mut mutations = Vec::new();
+    for offset in 0..frame.len() {
+        for bit in 0..8 {
+            mutations.push(Mutation::FlipBit { offset, bit });
+        }
+    }
+    mutations.extend((1..frame.len()).map(|length| Mutation::Truncate { length }));
+    mutations.extend((1..frame.len()).map(|offset| Mutation::InsertStart { offset }));
+    if let Some(escape) = escape_byte(framing) {
+        mutations.extend(
+            (0..frame.len())
+                .filter(|&offset| frame[offset] == escape)
+                .map(|offset| Mutation::DoubleEscape { offset }),
+        );
+    }
+
+    let original = decode(frame, framing, endianness)
+        .into_iter()
+        .find_map(|event| match event {
+            DecoderEvent::Frame(frame) => Some(frame),
+            _ => None,
+        });
+    mutations
+        .into_iter()
+        .map(|mutation| {
+            let mut bytes = apply(frame, framing, mutation);
+            bytes.extend_from_slice(frame);
+            let events = decode(&bytes, framing, endianness);
+            let recovered = matches!(
+                (events.last(), &original),
+                (Some(DecoderEvent::Frame(last)), Some(original)) if last == original
+            );
+            let outcome = classify(
+                &events[..events.len() - usize::from(recovered)],
+                original.as_ref(),
+            );
+            Mutant {
+                framing,
+                endianness,
+                mutation,
+                bytes,
+                events,
+                outcome,
+                recovered,
+            }
+        })
+        .collect()
+}
+
+/// Returns the mutants of every golden vector as text, one mutant a line
+///
+/// Lines starting with `#` are comments. Every other line holds the name, framing and byte order
+/// of the vector mutated, the mutation, the mutated bytes followed by the original frame in hex,
+/// the outcome, and `recovered` or `lost` for the original frame. `gsp-cli gen mutants <file>`
+/// writes the same text.
+#[must_use]
+pub fn export_mutants() -> String {
+    let mut text = String::from(
+        "# Mutated frames for the generic serial protocol, written by `gsp-cli gen mutants`\n\
+         #\n\
+         # name framing endianness mutation bytes outcome recovery\n",
+    );
+    for vector in golden() {
+        for mutant in vector.mutants() {
+            // Writing to a String cannot fail
+            let _ = writeln!(
+                text,
+                "{} {} {} {} {} {} {}",
+                vector.name,
+                framing_name(vector.framing),
+                endianness_name(vector.endianness),
+                mutant.mutation,
+                hex(&mutant.bytes),
+                mutant.outcome,
+                if mutant.recovered {
+                    "recovered"
+                } else {
+                    "lost"
+                },
+            );
+        }
+    }
+    text
+}
+
+/// The byte starting, or with SLIP and COBS ending, every frame
+fn delimiter(framing: Framing) -> u8 {
+    match framing {
+        Framing::Slip => SLIP_END,
+        Framing::Cobs => 0,
+        Framing::Native
+        | Framing::Compact
+        | Framing::Extended
+        | Framing::Binary
+        | Framing::CheckedHeader => START_BYTE,
+    }
+}
+
+fn escape_byte(framing: Framing) -> Option<u8> {
+    match framing {
+        Framing::Native | Framing::Compact | Framing::Extended => Some(ESCAPE_BYTE),
+        Framing::Slip => Some(SLIP_ESC),
+        Framing::Cobs | Framing::Binary | Framing::CheckedHeader => None,
+    }
+}
+
+fn apply(frame: &[u8], framing: Framing, mutation: Mutation) -> Vec<u8> {
+    let mut bytes = frame.to_vec();
+    match mutation {
+        Mutation::FlipBit { offset, bit } => bytes[offset] ^= 1 << bit,
+        Mutation::Truncate { length } => bytes.truncate(length),
+        Mutation::InsertStart { offset } => bytes.insert(offset, delimiter(framing)),
+        Mutation::DoubleEscape { offset } => bytes.insert(offset, frame[offset]),
+    }
+    bytes
+}
+
+fn decode(bytes: &[u8], framing: Framing, endianness: Endianness) -> Vec<DecoderEvent> {
+    let mut decoder = Decoder::new()
+        .with_framing(framing)
+        .with_endianness(endianness);
+    let mut events = Vec::new();
+    for &byte in bytes {
+        let mut event = decoder.push(byte);
+        while let Some(next) = event {
+            events.push(next);
+            event = decoder.poll();
+        }
+    }
+    events
+}
+
+/// Classifies the events produced by a mutated frame, without those of the original frame after
+/// it if it was recovered
+fn classify(events: &[DecoderEvent], original: Option<&Frame>) -> Outcome {
+    let mut frames = events.iter().filter_map(|event| match event {
+        DecoderEvent::Frame(frame) => Some(frame),
+        _ => None,
+    });
+    if frames.clone().any(|frame| Some(frame) != original) {
+        Outcome::Undetected
+    } else if frames.next().is_some() {
+        Outcome::Unaffected
+    } else if events.is_empty() {
+        Outcome::Lost
+    } else {
+        Outcome::Detected
+    }
+}
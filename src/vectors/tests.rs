@@ -0,0 +1,170 @@
+use super::*;
+use crate::codec::{Decoder, DecoderEvent, Frame};
+use crate::errors::ReceiveError;
+use crate::serial_manager::SerialManager;
+use crate::test_util::stream_pair;
+use std::io::{Cursor, Read};
+
+#[test]
+fn test_export_matches_golden_file() {
+    // A change to the wire format must be made on purpose, by writing the file again with
+    // `gsp-cli gen vectors vectors/golden.txt`
+    assert_eq!(export(), include_str!("../../vectors/golden.txt"));
+}
+
+#[test]
+fn test_golden_vectors_decode() {
+    for vector in golden() {
+        let mut decoder = Decoder::new()
+            .with_framing(vector.framing)
+            .with_endianness(vector.endianness);
+        let events: Vec<_> = vector
+            .frame
+            .iter()
+            .filter_map(|&byte| decoder.push(byte))
+            .collect();
+        let [DecoderEvent::Frame(frame)] = events.as_slice() else {
+            panic!("{} decoded as {events:?}", vector.name);
+        };
+        assert_eq!(frame.payload, vector.payload, "{}", vector.name);
+        let message =
+            Message::from_bytes_with(frame.message_type, frame.payload.clone(), vector.endianness);
+        assert_eq!(message.unwrap(), vector.message, "{}", vector.name);
+    }
+}
+
+#[test]
+fn test_golden_vectors_sent_and_received() {
+    for vector in golden() {
+        let (stream1, mut stream2) = stream_pair();
+        let mut sender = SerialManager::new(stream1)
+            .with_framing(vector.framing)
+            .with_endianness(vector.endianness);
+        sender.send(vector.message.clone()).unwrap();
+        let mut frame = vec![0; vector.frame.len()];
+        stream2.read_exact(&mut frame).unwrap();
+        assert_eq!(frame, vector.frame, "{}", vector.name);
+
+        // Goodbye is handled rather than returned
+        if vector.message == Message::Goodbye(message_types::Goodbye {}) {
+            continue;
+        }
+        let mut receiver = SerialManager::new(stream2)
+            .with_framing(vector.framing)
+            .with_endianness(vector.endianness);
+        sender.send(vector.message.clone()).unwrap();
+        assert_eq!(
+            receiver.receive().unwrap(),
+            vector.message,
+            "{}",
+            vector.name
+        );
+    }
+}
+
+#[test]
+fn test_mutants_annotated_with_outcome() {
+    let frame = Framing::Native.encode(5, &[0x34, ESCAPE_BYTE], Endianness::Little);
+    let mutants = mutate(&frame, Framing::Native, Endianness::Little);
+    let find = |mutation| {
+        mutants
+            .iter()
+            .find(|mutant| mutant.mutation == mutation)
+            .unwrap()
+    };
+
+    // Nothing in the native framing catches a flipped payload bit
+    let flipped = find(Mutation::FlipBit { offset: 5, bit: 0 });
+    assert_eq!(flipped.outcome, Outcome::Undetected);
+    assert_eq!(
+        flipped.events[0],
+        DecoderEvent::Frame(Frame {
+            message_type: 5,
+            payload: vec![0x35, ESCAPE_BYTE]
+        })
+    );
+    assert!(flipped.recovered);
+
+    let truncated = find(Mutation::Truncate { length: 6 });
+    assert_eq!(truncated.outcome, Outcome::Detected);
+    assert_eq!(truncated.events[0], DecoderEvent::Resync);
+    assert_eq!(truncated.bytes.len(), 6 + frame.len());
+    assert!(truncated.recovered);
+
+    let false_start = find(Mutation::InsertStart { offset: 3 });
+    assert_eq!(false_start.bytes[3], START_BYTE);
+    assert_eq!(false_start.outcome, Outcome::Detected);
+
+    let [doubled] = mutants
+        .iter()
+        .filter(|mutant| matches!(mutant.mutation, Mutation::DoubleEscape { .. }))
+        .collect::<Vec<_>>()[..]
+    else {
+        panic!("expected one escape to double");
+    };
+    assert_eq!(doubled.mutation, Mutation::DoubleEscape { offset: 6 });
+    assert_eq!(mutants.len(), 8 * frame.len() + 2 * (frame.len() - 1) + 1);
+}
+
+#[test]
+fn test_checksummed_framings_detect_every_bit_flip() {
+    for vector in golden() {
+        if !matches!(vector.framing, Framing::Binary | Framing::CheckedHeader) {
+            continue;
+        }
+        for mutant in vector.mutants() {
+            if matches!(mutant.mutation, Mutation::FlipBit { .. }) {
+                assert_ne!(
+                    mutant.outcome,
+                    Outcome::Undetected,
+                    "{} {:?}",
+                    vector.name,
+                    mutant.mutation
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_serial_manager_recovers_like_decoder() {
+    for vector in golden() {
+        // Goodbye is handled rather than returned
+        if vector.message == Message::Goodbye(message_types::Goodbye {}) {
+            continue;
+        }
+        for mutant in vector.mutants() {
+            let mut manager = SerialManager::new(Cursor::new(mutant.bytes.clone()))
+                .with_framing(vector.framing)
+                .with_endianness(vector.endianness);
+            let mut last = None;
+            loop {
+                match manager.receive() {
+                    Ok(message) => last = Some(message),
+                    Err(ReceiveError::Io(_)) => break,
+                    Err(_) => {}
+                }
+            }
+            if mutant.recovered {
+                assert_eq!(
+                    last.as_ref(),
+                    Some(&vector.message),
+                    "{} {:?} {}",
+                    vector.name,
+                    vector.framing,
+                    mutant.mutation
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_export_mutants() {
+    let text = export_mutants();
+    let line = text.lines().find(|line| !line.starts_with('#')).unwrap();
+    assert_eq!(
+        line,
+        "noop native little flip-bit:0:0 59020004005802000400 detected recovered"
+    );
+}
@@ -0,0 +1,85 @@
+use crate::time::Instant;
+use std::time::Duration;
+
+/// Settings for peers that sleep while the line is quiet and wake on the UART receiving a byte,
+/// set with [`SerialManager::with_wake_preamble`](crate::SerialManager::with_wake_preamble).
+///
+/// A sleeping UART loses the bytes that wake it, so after the line has been quiet for the idle
+/// time the manager sends a preamble of bytes that are not part of any frame before the next
+/// frame, which the peer may lose without losing the start of the frame. Frames with a start
+/// byte are preceded by `0x00` bytes, which a receiver with a wake preamble ignores, and SLIP and
+/// COBS frames by their delimiter, which makes empty packets that are ignored anyway.
+///
+/// A manager with a wake preamble also tells its event handler once the line goes quiet for the
+/// idle time, through [`on_idle`](crate::SerialManagerEvents::on_idle), so that a receiver can
+/// go to sleep. Activity in either direction counts, and the line is only checked when a read
+/// fails, so reads from the connection must time out.
+#[derive(Debug, Clone)]
+pub struct WakePreamble {
+    length: usize,
+    idle: Duration,
+    last_activity: Option<Instant>,
+    idle_notified: bool,
+    /// Whether the last bytes sent were followed by the rest of their frame
+    mid_frame: bool,
+}
+
+impl WakePreamble {
+    /// Creates settings sending `length` preamble bytes before every frame
+    #[must_use]
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            idle: Duration::ZERO,
+            last_activity: None,
+            idle_notified: false,
+            mid_frame: false,
+        }
+    }
+
+    /// Only sends the preamble once the line has been quiet for `idle`, the time after which
+    /// the peer goes to sleep
+    #[must_use]
+    pub fn with_idle(mut self, idle: Duration) -> Self {
+        self.idle = idle;
+        self
+    }
+
+    /// Returns the number of preamble bytes sent before a frame that wakes the peer
+    #[must_use]
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Records bytes sent or received at `now`
+    pub(crate) fn activity(&mut self, now: Instant) {
+        self.last_activity = Some(now);
+        self.idle_notified = false;
+    }
+
+    /// Records bytes sent at `now`, which end a frame unless `mid_frame` is set
+    pub(crate) fn sent(&mut self, now: Instant, mid_frame: bool) {
+        self.activity(now);
+        self.mid_frame = mid_frame;
+    }
+
+    /// Returns whether the peer may be asleep at `now`, so that the bytes sent next need the
+    /// preamble, which is never the case in the middle of a frame
+    pub(crate) fn preamble_due(&self, now: Instant) -> bool {
+        !self.mid_frame
+            && self
+                .last_activity
+                .is_none_or(|last| now.duration_since(last) >= self.idle)
+    }
+
+    /// Returns whether the line has gone quiet for the idle time by `now` since it was last
+    /// reported, so that it is only reported once each time
+    pub(crate) fn went_idle(&mut self, now: Instant) -> bool {
+        let idle = !self.idle_notified
+            && self
+                .last_activity
+                .is_some_and(|last| now.duration_since(last) >= self.idle);
+        self.idle_notified |= idle;
+        idle
+    }
+}
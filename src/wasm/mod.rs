@@ -0,0 +1,186 @@
+//! JavaScript bindings to the frame encoder and decoder and the message types, for decoding
+//! frames in a browser, such as ones read from a WebSerial port.
+//!
+//! Enabled with the `wasm` feature. Build a module for the browser with
+//! `cargo rustc --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib`
+//! and generate its JavaScript glue with
+//! `wasm-bindgen --target web target/wasm32-unknown-unknown/release/generic_serial_protocol.wasm --out-dir pkg`,
+//! using `wasm-bindgen-cli` of the same version as the `wasm-bindgen` crate in `Cargo.lock`.
+//!
+//! The sans-IO [`Decoder`], [`encode_frame`](crate::encode_frame) and [`Message`] themselves
+//! build for `wasm32-unknown-unknown` without the feature, and never touch `std::io` or the
+//! clock, which are unavailable there.
+//!
+//! ```js
+//! import init, { Decoder } from "./pkg/generic_serial_protocol.js";
+//!
+//! await init();
+//! const decoder = new Decoder();
+//! const reader = port.readable.getReader();
+//! for (;;) {
+//!   const { value } = await reader.read();
+//!   for (const frame of decoder.push(value)) {
+//!     console.log(frame.decode().toString());
+//!   }
+//! }
+//! ```
+
+use crate::codec::{self, Decoder, DecoderEvent, Endianness, Frame, MAX_PAYLOAD_LENGTH};
+use crate::errors::DecodeError;
+use crate::fmt;
+use crate::message::Message;
+use crate::schema;
+use wasm_bindgen::prelude::*;
+
+/// Encodes a frame with an already encoded payload and little-endian numbers
+#[wasm_bindgen(js_name = encodeFrame)]
+pub fn encode_frame(message_type: u16, payload: &[u8]) -> Result<Vec<u8>, JsError> {
+    if payload.len() > MAX_PAYLOAD_LENGTH {
+        return Err(JsError::new("payload too long for a frame"));
+    }
+    Ok(codec::encode_frame(message_type, payload))
+}
+
+/// A frame decoder that bytes are pushed into as they arrive, in chunks of any size
+#[wasm_bindgen(js_name = Decoder)]
+pub struct WasmDecoder {
+    decoder: Decoder,
+    endianness: Endianness,
+}
+
+#[wasm_bindgen(js_class = Decoder)]
+impl WasmDecoder {
+    /// Creates a decoder for frames with little-endian numbers, or big-endian ones if
+    /// `big_endian` is true
+    #[wasm_bindgen(constructor)]
+    #[must_use]
+    pub fn new(big_endian: Option<bool>) -> Self {
+        let endianness = if big_endian.unwrap_or(false) {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        };
+        Self {
+            decoder: Decoder::new().with_endianness(endianness),
+            endianness,
+        }
+    }
+
+    /// Pushes received bytes, returning the frames they completed
+    ///
+    /// Bytes outside frames and frames that fail to decode are skipped, as they are by
+    /// `SerialManager`.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<WasmFrame> {
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            let event = self.decoder.push(byte);
+            for event in event
+                .into_iter()
+                .chain(std::iter::from_fn(|| self.decoder.poll()))
+            {
+                if let DecoderEvent::Frame(frame) = event {
+                    frames.push(WasmFrame {
+                        frame,
+                        endianness: self.endianness,
+                    });
+                }
+            }
+        }
+        frames
+    }
+}
+
+/// A frame completed by a [`WasmDecoder`], with its payload not yet decoded
+#[wasm_bindgen(js_name = Frame)]
+pub struct WasmFrame {
+    frame: Frame,
+    endianness: Endianness,
+}
+
+#[wasm_bindgen(js_class = Frame)]
+impl WasmFrame {
+    #[wasm_bindgen(getter, js_name = messageType)]
+    #[must_use]
+    pub fn message_type(&self) -> u16 {
+        self.frame.message_type
+    }
+
+    /// The unescaped payload, copied into a new `Uint8Array`
+    #[wasm_bindgen(getter)]
+    #[must_use]
+    pub fn payload(&self) -> Vec<u8> {
+        self.frame.payload.clone()
+    }
+
+    /// Decodes the payload into a built-in message type, throwing if the message type is not
+    /// built in or the payload is malformed
+    pub fn decode(&self) -> Result<WasmMessage, JsError> {
+        self.decode_message().map_err(JsError::from)
+    }
+
+    fn decode_message(&self) -> Result<WasmMessage, DecodeError> {
+        let message = Message::from_bytes_with(
+            self.frame.message_type,
+            self.frame.payload.clone(),
+            self.endianness,
+        )?;
+        Ok(WasmMessage {
+            message,
+            endianness: self.endianness,
+        })
+    }
+}
+
+/// A decoded message of a built-in type
+#[wasm_bindgen(js_name = Message)]
+pub struct WasmMessage {
+    message: Message,
+    endianness: Endianness,
+}
+
+#[wasm_bindgen(js_class = Message)]
+impl WasmMessage {
+    #[wasm_bindgen(getter, js_name = messageType)]
+    #[must_use]
+    pub fn message_type(&self) -> u16 {
+        self.message.message_type()
+    }
+
+    /// The name of the message type, such as `U32`
+    #[wasm_bindgen(getter)]
+    #[must_use]
+    pub fn name(&self) -> Option<String> {
+        schema::message_name(self.message.message_type()).map(str::to_string)
+    }
+
+    /// The reading of a message holding a single number, for plotting, or `undefined`
+    #[wasm_bindgen(getter)]
+    #[must_use]
+    pub fn value(&self) -> Option<f64> {
+        self.message.reading()
+    }
+
+    /// The message on one line, such as `U32 { num: 7 }`
+    #[wasm_bindgen(js_name = toString)]
+    #[must_use]
+    pub fn to_display_string(&self) -> String {
+        self.message.to_string()
+    }
+
+    /// The frame the message is encoded as, one part per line with its offset, bytes and
+    /// meaning, like [`fmt::dump_frame`]
+    #[must_use]
+    pub fn dump(&self) -> String {
+        let payload = match self.message.clone().to_bytes_with(self.endianness) {
+            Ok(payload) => payload,
+            Err(e) => return format!("{:?}: {e}", self.message),
+        };
+        fmt::dump_frame_with(
+            &codec::encode_frame_with(self.message.message_type(), &payload, self.endianness),
+            self.endianness,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests;
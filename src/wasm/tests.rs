@@ -0,0 +1,45 @@
+use super::*;
+use crate::message::message_types;
+
+#[test]
+fn test_decoder_push() {
+    let message = Message::U32(message_types::U32 { num: 0x5842 });
+    let mut bytes = vec![0x13, 0x37];
+    bytes.extend(encode_frame(7, &message.clone().to_bytes().unwrap()).unwrap());
+    bytes.extend(encode_frame(0x1234, &[0xAB]).unwrap());
+
+    let mut decoder = WasmDecoder::new(None);
+    // Split in the middle of the first frame, as reads from a port would be
+    let mut frames = decoder.push(&bytes[..6]);
+    assert!(frames.is_empty());
+    frames.extend(decoder.push(&bytes[6..]));
+    assert_eq!(frames.len(), 2);
+
+    let received = frames[0].decode().unwrap();
+    assert_eq!(received.message_type(), 7);
+    assert_eq!(received.name().as_deref(), Some("U32"));
+    assert_eq!(received.value(), Some(22594.0));
+    assert_eq!(received.to_display_string(), message.to_string());
+    assert!(received.dump().contains("num: 22594"));
+
+    assert_eq!(frames[1].message_type(), 0x1234);
+    assert_eq!(frames[1].payload(), [0xAB]);
+    assert!(matches!(
+        frames[1].decode_message(),
+        Err(DecodeError::InvalidMessageType(0x1234))
+    ));
+}
+
+#[test]
+fn test_big_endian_decoder() {
+    let message = Message::I16(message_types::I16 { num: -2 });
+    let frame = codec::encode_frame_with(
+        10,
+        &message.to_bytes_with(Endianness::Big).unwrap(),
+        Endianness::Big,
+    );
+
+    let frames = WasmDecoder::new(Some(true)).push(&frame);
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].decode().unwrap().value(), Some(-2.0));
+}
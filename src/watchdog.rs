@@ -0,0 +1,283 @@
+use crate::errors::WatchdogError;
+use crate::time::Instant;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Whether a [`Watchdog`] has seen a valid frame recently, passed to its handler when it changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// A valid frame was received within the timeout
+    Up,
+    /// No valid frame was received for longer than the timeout
+    Down,
+}
+
+type StatusHandler = Box<dyn FnMut(LinkStatus) + Send>;
+
+#[derive(Debug)]
+struct State {
+    last_frame: Instant,
+    status: LinkStatus,
+}
+
+/// Detects a stalled link, set with
+/// [`SerialManager::with_watchdog`](crate::SerialManager::with_watchdog), for driving "link
+/// down" indicators.
+///
+/// The link is down once no valid frame has been received for the timeout, and up again with
+/// the next one. The time starts when the watchdog is created, so a link that never sends
+/// anything goes down after one timeout.
+pub struct Watchdog {
+    timeout: Duration,
+    probe_interval: Option<Duration>,
+    handler: Option<StatusHandler>,
+    state: Arc<Mutex<State>>,
+    last_probe: Option<Instant>,
+    challenge: Option<Challenge>,
+}
+
+/// The liveness challenges of a [`Watchdog`], set with
+/// [`with_challenge`](Watchdog::with_challenge)
+#[derive(Debug)]
+struct Challenge {
+    interval: Duration,
+    timeout: Duration,
+    /// Keys the hashing of `count` into nonces the peer cannot predict
+    keys: RandomState,
+    count: u64,
+    last_sent: Option<Instant>,
+    /// The nonce of the challenge awaiting its response, and when it was sent
+    outstanding: Option<(u32, Instant)>,
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("timeout", &self.timeout)
+            .field("probe_interval", &self.probe_interval)
+            .field("challenge", &self.challenge)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Watchdog {
+    /// Creates a watchdog that reports the link down after `timeout` without a valid frame
+    #[must_use]
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            probe_interval: None,
+            handler: None,
+            state: Arc::new(Mutex::new(State {
+                last_frame: Instant::now(),
+                status: LinkStatus::Up,
+            })),
+            last_probe: None,
+            challenge: None,
+        }
+    }
+
+    /// Sends a `Ping` once no valid frame has been received for `interval`, and again every
+    /// `interval` while the link stays quiet, so that an idle but healthy peer keeps it up
+    ///
+    /// The peer's `Pong` replies are received like any other message.
+    #[must_use]
+    pub fn with_probe(mut self, interval: Duration) -> Self {
+        self.probe_interval = Some(interval);
+        self
+    }
+
+    /// Sends a `Challenge` with a fresh nonce every `interval`, which the peer must echo back in
+    /// a `ChallengeResponse` within `timeout`
+    ///
+    /// Any valid frame keeps the link up, so a peer stuck sending the same frames in a boot loop
+    /// would appear healthy. Only a peer whose receive loop is working echoes the nonce, and
+    /// receiving fails with [`ReceiveError::PeerUnresponsive`] for each challenge that goes
+    /// unanswered. A [`SerialManager`](crate::SerialManager) answers challenges itself.
+    ///
+    /// [`ReceiveError::PeerUnresponsive`]: crate::ReceiveError::PeerUnresponsive
+    #[must_use]
+    pub fn with_challenge(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.challenge = Some(Challenge {
+            interval,
+            timeout,
+            keys: RandomState::new(),
+            count: 0,
+            last_sent: None,
+            outstanding: None,
+        });
+        self
+    }
+
+    /// Calls `handler` whenever the link goes down or comes back up
+    ///
+    /// It is called from the thread receiving with the manager, when a read returns.
+    #[must_use]
+    pub fn with_handler(mut self, handler: impl FnMut(LinkStatus) + Send + 'static) -> Self {
+        self.handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Returns a handle for checking the link from other threads, such as a UI
+    #[must_use]
+    pub fn handle(&self) -> WatchdogHandle {
+        WatchdogHandle {
+            timeout: self.timeout,
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Records a valid frame received at `now`, returning the new status if the link came back
+    /// up
+    pub(crate) fn feed(&mut self, now: Instant) -> Option<LinkStatus> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.last_frame = now;
+        self.last_probe = None;
+        if state.status == LinkStatus::Up {
+            return None;
+        }
+        state.status = LinkStatus::Up;
+        drop(state);
+        if let Some(handler) = &mut self.handler {
+            handler(LinkStatus::Up);
+        }
+        Some(LinkStatus::Up)
+    }
+
+    /// Checks for the link going down at `now`, returning the new status if it did
+    pub(crate) fn poll(&mut self, now: Instant) -> Option<LinkStatus> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let quiet = now.saturating_duration_since(state.last_frame);
+        if state.status == LinkStatus::Down || quiet <= self.timeout {
+            return None;
+        }
+        state.status = LinkStatus::Down;
+        drop(state);
+        if let Some(handler) = &mut self.handler {
+            handler(LinkStatus::Down);
+        }
+        Some(LinkStatus::Down)
+    }
+
+    /// Returns whether a probe should be sent at `now`, and if so takes it as sent
+    pub(crate) fn probe_due(&mut self, now: Instant) -> bool {
+        let Some(interval) = self.probe_interval else {
+            return false;
+        };
+        let quiet = now.saturating_duration_since(
+            self.state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .last_frame,
+        );
+        let due = quiet >= interval
+            && self
+                .last_probe
+                .is_none_or(|last| now.saturating_duration_since(last) >= interval);
+        if due {
+            self.last_probe = Some(now);
+        }
+        due
+    }
+
+    /// Returns the nonce of a challenge to send at `now`, if one is due, and takes it as sent
+    pub(crate) fn challenge_due(&mut self, now: Instant) -> Option<u32> {
+        let challenge = self.challenge.as_mut()?;
+        let due = challenge.outstanding.is_none()
+            && challenge
+                .last_sent
+                .is_none_or(|last| now.saturating_duration_since(last) >= challenge.interval);
+        if !due {
+            return None;
+        }
+        challenge.count += 1;
+        // Truncated, as any 32 bits of the hash are as unpredictable as the rest
+        #[allow(clippy::cast_possible_truncation)]
+        let nonce = challenge.keys.hash_one(challenge.count) as u32;
+        challenge.last_sent = Some(now);
+        challenge.outstanding = Some((nonce, now));
+        Some(nonce)
+    }
+
+    /// Returns whether challenges are sent, so that responses to them are expected
+    pub(crate) fn is_challenging(&self) -> bool {
+        self.challenge.is_some()
+    }
+
+    /// Records a response echoing `nonce`, returning whether it answered the outstanding
+    /// challenge
+    pub(crate) fn answer(&mut self, nonce: u32) -> bool {
+        let Some(challenge) = &mut self.challenge else {
+            return false;
+        };
+        let answered = challenge
+            .outstanding
+            .is_some_and(|(outstanding, _)| outstanding == nonce);
+        if answered {
+            challenge.outstanding = None;
+        }
+        answered
+    }
+
+    /// Returns whether the outstanding challenge has gone unanswered for longer than the
+    /// timeout at `now`, giving up on it if so
+    pub(crate) fn unanswered(&mut self, now: Instant) -> bool {
+        let Some(challenge) = &mut self.challenge else {
+            return false;
+        };
+        let expired = challenge
+            .outstanding
+            .is_some_and(|(_, sent)| now.saturating_duration_since(sent) > challenge.timeout);
+        if expired {
+            challenge.outstanding = None;
+        }
+        expired
+    }
+}
+
+/// A handle to a [`Watchdog`] that can be checked from any thread, returned by
+/// [`Watchdog::handle`]
+///
+/// Its status is worked out from the time of the last valid frame whenever it is checked, so it
+/// is up to date even while the manager is blocked in a read.
+#[derive(Debug, Clone)]
+pub struct WatchdogHandle {
+    timeout: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl WatchdogHandle {
+    /// Returns the time since the last valid frame was received, or since the watchdog was
+    /// created if none has been
+    #[must_use]
+    pub fn since_last_frame(&self) -> Duration {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .last_frame
+            .elapsed()
+    }
+
+    #[must_use]
+    pub fn status(&self) -> LinkStatus {
+        if self.since_last_frame() > self.timeout {
+            LinkStatus::Down
+        } else {
+            LinkStatus::Up
+        }
+    }
+
+    /// Fails with [`WatchdogError::Stalled`] if the link is down
+    pub fn check(&self) -> Result<(), WatchdogError> {
+        let quiet = self.since_last_frame();
+        if quiet > self.timeout {
+            Err(WatchdogError::Stalled(quiet))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use crate::serial_manager::{Buffers, TransportControl};
+use js_sys::{Function, Object, Promise, Reflect, Uint8Array};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+
+// The parts of the WebSerial and Streams APIs used, bound here rather than through `web-sys`,
+// which only exposes WebSerial with `--cfg=web_sys_unstable_apis`
+#[wasm_bindgen]
+extern "C" {
+    #[derive(Debug, Clone)]
+    type SerialPort;
+
+    #[wasm_bindgen(method)]
+    fn open(this: &SerialPort, options: &Object) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &SerialPort) -> Promise;
+
+    /// The stream of received bytes, or `null` once the port is closed or has failed for good
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &SerialPort) -> Option<ReadableStream>;
+
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &SerialPort) -> WritableStream;
+
+    #[wasm_bindgen(method, js_name = setSignals)]
+    fn set_signals(this: &SerialPort, signals: &Object) -> Promise;
+
+    type ReadableStream;
+
+    #[wasm_bindgen(method, js_name = getReader)]
+    fn get_reader(this: &ReadableStream) -> Reader;
+
+    #[derive(Debug, Clone)]
+    type Reader;
+
+    #[wasm_bindgen(method)]
+    fn read(this: &Reader) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn cancel(this: &Reader) -> Promise;
+
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &Reader);
+
+    type WritableStream;
+
+    #[wasm_bindgen(method, js_name = getWriter)]
+    fn get_writer(this: &WritableStream) -> Writer;
+
+    #[derive(Debug, Clone)]
+    type Writer;
+
+    #[wasm_bindgen(method)]
+    fn write(this: &Writer, chunk: &Uint8Array) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &Writer) -> Promise;
+
+    #[wasm_bindgen(method, js_name = releaseLock)]
+    fn release_lock(this: &Writer);
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &Function, milliseconds: f64);
+}
+
+/// A browser's WebSerial port, read and written through [`SerialManager`](crate::SerialManager)
+/// so that the same protocol code runs in browser-based configuration tools
+///
+/// Browsers have no blocking reads, so the port reads in the background and reads from it return
+/// [`io::ErrorKind::WouldBlock`] until bytes have arrived. Await [`readable`](Self::readable)
+/// and then call [`SerialManager::drain`](crate::SerialManager::drain), which stops at the first
+/// read that would block:
+///
+/// ```no_run
+/// # async fn run(port: wasm_bindgen::JsValue) -> std::io::Result<()> {
+/// use generic_serial_protocol::{SerialManager, WebSerialPort};
+///
+/// // `port` from `navigator.serial.requestPort()` in JavaScript
+/// let port = WebSerialPort::open(port, 115_200).await?;
+/// let mut manager = SerialManager::new(port.clone());
+/// loop {
+///     port.readable().await;
+///     for message in manager.drain().map_err(std::io::Error::other)? {
+///         show(&message);
+///     }
+/// }
+/// # }
+/// # fn show(_: &generic_serial_protocol::Message) {}
+/// ```
+///
+/// Writes are handed to the port's stream at once and transmitted in the background, so a write
+/// that fails is reported by the next write or flush. Once the port is closed, or unplugged,
+/// reads return end of file. Clones share the same port.
+#[derive(Debug, Clone)]
+pub struct WebSerialPort {
+    port: SerialPort,
+    writer: Writer,
+    reader: Rc<RefCell<Option<Reader>>>,
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl WebSerialPort {
+    /// Opens a `SerialPort` returned by `navigator.serial.requestPort()` or `getPorts()` at the
+    /// given baud rate, and starts reading from it
+    pub async fn open(port: JsValue, baud_rate: u32) -> io::Result<Self> {
+        let port: SerialPort = port.unchecked_into();
+        let options = Object::new();
+        set(&options, "baudRate", &baud_rate.into());
+        JsFuture::from(port.open(&options))
+            .await
+            .map_err(|e| js_error(&e))?;
+
+        let port = Self {
+            writer: port.writable().get_writer(),
+            port,
+            reader: Rc::default(),
+            shared: Rc::default(),
+        };
+        spawn_local(port.clone().read_loop());
+        Ok(port)
+    }
+
+    /// Reads the port until it is closed, passing the bytes read to `shared`
+    ///
+    /// Errors such as parity and framing errors end the port's current stream but not the port,
+    /// which then has a new stream to continue reading from.
+    async fn read_loop(self) {
+        while let Some(stream) = self.port.readable() {
+            if self.shared.borrow().closed {
+                return;
+            }
+            let reader = stream.get_reader();
+            *self.reader.borrow_mut() = Some(reader.clone());
+            loop {
+                let result = match JsFuture::from(reader.read()).await {
+                    Ok(result) => result,
+                    Err(e) => {
+                        self.shared.borrow_mut().fail(js_error(&e));
+                        break;
+                    }
+                };
+                if get(&result, "done").is_truthy() {
+                    break;
+                }
+                let chunk: Uint8Array = get(&result, "value").unchecked_into();
+                self.shared.borrow_mut().receive(&chunk.to_vec());
+            }
+            reader.release_lock();
+            self.reader.borrow_mut().take();
+        }
+        self.shared.borrow_mut().close();
+    }
+
+    /// Waits until a read would not block, because bytes have arrived, reading failed or the port
+    /// was closed
+    #[must_use]
+    pub fn readable(&self) -> Readable {
+        Readable {
+            shared: Rc::clone(&self.shared),
+        }
+    }
+
+    /// Stops reading, waits for the bytes written to be transmitted and closes the port
+    pub async fn close(&self) -> io::Result<()> {
+        self.shared.borrow_mut().close();
+        let reader = self.reader.borrow_mut().take();
+        if let Some(reader) = reader {
+            JsFuture::from(reader.cancel())
+                .await
+                .map_err(|e| js_error(&e))?;
+            reader.release_lock();
+        }
+        JsFuture::from(self.writer.close())
+            .await
+            .map_err(|e| js_error(&e))?;
+        self.writer.release_lock();
+        JsFuture::from(self.port.close())
+            .await
+            .map_err(|e| js_error(&e))?;
+        Ok(())
+    }
+
+    /// Sets one of the port's output signals in the background, reporting a failure through the
+    /// next write or flush
+    fn set_signal(&self, signal: &str, level: bool) {
+        self.spawn_checked(JsFuture::from(
+            self.port.set_signals(&signals(signal, level)),
+        ));
+    }
+
+    /// Runs `future` in the background, keeping its error for the next write or flush
+    fn spawn_checked(&self, future: impl Future<Output = Result<JsValue, JsValue>> + 'static) {
+        let shared = Rc::clone(&self.shared);
+        spawn_local(async move {
+            if let Err(e) = future.await {
+                shared.borrow_mut().fail_write(js_error(&e));
+            }
+        });
+    }
+}
+
+impl Read for WebSerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.shared.borrow_mut().read(buf)
+    }
+}
+
+impl Write for WebSerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.shared.borrow_mut().check_write()?;
+        self.spawn_checked(JsFuture::from(self.writer.write(&Uint8Array::from(buf))));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.shared.borrow_mut().check_write()
+    }
+}
+
+/// The RTS and DTR lines and break conditions through `SerialPort.setSignals()`, and discarding
+/// the bytes received but not read
+///
+/// Signals are set in the background, so `send_break` returns before the break has ended.
+impl TransportControl for WebSerialPort {
+    fn send_break(&mut self, duration: Duration) -> io::Result<()> {
+        self.shared.borrow_mut().check_write()?;
+        let port = self.port.clone();
+        self.spawn_checked(async move {
+            JsFuture::from(port.set_signals(&signals("break", true))).await?;
+            JsFuture::from(Promise::new(&mut |resolve: Function, _| {
+                set_timeout(&resolve, duration.as_secs_f64() * 1000.0);
+            }))
+            .await?;
+            JsFuture::from(port.set_signals(&signals("break", false))).await
+        });
+        Ok(())
+    }
+
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.shared.borrow_mut().check_write()?;
+        self.set_signal("requestToSend", level);
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.shared.borrow_mut().check_write()?;
+        self.set_signal("dataTerminalReady", level);
+        Ok(())
+    }
+
+    fn flush_buffers(&mut self, buffers: Buffers) -> io::Result<()> {
+        match buffers {
+            Buffers::Input => {
+                self.shared.borrow_mut().received.clear();
+                Ok(())
+            }
+            Buffers::Output | Buffers::Both => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "WebSerial ports cannot discard bytes written",
+            )),
+        }
+    }
+}
+
+fn signals(signal: &str, level: bool) -> Object {
+    let options = Object::new();
+    set(&options, signal, &level.into());
+    options
+}
+
+fn set(object: &Object, key: &str, value: &JsValue) {
+    // Setting a property of a plain object cannot fail
+    let _ = Reflect::set(object, &key.into(), value);
+}
+
+fn get(object: &JsValue, key: &str) -> JsValue {
+    Reflect::get(object, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Converts a rejection, usually a `DOMException` such as a `NetworkError` for an unplugged
+/// port, into an error with its message
+fn js_error(error: &JsValue) -> io::Error {
+    let message = get(error, "message")
+        .as_string()
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| format!("{error:?}"));
+    io::Error::other(message)
+}
+
+/// A future resolving once a read from a [`WebSerialPort`] would not block, returned by
+/// [`WebSerialPort::readable`]
+#[derive(Debug)]
+pub struct Readable {
+    shared: Rc<RefCell<Shared>>,
+}
+
+impl Future for Readable {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.shared.borrow_mut().poll_readable(cx)
+    }
+}
+
+/// The state shared between a port's clones and its background tasks
+#[derive(Debug, Default)]
+struct Shared {
+    received: VecDeque<u8>,
+    /// An error from reading, returned once the bytes received have been read
+    read_error: Option<io::Error>,
+    /// An error from writing or setting signals in the background, returned by the next write
+    write_error: Option<io::Error>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl Shared {
+    fn receive(&mut self, bytes: &[u8]) {
+        self.received.extend(bytes);
+        self.wake();
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        self.read_error.get_or_insert(error);
+        self.wake();
+    }
+
+    fn fail_write(&mut self, error: io::Error) {
+        self.write_error.get_or_insert(error);
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.received.is_empty() {
+            return Ok(self.received.read(buf).unwrap_or(0));
+        }
+        if let Some(error) = self.read_error.take() {
+            return Err(error);
+        }
+        if self.closed {
+            Ok(0)
+        } else {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    fn check_write(&mut self) -> io::Result<()> {
+        if let Some(error) = self.write_error.take() {
+            return Err(error);
+        }
+        if self.closed {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        Ok(())
+    }
+
+    fn poll_readable(&mut self, cx: &Context<'_>) -> Poll<()> {
+        if !self.received.is_empty() || self.read_error.is_some() || self.closed {
+            return Poll::Ready(());
+        }
+        self.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests;
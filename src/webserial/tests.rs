@@ -0,0 +1,128 @@
+use super::*;
+use crate::codec::encode_frame;
+use crate::message::{message_types, Message};
+use crate::SerialManager;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::Wake;
+
+/// Counts how many times it was woken
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CountingWaker {
+    fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A connection reading from `Shared` like a `WebSerialPort`, without a browser
+struct SharedConnection(Rc<RefCell<Shared>>);
+
+impl Read for SharedConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl Write for SharedConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reads_would_block_until_bytes_arrive() {
+    let mut shared = Shared::default();
+    let mut buf = [0; 4];
+    assert_eq!(
+        shared.read(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    shared.receive(&[1, 2, 3]);
+    shared.receive(&[4, 5]);
+    assert_eq!(shared.read(&mut buf).unwrap(), 4);
+    assert_eq!(buf, [1, 2, 3, 4]);
+    assert_eq!(shared.read(&mut buf).unwrap(), 1);
+    assert_eq!(buf[0], 5);
+    assert_eq!(
+        shared.read(&mut buf).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+}
+
+#[test]
+fn test_errors_and_close_follow_received_bytes() {
+    let mut shared = Shared::default();
+    shared.receive(&[1]);
+    shared.fail(io::Error::other("framing error"));
+    shared.receive(&[2]);
+    shared.close();
+
+    let mut buf = [0; 4];
+    assert_eq!(shared.read(&mut buf).unwrap(), 2);
+    assert_eq!(
+        shared.read(&mut buf).unwrap_err().to_string(),
+        "framing error"
+    );
+    assert_eq!(shared.read(&mut buf).unwrap(), 0);
+    assert_eq!(
+        shared.check_write().unwrap_err().kind(),
+        io::ErrorKind::NotConnected
+    );
+}
+
+#[test]
+fn test_write_errors_are_returned_once() {
+    let mut shared = Shared::default();
+    shared.fail_write(io::Error::other("device lost"));
+    assert_eq!(shared.check_write().unwrap_err().to_string(), "device lost");
+    assert!(shared.check_write().is_ok());
+}
+
+#[test]
+fn test_readable_wakes_when_bytes_arrive() {
+    let mut shared = Shared::default();
+    let waker = Arc::new(CountingWaker::default());
+    let context_waker = Waker::from(Arc::clone(&waker));
+    let cx = Context::from_waker(&context_waker);
+
+    assert!(shared.poll_readable(&cx).is_pending());
+    assert_eq!(waker.count(), 0);
+    shared.receive(&[1]);
+    assert_eq!(waker.count(), 1);
+    assert!(shared.poll_readable(&cx).is_ready());
+
+    shared.received.clear();
+    assert!(shared.poll_readable(&cx).is_pending());
+    shared.close();
+    assert_eq!(waker.count(), 2);
+    assert!(shared.poll_readable(&cx).is_ready());
+}
+
+#[test]
+fn test_drain_stops_at_bytes_not_yet_arrived() {
+    let shared = Rc::new(RefCell::new(Shared::default()));
+    let mut manager = SerialManager::new(SharedConnection(Rc::clone(&shared)));
+    let message = Message::U16(message_types::U16 { num: 0x1234 });
+    let frame = encode_frame(message.message_type(), &message.clone().to_bytes().unwrap());
+
+    shared.borrow_mut().receive(&frame);
+    shared.borrow_mut().receive(&frame[..3]);
+    assert_eq!(manager.drain().unwrap(), std::slice::from_ref(&message));
+    assert!(manager.drain().unwrap().is_empty());
+
+    shared.borrow_mut().receive(&frame[3..]);
+    assert_eq!(manager.drain().unwrap(), [message]);
+}
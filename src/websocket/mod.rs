@@ -0,0 +1,130 @@
+use crate::datagram::Datagram;
+use std::io::{self, Read, Write};
+use tungstenite::{Message as WsMessage, WebSocket};
+
+/// A connection that tunnels the escaped byte stream through a WebSocket, for a browser-based UI
+/// talking to a device through a bridge.
+///
+/// Everything written up to a `flush` is sent as a single binary WebSocket message. As
+/// [`SerialManager`](crate::SerialManager) flushes after every frame, each frame arrives as its
+/// own message, but the peer can also treat the messages as one byte stream, just as a serial
+/// port would deliver it. Received binary messages are read in order, and any other messages are
+/// skipped. Once the WebSocket is closed, reads return end of file.
+///
+/// To send each message as a binary WebSocket message holding just its message type and payload,
+/// without framing or escaping, pass the WebSocket to
+/// [`DatagramManager::new`](crate::DatagramManager::new) instead.
+pub struct WebSocketConnection<S> {
+    socket: WebSocket<S>,
+    read_buffer: Vec<u8>,
+    read_position: usize,
+    write_buffer: Vec<u8>,
+}
+
+impl<S> WebSocketConnection<S>
+where
+    S: Read + Write,
+{
+    /// Wraps a WebSocket on which the handshake has been completed
+    pub fn new(socket: WebSocket<S>) -> Self {
+        Self {
+            socket,
+            read_buffer: Vec::new(),
+            read_position: 0,
+            write_buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the WebSocket, discarding anything read but not consumed or written but not
+    /// flushed
+    pub fn into_inner(self) -> WebSocket<S> {
+        self.socket
+    }
+}
+
+/// Receives the next binary message, or `None` once the WebSocket is closed
+fn read_binary<S: Read + Write>(socket: &mut WebSocket<S>) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        match socket.read() {
+            Ok(WsMessage::Binary(data)) => return Ok(Some(data)),
+            Ok(WsMessage::Close(_))
+            | Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                return Ok(None)
+            }
+            // Pings are answered by the WebSocket itself
+            Ok(_) => (),
+            Err(e) => return Err(into_io_error(e)),
+        }
+    }
+}
+
+fn into_io_error(error: tungstenite::Error) -> io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+impl<S> Read for WebSocketConnection<S>
+where
+    S: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read_position == self.read_buffer.len() {
+            let Some(data) = read_binary(&mut self.socket)? else {
+                return Ok(0);
+            };
+            self.read_buffer = data;
+            self.read_position = 0;
+        }
+        let count = buf.len().min(self.read_buffer.len() - self.read_position);
+        buf[..count].copy_from_slice(&self.read_buffer[self.read_position..][..count]);
+        self.read_position += count;
+        Ok(count)
+    }
+}
+
+impl<S> Write for WebSocketConnection<S>
+where
+    S: Read + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.write_buffer.is_empty() {
+            let data = std::mem::take(&mut self.write_buffer);
+            self.socket
+                .send(WsMessage::Binary(data))
+                .map_err(into_io_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Datagram for WebSocket<S>
+where
+    S: Read + Write,
+{
+    fn send_datagram(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.send(WsMessage::Binary(datagram.to_vec()))
+            .map_err(into_io_error)
+    }
+
+    fn receive_datagram(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let data = read_binary(self)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let Some(buffer) = buffer.get_mut(..data.len()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "WebSocket message too large",
+            ));
+        };
+        buffer.copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,69 @@
+use super::*;
+use crate::message::{message_types, Message};
+use crate::test_util::{stream_pair, TestStream};
+use crate::{DatagramManager, SerialManager};
+use tungstenite::protocol::Role;
+
+fn socket_pair() -> (WebSocket<TestStream>, WebSocket<TestStream>) {
+    let (stream1, stream2) = stream_pair();
+    (
+        WebSocket::from_raw_socket(stream1, Role::Client, None),
+        WebSocket::from_raw_socket(stream2, Role::Server, None),
+    )
+}
+
+fn u8_message(num: u8) -> Message {
+    Message::U8(message_types::U8 { num })
+}
+
+#[test]
+fn test_frame_per_message() {
+    let (socket1, mut socket2) = socket_pair();
+    let mut sender = SerialManager::new(WebSocketConnection::new(socket1));
+    sender.send(u8_message(0x58)).unwrap();
+    sender.send(u8_message(1)).unwrap();
+
+    assert_eq!(
+        socket2.read().unwrap(),
+        WsMessage::Binary(vec![0x58, 0x03, 0x00, 0x01, 0x00, 0x42, 0x31])
+    );
+    assert_eq!(
+        socket2.read().unwrap(),
+        WsMessage::Binary(vec![0x58, 0x03, 0x00, 0x01, 0x00, 0x01])
+    );
+}
+
+#[test]
+fn test_byte_stream() {
+    let (mut socket1, socket2) = socket_pair();
+    let mut receiver = SerialManager::new(WebSocketConnection::new(socket2));
+
+    // A frame split across messages, and two frames in one
+    socket1.send(WsMessage::Binary(vec![0x58, 0x03])).unwrap();
+    socket1
+        .send(WsMessage::Text("ignored".to_string()))
+        .unwrap();
+    socket1
+        .send(WsMessage::Binary(vec![
+            0x00, 0x01, 0x00, 0x01, 0x58, 0x03, 0x00, 0x01, 0x00, 0x02,
+        ]))
+        .unwrap();
+    socket1.close(None).unwrap();
+
+    assert_eq!(receiver.receive().unwrap(), u8_message(1));
+    assert_eq!(receiver.receive().unwrap(), u8_message(2));
+    assert!(matches!(
+        receiver.receive(),
+        Err(crate::ReceiveError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn test_datagrams() {
+    let (socket1, socket2) = socket_pair();
+    let mut sender = DatagramManager::new(socket1);
+    let mut receiver = DatagramManager::new(socket2);
+
+    sender.send(u8_message(0x58)).unwrap();
+    assert_eq!(receiver.receive().unwrap(), u8_message(0x58));
+}